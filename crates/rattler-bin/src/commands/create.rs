@@ -4,53 +4,69 @@ use clap::ValueEnum;
 use indicatif::{ProgressBar, ProgressStyle};
 use itertools::Itertools;
 use rattler::install::{IndicatifReporter, Installer};
+use rattler::install::{Transaction, TransactionOperation};
 use rattler::package_cache::PackageCache;
-use rattler::{
-    default_cache_dir,
-    install::{Transaction, TransactionOperation},
-};
 use rattler_conda_types::{
-    Channel, ChannelConfig, GenericVirtualPackage, MatchSpec, ParseStrictness, Platform,
+    Channel, GenericVirtualPackage, MatchSpec, PackageName, ParseStrictness, Platform,
     PrefixRecord, RepoDataRecord, Version,
 };
-use rattler_networking::{AuthenticationMiddleware, AuthenticationStorage};
+use rattler_config::RattlerConfig;
+use rattler_networking::AuthenticatedClient;
 use rattler_repodata_gateway::{Gateway, RepoData};
 use rattler_solve::{
     libsolv_c::{self},
     resolvo, SolverImpl, SolverTask,
 };
-use reqwest::Client;
+use reqwest_middleware::ClientWithMiddleware;
 use std::future::IntoFuture;
-use std::sync::Arc;
 use std::time::Instant;
 use std::{borrow::Cow, env, path::PathBuf, str::FromStr, time::Duration};
 
+/// Create or update an environment from a set of specs.
 #[derive(Debug, clap::Parser)]
 pub struct Opt {
-    #[clap(short)]
+    /// The channels to fetch packages from (defaults to conda-forge)
+    #[clap(short, long = "channel")]
     channels: Option<Vec<String>>,
 
+    /// The specs of the packages to install
     #[clap(required = true)]
     specs: Vec<String>,
 
+    /// Only solve the environment and print the operations that would be
+    /// performed
     #[clap(long)]
     dry_run: bool,
 
+    /// The platform to create the environment for (defaults to the current
+    /// platform)
     #[clap(long)]
-    platform: Option<String>,
+    platform: Option<Platform>,
 
+    /// Virtual packages to use instead of the ones detected on this system,
+    /// formatted as `name=version=build`
     #[clap(long)]
     virtual_package: Option<Vec<String>>,
 
+    /// The solver to use
     #[clap(long)]
     solver: Option<Solver>,
 
+    /// The maximum time in milliseconds the solver is allowed to run
     #[clap(long)]
     timeout: Option<u64>,
 
-    #[clap(long)]
+    /// The prefix to create the environment in (defaults to `.prefix` in the
+    /// current directory)
+    #[clap(short = 'p', long, visible_alias = "prefix")]
     target_prefix: Option<PathBuf>,
 
+    /// The directory to cache repodata and packages in (defaults to the
+    /// rattler cache directory)
+    #[clap(long)]
+    cache_dir: Option<PathBuf>,
+
+    /// The strategy to select versions of packages
     #[clap(long)]
     strategy: Option<SolveStrategy>,
 }
@@ -86,19 +102,20 @@ impl From<SolveStrategy> for rattler_solve::SolveStrategy {
 }
 
 pub async fn create(opt: Opt) -> anyhow::Result<()> {
-    let channel_config = ChannelConfig::default_with_root_dir(env::current_dir()?);
+    let mut config = RattlerConfig::load()?;
+    if let Some(cache_dir) = opt.cache_dir {
+        config.cache_dir = Some(cache_dir);
+    }
+
     let current_dir = env::current_dir()?;
+    let channel_config = config.channel_config(current_dir.clone());
     let target_prefix = opt
         .target_prefix
         .unwrap_or_else(|| current_dir.join(".prefix"));
     println!("Target prefix: {}", target_prefix.display());
 
     // Determine the platform we're going to install for
-    let install_platform = if let Some(platform) = opt.platform {
-        Platform::from_str(&platform)?
-    } else {
-        Platform::current()
-    };
+    let install_platform = opt.platform.unwrap_or_else(Platform::current);
 
     println!("Installing for platform: {install_platform:?}");
 
@@ -110,19 +127,20 @@ pub async fn create(opt: Opt) -> anyhow::Result<()> {
         .map(|spec| MatchSpec::from_str(spec, ParseStrictness::Strict))
         .collect::<Result<Vec<_>, _>>()?;
 
-    // Find the default cache directory. Create it if it doesnt exist yet.
-    let cache_dir = default_cache_dir()?;
+    // Find the cache directory. Create it if it doesnt exist yet.
+    let cache_dir = config.cache_dir()?;
     std::fs::create_dir_all(&cache_dir)
         .map_err(|e| anyhow::anyhow!("could not create cache directory: {}", e))?;
 
     // Determine the channels to use from the command line or select the default. Like matchspecs
     // this also requires the use of the `channel_config` so we have to do this manually.
-    let channels = opt
-        .channels
-        .unwrap_or_else(|| vec![String::from("conda-forge")])
-        .into_iter()
-        .map(|channel_str| Channel::from_str(channel_str, &channel_config))
-        .collect::<Result<Vec<_>, _>>()?;
+    let channels = match opt.channels {
+        Some(channels) => channels
+            .into_iter()
+            .map(|channel_str| Channel::from_str(channel_str, &channel_config))
+            .collect::<Result<Vec<_>, _>>()?,
+        None => config.channels(&channel_config)?,
+    };
 
     // Determine the packages that are currently installed in the environment.
     let installed_packages = PrefixRecord::collect_from_prefix(&target_prefix)?;
@@ -130,26 +148,15 @@ pub async fn create(opt: Opt) -> anyhow::Result<()> {
     // For each channel/subdirectory combination, download and cache the `repodata.json` that should
     // be available from the corresponding Url. The code below also displays a nice CLI progress-bar
     // to give users some more information about what is going on.
-    let download_client = Client::builder()
-        .no_gzip()
-        .build()
-        .expect("failed to create client");
-
-    let authentication_storage = AuthenticationStorage::default();
-    let download_client = reqwest_middleware::ClientBuilder::new(download_client)
-        .with_arc(Arc::new(AuthenticationMiddleware::new(
-            authentication_storage,
-        )))
-        .with(rattler_networking::OciMiddleware)
-        .with(rattler_networking::GCSMiddleware)
-        .build();
+    let download_client = download_client(&config)?;
+
+    // Packages are downloaded to the package cache in the cache directory.
+    let package_cache = PackageCache::new(config.package_cache_dir()?);
 
     // Get the package names from the matchspecs so we can only load the package records that we need.
     let gateway = Gateway::builder()
-        .with_cache_dir(cache_dir.join(rattler_cache::REPODATA_CACHE_DIR))
-        .with_package_cache(PackageCache::new(
-            cache_dir.join(rattler_cache::PACKAGE_CACHE_DIR),
-        ))
+        .with_config(&config)
+        .with_package_cache(package_cache.clone())
         .with_client(download_client.clone())
         .finish();
 
@@ -180,20 +187,10 @@ pub async fn create(opt: Opt) -> anyhow::Result<()> {
     // hardware of the system.
     let virtual_packages = wrap_in_progress("determining virtual packages", move || {
         if let Some(virtual_packages) = opt.virtual_package {
-            Ok(virtual_packages
+            virtual_packages
                 .iter()
-                .map(|virt_pkg| {
-                    let elems = virt_pkg.split('=').collect::<Vec<&str>>();
-                    Ok(GenericVirtualPackage {
-                        name: elems[0].try_into()?,
-                        version: elems
-                            .get(1)
-                            .map_or(Version::from_str("0"), |s| Version::from_str(s))
-                            .expect("Could not parse virtual package version"),
-                        build_string: (*elems.get(2).unwrap_or(&"")).to_string(),
-                    })
-                })
-                .collect::<anyhow::Result<Vec<_>>>()?)
+                .map(|virt_pkg| parse_virtual_package(virt_pkg))
+                .collect::<anyhow::Result<Vec<_>>>()
         } else {
             rattler_virtual_packages::VirtualPackage::detect(
                 &rattler_virtual_packages::VirtualPackageOverrides::default(),
@@ -229,6 +226,7 @@ pub async fn create(opt: Opt) -> anyhow::Result<()> {
         specs,
         timeout: opt.timeout.map(Duration::from_millis),
         strategy: opt.strategy.map_or_else(Default::default, Into::into),
+        channel_priority: config.channel_priority.into(),
         ..SolverTask::from_iter(&repo_data)
     };
 
@@ -259,7 +257,9 @@ pub async fn create(opt: Opt) -> anyhow::Result<()> {
 
     let install_start = Instant::now();
     let result = Installer::new()
+        .with_config(&config)
         .with_download_client(download_client)
+        .with_package_cache(package_cache)
         .with_target_platform(install_platform)
         .with_installed_packages(installed_packages)
         .with_execute_link_scripts(true)
@@ -288,6 +288,50 @@ pub async fn create(opt: Opt) -> anyhow::Result<()> {
     Ok(())
 }
 
+/// Constructs the client that is used for all network requests. The client
+/// uses the authentication, SSL and proxy settings from the configuration.
+pub(crate) fn download_client(config: &RattlerConfig) -> anyhow::Result<ClientWithMiddleware> {
+    let builder = config.reqwest_client_builder()?.no_gzip();
+
+    // HTTP/3 requires the rustls TLS backend.
+    #[cfg(feature = "http3")]
+    let builder = builder.use_rustls_tls();
+
+    let client = builder.build().context("failed to create client")?;
+
+    let builder = AuthenticatedClient::builder(client, config.authentication_storage()?)
+        .with(rattler_networking::OciMiddleware::default())
+        .with(rattler_networking::GCSMiddleware)
+        .with(rattler_networking::AzureMiddleware::default())
+        .with(rattler_networking::S3Middleware::default());
+
+    // Use HTTP/3 for hosts that advertise support for it.
+    #[cfg(feature = "http3")]
+    let builder = builder.with(rattler_networking::Http3Middleware::new());
+
+    Ok(builder.build().into_inner())
+}
+
+/// Parses a virtual package from a string formatted as `name=version=build`.
+/// The version and build string are optional.
+pub(crate) fn parse_virtual_package(virt_pkg: &str) -> anyhow::Result<GenericVirtualPackage> {
+    let mut elems = virt_pkg.split('=');
+    let name = elems.next().unwrap_or_default();
+    let version = elems.next().unwrap_or("0");
+    let build_string = elems.next().unwrap_or_default();
+    if elems.next().is_some() {
+        anyhow::bail!("invalid virtual package '{virt_pkg}', expected 'name=version=build'");
+    }
+
+    Ok(GenericVirtualPackage {
+        name: PackageName::try_from(name)
+            .with_context(|| format!("invalid name in virtual package '{virt_pkg}'"))?,
+        version: Version::from_str(version)
+            .with_context(|| format!("invalid version in virtual package '{virt_pkg}'"))?,
+        build_string: build_string.to_string(),
+    })
+}
+
 /// Prints the operations of the transaction to the console.
 fn print_transaction(transaction: &Transaction<PrefixRecord, RepoDataRecord>) {
     let format_record = |r: &RepoDataRecord| {
@@ -338,7 +382,10 @@ fn print_transaction(transaction: &Transaction<PrefixRecord, RepoDataRecord>) {
 }
 
 /// Displays a spinner with the given message while running the specified function to completion.
-fn wrap_in_progress<T, F: FnOnce() -> T>(msg: impl Into<Cow<'static, str>>, func: F) -> T {
+pub(crate) fn wrap_in_progress<T, F: FnOnce() -> T>(
+    msg: impl Into<Cow<'static, str>>,
+    func: F,
+) -> T {
     let pb = ProgressBar::new_spinner();
     pb.enable_steady_tick(Duration::from_millis(100));
     pb.set_style(long_running_progress_style());
@@ -349,7 +396,7 @@ fn wrap_in_progress<T, F: FnOnce() -> T>(msg: impl Into<Cow<'static, str>>, func
 }
 
 /// Displays a spinner with the given message while running the specified function to completion.
-async fn wrap_in_async_progress<T, F: IntoFuture<Output = T>>(
+pub(crate) async fn wrap_in_async_progress<T, F: IntoFuture<Output = T>>(
     msg: impl Into<Cow<'static, str>>,
     fut: F,
 ) -> T {
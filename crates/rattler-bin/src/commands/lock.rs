@@ -0,0 +1,223 @@
+use crate::commands::create::{
+    download_client, parse_virtual_package, wrap_in_async_progress, wrap_in_progress,
+};
+use anyhow::Context;
+use rattler_conda_types::{EnvironmentYaml, GenericVirtualPackage, Platform, Version};
+use rattler_config::RattlerConfig;
+use rattler_lock::{
+    CondaLockDependency, CondaLockSpecification, LockFileBuilder, DEFAULT_ENVIRONMENT_NAME,
+};
+use rattler_repodata_gateway::Gateway;
+use rattler_solve::{resolvo, SolverImpl, SolverTask};
+use std::{env, path::PathBuf, str::FromStr};
+
+/// Solve the packages of an `environment.yml` for a set of platforms and
+/// write the result to a lock file.
+#[derive(Debug, clap::Parser)]
+pub struct Opt {
+    /// The environment file to read the channels and specs from
+    #[clap(short, long, default_value = "environment.yml")]
+    file: PathBuf,
+
+    /// The platforms to solve the environment for (defaults to the current
+    /// platform)
+    #[clap(short, long = "platform")]
+    platforms: Vec<Platform>,
+
+    /// The path of the lock file to write
+    #[clap(short, long, default_value = "conda-lock.yml")]
+    output: PathBuf,
+
+    /// Virtual packages to use instead of the defaults of each platform,
+    /// formatted as `name=version=build`
+    #[clap(long)]
+    virtual_package: Option<Vec<String>>,
+
+    /// The directory to cache repodata in (defaults to the rattler cache
+    /// directory)
+    #[clap(long)]
+    cache_dir: Option<PathBuf>,
+}
+
+pub async fn lock(opt: Opt) -> anyhow::Result<()> {
+    let mut config = RattlerConfig::load()?;
+    if let Some(cache_dir) = opt.cache_dir {
+        config.cache_dir = Some(cache_dir);
+    }
+    let channel_config = config.channel_config(env::current_dir()?);
+
+    let environment = EnvironmentYaml::from_path(&opt.file)
+        .with_context(|| format!("failed to read {}", opt.file.display()))?;
+    if environment.pip_specs().is_some() {
+        anyhow::bail!(
+            "{} contains pip dependencies, which cannot be locked",
+            opt.file.display()
+        );
+    }
+
+    let specs = environment.match_specs().cloned().collect::<Vec<_>>();
+    let dependencies = specs
+        .iter()
+        .map(|spec| {
+            CondaLockDependency::from_match_spec(spec)
+                .with_context(|| format!("the spec '{spec}' does not have a package name"))
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    // Use the channels from the environment file or the configured channels.
+    // The channels of the environment file are recorded as they are written,
+    // like conda-lock does, to compute the same content hash.
+    let (channels, lock_channels): (Vec<_>, Vec<_>) = if environment.channels.is_empty() {
+        config
+            .channels(&channel_config)?
+            .into_iter()
+            .map(|channel| {
+                let url = channel.base_url.as_str().to_string();
+                (channel, url)
+            })
+            .unzip()
+    } else {
+        environment
+            .channels
+            .iter()
+            .map(|channel| {
+                (
+                    channel.clone().into_channel(&channel_config),
+                    channel.to_string(),
+                )
+            })
+            .unzip()
+    };
+
+    let platforms = if opt.platforms.is_empty() {
+        vec![Platform::current()]
+    } else {
+        opt.platforms
+    };
+
+    let virtual_package_overrides = opt
+        .virtual_package
+        .map(|virtual_packages| {
+            virtual_packages
+                .iter()
+                .map(|virt_pkg| parse_virtual_package(virt_pkg))
+                .collect::<anyhow::Result<Vec<_>>>()
+        })
+        .transpose()?;
+
+    // Find the cache directory. Create it if it doesnt exist yet.
+    let cache_dir = config.cache_dir()?;
+    std::fs::create_dir_all(&cache_dir)
+        .map_err(|e| anyhow::anyhow!("could not create cache directory: {}", e))?;
+
+    let gateway = Gateway::builder()
+        .with_config(&config)
+        .with_client(download_client(&config)?)
+        .finish();
+
+    let specification = CondaLockSpecification {
+        channels: lock_channels.into_iter().map(Into::into).collect(),
+        dependencies: platforms
+            .iter()
+            .map(|&platform| (platform, dependencies.clone()))
+            .collect(),
+    };
+
+    let mut solved = Vec::with_capacity(platforms.len());
+    for platform in platforms {
+        let repo_data = wrap_in_async_progress(
+            format!("loading repodata for {platform}"),
+            gateway
+                .query(
+                    channels.clone(),
+                    [platform, Platform::NoArch],
+                    specs.clone(),
+                )
+                .recursive(true),
+        )
+        .await
+        .with_context(|| format!("failed to load repodata for {platform}"))?;
+
+        let virtual_packages = match &virtual_package_overrides {
+            Some(virtual_packages) => virtual_packages.clone(),
+            None => default_virtual_packages(platform)?,
+        };
+
+        let solver_task = SolverTask {
+            virtual_packages,
+            specs: specs.clone(),
+            channel_priority: config.channel_priority.into(),
+            ..SolverTask::from_iter(&repo_data)
+        };
+
+        let records = wrap_in_progress(format!("solving for {platform}"), move || {
+            resolvo::Solver.solve(solver_task)
+        })
+        .with_context(|| format!("failed to solve the environment for {platform}"))?;
+
+        println!("Solved {} packages for {platform}", records.len());
+        solved.push((platform, records));
+    }
+
+    let lock_file = LockFileBuilder::new()
+        .with_solved_environment(DEFAULT_ENVIRONMENT_NAME, &specification, solved)
+        .finish();
+    lock_file
+        .to_path(&opt.output)
+        .with_context(|| format!("failed to write {}", opt.output.display()))?;
+
+    println!(
+        "{} Wrote {}",
+        console::style(console::Emoji("✔", "")).green(),
+        opt.output.display()
+    );
+
+    Ok(())
+}
+
+/// Returns the virtual packages to assume when solving for `platform`. For
+/// the current platform the virtual packages are detected from the system,
+/// for other platforms a conservative set of defaults is used.
+fn default_virtual_packages(platform: Platform) -> anyhow::Result<Vec<GenericVirtualPackage>> {
+    if platform == Platform::current() {
+        return Ok(rattler_virtual_packages::VirtualPackage::detect(
+            &rattler_virtual_packages::VirtualPackageOverrides::default(),
+        )?
+        .into_iter()
+        .map(GenericVirtualPackage::from)
+        .collect());
+    }
+
+    let mut virtual_packages = Vec::new();
+    let mut push = |name: &str, version: &str| -> anyhow::Result<()> {
+        virtual_packages.push(GenericVirtualPackage {
+            name: name.try_into()?,
+            version: Version::from_str(version)?,
+            build_string: String::new(),
+        });
+        Ok(())
+    };
+
+    if platform.is_unix() {
+        push("__unix", "0")?;
+    }
+    if platform.is_windows() {
+        push("__win", "0")?;
+    }
+    if platform.is_linux() {
+        push("__linux", "0")?;
+        push("__glibc", "2.17")?;
+    }
+    if platform.is_osx() {
+        push(
+            "__osx",
+            if platform == Platform::OsxArm64 {
+                "11.0"
+            } else {
+                "10.15"
+            },
+        )?;
+    }
+
+    Ok(virtual_packages)
+}
@@ -1,2 +1,3 @@
 pub mod create;
+pub mod lock;
 pub mod virtual_packages;
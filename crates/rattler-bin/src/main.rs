@@ -2,6 +2,8 @@ use crate::writer::IndicatifWriter;
 use clap::Parser;
 use indicatif::{MultiProgress, ProgressDrawTarget};
 use once_cell::sync::Lazy;
+#[cfg(feature = "opentelemetry")]
+use tracing_subscriber::layer::SubscriberExt;
 use tracing_subscriber::{filter::LevelFilter, util::SubscriberInitExt, EnvFilter};
 
 mod commands;
@@ -38,6 +40,7 @@ struct Opt {
 #[derive(Debug, clap::Subcommand)]
 enum Command {
     Create(commands::create::Opt),
+    Lock(commands::lock::Opt),
     VirtualPackages(commands::virtual_packages::Opt),
 }
 
@@ -61,16 +64,25 @@ async fn main() -> anyhow::Result<()> {
         .add_directive("apple_codesign=off".parse()?);
 
     // Setup the tracing subscriber
-    tracing_subscriber::fmt()
+    let subscriber = tracing_subscriber::fmt()
         .with_env_filter(env_filter)
         .with_writer(IndicatifWriter::new(global_multi_progress()))
         .without_time()
-        .finish()
-        .try_init()?;
+        .finish();
+
+    // Export spans to an OpenTelemetry collector
+    #[cfg(feature = "opentelemetry")]
+    let (subscriber, _otel_guard) = {
+        let (layer, guard) = rattler::telemetry::otlp_layer("rattler")?;
+        (subscriber.with(layer), guard)
+    };
+
+    subscriber.try_init()?;
 
     // Dispatch the selected comment
     match opt.command {
         Command::Create(opts) => commands::create::create(opts).await,
+        Command::Lock(opts) => commands::lock::lock(opts).await,
         Command::VirtualPackages(opts) => commands::virtual_packages::virtual_packages(opts),
     }
 }
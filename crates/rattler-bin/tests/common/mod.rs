@@ -0,0 +1,19 @@
+use std::path::Path;
+
+/// Creates a channel in `dir` that contains the packages of the dummy test
+/// channel and an empty `noarch` subdirectory.
+pub fn dummy_channel(dir: &Path) {
+    let test_data = Path::new(env!("CARGO_MANIFEST_DIR")).join("../../test-data/channels/dummy");
+    std::fs::create_dir_all(dir.join("linux-64")).unwrap();
+    std::fs::copy(
+        test_data.join("linux-64/repodata.json"),
+        dir.join("linux-64/repodata.json"),
+    )
+    .unwrap();
+    std::fs::create_dir_all(dir.join("noarch")).unwrap();
+    std::fs::write(
+        dir.join("noarch/repodata.json"),
+        r#"{"info": {"subdir": "noarch"}, "packages": {}}"#,
+    )
+    .unwrap();
+}
@@ -0,0 +1,37 @@
+mod common;
+
+use std::process::Command;
+
+use common::dummy_channel;
+
+#[test]
+fn test_create_dry_run() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let channel_dir = temp_dir.path().join("channel");
+    dummy_channel(&channel_dir);
+
+    let output = Command::new(env!("CARGO_BIN_EXE_rattler"))
+        .arg("create")
+        .arg("--dry-run")
+        .arg("--channel")
+        .arg(&channel_dir)
+        .args(["--platform", "linux-64"])
+        .args(["--virtual-package", "__unix=0"])
+        .arg("--prefix")
+        .arg(temp_dir.path().join("env"))
+        .arg("--cache-dir")
+        .arg(temp_dir.path().join("cache"))
+        .arg("foobar")
+        .output()
+        .unwrap();
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        output.status.success(),
+        "rattler create failed:\n{stdout}\n{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert!(stdout.contains("+ foobar 2.1 bla_1"), "{stdout}");
+    assert!(stdout.contains("+ bors 1.2.1 bla_1"), "{stdout}");
+    assert!(!temp_dir.path().join("env/conda-meta").exists());
+}
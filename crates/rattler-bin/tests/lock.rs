@@ -0,0 +1,94 @@
+mod common;
+
+use std::process::Command;
+
+use common::dummy_channel;
+use rattler_conda_types::Platform;
+use rattler_lock::{CondaLockDependency, CondaLockSpecification, LockFile};
+
+#[test]
+fn test_lock() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let channel_dir = temp_dir.path().join("channel");
+    dummy_channel(&channel_dir);
+
+    let environment_file = temp_dir.path().join("environment.yml");
+    std::fs::write(
+        &environment_file,
+        format!(
+            "channels:\n  - {}\ndependencies:\n  - foobar\n",
+            channel_dir.display()
+        ),
+    )
+    .unwrap();
+    let lock_file_path = temp_dir.path().join("conda-lock.yml");
+
+    let output = Command::new(env!("CARGO_BIN_EXE_rattler"))
+        .arg("lock")
+        .arg("--file")
+        .arg(&environment_file)
+        .args(["--platform", "linux-64"])
+        .args(["--virtual-package", "__unix=0"])
+        .arg("--output")
+        .arg(&lock_file_path)
+        .arg("--cache-dir")
+        .arg(temp_dir.path().join("cache"))
+        .output()
+        .unwrap();
+
+    assert!(
+        output.status.success(),
+        "rattler lock failed:\n{}\n{}",
+        String::from_utf8_lossy(&output.stdout),
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    let lock_file = LockFile::from_path(&lock_file_path).unwrap();
+    let environment = lock_file.default_environment().unwrap();
+    let mut names = environment
+        .packages(Platform::Linux64)
+        .unwrap()
+        .map(|package| package.name().into_owned())
+        .collect::<Vec<_>>();
+    names.sort();
+    assert_eq!(names, ["bors", "foobar"]);
+
+    // The content hash is computed from the input like conda-lock does.
+    let specification = CondaLockSpecification {
+        channels: vec![channel_dir.display().to_string().into()],
+        dependencies: [(
+            Platform::Linux64,
+            vec![CondaLockDependency::conda("foobar", "")],
+        )]
+        .into(),
+    };
+    assert_eq!(
+        environment.content_hash(Platform::Linux64),
+        Some(&specification.content_hash_for_platform(Platform::Linux64))
+    );
+}
+
+#[test]
+fn test_lock_pip_dependencies() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let environment_file = temp_dir.path().join("environment.yml");
+    std::fs::write(
+        &environment_file,
+        "dependencies:\n  - python\n  - pip:\n    - requests\n",
+    )
+    .unwrap();
+    let lock_file_path = temp_dir.path().join("conda-lock.yml");
+
+    let output = Command::new(env!("CARGO_BIN_EXE_rattler"))
+        .arg("lock")
+        .arg("--file")
+        .arg(&environment_file)
+        .arg("--output")
+        .arg(&lock_file_path)
+        .output()
+        .unwrap();
+
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("pip dependencies"));
+    assert!(!lock_file_path.exists());
+}
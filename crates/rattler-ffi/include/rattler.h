@@ -0,0 +1,72 @@
+/*
+ * C API for rattler.
+ *
+ * Objects are returned as opaque pointers that are owned by the caller and
+ * must be released with the matching `*_free` function. Strings returned by
+ * the library must be released with `rattler_string_free`.
+ *
+ * Functions that return a pointer return NULL on failure, functions that
+ * return an int return 0 on success and -1 on failure. The message of the
+ * last error on the calling thread can be retrieved with
+ * `rattler_last_error_message`. `rattler_version_compare` returns INT_MIN
+ * and the `*_len` functions return 0 on failure.
+ */
+
+#ifndef RATTLER_H
+#define RATTLER_H
+
+#include <stddef.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct RattlerVersion RattlerVersion;
+typedef struct RattlerMatchSpec RattlerMatchSpec;
+typedef struct RattlerRepoData RattlerRepoData;
+typedef struct RattlerRecords RattlerRecords;
+
+/* Errors and strings */
+const char *rattler_last_error_message(void);
+void rattler_string_free(char *s);
+
+/* Versions */
+RattlerVersion *rattler_version_parse(const char *version);
+void rattler_version_free(RattlerVersion *version);
+char *rattler_version_to_string(const RattlerVersion *version);
+int rattler_version_compare(const RattlerVersion *a, const RattlerVersion *b);
+
+/* Match specs */
+RattlerMatchSpec *rattler_match_spec_parse(const char *spec, int strict);
+void rattler_match_spec_free(RattlerMatchSpec *spec);
+char *rattler_match_spec_to_string(const RattlerMatchSpec *spec);
+char *rattler_match_spec_name(const RattlerMatchSpec *spec);
+
+/* Repodata */
+RattlerRepoData *rattler_fetch_repo_data(const char *const *channels, size_t channels_len,
+                                         const char *const *platforms, size_t platforms_len,
+                                         const char *const *specs, size_t specs_len,
+                                         const char *cache_dir);
+size_t rattler_repo_data_len(const RattlerRepoData *repo_data);
+void rattler_repo_data_free(RattlerRepoData *repo_data);
+
+/* Solving */
+RattlerRecords *rattler_solve(const RattlerRepoData *repo_data,
+                              const char *const *specs, size_t specs_len,
+                              const char *const *virtual_packages, size_t virtual_packages_len);
+size_t rattler_records_len(const RattlerRecords *records);
+char *rattler_records_name(const RattlerRecords *records, size_t index);
+char *rattler_records_version(const RattlerRecords *records, size_t index);
+char *rattler_records_build(const RattlerRecords *records, size_t index);
+char *rattler_records_url(const RattlerRecords *records, size_t index);
+void rattler_records_free(RattlerRecords *records);
+
+/* Environments */
+int rattler_create_environment(const RattlerRecords *records, const char *prefix,
+                               const char *platform);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* RATTLER_H */
@@ -0,0 +1,82 @@
+//! Error reporting across the FFI boundary.
+
+use std::{
+    cell::RefCell,
+    ffi::{c_char, CString},
+    panic::{catch_unwind, AssertUnwindSafe},
+};
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+/// Stores the error as the last error of the current thread.
+fn set_last_error(message: String) {
+    let message =
+        CString::new(message.replace('\0', "\\0")).expect("interior nul bytes have been escaped");
+    LAST_ERROR.with(|last| *last.borrow_mut() = Some(message));
+}
+
+/// Runs `f` and converts errors and panics into `default`. The error message
+/// is stored so that it can be retrieved with
+/// [`rattler_last_error_message`].
+pub(crate) fn ffi_call<T>(default: T, f: impl FnOnce() -> anyhow::Result<T>) -> T {
+    match catch_unwind(AssertUnwindSafe(f)) {
+        Ok(Ok(value)) => value,
+        Ok(Err(err)) => {
+            set_last_error(format!("{err:#}"));
+            default
+        }
+        Err(panic) => {
+            let message = panic
+                .downcast_ref::<&str>()
+                .map(ToString::to_string)
+                .or_else(|| panic.downcast_ref::<String>().cloned())
+                .unwrap_or_else(|| String::from("unknown panic"));
+            set_last_error(format!("rattler panicked: {message}"));
+            default
+        }
+    }
+}
+
+/// Dereferences a handle that was passed to the library and fails if it is
+/// `NULL`.
+///
+/// # Safety
+///
+/// `ptr` must either be `NULL` or point to a valid `T` that outlives `'a`.
+pub(crate) unsafe fn handle_from_ptr<'a, T>(ptr: *const T, what: &str) -> anyhow::Result<&'a T> {
+    ptr.as_ref()
+        .ok_or_else(|| anyhow::anyhow!("{what} must not be NULL"))
+}
+
+/// Returns the message of the last error that occurred on the calling thread
+/// or `NULL` if no error occurred.
+///
+/// The returned string is owned by the library and remains valid until the
+/// next error occurs on the same thread. It must not be freed.
+#[no_mangle]
+pub extern "C" fn rattler_last_error_message() -> *const c_char {
+    LAST_ERROR.with(|last| {
+        last.borrow()
+            .as_ref()
+            .map_or(std::ptr::null(), |message| message.as_ptr())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ffi::CStr;
+
+    #[test]
+    fn test_errors_and_panics_are_recorded() {
+        assert_eq!(ffi_call(0, || Err(anyhow::anyhow!("boom"))), 0);
+        let message = unsafe { CStr::from_ptr(rattler_last_error_message()) };
+        assert_eq!(message.to_str().unwrap(), "boom");
+
+        assert_eq!(ffi_call(0, || -> anyhow::Result<i32> { panic!("oops") }), 0);
+        let message = unsafe { CStr::from_ptr(rattler_last_error_message()) };
+        assert_eq!(message.to_str().unwrap(), "rattler panicked: oops");
+    }
+}
@@ -0,0 +1,57 @@
+//! Creating environments.
+
+use std::{
+    ffi::{c_char, c_int},
+    str::FromStr,
+};
+
+use rattler::install::Installer;
+use rattler_conda_types::Platform;
+
+use crate::{
+    download_client,
+    error::{ffi_call, handle_from_ptr},
+    string::str_from_ptr,
+    RattlerRecords, RUNTIME,
+};
+
+/// Installs the given records into the prefix at `prefix`. If the prefix
+/// does not exist yet it is created, otherwise the environment is updated so
+/// that it contains exactly the given records.
+///
+/// `platform` is the platform the environment is created for, if it is `NULL`
+/// the current platform is used.
+///
+/// Returns `0` on success and `-1` on failure.
+///
+/// # Safety
+///
+/// `records` must either be `NULL` or a valid records handle. `prefix` must be
+/// a valid nul-terminated string. `platform` must either be `NULL` or a valid
+/// nul-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn rattler_create_environment(
+    records: *const RattlerRecords,
+    prefix: *const c_char,
+    platform: *const c_char,
+) -> c_int {
+    ffi_call(-1, || {
+        let records = handle_from_ptr(records, "records")?.0.clone();
+        let prefix = str_from_ptr(prefix, "prefix")?;
+        let platform = if platform.is_null() {
+            Platform::current()
+        } else {
+            Platform::from_str(str_from_ptr(platform, "platform")?)?
+        };
+
+        RUNTIME.block_on(
+            Installer::new()
+                .with_download_client(download_client()?.into_inner())
+                .with_target_platform(platform)
+                .with_execute_link_scripts(true)
+                .install(prefix, records),
+        )?;
+
+        Ok(0)
+    })
+}
@@ -0,0 +1,67 @@
+#![deny(missing_docs)]
+
+//! A stable C ABI for rattler.
+//!
+//! This crate allows package managers, editors and other tools that are not
+//! written in Rust to embed rattler. It exposes parsing of versions and match
+//! specs, fetching repodata, solving environments and installing the result
+//! into a prefix. The accompanying header can be found in
+//! `include/rattler.h`.
+//!
+//! All functions follow the same conventions:
+//!
+//! * Objects are returned as opaque pointers which are owned by the caller and
+//!   must be released with the matching `*_free` function.
+//! * Strings returned by the library are owned by the caller and must be
+//!   released with [`rattler_string_free`].
+//! * Functions that return a pointer return `NULL` on failure, functions that
+//!   return an `int` return `0` on success and `-1` on failure. A description
+//!   of the error can be retrieved with [`rattler_last_error_message`].
+//! * Panics never unwind across the FFI boundary, they are reported as errors
+//!   instead.
+
+mod error;
+mod install;
+mod match_spec;
+mod repodata;
+mod solve;
+mod string;
+mod version;
+
+pub use error::rattler_last_error_message;
+pub use install::rattler_create_environment;
+pub use match_spec::{
+    rattler_match_spec_free, rattler_match_spec_name, rattler_match_spec_parse,
+    rattler_match_spec_to_string, RattlerMatchSpec,
+};
+pub use repodata::{
+    rattler_fetch_repo_data, rattler_repo_data_free, rattler_repo_data_len, RattlerRepoData,
+};
+pub use solve::{
+    rattler_records_build, rattler_records_free, rattler_records_len, rattler_records_name,
+    rattler_records_url, rattler_records_version, rattler_solve, RattlerRecords,
+};
+pub use string::rattler_string_free;
+pub use version::{
+    rattler_version_compare, rattler_version_free, rattler_version_parse,
+    rattler_version_to_string, RattlerVersion,
+};
+
+use once_cell::sync::Lazy;
+use rattler_networking::AuthenticatedClient;
+
+/// The runtime that is used to drive all asynchronous operations. Callers of
+/// the C API don't have to know anything about async Rust.
+static RUNTIME: Lazy<tokio::runtime::Runtime> = Lazy::new(|| {
+    tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .expect("failed to create the tokio runtime")
+});
+
+/// Constructs the client that is used for all network requests. Credentials
+/// are read from the default authentication storage.
+fn download_client() -> anyhow::Result<AuthenticatedClient> {
+    let client = reqwest::Client::builder().no_gzip().build()?;
+    AuthenticatedClient::from_env(client)
+}
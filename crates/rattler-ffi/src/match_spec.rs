@@ -0,0 +1,113 @@
+//! Parsing match specs.
+
+use std::ffi::{c_char, c_int};
+
+use rattler_conda_types::{MatchSpec, ParseStrictness};
+
+use crate::{
+    error::{ffi_call, handle_from_ptr},
+    string::{into_c_string, str_from_ptr},
+};
+
+/// An opaque handle to a parsed match spec.
+pub struct RattlerMatchSpec(MatchSpec);
+
+/// Parses a match spec. If `strict` is non-zero the spec is parsed with
+/// [`ParseStrictness::Strict`], otherwise ambiguous specs are accepted too.
+/// Returns `NULL` if the spec is invalid.
+///
+/// # Safety
+///
+/// `spec` must point to a valid nul-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn rattler_match_spec_parse(
+    spec: *const c_char,
+    strict: c_int,
+) -> *mut RattlerMatchSpec {
+    ffi_call(std::ptr::null_mut(), || {
+        let strictness = if strict != 0 {
+            ParseStrictness::Strict
+        } else {
+            ParseStrictness::Lenient
+        };
+        let spec = MatchSpec::from_str(str_from_ptr(spec, "spec")?, strictness)?;
+        Ok(Box::into_raw(Box::new(RattlerMatchSpec(spec))))
+    })
+}
+
+/// Releases a match spec.
+///
+/// # Safety
+///
+/// `spec` must either be `NULL` or a match spec returned by
+/// [`rattler_match_spec_parse`] that has not been freed before.
+#[no_mangle]
+pub unsafe extern "C" fn rattler_match_spec_free(spec: *mut RattlerMatchSpec) {
+    if !spec.is_null() {
+        drop(Box::from_raw(spec));
+    }
+}
+
+/// Returns the string representation of a match spec. The result must be
+/// released with [`crate::rattler_string_free`].
+///
+/// # Safety
+///
+/// `spec` must either be `NULL` or a valid match spec.
+#[no_mangle]
+pub unsafe extern "C" fn rattler_match_spec_to_string(
+    spec: *const RattlerMatchSpec,
+) -> *mut c_char {
+    ffi_call(std::ptr::null_mut(), || {
+        into_c_string(handle_from_ptr(spec, "spec")?.0.to_string())
+    })
+}
+
+/// Returns the normalized name of the package the spec matches or `NULL` if
+/// the spec does not contain a name. The result must be released with
+/// [`crate::rattler_string_free`].
+///
+/// # Safety
+///
+/// `spec` must either be `NULL` or a valid match spec.
+#[no_mangle]
+pub unsafe extern "C" fn rattler_match_spec_name(spec: *const RattlerMatchSpec) -> *mut c_char {
+    ffi_call(std::ptr::null_mut(), || {
+        match &handle_from_ptr(spec, "spec")?.0.name {
+            Some(name) => into_c_string(name.as_normalized()),
+            None => Ok(std::ptr::null_mut()),
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rattler_string_free;
+    use std::ffi::{CStr, CString};
+
+    #[test]
+    fn test_parse_match_spec() {
+        let spec = CString::new("conda-forge::Python >=3.8,<4").unwrap();
+        unsafe {
+            let spec = rattler_match_spec_parse(spec.as_ptr(), 1);
+            assert!(!spec.is_null());
+
+            let name = rattler_match_spec_name(spec);
+            assert_eq!(CStr::from_ptr(name).to_str().unwrap(), "python");
+            rattler_string_free(name);
+
+            let s = rattler_match_spec_to_string(spec);
+            assert_eq!(
+                CStr::from_ptr(s).to_str().unwrap(),
+                "conda-forge::python >=3.8,<4"
+            );
+            rattler_string_free(s);
+
+            rattler_match_spec_free(spec);
+
+            assert!(rattler_match_spec_to_string(std::ptr::null()).is_null());
+            assert!(rattler_match_spec_name(std::ptr::null()).is_null());
+        }
+    }
+}
@@ -0,0 +1,113 @@
+//! Fetching repodata.
+
+use std::{ffi::c_char, future::IntoFuture, path::PathBuf, str::FromStr};
+
+use rattler_conda_types::{Channel, ChannelConfig, MatchSpec, ParseStrictness, Platform};
+use rattler_repodata_gateway::{Gateway, RepoData};
+
+use crate::{
+    download_client,
+    error::{ffi_call, handle_from_ptr},
+    string::{str_from_ptr, strs_from_array},
+    RUNTIME,
+};
+
+/// An opaque handle to the repodata records fetched for a set of channels.
+pub struct RattlerRepoData(pub(crate) Vec<RepoData>);
+
+/// Fetches the records from the given channels and platforms that are
+/// required to solve the given specs. Dependencies of the specs are fetched
+/// recursively.
+///
+/// Channels are either names (e.g. `conda-forge`) or URLs. Platforms are conda
+/// subdirs like `linux-64`, if `platforms_len` is `0` the current platform
+/// and `noarch` are used. Repodata is cached in `cache_dir`, or in the default
+/// cache directory if `cache_dir` is `NULL`.
+///
+/// Returns `NULL` on failure.
+///
+/// # Safety
+///
+/// `channels`, `platforms` and `specs` must point to arrays of respectively
+/// `channels_len`, `platforms_len` and `specs_len` valid nul-terminated
+/// strings. `cache_dir` must either be `NULL` or a valid nul-terminated
+/// string.
+#[no_mangle]
+pub unsafe extern "C" fn rattler_fetch_repo_data(
+    channels: *const *const c_char,
+    channels_len: usize,
+    platforms: *const *const c_char,
+    platforms_len: usize,
+    specs: *const *const c_char,
+    specs_len: usize,
+    cache_dir: *const c_char,
+) -> *mut RattlerRepoData {
+    ffi_call(std::ptr::null_mut(), || {
+        let channel_config = ChannelConfig::default_with_root_dir(std::env::current_dir()?);
+        let channels = strs_from_array(channels, channels_len, "channel")?
+            .into_iter()
+            .map(|channel| Channel::from_str(channel, &channel_config))
+            .collect::<Result<Vec<_>, _>>()?;
+        let platforms = if platforms_len == 0 {
+            vec![Platform::current(), Platform::NoArch]
+        } else {
+            strs_from_array(platforms, platforms_len, "platform")?
+                .into_iter()
+                .map(Platform::from_str)
+                .collect::<Result<Vec<_>, _>>()?
+        };
+        let specs = strs_from_array(specs, specs_len, "spec")?
+            .into_iter()
+            .map(|spec| MatchSpec::from_str(spec, ParseStrictness::Lenient))
+            .collect::<Result<Vec<_>, _>>()?;
+        let cache_dir = if cache_dir.is_null() {
+            rattler_cache::default_cache_dir()?
+        } else {
+            PathBuf::from(str_from_ptr(cache_dir, "cache_dir")?)
+        };
+
+        let gateway = Gateway::builder()
+            .with_cache_dir(cache_dir.join(rattler_cache::REPODATA_CACHE_DIR))
+            .with_client(download_client()?.into_inner())
+            .finish();
+
+        let repo_data = RUNTIME.block_on(
+            gateway
+                .query(channels, platforms, specs)
+                .recursive(true)
+                .into_future(),
+        )?;
+
+        Ok(Box::into_raw(Box::new(RattlerRepoData(repo_data))))
+    })
+}
+
+/// Returns the total number of records in the repodata, or `0` if
+/// `repo_data` is `NULL`.
+///
+/// # Safety
+///
+/// `repo_data` must either be `NULL` or a valid repodata handle.
+#[no_mangle]
+pub unsafe extern "C" fn rattler_repo_data_len(repo_data: *const RattlerRepoData) -> usize {
+    ffi_call(0, || {
+        Ok(handle_from_ptr(repo_data, "repo_data")?
+            .0
+            .iter()
+            .map(RepoData::len)
+            .sum())
+    })
+}
+
+/// Releases repodata.
+///
+/// # Safety
+///
+/// `repo_data` must either be `NULL` or a handle returned by
+/// [`rattler_fetch_repo_data`] that has not been freed before.
+#[no_mangle]
+pub unsafe extern "C" fn rattler_repo_data_free(repo_data: *mut RattlerRepoData) {
+    if !repo_data.is_null() {
+        drop(Box::from_raw(repo_data));
+    }
+}
@@ -0,0 +1,200 @@
+//! Solving environments.
+
+use std::{ffi::c_char, str::FromStr};
+
+use anyhow::Context;
+use rattler_conda_types::{
+    GenericVirtualPackage, MatchSpec, PackageName, ParseStrictness, RepoDataRecord, Version,
+};
+use rattler_solve::{resolvo, SolverImpl, SolverTask};
+use rattler_virtual_packages::VirtualPackages;
+
+use crate::{
+    error::{ffi_call, handle_from_ptr},
+    string::{into_c_string, strs_from_array},
+    RattlerRepoData,
+};
+
+/// An opaque handle to a list of records, e.g. the result of a solve.
+pub struct RattlerRecords(pub(crate) Vec<RepoDataRecord>);
+
+/// Solves the given specs using the records in `repo_data`.
+///
+/// Virtual packages are specified as `name=version=build` strings, the
+/// version and build are optional. If `virtual_packages` is `NULL` the virtual
+/// packages of the current system are detected instead.
+///
+/// Returns `NULL` on failure, for instance if the specs cannot be satisfied.
+///
+/// # Safety
+///
+/// `repo_data` must either be `NULL` or a valid repodata handle. `specs` must
+/// point to an array of `specs_len` valid nul-terminated strings.
+/// `virtual_packages` must either be `NULL` or point to an array of
+/// `virtual_packages_len` valid nul-terminated strings.
+#[no_mangle]
+pub unsafe extern "C" fn rattler_solve(
+    repo_data: *const RattlerRepoData,
+    specs: *const *const c_char,
+    specs_len: usize,
+    virtual_packages: *const *const c_char,
+    virtual_packages_len: usize,
+) -> *mut RattlerRecords {
+    ffi_call(std::ptr::null_mut(), || {
+        let repo_data = &handle_from_ptr(repo_data, "repo_data")?.0;
+        let specs = strs_from_array(specs, specs_len, "spec")?
+            .into_iter()
+            .map(|spec| MatchSpec::from_str(spec, ParseStrictness::Lenient))
+            .collect::<Result<Vec<_>, _>>()?;
+        let virtual_packages = if virtual_packages.is_null() {
+            VirtualPackages::current()?
+                .iter()
+                .cloned()
+                .map(GenericVirtualPackage::from)
+                .collect()
+        } else {
+            strs_from_array(virtual_packages, virtual_packages_len, "virtual package")?
+                .into_iter()
+                .map(parse_virtual_package)
+                .collect::<anyhow::Result<Vec<_>>>()?
+        };
+
+        let task = SolverTask {
+            specs,
+            virtual_packages,
+            ..SolverTask::from_iter(repo_data)
+        };
+        let records = resolvo::Solver.solve(task)?;
+
+        Ok(Box::into_raw(Box::new(RattlerRecords(records))))
+    })
+}
+
+/// Parses a virtual package from a `name=version=build` string.
+fn parse_virtual_package(s: &str) -> anyhow::Result<GenericVirtualPackage> {
+    let mut parts = s.splitn(3, '=');
+    let name = PackageName::try_from(parts.next().unwrap_or_default())?;
+    let version = Version::from_str(parts.next().unwrap_or("0"))
+        .with_context(|| format!("invalid version in virtual package '{s}'"))?;
+    let build_string = parts.next().unwrap_or_default().to_string();
+    Ok(GenericVirtualPackage {
+        name,
+        version,
+        build_string,
+    })
+}
+
+/// Returns the number of records, or `0` if `records` is `NULL`.
+///
+/// # Safety
+///
+/// `records` must either be `NULL` or a valid records handle.
+#[no_mangle]
+pub unsafe extern "C" fn rattler_records_len(records: *const RattlerRecords) -> usize {
+    ffi_call(0, || Ok(handle_from_ptr(records, "records")?.0.len()))
+}
+
+/// Calls `f` with the record at `index` and converts the result into a C
+/// string.
+unsafe fn record_field(
+    records: *const RattlerRecords,
+    index: usize,
+    f: impl FnOnce(&RepoDataRecord) -> String,
+) -> *mut c_char {
+    ffi_call(std::ptr::null_mut(), || {
+        let record = handle_from_ptr(records, "records")?
+            .0
+            .get(index)
+            .ok_or_else(|| anyhow::anyhow!("record index {index} is out of bounds"))?;
+        into_c_string(f(record))
+    })
+}
+
+/// Returns the normalized package name of the record at `index`. The result
+/// must be released with [`crate::rattler_string_free`].
+///
+/// # Safety
+///
+/// `records` must either be `NULL` or a valid records handle.
+#[no_mangle]
+pub unsafe extern "C" fn rattler_records_name(
+    records: *const RattlerRecords,
+    index: usize,
+) -> *mut c_char {
+    record_field(records, index, |r| {
+        r.package_record.name.as_normalized().to_string()
+    })
+}
+
+/// Returns the version of the record at `index`. The result must be released
+/// with [`crate::rattler_string_free`].
+///
+/// # Safety
+///
+/// `records` must either be `NULL` or a valid records handle.
+#[no_mangle]
+pub unsafe extern "C" fn rattler_records_version(
+    records: *const RattlerRecords,
+    index: usize,
+) -> *mut c_char {
+    record_field(records, index, |r| r.package_record.version.to_string())
+}
+
+/// Returns the build string of the record at `index`. The result must be
+/// released with [`crate::rattler_string_free`].
+///
+/// # Safety
+///
+/// `records` must either be `NULL` or a valid records handle.
+#[no_mangle]
+pub unsafe extern "C" fn rattler_records_build(
+    records: *const RattlerRecords,
+    index: usize,
+) -> *mut c_char {
+    record_field(records, index, |r| r.package_record.build.clone())
+}
+
+/// Returns the URL of the package archive of the record at `index`. The
+/// result must be released with [`crate::rattler_string_free`].
+///
+/// # Safety
+///
+/// `records` must either be `NULL` or a valid records handle.
+#[no_mangle]
+pub unsafe extern "C" fn rattler_records_url(
+    records: *const RattlerRecords,
+    index: usize,
+) -> *mut c_char {
+    record_field(records, index, |r| r.url.to_string())
+}
+
+/// Releases records.
+///
+/// # Safety
+///
+/// `records` must either be `NULL` or a handle returned by [`rattler_solve`]
+/// that has not been freed before.
+#[no_mangle]
+pub unsafe extern "C" fn rattler_records_free(records: *mut RattlerRecords) {
+    if !records.is_null() {
+        drop(Box::from_raw(records));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_virtual_package() {
+        let package = parse_virtual_package("__cuda=12.2").unwrap();
+        assert_eq!(package.name.as_normalized(), "__cuda");
+        assert_eq!(package.version.to_string(), "12.2");
+        assert_eq!(package.build_string, "");
+
+        let package = parse_virtual_package("__glibc=2.17=0").unwrap();
+        assert_eq!(package.build_string, "0");
+
+        assert!(parse_virtual_package("__unix").is_ok());
+    }
+}
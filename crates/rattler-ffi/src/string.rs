@@ -0,0 +1,59 @@
+//! Conversions between C strings and Rust strings.
+
+use std::ffi::{c_char, CStr, CString};
+
+use anyhow::Context;
+
+/// Converts a nul-terminated C string into a `&str`.
+///
+/// # Safety
+///
+/// `ptr` must either be `NULL` or point to a valid nul-terminated string that
+/// outlives `'a`.
+pub(crate) unsafe fn str_from_ptr<'a>(ptr: *const c_char, what: &str) -> anyhow::Result<&'a str> {
+    anyhow::ensure!(!ptr.is_null(), "{what} must not be NULL");
+    CStr::from_ptr(ptr)
+        .to_str()
+        .with_context(|| format!("{what} is not valid UTF-8"))
+}
+
+/// Converts an array of `len` nul-terminated C strings into a `Vec<&str>`.
+///
+/// # Safety
+///
+/// `ptr` must either be `NULL` (only if `len` is `0`) or point to `len` valid
+/// nul-terminated strings that outlive `'a`.
+pub(crate) unsafe fn strs_from_array<'a>(
+    ptr: *const *const c_char,
+    len: usize,
+    what: &str,
+) -> anyhow::Result<Vec<&'a str>> {
+    if len == 0 {
+        return Ok(Vec::new());
+    }
+    anyhow::ensure!(!ptr.is_null(), "{what} must not be NULL");
+    std::slice::from_raw_parts(ptr, len)
+        .iter()
+        .map(|&s| str_from_ptr(s, what))
+        .collect()
+}
+
+/// Converts a Rust string into a C string that is owned by the caller.
+pub(crate) fn into_c_string(s: impl Into<Vec<u8>>) -> anyhow::Result<*mut c_char> {
+    Ok(CString::new(s)
+        .context("string contains an interior nul byte")?
+        .into_raw())
+}
+
+/// Releases a string that was returned by the library.
+///
+/// # Safety
+///
+/// `s` must either be `NULL` or a string returned by one of the functions of
+/// this library that has not been freed before.
+#[no_mangle]
+pub unsafe extern "C" fn rattler_string_free(s: *mut c_char) {
+    if !s.is_null() {
+        drop(CString::from_raw(s));
+    }
+}
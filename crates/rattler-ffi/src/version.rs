@@ -0,0 +1,133 @@
+//! Parsing and comparing versions.
+
+use std::{
+    cmp::Ordering,
+    ffi::{c_char, c_int},
+    str::FromStr,
+};
+
+use rattler_conda_types::Version;
+
+use crate::{
+    error::{ffi_call, handle_from_ptr},
+    string::{into_c_string, str_from_ptr},
+};
+
+/// An opaque handle to a parsed version.
+pub struct RattlerVersion(Version);
+
+/// Parses a version string. Returns `NULL` if the version is invalid.
+///
+/// # Safety
+///
+/// `version` must point to a valid nul-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn rattler_version_parse(version: *const c_char) -> *mut RattlerVersion {
+    ffi_call(std::ptr::null_mut(), || {
+        let version = Version::from_str(str_from_ptr(version, "version")?)?;
+        Ok(Box::into_raw(Box::new(RattlerVersion(version))))
+    })
+}
+
+/// Releases a version.
+///
+/// # Safety
+///
+/// `version` must either be `NULL` or a version returned by
+/// [`rattler_version_parse`] that has not been freed before.
+#[no_mangle]
+pub unsafe extern "C" fn rattler_version_free(version: *mut RattlerVersion) {
+    if !version.is_null() {
+        drop(Box::from_raw(version));
+    }
+}
+
+/// Returns the canonical string representation of a version. The result
+/// must be released with [`crate::rattler_string_free`].
+///
+/// # Safety
+///
+/// `version` must either be `NULL` or a valid version.
+#[no_mangle]
+pub unsafe extern "C" fn rattler_version_to_string(version: *const RattlerVersion) -> *mut c_char {
+    ffi_call(std::ptr::null_mut(), || {
+        into_c_string(handle_from_ptr(version, "version")?.0.to_string())
+    })
+}
+
+/// Compares two versions. Returns a negative value if `a` is smaller than `b`,
+/// `0` if both are equal and a positive value if `a` is larger than `b`.
+/// Returns `INT_MIN` if either version is `NULL`.
+///
+/// # Safety
+///
+/// `a` and `b` must either be `NULL` or valid versions.
+#[no_mangle]
+pub unsafe extern "C" fn rattler_version_compare(
+    a: *const RattlerVersion,
+    b: *const RattlerVersion,
+) -> c_int {
+    ffi_call(c_int::MIN, || {
+        let a = &handle_from_ptr(a, "a")?.0;
+        let b = &handle_from_ptr(b, "b")?.0;
+        Ok(match a.cmp(b) {
+            Ordering::Less => -1,
+            Ordering::Equal => 0,
+            Ordering::Greater => 1,
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{rattler_last_error_message, rattler_string_free};
+    use std::ffi::{CStr, CString};
+
+    #[test]
+    fn test_parse_and_compare() {
+        let a = CString::new("1.2.3").unwrap();
+        let b = CString::new("1.10").unwrap();
+        unsafe {
+            let a = rattler_version_parse(a.as_ptr());
+            let b = rattler_version_parse(b.as_ptr());
+            assert!(!a.is_null() && !b.is_null());
+            assert_eq!(rattler_version_compare(a, b), -1);
+            assert_eq!(rattler_version_compare(b, a), 1);
+            assert_eq!(rattler_version_compare(a, a), 0);
+
+            let s = rattler_version_to_string(a);
+            assert_eq!(CStr::from_ptr(s).to_str().unwrap(), "1.2.3");
+            rattler_string_free(s);
+
+            rattler_version_free(a);
+            rattler_version_free(b);
+        }
+    }
+
+    #[test]
+    fn test_null_version() {
+        let a = CString::new("1.2.3").unwrap();
+        unsafe {
+            let a = rattler_version_parse(a.as_ptr());
+            assert_eq!(rattler_version_compare(a, std::ptr::null()), c_int::MIN);
+            assert_eq!(
+                CStr::from_ptr(rattler_last_error_message())
+                    .to_str()
+                    .unwrap(),
+                "b must not be NULL"
+            );
+            assert!(rattler_version_to_string(std::ptr::null()).is_null());
+            rattler_version_free(a);
+        }
+    }
+
+    #[test]
+    fn test_invalid_version() {
+        let invalid = CString::new("").unwrap();
+        unsafe {
+            assert!(rattler_version_parse(invalid.as_ptr()).is_null());
+            assert!(!rattler_last_error_message().is_null());
+        }
+    }
+}
@@ -0,0 +1,93 @@
+//! Blocking versions of the asynchronous APIs of this crate.
+//!
+//! The types in this module drive the asynchronous APIs to completion on a
+//! runtime that is managed by this crate. This allows installing environments
+//! from code that is not async, like CLI tools or build scripts, without
+//! having to set up a runtime.
+//!
+//! The functions in this module must not be called from within an async
+//! runtime, they will panic if they are.
+
+use std::{future::Future, path::Path, sync::OnceLock};
+
+use rattler_conda_types::RepoDataRecord;
+use tokio::runtime::{Handle, Runtime};
+
+use crate::install::{InstallationResult, InstallerError};
+
+/// Returns the runtime that drives all blocking operations.
+fn runtime() -> &'static Runtime {
+    static RUNTIME: OnceLock<Runtime> = OnceLock::new();
+    RUNTIME.get_or_init(|| {
+        tokio::runtime::Builder::new_multi_thread()
+            .enable_all()
+            .thread_name("rattler-blocking")
+            .build()
+            .expect("failed to create the tokio runtime")
+    })
+}
+
+/// Runs the future to completion on the runtime of this module.
+fn block_on<F: Future>(future: F) -> F::Output {
+    assert!(
+        Handle::try_current().is_err(),
+        "blocking functions cannot be called from within an async runtime, use the async API instead"
+    );
+    runtime().block_on(future)
+}
+
+/// Blocking version of [`crate::install::Installer`].
+///
+/// Configure an installer with the builder methods of
+/// [`crate::install::Installer`] and convert it with [`From`]:
+///
+/// ```no_run
+/// # fn install(records: Vec<rattler_conda_types::RepoDataRecord>) -> Result<(), rattler::install::InstallerError> {
+/// use rattler::{blocking, install::Installer};
+/// use rattler_conda_types::Platform;
+///
+/// let installer = Installer::new().with_target_platform(Platform::current());
+/// let result = blocking::Installer::from(installer).install("/path/to/prefix", records)?;
+/// println!("{} operations", result.transaction.operations.len());
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Default)]
+pub struct Installer {
+    inner: crate::install::Installer,
+}
+
+impl From<crate::install::Installer> for Installer {
+    fn from(inner: crate::install::Installer) -> Self {
+        Self { inner }
+    }
+}
+
+impl Installer {
+    /// Constructs an installer with the default configuration.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Installs the packages in the given prefix and blocks until the
+    /// installation is complete. See [`crate::install::Installer::install`].
+    pub fn install(
+        self,
+        prefix: impl AsRef<Path>,
+        records: impl IntoIterator<Item = RepoDataRecord>,
+    ) -> Result<InstallationResult, InstallerError> {
+        block_on(self.inner.install(prefix, records))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Installer;
+
+    #[test]
+    fn test_install_empty_environment() {
+        let prefix = tempfile::tempdir().unwrap();
+        let result = Installer::new().install(prefix.path(), []).unwrap();
+        assert!(result.transaction.operations.is_empty());
+    }
+}
@@ -1,3 +1,4 @@
+use rattler_error::ErrorCode;
 use simple_spawn_blocking::Cancelled;
 
 use crate::{
@@ -47,11 +48,38 @@ pub enum InstallerError {
     #[error("failed to unclobber clobbered files")]
     ClobberError(#[from] ClobberError),
 
+    /// No package cache was configured and the default package cache
+    /// directory could not be determined.
+    #[error("failed to determine the default package cache directory")]
+    NoDefaultPackageCache(#[source] rattler_config::ConfigError),
+
     /// The operation was cancelled
     #[error("the operation was cancelled")]
     Cancelled,
 }
 
+impl ErrorCode for InstallerError {
+    fn code(&self) -> &'static str {
+        match self {
+            InstallerError::FailedToDetectInstalledPackages(_) => {
+                "rattler::install::detect_installed_packages"
+            }
+            InstallerError::FailedToConstructTransaction(_) => "rattler::install::transaction",
+            InstallerError::FailedToFetch(..) => "rattler::install::fetch",
+            InstallerError::LinkError(..) => "rattler::install::link",
+            InstallerError::UnlinkError(..) => "rattler::install::unlink",
+            InstallerError::IoError(..) => "rattler::install::io",
+            InstallerError::PreProcessingFailed(_) => "rattler::install::pre_processing",
+            InstallerError::PostProcessingFailed(_) => "rattler::install::post_processing",
+            InstallerError::ClobberError(_) => "rattler::install::clobber",
+            InstallerError::NoDefaultPackageCache(_) => {
+                "rattler::install::no_default_package_cache"
+            }
+            InstallerError::Cancelled => "rattler::install::cancelled",
+        }
+    }
+}
+
 impl From<Cancelled> for InstallerError {
     fn from(_: Cancelled) -> Self {
         InstallerError::Cancelled
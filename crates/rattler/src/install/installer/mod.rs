@@ -12,7 +12,6 @@ use std::{
 use super::{unlink_package, AppleCodeSignBehavior, InstallDriver, InstallOptions, Transaction};
 use crate::install::link_script::LinkScriptError;
 use crate::{
-    default_cache_dir,
     install::{clobber_registry::ClobberedPath, link_script::PrePostLinkResult},
     package_cache::PackageCache,
 };
@@ -29,11 +28,13 @@ use rattler_conda_types::{
     prefix_record::{Link, LinkType},
     Platform, PrefixRecord, RepoDataRecord,
 };
+use rattler_config::RattlerConfig;
 use rattler_networking::retry_policies::default_retry_policy;
 pub use reporter::Reporter;
 use reqwest::Client;
 use simple_spawn_blocking::tokio::run_blocking_task;
 use tokio::{sync::Semaphore, task::JoinError};
+use tracing::{field::Empty, instrument, Instrument, Span};
 
 /// An installer that can install packages into a prefix.
 #[derive(Default)]
@@ -53,6 +54,7 @@ pub struct Installer {
     // allow_ref_links: Option<bool>,
 }
 
+/// The result of installing packages into a prefix with an [`Installer`].
 #[derive(Debug)]
 pub struct InstallationResult {
     /// The transaction that was applied
@@ -159,6 +161,44 @@ impl Installer {
         self
     }
 
+    /// Applies the settings from a [`RattlerConfig`]: the package cache in
+    /// the configured cache directory and the IO concurrency limit.
+    ///
+    /// If no download client was set yet, a client with the authentication,
+    /// SSL and proxy settings of the configuration is used (see
+    /// [`RattlerConfig::client_builder`]). A client that is set afterwards
+    /// with [`Self::with_download_client`] replaces it.
+    #[must_use]
+    pub fn with_config(mut self, config: &RattlerConfig) -> Self {
+        self.set_config(config);
+        self
+    }
+
+    /// Applies the settings from a [`RattlerConfig`].
+    ///
+    /// This function is similar to [`Self::with_config`], but modifies an
+    /// existing instance.
+    pub fn set_config(&mut self, config: &RattlerConfig) -> &mut Self {
+        match config.package_cache_dir() {
+            Ok(package_cache_dir) => {
+                self.set_package_cache(PackageCache::new(package_cache_dir));
+            }
+            Err(e) => tracing::warn!("{e}, using the default package cache"),
+        }
+        if let Some(limit) = config.concurrency.io {
+            self.set_io_concurrentcy_limit(limit);
+        }
+        if self.downloader.is_none() {
+            match config.client_builder() {
+                Ok(builder) => {
+                    self.set_download_client(builder.build().into_inner());
+                }
+                Err(e) => tracing::warn!("{e}, using the default download client"),
+            }
+        }
+        self
+    }
+
     /// Sets the download client to use
     #[must_use]
     pub fn with_download_client(
@@ -266,6 +306,21 @@ impl Installer {
     }
 
     /// Install the packages in the given prefix.
+    ///
+    /// The installation is recorded in an `install` span with a unique
+    /// `operation_id` that can be used to correlate all events of a single
+    /// installation.
+    #[instrument(
+        name = "install",
+        skip_all,
+        fields(
+            operation_id = %uuid::Uuid::new_v4(),
+            path = %prefix.as_ref().display(),
+            platform = Empty,
+            operations = Empty,
+        ),
+        err
+    )]
     pub async fn install(
         self,
         prefix: impl AsRef<Path>,
@@ -274,13 +329,13 @@ impl Installer {
         let downloader = self
             .downloader
             .unwrap_or_else(|| reqwest_middleware::ClientWithMiddleware::from(Client::default()));
-        let package_cache = self.package_cache.unwrap_or_else(|| {
-            PackageCache::new(
-                default_cache_dir()
-                    .expect("failed to determine default cache directory")
-                    .join(rattler_cache::PACKAGE_CACHE_DIR),
-            )
-        });
+        let package_cache = match self.package_cache {
+            Some(package_cache) => package_cache,
+            None => PackageCache::new(
+                rattler_config::default_package_cache_dir()
+                    .map_err(InstallerError::NoDefaultPackageCache)?,
+            ),
+        };
 
         // Create a future to determine the currently installed packages. We
         // can start this in parallel with the other operations and resolve it
@@ -314,6 +369,10 @@ impl Installer {
             target_platform,
         )?;
 
+        Span::current()
+            .record("platform", target_platform.as_str())
+            .record("operations", transaction.operations.len());
+
         // If the transaction is empty we can short-circuit the installation
         if transaction.operations.is_empty() {
             return Ok(InstallationResult {
@@ -362,23 +421,26 @@ impl Installer {
                     let downloader = downloader.clone();
                     let reporter = reporter.clone();
                     let package_cache = package_cache.clone();
-                    tokio::spawn(async move {
-                        let populate_cache_report = reporter.clone().map(|r| {
-                            let cache_index = r.on_populate_cache_start(idx, &record);
-                            (r, cache_index)
-                        });
-                        let cache_lock = populate_cache(
-                            &record,
-                            downloader,
-                            &package_cache,
-                            populate_cache_report.clone(),
-                        )
-                        .await?;
-                        if let Some((reporter, index)) = populate_cache_report {
-                            reporter.on_populate_cache_complete(index);
+                    tokio::spawn(
+                        async move {
+                            let populate_cache_report = reporter.clone().map(|r| {
+                                let cache_index = r.on_populate_cache_start(idx, &record);
+                                (r, cache_index)
+                            });
+                            let cache_lock = populate_cache(
+                                &record,
+                                downloader,
+                                &package_cache,
+                                populate_cache_report.clone(),
+                            )
+                            .await?;
+                            if let Some((reporter, index)) = populate_cache_report {
+                                reporter.on_populate_cache_complete(index);
+                            }
+                            Ok((cache_lock, record))
                         }
-                        Ok((cache_lock, record))
-                    })
+                        .in_current_span(),
+                    )
                     .map_err(JoinError::try_into_panic)
                     .map(|res| match res {
                         Ok(Ok(result)) => Ok(Some(result)),
@@ -455,6 +517,7 @@ impl Installer {
     }
 }
 
+#[instrument(skip_all, fields(package = %record.file_name))]
 async fn link_package(
     record: &RepoDataRecord,
     target_prefix: &Path,
@@ -515,6 +578,7 @@ async fn link_package(
 
 /// Given a repodata record, fetch the package into the cache if its not already
 /// there.
+#[instrument(skip_all, fields(package = %record.file_name))]
 async fn populate_cache(
     record: &RepoDataRecord,
     downloader: reqwest_middleware::ClientWithMiddleware,
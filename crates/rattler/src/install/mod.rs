@@ -47,7 +47,7 @@ pub use installer::{
     DefaultProgressFormatter, IndicatifReporter, IndicatifReporterBuilder, Placement,
     ProgressFormatter,
 };
-pub use installer::{Installer, InstallerError, Reporter};
+pub use installer::{InstallationResult, Installer, InstallerError, Reporter};
 use itertools::Itertools;
 pub use link::{link_file, LinkFileError, LinkMethod};
 pub use python::PythonInfo;
@@ -248,7 +248,7 @@ pub struct InstallOptions {
 /// Returns a [`PathsEntry`] for every file that was linked into the target
 /// directory. The entries are ordered in the same order as they appear in the
 /// `paths.json` file of the package.
-#[instrument(skip_all, fields(package_dir = % package_dir.display()))]
+#[instrument(skip_all, fields(path = %package_dir.display()))]
 pub async fn link_package(
     package_dir: &Path,
     target_dir: &Path,
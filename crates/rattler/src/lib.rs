@@ -15,9 +15,13 @@
 
 use std::path::PathBuf;
 
+#[cfg(feature = "blocking")]
+pub mod blocking;
 #[cfg(feature = "cli-tools")]
 pub mod cli;
 pub mod install;
+#[cfg(feature = "opentelemetry")]
+pub mod telemetry;
 pub use rattler_cache::{package_cache, validation};
 
 /// A helper function that returns a [`Channel`] instance that points to an
@@ -0,0 +1,84 @@
+//! Export the traces of rattler operations with OpenTelemetry.
+//!
+//! Rattler emits [`tracing`] spans for every step of creating an environment.
+//! These are the spans and their fields, as declared by the `#[instrument]`
+//! attributes in the respective crates:
+//!
+//! | Span                          | Crate                       | Fields                                             |
+//! |-------------------------------|-----------------------------|----------------------------------------------------|
+//! | `query_repodata`              | `rattler_repodata_gateway`  | `channels`, `platforms`, `specs`, `records`        |
+//! | `get_or_create_subdir`        | `rattler_repodata_gateway`  | `channel`, `platform`                              |
+//! | `fetch_repo_data`             | `rattler_repodata_gateway`  | `url`, `path`                                      |
+//! | `fetch_run_exports`           | `rattler_repodata_gateway`  | `url`, `path`                                      |
+//! | `fetch_patch_instructions`    | `rattler_repodata_gateway`  | `url`, `path`                                      |
+//! | `stream_and_decode_to_file`   | `rattler_repodata_gateway`  | `url`, `bytes`, `decoded_bytes`                    |
+//! | `solve`                       | `rattler_solve`             | `solver`, `specs`, `records`                       |
+//! | `install`                     | `rattler`                   | `operation_id`, `path`, `platform`, `operations`   |
+//! | `populate_cache`              | `rattler`                   | `package`                                          |
+//! | `link_package`                | `rattler`                   | `package` (installer) or `path` (`install`)        |
+//! | `extract_tar_bz2`             | `rattler_package_streaming` | `path`, `bytes` (`fs` only)                        |
+//! | `extract_conda`               | `rattler_package_streaming` | `path`, `bytes`                                    |
+//! | `extract_conda_via_streaming` | `rattler_package_streaming` | `path`                                             |
+//! | `extract_conda_via_buffering` | `rattler_package_streaming` | `path`                                             |
+//! | `extract`                     | `rattler_package_streaming` | `url`, `path` (`reqwest` feature)                  |
+//!
+//! Fields with the same name have the same meaning in every span:
+//!
+//! - `url` is the remote location that is read, always redacted.
+//! - `path` is the local file or directory that is read or written: the
+//!   repodata cache, the target prefix, the package directory that is linked,
+//!   the archive that is extracted (`fs`) or the extraction destination
+//!   (`read`, `reqwest`).
+//! - `package` is the file name of a package.
+//! - `platform` is the platform (subdirectory) of a channel or prefix.
+//! - `bytes` is the number of bytes read from an archive file
+//!   (`extract_tar_bz2` and `extract_conda` of `fs`) or downloaded
+//!   (`stream_and_decode_to_file`), `decoded_bytes` the number of bytes
+//!   after decompression.
+//!
+//! The [`otlp_layer`] function constructs a [`tracing_subscriber::Layer`] that
+//! exports these spans to an OTLP collector. The collector is configured
+//! through the standard `OTEL_EXPORTER_OTLP_*` environment variables.
+
+use opentelemetry::{trace::TraceError, KeyValue};
+use opentelemetry_sdk::{runtime, trace::Config, Resource};
+use tracing::Subscriber;
+use tracing_subscriber::{registry::LookupSpan, Layer};
+
+/// Flushes all pending spans and shuts down the exporter when dropped.
+#[must_use = "spans are only exported while the guard is alive"]
+pub struct OtelGuard {
+    _private: (),
+}
+
+impl Drop for OtelGuard {
+    fn drop(&mut self) {
+        opentelemetry::global::shutdown_tracer_provider();
+    }
+}
+
+/// Constructs a layer that exports spans to an OTLP collector with the given
+/// service name. The spans are exported in batches on the tokio runtime, so
+/// this function must be called from within a tokio runtime.
+///
+/// Keep the returned guard alive for as long as spans should be exported.
+pub fn otlp_layer<S>(
+    service_name: impl Into<String>,
+) -> Result<(impl Layer<S>, OtelGuard), TraceError>
+where
+    S: Subscriber + for<'span> LookupSpan<'span>,
+{
+    let tracer = opentelemetry_otlp::new_pipeline()
+        .tracing()
+        .with_exporter(opentelemetry_otlp::new_exporter().tonic())
+        .with_trace_config(
+            Config::default().with_resource(Resource::new([KeyValue::new(
+                "service.name",
+                service_name.into(),
+            )])),
+        )
+        .install_batch(runtime::Tokio)?;
+
+    let layer = tracing_opentelemetry::layer().with_tracer(tracer);
+    Ok((layer, OtelGuard { _private: () }))
+}
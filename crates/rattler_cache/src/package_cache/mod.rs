@@ -134,12 +134,13 @@ impl PackageCache {
     ///
     /// This is a convenience wrapper around `get_or_fetch` which fetches the
     /// package from the given URL if the package could not be found in the
-    /// cache.
+    /// cache. Any client that converts into a `ClientWithMiddleware` (e.g. an
+    /// `AuthenticatedClient`) can be used to download the package.
     pub async fn get_or_fetch_from_url(
         &self,
         pkg: impl Into<CacheKey>,
         url: Url,
-        client: reqwest_middleware::ClientWithMiddleware,
+        client: impl Into<reqwest_middleware::ClientWithMiddleware>,
         reporter: Option<Arc<dyn CacheReporter>>,
     ) -> Result<CacheLock, PackageCacheError> {
         self.get_or_fetch_from_url_with_retry(pkg, url, client, DoNotRetryPolicy, reporter)
@@ -181,10 +182,11 @@ impl PackageCache {
         &self,
         pkg: impl Into<CacheKey>,
         url: Url,
-        client: reqwest_middleware::ClientWithMiddleware,
+        client: impl Into<reqwest_middleware::ClientWithMiddleware>,
         retry_policy: impl RetryPolicy + Send + 'static + Clone,
         reporter: Option<Arc<dyn CacheReporter>>,
     ) -> Result<CacheLock, PackageCacheError> {
+        let client = client.into();
         let request_start = SystemTime::now();
         let cache_key = pkg.into();
         let sha256 = cache_key.sha256();
@@ -629,7 +631,7 @@ mod test {
             .get_or_fetch_from_url_with_retry(
                 ArchiveIdentifier::try_from_filename(archive_name).unwrap(),
                 server_url.join(archive_name).unwrap(),
-                reqwest::Client::default().into(),
+                reqwest::Client::default(),
                 DoNotRetryPolicy,
                 None,
             )
@@ -647,7 +649,7 @@ mod test {
             .get_or_fetch_from_url_with_retry(
                 ArchiveIdentifier::try_from_filename(archive_name).unwrap(),
                 server_url.join(archive_name).unwrap(),
-                reqwest::Client::default().into(),
+                reqwest::Client::default(),
                 ExponentialBackoffBuilder::default().build_with_max_retries(3),
                 None,
             )
@@ -6,6 +6,7 @@ use std::{
 };
 
 use file_url::directory_path_to_url;
+use rattler_error::ErrorCode;
 use rattler_redaction::Redact;
 use serde::{Deserialize, Serialize, Serializer};
 use thiserror::Error;
@@ -400,6 +401,20 @@ pub enum ParseChannelError {
     NotUtf8RootDir(PathBuf),
 }
 
+impl ErrorCode for ParseChannelError {
+    fn code(&self) -> &'static str {
+        match self {
+            ParseChannelError::ParsePlatformError(err) => err.code(),
+            ParseChannelError::ParseUrlError(_) => "rattler::channel::invalid_url",
+            ParseChannelError::InvalidPath(_) => "rattler::channel::invalid_path",
+            ParseChannelError::InvalidName(_) => "rattler::channel::invalid_name",
+            ParseChannelError::NonAbsoluteRootDir(_) | ParseChannelError::NotUtf8RootDir(_) => {
+                "rattler::channel::invalid_root_dir"
+            }
+        }
+    }
+}
+
 impl From<ParsePlatformError> for ParseChannelError {
     fn from(err: ParsePlatformError) -> Self {
         ParseChannelError::ParsePlatformError(err)
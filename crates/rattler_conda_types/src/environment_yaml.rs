@@ -1,4 +1,7 @@
-use std::path::{Path, PathBuf};
+use std::{
+    path::{Path, PathBuf},
+    str::FromStr,
+};
 
 use indexmap::IndexMap;
 use serde::{
@@ -112,6 +115,14 @@ impl EnvironmentYaml {
     }
 }
 
+impl FromStr for EnvironmentYaml {
+    type Err = serde_yaml::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::from_yaml_str(s)
+    }
+}
+
 impl<'a> serde::Deserialize<'a> for MatchSpecOrSubSection {
     fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
     where
@@ -191,6 +202,44 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_from_str() {
+        let environment_yaml = EnvironmentYaml::from_str(
+            r#"
+            name: test
+            channels:
+              - conda-forge
+            dependencies:
+              - python >=3.10,<3.13
+              - numpy=1.26
+              - pip
+              - pip:
+                - requests>=2.31
+                - -e ./local-package
+            "#,
+        )
+        .unwrap();
+        assert_eq!(environment_yaml.name.as_deref(), Some("test"));
+        assert_eq!(environment_yaml.channels.len(), 1);
+        assert_eq!(
+            environment_yaml
+                .match_specs()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>(),
+            ["python >=3.10,<3.13", "numpy 1.26.*", "pip"]
+        );
+        assert_eq!(
+            environment_yaml.pip_specs(),
+            Some(
+                [
+                    "requests>=2.31".to_string(),
+                    "-e ./local-package".to_string()
+                ]
+                .as_slice()
+            )
+        );
+    }
+
     #[test]
     fn test_pip_section() {
         let environment_yaml = EnvironmentYaml::from_path(
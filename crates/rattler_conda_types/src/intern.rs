@@ -0,0 +1,144 @@
+//! A process wide pool of shared strings.
+//!
+//! Package names occur over and over again: in the repodata of every channel
+//! and platform, in the input of the solver and in lock files. Instead of
+//! storing a separate copy of the same string in every record,
+//! [`crate::PackageName`] stores an [`Arc<str>`] that is obtained from the
+//! [`Interner`] so that all instances of the same name share a single
+//! allocation.
+//!
+//! Currently package names are the only identifiers that are interned. Other
+//! repeated strings like build strings ([`crate::PackageRecord::build`]) and
+//! channel urls ([`crate::RepoDataRecord::channel`]) are public `String`
+//! fields, interning them would require a breaking change to those types.
+
+use std::{
+    collections::HashSet,
+    hash::{BuildHasher, BuildHasherDefault},
+    sync::{Arc, Mutex, OnceLock, PoisonError},
+};
+
+use fxhash::FxHasher;
+
+type FxBuildHasher = BuildHasherDefault<FxHasher>;
+
+/// The number of independently locked shards. Repodata of different
+/// subdirectories is often parsed in parallel, sharding the pool reduces
+/// contention between those threads.
+const SHARD_COUNT: usize = 32;
+
+/// A thread-safe pool of deduplicated strings.
+///
+/// Strings are kept alive by the pool until [`Interner::collect_garbage`] is
+/// called, which removes all strings that are no longer referenced from
+/// outside of the pool. The gateway of `rattler_repodata_gateway` collects
+/// the garbage of the [`global`] pool when its in-memory repodata cache is
+/// cleared or dropped.
+pub struct Interner {
+    hasher: FxBuildHasher,
+    shards: [Mutex<HashSet<Arc<str>, FxBuildHasher>>; SHARD_COUNT],
+}
+
+impl Default for Interner {
+    fn default() -> Self {
+        Self {
+            hasher: FxBuildHasher::default(),
+            shards: std::array::from_fn(|_| Mutex::default()),
+        }
+    }
+}
+
+impl std::fmt::Debug for Interner {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Interner")
+            .field("len", &self.len())
+            .finish()
+    }
+}
+
+impl Interner {
+    /// Constructs a new empty pool.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns a shared instance of `value`. If the string was interned
+    /// before the existing allocation is returned.
+    pub fn intern(&self, value: &str) -> Arc<str> {
+        let mut shard = self.shard(value);
+        if let Some(existing) = shard.get(value) {
+            return existing.clone();
+        }
+        let interned: Arc<str> = Arc::from(value);
+        shard.insert(interned.clone());
+        interned
+    }
+
+    /// Returns the number of strings in the pool.
+    pub fn len(&self) -> usize {
+        self.shards
+            .iter()
+            .map(|shard| shard.lock().unwrap_or_else(PoisonError::into_inner).len())
+            .sum()
+    }
+
+    /// Returns true if the pool does not contain any strings.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Removes all strings that are only referenced by the pool itself and
+    /// returns the number of removed strings.
+    pub fn collect_garbage(&self) -> usize {
+        self.shards
+            .iter()
+            .map(|shard| {
+                let mut shard = shard.lock().unwrap_or_else(PoisonError::into_inner);
+                let len = shard.len();
+                shard.retain(|value| Arc::strong_count(value) > 1);
+                shard.shrink_to_fit();
+                len - shard.len()
+            })
+            .sum()
+    }
+
+    fn shard(&self, value: &str) -> std::sync::MutexGuard<'_, HashSet<Arc<str>, FxBuildHasher>> {
+        let index = self.hasher.hash_one(value) as usize % SHARD_COUNT;
+        self.shards[index]
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// Returns the global pool that is used by the types in this crate.
+pub fn global() -> &'static Interner {
+    static INTERNER: OnceLock<Interner> = OnceLock::new();
+    INTERNER.get_or_init(Interner::new)
+}
+
+/// Interns `value` in the [`global`] pool.
+pub fn intern(value: &str) -> Arc<str> {
+    global().intern(value)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_intern() {
+        let interner = Interner::new();
+        let a = interner.intern("python");
+        let b = interner.intern(&String::from("python"));
+        let c = interner.intern("numpy");
+
+        assert!(Arc::ptr_eq(&a, &b));
+        assert!(!Arc::ptr_eq(&a, &c));
+        assert_eq!(interner.len(), 2);
+
+        drop(c);
+        assert_eq!(interner.collect_garbage(), 1);
+        assert_eq!(interner.len(), 1);
+        assert!(Arc::ptr_eq(&a, &interner.intern("python")));
+    }
+}
@@ -20,6 +20,7 @@ pub mod version_spec;
 
 mod environment_yaml;
 mod generic_virtual_package;
+pub mod intern;
 pub mod package;
 mod package_name;
 pub mod prefix_record;
@@ -49,7 +50,9 @@ pub use prefix_record::PrefixRecord;
 pub use repo_data::{
     compute_package_url,
     patches::{PackageRecordPatch, PatchInstructions, RepoDataPatch},
+    run_exports::{PackageRunExports, SubdirRunExportsJson},
     sharded::{Shard, ShardedRepodata, ShardedSubdirInfo},
+    sparse::LoadRecords,
     ChannelInfo, ConvertSubdirError, PackageRecord, RepoData,
 };
 pub use repo_data_record::RepoDataRecord;
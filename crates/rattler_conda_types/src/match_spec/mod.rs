@@ -180,6 +180,18 @@ impl Display for MatchSpec {
 
         let mut keys = Vec::new();
 
+        if let Some(build_number) = &self.build_number {
+            keys.push(format!("build_number=\"{build_number}\""));
+        }
+
+        if let Some(file_name) = &self.file_name {
+            keys.push(format!("fn=\"{file_name}\""));
+        }
+
+        if let Some(url) = &self.url {
+            keys.push(format!("url=\"{url}\""));
+        }
+
         if let Some(md5) = &self.md5 {
             keys.push(format!("md5={md5:x}"));
         }
@@ -273,6 +285,18 @@ impl Display for NamelessMatchSpec {
 
         let mut keys = Vec::new();
 
+        if let Some(build_number) = &self.build_number {
+            keys.push(format!("build_number=\"{build_number}\""));
+        }
+
+        if let Some(file_name) = &self.file_name {
+            keys.push(format!("fn=\"{file_name}\""));
+        }
+
+        if let Some(url) = &self.url {
+            keys.push(format!("url=\"{url}\""));
+        }
+
         if let Some(md5) = &self.md5 {
             keys.push(format!("md5={md5:x}"));
         }
@@ -500,6 +524,27 @@ mod tests {
         assert_eq!(spec, rebuild_spec);
     }
 
+    #[test]
+    fn test_bracket_keys_format_eq() {
+        let spec = MatchSpec::from_str(
+            "conda-forge/linux-64::foo 1.0.*[build_number=\">=3\", fn=\"foo-1.0-h123_3.conda\", url=\"https://conda.anaconda.org/conda-forge/linux-64/foo-1.0-h123_3.conda\"]",
+            Strict,
+        )
+        .unwrap();
+        let spec_as_string = spec.to_string();
+        assert_eq!(MatchSpec::from_str(&spec_as_string, Strict).unwrap(), spec);
+
+        let nameless = NamelessMatchSpec::from_str(
+            "1.0.*[build_number=\">=3\", fn=\"foo-1.0-h123_3.conda\", url=\"https://conda.anaconda.org/conda-forge/linux-64/foo-1.0-h123_3.conda\"]",
+            Strict,
+        )
+        .unwrap();
+        assert_eq!(
+            NamelessMatchSpec::from_str(&nameless.to_string(), Strict).unwrap(),
+            nameless
+        );
+    }
+
     #[test]
     fn test_hash_match() {
         let spec1 = MatchSpec::from_str("tensorflow 2.6.*", Strict).unwrap();
@@ -11,6 +11,7 @@ use nom::{
     Finish, IResult,
 };
 use rattler_digest::{parse_digest_from_hex, Md5, Sha256};
+use rattler_error::ErrorCode;
 use smallvec::SmallVec;
 use thiserror::Error;
 use typed_path::Utf8TypedPath;
@@ -100,6 +101,83 @@ pub enum ParseMatchSpecError {
     InvalidPackageName(#[from] InvalidPackageNameError),
 }
 
+impl ErrorCode for ParseMatchSpecError {
+    fn code(&self) -> &'static str {
+        match self {
+            ParseMatchSpecError::InvalidPackagePathOrUrl => {
+                "rattler::match_spec::invalid_package_path_or_url"
+            }
+            ParseMatchSpecError::InvalidPackageUrl(_) => "rattler::match_spec::invalid_package_url",
+            ParseMatchSpecError::InvalidPackagePathOrUrlVersion(err) => err.code(),
+            ParseMatchSpecError::InvalidBracket => "rattler::match_spec::invalid_bracket",
+            ParseMatchSpecError::ParseChannelError(err) => err.code(),
+            ParseMatchSpecError::InvalidBracketKey(_) => "rattler::match_spec::invalid_bracket_key",
+            ParseMatchSpecError::MissingPackageName => "rattler::match_spec::missing_package_name",
+            ParseMatchSpecError::MultipleBracketSectionsNotAllowed => {
+                "rattler::match_spec::multiple_bracket_sections"
+            }
+            ParseMatchSpecError::InvalidVersionAndBuild(_) => {
+                "rattler::match_spec::invalid_version_and_build"
+            }
+            ParseMatchSpecError::InvalidBuildString(_) => {
+                "rattler::match_spec::invalid_build_string"
+            }
+            ParseMatchSpecError::InvalidVersionSpec(err) => err.code(),
+            ParseMatchSpecError::InvalidStringMatcher(_) => {
+                "rattler::match_spec::invalid_string_matcher"
+            }
+            ParseMatchSpecError::InvalidBuildNumber(_) => {
+                "rattler::match_spec::invalid_build_number"
+            }
+            ParseMatchSpecError::InvalidHashDigest => "rattler::match_spec::invalid_hash_digest",
+            ParseMatchSpecError::InvalidPackageName(err) => err.code(),
+        }
+    }
+}
+
+#[cfg(feature = "miette")]
+impl ParseMatchSpecError {
+    /// Converts this error into a [`miette::Diagnostic`] that points to the
+    /// part of `spec` that caused the error. `spec` should be the string
+    /// that was passed to the parser.
+    pub fn into_diagnostic(self, spec: &str) -> rattler_error::SourceDiagnostic<Self> {
+        let (span, label) = match &self {
+            ParseMatchSpecError::InvalidBracketKey(key) => (find_span(spec, key), "unknown key"),
+            ParseMatchSpecError::InvalidBuildString(build) => {
+                (find_span(spec, build), "invalid build string")
+            }
+            ParseMatchSpecError::InvalidVersionAndBuild(version) => {
+                (find_span(spec, version), "invalid version")
+            }
+            ParseMatchSpecError::MultipleBracketSectionsNotAllowed
+            | ParseMatchSpecError::InvalidBracket => (
+                spec.find('[')
+                    .map_or(0..spec.len(), |start| start..spec.len()),
+                "brackets",
+            ),
+            _ => (0..spec.len(), "while parsing this match spec"),
+        };
+        let diagnostic =
+            rattler_error::SourceDiagnostic::new(self, "match spec", spec).with_label(span, label);
+        match diagnostic.error() {
+            ParseMatchSpecError::InvalidBracketKey(_) => diagnostic.with_help(
+                "valid keys are: version, build, build_number, sha256, md5, fn, url, subdir and channel",
+            ),
+            _ => diagnostic,
+        }
+    }
+}
+
+/// Returns the range of the first occurrence of `needle` in `haystack` or the
+/// range of the whole `haystack` if it does not contain `needle`.
+#[cfg(feature = "miette")]
+fn find_span(haystack: &str, needle: &str) -> std::ops::Range<usize> {
+    match haystack.find(needle) {
+        Some(start) if !needle.is_empty() => start..start + needle.len(),
+        _ => 0..haystack.len(),
+    }
+}
+
 impl FromStr for MatchSpec {
     type Err = ParseMatchSpecError;
 
@@ -1126,6 +1204,22 @@ mod tests {
         );
     }
 
+    #[cfg(feature = "miette")]
+    #[test]
+    fn test_invalid_bracket_key_diagnostic() {
+        use miette::Diagnostic;
+
+        let source = "conda-forge::foo[unknown=1.0.*]";
+        let err = MatchSpec::from_str(source, Strict).unwrap_err();
+        assert_eq!(err.code(), "rattler::match_spec::invalid_bracket_key");
+
+        let diagnostic = err.into_diagnostic(source);
+        let labels = diagnostic.labels().unwrap().collect::<Vec<_>>();
+        assert_eq!(labels[0].offset(), source.find("unknown").unwrap());
+        assert_eq!(labels[0].len(), "unknown".len());
+        assert!(diagnostic.help().is_some());
+    }
+
     #[test]
     fn test_invalid_channel_name() {
         let spec = MatchSpec::from_str("conda-forge::::foo[version=\"1.0.*\"]", Strict);
@@ -1,11 +1,14 @@
+use crate::intern::intern;
 use crate::package::ArchiveIdentifier;
 use crate::utils::serde::DeserializeFromStrUnchecked;
+use rattler_error::ErrorCode;
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use serde_with::{DeserializeAs, DeserializeFromStr};
 use std::borrow::Borrow;
 use std::cmp::Ordering;
 use std::hash::{Hash, Hasher};
 use std::str::FromStr;
+use std::sync::Arc;
 use thiserror::Error;
 
 /// A representation of a conda package name. This struct both stores the source string from which
@@ -17,20 +20,23 @@ use thiserror::Error;
 /// This struct explicitly does not implement [`std::fmt::Display`] because its ambiguous if that
 /// would display the source or the normalized version. Simply call `as_source` or `as_normalized`
 /// to make the distinction.
+///
+/// The strings are stored in the global [`crate::intern`] pool, so cloning a
+/// name is cheap and all instances of the same name share a single allocation.
 #[derive(Debug, Clone, Eq, DeserializeFromStr)]
 pub struct PackageName {
-    normalized: Option<String>,
-    source: String,
+    normalized: Option<Arc<str>>,
+    source: Arc<str>,
 }
 
 impl PackageName {
     /// Constructs a new `PackageName` from a string without checking if the string is actually a
     /// valid or normalized conda package name. This should only be used if you are sure that the
     /// input string is valid, otherwise use the `TryFrom` implementations.
-    pub fn new_unchecked<S: Into<String>>(normalized: S) -> Self {
+    pub fn new_unchecked<S: AsRef<str>>(normalized: S) -> Self {
         Self {
             normalized: None,
-            source: normalized.into(),
+            source: intern(normalized.as_ref()),
         }
     }
 
@@ -43,7 +49,7 @@ impl PackageName {
     /// Returns the normalized version of the package name. The normalized string is guaranteed to
     /// be a valid conda package name.
     pub fn as_normalized(&self) -> &str {
-        self.normalized.as_ref().unwrap_or(&self.source)
+        self.normalized.as_deref().unwrap_or(&self.source)
     }
 }
 
@@ -55,11 +61,21 @@ pub enum InvalidPackageNameError {
     InvalidCharacters(String),
 }
 
+impl ErrorCode for InvalidPackageNameError {
+    fn code(&self) -> &'static str {
+        match self {
+            InvalidPackageNameError::InvalidCharacters(_) => {
+                "rattler::package_name::invalid_characters"
+            }
+        }
+    }
+}
+
 impl TryFrom<&String> for PackageName {
     type Error = InvalidPackageNameError;
 
     fn try_from(value: &String) -> Result<Self, Self::Error> {
-        value.clone().try_into()
+        value.as_str().try_into()
     }
 }
 
@@ -75,31 +91,36 @@ impl TryFrom<String> for PackageName {
     type Error = InvalidPackageNameError;
 
     fn try_from(source: String) -> Result<Self, Self::Error> {
+        source.as_str().try_into()
+    }
+}
+
+impl<'a> TryFrom<&'a str> for PackageName {
+    type Error = InvalidPackageNameError;
+
+    fn try_from(source: &'a str) -> Result<Self, Self::Error> {
         // Ensure that the string only contains valid characters
         if !source
             .chars()
             .all(|c| matches!(c, 'a'..='z'|'A'..='Z'|'0'..='9'|'-'|'_'|'.'))
         {
-            return Err(InvalidPackageNameError::InvalidCharacters(source));
+            return Err(InvalidPackageNameError::InvalidCharacters(
+                source.to_owned(),
+            ));
         }
 
         // Convert all characters to lowercase but only if it actually contains uppercase. This way
         // we dont allocate the memory of the string if it is already lowercase.
         let normalized = if source.chars().any(|c| c.is_ascii_uppercase()) {
-            Some(source.to_ascii_lowercase())
+            Some(intern(&source.to_ascii_lowercase()))
         } else {
             None
         };
 
-        Ok(Self { normalized, source })
-    }
-}
-
-impl<'a> TryFrom<&'a str> for PackageName {
-    type Error = InvalidPackageNameError;
-
-    fn try_from(value: &'a str) -> Result<Self, Self::Error> {
-        value.to_owned().try_into()
+        Ok(Self {
+            normalized,
+            source: intern(source),
+        })
     }
 }
 
@@ -107,7 +128,7 @@ impl FromStr for PackageName {
     type Err = InvalidPackageNameError;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        s.to_owned().try_into()
+        s.try_into()
     }
 }
 
@@ -178,4 +199,13 @@ mod test {
 
         assert!(PackageName::try_from("invalid$").is_err());
     }
+
+    #[test]
+    fn test_package_name_is_interned() {
+        let name1: PackageName = serde_json::from_str(r#""libzlib""#).unwrap();
+        let name2 = PackageName::try_from("libzlib").unwrap();
+        let name3 = PackageName::try_from("LibZlib").unwrap();
+        assert!(std::ptr::eq(name1.as_source(), name2.as_source()));
+        assert!(std::ptr::eq(name1.as_normalized(), name3.as_normalized()));
+    }
 }
@@ -1,4 +1,5 @@
 use itertools::Itertools;
+use rattler_error::ErrorCode;
 use serde::{Deserializer, Serializer};
 use std::cmp::Ordering;
 use std::fmt::Display;
@@ -239,6 +240,12 @@ pub struct ParsePlatformError {
     pub string: String,
 }
 
+impl ErrorCode for ParsePlatformError {
+    fn code(&self) -> &'static str {
+        "rattler::platform::unknown_platform"
+    }
+}
+
 impl Display for ParsePlatformError {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         write!(
@@ -381,6 +388,12 @@ pub struct ParseArchError {
     pub string: String,
 }
 
+impl ErrorCode for ParseArchError {
+    fn code(&self) -> &'static str {
+        "rattler::platform::unknown_arch"
+    }
+}
+
 impl FromStr for Arch {
     type Err = ParseArchError;
 
@@ -2,12 +2,16 @@
 //! in a subdirectory of a channel. It provides indexing functionality.
 
 pub mod patches;
+pub mod run_exports;
 pub mod sharded;
+pub mod sparse;
 mod topological_sort;
 
 use std::{
     collections::BTreeSet,
     fmt::{Display, Formatter},
+    fs::File,
+    io::{BufWriter, Write},
     path::Path,
 };
 
@@ -210,6 +214,25 @@ impl RepoData {
         Ok(serde_json::from_str(&contents)?)
     }
 
+    /// Writes the repodata as a `repodata.json` file to the specified location.
+    /// See [`Self::write_to`].
+    pub fn write_to_path(&self, path: impl AsRef<Path>, pretty: bool) -> std::io::Result<()> {
+        self.write_to(File::create(path)?, pretty)
+    }
+
+    /// Writes the repodata in the `repodata.json` format. The packages are
+    /// sorted by filename so the output is the same every time the same
+    /// repodata is written.
+    pub fn write_to(&self, writer: impl Write, pretty: bool) -> std::io::Result<()> {
+        let mut writer = BufWriter::new(writer);
+        if pretty {
+            serde_json::to_writer_pretty(&mut writer, self)?;
+        } else {
+            serde_json::to_writer(&mut writer, self)?;
+        }
+        writer.flush()
+    }
+
     /// Returns the `base_url` specified in the repodata.
     pub fn base_url(&self) -> Option<&str> {
         self.info.as_ref().and_then(|i| i.base_url.as_deref())
@@ -477,6 +500,24 @@ mod test {
         insta::assert_snapshot!(json);
     }
 
+    #[test]
+    fn test_write_to_path() {
+        let repodata = deserialize_json_from_test_data("channels/dummy/linux-64/repodata.json");
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("repodata.json");
+        repodata.write_to_path(&path, true).unwrap();
+        assert_eq!(RepoData::from_path(&path).unwrap(), repodata);
+
+        // The output is stable.
+        let mut output = Vec::new();
+        repodata.write_to(&mut output, true).unwrap();
+        assert_eq!(output, std::fs::read(&path).unwrap());
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            serde_json::to_string_pretty(&repodata).unwrap()
+        );
+    }
+
     #[test]
     fn test_deserialize_no_packages_conda() {
         let repodata = deserialize_json_from_test_data(
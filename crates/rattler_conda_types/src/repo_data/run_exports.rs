@@ -0,0 +1,94 @@
+//! Defines the `run_exports.json` file that channels publish next to the
+//! `repodata.json` of a subdirectory.
+
+use fxhash::FxHashMap;
+use serde::{Deserialize, Serialize};
+
+use crate::{package::RunExportsJson, utils::serde::sort_map_alphabetically, ChannelInfo};
+
+/// The `run_exports.json` file of a channel subdirectory.
+///
+/// The file contains the run exports of all packages in the subdirectory. This
+/// allows build tools to determine the run exports of a package without having
+/// to download the package itself.
+#[derive(Debug, Default, Clone, Deserialize, Serialize, Eq, PartialEq)]
+pub struct SubdirRunExportsJson {
+    /// The channel information contained in the file
+    pub info: Option<ChannelInfo>,
+
+    /// The run exports of the tar.bz2 packages in the subdirectory
+    #[serde(default, serialize_with = "sort_map_alphabetically")]
+    pub packages: FxHashMap<String, PackageRunExports>,
+
+    /// The run exports of the conda packages in the subdirectory
+    #[serde(
+        default,
+        rename = "packages.conda",
+        serialize_with = "sort_map_alphabetically"
+    )]
+    pub conda_packages: FxHashMap<String, PackageRunExports>,
+}
+
+/// The run exports of a single package in a [`SubdirRunExportsJson`].
+#[derive(Debug, Default, Clone, Deserialize, Serialize, Eq, PartialEq)]
+pub struct PackageRunExports {
+    /// The run exports of the package
+    #[serde(default)]
+    pub run_exports: RunExportsJson,
+}
+
+impl SubdirRunExportsJson {
+    /// Returns the run exports of the package with the given file name (e.g.
+    /// `zlib-1.3.1-hb9d3cd8_2.conda`).
+    pub fn get(&self, file_name: &str) -> Option<&RunExportsJson> {
+        self.conda_packages
+            .get(file_name)
+            .or_else(|| self.packages.get(file_name))
+            .map(|package| &package.run_exports)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::SubdirRunExportsJson;
+
+    #[test]
+    fn test_parse() {
+        let run_exports: SubdirRunExportsJson = serde_json::from_str(
+            r#"{
+                "info": { "subdir": "linux-64" },
+                "packages": {
+                    "zlib-1.2.13-hd590300_5.tar.bz2": {
+                        "run_exports": { "weak": ["libzlib >=1.2.13,<2.0.0a0"] }
+                    }
+                },
+                "packages.conda": {
+                    "zlib-1.3.1-hb9d3cd8_2.conda": {
+                        "run_exports": { "weak": ["libzlib >=1.3.1,<2.0a0"] }
+                    },
+                    "python-3.12.0-h0_0_cpython.conda": {
+                        "run_exports": {}
+                    }
+                }
+            }"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            run_exports.get("zlib-1.3.1-hb9d3cd8_2.conda").unwrap().weak,
+            vec!["libzlib >=1.3.1,<2.0a0"]
+        );
+        assert_eq!(
+            run_exports
+                .get("zlib-1.2.13-hd590300_5.tar.bz2")
+                .unwrap()
+                .weak,
+            vec!["libzlib >=1.2.13,<2.0.0a0"]
+        );
+        assert!(run_exports
+            .get("python-3.12.0-h0_0_cpython.conda")
+            .unwrap()
+            .is_empty());
+        assert!(run_exports.get("foo-1.0-0.conda").is_none());
+    }
+}
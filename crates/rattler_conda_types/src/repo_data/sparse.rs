@@ -0,0 +1,24 @@
+//! Defines [`LoadRecords`], the interface of repodata that only deserializes
+//! the records of the packages that are requested.
+
+use crate::{PackageName, RepoDataRecord};
+
+/// Repodata of a subdirectory from which the records can be loaded per
+/// package name.
+///
+/// Deserializing all records of a large channel is expensive while a solver
+/// usually only needs a small subset of them. Implementations index the raw
+/// repodata by package name and only parse the records that are requested.
+/// `rattler_repodata_gateway::sparse::SparseRepoData` implements this trait.
+pub trait LoadRecords {
+    /// The error that is returned if the records cannot be loaded.
+    type Error;
+
+    /// Loads the records of all packages with the given names. Names that do
+    /// not occur in the repodata are ignored, duplicate names are only loaded
+    /// once.
+    fn load_records_for_names<'a>(
+        &self,
+        package_names: impl IntoIterator<Item = &'a PackageName>,
+    ) -> Result<Vec<RepoDataRecord>, Self::Error>;
+}
@@ -281,24 +281,33 @@ impl Version {
         }
     }
 
+    /// Tries to extract the major version from the version. Returns None if this instance doesnt
+    /// start with a numeric segment.
+    pub fn as_major(&self) -> Option<u64> {
+        self.segment_as_number(0)
+    }
+
     /// Tries to extract the major and minor versions from the version. Returns None if this instance
     /// doesnt appear to contain a major and minor version.
     pub fn as_major_minor(&self) -> Option<(u64, u64)> {
-        let mut segments = self.segments();
-        let major_segment = segments.next()?;
-        let minor_segment = segments.next()?;
+        Some((self.segment_as_number(0)?, self.segment_as_number(1)?))
+    }
 
-        if major_segment.component_count() == 1 && minor_segment.component_count() == 1 {
-            Some((
-                major_segment
-                    .components()
-                    .next()
-                    .and_then(Component::as_number)?,
-                minor_segment
-                    .components()
-                    .next()
-                    .and_then(Component::as_number)?,
-            ))
+    /// Tries to extract the major, minor and patch versions from the version. Returns None if this
+    /// instance doesnt appear to contain a major, minor and patch version.
+    pub fn as_major_minor_patch(&self) -> Option<(u64, u64, u64)> {
+        Some((
+            self.segment_as_number(0)?,
+            self.segment_as_number(1)?,
+            self.segment_as_number(2)?,
+        ))
+    }
+
+    /// Returns the number of the segment at `index` if the segment consists of a single number.
+    fn segment_as_number(&self, index: usize) -> Option<u64> {
+        let segment = self.segments().nth(index)?;
+        if segment.component_count() == 1 {
+            segment.components().next().and_then(Component::as_number)
         } else {
             None
         }
@@ -1310,6 +1319,27 @@ mod test {
         );
     }
 
+    #[test]
+    fn as_major_and_as_major_minor_patch() {
+        assert_eq!(Version::from_str("5!3.2.1").unwrap().as_major(), Some(3));
+        assert_eq!(Version::from_str("3").unwrap().as_major(), Some(3));
+        assert_eq!(Version::from_str("3a.2").unwrap().as_major(), None);
+        assert_eq!(
+            Version::from_str("1.2.3.4").unwrap().as_major_minor_patch(),
+            Some((1, 2, 3))
+        );
+        assert_eq!(
+            Version::from_str("1.2").unwrap().as_major_minor_patch(),
+            None
+        );
+        assert_eq!(
+            Version::from_str("1.2.3rc1")
+                .unwrap()
+                .as_major_minor_patch(),
+            None
+        );
+    }
+
     #[test]
     fn canonical() {
         assert_eq!(Version::from_str("1.2.3").unwrap().to_string(), "1.2.3");
@@ -9,6 +9,7 @@ use nom::combinator::{map, opt, value};
 use nom::error::{ErrorKind, FromExternalError, ParseError};
 use nom::sequence::terminated;
 use nom::IResult;
+use rattler_error::ErrorCode;
 use smallvec::SmallVec;
 use std::{
     convert::Into,
@@ -43,6 +44,26 @@ impl Display for ParseVersionError {
 
 impl Error for ParseVersionError {}
 
+impl ErrorCode for ParseVersionError {
+    fn code(&self) -> &'static str {
+        match self.kind {
+            ParseVersionErrorKind::Empty => "rattler::version::empty",
+            ParseVersionErrorKind::EpochMustBeInteger(_) => "rattler::version::invalid_epoch",
+            ParseVersionErrorKind::InvalidNumeral(_) => "rattler::version::invalid_number",
+            ParseVersionErrorKind::TooManySegments
+            | ParseVersionErrorKind::TooManyComponentsInASegment => "rattler::version::too_long",
+            ParseVersionErrorKind::CannotMixAndMatchDashesAndUnderscores => {
+                "rattler::version::mixed_separators"
+            }
+            ParseVersionErrorKind::EmptyVersionComponent
+            | ParseVersionErrorKind::ExpectedComponent
+            | ParseVersionErrorKind::ExpectedSegmentSeparator
+            | ParseVersionErrorKind::ExpectedEof
+            | ParseVersionErrorKind::Nom(_) => "rattler::version::malformed",
+        }
+    }
+}
+
 impl ParseVersionError {
     /// Create a new parse error
     pub fn new(text: impl Into<String>, kind: ParseVersionErrorKind) -> Self {
@@ -14,6 +14,7 @@ use std::{
 pub(crate) use constraint::is_start_of_version_constraint;
 use constraint::Constraint;
 use parse::ParseConstraintError;
+use rattler_error::ErrorCode;
 use serde::{Deserialize, Serialize, Serializer};
 use thiserror::Error;
 use version_tree::VersionTree;
@@ -147,6 +148,18 @@ pub enum ParseVersionSpecError {
     InvalidConstraint(#[source] ParseConstraintError),
 }
 
+impl ErrorCode for ParseVersionSpecError {
+    fn code(&self) -> &'static str {
+        match self {
+            ParseVersionSpecError::InvalidVersion(err) => err.code(),
+            ParseVersionSpecError::InvalidVersionTree(_) => "rattler::version_spec::invalid_tree",
+            ParseVersionSpecError::InvalidConstraint(_) => {
+                "rattler::version_spec::invalid_constraint"
+            }
+        }
+    }
+}
+
 impl From<Constraint> for VersionSpec {
     fn from(constraint: Constraint) -> Self {
         match constraint {
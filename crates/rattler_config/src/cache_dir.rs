@@ -0,0 +1,88 @@
+//! Resolution of the directories in which rattler caches data.
+//!
+//! All rattler based tools share a single cache root. Every subsystem stores
+//! its data in a subdirectory of that root (see [`REPODATA_CACHE_DIR`] and
+//! [`PACKAGE_CACHE_DIR`]).
+
+use std::path::PathBuf;
+
+use crate::ConfigError;
+
+/// The environment variable that overrides the default cache root.
+pub const CACHE_DIR_ENV: &str = "RATTLER_CACHE_DIR";
+
+/// The location in the cache root where the conda package cache is stored.
+pub const PACKAGE_CACHE_DIR: &str = "pkgs";
+
+/// The location in the cache root where the repodata cache is stored.
+pub const REPODATA_CACHE_DIR: &str = "repodata";
+
+/// Returns the default root directory of the rattler cache.
+///
+/// If the `RATTLER_CACHE_DIR` environment variable is set its value is used.
+/// Otherwise the `rattler/cache` directory in the cache directory of the
+/// platform is used:
+///
+/// | Platform | Value                                                  |
+/// |----------|--------------------------------------------------------|
+/// | Linux    | `$XDG_CACHE_HOME/rattler/cache` or `$HOME/.cache/rattler/cache` |
+/// | macOS    | `$HOME/Library/Caches/rattler/cache`                   |
+/// | Windows  | `{FOLDERID_LocalAppData}\rattler\cache`                |
+pub fn default_cache_dir() -> Result<PathBuf, ConfigError> {
+    default_cache_dir_with(|name| std::env::var(name).ok(), dirs::cache_dir())
+}
+
+/// Returns the default directory of the repodata cache.
+pub fn default_repodata_cache_dir() -> Result<PathBuf, ConfigError> {
+    Ok(default_cache_dir()?.join(REPODATA_CACHE_DIR))
+}
+
+/// Returns the default directory of the package cache.
+pub fn default_package_cache_dir() -> Result<PathBuf, ConfigError> {
+    Ok(default_cache_dir()?.join(PACKAGE_CACHE_DIR))
+}
+
+fn default_cache_dir_with(
+    var: impl Fn(&str) -> Option<String>,
+    platform_cache_dir: Option<PathBuf>,
+) -> Result<PathBuf, ConfigError> {
+    if let Some(cache_dir) = var(CACHE_DIR_ENV).filter(|dir| !dir.is_empty()) {
+        return Ok(PathBuf::from(cache_dir));
+    }
+    Ok(platform_cache_dir
+        .ok_or(ConfigError::NoCacheDir)?
+        .join("rattler")
+        .join("cache"))
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::Path;
+
+    use super::*;
+
+    #[test]
+    fn test_default_cache_dir() {
+        let platform_cache_dir = Some(PathBuf::from("/home/user/.cache"));
+        assert_eq!(
+            default_cache_dir_with(|_| None, platform_cache_dir.clone()).unwrap(),
+            Path::new("/home/user/.cache").join("rattler").join("cache")
+        );
+        assert_eq!(
+            default_cache_dir_with(
+                |name| (name == CACHE_DIR_ENV).then(|| String::from("/tmp/cache")),
+                platform_cache_dir.clone()
+            )
+            .unwrap(),
+            PathBuf::from("/tmp/cache")
+        );
+        assert_eq!(
+            default_cache_dir_with(|_| Some(String::new()), platform_cache_dir).unwrap(),
+            Path::new("/home/user/.cache").join("rattler").join("cache")
+        );
+        assert!(matches!(
+            default_cache_dir_with(|_| None, None),
+            Err(ConfigError::NoCacheDir)
+        ));
+    }
+}
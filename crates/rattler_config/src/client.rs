@@ -0,0 +1,146 @@
+//! Construction of the HTTP client from the network settings of a
+//! [`RattlerConfig`].
+
+use std::path::PathBuf;
+
+use rattler_error::ErrorCode;
+use rattler_networking::{
+    authenticated_client::AuthenticatedClientBuilder, AuthenticatedClient, AuthenticationStorage,
+    ProxyConfig,
+};
+use thiserror::Error;
+
+use crate::{RattlerConfig, SslVerify};
+
+/// An error that is returned when the HTTP client cannot be constructed from
+/// the configuration.
+#[derive(Debug, Error)]
+pub enum ClientConfigError {
+    /// The CA bundle of [`SslVerify::CaBundle`] could not be read.
+    #[error("failed to read the CA bundle {0}")]
+    ReadCaBundle(PathBuf, #[source] std::io::Error),
+
+    /// The CA bundle of [`SslVerify::CaBundle`] does not contain valid
+    /// certificates.
+    #[error("invalid certificates in the CA bundle {0}")]
+    InvalidCaBundle(PathBuf, #[source] reqwest::Error),
+
+    /// One of the configured proxies is not valid.
+    #[error("invalid proxy configuration")]
+    InvalidProxy(#[source] reqwest::Error),
+
+    /// The authentication file could not be used.
+    #[error(transparent)]
+    AuthenticationFile(anyhow::Error),
+
+    /// The client could not be constructed.
+    #[error("failed to create the HTTP client")]
+    Client(#[source] reqwest::Error),
+}
+
+impl ErrorCode for ClientConfigError {
+    fn code(&self) -> &'static str {
+        match self {
+            ClientConfigError::ReadCaBundle(..) => "rattler::config::read_ca_bundle",
+            ClientConfigError::InvalidCaBundle(..) => "rattler::config::invalid_ca_bundle",
+            ClientConfigError::InvalidProxy(_) => "rattler::config::invalid_proxy",
+            ClientConfigError::AuthenticationFile(_) => "rattler::config::authentication_file",
+            ClientConfigError::Client(_) => "rattler::config::client",
+        }
+    }
+}
+
+impl RattlerConfig {
+    /// Returns a [`reqwest::ClientBuilder`] that verifies certificates as
+    /// configured by [`RattlerConfig::ssl_verify`] and uses the proxies of
+    /// [`RattlerConfig::proxy_servers`]. Proxies that are not configured are
+    /// read from the environment (see [`ProxyConfig::from_env`]).
+    pub fn reqwest_client_builder(&self) -> Result<reqwest::ClientBuilder, ClientConfigError> {
+        let mut builder = reqwest::Client::builder();
+        match &self.ssl_verify {
+            SslVerify::Enabled => {}
+            #[cfg(any(feature = "native-tls", feature = "rustls-tls"))]
+            SslVerify::Disabled => builder = builder.danger_accept_invalid_certs(true),
+            #[cfg(any(feature = "native-tls", feature = "rustls-tls"))]
+            SslVerify::CaBundle(path) => {
+                let pem = std::fs::read(path)
+                    .map_err(|e| ClientConfigError::ReadCaBundle(path.clone(), e))?;
+                let certificates = reqwest::Certificate::from_pem_bundle(&pem)
+                    .map_err(|e| ClientConfigError::InvalidCaBundle(path.clone(), e))?;
+                for certificate in certificates {
+                    builder = builder.add_root_certificate(certificate);
+                }
+            }
+            // Without a TLS backend there are no certificates to verify.
+            #[cfg(not(any(feature = "native-tls", feature = "rustls-tls")))]
+            SslVerify::Disabled | SslVerify::CaBundle(_) => {}
+        }
+
+        let mut proxies = ProxyConfig::from_env();
+        if let Some(http) = self.proxy_servers.get("http") {
+            proxies.http = Some(http.clone());
+        }
+        if let Some(https) = self.proxy_servers.get("https") {
+            proxies.https = Some(https.clone());
+        }
+        proxies
+            .apply(builder)
+            .map_err(ClientConfigError::InvalidProxy)
+    }
+
+    /// Returns the storage of the credentials that are used to authenticate
+    /// requests: the [`RattlerConfig::authentication_file`] if it is set,
+    /// otherwise the default storage.
+    pub fn authentication_storage(&self) -> Result<AuthenticationStorage, ClientConfigError> {
+        match &self.authentication_file {
+            Some(path) => AuthenticationStorage::from_file(path)
+                .map_err(ClientConfigError::AuthenticationFile),
+            None => Ok(AuthenticationStorage::default()),
+        }
+    }
+
+    /// Returns a builder for a client with all network settings of the
+    /// configuration applied. Additional middleware can be added before the
+    /// client is constructed.
+    pub fn client_builder(&self) -> Result<AuthenticatedClientBuilder, ClientConfigError> {
+        let client = self
+            .reqwest_client_builder()?
+            .build()
+            .map_err(ClientConfigError::Client)?;
+        Ok(AuthenticatedClient::builder(
+            client,
+            self.authentication_storage()?,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use rattler_error::ErrorCode;
+
+    use crate::{RattlerConfig, SslVerify};
+
+    #[test]
+    fn test_client_builder() {
+        let temp_dir = tempfile::tempdir().unwrap();
+
+        let config = RattlerConfig {
+            authentication_file: Some(temp_dir.path().join("credentials.json")),
+            ssl_verify: SslVerify::Disabled,
+            proxy_servers: [(
+                "https".to_string(),
+                "http://proxy.example.com:8080".parse().unwrap(),
+            )]
+            .into(),
+            ..RattlerConfig::default()
+        };
+        config.client_builder().unwrap().build();
+
+        let config = RattlerConfig {
+            ssl_verify: SslVerify::CaBundle(temp_dir.path().join("missing.pem")),
+            ..RattlerConfig::default()
+        };
+        let err = config.client_builder().err().unwrap();
+        assert_eq!(err.code(), "rattler::config::read_ca_bundle");
+    }
+}
@@ -0,0 +1,570 @@
+#![deny(missing_docs)]
+
+//! Configuration that is shared by all rattler based tools.
+//!
+//! A [`RattlerConfig`] is loaded from the `.condarc` files that conda also
+//! reads (see [`RattlerConfig::search_paths`]), after which environment
+//! variables are applied on top. Files that are found later in the search
+//! path take precedence over earlier ones. The configuration can then be
+//! passed to the gateway (`GatewayBuilder::with_config`), the installer
+//! (`Installer::with_config`) and the solver (through
+//! `rattler_solve::ChannelPriority::from`, which requires the `rattler_solve`
+//! feature) so that every tool behaves the same way.
+//!
+//! The network settings ([`RattlerConfig::authentication_file`],
+//! [`RattlerConfig::ssl_verify`] and [`RattlerConfig::proxy_servers`]) are
+//! applied to the HTTP client that is constructed with
+//! `RattlerConfig::client_builder`, which requires the `networking` feature.
+//! The gateway and the installer use such a client unless another client was
+//! passed to `GatewayBuilder::with_client` or
+//! `Installer::with_download_client`.
+//!
+//! The following environment variables override the values from the
+//! configuration files:
+//!
+//! | Variable                       | Field                                  |
+//! |--------------------------------|----------------------------------------|
+//! | `RATTLER_CACHE_DIR`            | [`RattlerConfig::cache_dir`]           |
+//! | `CONDA_CHANNELS`               | [`RattlerConfig::channels`] (comma separated) |
+//! | `CONDA_CHANNEL_ALIAS`          | [`RattlerConfig::channel_alias`]       |
+//! | `CONDA_CHANNEL_PRIORITY`       | [`RattlerConfig::channel_priority`]    |
+//! | `CONDA_OFFLINE`, `RATTLER_OFFLINE` | [`RattlerConfig::offline`]         |
+//! | `RATTLER_CONCURRENT_DOWNLOADS` | [`ConcurrencyConfig::downloads`]       |
+//! | `RATTLER_IO_CONCURRENCY`       | [`ConcurrencyConfig::io`]              |
+//! | `RATTLER_AUTH_FILE`            | [`RattlerConfig::authentication_file`] |
+//! | `CONDA_SSL_VERIFY`             | [`RattlerConfig::ssl_verify`]          |
+
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use rattler_conda_types::{Channel, ChannelConfig, NamedChannelOrUrl, ParseChannelError};
+use rattler_error::ErrorCode;
+use serde::Deserialize;
+use thiserror::Error;
+use url::Url;
+
+mod cache_dir;
+#[cfg(all(feature = "networking", not(target_arch = "wasm32")))]
+mod client;
+
+pub use cache_dir::{
+    default_cache_dir, default_package_cache_dir, default_repodata_cache_dir, CACHE_DIR_ENV,
+    PACKAGE_CACHE_DIR, REPODATA_CACHE_DIR,
+};
+#[cfg(all(feature = "networking", not(target_arch = "wasm32")))]
+pub use client::ClientConfigError;
+
+/// The channel that is used when no channels are configured.
+pub const DEFAULT_CHANNEL: &str = "conda-forge";
+
+/// Configuration shared by rattler based tools.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RattlerConfig {
+    /// The root directory of the rattler cache. Repodata and packages are
+    /// cached in subdirectories of this directory.
+    pub cache_dir: Option<PathBuf>,
+
+    /// The channels to use when no channels are specified explicitly.
+    pub channels: Vec<NamedChannelOrUrl>,
+
+    /// The url to prefix to channel names, defaults to
+    /// `https://conda.anaconda.org`.
+    pub channel_alias: Option<Url>,
+
+    /// How packages that are available in multiple channels are treated.
+    pub channel_priority: ChannelPriority,
+
+    /// When enabled, no network requests are made and only cached data is
+    /// used.
+    pub offline: bool,
+
+    /// Limits on the number of concurrent operations.
+    pub concurrency: ConcurrencyConfig,
+
+    /// The file to read credentials from instead of the default
+    /// authentication storage. Applied to the HTTP client, see the
+    /// [crate documentation](crate).
+    pub authentication_file: Option<PathBuf>,
+
+    /// How SSL certificates are verified. Applied to the HTTP client, see the
+    /// [crate documentation](crate).
+    pub ssl_verify: SslVerify,
+
+    /// The proxies to use for network requests keyed by the scheme of the
+    /// url (`http` or `https`), read from the `proxy_servers` key. If empty,
+    /// the proxies from the environment are used. Applied to the HTTP
+    /// client, see the [crate documentation](crate).
+    pub proxy_servers: BTreeMap<String, Url>,
+}
+
+/// Limits on the number of concurrent operations.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ConcurrencyConfig {
+    /// The maximum number of concurrent network requests.
+    pub downloads: Option<usize>,
+
+    /// The maximum number of concurrent filesystem operations.
+    pub io: Option<usize>,
+}
+
+/// How packages that are available in multiple channels are treated.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChannelPriority {
+    /// A package is only taken from the first channel it is found in.
+    #[default]
+    Strict,
+
+    /// Packages from lower priority channels are only used if that is
+    /// required to solve the environment. Solvers that do not support this
+    /// treat it as [`ChannelPriority::Disabled`].
+    Flexible,
+
+    /// Packages can be taken from any channel.
+    Disabled,
+}
+
+/// The error that is returned when a [`ChannelPriority`] cannot be parsed.
+#[derive(Debug, Clone, Error)]
+#[error("'{0}' is not a valid channel priority, expected 'strict', 'flexible' or 'disabled'")]
+pub struct ParseChannelPriorityError(pub String);
+
+#[cfg(feature = "rattler_solve")]
+impl From<ChannelPriority> for rattler_solve::ChannelPriority {
+    /// Flexible channel priority is not supported by the solvers, it is
+    /// treated as [`rattler_solve::ChannelPriority::Disabled`].
+    fn from(value: ChannelPriority) -> Self {
+        match value {
+            ChannelPriority::Strict => rattler_solve::ChannelPriority::Strict,
+            ChannelPriority::Flexible | ChannelPriority::Disabled => {
+                rattler_solve::ChannelPriority::Disabled
+            }
+        }
+    }
+}
+
+impl FromStr for ChannelPriority {
+    type Err = ParseChannelPriorityError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "strict" => Ok(Self::Strict),
+            "flexible" | "true" => Ok(Self::Flexible),
+            "disabled" | "false" => Ok(Self::Disabled),
+            _ => Err(ParseChannelPriorityError(s.to_string())),
+        }
+    }
+}
+
+/// How SSL certificates are verified.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum SslVerify {
+    /// Certificates are verified with the root certificates of the system.
+    #[default]
+    Enabled,
+
+    /// Certificates are not verified. This is insecure.
+    Disabled,
+
+    /// Certificates are verified with the certificates in the given bundle.
+    CaBundle(PathBuf),
+}
+
+impl<'de> Deserialize<'de> for SslVerify {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Raw {
+            Bool(bool),
+            Path(String),
+        }
+
+        Ok(match Raw::deserialize(deserializer)? {
+            Raw::Bool(true) => Self::Enabled,
+            Raw::Bool(false) => Self::Disabled,
+            Raw::Path(path) => SslVerify::from(path.as_str()),
+        })
+    }
+}
+
+impl From<&str> for SslVerify {
+    fn from(value: &str) -> Self {
+        match parse_bool(value) {
+            Some(true) => Self::Enabled,
+            Some(false) => Self::Disabled,
+            None => Self::CaBundle(PathBuf::from(value)),
+        }
+    }
+}
+
+/// An error that can occur when loading a [`RattlerConfig`].
+#[derive(Debug, Error)]
+pub enum ConfigError {
+    /// A configuration file could not be read.
+    #[error("failed to read {0}")]
+    Io(PathBuf, #[source] std::io::Error),
+
+    /// A configuration file could not be parsed.
+    #[error("failed to parse {0}")]
+    Parse(PathBuf, #[source] serde_yaml::Error),
+
+    /// An environment variable contains a value that is not valid.
+    #[error("invalid value '{value}' for environment variable {name}")]
+    InvalidEnvironmentVariable {
+        /// The name of the environment variable.
+        name: &'static str,
+        /// The value of the environment variable.
+        value: String,
+    },
+
+    /// The default cache directory could not be determined.
+    #[error("could not determine the cache directory for the current platform")]
+    NoCacheDir,
+}
+
+impl ErrorCode for ConfigError {
+    fn code(&self) -> &'static str {
+        match self {
+            ConfigError::Io(..) => "rattler::config::io",
+            ConfigError::Parse(..) => "rattler::config::parse",
+            ConfigError::InvalidEnvironmentVariable { .. } => {
+                "rattler::config::invalid_environment_variable"
+            }
+            ConfigError::NoCacheDir => "rattler::config::no_cache_dir",
+        }
+    }
+}
+
+/// The subset of the keys of a `.condarc` file that rattler understands.
+/// Unknown keys are ignored.
+#[derive(Debug, Default, Deserialize)]
+struct CondaRc {
+    channels: Option<Vec<NamedChannelOrUrl>>,
+    channel_alias: Option<Url>,
+    channel_priority: Option<ChannelPriority>,
+    offline: Option<bool>,
+    fetch_threads: Option<usize>,
+    ssl_verify: Option<SslVerify>,
+    proxy_servers: Option<BTreeMap<String, Url>>,
+}
+
+impl RattlerConfig {
+    /// Loads the configuration from the `.condarc` files in
+    /// [`RattlerConfig::search_paths`] and applies the environment variable
+    /// overrides.
+    pub fn load() -> Result<Self, ConfigError> {
+        let mut config = Self::from_paths(Self::search_paths())?;
+        config.apply_env()?;
+        Ok(config)
+    }
+
+    /// Reads the configuration from the given `.condarc` files. Files that do
+    /// not exist are skipped, values from later files override values from
+    /// earlier files. Environment variables are not taken into account.
+    pub fn from_paths(
+        paths: impl IntoIterator<Item = impl AsRef<Path>>,
+    ) -> Result<Self, ConfigError> {
+        let mut config = Self::default();
+        for path in paths {
+            let path = path.as_ref();
+            let contents = match std::fs::read_to_string(path) {
+                Ok(contents) => contents,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(ConfigError::Io(path.to_path_buf(), e)),
+            };
+            tracing::debug!("loading configuration from {}", path.display());
+            config.merge_condarc_str(&contents, path)?;
+        }
+        Ok(config)
+    }
+
+    /// Returns the locations of `.condarc` files in the order in which they
+    /// are read. This follows the search path of conda.
+    pub fn search_paths() -> Vec<PathBuf> {
+        let mut paths = Vec::new();
+
+        #[cfg(windows)]
+        {
+            paths.push(PathBuf::from("C:/ProgramData/conda/.condarc"));
+            paths.push(PathBuf::from("C:/ProgramData/conda/condarc"));
+        }
+        #[cfg(not(windows))]
+        {
+            paths.push(PathBuf::from("/etc/conda/.condarc"));
+            paths.push(PathBuf::from("/etc/conda/condarc"));
+            paths.push(PathBuf::from("/var/lib/conda/.condarc"));
+            paths.push(PathBuf::from("/var/lib/conda/condarc"));
+        }
+
+        if let Some(root) = std::env::var_os("CONDA_ROOT") {
+            let root = PathBuf::from(root);
+            paths.push(root.join(".condarc"));
+            paths.push(root.join("condarc"));
+        }
+
+        if let Some(config_dir) = std::env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| dirs::home_dir().map(|home| home.join(".config")))
+        {
+            paths.push(config_dir.join("conda/.condarc"));
+            paths.push(config_dir.join("conda/condarc"));
+        }
+
+        if let Some(home) = dirs::home_dir() {
+            paths.push(home.join(".conda/.condarc"));
+            paths.push(home.join(".conda/condarc"));
+            paths.push(home.join(".condarc"));
+        }
+
+        if let Some(prefix) = std::env::var_os("CONDA_PREFIX") {
+            let prefix = PathBuf::from(prefix);
+            paths.push(prefix.join(".condarc"));
+            paths.push(prefix.join("condarc"));
+        }
+
+        if let Some(condarc) = std::env::var_os("CONDARC") {
+            paths.push(PathBuf::from(condarc));
+        }
+
+        paths
+    }
+
+    /// Overrides values in this configuration with the values from the
+    /// environment variables documented at the [crate level](crate).
+    pub fn apply_env(&mut self) -> Result<(), ConfigError> {
+        self.apply_env_with(|name| std::env::var(name).ok())
+    }
+
+    /// Returns the [`ChannelConfig`] to use to resolve channel names.
+    pub fn channel_config(&self, root_dir: PathBuf) -> ChannelConfig {
+        let mut channel_config = ChannelConfig::default_with_root_dir(root_dir);
+        if let Some(channel_alias) = &self.channel_alias {
+            channel_config.channel_alias = channel_alias.clone();
+        }
+        channel_config
+    }
+
+    /// Returns the configured channels or [`DEFAULT_CHANNEL`] if no channels
+    /// are configured.
+    pub fn channels(
+        &self,
+        channel_config: &ChannelConfig,
+    ) -> Result<Vec<Channel>, ParseChannelError> {
+        if self.channels.is_empty() {
+            return Ok(vec![Channel::from_str(DEFAULT_CHANNEL, channel_config)?]);
+        }
+        Ok(self
+            .channels
+            .iter()
+            .cloned()
+            .map(|channel| channel.into_channel(channel_config))
+            .collect())
+    }
+
+    /// Returns the root directory of the cache. If no directory is configured
+    /// the [`default_cache_dir`] is used.
+    pub fn cache_dir(&self) -> Result<PathBuf, ConfigError> {
+        match &self.cache_dir {
+            Some(cache_dir) => Ok(cache_dir.clone()),
+            None => default_cache_dir(),
+        }
+    }
+
+    /// Returns the directory in which repodata is cached.
+    pub fn repodata_cache_dir(&self) -> Result<PathBuf, ConfigError> {
+        Ok(self.cache_dir()?.join(REPODATA_CACHE_DIR))
+    }
+
+    /// Returns the directory in which packages are cached.
+    pub fn package_cache_dir(&self) -> Result<PathBuf, ConfigError> {
+        Ok(self.cache_dir()?.join(PACKAGE_CACHE_DIR))
+    }
+
+    fn merge_condarc_str(&mut self, contents: &str, path: &Path) -> Result<(), ConfigError> {
+        let condarc: Option<CondaRc> = serde_yaml::from_str(contents)
+            .map_err(|e| ConfigError::Parse(path.to_path_buf(), e))?;
+        let Some(condarc) = condarc else {
+            // The file is empty
+            return Ok(());
+        };
+
+        if let Some(channels) = condarc.channels {
+            self.channels = channels;
+        }
+        if let Some(channel_alias) = condarc.channel_alias {
+            self.channel_alias = Some(channel_alias);
+        }
+        if let Some(channel_priority) = condarc.channel_priority {
+            self.channel_priority = channel_priority;
+        }
+        if let Some(offline) = condarc.offline {
+            self.offline = offline;
+        }
+        if let Some(fetch_threads) = condarc.fetch_threads {
+            self.concurrency.downloads = Some(fetch_threads);
+        }
+        if let Some(ssl_verify) = condarc.ssl_verify {
+            self.ssl_verify = ssl_verify;
+        }
+        if let Some(proxy_servers) = condarc.proxy_servers {
+            self.proxy_servers.extend(proxy_servers);
+        }
+        Ok(())
+    }
+
+    fn apply_env_with(&mut self, var: impl Fn(&str) -> Option<String>) -> Result<(), ConfigError> {
+        fn invalid(name: &'static str, value: String) -> ConfigError {
+            ConfigError::InvalidEnvironmentVariable { name, value }
+        }
+
+        if let Some(cache_dir) = var(CACHE_DIR_ENV) {
+            self.cache_dir = Some(PathBuf::from(cache_dir));
+        }
+        if let Some(channels) = var("CONDA_CHANNELS") {
+            self.channels = channels
+                .split(',')
+                .map(str::trim)
+                .filter(|channel| !channel.is_empty())
+                .map(NamedChannelOrUrl::from_str)
+                .collect::<Result<_, _>>()
+                .map_err(|_| invalid("CONDA_CHANNELS", channels.clone()))?;
+        }
+        if let Some(channel_alias) = var("CONDA_CHANNEL_ALIAS") {
+            self.channel_alias = Some(
+                Url::parse(&channel_alias)
+                    .map_err(|_| invalid("CONDA_CHANNEL_ALIAS", channel_alias))?,
+            );
+        }
+        if let Some(priority) = var("CONDA_CHANNEL_PRIORITY") {
+            self.channel_priority = priority
+                .parse()
+                .map_err(|_| invalid("CONDA_CHANNEL_PRIORITY", priority))?;
+        }
+        for name in ["CONDA_OFFLINE", "RATTLER_OFFLINE"] {
+            if let Some(offline) = var(name) {
+                self.offline = parse_bool(&offline).ok_or_else(|| invalid(name, offline))?;
+            }
+        }
+        if let Some(downloads) = var("RATTLER_CONCURRENT_DOWNLOADS") {
+            self.concurrency.downloads = Some(
+                downloads
+                    .parse()
+                    .map_err(|_| invalid("RATTLER_CONCURRENT_DOWNLOADS", downloads))?,
+            );
+        }
+        if let Some(io) = var("RATTLER_IO_CONCURRENCY") {
+            self.concurrency.io = Some(
+                io.parse()
+                    .map_err(|_| invalid("RATTLER_IO_CONCURRENCY", io))?,
+            );
+        }
+        if let Some(auth_file) = var("RATTLER_AUTH_FILE") {
+            self.authentication_file = Some(PathBuf::from(auth_file));
+        }
+        if let Some(ssl_verify) = var("CONDA_SSL_VERIFY") {
+            self.ssl_verify = SslVerify::from(ssl_verify.as_str());
+        }
+        Ok(())
+    }
+}
+
+fn parse_bool(value: &str) -> Option<bool> {
+    match value.to_ascii_lowercase().as_str() {
+        "1" | "true" | "yes" | "on" => Some(true),
+        "0" | "false" | "no" | "off" => Some(false),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    #[test]
+    fn test_later_files_take_precedence() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let system = temp_dir.path().join("system.condarc");
+        let user = temp_dir.path().join("user.condarc");
+        std::fs::write(
+            &system,
+            "channels:\n  - defaults\nchannel_priority: disabled\nssl_verify: false\nunknown_key: 1\nproxy_servers:\n  http: http://proxy.corp:3128\n  https: http://proxy.corp:3128\n",
+        )
+        .unwrap();
+        std::fs::write(
+            &user,
+            "channels:\n  - conda-forge\n  - https://example.com/channel\nfetch_threads: 5\nproxy_servers:\n  https: http://user-proxy:8080\n",
+        )
+        .unwrap();
+
+        let config =
+            RattlerConfig::from_paths([system, temp_dir.path().join("does-not-exist"), user])
+                .unwrap();
+
+        assert_eq!(
+            config
+                .channels
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>(),
+            ["conda-forge", "https://example.com/channel"]
+        );
+        assert_eq!(config.channel_priority, ChannelPriority::Disabled);
+        assert_eq!(config.ssl_verify, SslVerify::Disabled);
+        assert_eq!(config.concurrency.downloads, Some(5));
+        assert!(!config.offline);
+        assert_eq!(
+            config
+                .proxy_servers
+                .iter()
+                .map(|(scheme, url)| (scheme.as_str(), url.as_str()))
+                .collect::<Vec<_>>(),
+            [
+                ("http", "http://proxy.corp:3128/"),
+                ("https", "http://user-proxy:8080/")
+            ]
+        );
+    }
+
+    #[test]
+    fn test_environment_overrides() {
+        let env = HashMap::from([
+            ("RATTLER_CACHE_DIR", "/tmp/rattler"),
+            ("CONDA_CHANNELS", "bioconda, conda-forge"),
+            ("CONDA_CHANNEL_PRIORITY", "flexible"),
+            ("RATTLER_OFFLINE", "1"),
+            ("RATTLER_IO_CONCURRENCY", "8"),
+            ("CONDA_SSL_VERIFY", "/etc/ssl/bundle.pem"),
+        ]);
+
+        let mut config = RattlerConfig::default();
+        config
+            .apply_env_with(|name| env.get(name).map(ToString::to_string))
+            .unwrap();
+
+        assert_eq!(config.cache_dir().unwrap(), PathBuf::from("/tmp/rattler"));
+        assert_eq!(config.channels.len(), 2);
+        assert_eq!(config.channel_priority, ChannelPriority::Flexible);
+        assert!(config.offline);
+        assert_eq!(config.concurrency.io, Some(8));
+        assert_eq!(
+            config.ssl_verify,
+            SslVerify::CaBundle(PathBuf::from("/etc/ssl/bundle.pem"))
+        );
+
+        let err = RattlerConfig::default()
+            .apply_env_with(|name| (name == "RATTLER_OFFLINE").then(|| "maybe".to_string()))
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            ConfigError::InvalidEnvironmentVariable {
+                name: "RATTLER_OFFLINE",
+                ..
+            }
+        ));
+    }
+}
@@ -29,22 +29,40 @@
 //! # Available functions
 //!
 //! - [`compute_file_digest`]: Computes the hash of a file on disk.
+//! - `compute_stream_digest` and `compute_async_read_digest`: Compute the hash of an async stream
+//!   of bytes or an async reader (requires the `tokio` feature).
+//! - `parallel`: Functions that use multiple threads to hash large files or many files at once
+//!   (requires the `rayon` feature).
 //! - [`parse_digest_from_hex`]: Given a hex representation of a digest, parses it to bytes.
 //! - [`HashingWriter`]: An object that wraps a writable object and implements [`Write`] and
 //!   [`::tokio::io::AsyncWrite`]. It forwards the data to the wrapped object but also computes the hash of the
 //!   content on the fly.
+//! - [`VerifyingReader`] and [`VerifyingWriter`]: Wrap a reader or writer and fail with a
+//!   [`DigestMismatchError`] if the digest of the data does not match an expected digest.
+//!
+//! Besides SHA256, MD5 and BLAKE2 the `sha1`, `blake3` and `xxhash` features enable the
+//! `Sha1`, `Blake3` and `Xxh3` algorithms.
 //!
 //! For more information on the hashing algorithms provided by the
 //! [RustCrypto/hashes](https://github.com/RustCrypto/hashes) library, see the documentation for
 //! that library.
 
+#[cfg(feature = "rayon")]
+pub mod parallel;
+
 #[cfg(feature = "tokio")]
 mod tokio;
 
+mod verify;
+
+#[cfg(feature = "tokio")]
+pub use crate::tokio::{compute_async_read_digest, compute_stream_digest};
+
 #[cfg(feature = "serde")]
 pub mod serde;
 
 pub use digest;
+pub use verify::{DigestMismatchError, VerifyingReader, VerifyingWriter};
 
 use blake2::digest::consts::U32;
 use blake2::{Blake2b, Blake2bMac};
@@ -73,22 +91,48 @@ pub type Blake2bMac256 = Blake2bMac<U32>;
 /// A type alias for the output of a [`Blake2bMac256`] hash.
 pub type Blake2bMac256Hash = blake2::digest::Output<Blake2bMac256>;
 
+/// The SHA-1 hash algorithm. SHA-1 is not secure and should only be used to
+/// read legacy metadata.
+#[cfg(feature = "sha1")]
+pub use sha1::Sha1;
+
+/// A type alias for the output of a [`Sha1`] hash.
+#[cfg(feature = "sha1")]
+pub type Sha1Hash = Output<Sha1>;
+
+/// The BLAKE3 hash algorithm.
+#[cfg(feature = "blake3")]
+pub type Blake3 = blake3::Hasher;
+
+/// A type alias for the output of a [`Blake3`] hash.
+#[cfg(feature = "blake3")]
+pub type Blake3Hash = Output<Blake3>;
+
+/// The 64 bit variant of the XXH3 hash algorithm. XXH3 is a very fast
+/// non-cryptographic hash that can be used to validate cached files.
+#[cfg(feature = "xxhash")]
+pub type Xxh3 = twox_hash::xxh3::Hash64;
+
+/// A type alias for the output of a [`Xxh3`] hash.
+#[cfg(feature = "xxhash")]
+pub type Xxh3Hash = Output<Xxh3>;
+
 /// Compute a hash of the file at the specified location.
-pub fn compute_file_digest<D: Digest + Default + Write>(
+pub fn compute_file_digest<D: Digest + Default>(
     path: impl AsRef<Path>,
 ) -> Result<Output<D>, std::io::Error> {
     // Open the file for reading
     let mut file = File::open(path)?;
 
     // Determine the hash of the file on disk
-    let mut hasher = D::default();
+    let mut hasher = HashingWriter::<_, D>::new(std::io::sink());
     std::io::copy(&mut file, &mut hasher)?;
 
-    Ok(hasher.finalize())
+    Ok(hasher.finalize().1)
 }
 
 /// Compute a hash of the specified bytes.
-pub fn compute_bytes_digest<D: Digest + Default + Write>(bytes: impl AsRef<[u8]>) -> Output<D> {
+pub fn compute_bytes_digest<D: Digest + Default>(bytes: impl AsRef<[u8]>) -> Output<D> {
     let mut hasher = D::default();
     hasher.update(bytes);
     hasher.finalize()
@@ -226,4 +270,24 @@ mod test {
         let (_, hash) = cursor.finalize();
         assert_eq!(format!("{hash:x}"), expected_hash);
     }
+
+    #[cfg(feature = "sha1")]
+    #[test]
+    fn test_compute_sha1() {
+        let hash = super::compute_bytes_digest::<super::Sha1>("");
+        assert_eq!(
+            format!("{hash:x}"),
+            "da39a3ee5e6b4b0d3255bfef95601890afd80709"
+        );
+    }
+
+    #[cfg(feature = "blake3")]
+    #[test]
+    fn test_compute_blake3() {
+        let hash = super::compute_bytes_digest::<super::Blake3>("");
+        assert_eq!(
+            format!("{hash:x}"),
+            "af1349b9f5f9a1a6a0404dea36dcc9499bcb25c9adc112b7cc9a93cae41f3262"
+        );
+    }
 }
@@ -0,0 +1,149 @@
+//! Functions to compute digests of large files using multiple threads.
+//!
+//! A single digest can only be computed sequentially, but reading the next
+//! chunk of a file can overlap with hashing the current one, and multiple
+//! digests of the same data (or the digests of multiple files) can be computed
+//! at the same time. All functions in this module use the global [`rayon`]
+//! thread pool.
+
+use std::{fs::File, io::Read, path::Path};
+
+use digest::{Digest, Output};
+use rayon::prelude::*;
+
+/// The size of the chunks in which files are read.
+pub const DEFAULT_CHUNK_SIZE: usize = 4 * 1024 * 1024;
+
+/// Computes the hash of the file at the specified location. The file is read
+/// in chunks of [`DEFAULT_CHUNK_SIZE`] bytes and the next chunk is read while
+/// the current one is hashed.
+pub fn compute_file_digest<D: Digest + Default + Send>(
+    path: impl AsRef<Path>,
+) -> Result<Output<D>, std::io::Error> {
+    compute_reader_digest::<D>(File::open(path)?, DEFAULT_CHUNK_SIZE)
+}
+
+/// Computes the hash of all bytes read from `reader`. The reader is read in
+/// chunks of `chunk_size` bytes and the next chunk is read while the current
+/// one is hashed.
+pub fn compute_reader_digest<D: Digest + Default + Send>(
+    reader: impl Read + Send,
+    chunk_size: usize,
+) -> Result<Output<D>, std::io::Error> {
+    let mut hasher = D::default();
+    for_each_chunk(reader, chunk_size, |chunk| hasher.update(chunk))?;
+    Ok(hasher.finalize())
+}
+
+/// Computes two different hashes of the file at the specified location in a
+/// single pass over the file. Both hashes are computed in parallel.
+///
+/// This is useful to compute both the `sha256` and `md5` hash of a package
+/// archive without reading the archive twice.
+pub fn compute_file_digest_pair<D1, D2>(
+    path: impl AsRef<Path>,
+) -> Result<(Output<D1>, Output<D2>), std::io::Error>
+where
+    D1: Digest + Default + Send,
+    D2: Digest + Default + Send,
+{
+    let mut first = D1::default();
+    let mut second = D2::default();
+    for_each_chunk(File::open(path)?, DEFAULT_CHUNK_SIZE, |chunk| {
+        rayon::join(|| first.update(chunk), || second.update(chunk));
+    })?;
+    Ok((first.finalize(), second.finalize()))
+}
+
+/// Computes the hashes of multiple files in parallel. The results are returned
+/// in the same order as the paths.
+pub fn compute_file_digests<D, P>(paths: &[P]) -> Vec<Result<Output<D>, std::io::Error>>
+where
+    D: Digest + Default + Send,
+    Output<D>: Send,
+    P: AsRef<Path> + Sync,
+{
+    paths
+        .par_iter()
+        .map(|path| compute_file_digest::<D>(path))
+        .collect()
+}
+
+/// Reads `reader` in chunks of `chunk_size` bytes and calls `f` for every
+/// chunk. While `f` is processing a chunk the next chunk is read on another
+/// thread.
+fn for_each_chunk(
+    mut reader: impl Read + Send,
+    chunk_size: usize,
+    mut f: impl FnMut(&[u8]) + Send,
+) -> Result<(), std::io::Error> {
+    let mut current = vec![0u8; chunk_size];
+    let mut next = vec![0u8; chunk_size];
+    let mut len = read_chunk(&mut reader, &mut current)?;
+    while len > 0 {
+        let ((), next_len) =
+            rayon::join(|| f(&current[..len]), || read_chunk(&mut reader, &mut next));
+        len = next_len?;
+        std::mem::swap(&mut current, &mut next);
+    }
+    Ok(())
+}
+
+/// Fills `buf` with bytes from `reader` and returns the number of bytes read.
+/// The returned number is only smaller than the size of the buffer if the end
+/// of the reader was reached.
+fn read_chunk(reader: &mut impl Read, buf: &mut [u8]) -> Result<usize, std::io::Error> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..]) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(filled)
+}
+
+#[cfg(test)]
+mod test {
+    use md5::Md5;
+    use sha2::Sha256;
+
+    use super::*;
+
+    #[test]
+    fn test_parallel_digests_match_sequential() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let paths = (0..3)
+            .map(|i| {
+                let path = temp_dir.path().join(format!("file{i}"));
+                let contents = (0..10_000 * (i + 1)).map(|b| b as u8).collect::<Vec<_>>();
+                std::fs::write(&path, contents).unwrap();
+                path
+            })
+            .collect::<Vec<_>>();
+
+        for path in &paths {
+            let expected = crate::compute_file_digest::<Sha256>(path).unwrap();
+            let contents = std::fs::read(path).unwrap();
+            assert_eq!(
+                compute_reader_digest::<Sha256>(contents.as_slice(), 1000).unwrap(),
+                expected
+            );
+            assert_eq!(compute_file_digest::<Sha256>(path).unwrap(), expected);
+
+            let (sha256, md5) = compute_file_digest_pair::<Sha256, Md5>(path).unwrap();
+            assert_eq!(sha256, expected);
+            assert_eq!(md5, crate::compute_file_digest::<Md5>(path).unwrap());
+        }
+
+        let digests = compute_file_digests::<Sha256, _>(&paths);
+        for (path, digest) in paths.iter().zip(digests) {
+            assert_eq!(
+                digest.unwrap(),
+                crate::compute_file_digest::<Sha256>(path).unwrap()
+            );
+        }
+    }
+}
@@ -1,12 +1,51 @@
 use super::HashingWriter;
-use crate::HashingReader;
-use digest::Digest;
+use crate::{verify::verify, HashingReader, VerifyingReader, VerifyingWriter};
+use digest::{Digest, Output};
+use futures_util::{Stream, StreamExt};
 use std::{
     io::Error,
     pin::Pin,
     task::{Context, Poll},
 };
-use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, ReadBuf};
+
+/// Computes the hash of all chunks of bytes produced by a stream. This can be
+/// used to compute the hash of a download while it is streamed, e.g. from
+/// `reqwest::Response::bytes_stream`.
+///
+/// If the stream yields an error, computing the hash is aborted and the error
+/// is returned.
+pub async fn compute_stream_digest<D, B, E>(
+    stream: impl Stream<Item = Result<B, E>>,
+) -> Result<Output<D>, E>
+where
+    D: Digest + Default,
+    B: AsRef<[u8]>,
+{
+    let mut stream = std::pin::pin!(stream);
+    let mut hasher = D::default();
+    while let Some(bytes) = stream.next().await {
+        hasher.update(bytes?);
+    }
+    Ok(hasher.finalize())
+}
+
+/// Computes the hash of all bytes read from an [`AsyncRead`].
+pub async fn compute_async_read_digest<D: Digest + Default>(
+    reader: impl AsyncRead,
+) -> Result<Output<D>, Error> {
+    let mut reader = std::pin::pin!(reader);
+    let mut hasher = D::default();
+    let mut buf = vec![0u8; 64 * 1024];
+    loop {
+        let bytes_read = reader.read(&mut buf).await?;
+        if bytes_read == 0 {
+            break;
+        }
+        hasher.update(&buf[..bytes_read]);
+    }
+    Ok(hasher.finalize())
+}
 
 impl<W: AsyncWrite + Unpin, D: Digest> AsyncWrite for HashingWriter<W, D> {
     fn poll_write(
@@ -66,3 +105,109 @@ impl<R: AsyncRead + Unpin, D: Digest> AsyncRead for HashingReader<R, D> {
         }
     }
 }
+
+impl<R: AsyncRead + Unpin, D: Digest> AsyncRead for VerifyingReader<R, D> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        let previously_filled = buf.filled().len();
+        let requested = buf.remaining();
+
+        // This is okay because `reader` is pinned when `self` is and the other
+        // fields are never pinned.
+        let this = unsafe { self.get_unchecked_mut() };
+        let reader = unsafe { Pin::new_unchecked(&mut this.reader) };
+
+        match reader.poll_read(cx, buf) {
+            Poll::Ready(Ok(())) => {
+                Poll::Ready(this.update(&buf.filled()[previously_filled..], requested))
+            }
+            other => other,
+        }
+    }
+}
+
+impl<W: AsyncWrite + Unpin, D: Digest> AsyncWrite for VerifyingWriter<W, D> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<Result<usize, Error>> {
+        // pin-project the writer
+        let (writer, hasher) = unsafe {
+            let this = self.get_unchecked_mut();
+            (Pin::new_unchecked(&mut this.writer), &mut this.hasher)
+        };
+
+        match writer.poll_write(cx, buf) {
+            Poll::Ready(Ok(bytes)) => {
+                if let Some(hasher) = hasher {
+                    hasher.update(&buf[..bytes]);
+                }
+                Poll::Ready(Ok(bytes))
+            }
+            other => other,
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Error>> {
+        // This is okay because `writer` is pinned when `self` is.
+        let writer = unsafe { self.map_unchecked_mut(|s| &mut s.writer) };
+        writer.poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Error>> {
+        // This is okay because `writer` is pinned when `self` is and the other
+        // fields are never pinned.
+        let this = unsafe { self.get_unchecked_mut() };
+        if let Some(hasher) = this.hasher.take() {
+            verify(hasher, &this.expected)?;
+        }
+        let writer = unsafe { Pin::new_unchecked(&mut this.writer) };
+        writer.poll_shutdown(cx)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use sha2::Sha256;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_compute_stream_digest() {
+        let expected = crate::compute_bytes_digest::<Sha256>("Hello, world!");
+
+        let chunks = ["Hello", ", ", "world!"].map(Ok::<_, Error>);
+        let hash = compute_stream_digest::<Sha256, _, _>(futures_util::stream::iter(chunks))
+            .await
+            .unwrap();
+        assert_eq!(hash, expected);
+
+        let hash = compute_async_read_digest::<Sha256>("Hello, world!".as_bytes())
+            .await
+            .unwrap();
+        assert_eq!(hash, expected);
+    }
+
+    #[tokio::test]
+    async fn test_verifying_reader_and_writer() {
+        use tokio::io::AsyncWriteExt;
+
+        let expected = crate::compute_bytes_digest::<Sha256>("Hello, world!");
+
+        let mut reader = VerifyingReader::<_, Sha256>::new("Hello, world!".as_bytes(), expected);
+        let mut contents = String::new();
+        reader.read_to_string(&mut contents).await.unwrap();
+        assert_eq!(contents, "Hello, world!");
+
+        let mut reader = VerifyingReader::<_, Sha256>::new("Hello, moon!".as_bytes(), expected);
+        assert!(reader.read_to_string(&mut String::new()).await.is_err());
+
+        let mut writer = VerifyingWriter::<_, Sha256>::new(Vec::new(), expected);
+        writer.write_all(b"Hello, moon!").await.unwrap();
+        assert!(writer.shutdown().await.is_err());
+    }
+}
@@ -0,0 +1,184 @@
+//! Readers and writers that verify the digest of the data that passes through
+//! them.
+
+use std::io::{Read, Write};
+
+use digest::{Digest, Output};
+
+/// The error that is returned when the digest of the data does not match the
+/// expected digest.
+///
+/// [`VerifyingReader`] and [`VerifyingWriter`] return this error wrapped in an
+/// [`std::io::Error`] of kind [`std::io::ErrorKind::InvalidData`].
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("digest mismatch, expected {expected} but got {actual}")]
+pub struct DigestMismatchError {
+    /// The hex representation of the expected digest.
+    pub expected: String,
+
+    /// The hex representation of the digest of the data.
+    pub actual: String,
+}
+
+/// Verifies the final digest of `hasher` against `expected`.
+pub(crate) fn verify<D: Digest>(hasher: D, expected: &Output<D>) -> std::io::Result<()> {
+    let actual = hasher.finalize();
+    if &actual == expected {
+        Ok(())
+    } else {
+        Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            DigestMismatchError {
+                expected: hex::encode(expected),
+                actual: hex::encode(actual),
+            },
+        ))
+    }
+}
+
+/// A [`Read`] implementation that hashes all bytes read from the wrapped
+/// reader. When the end of the stream is reached the digest is compared with
+/// the expected digest and an error is returned if they do not match. The
+/// error is a [`DigestMismatchError`] wrapped in an [`std::io::Error`].
+///
+/// If the `tokio` feature is enabled this object also implements
+/// [`::tokio::io::AsyncRead`].
+pub struct VerifyingReader<R, D: Digest> {
+    pub(crate) reader: R,
+    pub(crate) hasher: Option<D>,
+    pub(crate) expected: Output<D>,
+}
+
+impl<R, D: Digest + Default> VerifyingReader<R, D> {
+    /// Constructs a new instance that verifies that the bytes read from
+    /// `reader` have the `expected` digest.
+    pub fn new(reader: R, expected: Output<D>) -> Self {
+        Self {
+            reader,
+            hasher: Some(D::default()),
+            expected,
+        }
+    }
+}
+
+impl<R, D: Digest> VerifyingReader<R, D> {
+    /// Consumes this instance and returns the wrapped reader.
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+
+    /// Updates the hash with the bytes that were read. If the end of the
+    /// stream was reached the digest is verified.
+    pub(crate) fn update(&mut self, buf: &[u8], requested: usize) -> std::io::Result<()> {
+        let Some(hasher) = self.hasher.as_mut() else {
+            return Ok(());
+        };
+        if !buf.is_empty() {
+            hasher.update(buf);
+        } else if requested > 0 {
+            let hasher = self.hasher.take().expect("hasher is present");
+            verify(hasher, &self.expected)?;
+        }
+        Ok(())
+    }
+}
+
+impl<R: Read, D: Digest> Read for VerifyingReader<R, D> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let bytes_read = self.reader.read(buf)?;
+        self.update(&buf[..bytes_read], buf.len())?;
+        Ok(bytes_read)
+    }
+}
+
+/// A [`Write`] implementation that hashes all bytes written to the wrapped
+/// writer. Call [`VerifyingWriter::finish`] after all data has been written to
+/// compare the digest with the expected digest.
+///
+/// If the `tokio` feature is enabled this object also implements
+/// [`::tokio::io::AsyncWrite`]. Shutting down the writer verifies the digest
+/// before the wrapped writer is shut down.
+pub struct VerifyingWriter<W, D: Digest> {
+    pub(crate) writer: W,
+    pub(crate) hasher: Option<D>,
+    pub(crate) expected: Output<D>,
+}
+
+impl<W, D: Digest + Default> VerifyingWriter<W, D> {
+    /// Constructs a new instance that verifies that the bytes written to
+    /// `writer` have the `expected` digest.
+    pub fn new(writer: W, expected: Output<D>) -> Self {
+        Self {
+            writer,
+            hasher: Some(D::default()),
+            expected,
+        }
+    }
+}
+
+impl<W, D: Digest> VerifyingWriter<W, D> {
+    /// Verifies the digest of all bytes written to this instance and returns
+    /// the wrapped writer if the digest matches.
+    pub fn finish(mut self) -> std::io::Result<W> {
+        if let Some(hasher) = self.hasher.take() {
+            verify(hasher, &self.expected)?;
+        }
+        Ok(self.writer)
+    }
+}
+
+impl<W: Write, D: Digest> Write for VerifyingWriter<W, D> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let bytes = self.writer.write(buf)?;
+        if let Some(hasher) = self.hasher.as_mut() {
+            hasher.update(&buf[..bytes]);
+        }
+        Ok(bytes)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::{Read, Write};
+
+    use sha2::Sha256;
+
+    use super::*;
+    use crate::{compute_bytes_digest, Sha256Hash};
+
+    #[test]
+    fn test_verifying_reader() {
+        let expected: Sha256Hash = compute_bytes_digest::<Sha256>("Hello, world!");
+
+        let mut reader = VerifyingReader::<_, Sha256>::new("Hello, world!".as_bytes(), expected);
+        let mut contents = String::new();
+        reader.read_to_string(&mut contents).unwrap();
+        assert_eq!(contents, "Hello, world!");
+
+        let mut reader = VerifyingReader::<_, Sha256>::new("Hello, moon!".as_bytes(), expected);
+        let err = reader.read_to_string(&mut String::new()).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+        assert!(err
+            .into_inner()
+            .unwrap()
+            .downcast::<DigestMismatchError>()
+            .is_ok());
+    }
+
+    #[test]
+    fn test_verifying_writer() {
+        let expected: Sha256Hash = compute_bytes_digest::<Sha256>("Hello, world!");
+
+        let mut writer = VerifyingWriter::<_, Sha256>::new(Vec::new(), expected);
+        writer.write_all(b"Hello, world!").unwrap();
+        assert_eq!(writer.finish().unwrap(), b"Hello, world!");
+
+        let mut writer = VerifyingWriter::<_, Sha256>::new(Vec::new(), expected);
+        writer.write_all(b"Hello, moon!").unwrap();
+        assert!(writer.finish().is_err());
+    }
+}
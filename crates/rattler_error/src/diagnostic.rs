@@ -0,0 +1,156 @@
+//! [`miette`] integration.
+
+use std::{error::Error, fmt, ops::Range};
+
+use miette::{Diagnostic, LabeledSpan, NamedSource, SourceCode};
+
+use crate::ErrorCode;
+
+/// A [`Diagnostic`] that attaches the input that caused an error to the error.
+///
+/// The diagnostic displays the message of the wrapped error, reports its
+/// [`ErrorCode`] and labels the parts of the input that caused the error.
+pub struct SourceDiagnostic<E> {
+    error: E,
+    source_code: NamedSource<String>,
+    labels: Vec<LabeledSpan>,
+    help: Option<String>,
+}
+
+impl<E: ErrorCode> SourceDiagnostic<E> {
+    /// Constructs a new diagnostic for `error` that was caused by the input
+    /// `source` which is identified by `name` (e.g. a file name).
+    pub fn new(error: E, name: impl AsRef<str>, source: impl Into<String>) -> Self {
+        Self {
+            error,
+            source_code: NamedSource::new(name, source.into()),
+            labels: Vec::new(),
+            help: None,
+        }
+    }
+
+    /// Adds a label that points to the byte range `span` of the input.
+    pub fn with_label(mut self, span: Range<usize>, label: impl Into<String>) -> Self {
+        self.labels.push(LabeledSpan::at(span, label));
+        self
+    }
+
+    /// Adds a help message that explains how the error can be resolved.
+    pub fn with_help(mut self, help: impl Into<String>) -> Self {
+        self.help = Some(help.into());
+        self
+    }
+
+    /// Returns the wrapped error.
+    pub fn error(&self) -> &E {
+        &self.error
+    }
+
+    /// Consumes this instance and returns the wrapped error.
+    pub fn into_inner(self) -> E {
+        self.error
+    }
+}
+
+impl<E: fmt::Debug> fmt::Debug for SourceDiagnostic<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SourceDiagnostic")
+            .field("error", &self.error)
+            .field("name", &self.source_code.name())
+            .field("labels", &self.labels)
+            .field("help", &self.help)
+            .finish()
+    }
+}
+
+impl<E: fmt::Display> fmt::Display for SourceDiagnostic<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.error.fmt(f)
+    }
+}
+
+impl<E: ErrorCode> Error for SourceDiagnostic<E> {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        self.error.source()
+    }
+}
+
+impl<E: ErrorCode> Diagnostic for SourceDiagnostic<E> {
+    fn code<'a>(&'a self) -> Option<Box<dyn fmt::Display + 'a>> {
+        Some(Box::new(self.error.code()))
+    }
+
+    fn help<'a>(&'a self) -> Option<Box<dyn fmt::Display + 'a>> {
+        self.help
+            .as_ref()
+            .map(|help| Box::new(help) as Box<dyn fmt::Display + 'a>)
+    }
+
+    fn source_code(&self) -> Option<&dyn SourceCode> {
+        Some(&self.source_code)
+    }
+
+    fn labels(&self) -> Option<Box<dyn Iterator<Item = LabeledSpan> + '_>> {
+        if self.labels.is_empty() {
+            None
+        } else {
+            Some(Box::new(self.labels.iter().cloned()))
+        }
+    }
+}
+
+/// Wraps any error with an [`ErrorCode`] to turn it into a [`Diagnostic`]
+/// that reports the code of the error.
+#[derive(Debug)]
+pub struct CodedDiagnostic<E>(pub E);
+
+impl<E: fmt::Display> fmt::Display for CodedDiagnostic<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl<E: ErrorCode> Error for CodedDiagnostic<E> {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        self.0.source()
+    }
+}
+
+impl<E: ErrorCode> Diagnostic for CodedDiagnostic<E> {
+    fn code<'a>(&'a self) -> Option<Box<dyn fmt::Display + 'a>> {
+        Some(Box::new(self.0.code()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[derive(Debug, thiserror::Error)]
+    #[error("invalid character")]
+    struct InvalidCharacter;
+
+    impl ErrorCode for InvalidCharacter {
+        fn code(&self) -> &'static str {
+            "rattler::test::invalid_character"
+        }
+    }
+
+    #[test]
+    fn test_source_diagnostic() {
+        let diagnostic = SourceDiagnostic::new(InvalidCharacter, "spec", "foo$bar")
+            .with_label(3..4, "not allowed")
+            .with_help("remove the character");
+
+        assert_eq!(diagnostic.to_string(), "invalid character");
+        assert_eq!(
+            diagnostic.code().unwrap().to_string(),
+            "rattler::test::invalid_character"
+        );
+        let labels = diagnostic.labels().unwrap().collect::<Vec<_>>();
+        assert_eq!(labels.len(), 1);
+        assert_eq!(labels[0].offset(), 3);
+        assert_eq!(labels[0].label(), Some("not allowed"));
+        assert!(diagnostic.source_code().is_some());
+    }
+}
@@ -0,0 +1,147 @@
+#![deny(missing_docs)]
+
+//! The error model that is shared by the rattler crates.
+//!
+//! Every crate defines its own error enums with [`thiserror`]. The errors
+//! that are returned by the parsers of `rattler_conda_types`, the repodata
+//! gateway and `fetch_repo_data`, the authentication storage backends, the
+//! solver, the installer, shell activation, package extraction, lock files
+//! and the configuration implement [`ErrorCode`]. The code of an error is a
+//! stable, machine readable identifier like
+//! `rattler::match_spec::missing_package_name` that does not change when the
+//! message of the error is reworded. This allows downstream tools to match on
+//! specific failures, link to documentation or translate messages.
+//!
+//! The codes form a hierarchy: an error that wraps another coded error
+//! returns the code of the wrapped error, e.g. a
+//! `GatewayError::FetchRepoDataError` has the code of the
+//! `FetchRepoDataError`. Errors of the lower level crates (`rattler_cache`,
+//! `rattler_digest`, `rattler_virtual_packages`, `file_url`) and errors that
+//! are only used internally do not have codes yet.
+//!
+//! Errors frequently wrap other errors. [`sources`] iterates over the chain
+//! of underlying errors and [`Report`] formats an error together with its
+//! code and all its causes:
+//!
+//! ```text
+//! error[rattler::match_spec::invalid_bracket_key]: invalid bracket key: foo
+//! ```
+//!
+//! With the `miette` feature enabled [`SourceDiagnostic`] can be used to
+//! attach the input that failed to parse (a match spec, a lock file, ...)
+//! together with a label that points at the offending part of the input.
+//! Crates use this to provide `into_diagnostic` functions on their parse
+//! errors.
+//!
+//! [`thiserror`]: https://docs.rs/thiserror
+
+use std::{error::Error, fmt};
+
+#[cfg(feature = "miette")]
+mod diagnostic;
+
+#[cfg(feature = "miette")]
+pub use diagnostic::{CodedDiagnostic, SourceDiagnostic};
+
+/// An error with a stable error code.
+pub trait ErrorCode: Error {
+    /// Returns the stable code of this error. Codes are namespaced with `::`
+    /// and always start with `rattler::`.
+    fn code(&self) -> &'static str;
+}
+
+impl<T: ErrorCode> ErrorCode for Box<T> {
+    fn code(&self) -> &'static str {
+        (**self).code()
+    }
+}
+
+/// Returns an iterator over the underlying causes of `error`. The error
+/// itself is not included.
+pub fn sources<'a>(
+    error: &'a (dyn Error + 'static),
+) -> impl Iterator<Item = &'a (dyn Error + 'static)> {
+    std::iter::successors(error.source(), |&err| err.source())
+}
+
+/// Formats an error with its code and the chain of its causes.
+///
+/// Causes that have the same message as the error they are wrapped in (for
+/// instance because the wrapping error is `#[error(transparent)]`) are only
+/// printed once.
+pub struct Report<'a, E: ?Sized> {
+    error: &'a E,
+}
+
+impl<'a, E: ErrorCode + 'static> Report<'a, E> {
+    /// Constructs a new report for the given error.
+    pub fn new(error: &'a E) -> Self {
+        Self { error }
+    }
+}
+
+impl<'a, E: ErrorCode + 'static> fmt::Display for Report<'a, E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let message = self.error.to_string();
+        write!(f, "error[{}]: {}", self.error.code(), message)?;
+
+        let mut previous = message;
+        let mut causes = sources(self.error)
+            .map(|err| err.to_string())
+            .filter(|cause| {
+                let duplicate = *cause == previous;
+                previous.clone_from(cause);
+                !duplicate
+            })
+            .peekable();
+        if causes.peek().is_some() {
+            write!(f, "\n\ncaused by:")?;
+            for cause in causes {
+                write!(f, "\n  - {cause}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<'a, E: ErrorCode + 'static> fmt::Debug for Report<'a, E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[derive(Debug, thiserror::Error)]
+    #[error("failed to read the file")]
+    struct ReadError(#[source] std::io::Error);
+
+    #[derive(Debug, thiserror::Error)]
+    enum TopLevelError {
+        #[error(transparent)]
+        Read(#[from] ReadError),
+    }
+
+    impl ErrorCode for TopLevelError {
+        fn code(&self) -> &'static str {
+            match self {
+                TopLevelError::Read(_) => "rattler::test::read",
+            }
+        }
+    }
+
+    #[test]
+    fn test_report() {
+        let err = TopLevelError::from(ReadError(std::io::Error::new(
+            std::io::ErrorKind::NotFound,
+            "no such file",
+        )));
+        assert_eq!(sources(&err).count(), 1);
+        assert_eq!(
+            Report::new(&err).to_string(),
+            "error[rattler::test::read]: failed to read the file\n\ncaused by:\n  - no such file"
+        );
+    }
+}
@@ -1,19 +1,20 @@
 //! Builder for the creation of lock files.
 
 use std::{
-    collections::{BTreeSet, HashMap},
+    collections::{BTreeMap, BTreeSet, HashMap},
     sync::Arc,
 };
 
 use fxhash::FxHashMap;
 use indexmap::{IndexMap, IndexSet};
 use pep508_rs::ExtraName;
-use rattler_conda_types::Platform;
+use rattler_conda_types::{Platform, RepoDataRecord};
+use rattler_digest::Sha256Hash;
 
 use crate::{
-    file_format_version::FileFormatVersion, Channel, CondaPackageData, EnvironmentData,
-    EnvironmentPackageData, LockFile, LockFileInner, Package, PypiIndexes, PypiPackageData,
-    PypiPackageEnvironmentData,
+    file_format_version::FileFormatVersion, Channel, CondaLockSpecification, CondaPackageData,
+    EnvironmentData, EnvironmentPackageData, GitMetadata, LockFile, LockFileInner, Package,
+    PypiIndexes, PypiPackageData, PypiPackageEnvironmentData, TimeMetadata,
 };
 
 /// A struct to incrementally build a lock-file.
@@ -26,6 +27,10 @@ pub struct LockFileBuilder {
     conda_packages: IndexSet<CondaPackageData>,
     pypi_packages: IndexSet<PypiPackageData>,
     pypi_runtime_configurations: IndexSet<HashablePypiPackageEnvironmentData>,
+
+    /// Provenance of the lock file.
+    git_metadata: Option<GitMetadata>,
+    time_metadata: Option<TimeMetadata>,
 }
 
 impl LockFileBuilder {
@@ -46,6 +51,8 @@ impl LockFileBuilder {
                 channels: vec![],
                 packages: FxHashMap::default(),
                 indexes: None,
+                content_hash: BTreeMap::new(),
+                extra: BTreeMap::new(),
             })
             .indexes = Some(indexes);
         self
@@ -63,11 +70,51 @@ impl LockFileBuilder {
                 channels: vec![],
                 packages: FxHashMap::default(),
                 indexes: None,
+                content_hash: BTreeMap::new(),
+                extra: BTreeMap::new(),
             })
             .channels = channels.into_iter().map(Into::into).collect();
         self
     }
 
+    /// Sets the conda-lock content hash of the input from which the packages
+    /// of an environment were solved for a platform, see
+    /// [`CondaLockSpecification::content_hash`].
+    pub fn set_content_hash(
+        &mut self,
+        environment: impl Into<String>,
+        platform: Platform,
+        content_hash: Sha256Hash,
+    ) -> &mut Self {
+        self.environments
+            .entry(environment.into())
+            .or_insert_with(|| EnvironmentData {
+                channels: vec![],
+                packages: FxHashMap::default(),
+                indexes: None,
+                content_hash: BTreeMap::new(),
+                extra: BTreeMap::new(),
+            })
+            .content_hash
+            .insert(platform, content_hash);
+        self
+    }
+
+    /// Sets the conda-lock content hash of the input from which the packages
+    /// of an environment were solved for a platform.
+    ///
+    /// This function is similar to [`Self::set_content_hash`] but differs in
+    /// that it consumes `self` instead of taking a mutable reference.
+    pub fn with_content_hash(
+        mut self,
+        environment: impl Into<String>,
+        platform: Platform,
+        content_hash: Sha256Hash,
+    ) -> Self {
+        self.set_content_hash(environment, platform, content_hash);
+        self
+    }
+
     /// Adds a conda locked package to a specific environment and platform.
     ///
     /// This function is similar to [`Self::with_conda_package`] but differs in
@@ -87,6 +134,8 @@ impl LockFileBuilder {
                 channels: vec![],
                 packages: HashMap::default(),
                 indexes: None,
+                content_hash: BTreeMap::new(),
+                extra: BTreeMap::new(),
             });
 
         // Add the package to the list of packages.
@@ -122,6 +171,8 @@ impl LockFileBuilder {
                 channels: vec![],
                 packages: HashMap::default(),
                 indexes: None,
+                content_hash: BTreeMap::new(),
+                extra: BTreeMap::new(),
             });
 
         // Add the package to the list of packages.
@@ -156,6 +207,49 @@ impl LockFileBuilder {
         self
     }
 
+    /// Adds the result of solving an environment for one or more platforms.
+    ///
+    /// The `specification` describes the input of the solve: its channels
+    /// are used as the channels of the environment and the content hash of
+    /// each platform is computed from it. The solved `records` of each
+    /// platform, including their hashes and dependencies, are added to the
+    /// environment.
+    pub fn add_solved_environment(
+        &mut self,
+        environment: impl Into<String>,
+        specification: &CondaLockSpecification,
+        records: impl IntoIterator<Item = (Platform, impl IntoIterator<Item = RepoDataRecord>)>,
+    ) -> &mut Self {
+        let environment = environment.into();
+        self.set_channels(environment.clone(), specification.channels.iter().cloned());
+        for (platform, records) in records {
+            self.set_content_hash(
+                environment.clone(),
+                platform,
+                specification.content_hash_for_platform(platform),
+            );
+            for record in records {
+                self.add_conda_package(environment.clone(), platform, record.into());
+            }
+        }
+        self
+    }
+
+    /// Adds the result of solving an environment for one or more platforms.
+    ///
+    /// This function is similar to [`Self::add_solved_environment`] but
+    /// differs in that it consumes `self` instead of taking a mutable
+    /// reference.
+    pub fn with_solved_environment(
+        mut self,
+        environment: impl Into<String>,
+        specification: &CondaLockSpecification,
+        records: impl IntoIterator<Item = (Platform, impl IntoIterator<Item = RepoDataRecord>)>,
+    ) -> Self {
+        self.add_solved_environment(environment, specification, records);
+        self
+    }
+
     /// Adds a package from another environment to a specific environment and
     /// platform.
     pub fn with_package(
@@ -225,6 +319,32 @@ impl LockFileBuilder {
         self
     }
 
+    /// Sets the git repository from which the lock file is created, see
+    /// [`GitMetadata::from_repository`].
+    pub fn set_git_metadata(&mut self, git_metadata: GitMetadata) -> &mut Self {
+        self.git_metadata = Some(git_metadata);
+        self
+    }
+
+    /// Sets the git repository from which the lock file is created, see
+    /// [`GitMetadata::from_repository`].
+    pub fn with_git_metadata(mut self, git_metadata: GitMetadata) -> Self {
+        self.set_git_metadata(git_metadata);
+        self
+    }
+
+    /// Sets when the lock file is created, usually [`TimeMetadata::now`].
+    pub fn set_time_metadata(&mut self, time_metadata: TimeMetadata) -> &mut Self {
+        self.time_metadata = Some(time_metadata);
+        self
+    }
+
+    /// Sets when the lock file is created, usually [`TimeMetadata::now`].
+    pub fn with_time_metadata(mut self, time_metadata: TimeMetadata) -> Self {
+        self.set_time_metadata(time_metadata);
+        self
+    }
+
     /// Build a [`LockFile`]
     pub fn finish(self) -> LockFile {
         let (environment_lookup, environments) = self
@@ -246,6 +366,9 @@ impl LockFileBuilder {
                     .collect(),
                 environments,
                 environment_lookup,
+                git_metadata: self.git_metadata,
+                time_metadata: self.time_metadata,
+                extra: BTreeMap::new(),
             }),
         }
     }
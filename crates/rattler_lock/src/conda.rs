@@ -1,7 +1,7 @@
 use rattler_conda_types::{PackageRecord, RepoDataRecord};
 use serde::{Deserialize, Serialize};
 use serde_with::{serde_as, skip_serializing_none};
-use std::cmp::Ordering;
+use std::{cmp::Ordering, collections::BTreeMap};
 use url::Url;
 
 /// A locked conda dependency is just a [`PackageRecord`] with some additional information on where
@@ -23,6 +23,12 @@ pub struct CondaPackageData {
 
     /// The channel of the package if this cannot be derived from the url.
     pub(crate) channel: Option<Url>,
+
+    /// Additional metadata of the package, e.g. a reference to a provenance attestation. These
+    /// fields are stored next to the other fields of the package in the lock file. Fields in the
+    /// lock file that are not known to this version of the crate also end up here.
+    #[serde(skip)]
+    pub extra: BTreeMap<String, serde_yaml::Value>,
 }
 
 impl AsRef<PackageRecord> for CondaPackageData {
@@ -75,13 +81,19 @@ impl From<RepoDataRecord> for CondaPackageData {
             Some(value.file_name)
         };
 
+        // Packages are not necessarily stored next to the repodata of their channel (e.g. when
+        // the repodata specifies a `base_url`). In that case the channel cannot be derived from
+        // the url and it is stored explicitly.
+        let channel = Url::parse(&value.channel).ok().filter(|channel| {
+            channel_from_url(&value.url).map_or(true, |derived| !is_same_url(&derived, channel))
+        });
+
         Self {
             package_record: value.package_record,
             url: value.url,
             file_name,
-            // TODO: This is not entirely correct. It should be derived from the `channel` field in
-            //  the repodata record.
-            channel: None,
+            channel,
+            extra: BTreeMap::new(),
         }
     }
 }
@@ -131,6 +143,11 @@ fn file_name_from_url(url: &Url) -> Option<&str> {
     path.last()
 }
 
+/// Returns true if both urls are the same, ignoring a trailing slash.
+pub(crate) fn is_same_url(a: &Url, b: &Url) -> bool {
+    a.as_str().trim_end_matches('/') == b.as_str().trim_end_matches('/')
+}
+
 /// Channel from url, this is everything before the filename and the subdir
 /// So for example: <https://conda.anaconda.org/conda-forge/> is a channel name
 /// that we parse from something like: <https://conda.anaconda.org/conda-forge/osx-64/python-3.11.0-h4150a38_1_cpython.conda>
@@ -162,6 +179,42 @@ mod test {
         );
     }
 
+    #[test]
+    fn test_channel_of_package_outside_of_channel() {
+        let mut package_record = PackageRecord::new(
+            "foo".parse().unwrap(),
+            "1.0".parse::<rattler_conda_types::Version>().unwrap(),
+            "h123_0".to_string(),
+        );
+        package_record.subdir = "linux-64".to_string();
+        let record = RepoDataRecord {
+            package_record,
+            file_name: "foo-1.0-h123_0.conda".to_string(),
+            url: Url::parse("https://cdn.example.com/packages/linux-64/foo-1.0-h123_0.conda")
+                .unwrap(),
+            channel: "https://conda.example.com/my-channel/".to_string(),
+        };
+
+        // The package is stored on a different host, the channel must be stored explicitly.
+        let data = CondaPackageData::from(record.clone());
+        assert_eq!(
+            data.channel(),
+            Some(Url::parse("https://conda.example.com/my-channel/").unwrap())
+        );
+        assert_eq!(
+            RepoDataRecord::try_from(&data).unwrap().channel,
+            record.channel
+        );
+
+        // If the package is stored next to the repodata the channel is derived from the url.
+        let data = CondaPackageData::from(RepoDataRecord {
+            url: Url::parse("https://conda.example.com/my-channel/linux-64/foo-1.0-h123_0.conda")
+                .unwrap(),
+            ..record
+        });
+        assert_eq!(data.channel, None);
+    }
+
     #[test]
     fn test_file_name_from_url() {
         assert_eq!(file_name_from_url(&Url::parse("https://conda.anaconda.org/conda-forge/osx-64/python-3.11.0-h4150a38_1_cpython.conda").unwrap()), Some("python-3.11.0-h4150a38_1_cpython.conda"));
@@ -0,0 +1,304 @@
+//! Computation of the `content_hash` that conda-lock stores for every platform
+//! in the metadata of a lock-file.
+//!
+//! Conda-lock hashes the input of a lock-file, not the locked packages. The
+//! hash is the SHA-256 of `json.dumps(data, sort_keys=True)` where `data`
+//! contains the channels and the dependencies of a platform. The JSON written
+//! here matches the output of Python's `json.dumps` byte-for-byte.
+
+use std::{collections::BTreeMap, fmt::Write};
+
+use rattler_conda_types::{MatchSpec, Platform};
+use rattler_digest::{Sha256, Sha256Hash};
+
+use crate::Channel;
+
+/// A dependency of a conda-lock lock specification. This mirrors the
+/// `VersionedDependency` model of conda-lock.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CondaLockDependency {
+    /// The name of the package.
+    pub name: String,
+
+    /// The package manager that provides the package, `conda` or `pip`.
+    pub manager: String,
+
+    /// The category of the dependency, `main` unless specified otherwise.
+    pub category: String,
+
+    /// The extras of a pip dependency.
+    pub extras: Vec<String>,
+
+    /// The environment markers of a pip dependency.
+    pub markers: Option<String>,
+
+    /// The version constraint, an empty string if there is none.
+    pub version: String,
+
+    /// The build string constraint.
+    pub build: Option<String>,
+
+    /// The channel the package must come from.
+    pub conda_channel: Option<String>,
+}
+
+impl CondaLockDependency {
+    /// Constructs a conda dependency in the `main` category.
+    pub fn conda(name: impl Into<String>, version: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            manager: "conda".to_string(),
+            category: "main".to_string(),
+            extras: Vec::new(),
+            markers: None,
+            version: version.into(),
+            build: None,
+            conda_channel: None,
+        }
+    }
+
+    /// Constructs a pip dependency in the `main` category.
+    pub fn pip(name: impl Into<String>, version: impl Into<String>) -> Self {
+        Self {
+            manager: "pip".to_string(),
+            ..Self::conda(name, version)
+        }
+    }
+
+    /// Constructs a conda dependency in the `main` category from a match
+    /// spec, the way conda-lock does for the specs of an `environment.yml`.
+    /// Returns `None` if the match spec has no name.
+    pub fn from_match_spec(spec: &MatchSpec) -> Option<Self> {
+        let version = spec
+            .version
+            .as_ref()
+            .map(ToString::to_string)
+            .unwrap_or_default();
+        Some(Self {
+            build: spec.build.as_ref().map(ToString::to_string),
+            conda_channel: spec.channel.as_ref().map(|channel| {
+                channel
+                    .name
+                    .clone()
+                    .unwrap_or_else(|| channel.canonical_name())
+            }),
+            ..Self::conda(spec.name.as_ref()?.as_source(), version)
+        })
+    }
+}
+
+/// The input of a conda-lock lock-file from which the content hashes are
+/// computed.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CondaLockSpecification {
+    /// The channels in order of priority.
+    pub channels: Vec<Channel>,
+
+    /// The dependencies for each platform of the lock-file.
+    pub dependencies: BTreeMap<Platform, Vec<CondaLockDependency>>,
+}
+
+impl CondaLockSpecification {
+    /// Computes the `content_hash` of every platform.
+    ///
+    /// The hash does not include the `virtual_package_hash` that conda-lock
+    /// adds when it is run with a virtual package repository.
+    pub fn content_hash(&self) -> BTreeMap<Platform, Sha256Hash> {
+        self.dependencies
+            .keys()
+            .map(|&platform| (platform, self.content_hash_for_platform(platform)))
+            .collect()
+    }
+
+    /// Computes the `content_hash` of a single platform.
+    pub fn content_hash_for_platform(&self, platform: Platform) -> Sha256Hash {
+        rattler_digest::compute_bytes_digest::<Sha256>(self.hash_input(platform).as_bytes())
+    }
+
+    /// Returns the JSON document that is hashed for the given platform.
+    fn hash_input(&self, platform: Platform) -> String {
+        let mut specs: Vec<&CondaLockDependency> = self
+            .dependencies
+            .get(&platform)
+            .into_iter()
+            .flatten()
+            .collect();
+        specs.sort_by(|a, b| (&a.manager, &a.name).cmp(&(&b.manager, &b.name)));
+
+        let mut json = String::from("{\"channels\": [");
+        for (idx, channel) in self.channels.iter().enumerate() {
+            if idx > 0 {
+                json.push_str(", ");
+            }
+            json.push_str("{\"url\": ");
+            write_string(&mut json, &channel.url);
+            json.push_str(", \"used_env_vars\": ");
+            write_string_list(&mut json, channel.used_env_vars.iter());
+            json.push('}');
+        }
+        json.push_str("], \"specs\": [");
+        for (idx, spec) in specs.into_iter().enumerate() {
+            if idx > 0 {
+                json.push_str(", ");
+            }
+            // Conda-lock sorts the extras when the dependency is constructed.
+            let mut extras: Vec<&String> = spec.extras.iter().collect();
+            extras.sort();
+
+            json.push_str("{\"build\": ");
+            write_optional_string(&mut json, spec.build.as_deref());
+            json.push_str(", \"category\": ");
+            write_string(&mut json, &spec.category);
+            json.push_str(", \"conda_channel\": ");
+            write_optional_string(&mut json, spec.conda_channel.as_deref());
+            json.push_str(", \"extras\": ");
+            write_string_list(&mut json, extras.into_iter());
+            json.push_str(", \"manager\": ");
+            write_string(&mut json, &spec.manager);
+            json.push_str(", \"markers\": ");
+            write_optional_string(&mut json, spec.markers.as_deref());
+            json.push_str(", \"name\": ");
+            write_string(&mut json, &spec.name);
+            json.push_str(", \"version\": ");
+            write_string(&mut json, &spec.version);
+            json.push('}');
+        }
+        json.push_str("]}");
+        json
+    }
+}
+
+fn write_string_list<'a>(json: &mut String, values: impl Iterator<Item = &'a String>) {
+    json.push('[');
+    for (idx, value) in values.enumerate() {
+        if idx > 0 {
+            json.push_str(", ");
+        }
+        write_string(json, value);
+    }
+    json.push(']');
+}
+
+fn write_optional_string(json: &mut String, value: Option<&str>) {
+    match value {
+        Some(value) => write_string(json, value),
+        None => json.push_str("null"),
+    }
+}
+
+/// Writes a JSON string the way Python's `json.dumps` does with the default
+/// `ensure_ascii=True`.
+fn write_string(json: &mut String, value: &str) {
+    json.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            '\u{08}' => json.push_str("\\b"),
+            '\u{0c}' => json.push_str("\\f"),
+            ' '..='~' => json.push(c),
+            _ => {
+                let mut buf = [0u16; 2];
+                for unit in c.encode_utf16(&mut buf) {
+                    write!(json, "\\u{unit:04x}").expect("writing to a string cannot fail");
+                }
+            }
+        }
+    }
+    json.push('"');
+}
+
+#[cfg(test)]
+mod test {
+    use std::{collections::BTreeMap, path::Path, str::FromStr};
+
+    use rattler_conda_types::Platform;
+    use rstest::rstest;
+
+    use super::{CondaLockDependency, CondaLockSpecification};
+    use crate::Channel;
+
+    #[rstest]
+    #[case("v3/robostack-turtlesim-conda-lock.yml")]
+    #[case("forward-compatible-lock.yml")]
+    fn test_conda_lock_content_hash(#[case] file_name: &str) {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("../../test-data/conda-lock")
+            .join(file_name);
+        let lock_file: serde_yaml::Value =
+            serde_yaml::from_str(&std::fs::read_to_string(path).unwrap()).unwrap();
+        let metadata = &lock_file["metadata"];
+
+        // These lock-files were created from an input without any dependencies.
+        let spec = CondaLockSpecification {
+            channels: metadata["channels"]
+                .as_sequence()
+                .unwrap()
+                .iter()
+                .map(|channel| serde_yaml::from_value::<Channel>(channel.clone()).unwrap())
+                .collect(),
+            dependencies: metadata["platforms"]
+                .as_sequence()
+                .unwrap()
+                .iter()
+                .map(|platform| {
+                    (
+                        Platform::from_str(platform.as_str().unwrap()).unwrap(),
+                        vec![],
+                    )
+                })
+                .collect(),
+        };
+
+        let expected: BTreeMap<Platform, String> =
+            serde_yaml::from_value(metadata["content_hash"].clone()).unwrap();
+        let actual: BTreeMap<Platform, String> = spec
+            .content_hash()
+            .into_iter()
+            .map(|(platform, hash)| (platform, format!("{hash:x}")))
+            .collect();
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn test_content_hash_json() {
+        let spec = CondaLockSpecification {
+            channels: vec![
+                Channel::from("conda-forge"),
+                Channel {
+                    url: "https://${USER}@example.com/ch\u{e9}/".to_string(),
+                    used_env_vars: vec!["USER".to_string()],
+                },
+            ],
+            dependencies: BTreeMap::from([(
+                Platform::Linux64,
+                vec![
+                    CondaLockDependency {
+                        extras: vec!["socks".to_string()],
+                        markers: Some("python_version >= \"3.8\"".to_string()),
+                        ..CondaLockDependency::pip("requests", ">=2")
+                    },
+                    CondaLockDependency::conda("python", "3.11.*"),
+                    CondaLockDependency {
+                        build: Some("py311*".to_string()),
+                        conda_channel: Some("conda-forge".to_string()),
+                        ..CondaLockDependency::conda("numpy", "")
+                    },
+                ],
+            )]),
+        };
+
+        // Created with Python's `json.dumps(data, sort_keys=True)`.
+        assert_eq!(
+            spec.hash_input(Platform::Linux64),
+            r#"{"channels": [{"url": "conda-forge", "used_env_vars": []}, {"url": "https://${USER}@example.com/ch\u00e9/", "used_env_vars": ["USER"]}], "specs": [{"build": "py311*", "category": "main", "conda_channel": "conda-forge", "extras": [], "manager": "conda", "markers": null, "name": "numpy", "version": ""}, {"build": null, "category": "main", "conda_channel": null, "extras": [], "manager": "conda", "markers": null, "name": "python", "version": "3.11.*"}, {"build": null, "category": "main", "conda_channel": null, "extras": ["socks"], "manager": "pip", "markers": "python_version >= \"3.8\"", "name": "requests", "version": ">=2"}]}"#
+        );
+        assert_eq!(
+            format!("{:x}", spec.content_hash_for_platform(Platform::Linux64)),
+            "a5932cbaf895ea10dd7e389b3ec6440a55c14de77dee8f0560d7f3027aa8714e"
+        );
+    }
+}
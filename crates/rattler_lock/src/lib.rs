@@ -58,6 +58,12 @@
 //! input/source without requiring additional input (e.g. network requests) or
 //! expensive solves. We call this static satisfiability verification.
 //!
+//! The `content-hash` of conda-lock files is kept when they are parsed and is
+//! available through [`Environment::content_hash`]. Lock-files built with
+//! [`LockFileBuilder::add_solved_environment`] record the hash of the
+//! [`CondaLockSpecification`] they were solved from, which allows tools to
+//! verify it the same way conda-lock does.
+//!
 //! Conda-lock stores a custom __partial__ representation of a
 //! [`rattler_conda_types::RepoDataRecord`] in the lock-file. This poses a
 //! problem when incrementally updating an environment. To only partially update
@@ -78,7 +84,7 @@
 
 use std::{
     borrow::Cow,
-    collections::{BTreeSet, HashMap},
+    collections::{BTreeMap, BTreeSet, HashMap},
     io::Read,
     path::Path,
     str::FromStr,
@@ -87,14 +93,20 @@ use std::{
 
 use fxhash::FxHashMap;
 use pep508_rs::{ExtraName, Requirement};
-use rattler_conda_types::{MatchSpec, PackageRecord, Platform, RepoDataRecord};
+use rattler_conda_types::{
+    ExplicitEnvironmentEntry, ExplicitEnvironmentSpec, MatchSpec, NamelessMatchSpec, PackageName,
+    PackageRecord, ParseMatchSpecError, ParseStrictness, Platform, RepoDataRecord,
+};
+use rattler_digest::Sha256Hash;
 use url::Url;
 
 mod builder;
 mod channel;
 mod conda;
+mod content_hash;
 mod file_format_version;
 mod hash;
+mod metadata;
 mod parse;
 mod pypi;
 mod pypi_indexes;
@@ -102,12 +114,16 @@ mod url_or_path;
 mod utils;
 
 pub use builder::LockFileBuilder;
-pub use channel::Channel;
+pub use channel::{Channel, MissingEnvVarError};
 pub use conda::{CondaPackageData, ConversionError};
+pub use content_hash::{CondaLockDependency, CondaLockSpecification};
 pub use file_format_version::FileFormatVersion;
 pub use hash::PackageHashes;
+pub use metadata::{GitMetadata, GitMetadataError, TimeMetadata};
 pub use parse::ParseCondaLockError;
-pub use pypi::{PypiPackageData, PypiPackageEnvironmentData, PypiSourceTreeHashable};
+pub use pypi::{
+    PypiDistributionKind, PypiPackageData, PypiPackageEnvironmentData, PypiSourceTreeHashable,
+};
 pub use pypi_indexes::{FindLinksUrlOrPath, PypiIndexes};
 pub use rattler_conda_types::Matches;
 pub use url_or_path::UrlOrPath;
@@ -140,13 +156,23 @@ struct LockFileInner {
     pypi_environment_package_data: Vec<PypiPackageEnvironmentData>,
 
     environment_lookup: FxHashMap<String, usize>,
+
+    /// The git repository from which the lock file was created.
+    git_metadata: Option<GitMetadata>,
+
+    /// When the lock file was created.
+    time_metadata: Option<TimeMetadata>,
+
+    /// Top-level fields of the lock file that are not known to this version
+    /// of the crate. They are written back when the lock file is serialized.
+    extra: BTreeMap<String, serde_yaml::Value>,
 }
 
 /// An package used in an environment. Selects a type of package based on the
 /// enum and might contain additional data that is specific to the environment.
 /// For instance different environments might select the same Pypi package but
 /// with different extras.
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 enum EnvironmentPackageData {
     Conda(usize),
     Pypi(usize, usize),
@@ -169,6 +195,14 @@ struct EnvironmentData {
     /// For each individual platform this environment supports we store the
     /// package identifiers associated with the environment.
     packages: FxHashMap<Platform, Vec<EnvironmentPackageData>>,
+
+    /// The conda-lock content hash of the input from which the packages of
+    /// each platform were solved.
+    content_hash: BTreeMap<Platform, Sha256Hash>,
+
+    /// Fields of the environment that are not known to this version of the
+    /// crate.
+    extra: BTreeMap<String, serde_yaml::Value>,
 }
 
 impl LockFile {
@@ -191,10 +225,19 @@ impl LockFile {
         Self::from_str(&source)
     }
 
-    /// Writes the conda lock to a file
+    /// Writes the conda lock to a file. See [`LockFile::render_to_string`].
     pub fn to_path(&self, path: &Path) -> Result<(), std::io::Error> {
-        let file = std::fs::File::create(path)?;
-        serde_yaml::to_writer(file, self)
+        std::fs::write(path, self.render_to_string()?)
+    }
+
+    /// Renders the conda lock to a YAML string.
+    ///
+    /// The output is deterministic: environments, platforms and packages are
+    /// sorted so that the same lock-file always renders to the same string,
+    /// regardless of the order in which its content was added. This keeps
+    /// diffs of lock-files that are checked into version control small.
+    pub fn render_to_string(&self) -> Result<String, std::io::Error> {
+        serde_yaml::to_string(self)
             .map_err(|err| std::io::Error::new(std::io::ErrorKind::Other, err))
     }
 
@@ -233,6 +276,124 @@ impl LockFile {
     pub fn version(&self) -> FileFormatVersion {
         self.inner.version
     }
+
+    /// Returns information about the git repository from which the lock-file
+    /// was created, if it was recorded.
+    pub fn git_metadata(&self) -> Option<&GitMetadata> {
+        self.inner.git_metadata.as_ref()
+    }
+
+    /// Returns when the lock-file was created, if it was recorded.
+    pub fn time_metadata(&self) -> Option<&TimeMetadata> {
+        self.inner.time_metadata.as_ref()
+    }
+
+    /// Returns a copy of this lock-file in which the conda packages of the
+    /// given environment and platform are replaced by `records`, e.g. the
+    /// result of a new solve. All other packages are left untouched.
+    ///
+    /// Records of packages that were already locked for the environment and
+    /// platform (i.e. that have the same url) keep their previously locked
+    /// data, even if the metadata of the package changed since (e.g. because
+    /// of a repodata patch). This way only the packages that were actually
+    /// updated change when the lock-file is written again. The environment is
+    /// added if it does not exist yet.
+    pub fn with_updated_conda_packages(
+        &self,
+        environment: &str,
+        platform: Platform,
+        records: impl IntoIterator<Item = RepoDataRecord>,
+    ) -> LockFile {
+        let mut builder = LockFileBuilder::new();
+        let mut previous = HashMap::new();
+        for (name, env) in self.environments() {
+            builder.set_channels(name, env.channels().iter().cloned());
+            if let Some(indexes) = env.pypi_indexes() {
+                builder.set_pypi_indexes(name, indexes.clone());
+            }
+            for (&platform, &content_hash) in &env.data().content_hash {
+                builder.set_content_hash(name, platform, content_hash);
+            }
+            for (env_platform, packages) in env.packages_by_platform() {
+                let replace = name == environment && env_platform == platform;
+                for package in packages {
+                    match package {
+                        Package::Conda(package) if replace => {
+                            previous.insert(package.url().clone(), package);
+                        }
+                        package => {
+                            builder.add_package(name, env_platform, package);
+                        }
+                    }
+                }
+            }
+        }
+
+        for record in records {
+            let package_data = match previous.get(&record.url) {
+                Some(package) => package.package_data().clone(),
+                None => CondaPackageData::from(record),
+            };
+            builder.add_conda_package(environment, platform, package_data);
+        }
+
+        self.finish_derived(builder)
+    }
+
+    /// Returns a normalized copy of this lock-file. Lock-files that lock the
+    /// same packages are rendered identically after normalization, even if
+    /// they were created on different machines.
+    ///
+    /// Duplicate packages in an environment are removed and the packages are
+    /// sorted, conda packages first and then by name and url. Channel urls
+    /// are canonicalized (see [`Channel::normalize`]). Hashes need no
+    /// normalization because they are stored as bytes and always written in
+    /// lower-case.
+    pub fn normalize(&self) -> LockFile {
+        let mut builder = LockFileBuilder::new();
+        for (name, env) in self.environments() {
+            builder.set_channels(name, env.channels().iter().map(Channel::normalize));
+            if let Some(indexes) = env.pypi_indexes() {
+                builder.set_pypi_indexes(name, indexes.clone());
+            }
+            for (&platform, &content_hash) in &env.data().content_hash {
+                builder.set_content_hash(name, platform, content_hash);
+            }
+            for (platform, packages) in env.packages_by_platform() {
+                let mut packages = packages.collect::<Vec<_>>();
+                packages.sort_by_cached_key(|package| {
+                    (
+                        package.is_pypi(),
+                        package.name().to_lowercase(),
+                        package.url_or_path().to_string(),
+                    )
+                });
+                packages.dedup_by(|a, b| {
+                    a.is_pypi() == b.is_pypi() && a.url_or_path() == b.url_or_path()
+                });
+                for package in packages {
+                    builder.add_package(name, platform, package);
+                }
+            }
+        }
+        self.finish_derived(builder)
+    }
+
+    /// Finishes a lock-file that is derived from this lock-file, copying
+    /// the fields the builder does not know about.
+    fn finish_derived(&self, builder: LockFileBuilder) -> LockFile {
+        let mut lock_file = builder.finish();
+        let inner = Arc::get_mut(&mut lock_file.inner).expect("the lock file was just created");
+        inner.extra.clone_from(&self.inner.extra);
+        inner.git_metadata.clone_from(&self.inner.git_metadata);
+        inner.time_metadata.clone_from(&self.inner.time_metadata);
+        for (name, &idx) in &inner.environment_lookup {
+            if let Some(env) = self.environment(name) {
+                inner.environments[idx].extra.clone_from(&env.data().extra);
+            }
+        }
+        lock_file
+    }
 }
 
 /// Information about a specific environment in the lock-file.
@@ -261,6 +422,13 @@ impl Environment {
         &self.data().channels
     }
 
+    /// Returns the conda-lock content hash of the input from which the
+    /// packages of the given platform were solved, if it was recorded. See
+    /// [`CondaLockSpecification::content_hash`].
+    pub fn content_hash(&self, platform: Platform) -> Option<&Sha256Hash> {
+        self.data().content_hash.get(&platform)
+    }
+
     /// Returns the Pypi indexes that were used to solve this environment.
     ///
     /// If there are no pypi packages in the lock-file this will return `None`.
@@ -283,6 +451,53 @@ impl Environment {
         )
     }
 
+    /// Returns the conda packages for a specific platform in this environment.
+    /// Returns `None` if the platform is not defined for this environment.
+    pub fn conda_packages(
+        &self,
+        platform: Platform,
+    ) -> Option<impl DoubleEndedIterator<Item = CondaPackage> + '_> {
+        let packages = self.data().packages.get(&platform)?;
+        Some(packages.iter().filter_map(move |package| match *package {
+            EnvironmentPackageData::Conda(index) => Some(CondaPackage {
+                inner: self.inner.clone(),
+                index,
+            }),
+            EnvironmentPackageData::Pypi(..) => None,
+        }))
+    }
+
+    /// Returns the conda packages for a specific platform as an
+    /// [`ExplicitEnvironmentSpec`], which can be installed with conda itself
+    /// (`conda create --file <file>`). Returns `None` if the platform is not
+    /// defined for this environment.
+    ///
+    /// The packages are sorted in installation order and the url of each
+    /// package includes its MD5 (or, if not available, SHA256) hash. Pypi
+    /// packages cannot be represented in an explicit environment file and are
+    /// omitted.
+    pub fn explicit_environment_spec(&self, platform: Platform) -> Option<ExplicitEnvironmentSpec> {
+        let packages =
+            PackageRecord::sort_topologically(self.conda_packages(platform)?.collect::<Vec<_>>());
+        let packages = packages
+            .iter()
+            .map(|package| {
+                let record = package.package_record();
+                let mut url = package.url().clone();
+                if let Some(md5) = &record.md5 {
+                    url.set_fragment(Some(&format!("{md5:x}")));
+                } else if let Some(sha256) = &record.sha256 {
+                    url.set_fragment(Some(&format!("sha256:{sha256:x}")));
+                }
+                ExplicitEnvironmentEntry { url }
+            })
+            .collect();
+        Some(ExplicitEnvironmentSpec {
+            platform: Some(platform),
+            packages,
+        })
+    }
+
     /// Returns an iterator over all packages and platforms defined for this
     /// environment
     pub fn packages_by_platform(
@@ -546,6 +761,29 @@ impl CondaPackage {
         self.package_data().channel()
     }
 
+    /// Returns the additional metadata of the package, see
+    /// [`CondaPackageData::extra`].
+    pub fn extra(&self) -> &BTreeMap<String, serde_yaml::Value> {
+        &self.package_data().extra
+    }
+
+    /// Parses the dependencies of the package ([`PackageRecord::depends`]).
+    /// Each dependency is returned as the name of the package that is
+    /// depended on together with the spec that the package must match.
+    pub fn dependencies(
+        &self,
+    ) -> Result<Vec<(PackageName, NamelessMatchSpec)>, ParseMatchSpecError> {
+        parse_named_specs(&self.package_record().depends)
+    }
+
+    /// Parses the constraints of the package ([`PackageRecord::constrains`]),
+    /// see [`Self::dependencies`].
+    pub fn constraints(
+        &self,
+    ) -> Result<Vec<(PackageName, NamelessMatchSpec)>, ParseMatchSpecError> {
+        parse_named_specs(&self.package_record().constrains)
+    }
+
     /// Returns true if this package satisfies the given `spec`.
     pub fn satisfies(&self, spec: &MatchSpec) -> bool {
         // Check the data in the package record
@@ -555,7 +793,10 @@ impl CondaPackage {
 
         // Check the the channel
         if let Some(channel) = &spec.channel {
-            if !self.url().as_str().starts_with(channel.base_url.as_str()) {
+            let matches_channel = self
+                .channel()
+                .map_or(false, |url| conda::is_same_url(&url, &channel.base_url));
+            if !matches_channel {
                 return false;
             }
         }
@@ -564,6 +805,22 @@ impl CondaPackage {
     }
 }
 
+/// Parses the match specs that are stored in a [`PackageRecord`] and splits
+/// them into a name and a [`NamelessMatchSpec`].
+fn parse_named_specs(
+    specs: &[String],
+) -> Result<Vec<(PackageName, NamelessMatchSpec)>, ParseMatchSpecError> {
+    specs
+        .iter()
+        .map(
+            |spec| match MatchSpec::from_str(spec, ParseStrictness::Lenient)?.into_nameless() {
+                (Some(name), spec) => Ok((name, spec)),
+                (None, _) => Err(ParseMatchSpecError::MissingPackageName),
+            },
+        )
+        .collect()
+}
+
 impl AsRef<PackageRecord> for CondaPackage {
     fn as_ref(&self) -> &PackageRecord {
         self.package_record()
@@ -639,12 +896,16 @@ pub struct PypiPackageDataRef<'p> {
 
 #[cfg(test)]
 mod test {
-    use std::path::Path;
+    use std::{collections::BTreeMap, path::Path, str::FromStr};
 
-    use rattler_conda_types::Platform;
+    use chrono::{TimeZone, Utc};
+    use rattler_conda_types::{ExplicitEnvironmentSpec, ParseStrictness, Platform, VersionSpec};
     use rstest::*;
 
-    use super::{LockFile, DEFAULT_ENVIRONMENT_NAME};
+    use super::{
+        Channel, CondaLockDependency, CondaLockSpecification, CondaPackageData, GitMetadata,
+        LockFile, TimeMetadata, DEFAULT_ENVIRONMENT_NAME,
+    };
 
     #[rstest]
     #[case("v0/numpy-conda-lock.yml")]
@@ -665,6 +926,274 @@ mod test {
         insta::assert_yaml_snapshot!(file_name, conda_lock);
     }
 
+    #[rstest]
+    #[case("v0/numpy-conda-lock.yml")]
+    #[case("v4/pypi-matplotlib-lock.yml")]
+    #[case("v5/flat-index-lock.yml")]
+    fn test_render_round_trip(#[case] file_name: &str) {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("../../test-data/conda-lock")
+            .join(file_name);
+        let rendered = LockFile::from_path(&path)
+            .unwrap()
+            .render_to_string()
+            .unwrap();
+
+        // Parsing and rendering the output again must not change it.
+        let reparsed = LockFile::from_str(&rendered).unwrap();
+        similar_asserts::assert_eq!(rendered, reparsed.render_to_string().unwrap());
+    }
+
+    #[test]
+    fn test_explicit_environment_spec() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("../../test-data/conda-lock/v0/numpy-conda-lock.yml");
+        let environment = LockFile::from_path(&path)
+            .unwrap()
+            .default_environment()
+            .unwrap();
+        let spec = environment
+            .explicit_environment_spec(Platform::Linux64)
+            .unwrap();
+        assert_eq!(spec.platform, Some(Platform::Linux64));
+        assert_eq!(
+            spec.packages.len(),
+            environment.packages(Platform::Linux64).unwrap().len()
+        );
+        assert!(spec
+            .packages
+            .iter()
+            .all(|entry| entry.package_archive_hash().unwrap().is_some()));
+
+        // Dependencies are installed first.
+        let position = |name: &str| {
+            spec.packages
+                .iter()
+                .position(|entry| entry.url.path().contains(&format!("/{name}-")))
+                .unwrap()
+        };
+        assert!(position("python") < position("numpy"));
+
+        // The spec can be parsed again.
+        let parsed = ExplicitEnvironmentSpec::from_str(&spec.to_spec_string()).unwrap();
+        assert_eq!(parsed.packages.len(), spec.packages.len());
+    }
+
+    #[test]
+    fn test_conda_package_dependencies() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("../../test-data/conda-lock/v4/numpy-lock.yml");
+        let lock_file = LockFile::from_path(&path).unwrap();
+        let openmp_mutex = lock_file
+            .default_environment()
+            .unwrap()
+            .conda_packages(Platform::Linux64)
+            .unwrap()
+            .find(|p| p.package_record().name.as_normalized() == "_openmp_mutex")
+            .unwrap();
+
+        let dependencies = openmp_mutex.dependencies().unwrap();
+        let names = dependencies
+            .iter()
+            .map(|(name, _)| name.as_normalized())
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["_libgcc_mutex", "libgomp"]);
+        assert_eq!(
+            dependencies[1].1.version,
+            Some(VersionSpec::from_str(">=7.5.0", ParseStrictness::Strict).unwrap())
+        );
+        assert!(openmp_mutex.constraints().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_solved_environment() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("../../test-data/conda-lock/v4/numpy-lock.yml");
+        let environment = LockFile::from_path(&path)
+            .unwrap()
+            .default_environment()
+            .unwrap();
+        let solved = environment.conda_repodata_records().unwrap();
+        let specification = CondaLockSpecification {
+            channels: environment.channels().to_vec(),
+            dependencies: solved
+                .keys()
+                .map(|&platform| (platform, vec![CondaLockDependency::conda("numpy", "")]))
+                .collect(),
+        };
+
+        let lock_file = LockFile::builder()
+            .with_solved_environment(DEFAULT_ENVIRONMENT_NAME, &specification, solved.clone())
+            .finish();
+        let locked = lock_file.default_environment().unwrap();
+        assert_eq!(locked.channels(), environment.channels());
+        assert_eq!(locked.conda_repodata_records().unwrap(), solved);
+        for &platform in solved.keys() {
+            assert_eq!(
+                locked.content_hash(platform),
+                Some(&specification.content_hash_for_platform(platform))
+            );
+        }
+
+        // The content hashes survive a round trip through the file format.
+        let reparsed = LockFile::from_str(&lock_file.render_to_string().unwrap()).unwrap();
+        let reparsed = reparsed.default_environment().unwrap();
+        for &platform in solved.keys() {
+            assert_eq!(
+                reparsed.content_hash(platform),
+                locked.content_hash(platform)
+            );
+        }
+    }
+
+    #[test]
+    fn test_conda_package_extra() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("../../test-data/conda-lock/v4/numpy-lock.yml");
+        let record = LockFile::from_path(&path)
+            .unwrap()
+            .default_environment()
+            .unwrap()
+            .conda_repodata_records_for_platform(Platform::Linux64)
+            .unwrap()
+            .unwrap()
+            .remove(0);
+
+        let mut package = CondaPackageData::from(record);
+        package.extra.insert(
+            "attestation".to_string(),
+            serde_yaml::Value::from("https://example.com/attestation.json"),
+        );
+        let rendered = LockFile::builder()
+            .with_conda_package(DEFAULT_ENVIRONMENT_NAME, Platform::Linux64, package)
+            .finish()
+            .render_to_string()
+            .unwrap();
+        assert!(rendered.contains("attestation: https://example.com/attestation.json"));
+
+        let lock_file = LockFile::from_str(&rendered).unwrap();
+        let package = lock_file
+            .default_environment()
+            .unwrap()
+            .conda_packages(Platform::Linux64)
+            .unwrap()
+            .next()
+            .unwrap();
+        assert_eq!(
+            package.extra().get("attestation").and_then(|v| v.as_str()),
+            Some("https://example.com/attestation.json")
+        );
+    }
+
+    #[test]
+    fn test_normalize() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("../../test-data/conda-lock/v4/numpy-lock.yml");
+        let environment = LockFile::from_path(&path)
+            .unwrap()
+            .default_environment()
+            .unwrap();
+        let mut records = environment
+            .conda_repodata_records_for_platform(Platform::Linux64)
+            .unwrap()
+            .unwrap();
+
+        let specification = CondaLockSpecification {
+            channels: vec![Channel::from("https://conda.anaconda.org/conda-forge/")],
+            dependencies: BTreeMap::new(),
+        };
+        let lock_file = |channel: &str, records: Vec<_>| {
+            LockFile::builder()
+                .with_solved_environment(
+                    DEFAULT_ENVIRONMENT_NAME,
+                    &specification,
+                    [(Platform::Linux64, records)],
+                )
+                .with_channels(DEFAULT_ENVIRONMENT_NAME, [channel])
+                .finish()
+                .normalize()
+        };
+        let expected = lock_file("https://conda.anaconda.org/conda-forge/", records.clone());
+
+        // Shuffle and duplicate the packages and use a different url for the
+        // channel.
+        records.reverse();
+        records.push(records[0].clone());
+        let normalized = lock_file("https://Conda.Anaconda.org/conda-forge", records);
+
+        similar_asserts::assert_eq!(
+            normalized.render_to_string().unwrap(),
+            expected.render_to_string().unwrap()
+        );
+        let urls = |normalized: &LockFile| {
+            normalized
+                .default_environment()
+                .unwrap()
+                .packages(Platform::Linux64)
+                .unwrap()
+                .map(|p| p.url_or_path().into_owned())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(urls(&normalized), urls(&expected));
+    }
+
+    #[test]
+    fn test_with_updated_conda_packages() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("../../test-data/conda-lock/v0/numpy-conda-lock.yml");
+        let lock_file = LockFile::from_path(&path).unwrap();
+        let environment = lock_file.default_environment().unwrap();
+        let mut records = environment
+            .conda_repodata_records_for_platform(Platform::Linux64)
+            .unwrap()
+            .unwrap();
+
+        // Update numpy and change the metadata of all other packages, which
+        // should be ignored.
+        let numpy = records
+            .iter_mut()
+            .find(|record| record.package_record.name.as_normalized() == "numpy")
+            .unwrap();
+        numpy.url = numpy
+            .url
+            .as_str()
+            .replace(&numpy.file_name, "numpy-99.0.0-py_0.conda")
+            .parse()
+            .unwrap();
+        numpy.file_name = "numpy-99.0.0-py_0.conda".to_string();
+        for record in &mut records {
+            record.package_record.license = Some("changed".to_string());
+        }
+
+        let updated = lock_file.with_updated_conda_packages(
+            DEFAULT_ENVIRONMENT_NAME,
+            Platform::Linux64,
+            records,
+        );
+        let updated_environment = updated.default_environment().unwrap();
+        let licenses = updated_environment
+            .conda_packages(Platform::Linux64)
+            .unwrap()
+            .filter(|package| package.package_record().license.as_deref() == Some("changed"))
+            .map(|package| package.file_name().unwrap().to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(licenses, ["numpy-99.0.0-py_0.conda"]);
+
+        // Other platforms are not modified.
+        assert_eq!(
+            updated_environment
+                .packages(Platform::Osx64)
+                .unwrap()
+                .map(|p| p.url_or_path().into_owned())
+                .collect::<Vec<_>>(),
+            environment
+                .packages(Platform::Osx64)
+                .unwrap()
+                .map(|p| p.url_or_path().into_owned())
+                .collect::<Vec<_>>()
+        );
+    }
+
     /// Absolute paths on Windows are not properly parsed.
     /// See: <https://github.com/conda/rattler/issues/615>
     #[test]
@@ -699,5 +1228,62 @@ mod test {
             .unwrap()
             .map(|p| p.url_or_path().into_owned())
             .collect::<Vec<_>>());
+
+        // All packages in the file are conda packages.
+        let environment = conda_lock.default_environment().unwrap();
+        assert_eq!(
+            environment
+                .conda_packages(Platform::Linux64)
+                .unwrap()
+                .count(),
+            environment.packages(Platform::Linux64).unwrap().len()
+        );
+        assert!(environment.conda_packages(Platform::Win64).is_none());
+    }
+
+    #[test]
+    fn test_provenance_metadata() {
+        let git_metadata = GitMetadata {
+            git_user_name: Some("Jane Doe".to_string()),
+            git_user_email: Some("jane@example.com".to_string()),
+            git_sha: Some("0123456789abcdef0123456789abcdef01234567".to_string()),
+        };
+        let time_metadata = TimeMetadata {
+            created_at: Utc.with_ymd_and_hms(2023, 4, 5, 6, 7, 8).unwrap(),
+        };
+        let lock_file = LockFile::builder()
+            .with_channels(DEFAULT_ENVIRONMENT_NAME, ["conda-forge"])
+            .with_git_metadata(git_metadata.clone())
+            .with_time_metadata(time_metadata.clone())
+            .finish();
+
+        let rendered = lock_file.render_to_string().unwrap();
+        assert!(rendered.contains("created_at: 2023-04-05T06:07:08Z"));
+        let reparsed = LockFile::from_str(&rendered).unwrap();
+        assert_eq!(reparsed.git_metadata(), Some(&git_metadata));
+        assert_eq!(reparsed.time_metadata(), Some(&time_metadata));
+        assert_eq!(reparsed.normalize().git_metadata(), Some(&git_metadata));
+
+        // The metadata of conda-lock files is read as well.
+        let conda_lock = LockFile::from_str(
+            r#"version: 1
+metadata:
+  channels:
+  - url: conda-forge
+    used_env_vars: []
+  platforms:
+  - linux-64
+  git_metadata:
+    git_user_name: Jane Doe
+    git_user_email: jane@example.com
+    git_sha: 0123456789abcdef0123456789abcdef01234567
+  time_metadata:
+    created_at: '2023-04-05T06:07:08'
+package: []
+"#,
+        )
+        .unwrap();
+        assert_eq!(conda_lock.git_metadata(), Some(&git_metadata));
+        assert_eq!(conda_lock.time_metadata(), Some(&time_metadata));
     }
 }
@@ -0,0 +1,170 @@
+//! Provenance metadata of a lock-file, compatible with the `git_metadata` and
+//! `time_metadata` sections of conda-lock files.
+
+use std::{path::Path, process::Command};
+
+use chrono::{DateTime, NaiveDateTime, SecondsFormat, Timelike, Utc};
+use serde::{de::Error, Deserialize, Deserializer, Serialize, Serializer};
+use serde_with::skip_serializing_none;
+
+/// Information about the git repository from which a lock-file was created.
+#[skip_serializing_none]
+#[derive(Serialize, Deserialize, Clone, Debug, Default, Eq, PartialEq)]
+pub struct GitMetadata {
+    /// The name of the git user (`user.name`).
+    pub git_user_name: Option<String>,
+
+    /// The email address of the git user (`user.email`).
+    pub git_user_email: Option<String>,
+
+    /// The sha of the commit that was checked out (`HEAD`).
+    pub git_sha: Option<String>,
+}
+
+/// An error that can occur when reading the [`GitMetadata`] of a repository.
+#[derive(Debug, thiserror::Error)]
+pub enum GitMetadataError {
+    /// The `git` executable could not be run.
+    #[error("failed to run git")]
+    Io(#[from] std::io::Error),
+
+    /// The directory is not part of a git repository or it has no commits.
+    #[error("'{0}' is not a git repository with a checked out commit")]
+    NotARepository(String),
+}
+
+impl GitMetadata {
+    /// Reads the metadata from the git repository that contains `path` by
+    /// invoking the `git` executable. The user name and email are `None` if
+    /// they are not configured.
+    pub fn from_repository(path: &Path) -> Result<Self, GitMetadataError> {
+        let git_sha = git(path, &["rev-parse", "HEAD"])?
+            .ok_or_else(|| GitMetadataError::NotARepository(path.display().to_string()))?;
+        Ok(Self {
+            git_user_name: git(path, &["config", "user.name"])?,
+            git_user_email: git(path, &["config", "user.email"])?,
+            git_sha: Some(git_sha),
+        })
+    }
+}
+
+/// Runs git in `path` and returns the trimmed output, or `None` if the
+/// command failed or printed nothing.
+fn git(path: &Path, args: &[&str]) -> Result<Option<String>, std::io::Error> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(path)
+        .args(args)
+        .output()?;
+    let stdout = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    Ok((output.status.success() && !stdout.is_empty()).then_some(stdout))
+}
+
+/// Information about when a lock-file was created.
+#[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq)]
+pub struct TimeMetadata {
+    /// The time at which the lock-file was created, in UTC.
+    #[serde(
+        serialize_with = "serialize_created_at",
+        deserialize_with = "deserialize_created_at"
+    )]
+    pub created_at: DateTime<Utc>,
+}
+
+impl TimeMetadata {
+    /// Returns the metadata for a lock-file that is created now. The time is
+    /// truncated to whole seconds, the precision with which it is stored.
+    pub fn now() -> Self {
+        let now = Utc::now();
+        Self {
+            created_at: now.with_nanosecond(0).unwrap_or(now),
+        }
+    }
+}
+
+fn serialize_created_at<S: Serializer>(
+    created_at: &DateTime<Utc>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    created_at
+        .to_rfc3339_opts(SecondsFormat::AutoSi, true)
+        .serialize(serializer)
+}
+
+/// Conda-lock writes the time without a timezone, it is always UTC.
+fn deserialize_created_at<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<DateTime<Utc>, D::Error> {
+    let value = String::deserialize(deserializer)?;
+    DateTime::parse_from_rfc3339(&value)
+        .map(|created_at| created_at.with_timezone(&Utc))
+        .or_else(|_| {
+            NaiveDateTime::parse_from_str(&value, "%Y-%m-%dT%H:%M:%S%.f")
+                .map(|created_at| created_at.and_utc())
+        })
+        .map_err(|_| D::Error::custom(format!("invalid timestamp '{value}'")))
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::{TimeZone, Utc};
+
+    use super::{GitMetadata, TimeMetadata};
+
+    #[test]
+    fn test_time_metadata() {
+        let metadata: TimeMetadata =
+            serde_yaml::from_str("created_at: 2023-04-05T06:07:08.123456").unwrap();
+        assert_eq!(
+            metadata.created_at.timestamp_micros(),
+            Utc.with_ymd_and_hms(2023, 4, 5, 6, 7, 8)
+                .unwrap()
+                .timestamp_micros()
+                + 123_456
+        );
+
+        let metadata = TimeMetadata {
+            created_at: Utc.with_ymd_and_hms(2023, 4, 5, 6, 7, 8).unwrap(),
+        };
+        let rendered = serde_yaml::to_string(&metadata).unwrap();
+        assert_eq!(rendered, "created_at: 2023-04-05T06:07:08Z\n");
+        assert_eq!(
+            serde_yaml::from_str::<TimeMetadata>(&rendered).unwrap(),
+            metadata
+        );
+
+        assert_eq!(TimeMetadata::now().created_at.timestamp_subsec_nanos(), 0);
+    }
+
+    #[test]
+    fn test_git_metadata_from_repository() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let git = |args: &[&str]| {
+            let status = std::process::Command::new("git")
+                .arg("-C")
+                .arg(dir.path())
+                .args(args)
+                .status()
+                .unwrap();
+            assert!(status.success());
+        };
+        git(&["init", "--quiet"]);
+        git(&["config", "user.name", "Jane Doe"]);
+        git(&["config", "user.email", "jane@example.com"]);
+        git(&[
+            "-c",
+            "commit.gpgsign=false",
+            "commit",
+            "--quiet",
+            "--allow-empty",
+            "-m",
+            "initial",
+        ]);
+
+        let metadata = GitMetadata::from_repository(dir.path()).unwrap();
+        assert_eq!(metadata.git_user_name.as_deref(), Some("Jane Doe"));
+        assert_eq!(metadata.git_user_email.as_deref(), Some("jane@example.com"));
+        assert_eq!(metadata.git_sha.unwrap().len(), 40);
+    }
+}
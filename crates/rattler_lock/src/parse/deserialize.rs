@@ -1,14 +1,15 @@
 use crate::file_format_version::FileFormatVersion;
 use crate::utils::serde::RawCondaPackageData;
 use crate::{
-    Channel, CondaPackageData, EnvironmentData, EnvironmentPackageData, LockFile, LockFileInner,
-    ParseCondaLockError, PypiIndexes, PypiPackageData, PypiPackageEnvironmentData, UrlOrPath,
+    Channel, CondaPackageData, EnvironmentData, EnvironmentPackageData, GitMetadata, LockFile,
+    LockFileInner, ParseCondaLockError, PypiIndexes, PypiPackageData, PypiPackageEnvironmentData,
+    TimeMetadata, UrlOrPath,
 };
 use fxhash::FxHashMap;
 use indexmap::IndexSet;
-use itertools::{Either, Itertools};
 use pep508_rs::ExtraName;
 use rattler_conda_types::Platform;
+use rattler_digest::{serde::SerializableHash, Sha256};
 use serde::Deserialize;
 use serde_yaml::Value;
 use std::collections::{BTreeMap, BTreeSet};
@@ -16,9 +17,17 @@ use std::sync::Arc;
 use url::Url;
 
 #[derive(Deserialize)]
-struct DeserializableLockFile<'d> {
+struct DeserializableLockFile {
     environments: BTreeMap<String, DeserializableEnvironment>,
-    packages: Vec<DeserializablePackageData<'d>>,
+    // The packages are deserialized one by one to be able to tell which
+    // package is invalid.
+    packages: Vec<Value>,
+    #[serde(default)]
+    git_metadata: Option<GitMetadata>,
+    #[serde(default)]
+    time_metadata: Option<TimeMetadata>,
+    #[serde(flatten)]
+    extra: BTreeMap<String, Value>,
 }
 
 #[derive(Deserialize)]
@@ -26,13 +35,17 @@ struct DeserializableEnvironment {
     channels: Vec<Channel>,
     #[serde(flatten)]
     indexes: Option<PypiIndexes>,
+    #[serde(default)]
+    content_hash: BTreeMap<Platform, SerializableHash<Sha256>>,
     packages: BTreeMap<Platform, Vec<DeserializablePackageSelector>>,
+    #[serde(flatten)]
+    extra: BTreeMap<String, Value>,
 }
 
 #[derive(Deserialize)]
 #[serde(tag = "kind", rename_all = "snake_case")]
-enum DeserializablePackageData<'d> {
-    Conda(Box<RawCondaPackageData<'d>>),
+enum DeserializablePackageData {
+    Conda(Box<RawCondaPackageData<'static>>),
     Pypi(Box<PypiPackageData>),
 }
 
@@ -63,20 +76,50 @@ impl From<DeserializablePypiPackageEnvironmentData> for PypiPackageEnvironmentDa
     }
 }
 
-/// Parses a [`LockFile`] from a [`serde_yaml::Value`].
+/// Parses a [`LockFile`] from a [`serde_yaml::Value`] that was parsed from
+/// `source`.
 pub fn parse_from_document(
     document: Value,
+    source: &str,
     version: FileFormatVersion,
 ) -> Result<LockFile, ParseCondaLockError> {
-    let raw: DeserializableLockFile<'_> =
-        serde_yaml::from_value(document).map_err(ParseCondaLockError::ParseError)?;
+    let mut raw: DeserializableLockFile = match serde_yaml::from_value(document) {
+        Ok(raw) => raw,
+        Err(err) => {
+            // A value does not know where it was parsed from, parse the source
+            // again to report where the error is located.
+            let err = serde_yaml::from_str::<DeserializableLockFile>(source)
+                .err()
+                .unwrap_or(err);
+            return Err(ParseCondaLockError::ParseError(err));
+        }
+    };
+
+    // The version has already been parsed from the document.
+    raw.extra.remove("version");
 
     // Split the packages into conda and pypi packages.
-    let (conda_packages, pypi_packages): (Vec<_>, Vec<_>) =
-        raw.packages.into_iter().partition_map(|p| match p {
-            DeserializablePackageData::Conda(p) => Either::Left(CondaPackageData::from(*p)),
-            DeserializablePackageData::Pypi(p) => Either::Right(*p),
-        });
+    let mut conda_packages = Vec::new();
+    let mut pypi_packages = Vec::new();
+    for (index, package) in raw.packages.into_iter().enumerate() {
+        let name = package
+            .get("name")
+            .and_then(Value::as_str)
+            .map(ToOwned::to_owned);
+        match serde_yaml::from_value(package) {
+            Ok(DeserializablePackageData::Conda(p)) => {
+                conda_packages.push(CondaPackageData::from(*p));
+            }
+            Ok(DeserializablePackageData::Pypi(p)) => pypi_packages.push(*p),
+            Err(source) => {
+                return Err(ParseCondaLockError::InvalidPackage {
+                    index,
+                    name,
+                    source,
+                })
+            }
+        }
+    }
 
     // Determine the indices of the packages by url
     let conda_url_lookup = conda_packages
@@ -100,6 +143,12 @@ pub fn parse_from_document(
                 EnvironmentData {
                     channels: env.channels,
                     indexes: env.indexes,
+                    content_hash: env
+                        .content_hash
+                        .into_iter()
+                        .map(|(platform, hash)| (platform, hash.into()))
+                        .collect(),
+                    extra: env.extra,
                     packages: env
                         .packages
                         .into_iter()
@@ -160,6 +209,9 @@ pub fn parse_from_document(
                 .into_iter()
                 .map(Into::into)
                 .collect(),
+            git_metadata: raw.git_metadata,
+            time_metadata: raw.time_metadata,
+            extra: raw.extra,
         }),
     })
 }
@@ -5,6 +5,7 @@ mod v3;
 use super::{LockFile, UrlOrPath};
 use crate::file_format_version::FileFormatVersion;
 use rattler_conda_types::Platform;
+use rattler_error::ErrorCode;
 use serde::de::Error;
 use serde_yaml::Value;
 use std::str::FromStr;
@@ -30,6 +31,105 @@ pub enum ParseCondaLockError {
 
     #[error(transparent)]
     InvalidPypiPackageName(#[from] pep508_rs::InvalidNameError),
+
+    #[error(
+        "failed to parse package #{index}{}",
+        .name.as_ref().map_or_else(String::new, |name| format!(" ({name})"))
+    )]
+    InvalidPackage {
+        index: usize,
+        name: Option<String>,
+        #[source]
+        source: serde_yaml::Error,
+    },
+}
+
+impl ErrorCode for ParseCondaLockError {
+    fn code(&self) -> &'static str {
+        match self {
+            ParseCondaLockError::IoError(_) => "rattler::lock::io",
+            ParseCondaLockError::ParseError(_) => "rattler::lock::parse",
+            ParseCondaLockError::IncompatibleVersion { .. } => {
+                "rattler::lock::incompatible_version"
+            }
+            ParseCondaLockError::MissingPackage(..) => "rattler::lock::missing_package",
+            ParseCondaLockError::InvalidPypiPackageName(_) => {
+                "rattler::lock::invalid_pypi_package_name"
+            }
+            ParseCondaLockError::InvalidPackage { .. } => "rattler::lock::invalid_package",
+        }
+    }
+}
+
+#[cfg(feature = "miette")]
+impl ParseCondaLockError {
+    /// Converts this error into a [`miette::Diagnostic`] that points to the
+    /// location in the lock file that caused the error. `name` identifies the
+    /// lock file (e.g. its path) and `source` is the content that was parsed.
+    pub fn into_diagnostic(
+        self,
+        name: impl AsRef<str>,
+        source: &str,
+    ) -> rattler_error::SourceDiagnostic<Self> {
+        let label = match &self {
+            ParseCondaLockError::ParseError(err) => err.location().and_then(|location| {
+                // Label the entire line, the column is reported in characters
+                // which does not necessarily correspond to a byte offset.
+                let start = source
+                    .split_inclusive('\n')
+                    .take(location.line().saturating_sub(1))
+                    .map(str::len)
+                    .sum::<usize>();
+                let line = source.get(start..)?.lines().next().unwrap_or_default();
+                Some((start..start + line.len(), "invalid lock file"))
+            }),
+            ParseCondaLockError::IncompatibleVersion { .. } => source
+                .find("version:")
+                .map(|start| (start..start + "version:".len(), "unsupported version")),
+            ParseCondaLockError::MissingPackage(_, _, url) => {
+                let url = url.to_string();
+                source
+                    .find(&url)
+                    .map(|start| (start..start + url.len(), "package not found"))
+            }
+            ParseCondaLockError::InvalidPackage { index, .. } => {
+                package_entry_span(source, *index).map(|span| (span, "invalid package"))
+            }
+            _ => None,
+        };
+
+        let diagnostic = rattler_error::SourceDiagnostic::new(self, name, source);
+        match label {
+            Some((span, label)) => diagnostic.with_label(span, label),
+            None => diagnostic,
+        }
+    }
+}
+
+/// Returns the span of the first line of the `index`-th entry in the top-level
+/// `packages` sequence of a lock file.
+#[cfg(feature = "miette")]
+fn package_entry_span(source: &str, index: usize) -> Option<std::ops::Range<usize>> {
+    let mut offset = 0;
+    let mut lines = source.split_inclusive('\n').map(|line| {
+        let start = offset;
+        offset += line.len();
+        (start, line.trim_end())
+    });
+    lines.find(|(_, line)| line.starts_with("packages:"))?;
+
+    // Entries are the lines that start with a dash at the indentation of the
+    // first entry.
+    let mut indentation = None;
+    lines
+        .filter(|(_, line)| {
+            let trimmed = line.trim_start();
+            let line_indentation = line.len() - trimmed.len();
+            trimmed.starts_with('-')
+                && *indentation.get_or_insert(line_indentation) == line_indentation
+        })
+        .nth(index)
+        .map(|(start, line)| start..start + line.len())
 }
 
 impl FromStr for LockFile {
@@ -60,7 +160,7 @@ impl FromStr for LockFile {
         if version <= FileFormatVersion::V3 {
             parse_v3_or_lower(document, version)
         } else {
-            deserialize::parse_from_document(document, version)
+            deserialize::parse_from_document(document, s, version)
         }
     }
 }
@@ -80,6 +180,193 @@ mod test {
         .unwrap();
 
         insta::assert_snapshot!(format!("{}", err), @"found newer lockfile format version 1000, but only up to including version 5 is supported");
+        assert_eq!(err.code(), "rattler::lock::incompatible_version");
+    }
+
+    #[cfg(feature = "miette")]
+    #[test]
+    fn test_parse_error_diagnostic() {
+        use miette::Diagnostic;
+
+        let source = "version: 5\nenvironments: [\n";
+        let err = LockFile::from_str(source).unwrap_err();
+        let diagnostic = err.into_diagnostic("conda-lock.yml", source);
+        assert_eq!(
+            diagnostic.code().unwrap().to_string(),
+            "rattler::lock::parse"
+        );
+        assert!(diagnostic.labels().is_some());
+    }
+
+    const INVALID_PACKAGE_LOCK_FILE: &str = r#"version: 5
+environments: {}
+packages:
+- kind: conda
+  name: foo
+  version: '1.0'
+  url: https://conda.anaconda.org/conda-forge/linux-64/foo-1.0-h123_0.conda
+- kind: conda
+  name: bar
+  version: '1.0'
+"#;
+
+    #[test]
+    fn test_invalid_package_error() {
+        let err = LockFile::from_str(INVALID_PACKAGE_LOCK_FILE).unwrap_err();
+        assert_eq!(err.to_string(), "failed to parse package #1 (bar)");
+        assert_eq!(err.code(), "rattler::lock::invalid_package");
+        let ParseCondaLockError::InvalidPackage { source, .. } = err else {
+            panic!("unexpected error: {err:?}");
+        };
+        assert!(source.to_string().contains("missing field `url`"));
+    }
+
+    #[cfg(feature = "miette")]
+    #[test]
+    fn test_package_entry_span() {
+        let span = package_entry_span(INVALID_PACKAGE_LOCK_FILE, 1).unwrap();
+        assert_eq!(&INVALID_PACKAGE_LOCK_FILE[span.clone()], "- kind: conda");
+        assert!(INVALID_PACKAGE_LOCK_FILE[..span.start].contains("name: foo"));
+        assert_eq!(package_entry_span(INVALID_PACKAGE_LOCK_FILE, 2), None);
+    }
+
+    #[test]
+    fn test_conda_lock_categories() {
+        let package = |name: &str, category: &str| {
+            format!(
+                r#"
+  - name: {name}
+    version: "1.0"
+    manager: conda
+    platform: linux-64
+    dependencies: {{}}
+    url: https://conda.anaconda.org/conda-forge/linux-64/{name}-1.0-0.tar.bz2
+    hash:
+      md5: 00000000000000000000000000000000
+    category: {category}
+    optional: false"#
+            )
+        };
+        let source = format!(
+            r#"version: 1
+metadata:
+  channels:
+    - url: conda-forge
+      used_env_vars: []
+  platforms:
+    - linux-64
+package:{}{}{}"#,
+            package("python", "main"),
+            package("pytest", "dev"),
+            package("sphinx", "docs")
+        );
+        let lock_file = LockFile::from_str(&source).unwrap();
+
+        let package_names = |environment: &str| {
+            let mut names = lock_file
+                .environment(environment)
+                .unwrap()
+                .packages(Platform::Linux64)
+                .unwrap()
+                .map(|package| package.name().into_owned())
+                .collect::<Vec<_>>();
+            names.sort();
+            names
+        };
+        assert_eq!(
+            package_names(crate::DEFAULT_ENVIRONMENT_NAME),
+            ["pytest", "python", "sphinx"]
+        );
+        assert_eq!(package_names("main"), ["python"]);
+        assert_eq!(package_names("dev"), ["pytest", "python"]);
+        assert_eq!(package_names("docs"), ["python", "sphinx"]);
+    }
+
+    #[test]
+    fn test_unknown_fields_round_trip() {
+        let source = r#"version: 5
+environments:
+  default:
+    channels:
+    - url: https://conda.anaconda.org/conda-forge/
+    packages:
+      linux-64:
+      - conda: https://conda.anaconda.org/conda-forge/linux-64/foo-1.0-h123_0.conda
+    solver: resolvo
+packages:
+- kind: conda
+  name: foo
+  version: '1.0'
+  build: h123_0
+  subdir: linux-64
+  url: https://conda.anaconda.org/conda-forge/linux-64/foo-1.0-h123_0.conda
+  requested-by:
+  - bar
+metadata:
+  generator: some-tool
+"#;
+        let lock_file = LockFile::from_str(source).unwrap();
+        let rendered = lock_file.render_to_string().unwrap();
+        for field in ["solver: resolvo", "requested-by:", "generator: some-tool"] {
+            assert!(rendered.contains(field), "{field} is missing:\n{rendered}");
+        }
+        assert_eq!(
+            LockFile::from_str(&rendered)
+                .unwrap()
+                .render_to_string()
+                .unwrap(),
+            rendered
+        );
+    }
+
+    #[test]
+    fn test_run_exports_and_purls_round_trip() {
+        let source = r#"version: 5
+environments:
+  default:
+    channels:
+    - url: https://conda.anaconda.org/conda-forge/
+    packages:
+      linux-64:
+      - conda: https://conda.anaconda.org/conda-forge/linux-64/foo-1.0-h123_0.conda
+packages:
+- kind: conda
+  name: foo
+  version: '1.0'
+  build: h123_0
+  subdir: linux-64
+  url: https://conda.anaconda.org/conda-forge/linux-64/foo-1.0-h123_0.conda
+  purls:
+  - pkg:pypi/foo
+  run_exports:
+    weak:
+    - foo >=1.0,<2
+"#;
+        let lock_file = LockFile::from_str(source).unwrap();
+        let package = lock_file
+            .default_environment()
+            .unwrap()
+            .conda_packages(Platform::Linux64)
+            .unwrap()
+            .next()
+            .unwrap();
+        let record = package.package_record();
+        assert_eq!(record.purls.as_ref().map(|purls| purls.len()), Some(1));
+        assert_eq!(record.run_exports.as_ref().unwrap().weak, ["foo >=1.0,<2"]);
+
+        let rendered = lock_file.render_to_string().unwrap();
+        assert_eq!(
+            LockFile::from_str(&rendered)
+                .unwrap()
+                .default_environment()
+                .unwrap()
+                .conda_packages(Platform::Linux64)
+                .unwrap()
+                .next()
+                .unwrap()
+                .package_record(),
+            record
+        );
     }
 
     // This test verifies the deterministic ordering of lock files. It does so by comparing the serialized
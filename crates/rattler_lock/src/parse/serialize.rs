@@ -7,13 +7,14 @@ use std::{
 use itertools::Itertools;
 use pep508_rs::ExtraName;
 use rattler_conda_types::Platform;
+use rattler_digest::{serde::SerializableHash, Sha256};
 use serde::{Serialize, Serializer};
 use url::Url;
 
 use crate::{
     file_format_version::FileFormatVersion, utils::serde::RawCondaPackageData, Channel,
-    CondaPackage, EnvironmentPackageData, LockFile, Package, PypiIndexes, PypiPackage,
-    PypiPackageData, UrlOrPath,
+    CondaPackage, EnvironmentPackageData, GitMetadata, LockFile, Package, PypiIndexes, PypiPackage,
+    PypiPackageData, TimeMetadata, UrlOrPath,
 };
 
 #[derive(Serialize)]
@@ -21,6 +22,12 @@ struct SerializableLockFile<'a> {
     version: FileFormatVersion,
     environments: BTreeMap<&'a String, SerializableEnvironment<'a>>,
     packages: Vec<SerializablePackageData<'a>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    git_metadata: Option<&'a GitMetadata>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    time_metadata: Option<&'a TimeMetadata>,
+    #[serde(flatten)]
+    extra: &'a BTreeMap<String, serde_yaml::Value>,
 }
 
 #[derive(Serialize)]
@@ -28,7 +35,11 @@ struct SerializableEnvironment<'a> {
     channels: &'a [Channel],
     #[serde(flatten)]
     indexes: Option<&'a PypiIndexes>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    content_hash: BTreeMap<Platform, SerializableHash<Sha256>>,
     packages: BTreeMap<Platform, Vec<SerializablePackageSelector<'a>>>,
+    #[serde(flatten)]
+    extra: &'a BTreeMap<String, serde_yaml::Value>,
 }
 
 #[allow(clippy::large_enum_variant)]
@@ -227,6 +238,11 @@ impl Serialize for LockFile {
                     SerializableEnvironment {
                         channels: &env_data.channels,
                         indexes: env_data.indexes.as_ref(),
+                        content_hash: env_data
+                            .content_hash
+                            .iter()
+                            .map(|(platform, hash)| (*platform, SerializableHash::from(*hash)))
+                            .collect(),
                         packages: env_data
                             .packages
                             .iter()
@@ -260,6 +276,7 @@ impl Serialize for LockFile {
                                 )
                             })
                             .collect(),
+                        extra: &env_data.extra,
                     },
                 )
             })
@@ -286,6 +303,9 @@ impl Serialize for LockFile {
             version: FileFormatVersion::LATEST,
             environments,
             packages,
+            git_metadata: inner.git_metadata.as_ref(),
+            time_metadata: inner.time_metadata.as_ref(),
+            extra: &inner.extra,
         };
 
         raw.serialize(serializer)
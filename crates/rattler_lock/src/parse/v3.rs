@@ -3,9 +3,9 @@
 use super::ParseCondaLockError;
 use crate::file_format_version::FileFormatVersion;
 use crate::{
-    Channel, CondaPackageData, EnvironmentData, EnvironmentPackageData, LockFile, LockFileInner,
-    PackageHashes, PypiPackageData, PypiPackageEnvironmentData, UrlOrPath,
-    DEFAULT_ENVIRONMENT_NAME,
+    Channel, CondaPackageData, EnvironmentData, EnvironmentPackageData, GitMetadata, LockFile,
+    LockFileInner, PackageHashes, PypiPackageData, PypiPackageEnvironmentData, TimeMetadata,
+    UrlOrPath, DEFAULT_ENVIRONMENT_NAME,
 };
 use fxhash::FxHashMap;
 use indexmap::IndexSet;
@@ -14,10 +14,14 @@ use pep508_rs::{ExtraName, Requirement};
 use rattler_conda_types::{
     NoArchType, PackageName, PackageRecord, PackageUrl, Platform, VersionWithSource,
 };
+use rattler_digest::{serde::SerializableHash, Sha256, Sha256Hash};
 use serde::Deserialize;
 use serde_with::{serde_as, skip_serializing_none, OneOrMany};
 use std::ops::Not;
-use std::{collections::BTreeSet, sync::Arc};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    sync::Arc,
+};
 use url::Url;
 
 #[derive(Deserialize)]
@@ -34,15 +38,34 @@ struct LockMetaV3 {
     /// The platforms this lock file supports
     #[serde_as(as = "crate::utils::serde::Ordered<_>")]
     pub platforms: Vec<Platform>,
+    /// The git repository from which the lock file was created
+    #[serde(default)]
+    pub git_metadata: Option<GitMetadata>,
+    /// When the lock file was created
+    #[serde(default)]
+    pub time_metadata: Option<TimeMetadata>,
+    /// The hash of the input from which each platform was locked
+    #[serde(default)]
+    #[serde_as(as = "BTreeMap<_, SerializableHash<Sha256>>")]
+    pub content_hash: BTreeMap<Platform, Sha256Hash>,
 }
 
 #[derive(Deserialize, Eq, PartialEq, Clone, Debug)]
 struct LockedPackageV3 {
     pub platform: Platform,
+    #[serde(default = "default_category")]
+    pub category: String,
     #[serde(flatten)]
     pub kind: LockedPackageKindV3,
 }
 
+/// The category of packages that are always installed.
+const MAIN_CATEGORY: &str = "main";
+
+fn default_category() -> String {
+    MAIN_CATEGORY.to_string()
+}
+
 #[derive(Deserialize, Eq, PartialEq, Clone, Debug)]
 #[serde(tag = "manager", rename_all = "snake_case")]
 enum LockedPackageKindV3 {
@@ -119,6 +142,13 @@ pub struct CondaLockedPackageV3 {
 }
 
 /// A function that enables parsing of lock files version 3 or lower.
+///
+/// All packages are stored in the default environment. Conda-lock files can
+/// also group packages into categories (e.g. `main` and `dev`) that can be
+/// installed selectively. If a file contains categories other than `main`, an
+/// environment is added for every category, which contains the packages of the
+/// category and of the `main` category. The `main` environment only contains
+/// the packages of the `main` category.
 pub fn parse_v3_or_lower(
     document: serde_yaml::Value,
     version: FileFormatVersion,
@@ -131,9 +161,14 @@ pub fn parse_v3_or_lower(
     let mut conda_packages = IndexSet::with_capacity(lock_file.package.len());
     let mut pypi_packages = IndexSet::with_capacity(lock_file.package.len());
     let mut pypi_runtime_configs = IndexSet::with_capacity(lock_file.package.len());
-    let mut per_platform: FxHashMap<Platform, Vec<EnvironmentPackageData>> = FxHashMap::default();
+    let mut per_category: BTreeMap<String, FxHashMap<Platform, IndexSet<EnvironmentPackageData>>> =
+        BTreeMap::new();
     for package in lock_file.package {
-        let LockedPackageV3 { platform, kind } = package;
+        let LockedPackageV3 {
+            platform,
+            category,
+            kind,
+        } = package;
 
         let pkg: EnvironmentPackageData = match kind {
             LockedPackageKindV3::Conda(value) => {
@@ -177,6 +212,7 @@ pub fn parse_v3_or_lower(
                         url: value.url,
                         file_name: None,
                         channel: None,
+                        extra: BTreeMap::new(),
                     })
                     .0;
 
@@ -201,16 +237,59 @@ pub fn parse_v3_or_lower(
             }
         };
 
-        per_platform.entry(package.platform).or_default().push(pkg);
+        per_category
+            .entry(category)
+            .or_default()
+            .entry(platform)
+            .or_default()
+            .insert(pkg);
     }
 
-    // Construct the default environment
-    let default_environment = EnvironmentData {
-        channels: lock_file.metadata.channels,
-        indexes: None,
-        packages: per_platform,
+    // Construct an environment that contains the packages of the given categories.
+    let environment = |categories: &[&str]| {
+        let mut packages: FxHashMap<Platform, IndexSet<EnvironmentPackageData>> =
+            FxHashMap::default();
+        for (category, per_platform) in &per_category {
+            if categories.is_empty() || categories.contains(&category.as_str()) {
+                for (platform, category_packages) in per_platform {
+                    packages
+                        .entry(*platform)
+                        .or_default()
+                        .extend(category_packages.iter().copied());
+                }
+            }
+        }
+        EnvironmentData {
+            channels: lock_file.metadata.channels.clone(),
+            indexes: None,
+            content_hash: lock_file.metadata.content_hash.iter().clone(),
+            extra: BTreeMap::new(),
+            packages: packages
+                .into_iter()
+                .map(|(platform, packages)| (platform, packages.into_iter().collect()))
+                .collect(),
+        }
     };
 
+    // The default environment contains all packages.
+    let mut environments = vec![(DEFAULT_ENVIRONMENT_NAME.to_string(), environment(&[]))];
+    if per_category
+        .keys()
+        .any(|category| category != MAIN_CATEGORY)
+    {
+        environments.extend(per_category.keys().map(|category| {
+            (
+                category.clone(),
+                environment(&[MAIN_CATEGORY, category.as_str()]),
+            )
+        }));
+    }
+    let (environment_lookup, environments) = environments
+        .into_iter()
+        .enumerate()
+        .map(|(idx, (name, environment))| ((name, idx), environment))
+        .unzip();
+
     Ok(LockFile {
         inner: Arc::new(LockFileInner {
             version,
@@ -221,10 +300,11 @@ pub fn parse_v3_or_lower(
                 .map(Into::into)
                 .collect(),
 
-            environment_lookup: [(DEFAULT_ENVIRONMENT_NAME.to_string(), 0)]
-                .into_iter()
-                .collect(),
-            environments: vec![default_environment],
+            environment_lookup,
+            environments,
+            git_metadata: lock_file.metadata.git_metadata,
+            time_metadata: lock_file.metadata.time_metadata,
+            extra: BTreeMap::new(),
         }),
     })
 }
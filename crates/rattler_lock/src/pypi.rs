@@ -48,6 +48,51 @@ pub struct PypiPackageEnvironmentData {
     pub extras: BTreeSet<ExtraName>,
 }
 
+/// The kind of artifact that a [`PypiPackageData`] refers to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PypiDistributionKind {
+    /// A built distribution (a `.whl` file).
+    Wheel,
+
+    /// A source distribution archive (e.g. a `.tar.gz` or `.zip` file).
+    SourceDistribution,
+
+    /// A source tree, e.g. a local directory or a git repository.
+    SourceTree,
+}
+
+impl PypiDistributionKind {
+    /// Determines the kind of artifact from its location.
+    fn from_url_or_path(url_or_path: &UrlOrPath) -> Self {
+        let file_name = match url_or_path {
+            UrlOrPath::Url(url) => {
+                if url.scheme().starts_with("git+") {
+                    return Self::SourceTree;
+                }
+                url.path_segments()
+                    .and_then(Iterator::last)
+                    .map(str::to_lowercase)
+            }
+            UrlOrPath::Path(path) => path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .map(str::to_lowercase),
+        }
+        .unwrap_or_default();
+
+        if file_name.ends_with(".whl") {
+            Self::Wheel
+        } else if [".tar.gz", ".tgz", ".tar.bz2", ".tar.xz", ".tar", ".zip"]
+            .iter()
+            .any(|extension| file_name.ends_with(extension))
+        {
+            Self::SourceDistribution
+        } else {
+            Self::SourceTree
+        }
+    }
+}
+
 impl PartialOrd for PypiPackageData {
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
         Some(self.cmp(other))
@@ -65,6 +110,13 @@ impl Ord for PypiPackageData {
 }
 
 impl PypiPackageData {
+    /// Returns whether this package refers to a wheel, a source distribution
+    /// or a source tree. Only wheels can be installed without building them
+    /// first.
+    pub fn distribution_kind(&self) -> PypiDistributionKind {
+        PypiDistributionKind::from_url_or_path(&self.url_or_path)
+    }
+
     /// Returns true if this package satisfies the given `spec`.
     pub fn satisfies(&self, spec: &Requirement) -> bool {
         // Check if the name matches
@@ -156,3 +208,40 @@ impl PypiSourceTreeHashable {
         PackageHashes::Sha256(hasher.finalize())
     }
 }
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+
+    use super::PypiDistributionKind;
+    use crate::UrlOrPath;
+
+    #[test]
+    fn test_distribution_kind() {
+        for (url_or_path, kind) in [
+            (
+                "https://files.pythonhosted.org/packages/05/8e/packaging-21.3-py3-none-any.whl",
+                PypiDistributionKind::Wheel,
+            ),
+            (
+                "https://files.pythonhosted.org/packages/4d/5b/requests-2.31.0.tar.gz",
+                PypiDistributionKind::SourceDistribution,
+            ),
+            (
+                "git+https://github.com/pallets/flask.git@2.3.2",
+                PypiDistributionKind::SourceTree,
+            ),
+            ("./libs/local-package", PypiDistributionKind::SourceTree),
+            (
+                "./dist/local_package-1.0.zip",
+                PypiDistributionKind::SourceDistribution,
+            ),
+        ] {
+            assert_eq!(
+                PypiDistributionKind::from_url_or_path(&UrlOrPath::from_str(url_or_path).unwrap()),
+                kind,
+                "{url_or_path}"
+            );
+        }
+    }
+}
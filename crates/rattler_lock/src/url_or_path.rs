@@ -4,7 +4,7 @@ use serde_with::{DeserializeFromStr, SerializeDisplay};
 use std::borrow::Cow;
 use std::cmp::Ordering;
 use std::hash::Hash;
-use std::path::Path;
+use std::path::{Component, Path};
 use std::{
     fmt::{Display, Formatter},
     path::PathBuf,
@@ -123,6 +123,52 @@ impl UrlOrPath {
 
         Cow::Borrowed(self)
     }
+
+    /// Resolves a relative path against `base`, which is usually the directory that contains the
+    /// lock file. `.` and `..` components are removed from the resulting path. URLs and absolute
+    /// paths are returned as is.
+    pub fn resolve(&self, base: &Path) -> Cow<'_, Self> {
+        match self {
+            UrlOrPath::Path(path) if path.is_relative() => {
+                Cow::Owned(UrlOrPath::Path(normalize_path(&base.join(path))))
+            }
+            _ => Cow::Borrowed(self),
+        }
+    }
+
+    /// Returns a path relative to `base` if this refers to a path inside `base`, this is the
+    /// inverse of [`Self::resolve`]. Storing relative paths allows a lock file to refer to
+    /// packages next to it regardless of where it is located.
+    pub fn relative_to(&self, base: &Path) -> Cow<'_, Self> {
+        let this = self.canonicalize();
+        let Some(path) = this.as_path().filter(|path| path.is_absolute()) else {
+            return this;
+        };
+        match normalize_path(path).strip_prefix(normalize_path(base)) {
+            Ok(relative) => Cow::Owned(UrlOrPath::Path(Path::new(".").join(relative))),
+            Err(_) => this,
+        }
+    }
+}
+
+/// Lexically removes `.` and `..` components from a path without accessing the filesystem.
+fn normalize_path(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => match normalized.components().next_back() {
+                Some(Component::Normal(_)) => {
+                    normalized.pop();
+                }
+                // The parent of the root is the root itself.
+                Some(Component::RootDir | Component::Prefix(_)) => {}
+                _ => normalized.push(component),
+            },
+            _ => normalized.push(component),
+        }
+    }
+    normalized
 }
 
 #[derive(Debug, Error, Eq, PartialEq)]
@@ -235,4 +281,45 @@ mod test {
             );
         }
     }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_resolve_and_relative_to() {
+        let base = Path::new("/home/bob/project");
+        let resolved = |path: &str| {
+            UrlOrPath::from_str(path)
+                .unwrap()
+                .resolve(base)
+                .into_owned()
+        };
+        assert_eq!(
+            resolved("./links/requests-2.31.0-py3-none-any.whl"),
+            UrlOrPath::Path("/home/bob/project/links/requests-2.31.0-py3-none-any.whl".into())
+        );
+        assert_eq!(
+            resolved("../other/./foo"),
+            UrlOrPath::Path("/home/bob/other/foo".into())
+        );
+        assert_eq!(resolved("/opt/foo"), UrlOrPath::Path("/opt/foo".into()));
+        assert_eq!(
+            resolved("https://example.com/foo.whl"),
+            UrlOrPath::Url("https://example.com/foo.whl".parse().unwrap())
+        );
+
+        let relative = |path: &str| {
+            UrlOrPath::from_str(path)
+                .unwrap()
+                .relative_to(base)
+                .into_owned()
+        };
+        assert_eq!(
+            relative("file:///home/bob/project/links/foo.whl").to_string(),
+            "./links/foo.whl"
+        );
+        assert_eq!(
+            relative("/home/bob/other/foo").to_string(),
+            "/home/bob/other/foo"
+        );
+        assert_eq!(relative("./foo").to_string(), "./foo");
+    }
 }
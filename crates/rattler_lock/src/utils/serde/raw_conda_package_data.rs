@@ -1,13 +1,14 @@
 use crate::CondaPackageData;
 use rattler_conda_types::{
-    BuildNumber, NoArchType, PackageName, PackageRecord, PackageUrl, VersionWithSource,
+    package::RunExportsJson, BuildNumber, NoArchType, PackageName, PackageRecord, PackageUrl,
+    VersionWithSource,
 };
 use rattler_digest::{serde::SerializableHash, Md5Hash, Sha256Hash};
 use serde::{Deserialize, Serialize};
 use serde_with::serde_as;
 use std::borrow::Cow;
 use std::cmp::Ordering;
-use std::collections::BTreeSet;
+use std::collections::{BTreeMap, BTreeSet};
 use url::Url;
 
 fn is_default<T: Default + Eq>(value: &T) -> bool {
@@ -89,6 +90,8 @@ pub(crate) struct RawCondaPackageData<'a> {
     pub license_family: Cow<'a, Option<String>>,
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub purls: Cow<'a, Option<BTreeSet<PackageUrl>>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub run_exports: Cow<'a, Option<RunExportsJson>>,
 
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub size: Cow<'a, Option<u64>>,
@@ -98,6 +101,10 @@ pub(crate) struct RawCondaPackageData<'a> {
     #[serde(default, skip_serializing_if = "Option::is_none")]
     #[serde_as(as = "Option<crate::utils::serde::Timestamp>")]
     pub timestamp: Option<chrono::DateTime<chrono::Utc>>,
+
+    // Fields that are not known to this version of the crate go to the bottom
+    #[serde(flatten)]
+    pub extra: Cow<'a, BTreeMap<String, serde_yaml::Value>>,
 }
 
 impl<'a> From<RawCondaPackageData<'a>> for CondaPackageData {
@@ -125,11 +132,12 @@ impl<'a> From<RawCondaPackageData<'a>> for CondaPackageData {
                 timestamp: value.timestamp,
                 track_features: value.track_features.into_owned(),
                 version: value.version.into_owned(),
-                run_exports: None,
+                run_exports: value.run_exports.into_owned(),
             },
             url: value.url.into_owned(),
             file_name: value.file_name.into_owned(),
             channel: value.channel.into_owned(),
+            extra: value.extra.into_owned(),
         }
     }
 }
@@ -147,6 +155,7 @@ impl<'a> From<&'a CondaPackageData> for RawCondaPackageData<'a> {
             channel: Cow::Borrowed(&value.channel),
             file_name: Cow::Borrowed(&value.file_name),
             purls: Cow::Borrowed(&value.package_record.purls),
+            run_exports: Cow::Borrowed(&value.package_record.run_exports),
             depends: Cow::Borrowed(&value.package_record.depends),
             constrains: Cow::Borrowed(&value.package_record.constrains),
             platform: Cow::Borrowed(&value.package_record.platform),
@@ -161,6 +170,7 @@ impl<'a> From<&'a CondaPackageData> for RawCondaPackageData<'a> {
             track_features: Cow::Borrowed(&value.package_record.track_features),
             license: Cow::Borrowed(&value.package_record.license),
             license_family: Cow::Borrowed(&value.package_record.license_family),
+            extra: Cow::Borrowed(&value.extra),
         }
     }
 }
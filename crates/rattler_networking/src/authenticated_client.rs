@@ -0,0 +1,159 @@
+//! A [`reqwest_middleware::ClientWithMiddleware`] that authenticates all
+//! requests with credentials from an [`AuthenticationStorage`].
+use std::{ops::Deref, sync::Arc};
+
+use reqwest_middleware::{ClientBuilder, ClientWithMiddleware, Middleware};
+
+use crate::{AuthenticationMiddleware, AuthenticationStorage};
+
+/// An HTTP client that applies stored credentials to every request.
+///
+/// Credentials are looked up per request in the [`AuthenticationStorage`].
+/// Conda tokens are injected into the path of the URL, bearer tokens and basic
+/// HTTP credentials are added as (sensitive) `Authorization` headers. When a
+/// request is redirected to another host, `reqwest` removes the
+/// `Authorization` header so credentials are never forwarded to a host they
+/// were not stored for.
+///
+/// The client dereferences to a [`ClientWithMiddleware`] and can be converted
+/// into one, so it can be passed to every function in rattler that performs
+/// network requests.
+#[derive(Clone, Debug)]
+pub struct AuthenticatedClient {
+    client: ClientWithMiddleware,
+}
+
+impl AuthenticatedClient {
+    /// Constructs a new client that authenticates requests with credentials
+    /// from the given storage.
+    pub fn new(client: reqwest::Client, auth_storage: AuthenticationStorage) -> Self {
+        Self::builder(client, auth_storage).build()
+    }
+
+    /// Constructs a new client that uses the default authentication storage
+    /// (see [`AuthenticationStorage::from_env`]).
+    pub fn from_env(client: reqwest::Client) -> anyhow::Result<Self> {
+        Ok(Self::new(client, AuthenticationStorage::from_env()?))
+    }
+
+    /// Returns a builder that allows adding additional middleware that is
+    /// executed after the request has been authenticated.
+    pub fn builder(
+        client: reqwest::Client,
+        auth_storage: AuthenticationStorage,
+    ) -> AuthenticatedClientBuilder {
+        AuthenticatedClientBuilder {
+            builder: ClientBuilder::new(client)
+                .with_arc(Arc::new(AuthenticationMiddleware::new(auth_storage))),
+        }
+    }
+
+    /// Returns the underlying client.
+    pub fn client(&self) -> &ClientWithMiddleware {
+        &self.client
+    }
+
+    /// Consumes this instance and returns the underlying client.
+    pub fn into_inner(self) -> ClientWithMiddleware {
+        self.client
+    }
+}
+
+impl Deref for AuthenticatedClient {
+    type Target = ClientWithMiddleware;
+
+    fn deref(&self) -> &Self::Target {
+        &self.client
+    }
+}
+
+impl From<AuthenticatedClient> for ClientWithMiddleware {
+    fn from(value: AuthenticatedClient) -> Self {
+        value.client
+    }
+}
+
+/// A builder for an [`AuthenticatedClient`].
+pub struct AuthenticatedClientBuilder {
+    builder: ClientBuilder,
+}
+
+impl AuthenticatedClientBuilder {
+    /// Adds a middleware that is executed after the authentication middleware.
+    #[must_use]
+    pub fn with<M: Middleware>(self, middleware: M) -> Self {
+        Self {
+            builder: self.builder.with(middleware),
+        }
+    }
+
+    /// Adds a middleware that is executed after the authentication middleware.
+    #[must_use]
+    pub fn with_arc(self, middleware: Arc<dyn Middleware>) -> Self {
+        Self {
+            builder: self.builder.with_arc(middleware),
+        }
+    }
+
+    /// Finishes the construction of the client.
+    pub fn build(self) -> AuthenticatedClient {
+        AuthenticatedClient {
+            client: self.builder.build(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{authentication_storage::backends::file::FileStorage, Authentication};
+    use axum::{http::HeaderMap, routing::get, Router};
+    use std::future::IntoFuture;
+    use tempfile::tempdir;
+
+    #[tokio::test]
+    async fn test_credentials_are_applied() -> anyhow::Result<()> {
+        async fn echo_authorization(headers: HeaderMap) -> String {
+            headers
+                .get("authorization")
+                .and_then(|value| value.to_str().ok())
+                .unwrap_or_default()
+                .to_string()
+        }
+
+        let router = Router::new().route("/", get(echo_authorization));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        tokio::spawn(axum::serve(listener, router.into_make_service()).into_future());
+
+        let tdir = tempdir()?;
+        let mut storage = AuthenticationStorage::new();
+        storage.add_backend(Arc::from(FileStorage::new(tdir.path().join("auth.json"))?));
+        storage.store(
+            "127.0.0.1",
+            &Authentication::BearerToken("secret".to_string()),
+        )?;
+
+        let client = AuthenticatedClient::new(reqwest::Client::new(), storage);
+        let body = client
+            .get(format!("http://{addr}/"))
+            .send()
+            .await?
+            .text()
+            .await?;
+        assert_eq!(body, "Bearer secret");
+
+        // The client can be used wherever a `ClientWithMiddleware` is expected.
+        let client: ClientWithMiddleware = client.into();
+        let body = client
+            .get(format!("http://{addr}/"))
+            .header("Authorization", "Bearer other")
+            .send()
+            .await?
+            .text()
+            .await?;
+        assert_eq!(body, "Bearer other");
+
+        Ok(())
+    }
+}
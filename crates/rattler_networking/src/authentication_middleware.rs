@@ -3,19 +3,53 @@ use crate::{Authentication, AuthenticationStorage};
 use async_trait::async_trait;
 use base64::prelude::BASE64_STANDARD;
 use base64::Engine;
-use reqwest::{Request, Response};
+use reqwest::{Request, Response, StatusCode};
 use reqwest_middleware::{Middleware, Next};
+use std::collections::HashMap;
 use std::path::{Path, PathBuf};
-use std::sync::OnceLock;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, SystemTime};
 use url::Url;
 
+/// Credentials are refreshed before a request when they expire within this
+/// margin.
+const EXPIRY_MARGIN: Duration = Duration::from_secs(60);
+
+/// New credentials returned by a [`TokenRefresher`].
+#[derive(Clone, Debug)]
+pub struct RefreshedCredentials {
+    /// The new credentials
+    pub authentication: Authentication,
+    /// The moment the new credentials expire, if known
+    pub expires_at: Option<SystemTime>,
+}
+
+/// A hook that is invoked to obtain new credentials for a host when the
+/// current credentials are rejected by the server (`401 Unauthorized`) or when
+/// they are about to expire.
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+pub trait TokenRefresher: Send + Sync {
+    /// Returns new credentials for the given host. `current` contains the
+    /// credentials that are currently in use, if any. Returning `None`
+    /// indicates that no new credentials are available.
+    async fn refresh(
+        &self,
+        host: &str,
+        current: Option<&Authentication>,
+    ) -> anyhow::Result<Option<RefreshedCredentials>>;
+}
+
 /// `reqwest` middleware to authenticate requests
 #[derive(Clone, Default)]
 pub struct AuthenticationMiddleware {
     auth_storage: AuthenticationStorage,
+    token_refresher: Option<Arc<dyn TokenRefresher>>,
+    expirations: Arc<Mutex<HashMap<String, SystemTime>>>,
 }
 
-#[async_trait]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
 impl Middleware for AuthenticationMiddleware {
     async fn handle(
         &self,
@@ -28,29 +62,134 @@ impl Middleware for AuthenticationMiddleware {
             return next.run(req, extensions).await;
         }
 
-        let url = req.url().clone();
-        match self.auth_storage.get_by_url(url) {
-            Err(_) => {
-                // Forward error to caller (invalid URL)
-                next.run(req, extensions).await
-            }
-            Ok((url, auth)) => {
-                let url = Self::authenticate_url(url, &auth);
+        let (Some(refresher), Some(host)) = (
+            self.token_refresher.as_deref(),
+            req.url().host_str().map(ToOwned::to_owned),
+        ) else {
+            let req = self.authenticate(req).await?;
+            return next.run(req, extensions).await;
+        };
 
-                let mut req = req;
-                *req.url_mut() = url;
+        if self.expires_soon(&host) {
+            self.refresh_credentials(refresher, req.url(), &host).await;
+        }
 
-                let req = Self::authenticate_request(req, &auth).await?;
-                next.run(req, extensions).await
-            }
+        // Keep an unauthenticated copy of the request around so it can be
+        // retried once with refreshed credentials. Requests with a streaming
+        // body cannot be cloned and are not retried.
+        let retry_req = req.try_clone();
+        let response = next
+            .clone()
+            .run(self.authenticate(req).await?, extensions)
+            .await?;
+
+        let Some(retry_req) = retry_req else {
+            return Ok(response);
+        };
+        if response.status() != StatusCode::UNAUTHORIZED
+            || !self
+                .refresh_credentials(refresher, retry_req.url(), &host)
+                .await
+        {
+            return Ok(response);
         }
+
+        let retry_req = self.authenticate(retry_req).await?;
+        next.run(retry_req, extensions).await
     }
 }
 
 impl AuthenticationMiddleware {
     /// Create a new authentication middleware with the given authentication storage
     pub fn new(auth_storage: AuthenticationStorage) -> Self {
-        Self { auth_storage }
+        Self {
+            auth_storage,
+            token_refresher: None,
+            expirations: Arc::default(),
+        }
+    }
+
+    /// Sets a hook that is invoked to refresh expired or rejected
+    /// credentials. Refreshed credentials are stored in the authentication
+    /// storage (for the exact host of the request) and the request is retried
+    /// once.
+    #[must_use]
+    pub fn with_token_refresher(self, token_refresher: Arc<dyn TokenRefresher>) -> Self {
+        Self {
+            token_refresher: Some(token_refresher),
+            ..self
+        }
+    }
+
+    /// Records when the credentials for the given host expire. The
+    /// credentials are refreshed before a request if they expire soon.
+    pub fn set_expiration(&self, host: &str, expires_at: SystemTime) {
+        self.expirations
+            .lock()
+            .unwrap()
+            .insert(host.to_string(), expires_at);
+    }
+
+    /// Returns true if the credentials for the given host are known to expire
+    /// within [`EXPIRY_MARGIN`].
+    fn expires_soon(&self, host: &str) -> bool {
+        self.expirations
+            .lock()
+            .unwrap()
+            .get(host)
+            .is_some_and(|expires_at| *expires_at <= SystemTime::now() + EXPIRY_MARGIN)
+    }
+
+    /// Invokes the token refresher and stores the new credentials. Returns
+    /// true if new credentials were obtained.
+    async fn refresh_credentials(
+        &self,
+        refresher: &dyn TokenRefresher,
+        url: &Url,
+        host: &str,
+    ) -> bool {
+        let current = self
+            .auth_storage
+            .get_by_url(url.clone())
+            .ok()
+            .and_then(|(_, auth)| auth);
+
+        let refreshed = match refresher.refresh(host, current.as_ref()).await {
+            Ok(Some(refreshed)) => refreshed,
+            Ok(None) => return false,
+            Err(e) => {
+                tracing::warn!("failed to refresh credentials for {host}: {e}");
+                return false;
+            }
+        };
+
+        if let Err(e) = self.auth_storage.store(host, &refreshed.authentication) {
+            tracing::warn!("failed to persist refreshed credentials for {host}: {e}");
+        }
+
+        let mut expirations = self.expirations.lock().unwrap();
+        match refreshed.expires_at {
+            Some(expires_at) => expirations.insert(host.to_string(), expires_at),
+            None => expirations.remove(host),
+        };
+
+        true
+    }
+
+    /// Adds the stored credentials for the URL of the request to the request.
+    async fn authenticate(&self, req: Request) -> reqwest_middleware::Result<Request> {
+        match self.auth_storage.get_by_url(req.url().clone()) {
+            // Forward error to caller (invalid URL)
+            Err(_) => Ok(req),
+            Ok((url, auth)) => {
+                let url = Self::authenticate_url(url, &auth);
+
+                let mut req = req;
+                *req.url_mut() = url;
+
+                Self::authenticate_request(req, &auth).await
+            }
+        }
     }
 
     /// Authenticate the given URL with the given authentication information
@@ -130,7 +269,8 @@ pub fn default_auth_store_fallback_directory() -> &'static Path {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::authentication_storage::backends::file::FileStorage;
+    use crate::authentication_storage::backends::{file::FileStorage, memory::MemoryStorage};
+    use crate::authentication_storage::StorageBackend;
     use anyhow::anyhow;
     use std::sync::Arc;
     use tempfile::tempdir;
@@ -370,6 +510,121 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_explicit_credentials_take_precedence() -> anyhow::Result<()> {
+        let tdir = tempdir()?;
+        let file_storage = FileStorage::new(tdir.path().to_path_buf().join("auth.json"))?;
+        file_storage.store(
+            "artifactory.example.com",
+            &Authentication::BearerToken("stored".to_string()),
+        )?;
+
+        let mut storage = AuthenticationStorage::new();
+        storage.add_backend(Arc::from(file_storage));
+        storage.insert_backend(
+            0,
+            Arc::new(MemoryStorage::new().with_basic_auth(
+                "artifactory.example.com",
+                "testuser",
+                "testpassword",
+            )),
+        );
+
+        let (client, mut captured_rx) = make_client_harness(&storage);
+        let _ = client
+            .get("https://artifactory.example.com/conda-forge/noarch/repodata.json")
+            .send()
+            .await;
+
+        let captured_request = captured_rx.recv().await.unwrap();
+        assert_eq!(
+            captured_request
+                .headers()
+                .get(reqwest::header::AUTHORIZATION)
+                .unwrap(),
+            // this is the base64 encoding of "testuser:testpassword"
+            "Basic dGVzdHVzZXI6dGVzdHBhc3N3b3Jk"
+        );
+
+        Ok(())
+    }
+
+    struct CountingRefresher {
+        calls: std::sync::atomic::AtomicUsize,
+        expires_in: Duration,
+    }
+
+    #[async_trait]
+    impl TokenRefresher for CountingRefresher {
+        async fn refresh(
+            &self,
+            _host: &str,
+            _current: Option<&Authentication>,
+        ) -> anyhow::Result<Option<RefreshedCredentials>> {
+            let call = self.calls.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            Ok(Some(RefreshedCredentials {
+                authentication: Authentication::BearerToken(format!("fresh-{call}")),
+                expires_at: Some(SystemTime::now() + self.expires_in),
+            }))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_token_refresh() -> anyhow::Result<()> {
+        use axum::{http::HeaderMap, http::StatusCode, routing::get, Router};
+        use std::future::IntoFuture;
+
+        // Only accepts refreshed tokens
+        async fn protected(headers: HeaderMap) -> (StatusCode, String) {
+            match headers.get("authorization").and_then(|v| v.to_str().ok()) {
+                Some(token) if token.starts_with("Bearer fresh-") => {
+                    (StatusCode::OK, token.to_string())
+                }
+                _ => (StatusCode::UNAUTHORIZED, String::new()),
+            }
+        }
+
+        let router = Router::new().route("/", get(protected));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        tokio::spawn(axum::serve(listener, router.into_make_service()).into_future());
+
+        let tdir = tempdir()?;
+        let storage = AuthenticationStorage::from_file(&tdir.path().join("auth.json"))?;
+        storage.store(
+            "127.0.0.1",
+            &Authentication::BearerToken("stale".to_string()),
+        )?;
+
+        let refresher = Arc::new(CountingRefresher {
+            calls: Default::default(),
+            expires_in: Duration::from_secs(10),
+        });
+        let client = reqwest_middleware::ClientBuilder::new(reqwest::Client::default())
+            .with(
+                AuthenticationMiddleware::new(storage.clone())
+                    .with_token_refresher(refresher.clone()),
+            )
+            .build();
+
+        // The stale token is rejected, refreshed and the request is retried.
+        let response = client.get(format!("http://{addr}/")).send().await?;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.text().await?, "Bearer fresh-0");
+        assert_eq!(
+            storage.get("127.0.0.1")?,
+            Some(Authentication::BearerToken("fresh-0".to_string()))
+        );
+
+        // The refreshed token expires within the margin so it is refreshed
+        // before the next request.
+        let response = client.get(format!("http://{addr}/")).send().await?;
+        assert_eq!(response.text().await?, "Bearer fresh-1");
+        assert_eq!(refresher.calls.load(std::sync::atomic::Ordering::SeqCst), 2);
+
+        Ok(())
+    }
+
     #[test]
     fn test_host_wildcard_expansion() -> anyhow::Result<()> {
         for (host, should_succeed) in [
@@ -1,6 +1,7 @@
 //! Authentication methods for the conda ecosystem
 use std::str::FromStr;
 
+use rattler_error::ErrorCode;
 use serde::{Deserialize, Serialize};
 
 /// The different Authentication methods that are supported in the conda
@@ -22,14 +23,25 @@ pub enum Authentication {
 }
 
 /// An error that can occur when parsing an authentication string
-#[derive(Debug)]
+#[derive(Debug, thiserror::Error)]
 pub enum AuthenticationParseError {
     /// The scheme is not valid
+    #[error("invalid authentication scheme")]
     InvalidScheme,
     /// The token could not be parsed
+    #[error("invalid authentication token")]
     InvalidToken,
 }
 
+impl ErrorCode for AuthenticationParseError {
+    fn code(&self) -> &'static str {
+        match self {
+            AuthenticationParseError::InvalidScheme => "rattler::authentication::invalid_scheme",
+            AuthenticationParseError::InvalidToken => "rattler::authentication::invalid_token",
+        }
+    }
+}
+
 impl FromStr for Authentication {
     type Err = AuthenticationParseError;
 
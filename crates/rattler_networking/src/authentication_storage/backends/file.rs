@@ -1,7 +1,10 @@
 //! file storage for passwords.
 use anyhow::Result;
+#[cfg(not(target_arch = "wasm32"))]
 use fslock::LockFile;
+use rattler_error::ErrorCode;
 use std::collections::BTreeMap;
+use std::io::Write;
 use std::path::Path;
 use std::sync::Arc;
 use std::{path::PathBuf, sync::Mutex};
@@ -17,6 +20,10 @@ struct FileStorageCache {
 
 /// A struct that implements storage and access of authentication
 /// information backed by a on-disk JSON file
+///
+/// This is used as a fallback on (headless) systems without a keyring. The
+/// file is written atomically and, on Unix, is only readable and writable by
+/// its owner (mode `0600`).
 #[derive(Clone, Debug)]
 pub struct FileStorage {
     /// The path to the JSON file
@@ -44,8 +51,21 @@ pub enum FileStorageError {
     JSONError(#[from] serde_json::Error),
 }
 
+impl ErrorCode for FileStorageError {
+    fn code(&self) -> &'static str {
+        match self {
+            FileStorageError::IOError(_) => "rattler::authentication::file_storage::io",
+            FileStorageError::FailedToLock(..) => {
+                "rattler::authentication::file_storage::failed_to_lock"
+            }
+            FileStorageError::JSONError(_) => "rattler::authentication::file_storage::json",
+        }
+    }
+}
+
 /// Lock the file storage file for reading and writing. This will block until the lock is
 /// acquired.
+#[cfg(not(target_arch = "wasm32"))]
 fn lock_file_storage(path: &Path, write: bool) -> Result<Option<LockFile>, FileStorageError> {
     if !write && !path.exists() {
         return Ok(None);
@@ -69,11 +89,56 @@ fn lock_file_storage(path: &Path, write: bool) -> Result<Option<LockFile>, FileS
     Ok(Some(lock))
 }
 
+/// File locks are not available on wasm, the file storage is not locked.
+#[cfg(target_arch = "wasm32")]
+fn lock_file_storage(_path: &Path, _write: bool) -> Result<Option<()>, FileStorageError> {
+    Ok(None)
+}
+
+/// Restricts the permissions of the credentials file so that it is only
+/// accessible by its owner.
+#[cfg(unix)]
+fn restrict_permissions(file: &std::fs::File) -> Result<(), std::io::Error> {
+    use std::os::unix::fs::PermissionsExt;
+    file.set_permissions(std::fs::Permissions::from_mode(0o600))
+}
+
+#[cfg(not(unix))]
+#[allow(clippy::unnecessary_wraps)]
+fn restrict_permissions(_file: &std::fs::File) -> Result<(), std::io::Error> {
+    Ok(())
+}
+
+/// Warns about and fixes credential files that are accessible by other users.
+#[cfg(unix)]
+fn enforce_strict_permissions(path: &Path) -> Result<(), std::io::Error> {
+    use std::os::unix::fs::PermissionsExt;
+    let mode = std::fs::metadata(path)?.permissions().mode();
+    if mode & 0o077 != 0 {
+        tracing::warn!(
+            "credentials file {} is accessible by other users (mode {:o}), restricting permissions to 0600",
+            path.display(),
+            mode & 0o777
+        );
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600))?;
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+#[allow(clippy::unnecessary_wraps)]
+fn enforce_strict_permissions(_path: &Path) -> Result<(), std::io::Error> {
+    Ok(())
+}
+
 impl FileStorageCache {
     pub fn from_path(path: &Path) -> Result<Self, FileStorageError> {
         let file_exists = path.exists();
         let cache = if file_exists {
             lock_file_storage(path, false)?;
+            if let Err(e) = enforce_strict_permissions(path) {
+                tracing::warn!("failed to restrict permissions of {}: {e}", path.display());
+            }
             let file = std::fs::File::open(path)?;
             let reader = std::io::BufReader::new(file);
             serde_json::from_reader(reader)?
@@ -106,12 +171,25 @@ impl FileStorage {
     }
 
     /// Serialize the given `BTreeMap` and write it to the JSON file
+    ///
+    /// The data is first written to a temporary file in the same directory
+    /// which then replaces the original file. This ensures that the file is
+    /// never left in a partially written state.
     fn write_json(&self, dict: &BTreeMap<String, Authentication>) -> Result<(), FileStorageError> {
         let _lock = lock_file_storage(&self.path, true)?;
 
-        let file = std::fs::File::create(&self.path)?;
-        let writer = std::io::BufWriter::new(file);
-        serde_json::to_writer(writer, dict)?;
+        let parent = self.path.parent().unwrap_or_else(|| Path::new("."));
+        let mut temp_file = tempfile::NamedTempFile::new_in(parent)?;
+        restrict_permissions(temp_file.as_file())?;
+        {
+            let mut writer = std::io::BufWriter::new(temp_file.as_file_mut());
+            serde_json::to_writer(&mut writer, dict)?;
+            writer.flush()?;
+        }
+        temp_file.as_file().sync_all()?;
+        temp_file
+            .persist(&self.path)
+            .map_err(|e| FileStorageError::IOError(e.error))?;
 
         // Store the new data in the cache
         let mut cache = self.cache.lock().unwrap();
@@ -209,4 +287,31 @@ mod tests {
 
         assert!(FileStorage::new(path.clone()).is_err());
     }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_file_storage_permissions() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let file = tempdir().unwrap();
+        let path = file.path().join("credentials.json");
+
+        let storage = FileStorage::new(path.clone()).unwrap();
+        storage
+            .store("test", &Authentication::CondaToken("password".to_string()))
+            .unwrap();
+
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+
+        // Files with too open permissions are restricted when they are read.
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o644)).unwrap();
+        let storage = FileStorage::new(path.clone()).unwrap();
+        assert_eq!(
+            storage.get("test").unwrap(),
+            Some(Authentication::CondaToken("password".to_string()))
+        );
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+    }
 }
@@ -2,6 +2,7 @@
 
 use anyhow::Result;
 use keyring::Entry;
+use rattler_error::ErrorCode;
 use std::str::FromStr;
 
 use crate::{authentication_storage::StorageBackend, Authentication};
@@ -42,6 +43,22 @@ pub enum KeyringAuthenticationStorageError {
     },
 }
 
+impl ErrorCode for KeyringAuthenticationStorageError {
+    fn code(&self) -> &'static str {
+        match self {
+            KeyringAuthenticationStorageError::StorageError(_) => {
+                "rattler::authentication::keyring::storage"
+            }
+            KeyringAuthenticationStorageError::SerializeCredentialsError(_) => {
+                "rattler::authentication::keyring::serialize_credentials"
+            }
+            KeyringAuthenticationStorageError::ParseCredentialsError { .. } => {
+                "rattler::authentication::keyring::parse_credentials"
+            }
+        }
+    }
+}
+
 impl Default for KeyringAuthenticationStorage {
     fn default() -> Self {
         Self::from_key("rattler")
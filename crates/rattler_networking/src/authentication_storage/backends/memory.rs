@@ -0,0 +1,96 @@
+//! In-memory storage for credentials that are provided explicitly (e.g. on the
+//! command line).
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use crate::{authentication_storage::StorageBackend, Authentication};
+
+/// A struct that implements storage and access of authentication
+/// information that only lives in memory.
+///
+/// This is useful for credentials that are provided explicitly, like a
+/// username and password passed on the command line for a private channel.
+/// Nothing is persisted. Add the backend with
+/// [`crate::AuthenticationStorage::insert_backend`] at the front of the
+/// storage to give explicit credentials precedence over stored ones.
+#[derive(Clone, Debug, Default)]
+pub struct MemoryStorage {
+    credentials: Arc<Mutex<HashMap<String, Authentication>>>,
+}
+
+impl MemoryStorage {
+    /// Create a new empty in-memory storage
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds the authentication information for the given host.
+    #[must_use]
+    pub fn with_credentials(self, host: impl Into<String>, authentication: Authentication) -> Self {
+        self.credentials
+            .lock()
+            .unwrap()
+            .insert(host.into(), authentication);
+        self
+    }
+
+    /// Adds HTTP basic authentication with the given username and password
+    /// for the given host.
+    #[must_use]
+    pub fn with_basic_auth(
+        self,
+        host: impl Into<String>,
+        username: impl Into<String>,
+        password: impl Into<String>,
+    ) -> Self {
+        self.with_credentials(
+            host,
+            Authentication::BasicHTTP {
+                username: username.into(),
+                password: password.into(),
+            },
+        )
+    }
+}
+
+impl StorageBackend for MemoryStorage {
+    fn store(&self, host: &str, authentication: &Authentication) -> anyhow::Result<()> {
+        self.credentials
+            .lock()
+            .unwrap()
+            .insert(host.to_string(), authentication.clone());
+        Ok(())
+    }
+
+    fn get(&self, host: &str) -> anyhow::Result<Option<Authentication>> {
+        Ok(self.credentials.lock().unwrap().get(host).cloned())
+    }
+
+    fn delete(&self, host: &str) -> anyhow::Result<()> {
+        self.credentials.lock().unwrap().remove(host);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_memory_storage() {
+        let storage = MemoryStorage::new().with_basic_auth("artifactory.corp", "user", "secret");
+        assert_eq!(
+            storage.get("artifactory.corp").unwrap(),
+            Some(Authentication::BasicHTTP {
+                username: "user".to_string(),
+                password: "secret".to_string(),
+            })
+        );
+        assert_eq!(storage.get("example.com").unwrap(), None);
+
+        storage.delete("artifactory.corp").unwrap();
+        assert_eq!(storage.get("artifactory.corp").unwrap(), None);
+    }
+}
@@ -2,6 +2,7 @@
 
 use crate::{authentication_storage::StorageBackend, Authentication};
 use netrc_rs::{Machine, Netrc};
+use rattler_error::ErrorCode;
 use std::{collections::HashMap, env, io::ErrorKind, path::Path, path::PathBuf};
 
 /// A struct that implements storage and access of authentication
@@ -10,6 +11,10 @@ use std::{collections::HashMap, env, io::ErrorKind, path::Path, path::PathBuf};
 pub struct NetRcStorage {
     /// The netrc file contents
     machines: HashMap<String, Machine>,
+
+    /// The `default` entry of the netrc file that is used for hosts without
+    /// a matching `machine` entry
+    default: Option<Machine>,
 }
 
 /// An error that can occur when accessing the fallback storage
@@ -24,6 +29,15 @@ pub enum NetRcStorageError {
     ParseError(netrc_rs::Error),
 }
 
+impl ErrorCode for NetRcStorageError {
+    fn code(&self) -> &'static str {
+        match self {
+            NetRcStorageError::IOError(_) => "rattler::authentication::netrc::io",
+            NetRcStorageError::ParseError(_) => "rattler::authentication::netrc::parse",
+        }
+    }
+}
+
 impl NetRcStorage {
     /// Create a new fallback storage by retrieving the netrc file from the user environment.  
     /// This uses the same environment variable as curl and will read the file from $NETRC
@@ -63,18 +77,22 @@ impl NetRcStorage {
     pub fn from_path(path: &Path) -> Result<Self, NetRcStorageError> {
         let content = std::fs::read_to_string(path)?;
         let netrc = Netrc::parse(content, false).map_err(NetRcStorageError::ParseError)?;
-        let machines = netrc
-            .machines
-            .into_iter()
-            .map(|m| (m.name.clone(), m))
-            .filter_map(|(name, value)| name.map(|n| (n, value)))
-            .collect();
-        Ok(Self { machines })
+        let mut machines = HashMap::new();
+        let mut default = None;
+        for machine in netrc.machines {
+            match machine.name.clone() {
+                Some(name) => {
+                    machines.insert(name, machine);
+                }
+                None => default = Some(machine),
+            }
+        }
+        Ok(Self { machines, default })
     }
 
     /// Retrieve the authentication information for the given host
     pub fn get_password(&self, host: &str) -> Result<Option<Authentication>, NetRcStorageError> {
-        match self.machines.get(host) {
+        match self.machines.get(host).or(self.default.as_ref()) {
             Some(machine) => Ok(Some(Authentication::BasicHTTP {
                 username: machine.login.clone().unwrap_or_default(),
                 password: machine.password.clone().unwrap_or_default(),
@@ -131,6 +149,33 @@ mod tests {
         assert_eq!(storage.get("test_unknown").unwrap(), None);
     }
 
+    #[test]
+    fn test_default_machine() {
+        let file = tempdir().unwrap();
+        let path = file.path().join(".testnetrc");
+        std::fs::write(
+            &path,
+            "machine artifactory.corp\nlogin test\npassword password\n\ndefault\nlogin anonymous\npassword guest\n",
+        )
+        .unwrap();
+
+        let storage = NetRcStorage::from_path(path.as_path()).unwrap();
+        assert_eq!(
+            storage.get("artifactory.corp").unwrap(),
+            Some(Authentication::BasicHTTP {
+                username: "test".to_string(),
+                password: "password".to_string(),
+            })
+        );
+        assert_eq!(
+            storage.get("nexus.corp").unwrap(),
+            Some(Authentication::BasicHTTP {
+                username: "anonymous".to_string(),
+                password: "guest".to_string(),
+            })
+        );
+    }
+
     #[test]
     fn test_file_storage_from_env() {
         let file = tempdir().unwrap();
@@ -91,8 +91,25 @@ impl AuthenticationStorage {
         self.backends.push(backend);
     }
 
+    /// Insert a storage backend at the given position. Backends are tried in
+    /// order, so inserting a backend at index `0` (e.g. a
+    /// [`super::backends::memory::MemoryStorage`] with explicitly provided
+    /// credentials) gives it precedence over all other backends.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is larger than the number of backends.
+    pub fn insert_backend(&mut self, index: usize, backend: Arc<dyn StorageBackend + Send + Sync>) {
+        self.backends.insert(index, backend);
+    }
+
     /// Store the given authentication information for the given host
+    ///
+    /// The host can also be a wildcard pattern like `*.prefix.dev` to use
+    /// the credentials for all subdomains of `prefix.dev`.
     pub fn store(&self, host: &str, authentication: &Authentication) -> Result<()> {
+        validate_host_pattern(host)?;
+
         {
             let mut cache = self.cache.lock().unwrap();
             cache.insert(host.to_string(), Some(authentication.clone()));
@@ -144,6 +161,10 @@ impl AuthenticationStorage {
     /// E.g. if credentials are stored for `*.prefix.dev` and the
     /// given URL is `https://repo.prefix.dev`, the credentials
     /// for `*.prefix.dev` will be returned.
+    ///
+    /// If credentials are stored for multiple matching patterns the most
+    /// specific one is used: credentials for the exact host take precedence
+    /// over `*.repo.prefix.dev`, which takes precedence over `*.prefix.dev`.
     pub fn get_by_url<U: IntoUrl>(
         &self,
         url: U,
@@ -160,30 +181,19 @@ impl AuthenticationStorage {
         };
 
         // Check for credentials under e.g. `*.prefix.dev`
-        let Some(mut domain) = url.domain() else {
+        let Some(domain) = url.domain() else {
             return Ok((url, None));
         };
 
-        loop {
-            let wildcard_host = format!("*.{domain}");
-
-            let Ok(credentials) = self.get(&wildcard_host) else {
-                return Ok((url, None));
-            };
-
-            if let Some(credentials) = credentials {
-                return Ok((url, Some(credentials)));
-            }
-
-            let possible_rest = domain.split_once('.').map(|(_, rest)| rest);
-
-            match possible_rest {
-                Some(rest) => {
-                    domain = rest;
-                }
-                _ => return Ok((url, None)), // No more subdomains to check
+        for wildcard_host in wildcard_hosts(domain) {
+            match self.get(&wildcard_host) {
+                Ok(Some(credentials)) => return Ok((url, Some(credentials))),
+                Ok(None) => {}
+                Err(_) => return Ok((url, None)),
             }
         }
+
+        Ok((url, None))
     }
 
     /// Delete the authentication information for the given host
@@ -210,3 +220,78 @@ impl AuthenticationStorage {
         }
     }
 }
+
+/// Checks that a wildcard is only used as the first label of a host pattern
+/// (e.g. `*.prefix.dev`).
+fn validate_host_pattern(host: &str) -> Result<()> {
+    let rest = host.strip_prefix("*.").unwrap_or(host);
+    if rest.is_empty() || rest.contains('*') {
+        return Err(anyhow!(
+            "invalid host pattern '{host}', a wildcard is only allowed as the first label (e.g. '*.example.com')"
+        ));
+    }
+    Ok(())
+}
+
+/// Returns the wildcard patterns that match the given domain, ordered from
+/// the most to the least specific pattern. For `repo.prefix.dev` this yields
+/// `*.repo.prefix.dev`, `*.prefix.dev` and `*.dev`.
+fn wildcard_hosts(domain: &str) -> impl Iterator<Item = String> + '_ {
+    std::iter::successors(Some(domain), |domain| {
+        domain.split_once('.').map(|(_, rest)| rest)
+    })
+    .filter(|domain| !domain.is_empty())
+    .map(|domain| format!("*.{domain}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::authentication_storage::backends::file::FileStorage;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_wildcard_hosts() {
+        assert_eq!(
+            wildcard_hosts("repo.prefix.dev").collect::<Vec<_>>(),
+            vec!["*.repo.prefix.dev", "*.prefix.dev", "*.dev"]
+        );
+    }
+
+    #[test]
+    fn test_most_specific_wildcard_is_used() -> Result<()> {
+        let tdir = tempdir()?;
+        let storage = AuthenticationStorage::from_file(&tdir.path().join("auth.json"))?;
+
+        let corp = Authentication::BearerToken("corp".to_string());
+        let internal = Authentication::BearerToken("internal".to_string());
+        let exact = Authentication::BearerToken("exact".to_string());
+        storage.store("*.corp", &corp)?;
+        storage.store("*.internal.corp", &internal)?;
+        storage.store("conda.internal.corp", &exact)?;
+
+        let get = |url: &str| storage.get_by_url(url).unwrap().1;
+        assert_eq!(get("https://conda.internal.corp/channel"), Some(exact));
+        assert_eq!(get("https://pypi.internal.corp/simple"), Some(internal));
+        assert_eq!(get("https://artifacts.corp/channel"), Some(corp));
+        assert_eq!(get("https://example.com/channel"), None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid_host_patterns() -> Result<()> {
+        let tdir = tempdir()?;
+        let storage = AuthenticationStorage::from_file(&tdir.path().join("auth.json"))?;
+        let auth = Authentication::BearerToken("token".to_string());
+
+        for host in ["*", "*.", "repo.*.dev", "*.*.dev", "*prefix.dev"] {
+            assert!(
+                storage.store(host, &auth).is_err(),
+                "{host} should be rejected"
+            );
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,320 @@
+//! Middleware to authenticate requests to channels hosted on Azure Blob
+//! Storage.
+//!
+//! Requests to `az://<account>/<container>/<path>` URLs are rewritten to
+//! `https://<account>.blob.core.windows.net/<container>/<path>`. Requests to
+//! Azure Blob Storage are authenticated with a bearer token obtained from the
+//! ambient credentials of the machine. The following sources are tried in
+//! order:
+//!
+//! 1. Workload identity, configured through the `AZURE_FEDERATED_TOKEN_FILE`,
+//!    `AZURE_CLIENT_ID` and `AZURE_TENANT_ID` environment variables.
+//! 2. A managed identity through the Azure instance metadata service.
+//!
+//! If no credentials are available the request is sent without
+//! authentication, which allows reading from public containers.
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use async_trait::async_trait;
+use reqwest::{
+    header::{HeaderValue, AUTHORIZATION},
+    Request, Response,
+};
+use reqwest_middleware::{Middleware, Next, Result as MiddlewareResult};
+use serde::Deserialize;
+use url::Url;
+
+const STORAGE_RESOURCE: &str = "https://storage.azure.com/";
+const BLOB_HOST_SUFFIX: &str = ".blob.core.windows.net";
+const IMDS_TOKEN_URL: &str = "http://169.254.169.254/metadata/identity/oauth2/token";
+const DEFAULT_AUTHORITY_HOST: &str = "https://login.microsoftonline.com/";
+
+/// The version of the Blob Storage REST API. OAuth authentication requires at
+/// least version `2017-11-09`.
+const STORAGE_API_VERSION: &str = "2020-04-08";
+
+/// Tokens are refreshed when they expire within this margin.
+const EXPIRY_MARGIN: Duration = Duration::from_secs(300);
+
+/// How long requests are sent without authentication before looking for
+/// credentials again, after no token could be acquired.
+const UNAVAILABLE_RETRY_INTERVAL: Duration = Duration::from_secs(300);
+
+#[derive(Clone, Debug)]
+struct AzureToken {
+    access_token: String,
+    expires_at: Instant,
+}
+
+/// The result of the last attempt to acquire a token.
+#[derive(Clone, Debug)]
+enum CachedToken {
+    Token(AzureToken),
+
+    /// No credentials were found or acquiring a token failed. Looking for
+    /// credentials again, e.g. waiting for the instance metadata service to
+    /// time out, is skipped until `retry_at`.
+    Unavailable {
+        retry_at: Instant,
+    },
+}
+
+/// `reqwest` middleware to authenticate requests to Azure Blob Storage
+#[derive(Clone, Debug, Default)]
+pub struct AzureMiddleware {
+    token_client: reqwest::Client,
+    token: Arc<Mutex<Option<CachedToken>>>,
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+impl Middleware for AzureMiddleware {
+    async fn handle(
+        &self,
+        mut req: Request,
+        extensions: &mut http::Extensions,
+        next: Next<'_>,
+    ) -> MiddlewareResult<Response> {
+        if req.url().scheme() == "az" {
+            *req.url_mut() = azure_blob_url(req.url())?;
+        }
+
+        if is_azure_blob_url(req.url()) && req.headers().get(AUTHORIZATION).is_none() {
+            if let Some(token) = self.token().await {
+                let mut header_value = HeaderValue::from_str(&format!("Bearer {token}"))
+                    .map_err(reqwest_middleware::Error::middleware)?;
+                header_value.set_sensitive(true);
+                req.headers_mut().insert(AUTHORIZATION, header_value);
+                req.headers_mut().insert(
+                    "x-ms-version",
+                    HeaderValue::from_static(STORAGE_API_VERSION),
+                );
+            }
+        }
+
+        next.run(req, extensions).await
+    }
+}
+
+impl AzureMiddleware {
+    /// Returns a cached access token or requests a new one if there is no
+    /// token or the token is about to expire. If no token could be acquired,
+    /// that result is cached as well.
+    async fn token(&self) -> Option<String> {
+        let cached = self.token.lock().unwrap().clone();
+        match cached {
+            Some(CachedToken::Token(token))
+                if token.expires_at > Instant::now() + EXPIRY_MARGIN =>
+            {
+                return Some(token.access_token);
+            }
+            Some(CachedToken::Unavailable { retry_at }) if retry_at > Instant::now() => {
+                return None;
+            }
+            _ => {}
+        }
+
+        let token = match self.request_token().await {
+            Ok(Some(token)) => token,
+            Ok(None) => {
+                tracing::debug!("no Azure credentials found, sending unauthenticated requests");
+                self.set_unavailable();
+                return None;
+            }
+            Err(e) => {
+                tracing::warn!("failed to acquire Azure access token: {e}");
+                self.set_unavailable();
+                return None;
+            }
+        };
+
+        let access_token = token.access_token.clone();
+        *self.token.lock().unwrap() = Some(CachedToken::Token(token));
+        Some(access_token)
+    }
+
+    fn set_unavailable(&self) {
+        *self.token.lock().unwrap() = Some(CachedToken::Unavailable {
+            retry_at: Instant::now() + UNAVAILABLE_RETRY_INTERVAL,
+        });
+    }
+
+    async fn request_token(&self) -> Result<Option<AzureToken>, reqwest::Error> {
+        if let Some(token) = self.request_workload_identity_token().await? {
+            return Ok(Some(token));
+        }
+        self.request_managed_identity_token().await
+    }
+
+    /// Exchanges a federated token (e.g. from a Kubernetes service account)
+    /// for an access token.
+    async fn request_workload_identity_token(&self) -> Result<Option<AzureToken>, reqwest::Error> {
+        let (Ok(token_file), Ok(client_id), Ok(tenant_id)) = (
+            std::env::var("AZURE_FEDERATED_TOKEN_FILE"),
+            std::env::var("AZURE_CLIENT_ID"),
+            std::env::var("AZURE_TENANT_ID"),
+        ) else {
+            return Ok(None);
+        };
+
+        let assertion = match std::fs::read_to_string(&token_file) {
+            Ok(assertion) => assertion,
+            Err(e) => {
+                tracing::warn!("failed to read federated token file {token_file}: {e}");
+                return Ok(None);
+            }
+        };
+
+        let authority = std::env::var("AZURE_AUTHORITY_HOST")
+            .unwrap_or_else(|_| DEFAULT_AUTHORITY_HOST.to_string());
+        let url = format!(
+            "{}/{tenant_id}/oauth2/v2.0/token",
+            authority.trim_end_matches('/')
+        );
+        let scope = format!("{STORAGE_RESOURCE}.default");
+
+        let response: TokenResponse = self
+            .token_client
+            .post(url)
+            .form(&[
+                ("client_id", client_id.as_str()),
+                ("scope", scope.as_str()),
+                (
+                    "client_assertion_type",
+                    "urn:ietf:params:oauth:client-assertion-type:jwt-bearer",
+                ),
+                ("client_assertion", assertion.trim()),
+                ("grant_type", "client_credentials"),
+            ])
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        Ok(Some(response.into_token()))
+    }
+
+    /// Requests an access token for a managed identity from the instance
+    /// metadata service. Returns `None` if the service is not reachable.
+    async fn request_managed_identity_token(&self) -> Result<Option<AzureToken>, reqwest::Error> {
+        let mut request = self
+            .token_client
+            .get(IMDS_TOKEN_URL)
+            .query(&[
+                ("api-version", "2018-02-01"),
+                ("resource", STORAGE_RESOURCE),
+            ])
+            .header("Metadata", "true")
+            .timeout(Duration::from_secs(2));
+        if let Ok(client_id) = std::env::var("AZURE_CLIENT_ID") {
+            request = request.query(&[("client_id", client_id)]);
+        }
+
+        let response = match request.send().await {
+            Ok(response) => response,
+            Err(e) if e.is_request() || e.is_timeout() => return Ok(None),
+            Err(e) => return Err(e),
+        };
+
+        let response: TokenResponse = response.error_for_status()?.json().await?;
+        Ok(Some(response.into_token()))
+    }
+}
+
+/// The response of both the Azure AD token endpoint and the instance metadata
+/// service. Depending on the service, numbers are encoded as strings.
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    #[serde(default)]
+    expires_in: Option<serde_json::Value>,
+}
+
+impl TokenResponse {
+    fn into_token(self) -> AzureToken {
+        let expires_in = self
+            .expires_in
+            .and_then(|value| match value {
+                serde_json::Value::Number(n) => n.as_u64(),
+                serde_json::Value::String(s) => s.parse().ok(),
+                _ => None,
+            })
+            .unwrap_or(0);
+        AzureToken {
+            access_token: self.access_token,
+            expires_at: Instant::now() + Duration::from_secs(expires_in),
+        }
+    }
+}
+
+/// Returns true if the URL points to Azure Blob Storage.
+fn is_azure_blob_url(url: &Url) -> bool {
+    url.scheme() == "https"
+        && url
+            .host_str()
+            .map_or(false, |host| host.ends_with(BLOB_HOST_SUFFIX))
+}
+
+/// Converts an `az://<account>/<container>/<path>` URL into the URL of the
+/// blob.
+fn azure_blob_url(url: &Url) -> Result<Url, reqwest_middleware::Error> {
+    let account = url
+        .host_str()
+        .ok_or_else(|| anyhow::anyhow!("host should be present in Azure URL"))?;
+    let mut blob_url = format!("https://{account}{BLOB_HOST_SUFFIX}{}", url.path());
+    if let Some(query) = url.query() {
+        blob_url.push('?');
+        blob_url.push_str(query);
+    }
+    Url::parse(&blob_url).map_err(reqwest_middleware::Error::middleware)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_azure_blob_url() {
+        let url = Url::parse("az://myaccount/conda/noarch/repodata.json").unwrap();
+        let blob_url = azure_blob_url(&url).unwrap();
+        assert_eq!(
+            blob_url.as_str(),
+            "https://myaccount.blob.core.windows.net/conda/noarch/repodata.json"
+        );
+        assert!(is_azure_blob_url(&blob_url));
+        assert!(!is_azure_blob_url(
+            &Url::parse("https://conda.anaconda.org/conda-forge").unwrap()
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_unavailable_token_is_cached() {
+        let middleware = AzureMiddleware::default();
+        middleware.set_unavailable();
+        // The token is not requested again, so this returns immediately.
+        assert_eq!(middleware.token().await, None);
+
+        *middleware.token.lock().unwrap() = Some(CachedToken::Token(AzureToken {
+            access_token: String::from("abc"),
+            expires_at: Instant::now() + Duration::from_secs(3600),
+        }));
+        assert_eq!(middleware.token().await.as_deref(), Some("abc"));
+    }
+
+    #[test]
+    fn test_token_response() {
+        let imds: TokenResponse =
+            serde_json::from_str(r#"{"access_token": "abc", "expires_in": "3599"}"#).unwrap();
+        let token = imds.into_token();
+        assert_eq!(token.access_token, "abc");
+        assert!(token.expires_at > Instant::now() + Duration::from_secs(3500));
+
+        let aad: TokenResponse =
+            serde_json::from_str(r#"{"access_token": "def", "expires_in": 3599}"#).unwrap();
+        assert!(aad.into_token().expires_at > Instant::now() + Duration::from_secs(3500));
+    }
+}
@@ -33,7 +33,9 @@ impl Middleware for GCSMiddleware {
     }
 }
 
-/// Auth to GCS
+/// Authenticate the request to GCS with a bearer token obtained from the
+/// ambient credentials (service account key, workload identity or the metadata
+/// server). Public buckets can be accessed without credentials.
 async fn authenticate_with_google_cloud(mut req: Request) -> MiddlewareResult<Request> {
     let audience = "https://storage.googleapis.com/";
     let scopes = [
@@ -58,6 +60,13 @@ async fn authenticate_with_google_cloud(mut req: Request) -> MiddlewareResult<Re
             }
             Err(e) => Err(reqwest_middleware::Error::Middleware(anyhow::Error::new(e))),
         },
-        Err(e) => Err(reqwest_middleware::Error::Middleware(anyhow::Error::new(e))),
+        Err(e) => {
+            // No ambient credentials are available (e.g. no service account or
+            // metadata server), try to access the bucket anonymously.
+            tracing::debug!(
+                "no Google Cloud credentials found, sending unauthenticated request: {e}"
+            );
+            Ok(req)
+        }
     }
 }
@@ -0,0 +1,299 @@
+//! Middleware to use HTTP/3 (QUIC) for hosts that support it.
+//!
+//! Support for HTTP/3 in `reqwest` is unstable, building with the `http3`
+//! feature requires `--cfg reqwest_unstable` in `RUSTFLAGS` (this is set in
+//! the `.cargo/config.toml` of the repository). The client
+//! must use the `rustls` TLS backend
+//! ([`reqwest::ClientBuilder::use_rustls_tls`]).
+use async_trait::async_trait;
+use http::Version;
+use reqwest::{header::ALT_SVC, Request, Response};
+use reqwest_middleware::{Middleware, Next, Result as MiddlewareResult};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use url::Url;
+
+/// How long an `Alt-Svc` advertisement is valid if it has no `ma` parameter
+/// (RFC 7838).
+const DEFAULT_ALT_SVC_MAX_AGE: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// How long HTTP/3 is not used for a host after a failed HTTP/3 request by
+/// default.
+const DEFAULT_FALLBACK_DURATION: Duration = Duration::from_secs(5 * 60);
+
+/// Whether a host is known to support HTTP/3.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Http3Support {
+    /// The host supports HTTP/3, until the given time if the support was
+    /// advertised.
+    Supported { expires: Option<Instant> },
+
+    /// An HTTP/3 request to the host failed, HTTP/3 is not used until the
+    /// given time.
+    Unsupported { expires: Instant },
+}
+
+impl Http3Support {
+    fn is_expired(self, now: Instant) -> bool {
+        match self {
+            Http3Support::Supported { expires } => expires.is_some_and(|expires| expires <= now),
+            Http3Support::Unsupported { expires } => expires <= now,
+        }
+    }
+}
+
+/// `reqwest` middleware that sends requests with HTTP/3 to hosts that support
+/// it.
+///
+/// A host is considered to support HTTP/3 once it advertises it with an
+/// `Alt-Svc` header in a response over HTTP/1.1 or HTTP/2, or if it was
+/// configured with [`Http3Middleware::with_hosts`]. An advertisement is valid
+/// for the `ma` (max-age) of the `Alt-Svc` header. If an HTTP/3 request
+/// fails, the request is sent again over HTTP/2 or HTTP/1.1 and HTTP/3 is not
+/// used for the host for a while (see
+/// [`Http3Middleware::with_fallback_duration`]).
+#[derive(Clone, Debug)]
+pub struct Http3Middleware {
+    hosts: Arc<Mutex<HashMap<String, Http3Support>>>,
+    fallback_duration: Duration,
+}
+
+impl Default for Http3Middleware {
+    fn default() -> Self {
+        Self {
+            hosts: Arc::default(),
+            fallback_duration: DEFAULT_FALLBACK_DURATION,
+        }
+    }
+}
+
+impl Http3Middleware {
+    /// Create a new HTTP/3 middleware
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets how long HTTP/3 is not used for a host after an HTTP/3 request to
+    /// it failed. Defaults to 5 minutes.
+    #[must_use]
+    pub fn with_fallback_duration(self, duration: Duration) -> Self {
+        Self {
+            fallback_duration: duration,
+            ..self
+        }
+    }
+
+    /// Use HTTP/3 for the given hosts right away instead of waiting for them
+    /// to advertise support. A host can include a port (`example.com:8443`),
+    /// otherwise port 443 is assumed.
+    #[must_use]
+    pub fn with_hosts(self, hosts: impl IntoIterator<Item = impl AsRef<str>>) -> Self {
+        {
+            let mut known = self.hosts.lock().unwrap();
+            for host in hosts {
+                let host = host.as_ref();
+                let key = if host.contains(':') {
+                    host.to_string()
+                } else {
+                    format!("{host}:443")
+                };
+                known.insert(key, Http3Support::Supported { expires: None });
+            }
+        }
+        self
+    }
+
+    /// Returns the HTTP/3 support of the host, forgetting it if it expired.
+    fn support(&self, key: &str) -> Option<Http3Support> {
+        let mut hosts = self.hosts.lock().unwrap();
+        let support = *hosts.get(key)?;
+        if support.is_expired(Instant::now()) {
+            hosts.remove(key);
+            return None;
+        }
+        Some(support)
+    }
+
+    /// Stops using HTTP/3 for the host for the fallback duration.
+    fn mark_unsupported(&self, key: String) {
+        let expires = Instant::now() + self.fallback_duration;
+        self.hosts
+            .lock()
+            .unwrap()
+            .insert(key, Http3Support::Unsupported { expires });
+    }
+
+    /// Records whether the host advertises HTTP/3 in the `Alt-Svc` header of
+    /// the response.
+    fn update_from_response(&self, key: &str, url: &Url, response: &Response) {
+        let Some(alt_svc) = response
+            .headers()
+            .get(ALT_SVC)
+            .and_then(|value| value.to_str().ok())
+        else {
+            return;
+        };
+        let Some(port) = url.port_or_known_default() else {
+            return;
+        };
+
+        let now = Instant::now();
+        let mut hosts = self.hosts.lock().unwrap();
+        if let Some(max_age) = advertised_http3_max_age(alt_svc, port) {
+            // Don't try again while the fallback after a failed HTTP/3
+            // request is in effect, and don't shorten a configured host.
+            match hosts.get(key) {
+                Some(&Http3Support::Unsupported { expires }) if expires > now => {}
+                Some(Http3Support::Supported { expires: None }) => {}
+                _ => {
+                    hosts.insert(
+                        key.to_string(),
+                        Http3Support::Supported {
+                            expires: Some(now + max_age),
+                        },
+                    );
+                }
+            }
+        } else if alt_svc.trim() == "clear" {
+            hosts.remove(key);
+        }
+    }
+}
+
+/// Returns the key under which the HTTP/3 support of the host of the url is
+/// stored, or `None` if HTTP/3 cannot be used for the url.
+fn host_key(url: &Url) -> Option<String> {
+    if url.scheme() != "https" {
+        return None;
+    }
+    Some(format!(
+        "{}:{}",
+        url.host_str()?,
+        url.port_or_known_default()?
+    ))
+}
+
+/// If the value of an `Alt-Svc` header advertises HTTP/3 on the same host and
+/// port (e.g. `h3=":443"; ma=86400`), returns for how long the advertisement
+/// is valid. Alternative services on other hosts or ports are ignored.
+fn advertised_http3_max_age(alt_svc: &str, port: u16) -> Option<Duration> {
+    alt_svc.split(',').find_map(|service| {
+        let mut parameters = service.split(';');
+        let (protocol, authority) = parameters.next()?.trim().split_once('=')?;
+        if protocol.trim() != "h3" || authority.trim().trim_matches('"') != format!(":{port}") {
+            return None;
+        }
+        let max_age = parameters
+            .filter_map(|parameter| parameter.trim().strip_prefix("ma="))
+            .find_map(|max_age| max_age.trim_matches('"').parse().ok())
+            .map_or(DEFAULT_ALT_SVC_MAX_AGE, Duration::from_secs);
+        Some(max_age)
+    })
+}
+
+#[async_trait]
+impl Middleware for Http3Middleware {
+    async fn handle(
+        &self,
+        req: Request,
+        extensions: &mut http::Extensions,
+        next: Next<'_>,
+    ) -> MiddlewareResult<Response> {
+        let Some(key) = host_key(req.url()) else {
+            return next.run(req, extensions).await;
+        };
+
+        if matches!(self.support(&key), Some(Http3Support::Supported { .. })) {
+            // Requests with a streaming body cannot be cloned and are not
+            // sent with HTTP/3 because they could not be sent again.
+            if let Some(mut http3_req) = req.try_clone() {
+                *http3_req.version_mut() = Version::HTTP_3;
+                match next.clone().run(http3_req, extensions).await {
+                    Ok(response) => return Ok(response),
+                    Err(err) => {
+                        tracing::debug!(
+                            "HTTP/3 request to {key} failed, falling back to HTTP/2 or HTTP/1.1: {err}"
+                        );
+                        self.mark_unsupported(key.clone());
+                    }
+                }
+            }
+        }
+
+        let url = req.url().clone();
+        let response = next.run(req, extensions).await?;
+        self.update_from_response(&key, &url, &response);
+        Ok(response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_advertised_http3_max_age() {
+        assert_eq!(
+            advertised_http3_max_age(r#"h3=":443"; ma=3600"#, 443),
+            Some(Duration::from_secs(3600))
+        );
+        assert_eq!(
+            advertised_http3_max_age(r#"h3-29=":443", h3=":443"; persist=1"#, 443),
+            Some(DEFAULT_ALT_SVC_MAX_AGE)
+        );
+        assert_eq!(advertised_http3_max_age(r#"h3=":443""#, 8443), None);
+        assert_eq!(
+            advertised_http3_max_age(r#"h3="cdn.example.com:443""#, 443),
+            None
+        );
+        assert_eq!(advertised_http3_max_age(r#"h2=":443""#, 443), None);
+        assert_eq!(advertised_http3_max_age("clear", 443), None);
+    }
+
+    #[test]
+    fn test_support_expires() {
+        let middleware = Http3Middleware::new()
+            .with_hosts(["example.com"])
+            .with_fallback_duration(Duration::ZERO);
+        let key = "example.com:443";
+        assert!(matches!(
+            middleware.support(key),
+            Some(Http3Support::Supported { expires: None })
+        ));
+
+        // After a failed request HTTP/3 is tried again once the fallback
+        // expired.
+        middleware.mark_unsupported(key.to_string());
+        assert_eq!(middleware.support(key), None);
+
+        let middleware = Http3Middleware::new().with_hosts(["example.com"]);
+        middleware.mark_unsupported(key.to_string());
+        assert!(matches!(
+            middleware.support(key),
+            Some(Http3Support::Unsupported { .. })
+        ));
+    }
+
+    #[test]
+    fn test_host_key() {
+        let middleware =
+            Http3Middleware::new().with_hosts(["conda.anaconda.org", "example.com:8443"]);
+        for url in [
+            "https://conda.anaconda.org/conda-forge/noarch/repodata.json",
+            "https://example.com:8443/channel/noarch/repodata.json",
+        ] {
+            let key = host_key(&Url::parse(url).unwrap()).unwrap();
+            assert_eq!(
+                middleware.support(&key),
+                Some(Http3Support::Supported { expires: None })
+            );
+        }
+
+        // HTTP/3 is only used for https urls.
+        assert_eq!(
+            host_key(&Url::parse("http://conda.anaconda.org/conda-forge").unwrap()),
+            None
+        );
+    }
+}
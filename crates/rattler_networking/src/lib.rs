@@ -1,19 +1,39 @@
 #![deny(missing_docs)]
 
 //! Networking utilities for Rattler, specifically authenticating requests
-pub use authentication_middleware::AuthenticationMiddleware;
+pub use authenticated_client::AuthenticatedClient;
+pub use authentication_middleware::{
+    AuthenticationMiddleware, RefreshedCredentials, TokenRefresher,
+};
 pub use authentication_storage::{authentication::Authentication, storage::AuthenticationStorage};
+pub use azure_middleware::AzureMiddleware;
 pub use mirror_middleware::MirrorMiddleware;
 pub use oci_middleware::OciMiddleware;
+#[cfg(not(target_arch = "wasm32"))]
+pub use proxy::ProxyConfig;
 
 #[cfg(feature = "google-cloud-auth")]
 pub mod gcs_middleware;
 #[cfg(feature = "google-cloud-auth")]
 pub use gcs_middleware::GCSMiddleware;
 
+#[cfg(feature = "s3")]
+pub mod s3_middleware;
+#[cfg(feature = "s3")]
+pub use s3_middleware::S3Middleware;
+
+#[cfg(feature = "http3")]
+pub mod http3_middleware;
+#[cfg(feature = "http3")]
+pub use http3_middleware::Http3Middleware;
+
+pub mod authenticated_client;
 pub mod authentication_middleware;
 pub mod authentication_storage;
+pub mod azure_middleware;
 
 pub mod mirror_middleware;
 pub mod oci_middleware;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod proxy;
 pub mod retry_policies;
@@ -102,7 +102,8 @@ fn select_mirror(mirrors: &[MirrorState]) -> Option<&MirrorState> {
     Some(&mirrors[min_failures_index])
 }
 
-#[async_trait::async_trait]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait::async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait::async_trait(?Send))]
 impl Middleware for MirrorMiddleware {
     async fn handle(
         &self,
@@ -2,11 +2,13 @@
 use std::{
     collections::HashMap,
     fmt::{Display, Formatter},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
 };
 
 use http::{
-    header::{ACCEPT, AUTHORIZATION},
-    Extensions,
+    header::{ACCEPT, AUTHORIZATION, WWW_AUTHENTICATE},
+    Extensions, HeaderValue, Method, StatusCode,
 };
 use reqwest::{Request, Response};
 use reqwest_middleware::{Middleware, Next};
@@ -20,18 +22,58 @@ enum OciMiddlewareError {
     #[error("Reqwest error: {0}")]
     Reqwest(#[from] reqwest::Error),
 
+    #[error(transparent)]
+    Middleware(#[from] reqwest_middleware::Error),
+
     #[error("URL parse error: {0}")]
     ParseError(#[from] ParseError),
 
     #[error("Layer not found")]
     LayerNotFound,
+
+    #[error("Manifest not found")]
+    ManifestNotFound,
+
+    #[error("the registry did not return a token")]
+    TokenNotFound,
+
+    #[error("image indices are nested more than {MAX_MANIFEST_INDEX_DEPTH} levels deep")]
+    ManifestIndexTooDeep,
 }
 
+/// Tokens are requested again when they expire within this margin.
+const TOKEN_EXPIRY_MARGIN: Duration = Duration::from_secs(10);
+
+/// The lifetime of a token if the registry does not specify one, see the
+/// [token specification](https://distribution.github.io/distribution/spec/auth/token/).
+/// This is also how long it is remembered that a repository does not require
+/// authentication.
+const DEFAULT_TOKEN_LIFETIME: Duration = Duration::from_secs(60);
+
+/// The maximum number of nested image indices that are followed to find the
+/// manifest of an artifact.
+const MAX_MANIFEST_INDEX_DEPTH: usize = 4;
+
 /// Middleware to handle `oci://` URLs
+///
+/// The requests to the registry (for tokens and manifests) are sent through
+/// the rest of the middleware stack, so they use the same client as the
+/// request itself. Tokens are cached per registry and scope until they expire.
 #[derive(Default, Debug, Clone)]
-pub struct OciMiddleware;
+pub struct OciMiddleware {
+    tokens: Arc<Mutex<HashMap<(String, String), CachedToken>>>,
+}
+
+/// The result of a token handshake with a registry. `token` is `None` if the
+/// registry does not require authentication.
+#[derive(Clone, Debug)]
+struct CachedToken {
+    token: Option<String>,
+    expires_at: Instant,
+}
 
 /// The action to perform on the OCI registry
+#[derive(Debug, Clone, Copy)]
 pub enum OciAction {
     /// Pull an artifact
     Pull,
@@ -41,9 +83,13 @@ pub enum OciAction {
     PushPull,
 }
 
+/// The response of a token endpoint. Registries return the token as `token`,
+/// `access_token` or both.
 #[derive(Clone, Debug, Deserialize)]
 struct OCIToken {
-    token: String,
+    token: Option<String>,
+    access_token: Option<String>,
+    expires_in: Option<u64>,
 }
 
 impl Display for OciAction {
@@ -56,19 +102,165 @@ impl Display for OciAction {
     }
 }
 
-// [oci://ghcr.io/channel-mirrors/conda-forge]/[osx-arm64/xtensor]
-async fn get_token(url: &OCIUrl, action: OciAction) -> Result<String, OciMiddlewareError> {
-    let token_url = url.token_url(action)?;
+/// The media type of an OCI image index.
+const IMAGE_INDEX_MEDIA_TYPE: &str = "application/vnd.oci.image.index.v1+json";
+
+/// The media types of manifests that list the layers of an artifact.
+const IMAGE_MANIFEST_MEDIA_TYPES: [&str; 2] = [
+    "application/vnd.oci.image.manifest.v1+json",
+    "application/vnd.docker.distribution.manifest.v2+json",
+];
+
+/// The manifest media types that are accepted when resolving a tag.
+const MANIFEST_MEDIA_TYPES: [&str; 3] = [
+    IMAGE_MANIFEST_MEDIA_TYPES[0],
+    IMAGE_INDEX_MEDIA_TYPE,
+    IMAGE_MANIFEST_MEDIA_TYPES[1],
+];
+
+/// A `Bearer` challenge from the `WWW-Authenticate` header of a registry
+/// that describes where a token can be requested.
+#[derive(Debug, PartialEq, Eq)]
+struct BearerChallenge {
+    realm: String,
+    service: Option<String>,
+    scope: Option<String>,
+}
+
+impl BearerChallenge {
+    /// Parses a challenge like
+    /// `Bearer realm="https://ghcr.io/token",service="ghcr.io",scope="repository:foo:pull"`.
+    fn parse(header: &str) -> Option<Self> {
+        let (scheme, mut rest) = header.trim().split_once(' ')?;
+        if !scheme.eq_ignore_ascii_case("bearer") {
+            return None;
+        }
+
+        let (mut realm, mut service, mut scope) = (None, None, None);
+        loop {
+            rest = rest.trim_start().trim_start_matches(',').trim_start();
+            if rest.is_empty() {
+                break;
+            }
+            let (key, value) = rest.split_once('=')?;
+            let value = value.trim_start();
+            let (value, remainder) = if let Some(quoted) = value.strip_prefix('"') {
+                let end = quoted.find('"')?;
+                (&quoted[..end], &quoted[end + 1..])
+            } else {
+                value.split_once(',').unwrap_or((value, ""))
+            };
+            match key.trim().to_ascii_lowercase().as_str() {
+                "realm" => realm = Some(value.to_string()),
+                "service" => service = Some(value.to_string()),
+                "scope" => scope = Some(value.to_string()),
+                _ => {}
+            }
+            rest = remainder;
+        }
+
+        Some(Self {
+            realm: realm?,
+            service,
+            scope,
+        })
+    }
+
+    /// Returns the URL to request a token from. If the challenge does not
+    /// specify a scope `default_scope` is requested.
+    fn token_url(&self, default_scope: &str) -> Result<Url, ParseError> {
+        let mut url = Url::parse(&self.realm)?;
+        {
+            let mut query = url.query_pairs_mut();
+            if let Some(service) = &self.service {
+                query.append_pair("service", service);
+            }
+            query.append_pair("scope", self.scope.as_deref().unwrap_or(default_scope));
+        }
+        Ok(url)
+    }
+}
+
+/// Sends a request to the registry through the rest of the middleware stack.
+async fn send(
+    next: &Next<'_>,
+    method: Method,
+    url: Url,
+    token: Option<&str>,
+) -> Result<Response, OciMiddlewareError> {
+    let mut request = Request::new(method, url);
+    request.headers_mut().insert(
+        ACCEPT,
+        HeaderValue::from_str(&MANIFEST_MEDIA_TYPES.join(", "))
+            .expect("media types are valid header values"),
+    );
+    if let Some(token) = token {
+        request
+            .headers_mut()
+            .insert(AUTHORIZATION, bearer_header(token)?);
+    }
+    Ok(next.clone().run(request, &mut Extensions::new()).await?)
+}
+
+fn bearer_header(token: &str) -> Result<HeaderValue, OciMiddlewareError> {
+    let mut value = HeaderValue::from_str(&format!("Bearer {token}"))
+        .map_err(|e| OciMiddlewareError::Middleware(reqwest_middleware::Error::middleware(e)))?;
+    value.set_sensitive(true);
+    Ok(value)
+}
+
+/// Performs the token handshake with the registry for the resource at
+/// `resource_url`.
+///
+/// The resource is first requested without credentials. If the registry
+/// responds with `401 Unauthorized` a token is requested from the realm in
+/// the `WWW-Authenticate` challenge. Registries that do not send a challenge
+/// are asked for a token at the conventional `/token` endpoint. Returns `None`
+/// if the registry does not require authentication.
+async fn get_token(
+    next: &Next<'_>,
+    url: &OCIUrl,
+    resource_url: &Url,
+    action: OciAction,
+) -> Result<CachedToken, OciMiddlewareError> {
+    let response = send(next, Method::HEAD, resource_url.clone(), None).await?;
+    if response.status() != StatusCode::UNAUTHORIZED {
+        return Ok(CachedToken {
+            token: None,
+            expires_at: Instant::now() + DEFAULT_TOKEN_LIFETIME,
+        });
+    }
+
+    let challenge = response
+        .headers()
+        .get(WWW_AUTHENTICATE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(BearerChallenge::parse);
+    let token_url = match challenge {
+        Some(challenge) => challenge.token_url(&url.scope(action))?,
+        None => url.token_url(action)?,
+    };
 
     tracing::trace!("OCI Mirror: requesting token from {}", token_url);
 
-    let token = reqwest::get(token_url)
+    let response = send(next, Method::GET, token_url, None)
         .await?
+        .error_for_status()?
         .json::<OCIToken>()
-        .await?
-        .token;
-
-    Ok(token)
+        .await?;
+    let lifetime = response
+        .expires_in
+        .map_or(DEFAULT_TOKEN_LIFETIME, Duration::from_secs);
+
+    Ok(CachedToken {
+        token: Some(
+            response
+                .token
+                .or(response.access_token)
+                .ok_or(OciMiddlewareError::TokenNotFound)?,
+        ),
+        expires_at: Instant::now() + lifetime,
+    })
 }
 
 #[derive(Debug)]
@@ -99,19 +291,25 @@ fn version_build_tag(tag: &str) -> String {
 
 impl OCIUrl {
     pub fn manifest_url(&self) -> Result<Url, ParseError> {
+        self.manifest_url_for(&self.tag)
+    }
+
+    /// Returns the URL of the manifest identified by `reference`, which is
+    /// either a tag or a digest.
+    pub fn manifest_url_for(&self, reference: &str) -> Result<Url, ParseError> {
         format!(
             "https://{}/v2/{}/manifests/{}",
-            self.host, self.path, self.tag
+            self.host, self.path, reference
         )
         .parse()
     }
 
+    pub fn scope(&self, action: OciAction) -> String {
+        format!("repository:{}:{}", self.path, action)
+    }
+
     pub fn token_url(&self, action: OciAction) -> Result<Url, ParseError> {
-        format!(
-            "https://{}/token?scope=repository:{}:{}",
-            self.host, self.path, action
-        )
-        .parse()
+        format!("https://{}/token?scope={}", self.host, self.scope(action)).parse()
     }
 
     pub fn blob_url(&self, sha256: &str) -> Result<Url, ParseError> {
@@ -169,37 +367,37 @@ impl OCIUrl {
         Ok(res)
     }
 
-    pub async fn get_blob_url(req: &mut Request) -> Result<(), OciMiddlewareError> {
+    async fn get_blob_url(
+        middleware: &OciMiddleware,
+        next: &Next<'_>,
+        req: &mut Request,
+    ) -> Result<(), OciMiddlewareError> {
         let oci_url = OCIUrl::new(req.url())?;
-        let token = get_token(&oci_url, OciAction::Pull).await?;
-
-        req.headers_mut().insert(
-            AUTHORIZATION,
-            format!("Bearer {token}")
-                .parse()
-                .expect("Could not parse token header"),
-        );
 
         // if we know the hash, we can pull the artifact directly
         // if we don't, we need to pull the manifest and then pull the artifact
-        if let Some(expected_sha_hash) = req
+        let expected_sha_hash = req
             .headers()
             .get("X-Expected-Sha256")
             .and_then(|s| s.to_str().ok())
-        {
-            *req.url_mut() = oci_url.blob_url(&format!("sha256:{expected_sha_hash}"))?;
-        } else {
-            // get the tag from the URL retrieve the manifest
-            let manifest_url = oci_url.manifest_url()?; // TODO: handle error
-
-            let manifest = reqwest::Client::new()
-                .get(manifest_url)
-                .header(AUTHORIZATION, format!("Bearer {token}"))
-                .header(ACCEPT, "application/vnd.oci.image.manifest.v1+json")
-                .send()
-                .await?;
+            .map(ToOwned::to_owned);
+        let resource_url = match &expected_sha_hash {
+            Some(expected_sha_hash) => oci_url.blob_url(&format!("sha256:{expected_sha_hash}"))?,
+            None => oci_url.manifest_url()?,
+        };
 
-            let manifest: Manifest = manifest.json().await?;
+        let token = middleware
+            .token(next, &oci_url, &resource_url, OciAction::Pull)
+            .await?;
+        if let Some(token) = &token {
+            req.headers_mut()
+                .insert(AUTHORIZATION, bearer_header(token)?);
+        }
+
+        if expected_sha_hash.is_none() {
+            let manifest = oci_url
+                .fetch_manifest(next, resource_url, token.as_deref())
+                .await?;
 
             let layer = if let Some(layer) = manifest
                 .layers
@@ -212,10 +410,60 @@ impl OCIUrl {
             };
 
             *req.url_mut() = oci_url.blob_url(&layer.digest)?;
+        } else {
+            *req.url_mut() = resource_url;
         }
 
         Ok(())
     }
+
+    /// Fetches the manifest at `manifest_url`. If the registry returns an
+    /// image index instead of a manifest, the manifest for the media type of
+    /// the artifact is selected from the index, see [`select_manifest`].
+    async fn fetch_manifest(
+        &self,
+        next: &Next<'_>,
+        manifest_url: Url,
+        token: Option<&str>,
+    ) -> Result<Manifest, OciMiddlewareError> {
+        let mut manifest_url = manifest_url;
+        for _ in 0..=MAX_MANIFEST_INDEX_DEPTH {
+            let response = send(next, Method::GET, manifest_url, token).await?;
+            if response.status() == StatusCode::NOT_FOUND {
+                return Err(OciMiddlewareError::ManifestNotFound);
+            }
+            let manifest: Manifest = response.error_for_status()?.json().await?;
+            if manifest.manifests.is_empty() {
+                return Ok(manifest);
+            }
+
+            let entry = select_manifest(&manifest.manifests, &self.media_type)
+                .ok_or(OciMiddlewareError::ManifestNotFound)?;
+            manifest_url = self.manifest_url_for(&entry.digest)?;
+        }
+        Err(OciMiddlewareError::ManifestIndexTooDeep)
+    }
+}
+
+/// Selects the manifest of the artifact with layers of `media_type` from the
+/// entries of an image index. Entries that declare `media_type` as their
+/// artifact type are preferred over image manifests without an artifact type.
+/// Nested image indices are only selected if there is no such manifest.
+fn select_manifest<'a>(manifests: &'a [Layer], media_type: &str) -> Option<&'a Layer> {
+    manifests
+        .iter()
+        .find(|entry| entry.artifact_type.as_deref() == Some(media_type))
+        .or_else(|| {
+            manifests.iter().find(|entry| {
+                entry.artifact_type.is_none()
+                    && IMAGE_MANIFEST_MEDIA_TYPES.contains(&entry.media_type.as_str())
+            })
+        })
+        .or_else(|| {
+            manifests
+                .iter()
+                .find(|entry| entry.media_type == IMAGE_INDEX_MEDIA_TYPE)
+        })
 }
 
 #[allow(dead_code)]
@@ -224,21 +472,55 @@ struct Layer {
     digest: String,
     #[serde(rename = "mediaType")]
     media_type: String,
+    #[serde(rename = "artifactType")]
+    artifact_type: Option<String>,
     size: u64,
     annotations: Option<HashMap<String, String>>,
 }
 
+/// An OCI image manifest or image index. A manifest lists the `layers` of
+/// an artifact, an index lists the `manifests` of the artifact.
 #[allow(dead_code)]
 #[derive(Debug, Deserialize)]
 #[serde(rename_all = "camelCase")]
 struct Manifest {
     schema_version: u64,
+    #[serde(default)]
     layers: Vec<Layer>,
-    config: Layer,
+    #[serde(default)]
+    manifests: Vec<Layer>,
+    config: Option<Layer>,
     annotations: Option<HashMap<String, String>>,
 }
 
-#[async_trait::async_trait]
+impl OciMiddleware {
+    /// Returns the token for the repository of `url`, performing the token
+    /// handshake if there is no cached token or the token is about to
+    /// expire.
+    async fn token(
+        &self,
+        next: &Next<'_>,
+        url: &OCIUrl,
+        resource_url: &Url,
+        action: OciAction,
+    ) -> Result<Option<String>, OciMiddlewareError> {
+        let key = (url.host.clone(), url.scope(action));
+        let cached = self.tokens.lock().unwrap().get(&key).cloned();
+        if let Some(cached) = cached {
+            if cached.expires_at > Instant::now() + TOKEN_EXPIRY_MARGIN {
+                return Ok(cached.token);
+            }
+        }
+
+        let cached = get_token(next, url, resource_url, action).await?;
+        let token = cached.token.clone();
+        self.tokens.lock().unwrap().insert(key, cached);
+        Ok(token)
+    }
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), async_trait::async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait::async_trait(?Send))]
 impl Middleware for OciMiddleware {
     async fn handle(
         &self,
@@ -259,7 +541,7 @@ impl Middleware for OciMiddleware {
             ));
         }
 
-        let res = OCIUrl::get_blob_url(&mut req).await;
+        let res = OCIUrl::get_blob_url(self, &next, &mut req).await;
 
         match res {
             Ok(_) => next.run(req, extensions).await,
@@ -270,6 +552,12 @@ impl Middleware for OciMiddleware {
                         "No layer available for media type",
                     ));
                 }
+                OciMiddlewareError::ManifestNotFound => {
+                    return Ok(create_404_response(
+                        req.url(),
+                        "No manifest available for tag",
+                    ));
+                }
                 _ => {
                     return Err(reqwest_middleware::Error::Middleware(e.into()));
                 }
@@ -282,13 +570,106 @@ impl Middleware for OciMiddleware {
 mod tests {
     use sha2::{Digest, Sha256};
 
+    use super::{select_manifest, BearerChallenge, Layer, OCIToken};
     use crate::OciMiddleware;
 
+    #[test]
+    fn test_parse_bearer_challenge() {
+        let challenge = BearerChallenge::parse(
+            r#"Bearer realm="https://ghcr.io/token",service="ghcr.io",scope="repository:channel-mirrors/conda-forge/xtensor:pull""#,
+        )
+        .unwrap();
+        assert_eq!(
+            challenge,
+            BearerChallenge {
+                realm: "https://ghcr.io/token".to_string(),
+                service: Some("ghcr.io".to_string()),
+                scope: Some("repository:channel-mirrors/conda-forge/xtensor:pull".to_string()),
+            }
+        );
+        assert_eq!(
+            challenge.token_url("unused").unwrap().as_str(),
+            "https://ghcr.io/token?service=ghcr.io&scope=repository%3Achannel-mirrors%2Fconda-forge%2Fxtensor%3Apull"
+        );
+
+        let challenge = BearerChallenge::parse(
+            r#"bearer realm="https://auth.docker.io/token", service=registry.docker.io"#,
+        )
+        .unwrap();
+        assert_eq!(challenge.service.as_deref(), Some("registry.docker.io"));
+        assert_eq!(challenge.scope, None);
+        assert_eq!(
+            challenge.token_url("repository:foo:push,pull").unwrap().as_str(),
+            "https://auth.docker.io/token?service=registry.docker.io&scope=repository%3Afoo%3Apush%2Cpull"
+        );
+
+        assert_eq!(BearerChallenge::parse(r#"Basic realm="registry""#), None);
+        assert_eq!(BearerChallenge::parse(r#"Bearer service="ghcr.io""#), None);
+    }
+
+    #[test]
+    fn test_select_manifest() {
+        let entry = |media_type: &str, artifact_type: Option<&str>, digest: &str| Layer {
+            digest: digest.to_string(),
+            media_type: media_type.to_string(),
+            artifact_type: artifact_type.map(ToString::to_string),
+            size: 0,
+            annotations: None,
+        };
+        let manifests = [
+            entry("application/vnd.oci.image.index.v1+json", None, "index"),
+            entry(
+                "application/vnd.oci.image.manifest.v1+json",
+                Some("application/vnd.example.signature"),
+                "signature",
+            ),
+            entry("application/vnd.oci.image.manifest.v1+json", None, "image"),
+            entry(
+                "application/vnd.oci.image.manifest.v1+json",
+                Some("application/vnd.conda.package.v2"),
+                "conda",
+            ),
+        ];
+
+        let select = |manifests: &[Layer], media_type| {
+            select_manifest(manifests, media_type).map(|entry| entry.digest.clone())
+        };
+        assert_eq!(
+            select(&manifests, "application/vnd.conda.package.v2").as_deref(),
+            Some("conda")
+        );
+        assert_eq!(
+            select(&manifests, "application/vnd.conda.package.v1").as_deref(),
+            Some("image")
+        );
+        assert_eq!(
+            select(&manifests[..2], "application/vnd.conda.package.v1").as_deref(),
+            Some("index")
+        );
+        assert_eq!(
+            select(&manifests[1..2], "application/vnd.conda.package.v1"),
+            None
+        );
+    }
+
+    #[test]
+    fn test_parse_token() {
+        let token: OCIToken =
+            serde_json::from_str(r#"{"token": "abc", "access_token": "abc", "expires_in": 300}"#)
+                .unwrap();
+        assert_eq!(token.token.as_deref(), Some("abc"));
+        assert_eq!(token.expires_in, Some(300));
+
+        let token: OCIToken = serde_json::from_str(r#"{"access_token": "def"}"#).unwrap();
+        assert_eq!(token.access_token.as_deref(), Some("def"));
+        assert_eq!(token.expires_in, None);
+    }
+
     // test pulling an image from OCI registry
     #[cfg(any(feature = "rustls-tls", feature = "native-tls"))]
     #[tokio::test]
     async fn test_oci_middleware() {
-        let middleware = OciMiddleware;
+        let middleware = OciMiddleware::default();
 
         let client = reqwest::Client::new();
         let client_with_middleware = reqwest_middleware::ClientBuilder::new(client)
@@ -321,7 +702,7 @@ mod tests {
     #[cfg(any(feature = "rustls-tls", feature = "native-tls"))]
     #[tokio::test]
     async fn test_oci_middleware_repodata() {
-        let middleware = OciMiddleware;
+        let middleware = OciMiddleware::default();
 
         let client = reqwest::Client::new();
         let client_with_middleware = reqwest_middleware::ClientBuilder::new(client)
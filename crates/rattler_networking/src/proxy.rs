@@ -0,0 +1,189 @@
+//! Configuration of the proxies that are used for network requests.
+//!
+//! By default `reqwest` picks up the proxies from the `HTTP_PROXY`,
+//! `HTTPS_PROXY`, `ALL_PROXY` and `NO_PROXY` environment variables. A
+//! [`ProxyConfig`] allows configuring the proxies explicitly instead, e.g. from
+//! a configuration file, and applying them to a [`reqwest::ClientBuilder`].
+
+use reqwest::{ClientBuilder, NoProxy, Proxy};
+use url::Url;
+
+/// The proxies that are used for network requests.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ProxyConfig {
+    /// The proxy that is used for `http://` urls.
+    pub http: Option<Url>,
+
+    /// The proxy that is used for `https://` urls.
+    pub https: Option<Url>,
+
+    /// The proxy that is used for urls without a scheme specific proxy. This
+    /// can also be a `socks5://` or `socks5h://` proxy, which requires the
+    /// `socks` feature.
+    pub all: Option<Url>,
+
+    /// Hosts that are accessed without a proxy. An entry can be a domain
+    /// (which also matches its subdomains), an IP address or an IP range in
+    /// CIDR notation. The entry `*` disables the proxies for all hosts.
+    pub no_proxy: Vec<String>,
+}
+
+impl ProxyConfig {
+    /// Reads the proxy configuration from the `HTTPS_PROXY`, `HTTP_PROXY`,
+    /// `ALL_PROXY` and `NO_PROXY` environment variables (or their lowercase
+    /// variants). Variables that do not contain a valid url are ignored.
+    pub fn from_env() -> Self {
+        Self::from_env_with(|name| std::env::var(name).ok())
+    }
+
+    fn from_env_with(var: impl Fn(&str) -> Option<String>) -> Self {
+        let get = |name: &str| {
+            var(name)
+                .or_else(|| var(&name.to_ascii_lowercase()))
+                .filter(|value| !value.is_empty())
+        };
+        let get_url = |name: &str| {
+            let value = get(name)?;
+            match Url::parse(&value) {
+                Ok(url) => Some(url),
+                Err(e) => {
+                    tracing::warn!("ignoring invalid proxy url in {name}: {e}");
+                    None
+                }
+            }
+        };
+
+        Self {
+            http: get_url("HTTP_PROXY"),
+            https: get_url("HTTPS_PROXY"),
+            all: get_url("ALL_PROXY"),
+            no_proxy: get("NO_PROXY")
+                .map(|no_proxy| {
+                    no_proxy
+                        .split(',')
+                        .map(str::trim)
+                        .filter(|host| !host.is_empty())
+                        .map(ToOwned::to_owned)
+                        .collect()
+                })
+                .unwrap_or_default(),
+        }
+    }
+
+    /// Returns true if no proxy is configured.
+    pub fn is_empty(&self) -> bool {
+        self.http.is_none() && self.https.is_none() && self.all.is_none()
+    }
+
+    /// Returns the configured proxies.
+    pub fn proxies(&self) -> Result<Vec<Proxy>, reqwest::Error> {
+        let no_proxy = || NoProxy::from_string(&self.no_proxy.join(","));
+        let mut proxies = Vec::new();
+        if let Some(http) = &self.http {
+            proxies.push(Proxy::http(http.as_str())?.no_proxy(no_proxy()));
+        }
+        if let Some(https) = &self.https {
+            proxies.push(Proxy::https(https.as_str())?.no_proxy(no_proxy()));
+        }
+        if let Some(all) = &self.all {
+            proxies.push(Proxy::all(all.as_str())?.no_proxy(no_proxy()));
+        }
+        Ok(proxies)
+    }
+
+    /// Configures the client to use the proxies. If any proxy is configured,
+    /// the proxies from the environment are no longer used.
+    pub fn apply(&self, builder: ClientBuilder) -> Result<ClientBuilder, reqwest::Error> {
+        if self.is_empty() {
+            return Ok(builder);
+        }
+        Ok(self
+            .proxies()?
+            .into_iter()
+            .fold(builder.no_proxy(), ClientBuilder::proxy))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, future::IntoFuture};
+
+    use axum::http::Uri;
+
+    use super::*;
+
+    async fn serve(app: axum::Router) -> String {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(axum::serve(listener, app).into_future());
+        format!("http://{addr}")
+    }
+
+    #[test]
+    fn test_from_env() {
+        let env = HashMap::from([
+            ("https_proxy", "http://proxy.corp:3128"),
+            ("ALL_PROXY", "socks5://proxy.corp:1080"),
+            ("NO_PROXY", "localhost, .internal.corp,,10.0.0.0/8"),
+            ("HTTP_PROXY", "not a url"),
+        ]);
+        let config = ProxyConfig::from_env_with(|name| env.get(name).map(ToString::to_string));
+
+        assert_eq!(config.http, None);
+        assert_eq!(
+            config.https,
+            Some(Url::parse("http://proxy.corp:3128").unwrap())
+        );
+        assert_eq!(
+            config.all,
+            Some(Url::parse("socks5://proxy.corp:1080").unwrap())
+        );
+        assert_eq!(
+            config.no_proxy,
+            vec!["localhost", ".internal.corp", "10.0.0.0/8"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_proxy() {
+        // The proxy responds with the (absolute) url that was requested through it.
+        let proxy =
+            serve(axum::Router::new().fallback(|uri: Uri| async move { format!("proxy {uri}") }))
+                .await;
+        let direct = serve(axum::Router::new().fallback(|| async { "direct" })).await;
+
+        let config = ProxyConfig {
+            http: Some(Url::parse(&proxy).unwrap()),
+            no_proxy: vec!["127.0.0.1".to_string()],
+            ..ProxyConfig::default()
+        };
+        let client = config
+            .apply(reqwest::Client::builder())
+            .unwrap()
+            .build()
+            .unwrap();
+
+        let body = client
+            .get("http://conda.example.com/conda-forge/noarch/repodata.json")
+            .send()
+            .await
+            .unwrap()
+            .text()
+            .await
+            .unwrap();
+        assert_eq!(
+            body,
+            "proxy http://conda.example.com/conda-forge/noarch/repodata.json"
+        );
+
+        let body = client
+            .get(&direct)
+            .send()
+            .await
+            .unwrap()
+            .text()
+            .await
+            .unwrap();
+        assert_eq!(body, "direct");
+    }
+}
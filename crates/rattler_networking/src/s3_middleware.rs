@@ -0,0 +1,231 @@
+//! Middleware to handle `s3://` URLs to pull artifacts from an S3 bucket (or
+//! an S3 compatible endpoint).
+//!
+//! Requests are rewritten to presigned `https://` URLs. The presigned URLs are
+//! signed with `SigV4` using credentials from the standard AWS credential chain
+//! (environment variables, shared config and credential profiles, web
+//! identity tokens and the instance metadata service).
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use anyhow::anyhow;
+use async_trait::async_trait;
+use aws_config::{BehaviorVersion, Region};
+use aws_sdk_s3::presigning::PresigningConfig;
+use reqwest::{Method, Request, Response};
+use reqwest_middleware::{Middleware, Next, Result as MiddlewareResult};
+use url::Url;
+
+/// The configuration used to access a specific bucket.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum S3Config {
+    /// Use the endpoint and region from the standard AWS configuration.
+    FromAWS,
+
+    /// Use a custom (S3 compatible) endpoint.
+    Custom {
+        /// The URL of the endpoint, e.g. `https://my-minio.example.com`.
+        endpoint_url: Url,
+        /// The region of the bucket.
+        region: String,
+        /// Whether to address the bucket as part of the path instead of as a
+        /// subdomain of the endpoint.
+        force_path_style: bool,
+    },
+}
+
+/// `reqwest` middleware that converts `s3://bucket/key` URLs into presigned
+/// `https://` URLs.
+#[derive(Clone, Debug)]
+pub struct S3Middleware {
+    config: HashMap<String, S3Config>,
+    expiration: Duration,
+
+    /// The clients that have been constructed so far. Buckets that share a
+    /// configuration share a client.
+    clients: Arc<Mutex<HashMap<S3Config, aws_sdk_s3::Client>>>,
+}
+
+impl Default for S3Middleware {
+    fn default() -> Self {
+        Self::new(HashMap::new())
+    }
+}
+
+impl S3Middleware {
+    /// Create a new S3 middleware with a specific configuration per bucket.
+    /// Buckets that are not present in `config` use [`S3Config::FromAWS`].
+    pub fn new(config: HashMap<String, S3Config>) -> Self {
+        Self {
+            config,
+            expiration: Duration::from_secs(300),
+            clients: Arc::default(),
+        }
+    }
+
+    /// Sets the duration for which presigned URLs are valid. Defaults to 5
+    /// minutes.
+    #[must_use]
+    pub fn with_expiration(self, expiration: Duration) -> Self {
+        Self { expiration, ..self }
+    }
+
+    /// Returns the S3 client for the given bucket. Loading the AWS
+    /// configuration is expensive so clients are constructed once per
+    /// configuration and reused for subsequent requests. The clients refresh
+    /// their credentials by themselves.
+    async fn s3_client(&self, bucket: &str) -> aws_sdk_s3::Client {
+        let config = self
+            .config
+            .get(bucket)
+            .cloned()
+            .unwrap_or(S3Config::FromAWS);
+        if let Some(client) = self.clients.lock().unwrap().get(&config) {
+            return client.clone();
+        }
+
+        let client = Self::create_s3_client(&config).await;
+        self.clients
+            .lock()
+            .unwrap()
+            .entry(config)
+            .or_insert(client)
+            .clone()
+    }
+
+    /// Constructs an S3 client for the given configuration.
+    async fn create_s3_client(config: &S3Config) -> aws_sdk_s3::Client {
+        match config {
+            S3Config::Custom {
+                endpoint_url,
+                region,
+                force_path_style,
+            } => {
+                let sdk_config = aws_config::defaults(BehaviorVersion::latest())
+                    .region(Region::new(region.clone()))
+                    .endpoint_url(endpoint_url.as_str())
+                    .load()
+                    .await;
+                let s3_config = aws_sdk_s3::config::Builder::from(&sdk_config)
+                    .force_path_style(*force_path_style)
+                    .build();
+                aws_sdk_s3::Client::from_conf(s3_config)
+            }
+            S3Config::FromAWS => {
+                let sdk_config = aws_config::defaults(BehaviorVersion::latest()).load().await;
+                aws_sdk_s3::Client::new(&sdk_config)
+            }
+        }
+    }
+
+    /// Generates a presigned URL for the given `s3://` URL. `HEAD` requests
+    /// need a different signature than `GET` requests so the method of the
+    /// request is taken into account.
+    pub async fn generate_presigned_s3_url(
+        &self,
+        url: &Url,
+        method: &Method,
+    ) -> MiddlewareResult<Url> {
+        let bucket = url
+            .host_str()
+            .ok_or_else(|| anyhow!("host should be present in S3 URL"))?;
+        let key = url
+            .path()
+            .strip_prefix('/')
+            .ok_or_else(|| anyhow!("invalid S3 path"))?;
+
+        let client = self.s3_client(bucket).await;
+        let presigning_config = PresigningConfig::expires_in(self.expiration)
+            .map_err(reqwest_middleware::Error::middleware)?;
+
+        let presigned = if *method == Method::HEAD {
+            client
+                .head_object()
+                .bucket(bucket)
+                .key(key)
+                .presigned(presigning_config)
+                .await
+        } else {
+            client
+                .get_object()
+                .bucket(bucket)
+                .key(key)
+                .presigned(presigning_config)
+                .await
+        }
+        .map_err(reqwest_middleware::Error::middleware)?;
+
+        Url::parse(presigned.uri()).map_err(reqwest_middleware::Error::middleware)
+    }
+}
+
+#[async_trait]
+impl Middleware for S3Middleware {
+    async fn handle(
+        &self,
+        mut req: Request,
+        extensions: &mut http::Extensions,
+        next: Next<'_>,
+    ) -> MiddlewareResult<Response> {
+        if req.url().scheme() == "s3" {
+            let url = self
+                .generate_presigned_s3_url(req.url(), req.method())
+                .await?;
+            *req.url_mut() = url;
+        }
+        next.run(req, extensions).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_presigned_url_custom_endpoint() {
+        temp_env::async_with_vars(
+            [
+                ("AWS_ACCESS_KEY_ID", Some("minioadmin")),
+                ("AWS_SECRET_ACCESS_KEY", Some("minioadmin")),
+            ],
+            async {
+                let middleware = S3Middleware::new(HashMap::from([(
+                    String::from("my-channel"),
+                    S3Config::Custom {
+                        endpoint_url: Url::parse("http://localhost:9000").unwrap(),
+                        region: String::from("eu-central-1"),
+                        force_path_style: true,
+                    },
+                )]));
+
+                let url = middleware
+                    .generate_presigned_s3_url(
+                        &Url::parse("s3://my-channel/noarch/repodata.json").unwrap(),
+                        &Method::GET,
+                    )
+                    .await
+                    .unwrap();
+
+                assert_eq!(url.host_str(), Some("localhost"));
+                assert_eq!(url.path(), "/my-channel/noarch/repodata.json");
+                assert!(url
+                    .query_pairs()
+                    .any(|(key, value)| key == "X-Amz-Algorithm" && value == "AWS4-HMAC-SHA256"));
+
+                // The client is constructed once and reused for other requests.
+                middleware
+                    .generate_presigned_s3_url(
+                        &Url::parse("s3://my-channel/noarch/repodata.json.zst").unwrap(),
+                        &Method::HEAD,
+                    )
+                    .await
+                    .unwrap();
+                assert_eq!(middleware.clients.lock().unwrap().len(), 1);
+            },
+        )
+        .await;
+    }
+}
@@ -4,6 +4,14 @@ use crate::{ExtractError, ExtractResult};
 use rattler_conda_types::package::ArchiveType;
 use std::fs::File;
 use std::path::Path;
+use tracing::{field::Empty, instrument, Span};
+
+/// Opens the archive and records its size on the current span.
+fn open_archive(archive: &Path) -> Result<File, ExtractError> {
+    let file = File::open(archive)?;
+    Span::current().record("bytes", file.metadata()?.len());
+    Ok(file)
+}
 
 /// Extracts the contents a `.tar.bz2` package archive at the specified path to a directory.
 ///
@@ -15,8 +23,9 @@ use std::path::Path;
 ///     Path::new("/tmp"))
 ///     .unwrap();
 /// ```
+#[instrument(skip_all, fields(path = %archive.display(), bytes = Empty))]
 pub fn extract_tar_bz2(archive: &Path, destination: &Path) -> Result<ExtractResult, ExtractError> {
-    let file = File::open(archive)?;
+    let file = open_archive(archive)?;
     crate::read::extract_tar_bz2(file, destination)
 }
 
@@ -30,8 +39,9 @@ pub fn extract_tar_bz2(archive: &Path, destination: &Path) -> Result<ExtractResu
 ///     Path::new("/tmp"))
 ///     .unwrap();
 /// ```
+#[instrument(skip_all, fields(path = %archive.display(), bytes = Empty))]
 pub fn extract_conda(archive: &Path, destination: &Path) -> Result<ExtractResult, ExtractError> {
-    let file = File::open(archive)?;
+    let file = open_archive(archive)?;
     crate::read::extract_conda_via_streaming(file, destination)
 }
 
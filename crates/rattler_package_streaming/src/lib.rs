@@ -6,6 +6,7 @@ use std::path::PathBuf;
 use zip::result::ZipError;
 
 use rattler_digest::{Md5Hash, Sha256Hash};
+use rattler_error::ErrorCode;
 
 #[cfg(feature = "reqwest")]
 use rattler_redaction::Redact;
@@ -53,6 +54,27 @@ pub enum ExtractError {
     ArchiveMemberParseError(PathBuf, #[source] std::io::Error),
 }
 
+impl ErrorCode for ExtractError {
+    fn code(&self) -> &'static str {
+        match self {
+            ExtractError::IoError(_) => "rattler::extract::io",
+            ExtractError::CouldNotCreateDestination(_) => {
+                "rattler::extract::could_not_create_destination"
+            }
+            ExtractError::ZipError(_) => "rattler::extract::zip",
+            ExtractError::MissingComponent => "rattler::extract::missing_component",
+            ExtractError::UnsupportedCompressionMethod => {
+                "rattler::extract::unsupported_compression_method"
+            }
+            #[cfg(feature = "reqwest")]
+            ExtractError::ReqwestError(_) => "rattler::extract::http",
+            ExtractError::UnsupportedArchiveType => "rattler::extract::unsupported_archive_type",
+            ExtractError::Cancelled => "rattler::extract::cancelled",
+            ExtractError::ArchiveMemberParseError(..) => "rattler::extract::archive_member_parse",
+        }
+    }
+}
+
 impl From<ZipError> for ExtractError {
     fn from(value: ZipError) -> Self {
         match value {
@@ -7,6 +7,7 @@ use std::io::{copy, Seek, SeekFrom};
 use std::mem::ManuallyDrop;
 use std::{ffi::OsStr, io::Read, path::Path};
 use tempfile::SpooledTempFile;
+use tracing::instrument;
 use zip::read::{read_zipfile_from_stream, ZipArchive, ZipFile};
 
 /// Returns the `.tar.bz2` as a decompressed `tar::Archive`. The `tar::Archive` can be used to
@@ -24,6 +25,7 @@ pub(crate) fn stream_tar_zst(
 }
 
 /// Extracts the contents a `.tar.bz2` package archive.
+#[instrument(skip_all, fields(path = %destination.display()))]
 pub fn extract_tar_bz2(
     reader: impl Read,
     destination: &Path,
@@ -47,6 +49,7 @@ pub fn extract_tar_bz2(
 }
 
 /// Extracts the contents of a `.conda` package archive.
+#[instrument(skip_all, fields(path = %destination.display()))]
 pub fn extract_conda_via_streaming(
     reader: impl Read,
     destination: &Path,
@@ -68,6 +71,7 @@ pub fn extract_conda_via_streaming(
 }
 
 /// Extracts the contents of a .conda package archive by fully reading the stream and then decompressing
+#[instrument(skip_all, fields(path = %destination.display()))]
 pub fn extract_conda_via_buffering(
     reader: impl Read,
     destination: &Path,
@@ -5,13 +5,14 @@ use crate::{DownloadReporter, ExtractError, ExtractResult};
 use futures_util::stream::TryStreamExt;
 use rattler_conda_types::package::ArchiveType;
 use rattler_digest::Sha256Hash;
+use rattler_redaction::Redact;
 use reqwest::Response;
 use std::path::Path;
 use std::sync::Arc;
 use tokio::io::BufReader;
 use tokio_util::either::Either;
 use tokio_util::io::StreamReader;
-use tracing;
+use tracing::instrument;
 use url::Url;
 use zip::result::ZipError;
 
@@ -188,6 +189,10 @@ pub async fn extract_conda(
 ///     .unwrap();
 /// # }
 /// ```
+#[instrument(
+    skip_all,
+    fields(url = %url.clone().redact(), path = %destination.display())
+)]
 pub async fn extract(
     client: reqwest_middleware::ClientWithMiddleware,
     url: Url,
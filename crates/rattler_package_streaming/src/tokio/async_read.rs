@@ -5,6 +5,7 @@ use std::{io::Read, path::Path};
 
 use tokio::io::AsyncRead;
 use tokio_util::io::SyncIoBridge;
+use tracing::Span;
 
 use crate::{ExtractError, ExtractResult};
 
@@ -18,8 +19,11 @@ pub async fn extract_tar_bz2(
 
     // Spawn a block task to perform the extraction
     let destination = destination.to_owned();
-    match tokio::task::spawn_blocking(move || crate::read::extract_tar_bz2(reader, &destination))
-        .await
+    let span = Span::current();
+    match tokio::task::spawn_blocking(move || {
+        span.in_scope(|| crate::read::extract_tar_bz2(reader, &destination))
+    })
+    .await
     {
         Ok(result) => result,
         Err(err) => {
@@ -71,9 +75,10 @@ async fn extract_conda_internal(
 
     // Spawn a block task to perform the extraction
     let destination = destination.to_owned();
+    let span = Span::current();
     tokio::task::spawn_blocking(move || {
         let reader: Box<dyn Read> = Box::new(reader);
-        extract_fn(reader, &destination)
+        span.in_scope(|| extract_fn(reader, &destination))
     })
     .await
     .unwrap_or_else(|err| {
@@ -3,6 +3,7 @@
 use crate::{ExtractError, ExtractResult};
 use rattler_conda_types::package::ArchiveType;
 use std::path::Path;
+use tracing::Span;
 
 /// Extracts the contents a `.tar.bz2` package archive at the specified path to a directory.
 ///
@@ -25,8 +26,11 @@ pub async fn extract_tar_bz2(
     // Spawn a block task to perform the extraction
     let destination = destination.to_owned();
     let archive = archive.to_owned();
-    match tokio::task::spawn_blocking(move || crate::fs::extract_tar_bz2(&archive, &destination))
-        .await
+    let span = Span::current();
+    match tokio::task::spawn_blocking(move || {
+        span.in_scope(|| crate::fs::extract_tar_bz2(&archive, &destination))
+    })
+    .await
     {
         Ok(result) => result,
         Err(err) => {
@@ -59,8 +63,11 @@ pub async fn extract_conda(
     // Spawn a block task to perform the extraction
     let destination = destination.to_owned();
     let archive = archive.to_owned();
-    match tokio::task::spawn_blocking(move || crate::fs::extract_conda(&archive, &destination))
-        .await
+    let span = Span::current();
+    match tokio::task::spawn_blocking(move || {
+        span.in_scope(|| crate::fs::extract_conda(&archive, &destination))
+    })
+    .await
     {
         Ok(result) => result,
         Err(err) => {
@@ -0,0 +1,268 @@
+//! Blocking versions of the asynchronous APIs of this crate.
+//!
+//! The functions and types in this module drive the asynchronous APIs to
+//! completion on a runtime that is managed by this crate. This allows using
+//! them from code that is not async, like CLI tools or build scripts, without
+//! having to set up a runtime.
+//!
+//! The functions in this module must not be called from within an async
+//! runtime, they will panic if they are.
+//!
+//! Solving is already synchronous, so a complete fetch and solve pipeline
+//! looks like this:
+//!
+//! ```no_run
+//! # #[cfg(feature = "gateway")]
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! use rattler_conda_types::{Channel, ChannelConfig, MatchSpec, ParseStrictness, Platform};
+//! use rattler_repodata_gateway::blocking::Gateway;
+//!
+//! let channel_config = ChannelConfig::default_with_root_dir(std::env::current_dir()?);
+//! let channel = Channel::from_str("conda-forge", &channel_config)?;
+//! let spec = MatchSpec::from_str("python >=3.12", ParseStrictness::Strict)?;
+//!
+//! let repo_data = Gateway::new()
+//!     .query([channel], [Platform::current(), Platform::NoArch], [spec])
+//!     .recursive(true)
+//!     .execute()?;
+//!
+//! // Pass `repo_data` to a solver from `rattler_solve`.
+//! # Ok(())
+//! # }
+//! # #[cfg(not(feature = "gateway"))]
+//! # fn main() {}
+//! ```
+
+use std::{
+    future::Future,
+    path::PathBuf,
+    sync::{Arc, OnceLock},
+};
+
+use tokio::runtime::{Handle, Runtime};
+use url::Url;
+
+use crate::{
+    fetch::{CachedRepoData, FetchRepoDataError, FetchRepoDataOptions},
+    Reporter,
+};
+
+/// Returns the runtime that drives all blocking operations.
+fn runtime() -> &'static Runtime {
+    static RUNTIME: OnceLock<Runtime> = OnceLock::new();
+    RUNTIME.get_or_init(|| {
+        tokio::runtime::Builder::new_multi_thread()
+            .enable_all()
+            .thread_name("rattler-blocking")
+            .build()
+            .expect("failed to create the tokio runtime")
+    })
+}
+
+/// Runs the future to completion on the runtime of this module.
+pub(crate) fn block_on<F: Future>(future: F) -> F::Output {
+    assert!(
+        Handle::try_current().is_err(),
+        "blocking functions cannot be called from within an async runtime, use the async API instead"
+    );
+    runtime().block_on(future)
+}
+
+/// Blocking version of [`crate::fetch::fetch_repo_data`].
+pub fn fetch_repo_data(
+    subdir_url: Url,
+    client: impl Into<reqwest_middleware::ClientWithMiddleware>,
+    cache_path: PathBuf,
+    options: FetchRepoDataOptions,
+    reporter: Option<Arc<dyn Reporter>>,
+) -> Result<CachedRepoData, FetchRepoDataError> {
+    block_on(crate::fetch::fetch_repo_data(
+        subdir_url, client, cache_path, options, reporter,
+    ))
+}
+
+#[cfg(feature = "gateway")]
+mod gateway {
+    use rattler_conda_types::{Channel, MatchSpec, PackageName, Platform};
+
+    use super::block_on;
+    use crate::{GatewayError, RepoData, Reporter, SubdirSelection};
+
+    /// Blocking version of [`crate::Gateway`].
+    ///
+    /// Like the async gateway, this type is cheaply clonable and can be shared
+    /// between threads. Use [`crate::Gateway::builder`] to configure a gateway
+    /// and convert it with [`From`].
+    #[derive(Clone, Default)]
+    pub struct Gateway {
+        inner: crate::Gateway,
+    }
+
+    impl From<crate::Gateway> for Gateway {
+        fn from(inner: crate::Gateway) -> Self {
+            Self { inner }
+        }
+    }
+
+    impl Gateway {
+        /// Constructs a gateway with the default configuration.
+        pub fn new() -> Self {
+            Self::default()
+        }
+
+        /// Returns the async gateway this instance wraps.
+        pub fn as_async(&self) -> &crate::Gateway {
+            &self.inner
+        }
+
+        /// Constructs a new query for repodata records. See
+        /// [`crate::Gateway::query`].
+        pub fn query<AsChannel, ChannelIter, PlatformIter, PackageNameIter, IntoMatchSpec>(
+            &self,
+            channels: ChannelIter,
+            platforms: PlatformIter,
+            specs: PackageNameIter,
+        ) -> RepoDataQuery
+        where
+            AsChannel: Into<Channel>,
+            ChannelIter: IntoIterator<Item = AsChannel>,
+            PlatformIter: IntoIterator<Item = Platform>,
+            <PlatformIter as IntoIterator>::IntoIter: Clone,
+            PackageNameIter: IntoIterator<Item = IntoMatchSpec>,
+            IntoMatchSpec: Into<MatchSpec>,
+        {
+            RepoDataQuery {
+                inner: self.inner.query(channels, platforms, specs),
+            }
+        }
+
+        /// Constructs a new query for all package names. See
+        /// [`crate::Gateway::names`].
+        pub fn names<AsChannel, ChannelIter, PlatformIter>(
+            &self,
+            channels: ChannelIter,
+            platforms: PlatformIter,
+        ) -> NamesQuery
+        where
+            AsChannel: Into<Channel>,
+            ChannelIter: IntoIterator<Item = AsChannel>,
+            PlatformIter: IntoIterator<Item = Platform>,
+            <PlatformIter as IntoIterator>::IntoIter: Clone,
+        {
+            NamesQuery {
+                inner: self.inner.names(channels, platforms),
+            }
+        }
+
+        /// Fetches all records from the given channels and platforms. See
+        /// [`crate::Gateway::fetch_all`].
+        pub fn fetch_all<AsChannel, ChannelIter, PlatformIter>(
+            &self,
+            channels: ChannelIter,
+            platforms: PlatformIter,
+        ) -> Result<Vec<RepoData>, GatewayError>
+        where
+            AsChannel: Into<Channel>,
+            ChannelIter: IntoIterator<Item = AsChannel>,
+            PlatformIter: IntoIterator<Item = Platform>,
+            <PlatformIter as IntoIterator>::IntoIter: Clone,
+        {
+            block_on(self.inner.fetch_all(channels, platforms))
+        }
+
+        /// Clears any in-memory cache for the given channel. See
+        /// [`crate::Gateway::clear_repodata_cache`].
+        pub fn clear_repodata_cache(&self, channel: &Channel, subdirs: SubdirSelection) {
+            self.inner.clear_repodata_cache(channel, subdirs);
+        }
+    }
+
+    /// Blocking version of the query returned by [`crate::Gateway::query`].
+    pub struct RepoDataQuery {
+        inner: crate::gateway::RepoDataQuery,
+    }
+
+    impl RepoDataQuery {
+        /// Sets whether the dependencies of the specs should be fetched as
+        /// well.
+        #[must_use]
+        pub fn recursive(self, recursive: bool) -> Self {
+            Self {
+                inner: self.inner.recursive(recursive),
+            }
+        }
+
+        /// Sets the reporter to use for this query.
+        #[must_use]
+        pub fn with_reporter(self, reporter: impl Reporter + 'static) -> Self {
+            Self {
+                inner: self.inner.with_reporter(reporter),
+            }
+        }
+
+        /// Executes the query and blocks until all records have been fetched.
+        pub fn execute(self) -> Result<Vec<RepoData>, GatewayError> {
+            block_on(self.inner.execute())
+        }
+    }
+
+    /// Blocking version of the query returned by [`crate::Gateway::names`].
+    pub struct NamesQuery {
+        inner: crate::gateway::NamesQuery,
+    }
+
+    impl NamesQuery {
+        /// Sets the reporter to use for this query.
+        #[must_use]
+        pub fn with_reporter(self, reporter: impl Reporter + 'static) -> Self {
+            Self {
+                inner: self.inner.with_reporter(reporter),
+            }
+        }
+
+        /// Executes the query and blocks until all names have been fetched.
+        pub fn execute(self) -> Result<Vec<PackageName>, GatewayError> {
+            block_on(self.inner.execute())
+        }
+    }
+}
+
+#[cfg(feature = "gateway")]
+pub use gateway::{Gateway, NamesQuery, RepoDataQuery};
+
+#[cfg(all(test, feature = "gateway"))]
+mod test {
+    use std::{path::Path, str::FromStr};
+
+    use rattler_conda_types::{Channel, PackageName, Platform};
+
+    use super::Gateway;
+    use crate::RepoData;
+
+    #[test]
+    fn test_blocking_query() {
+        let channel = Channel::from_directory(
+            &Path::new(env!("CARGO_MANIFEST_DIR")).join("../../test-data/channels/dummy"),
+        );
+
+        let records = Gateway::new()
+            .query(
+                [channel],
+                [Platform::Linux64],
+                [PackageName::from_str("foo").unwrap()],
+            )
+            .execute()
+            .unwrap();
+
+        let total_records: usize = records.iter().map(RepoData::len).sum();
+        assert_eq!(total_records, 4);
+    }
+
+    #[tokio::test]
+    #[should_panic(expected = "cannot be called from within an async runtime")]
+    async fn test_blocking_in_async_context_panics() {
+        let _ = Gateway::new()
+            .names(Vec::<Channel>::new(), [Platform::Linux64])
+            .execute();
+    }
+}
@@ -0,0 +1,465 @@
+//! Maintenance of the on-disk repodata cache.
+//!
+//! [`crate::fetch::fetch_repo_data`] stores every subdirectory it fetches as
+//! three files in the cache directory: `<key>.json` contains the repodata
+//! itself (or `<key>.json.zst` if it is stored compressed), `<key>.info.json`
+//! contains the [`RepoDataState`] that describes it and `<key>.lock` is used to
+//! synchronize access between processes. A parsed binary representation of the
+//! repodata may be stored in `<key>.msgpack` and the zchunk file the repodata
+//! was assembled from in `<key>.zck`. If the previous version of the repodata
+//! is kept it is stored in `<key>.previous.json` (or
+//! `<key>.previous.json.zst`). Over time the cache accumulates entries
+//! for channels that are no longer used and files that were left behind by
+//! interrupted processes. [`gc`] removes them.
+
+use std::{
+    collections::BTreeMap,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use rattler_error::ErrorCode;
+use url::Url;
+
+use crate::{fetch::cache::RepoDataState, utils::LockedFile};
+
+/// Determines which entries of the cache are removed by [`gc`].
+///
+/// Entries that are incomplete (e.g. a `.json` file without a matching
+/// `.info.json` file or a lonely `.lock` file) are always removed.
+#[derive(Debug, Clone, Default)]
+pub struct GcPolicy {
+    /// Remove entries whose repodata was last written longer ago than this.
+    pub max_age: Option<Duration>,
+
+    /// If set, remove all entries whose repodata was not fetched from one of
+    /// these channels.
+    pub keep_channels: Option<Vec<Url>>,
+
+    /// If set, evict the least recently used entries until the total size of
+    /// the cache in bytes is no larger than this.
+    pub max_size: Option<u64>,
+}
+
+/// Describes what has been removed by [`gc`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct GcReport {
+    /// The number of cache entries that were removed.
+    pub removed_entries: usize,
+
+    /// The total size in bytes of all removed files.
+    pub reclaimed_bytes: u64,
+
+    /// The total size in bytes of the entries that remain in the cache.
+    pub remaining_bytes: u64,
+}
+
+/// An error that can occur while collecting garbage in the cache.
+#[derive(Debug, thiserror::Error)]
+#[allow(missing_docs)]
+pub enum GcError {
+    #[error("failed to acquire a lock on {0}")]
+    FailedToAcquireLock(PathBuf, #[source] anyhow::Error),
+
+    #[error(transparent)]
+    IoError(#[from] std::io::Error),
+}
+
+impl ErrorCode for GcError {
+    fn code(&self) -> &'static str {
+        match self {
+            GcError::FailedToAcquireLock(..) => "rattler::cache_gc::failed_to_acquire_lock",
+            GcError::IoError(_) => "rattler::cache_gc::io",
+        }
+    }
+}
+
+/// The files on disk that belong to a single cache entry.
+#[derive(Default)]
+struct CacheEntry {
+    repo_data: Option<PathBuf>,
+    state: Option<PathBuf>,
+    binary: Option<PathBuf>,
+    zchunk: Option<PathBuf>,
+    previous: Option<PathBuf>,
+    lock: Option<PathBuf>,
+}
+
+impl CacheEntry {
+    /// Returns the paths of all the files of this entry.
+    fn files(&self) -> impl Iterator<Item = &PathBuf> {
+        [
+            &self.repo_data,
+            &self.state,
+            &self.binary,
+            &self.zchunk,
+            &self.previous,
+            &self.lock,
+        ]
+        .into_iter()
+        .flatten()
+    }
+
+    /// Returns the total size in bytes of all the files of this entry.
+    fn size(&self) -> u64 {
+        self.files()
+            .filter_map(|path| std::fs::metadata(path).ok())
+            .map(|metadata| metadata.len())
+            .sum()
+    }
+
+    /// Returns when this entry was last used. Every fetch touches the lock
+    /// file, for older entries the time the repodata was written is used.
+    fn last_used(&self) -> Option<SystemTime> {
+        [&self.lock, &self.repo_data]
+            .into_iter()
+            .flatten()
+            .find_map(|path| std::fs::metadata(path).and_then(|m| m.modified()).ok())
+    }
+}
+
+/// Reads all the entries of the cache at `cache_path` grouped by their cache
+/// key.
+fn read_entries(cache_path: &Path) -> std::io::Result<BTreeMap<String, CacheEntry>> {
+    let read_dir = match std::fs::read_dir(cache_path) {
+        Ok(read_dir) => read_dir,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(BTreeMap::new()),
+        Err(err) => return Err(err),
+    };
+
+    let mut entries: BTreeMap<String, CacheEntry> = BTreeMap::new();
+    for dir_entry in read_dir {
+        let dir_entry = dir_entry?;
+        if !dir_entry.file_type()?.is_file() {
+            continue;
+        }
+        let file_name = dir_entry.file_name();
+        let Some(file_name) = file_name.to_str() else {
+            continue;
+        };
+        let path = dir_entry.path();
+        if let Some(key) = file_name.strip_suffix(".info.json") {
+            entries.entry(key.to_owned()).or_default().state = Some(path);
+        } else if let Some(key) = file_name
+            .strip_suffix(".previous.json")
+            .or_else(|| file_name.strip_suffix(".previous.json.zst"))
+        {
+            entries.entry(key.to_owned()).or_default().previous = Some(path);
+        } else if let Some(key) = file_name
+            .strip_suffix(".json")
+            .or_else(|| file_name.strip_suffix(".json.zst"))
+        {
+            entries.entry(key.to_owned()).or_default().repo_data = Some(path);
+        } else if let Some(key) = file_name.strip_suffix(".msgpack") {
+            entries.entry(key.to_owned()).or_default().binary = Some(path);
+        } else if let Some(key) = file_name.strip_suffix(".zck") {
+            entries.entry(key.to_owned()).or_default().zchunk = Some(path);
+        } else if let Some(key) = file_name.strip_suffix(".lock") {
+            entries.entry(key.to_owned()).or_default().lock = Some(path);
+        }
+    }
+    Ok(entries)
+}
+
+/// Returns the total size in bytes of all the entries in the repodata cache at
+/// `cache_path`.
+pub fn cache_size(cache_path: &Path) -> std::io::Result<u64> {
+    Ok(read_entries(cache_path)?
+        .values()
+        .map(CacheEntry::size)
+        .sum())
+}
+
+/// Removes entries from the repodata cache at `cache_path` that match the
+/// given `policy` and returns how much space was reclaimed.
+///
+/// Entries that are currently locked by another process (for instance because
+/// they are being fetched) are skipped.
+pub fn gc(cache_path: &Path, policy: &GcPolicy) -> Result<GcReport, GcError> {
+    let entries = read_entries(cache_path)?;
+
+    let keep_channels = policy.keep_channels.as_ref().map(|channels| {
+        channels
+            .iter()
+            .map(|url| {
+                let mut url = url.to_string();
+                if !url.ends_with('/') {
+                    url.push('/');
+                }
+                url
+            })
+            .collect::<Vec<_>>()
+    });
+
+    let mut report = GcReport::default();
+    let mut remaining = Vec::new();
+    for (key, entry) in entries {
+        let should_remove = match (&entry.repo_data, &entry.state) {
+            (Some(repo_data), Some(state)) => {
+                is_expired(repo_data, policy.max_age)?
+                    || match RepoDataState::from_path(state) {
+                        Ok(state) => keep_channels.as_ref().map_or(false, |channels| {
+                            !channels
+                                .iter()
+                                .any(|channel| state.url.as_str().starts_with(channel.as_str()))
+                        }),
+                        // The entry was written by a newer version of rattler which may still
+                        // use it.
+                        Err(err) if err.kind() == ErrorKind::Unsupported => false,
+                        // The state cannot be read so the entry cannot be used anyway.
+                        Err(_) => true,
+                    }
+            }
+            _ => true,
+        };
+
+        if should_remove && remove_entry(cache_path, &key, &entry, &mut report)? {
+            continue;
+        }
+        remaining.push((key, entry));
+    }
+
+    // Evict the least recently used entries until the cache fits.
+    report.remaining_bytes = remaining.iter().map(|(_, entry)| entry.size()).sum();
+    if let Some(max_size) = policy.max_size {
+        remaining.sort_by_cached_key(|(_, entry)| entry.last_used());
+        for (key, entry) in remaining {
+            if report.remaining_bytes <= max_size {
+                break;
+            }
+            let size = entry.size();
+            if remove_entry(cache_path, &key, &entry, &mut report)? {
+                report.remaining_bytes = report.remaining_bytes.saturating_sub(size);
+            }
+        }
+    }
+
+    Ok(report)
+}
+
+/// Removes all the files of a cache entry and records it in `report`. Returns
+/// `false` if the entry is currently locked by someone else.
+fn remove_entry(
+    cache_path: &Path,
+    key: &str,
+    entry: &CacheEntry,
+    report: &mut GcReport,
+) -> Result<bool, GcError> {
+    // Make sure nobody is using the entry while we remove it.
+    let lock_path = entry
+        .lock
+        .clone()
+        .unwrap_or_else(|| cache_path.join(format!("{key}.lock")));
+    let Some(lock) = LockedFile::try_open_rw(&lock_path)
+        .map_err(|err| GcError::FailedToAcquireLock(lock_path.clone(), err))?
+    else {
+        tracing::debug!("skipping {key} because it is in use");
+        return Ok(false);
+    };
+
+    for path in [
+        &entry.repo_data,
+        &entry.state,
+        &entry.binary,
+        &entry.zchunk,
+        &entry.previous,
+    ]
+    .into_iter()
+    .flatten()
+    {
+        report.reclaimed_bytes += remove_file(path)?;
+    }
+    drop(lock);
+    report.reclaimed_bytes += remove_file(&lock_path)?;
+    report.removed_entries += 1;
+    Ok(true)
+}
+
+/// Returns true if the file at `path` was modified longer ago than `max_age`.
+fn is_expired(path: &Path, max_age: Option<Duration>) -> std::io::Result<bool> {
+    let Some(max_age) = max_age else {
+        return Ok(false);
+    };
+    let modified = std::fs::metadata(path)?.modified()?;
+    Ok(modified.elapsed().map_or(false, |age| age > max_age))
+}
+
+/// Removes the file at `path` and returns its size. Files that no longer exist
+/// are ignored.
+fn remove_file(path: &Path) -> std::io::Result<u64> {
+    let size = match std::fs::metadata(path) {
+        Ok(metadata) => metadata.len(),
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(0),
+        Err(err) => return Err(err),
+    };
+    match std::fs::remove_file(path) {
+        Ok(()) => Ok(size),
+        Err(err) if err.kind() == ErrorKind::NotFound => Ok(0),
+        Err(err) => Err(err),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, SystemTime};
+
+    use url::Url;
+
+    use super::{cache_size, gc, GcPolicy};
+    use crate::{fetch::cache::RepoDataState, utils::LockedFile};
+
+    fn write_entry(dir: &std::path::Path, key: &str, url: &str) {
+        let repo_data = dir.join(format!("{key}.json"));
+        std::fs::write(&repo_data, "{}").unwrap();
+        let state: RepoDataState = serde_json::from_value(serde_json::json!({
+            "url": url,
+            "mtime_ns": 0,
+            "size": 2,
+        }))
+        .unwrap();
+        state
+            .to_path(&dir.join(format!("{key}.info.json")))
+            .unwrap();
+        std::fs::write(dir.join(format!("{key}.lock")), "").unwrap();
+    }
+
+    #[test]
+    fn test_gc() {
+        let cache_dir = tempfile::tempdir().unwrap();
+        let dir = cache_dir.path();
+
+        write_entry(
+            dir,
+            "conda-forge",
+            "https://conda.anaconda.org/conda-forge/linux-64/repodata.json",
+        );
+        write_entry(
+            dir,
+            "bioconda",
+            "https://conda.anaconda.org/bioconda/linux-64/repodata.json",
+        );
+        std::fs::write(dir.join("bioconda.zck"), "zchunk").unwrap();
+        std::fs::write(dir.join("bioconda.previous.json"), "{}").unwrap();
+        write_entry(
+            dir,
+            "in-use",
+            "https://conda.anaconda.org/in-use/linux-64/repodata.json",
+        );
+
+        // Orphaned files.
+        std::fs::write(dir.join("orphan.json"), "{}").unwrap();
+        std::fs::write(dir.join("lonely.lock"), "").unwrap();
+
+        // Nothing but the orphans are removed by default.
+        let report = gc(dir, &GcPolicy::default()).unwrap();
+        assert_eq!(report.removed_entries, 2);
+        assert_eq!(report.reclaimed_bytes, 2);
+        assert!(!dir.join("orphan.json").exists());
+        assert!(!dir.join("lonely.lock").exists());
+        assert!(dir.join("conda-forge.json").exists());
+
+        // Entries that have not been written recently are kept.
+        let report = gc(
+            dir,
+            &GcPolicy {
+                max_age: Some(Duration::from_secs(3600)),
+                ..GcPolicy::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(report.removed_entries, 0);
+        assert_eq!(report.remaining_bytes, cache_size(dir).unwrap());
+
+        // Entries of channels that are no longer used are removed unless they
+        // are locked.
+        let lock = LockedFile::open_rw(dir.join("in-use.lock"), "test").unwrap();
+        let report = gc(
+            dir,
+            &GcPolicy {
+                keep_channels: Some(vec![
+                    Url::parse("https://conda.anaconda.org/conda-forge").unwrap()
+                ]),
+                ..GcPolicy::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(report.removed_entries, 1);
+        assert!(report.reclaimed_bytes > 2);
+        assert!(!dir.join("bioconda.json").exists());
+        assert!(!dir.join("bioconda.info.json").exists());
+        assert!(!dir.join("bioconda.lock").exists());
+        assert!(!dir.join("bioconda.zck").exists());
+        assert!(!dir.join("bioconda.previous.json").exists());
+        assert!(dir.join("conda-forge.info.json").exists());
+        assert!(dir.join("in-use.json").exists());
+        drop(lock);
+
+        // Everything is older than zero seconds.
+        let report = gc(
+            dir,
+            &GcPolicy {
+                max_age: Some(Duration::ZERO),
+                ..GcPolicy::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(report.removed_entries, 2);
+        assert_eq!(std::fs::read_dir(dir).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn test_gc_max_size() {
+        let cache_dir = tempfile::tempdir().unwrap();
+        let dir = cache_dir.path();
+
+        for (index, key) in ["old", "recent", "new"].into_iter().enumerate() {
+            write_entry(
+                dir,
+                key,
+                &format!("https://conda.anaconda.org/{key}/noarch/repodata.json"),
+            );
+            let last_used = SystemTime::now() - Duration::from_secs(3600 * (3 - index as u64));
+            std::fs::File::options()
+                .write(true)
+                .open(dir.join(format!("{key}.lock")))
+                .unwrap()
+                .set_modified(last_used)
+                .unwrap();
+        }
+
+        let total_size = cache_size(dir).unwrap();
+        let entry_size: u64 = ["old.json", "old.info.json", "old.lock"]
+            .into_iter()
+            .map(|name| std::fs::metadata(dir.join(name)).unwrap().len())
+            .sum();
+
+        // Evict the least recently used entry.
+        let report = gc(
+            dir,
+            &GcPolicy {
+                max_size: Some(total_size - 1),
+                ..GcPolicy::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(report.removed_entries, 1);
+        assert_eq!(report.reclaimed_bytes, entry_size);
+        assert_eq!(report.remaining_bytes, total_size - entry_size);
+        assert_eq!(cache_size(dir).unwrap(), report.remaining_bytes);
+        assert!(!dir.join("old.json").exists());
+        assert!(dir.join("recent.json").exists());
+        assert!(dir.join("new.json").exists());
+
+        // Nothing to do if the cache is small enough.
+        let report = gc(
+            dir,
+            &GcPolicy {
+                max_size: Some(total_size),
+                ..GcPolicy::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(report.removed_entries, 0);
+    }
+}
@@ -0,0 +1,128 @@
+//! Limiting the number of concurrent fetches.
+
+use std::sync::Arc;
+
+use dashmap::DashMap;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use url::Url;
+
+/// Limits the number of fetches that access the network at the same time.
+///
+/// When many subdirectories are fetched at once, every fetch performs its own
+/// requests. Sharing a limiter between the fetches (through
+/// `FetchRepoDataOptions::concurrency_limiter`, the gateway shares one between
+/// all of its fetches) bounds the total number of fetches and the number of
+/// fetches per host, so a server is not flooded with connections. A fetch
+/// holds its permit from the first request until the downloaded repodata has
+/// been written to the cache. Fetches that can be answered from the cache do
+/// not need a permit.
+///
+/// Cloning this type is cheap, all clones share the same limits.
+#[derive(Debug, Clone, Default)]
+pub struct ConcurrencyLimiter {
+    total: Option<Arc<Semaphore>>,
+    per_host: Option<(usize, Arc<DashMap<String, Arc<Semaphore>>>)>,
+}
+
+/// A permit to access the network obtained from a [`ConcurrencyLimiter`].
+pub(crate) struct ConcurrencyPermit {
+    _host: Option<OwnedSemaphorePermit>,
+    _total: Option<OwnedSemaphorePermit>,
+}
+
+impl ConcurrencyLimiter {
+    /// Constructs a limiter without any limits.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Limits the total number of concurrent fetches.
+    #[must_use]
+    pub fn with_max_concurrent_requests(self, max_concurrent_requests: usize) -> Self {
+        self.with_semaphore(Arc::new(Semaphore::new(max_concurrent_requests)))
+    }
+
+    /// Limits the total number of concurrent fetches with an existing
+    /// semaphore. This allows sharing the limit with other requests.
+    #[must_use]
+    pub fn with_semaphore(self, semaphore: Arc<Semaphore>) -> Self {
+        Self {
+            total: Some(semaphore),
+            ..self
+        }
+    }
+
+    /// Limits the number of concurrent fetches per host.
+    #[must_use]
+    pub fn with_max_concurrent_requests_per_host(self, max_concurrent_requests: usize) -> Self {
+        Self {
+            per_host: Some((max_concurrent_requests, Arc::default())),
+            ..self
+        }
+    }
+
+    /// Waits until a fetch from the given url is allowed.
+    pub(crate) async fn acquire(&self, url: &Url) -> ConcurrencyPermit {
+        // Wait for the host first so a fetch does not take up a permit of the
+        // total limit while waiting for a busy host.
+        let host = match (&self.per_host, url.host_str()) {
+            (Some((max, semaphores)), Some(host)) => {
+                let semaphore = semaphores
+                    .entry(host.to_string())
+                    .or_insert_with(|| Arc::new(Semaphore::new(*max)))
+                    .clone();
+                semaphore.acquire_owned().await.ok()
+            }
+            _ => None,
+        };
+        let total = match &self.total {
+            Some(semaphore) => semaphore.clone().acquire_owned().await.ok(),
+            None => None,
+        };
+        ConcurrencyPermit {
+            _host: host,
+            _total: total,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use url::Url;
+
+    use super::ConcurrencyLimiter;
+
+    #[tokio::test]
+    async fn test_per_host_limit() {
+        let limiter = ConcurrencyLimiter::new()
+            .with_max_concurrent_requests(2)
+            .with_max_concurrent_requests_per_host(1);
+        let conda_forge = Url::parse("https://conda.anaconda.org/conda-forge/").unwrap();
+        let prefix = Url::parse("https://repo.prefix.dev/conda-forge/").unwrap();
+
+        let _first = limiter.acquire(&conda_forge).await;
+
+        // Another host can still be accessed.
+        let second = limiter.acquire(&prefix).await;
+
+        // But the same host has to wait.
+        assert!(
+            tokio::time::timeout(Duration::from_millis(50), limiter.acquire(&conda_forge))
+                .await
+                .is_err()
+        );
+
+        // As does a third host because the total limit is reached.
+        let other = Url::parse("https://example.com/channel/").unwrap();
+        assert!(
+            tokio::time::timeout(Duration::from_millis(50), limiter.acquire(&other))
+                .await
+                .is_err()
+        );
+
+        drop(second);
+        let _third = limiter.acquire(&other).await;
+    }
+}
@@ -0,0 +1,127 @@
+//! A binary representation of parsed repodata that is stored next to the cached
+//! `repodata.json`.
+//!
+//! Parsing a large `repodata.json` can take several seconds. After the first
+//! parse the [`RepoData`] is stored as msgpack together with the BLAKE2 hash of
+//! the `repodata.json` it was created from. As long as the hash matches, the
+//! binary representation is loaded instead of parsing the JSON again.
+
+use std::{
+    fs::File,
+    io::{BufReader, BufWriter, ErrorKind, Read, Write},
+    path::{Path, PathBuf},
+};
+
+use rattler_conda_types::RepoData;
+use rattler_digest::Blake2b256;
+use tempfile::NamedTempFile;
+
+/// Magic number that identifies the cache file format. This must be changed
+/// whenever the binary representation of [`RepoData`] changes.
+const MAGIC_NUMBER: &[u8] = b"REPODATA-CACHE-V1";
+
+/// Returns the path of the binary cache that belongs to a cached
+/// `repodata.json`, which may be compressed (`<key>.json.zst`).
+pub(crate) fn binary_cache_path(repo_data_json_path: &Path) -> PathBuf {
+    let repo_data_json_path = if repo_data_json_path.extension() == Some("zst".as_ref()) {
+        repo_data_json_path.with_extension("")
+    } else {
+        repo_data_json_path.to_path_buf()
+    };
+    repo_data_json_path.with_extension("msgpack")
+}
+
+/// Reads the repodata from the binary cache if it matches the `repodata.json`
+/// with the given hash. Otherwise the `repodata.json` is parsed and the binary
+/// cache is (re)created.
+pub(crate) fn read_repo_data(
+    repo_data_json_path: &Path,
+    compressed: bool,
+    hash: Option<&blake2::digest::Output<Blake2b256>>,
+) -> std::io::Result<RepoData> {
+    // Without a hash there is no way to tell whether the binary cache is up-to-date.
+    let Some(hash) = hash else {
+        return parse_repo_data(repo_data_json_path, compressed);
+    };
+
+    let cache_path = binary_cache_path(repo_data_json_path);
+    match read_binary_cache(&cache_path, hash) {
+        Ok(Some(repo_data)) => return Ok(repo_data),
+        Ok(None) => {
+            tracing::debug!("binary repodata cache is outdated");
+        }
+        Err(e) if e.kind() == ErrorKind::NotFound => {}
+        Err(e) => {
+            tracing::warn!(
+                "failed to read binary repodata cache '{}': {e}. Ignoring...",
+                cache_path.display()
+            );
+        }
+    }
+
+    let repo_data = parse_repo_data(repo_data_json_path, compressed)?;
+    if let Err(e) = write_binary_cache(&cache_path, hash, &repo_data) {
+        tracing::warn!(
+            "failed to write binary repodata cache '{}': {e}",
+            cache_path.display()
+        );
+    }
+    Ok(repo_data)
+}
+
+/// Parses the `repodata.json` at `path`, which is decompressed first if it is
+/// stored `compressed`.
+fn parse_repo_data(path: &Path, compressed: bool) -> std::io::Result<RepoData> {
+    let mut contents = String::new();
+    super::open_cached_repo_data(path, compressed)?.read_to_string(&mut contents)?;
+    Ok(serde_json::from_str(&contents)?)
+}
+
+/// Reads the binary cache at `path`. Returns `None` if the cache was created
+/// from a different `repodata.json`.
+fn read_binary_cache(
+    path: &Path,
+    hash: &blake2::digest::Output<Blake2b256>,
+) -> std::io::Result<Option<RepoData>> {
+    let mut reader = BufReader::new(File::open(path)?);
+
+    // Read the magic from the file
+    let mut magic_number = [0; MAGIC_NUMBER.len()];
+    reader.read_exact(&mut magic_number)?;
+    if magic_number != MAGIC_NUMBER {
+        return Err(std::io::Error::new(
+            ErrorKind::InvalidData,
+            "invalid magic number",
+        ));
+    }
+
+    // Check whether the cache belongs to the repodata.json
+    let mut cached_hash = vec![0; hash.len()];
+    reader.read_exact(&mut cached_hash)?;
+    if cached_hash.as_slice() != hash.as_slice() {
+        return Ok(None);
+    }
+
+    rmp_serde::from_read(reader)
+        .map(Some)
+        .map_err(|e| std::io::Error::new(ErrorKind::InvalidData, e.to_string()))
+}
+
+/// Atomically writes the binary cache to `path`.
+fn write_binary_cache(
+    path: &Path,
+    hash: &blake2::digest::Output<Blake2b256>,
+    repo_data: &RepoData,
+) -> std::io::Result<()> {
+    let parent = path.parent().unwrap_or(Path::new("."));
+    let mut writer = BufWriter::new(NamedTempFile::new_in(parent)?);
+    writer.write_all(MAGIC_NUMBER)?;
+    writer.write_all(hash)?;
+    rmp_serde::encode::write_named(&mut writer, repo_data)
+        .map_err(|e| std::io::Error::new(ErrorKind::Other, e.to_string()))?;
+    writer
+        .into_inner()
+        .map_err(std::io::IntoInnerError::into_error)?
+        .persist(path)?;
+    Ok(())
+}
@@ -7,7 +7,14 @@ use serde_with::serde_as;
 use std::{fs, fs::File, path::Path, str::FromStr, time::SystemTime};
 use url::Url;
 
+/// The version of the format of the repodata cache that is written by this version of rattler. It
+/// covers the contents of the `.info.json` file and the layout of the files that belong to a cache
+/// entry (see [`crate::cache`]). Entries that were written in an older format are migrated when
+/// they are read, entries that were written by a newer version of rattler are not used.
+pub const CACHE_FORMAT_VERSION: u32 = 1;
+
 /// Representation of the `.info.json` file alongside a `repodata.json` file.
+#[serde_as]
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RepoDataState {
     /// The URL from where the repodata was downloaded. This is the URL of the `repodata.json`,
@@ -59,17 +66,72 @@ pub struct RepoDataState {
     /// Whether or not JLAP is available for the subdirectory
     pub has_jlap: Option<Expiring<bool>>,
 
+    /// Whether or not a zchunk file is available for the subdirectory
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub has_zck: Option<Expiring<bool>>,
+
+    /// Whether the repodata is stored compressed with zstd, exactly as it was downloaded. See
+    /// [`crate::fetch::FetchRepoDataOptions::keep_compressed`].
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub compressed: bool,
+
+    /// The namespace of the consumer the repodata was fetched for. See
+    /// [`crate::fetch::FetchRepoDataOptions::cache_namespace`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub namespace: Option<String>,
+
+    /// The SHA-256 hash of the downloaded file as it was received, which was verified against a
+    /// checksum that the server sent along with it. See
+    /// [`crate::fetch::FetchRepoDataOptions::verify_checksum_headers`].
+    #[serde_as(as = "Option<SerializableHash::<rattler_digest::Sha256>>")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub verified_sha256: Option<rattler_digest::Sha256Hash>,
+
     /// State information related to JLAP
     pub jlap: Option<JLAPState>,
+
+    /// The version of the format the cache entry was written in, see [`CACHE_FORMAT_VERSION`].
+    /// Entries that were written before the format was versioned have version `0`.
+    #[serde(default)]
+    pub cache_version: u32,
+}
+
+/// The part of the `.info.json` file that is read to determine whether the rest of the file can
+/// be understood.
+#[derive(Deserialize)]
+struct VersionedState {
+    #[serde(default)]
+    cache_version: u32,
 }
 
 impl RepoDataState {
-    /// Reads and parses a file from disk.
+    /// Reads and parses a file from disk. Fails with [`std::io::ErrorKind::Unsupported`] if the
+    /// file was written in a newer format than [`CACHE_FORMAT_VERSION`].
     pub fn from_path(path: &Path) -> Result<RepoDataState, std::io::Error> {
         let content = fs::read_to_string(path)?;
+        let VersionedState { cache_version } = serde_json::from_str(&content)?;
+        if cache_version > CACHE_FORMAT_VERSION {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::Unsupported,
+                format!(
+                    "the cache entry was written in format version {cache_version}, only versions up to {CACHE_FORMAT_VERSION} are supported"
+                ),
+            ));
+        }
         Ok(Self::from_str(&content)?)
     }
 
+    /// Upgrades a state that was written in an older format to [`CACHE_FORMAT_VERSION`]. A state
+    /// that was written in a newer format is returned as is.
+    fn migrate(mut self) -> Self {
+        // Version 0 was written before the format was versioned. All the fields that were added
+        // since have sensible defaults, so only the version changes.
+        if self.cache_version == 0 {
+            self.cache_version = 1;
+        }
+        self
+    }
+
     /// Save the cache state to the specified file.
     pub fn to_path(&self, path: &Path) -> Result<(), std::io::Error> {
         let file = File::create(path)?;
@@ -81,7 +143,7 @@ impl FromStr for RepoDataState {
     type Err = serde_json::Error;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        serde_json::from_str(s)
+        serde_json::from_str(s).map(Self::migrate)
     }
 }
 
@@ -184,7 +246,7 @@ fn serialize_blake2_hash<S: Serializer>(
 
 #[cfg(test)]
 mod test {
-    use super::RepoDataState;
+    use super::{RepoDataState, CACHE_FORMAT_VERSION};
     use std::str::FromStr;
 
     const JSON_STATE_ONE: &str = r#"{
@@ -228,4 +290,37 @@ mod test {
     pub fn test_parse_repo_data_state_two() {
         insta::assert_yaml_snapshot!(RepoDataState::from_str(JSON_STATE_TWO).unwrap());
     }
+
+    #[test]
+    pub fn test_cache_version() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("state.info.json");
+
+        // Entries that were written before the format was versioned are migrated.
+        std::fs::write(&path, JSON_STATE_TWO).unwrap();
+        let state = RepoDataState::from_path(&path).unwrap();
+        assert_eq!(state.cache_version, CACHE_FORMAT_VERSION);
+
+        // The current version is written along with the state.
+        state.to_path(&path).unwrap();
+        assert_eq!(
+            RepoDataState::from_path(&path).unwrap().cache_version,
+            CACHE_FORMAT_VERSION
+        );
+
+        // Entries that were written by a newer version of rattler are not read, even if their
+        // format cannot be parsed anymore.
+        std::fs::write(
+            &path,
+            format!(
+                r#"{{"cache_version": {}, "url": 42}}"#,
+                CACHE_FORMAT_VERSION + 1
+            ),
+        )
+        .unwrap();
+        assert_eq!(
+            RepoDataState::from_path(&path).unwrap_err().kind(),
+            std::io::ErrorKind::Unsupported
+        );
+    }
 }
@@ -0,0 +1,159 @@
+//! Fetching the repodata of a platform together with the `noarch` repodata of
+//! a channel.
+
+use std::{path::PathBuf, sync::Arc};
+
+use rattler_conda_types::{Channel, Platform, RepoDataRecord};
+
+use super::{fetch_repo_data, CachedRepoData, FetchRepoDataError, FetchRepoDataOptions};
+use crate::Reporter;
+
+/// The repodata of a platform specific subdirectory of a channel together
+/// with the repodata of the `noarch` subdirectory of the channel.
+#[derive(Debug)]
+pub struct ChannelRepoData {
+    /// The repodata of the platform specific subdirectory. This is `None` if
+    /// the channel does not have the subdirectory or if the repodata of
+    /// [`Platform::NoArch`] was requested.
+    pub platform: Option<CachedRepoData>,
+
+    /// The repodata of the `noarch` subdirectory.
+    pub noarch: CachedRepoData,
+}
+
+impl ChannelRepoData {
+    /// Parses the cached repodata of both subdirectories and returns all
+    /// their records, those of the platform specific subdirectory first. See
+    /// [`CachedRepoData::repo_data_records`].
+    pub async fn repo_data_records(
+        &self,
+        channel: &Channel,
+    ) -> Result<Vec<RepoDataRecord>, std::io::Error> {
+        let mut records = match &self.platform {
+            Some(platform) => platform.repo_data_records(channel).await?,
+            None => Vec::new(),
+        };
+        records.extend(self.noarch.repo_data_records(channel).await?);
+        Ok(records)
+    }
+}
+
+/// Fetches the repodata of the subdirectory of `channel` for `platform` and of
+/// the `noarch` subdirectory of the channel concurrently, see
+/// [`fetch_repo_data`].
+///
+/// Just like in the gateway, a missing platform specific subdirectory
+/// is considered empty, while a missing `noarch` subdirectory is an error.
+pub async fn fetch_channel_repo_data(
+    channel: &Channel,
+    platform: Platform,
+    client: impl Into<reqwest_middleware::ClientWithMiddleware>,
+    cache_path: PathBuf,
+    options: FetchRepoDataOptions,
+    reporter: Option<Arc<dyn Reporter>>,
+) -> Result<ChannelRepoData, FetchRepoDataError> {
+    let client = client.into();
+    let noarch = fetch_repo_data(
+        channel.platform_url(Platform::NoArch),
+        client.clone(),
+        cache_path.clone(),
+        options.clone(),
+        reporter.clone(),
+    );
+    if platform == Platform::NoArch {
+        return Ok(ChannelRepoData {
+            platform: None,
+            noarch: noarch.await?,
+        });
+    }
+
+    let platform_repo_data = async {
+        match fetch_repo_data(
+            channel.platform_url(platform),
+            client,
+            cache_path,
+            options,
+            reporter,
+        )
+        .await
+        {
+            Ok(repo_data) => Ok(Some(repo_data)),
+            Err(FetchRepoDataError::NotFound(err)) => {
+                tracing::info!(
+                    "subdir {} of channel {} was not found, ignoring: {}",
+                    platform,
+                    channel.canonical_name(),
+                    err
+                );
+                Ok(None)
+            }
+            Err(err) => Err(err),
+        }
+    };
+    let (platform, noarch) = futures::try_join!(platform_repo_data, noarch)?;
+    Ok(ChannelRepoData { platform, noarch })
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::Path;
+
+    use rattler_conda_types::{Channel, Platform};
+    use reqwest::Client;
+    use tempfile::TempDir;
+
+    use super::fetch_channel_repo_data;
+    use crate::fetch::FetchRepoDataOptions;
+
+    fn write_repodata(channel_dir: &Path, subdir: &str, name: &str) {
+        let subdir_dir = channel_dir.join(subdir);
+        std::fs::create_dir_all(&subdir_dir).unwrap();
+        std::fs::write(
+            subdir_dir.join("repodata.json"),
+            format!(
+                r#"{{"info": {{"subdir": "{subdir}"}}, "packages": {{"{name}-1-0.tar.bz2": {{"name": "{name}", "version": "1", "build": "0", "build_number": 0, "subdir": "{subdir}", "depends": []}}}}}}"#
+            ),
+        )
+        .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_fetch_channel_repo_data() {
+        let channel_dir = TempDir::new().unwrap();
+        write_repodata(channel_dir.path(), "noarch", "foo");
+        write_repodata(channel_dir.path(), "linux-64", "bar");
+        let channel = Channel::from_directory(channel_dir.path());
+        let cache_dir = TempDir::new().unwrap();
+
+        let fetch = |platform| {
+            fetch_channel_repo_data(
+                &channel,
+                platform,
+                Client::new(),
+                cache_dir.path().to_owned(),
+                FetchRepoDataOptions::default(),
+                None,
+            )
+        };
+
+        let repo_data = fetch(Platform::Linux64).await.unwrap();
+        let records = repo_data.repo_data_records(&channel).await.unwrap();
+        let names = records
+            .iter()
+            .map(|record| record.package_record.name.as_normalized())
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["bar", "foo"]);
+
+        // A missing platform specific subdirectory is empty.
+        let repo_data = fetch(Platform::Win64).await.unwrap();
+        assert!(repo_data.platform.is_none());
+        assert_eq!(
+            repo_data.repo_data_records(&channel).await.unwrap().len(),
+            1
+        );
+
+        // Only the noarch subdirectory is fetched for noarch.
+        let repo_data = fetch(Platform::NoArch).await.unwrap();
+        assert!(repo_data.platform.is_none());
+    }
+}
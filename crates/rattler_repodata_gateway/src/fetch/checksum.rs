@@ -0,0 +1,79 @@
+//! Verification of the checksums that some servers send along with a file.
+
+use rattler_digest::{Sha256, Sha256Hash};
+use reqwest::{
+    header::{self, HeaderName},
+    Response,
+};
+
+/// The header in which Artifactory (and other servers) send the SHA-256 hash
+/// of a file.
+const X_CHECKSUM_SHA256: HeaderName = HeaderName::from_static("x-checksum-sha256");
+
+/// A checksum of the body of a response that was sent by the server.
+#[derive(Debug, Clone)]
+pub(crate) struct ExpectedChecksum {
+    /// The header that contained the checksum.
+    pub header: HeaderName,
+
+    /// The expected SHA-256 hash of the body.
+    pub sha256: Sha256Hash,
+}
+
+/// Returns the checksum of the body that the server sent along with the
+/// response, if any.
+///
+/// The checksum is only read from the explicit `X-Checksum-Sha256` header. An
+/// `ETag` is an opaque validator, even if it happens to look like a SHA-256
+/// hash it does not have to be one. The checksum refers to the file as it is
+/// stored on the server, so it is ignored if the body has a
+/// `Content-Encoding`.
+pub(crate) fn expected_checksum(response: &Response) -> Option<ExpectedChecksum> {
+    let headers = response.headers();
+    if headers.contains_key(header::CONTENT_ENCODING) {
+        return None;
+    }
+
+    let value = headers.get(X_CHECKSUM_SHA256)?.to_str().ok()?.trim();
+    if value.len() != 64 {
+        return None;
+    }
+    Some(ExpectedChecksum {
+        header: X_CHECKSUM_SHA256,
+        sha256: rattler_digest::parse_digest_from_hex::<Sha256>(value)?,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::expected_checksum;
+
+    const HASH: &str = "7ed530efddd47a96c11197906b4008405b90e3bc2f4e0df722a36e0e6103fd9c";
+
+    fn response(headers: &[(&str, &str)]) -> reqwest::Response {
+        let mut builder = http::Response::builder();
+        for (name, value) in headers {
+            builder = builder.header(*name, *value);
+        }
+        builder.body("").unwrap().into()
+    }
+
+    #[test]
+    fn test_expected_checksum() {
+        let checksum = expected_checksum(&response(&[("X-Checksum-Sha256", HASH)])).unwrap();
+        assert_eq!(checksum.header.as_str(), "x-checksum-sha256");
+        assert_eq!(format!("{:x}", checksum.sha256), HASH);
+
+        // An ETag is never trusted as a checksum, even if it looks like one.
+        let etag = format!("\"{HASH}\"");
+        assert!(expected_checksum(&response(&[("ETag", &etag)])).is_none());
+        assert!(expected_checksum(&response(&[("X-Checksum-Sha256", "abc")])).is_none());
+
+        // The checksum does not describe encoded content.
+        assert!(expected_checksum(&response(&[
+            ("X-Checksum-Sha256", HASH),
+            ("Content-Encoding", "gzip")
+        ]))
+        .is_none());
+    }
+}
@@ -0,0 +1,54 @@
+//! Handling of anaconda.org style tokens that are part of the url path
+//! (`https://conda.anaconda.org/t/<token>/channel/subdir/`).
+//!
+//! The token is a secret. It is only added to the urls of the requests, the
+//! cache key and the cache state are always based on the url without it.
+
+use url::Url;
+
+/// Splits the conda token from the path of the url. Returns the url without
+/// the token and the token if there was one.
+pub(crate) fn split_conda_token(url: Url) -> (Url, Option<String>) {
+    let Some(mut segments) = url.path_segments() else {
+        return (url, None);
+    };
+    let (Some("t"), Some(token)) = (segments.next(), segments.next()) else {
+        return (url, None);
+    };
+    let token = token.to_owned();
+    let remainder = segments.collect::<Vec<_>>().join("/");
+
+    let mut stripped = url.clone();
+    stripped.set_path(&format!("/{remainder}"));
+    (stripped, Some(token))
+}
+
+/// Returns the url with the given conda token added to the start of the path.
+pub(crate) fn with_conda_token(url: &Url, token: &str) -> Url {
+    let mut url = url.clone();
+    let path = format!("/t/{token}{}", url.path());
+    url.set_path(&path);
+    url
+}
+
+#[cfg(test)]
+mod test {
+    use url::Url;
+
+    use super::{split_conda_token, with_conda_token};
+
+    #[test]
+    fn test_split_conda_token() {
+        let url = Url::parse("https://conda.anaconda.org/t/secret/conda-forge/noarch/").unwrap();
+        let (stripped, token) = split_conda_token(url.clone());
+        assert_eq!(
+            stripped.as_str(),
+            "https://conda.anaconda.org/conda-forge/noarch/"
+        );
+        assert_eq!(token.as_deref(), Some("secret"));
+        assert_eq!(with_conda_token(&stripped, "secret"), url);
+
+        let url = Url::parse("https://conda.anaconda.org/conda-forge/noarch/").unwrap();
+        assert_eq!(split_conda_token(url.clone()), (url, None));
+    }
+}
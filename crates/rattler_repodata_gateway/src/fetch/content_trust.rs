@@ -0,0 +1,441 @@
+//! Verification of signed repodata as specified by
+//! [conda-content-trust](https://github.com/conda/conda-content-trust).
+//!
+//! Trust is established through a chain of delegations:
+//!
+//! * The `root.json` metadata is trusted by the user. It is signed by the
+//!   `root` keys and delegates trust to the `key_mgr` keys.
+//! * The `key_mgr.json` metadata is published at the root of the channel. It
+//!   must be signed by the `key_mgr` keys and delegates trust to the `pkg_mgr`
+//!   keys.
+//! * Every package record in the `repodata.json` must be signed by the
+//!   `pkg_mgr` keys. The signatures are stored in the `signatures` field of
+//!   the `repodata.json`.
+//!
+//! All signatures are ed25519 signatures over the canonical JSON serialization
+//! of the signed data.
+
+use std::{collections::HashMap, fmt::Write};
+
+use chrono::{DateTime, Utc};
+use ed25519_dalek::{Signature, Verifier, VerifyingKey};
+use rattler_error::ErrorCode;
+use serde::Deserialize;
+use serde_json::Value;
+
+/// An error that can occur while verifying signed repodata.
+#[derive(Debug, thiserror::Error)]
+pub enum ContentTrustError {
+    /// The channel does not publish signed metadata.
+    #[error("the channel is not signed")]
+    UnsignedChannel,
+
+    /// The metadata of a role could not be parsed.
+    #[error("invalid {0} metadata")]
+    InvalidMetadata(String, #[source] serde_json::Error),
+
+    /// The metadata of a role is of a different type than expected.
+    #[error("expected {expected} metadata but found {found} metadata")]
+    UnexpectedMetadataType {
+        /// The expected type of metadata
+        expected: String,
+        /// The type of metadata that was found
+        found: String,
+    },
+
+    /// The metadata of a role is not signed by enough trusted keys.
+    #[error("the {0} metadata is not signed by enough trusted keys")]
+    InvalidMetadataSignature(String),
+
+    /// The metadata of a role has expired.
+    #[error("the {0} metadata expired at {1}")]
+    Expired(String, DateTime<Utc>),
+
+    /// The metadata does not delegate trust to a required role.
+    #[error("the {0} metadata does not delegate to {1}")]
+    MissingDelegation(String, String),
+
+    /// A package in the repodata does not have a signature.
+    #[error("the package {0} is not signed")]
+    MissingSignature(String),
+
+    /// The signature of a package in the repodata is not valid.
+    #[error("the signature of the package {0} is invalid")]
+    InvalidSignature(String),
+
+    /// The repodata could not be read.
+    #[error("failed to read the repodata")]
+    IoError(#[from] std::io::Error),
+}
+
+impl ErrorCode for ContentTrustError {
+    fn code(&self) -> &'static str {
+        match self {
+            ContentTrustError::UnsignedChannel => "rattler::content_trust::unsigned_channel",
+            ContentTrustError::InvalidMetadata(..) => "rattler::content_trust::invalid_metadata",
+            ContentTrustError::UnexpectedMetadataType { .. } => {
+                "rattler::content_trust::unexpected_metadata_type"
+            }
+            ContentTrustError::InvalidMetadataSignature(_) => {
+                "rattler::content_trust::invalid_metadata_signature"
+            }
+            ContentTrustError::Expired(..) => "rattler::content_trust::expired",
+            ContentTrustError::MissingDelegation(..) => {
+                "rattler::content_trust::missing_delegation"
+            }
+            ContentTrustError::MissingSignature(_) => "rattler::content_trust::missing_signature",
+            ContentTrustError::InvalidSignature(_) => "rattler::content_trust::invalid_signature",
+            ContentTrustError::IoError(_) => "rattler::content_trust::io",
+        }
+    }
+}
+
+/// Metadata that is signed by one or more keys.
+#[derive(Debug, Deserialize)]
+struct Signable {
+    signed: Value,
+    #[serde(default)]
+    signatures: HashMap<String, SignatureEntry>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SignatureEntry {
+    signature: String,
+}
+
+/// The signed part of the `root.json` and `key_mgr.json` metadata.
+#[derive(Debug, Deserialize)]
+struct RoleMetadata {
+    #[serde(rename = "type")]
+    kind: String,
+    expiration: DateTime<Utc>,
+    #[serde(default)]
+    delegations: HashMap<String, Delegation>,
+}
+
+/// The keys that are trusted for a role and how many of them must sign.
+#[derive(Debug, Clone, Deserialize)]
+struct Delegation {
+    pubkeys: Vec<String>,
+    threshold: usize,
+}
+
+/// Trusted `root.json` metadata that is used to verify signed channels.
+///
+/// Enable verification by setting
+/// [`super::FetchRepoDataOptions::content_trust`].
+#[derive(Debug, Clone)]
+pub struct ContentTrust {
+    key_mgr: Delegation,
+}
+
+impl ContentTrust {
+    /// Constructs an instance from the contents of a trusted `root.json` file.
+    /// The metadata must be signed by its own root keys and must not be
+    /// expired.
+    pub fn from_root_json(root_json: &str) -> Result<Self, ContentTrustError> {
+        let root = verify_role_metadata(root_json, "root", None)?;
+        let key_mgr = delegation(&root, "root", "key_mgr")?;
+        Ok(Self { key_mgr })
+    }
+
+    /// Verifies the `key_mgr.json` of a channel and all package signatures of
+    /// the given `repodata.json`.
+    pub(crate) fn verify_repo_data(
+        &self,
+        key_mgr_json: &str,
+        repo_data_json: &str,
+    ) -> Result<(), ContentTrustError> {
+        let key_mgr = verify_role_metadata(key_mgr_json, "key_mgr", Some(&self.key_mgr))?;
+        let pkg_mgr = delegation(&key_mgr, "key_mgr", "pkg_mgr")?;
+
+        let repo_data: Value = serde_json::from_str(repo_data_json)
+            .map_err(|e| ContentTrustError::InvalidMetadata(String::from("repodata"), e))?;
+        let Some(signatures) = repo_data.get("signatures").and_then(Value::as_object) else {
+            return Err(ContentTrustError::UnsignedChannel);
+        };
+
+        let records = ["packages", "packages.conda"]
+            .into_iter()
+            .filter_map(|key| repo_data.get(key).and_then(Value::as_object))
+            .flatten();
+        for (file_name, record) in records {
+            let package_signatures = signatures
+                .get(file_name)
+                .cloned()
+                .map(serde_json::from_value::<HashMap<String, SignatureEntry>>)
+                .transpose()
+                .map_err(|_| ContentTrustError::InvalidSignature(file_name.clone()))?
+                .ok_or_else(|| ContentTrustError::MissingSignature(file_name.clone()))?;
+            if !is_signed_by(&canonical_json(record), &package_signatures, &pkg_mgr) {
+                return Err(ContentTrustError::InvalidSignature(file_name.clone()));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Parses the metadata of a role and verifies that it is signed by the
+/// `trusted` keys. If `trusted` is `None` the metadata must be signed by the
+/// keys it delegates its own role to (which is the case for `root.json`).
+fn verify_role_metadata(
+    json: &str,
+    role: &str,
+    trusted: Option<&Delegation>,
+) -> Result<RoleMetadata, ContentTrustError> {
+    let signable: Signable = serde_json::from_str(json)
+        .map_err(|e| ContentTrustError::InvalidMetadata(role.to_owned(), e))?;
+    let metadata: RoleMetadata = serde_json::from_value(signable.signed.clone())
+        .map_err(|e| ContentTrustError::InvalidMetadata(role.to_owned(), e))?;
+    if metadata.kind != role {
+        return Err(ContentTrustError::UnexpectedMetadataType {
+            expected: role.to_owned(),
+            found: metadata.kind,
+        });
+    }
+
+    let trusted = match trusted {
+        Some(trusted) => trusted.clone(),
+        None => delegation(&metadata, role, role)?,
+    };
+    if !is_signed_by(
+        &canonical_json(&signable.signed),
+        &signable.signatures,
+        &trusted,
+    ) {
+        return Err(ContentTrustError::InvalidMetadataSignature(role.to_owned()));
+    }
+
+    if metadata.expiration < Utc::now() {
+        return Err(ContentTrustError::Expired(
+            role.to_owned(),
+            metadata.expiration,
+        ));
+    }
+
+    Ok(metadata)
+}
+
+/// Returns the delegation to `to` from the metadata of the role `from`.
+fn delegation(
+    metadata: &RoleMetadata,
+    from: &str,
+    to: &str,
+) -> Result<Delegation, ContentTrustError> {
+    metadata
+        .delegations
+        .get(to)
+        .cloned()
+        .ok_or_else(|| ContentTrustError::MissingDelegation(from.to_owned(), to.to_owned()))
+}
+
+/// Returns true if `message` is signed by at least `threshold` of the keys of
+/// the delegation.
+fn is_signed_by(
+    message: &str,
+    signatures: &HashMap<String, SignatureEntry>,
+    delegation: &Delegation,
+) -> bool {
+    let valid_signatures = delegation
+        .pubkeys
+        .iter()
+        .filter(|pubkey| {
+            signatures
+                .get(pubkey.as_str())
+                .is_some_and(|entry| verify_signature(message, pubkey, &entry.signature))
+        })
+        .count();
+    delegation.threshold > 0 && valid_signatures >= delegation.threshold
+}
+
+/// Verifies a single hex encoded ed25519 signature.
+fn verify_signature(message: &str, pubkey: &str, signature: &str) -> bool {
+    let Some(pubkey) = hex::decode(pubkey)
+        .ok()
+        .and_then(|bytes| <[u8; 32]>::try_from(bytes).ok())
+        .and_then(|bytes| VerifyingKey::from_bytes(&bytes).ok())
+    else {
+        return false;
+    };
+    let Some(signature) = hex::decode(signature)
+        .ok()
+        .and_then(|bytes| <[u8; 64]>::try_from(bytes).ok())
+        .map(|bytes| Signature::from_bytes(&bytes))
+    else {
+        return false;
+    };
+    pubkey.verify(message.as_bytes(), &signature).is_ok()
+}
+
+/// Serializes a value in the canonical form that is used by
+/// conda-content-trust. This is the same output as Python's
+/// `json.dumps(value, indent=2, sort_keys=True)`.
+fn canonical_json(value: &Value) -> String {
+    fn write_value(out: &mut String, value: &Value, indent: usize) {
+        match value {
+            Value::Null => out.push_str("null"),
+            Value::Bool(b) => out.push_str(if *b { "true" } else { "false" }),
+            Value::Number(n) => out.push_str(&n.to_string()),
+            Value::String(s) => write_string(out, s),
+            Value::Array(items) if items.is_empty() => out.push_str("[]"),
+            Value::Array(items) => {
+                out.push('[');
+                for (index, item) in items.iter().enumerate() {
+                    if index > 0 {
+                        out.push(',');
+                    }
+                    write_newline(out, indent + 1);
+                    write_value(out, item, indent + 1);
+                }
+                write_newline(out, indent);
+                out.push(']');
+            }
+            Value::Object(map) if map.is_empty() => out.push_str("{}"),
+            Value::Object(map) => {
+                let mut entries = map.iter().collect::<Vec<_>>();
+                entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+                out.push('{');
+                for (index, (key, value)) in entries.into_iter().enumerate() {
+                    if index > 0 {
+                        out.push(',');
+                    }
+                    write_newline(out, indent + 1);
+                    write_string(out, key);
+                    out.push_str(": ");
+                    write_value(out, value, indent + 1);
+                }
+                write_newline(out, indent);
+                out.push('}');
+            }
+        }
+    }
+
+    fn write_newline(out: &mut String, indent: usize) {
+        out.push('\n');
+        out.extend(std::iter::repeat(' ').take(indent * 2));
+    }
+
+    fn write_string(out: &mut String, s: &str) {
+        out.push('"');
+        for c in s.chars() {
+            match c {
+                '"' => out.push_str("\\\""),
+                '\\' => out.push_str("\\\\"),
+                '\n' => out.push_str("\\n"),
+                '\r' => out.push_str("\\r"),
+                '\t' => out.push_str("\\t"),
+                '\u{08}' => out.push_str("\\b"),
+                '\u{0c}' => out.push_str("\\f"),
+                ' '..='~' => out.push(c),
+                _ => {
+                    // Like Python, escape everything that is not printable ASCII.
+                    let mut buf = [0u16; 2];
+                    for unit in c.encode_utf16(&mut buf) {
+                        write!(out, "\\u{unit:04x}").expect("writing to a string cannot fail");
+                    }
+                }
+            }
+        }
+        out.push('"');
+    }
+
+    let mut out = String::new();
+    write_value(&mut out, value, 0);
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use ed25519_dalek::{Signer, SigningKey};
+    use serde_json::{json, Value};
+
+    use super::{canonical_json, ContentTrust, ContentTrustError};
+
+    fn sign(key: &SigningKey, signed: &Value) -> Value {
+        let pubkey = hex::encode(key.verifying_key().as_bytes());
+        let signature = hex::encode(key.sign(canonical_json(signed).as_bytes()).to_bytes());
+        json!({ pubkey: { "signature": signature } })
+    }
+
+    fn role(key: &SigningKey, kind: &str, delegate: &str, delegate_key: &SigningKey) -> String {
+        let signed = json!({
+            "type": kind,
+            "version": 1,
+            "metadata_spec_version": "0.6.0",
+            "expiration": "2100-01-01T00:00:00Z",
+            "delegations": {
+                delegate: {
+                    "pubkeys": [hex::encode(delegate_key.verifying_key().as_bytes())],
+                    "threshold": 1
+                }
+            }
+        });
+        json!({ "signatures": sign(key, &signed), "signed": signed }).to_string()
+    }
+
+    #[test]
+    fn test_canonical_json() {
+        let value = json!({ "b": [1, "ü"], "a": {}, "c": [] });
+        assert_eq!(
+            canonical_json(&value),
+            "{\n  \"a\": {},\n  \"b\": [\n    1,\n    \"\\u00fc\"\n  ],\n  \"c\": []\n}"
+        );
+    }
+
+    #[test]
+    fn test_verify_repo_data() {
+        let root_key = SigningKey::from_bytes(&[1; 32]);
+        let key_mgr_key = SigningKey::from_bytes(&[2; 32]);
+        let pkg_mgr_key = SigningKey::from_bytes(&[3; 32]);
+
+        // The root must delegate to the key_mgr.
+        let root_json = role(&root_key, "root", "root", &root_key);
+        let root_without_key_mgr = ContentTrust::from_root_json(&root_json);
+        assert!(matches!(
+            root_without_key_mgr,
+            Err(ContentTrustError::MissingDelegation(..))
+        ));
+
+        let mut root: Value = serde_json::from_str(&root_json).unwrap();
+        root["signed"]["delegations"]["key_mgr"] = json!({
+            "pubkeys": [hex::encode(key_mgr_key.verifying_key().as_bytes())],
+            "threshold": 1
+        });
+        root["signatures"] = sign(&root_key, &root["signed"]);
+        let content_trust = ContentTrust::from_root_json(&root.to_string()).unwrap();
+
+        let key_mgr_json = role(&key_mgr_key, "key_mgr", "pkg_mgr", &pkg_mgr_key);
+        let record = json!({ "name": "foo", "version": "1.0", "build": "0", "depends": [] });
+        let repo_data = json!({
+            "packages.conda": { "foo-1.0-0.conda": record },
+            "signatures": { "foo-1.0-0.conda": sign(&pkg_mgr_key, &record) }
+        });
+
+        content_trust
+            .verify_repo_data(&key_mgr_json, &repo_data.to_string())
+            .unwrap();
+
+        // The key_mgr must be signed by the key that is trusted by the root.
+        let untrusted_key_mgr_json = role(&pkg_mgr_key, "key_mgr", "pkg_mgr", &pkg_mgr_key);
+        assert!(matches!(
+            content_trust.verify_repo_data(&untrusted_key_mgr_json, &repo_data.to_string()),
+            Err(ContentTrustError::InvalidMetadataSignature(_))
+        ));
+
+        // Tampering with a record invalidates its signature.
+        let mut tampered = repo_data.clone();
+        tampered["packages.conda"]["foo-1.0-0.conda"]["depends"] = json!(["evil"]);
+        assert!(matches!(
+            content_trust.verify_repo_data(&key_mgr_json, &tampered.to_string()),
+            Err(ContentTrustError::InvalidSignature(name)) if name == "foo-1.0-0.conda"
+        ));
+
+        // Repodata without signatures is unsigned.
+        let unsigned = json!({ "packages.conda": { "foo-1.0-0.conda": record } });
+        assert!(matches!(
+            content_trust.verify_repo_data(&key_mgr_json, &unsigned.to_string()),
+            Err(ContentTrustError::UnsignedChannel)
+        ));
+    }
+}
@@ -0,0 +1,70 @@
+//! Comparing two versions of the repodata of a subdirectory.
+
+use std::collections::BTreeMap;
+
+use rattler_conda_types::{PackageRecord, RepoData};
+
+/// A record whose contents differ between two versions of the repodata.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChangedRecord {
+    /// The record in the previous version of the repodata.
+    pub previous: PackageRecord,
+
+    /// The record in the current version of the repodata.
+    pub current: PackageRecord,
+}
+
+/// The differences between two versions of the repodata of a subdirectory.
+/// Records are identified by their filename, e.g.
+/// `python-3.12.0-h1234_0.conda`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RepoDataDiff {
+    /// Records that are only in the current version.
+    pub added: BTreeMap<String, PackageRecord>,
+
+    /// Records that are only in the previous version.
+    pub removed: BTreeMap<String, PackageRecord>,
+
+    /// Records that are in both versions but whose contents differ, e.g.
+    /// because their dependencies were patched.
+    pub changed: BTreeMap<String, ChangedRecord>,
+}
+
+impl RepoDataDiff {
+    /// Computes the differences between the `previous` and the `current`
+    /// version of the repodata. Both `.tar.bz2` and `.conda` records are
+    /// compared.
+    pub fn new(previous: RepoData, current: RepoData) -> Self {
+        let mut removed: BTreeMap<String, PackageRecord> = previous
+            .packages
+            .into_iter()
+            .chain(previous.conda_packages)
+            .collect();
+
+        let mut diff = Self::default();
+        for (filename, record) in current.packages.into_iter().chain(current.conda_packages) {
+            match removed.remove(&filename) {
+                None => {
+                    diff.added.insert(filename, record);
+                }
+                Some(previous) if previous != record => {
+                    diff.changed.insert(
+                        filename,
+                        ChangedRecord {
+                            previous,
+                            current: record,
+                        },
+                    );
+                }
+                Some(_) => {}
+            }
+        }
+        diff.removed = removed;
+        diff
+    }
+
+    /// Returns true if both versions contain the same records.
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
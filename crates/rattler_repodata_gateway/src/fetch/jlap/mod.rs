@@ -71,6 +71,7 @@
 //!         subdir_url,
 //!         repo_data_state,
 //!         &current_repo_data,
+//!         &reqwest::header::HeaderMap::default(),
 //!         None
 //!     ).await.unwrap();
 //!
@@ -84,6 +85,7 @@ use blake2::digest::{FixedOutput, Update};
 use rattler_digest::{
     parse_digest_from_hex, serde::SerializableHash, Blake2b256, Blake2b256Hash, Blake2bMac256,
 };
+use rattler_error::ErrorCode;
 use rattler_redaction::Redact;
 use reqwest::{
     header::{HeaderMap, HeaderValue},
@@ -167,6 +169,22 @@ pub enum JLAPError {
     Cancelled,
 }
 
+impl ErrorCode for JLAPError {
+    fn code(&self) -> &'static str {
+        match self {
+            JLAPError::JSONParse(_) => "rattler::jlap::json_parse",
+            JLAPError::JSONPatch(_) => "rattler::jlap::json_patch",
+            JLAPError::HTTP(_) => "rattler::jlap::http",
+            JLAPError::FileSystem(_) => "rattler::jlap::file_system",
+            JLAPError::NoHashFound => "rattler::jlap::no_hash_found",
+            JLAPError::ChecksumMismatch => "rattler::jlap::checksum_mismatch",
+            JLAPError::ChecksumParse => "rattler::jlap::checksum_parse",
+            JLAPError::InvalidResponse => "rattler::jlap::invalid_response",
+            JLAPError::Cancelled => "rattler::jlap::cancelled",
+        }
+    }
+}
+
 impl From<Cancelled> for JLAPError {
     fn from(_: Cancelled) -> Self {
         JLAPError::Cancelled
@@ -408,12 +426,15 @@ fn get_bytes_offset(lines: &[&str]) -> u64 {
 /// At the end, we compare the new `blake2b` hash with what was listed in the JLAP metadata to
 /// ensure the file is correct.
 ///
+/// The `headers` are sent along with the request for the JLAP file.
+///
 /// The return value is the updated [`JLAPState`] and the Blake2b256 hash of the new file.
 pub async fn patch_repo_data(
     client: &ClientWithMiddleware,
     subdir_url: Url,
     repo_data_state: RepoDataState,
     repo_data_json_path: &Path,
+    headers: &HeaderMap,
     reporter: Option<Arc<dyn Reporter>>,
 ) -> Result<(JLAPState, Blake2b256Hash), JLAPError> {
     // Determine what we should use as our starting state
@@ -427,7 +448,7 @@ pub async fn patch_repo_data(
         .as_deref()
         .map(|reporter| (reporter, reporter.on_download_start(&jlap_url)));
     let (response, position) =
-        fetch_jlap_with_retry(&jlap_url, client, jlap_state.position).await?;
+        fetch_jlap_with_retry(&jlap_url, client, headers, jlap_state.position).await?;
     let jlap_response_url = response.url().clone();
     let response_text = match response.text_with_progress(download_report).await {
         Ok(value) => value,
@@ -467,10 +488,11 @@ pub async fn patch_repo_data(
 async fn fetch_jlap(
     url: &Url,
     client: &ClientWithMiddleware,
+    headers: &HeaderMap,
     range: &str,
 ) -> reqwest_middleware::Result<Response> {
     let request_builder = client.get(url.clone());
-    let mut headers = HeaderMap::default();
+    let mut headers = headers.clone();
 
     headers.insert(
         reqwest::header::RANGE,
@@ -491,19 +513,20 @@ async fn fetch_jlap(
 async fn fetch_jlap_with_retry(
     url: &Url,
     client: &ClientWithMiddleware,
+    headers: &HeaderMap,
     position: u64,
 ) -> Result<(Response, u64), JLAPError> {
     tracing::info!("fetching JLAP state from {url} (bytes={position}-)");
     let range = format!("bytes={position}-");
 
-    match fetch_jlap(url, client, &range).await {
+    match fetch_jlap(url, client, headers, &range).await {
         Ok(response) => {
             if response.status() == StatusCode::RANGE_NOT_SATISFIABLE && position != 0 {
                 tracing::warn!(
                     "JLAP range request could not be satisfied, fetching the entire file.."
                 );
                 let range = "bytes=0-";
-                return match fetch_jlap(url, client, range).await {
+                return match fetch_jlap(url, client, headers, range).await {
                     Ok(response) => Ok((response, 0)),
                     Err(error) => Err(error.into()),
                 };
@@ -626,6 +649,7 @@ fn blake2b_256_hash_with_key(data: &[u8], key: &[u8]) -> Output<Blake2bMac256> {
 #[cfg(test)]
 mod test {
     use super::patch_repo_data;
+    use reqwest::header::HeaderMap;
     use std::path::PathBuf;
 
     use crate::fetch::cache::RepoDataState;
@@ -948,6 +972,7 @@ mod test {
             test_env.server_url,
             test_env.repo_data_state,
             &test_env.cache_repo_data,
+            &HeaderMap::default(),
             None,
         )
         .await
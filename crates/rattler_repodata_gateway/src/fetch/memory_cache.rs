@@ -0,0 +1,82 @@
+//! An in-memory layer on top of the on-disk repodata cache.
+
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::SystemTime,
+};
+
+use dashmap::DashMap;
+
+use super::cache::RepoDataState;
+
+/// Remembers the state of the repodata cache entries that were validated by
+/// this process.
+///
+/// Validating a cache entry requires reading its state from disk and hashing
+/// the cached `repodata.json` which can take a while for large channels. If
+/// the same subdir is fetched multiple times, an entry from this cache is used
+/// instead as long as the cached repodata file on disk was not modified since.
+/// Entries are keyed by the path of that file, which is the `.json.zst` file if
+/// the repodata is stored compressed. The lock on the cache entry is still
+/// acquired for every fetch.
+///
+/// Cloning this type is cheap, all clones share the same entries.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryCache {
+    entries: Arc<DashMap<PathBuf, MemoryCacheEntry>>,
+}
+
+#[derive(Debug, Clone)]
+struct MemoryCacheEntry {
+    cache_state: RepoDataState,
+    size: u64,
+    last_modified: SystemTime,
+}
+
+impl MemoryCache {
+    /// Constructs a new empty cache.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Removes all entries from the cache.
+    pub fn clear(&self) {
+        self.entries.clear();
+    }
+
+    /// Returns the cache state and the last modification date of the
+    /// `repodata.json` at the given path if it did not change since it was
+    /// stored.
+    pub(crate) fn get(&self, repo_data_json_path: &Path) -> Option<(RepoDataState, SystemTime)> {
+        let entry = self.entries.get(repo_data_json_path)?.clone();
+        let unchanged = std::fs::metadata(repo_data_json_path).is_ok_and(|metadata| {
+            metadata.len() == entry.size && metadata.modified().ok() == Some(entry.last_modified)
+        });
+        if unchanged {
+            Some((entry.cache_state, entry.last_modified))
+        } else {
+            self.entries.remove(repo_data_json_path);
+            None
+        }
+    }
+
+    /// Stores the validated cache state of the `repodata.json` at the given
+    /// path.
+    pub(crate) fn insert(&self, repo_data_json_path: &Path, cache_state: RepoDataState) {
+        let Some((size, last_modified)) = std::fs::metadata(repo_data_json_path)
+            .and_then(|metadata| Ok((metadata.len(), metadata.modified()?)))
+            .ok()
+        else {
+            return;
+        };
+        self.entries.insert(
+            repo_data_json_path.to_path_buf(),
+            MemoryCacheEntry {
+                cache_state,
+                size,
+                last_modified,
+            },
+        );
+    }
+}
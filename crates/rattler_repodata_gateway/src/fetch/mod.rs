@@ -1,13 +1,14 @@
 //! This module provides functionality to download and cache `repodata.json` from a remote location.
 
-use crate::reporter::ResponseReporterExt;
 use crate::utils::{AsyncEncoding, Encoding, LockedFile};
-use crate::Reporter;
-use cache::{CacheHeaders, Expiring, RepoDataState};
+use crate::{ConcurrencyLimiter, Reporter};
+use cache::{CacheHeaders, Expiring, RepoDataState, CACHE_FORMAT_VERSION};
 use cache_control::{Cachability, CacheControl};
-use futures::{future::ready, FutureExt, TryStreamExt};
+use futures::{future::ready, FutureExt, StreamExt, TryStreamExt};
 use humansize::{SizeFormatter, DECIMAL};
-use rattler_digest::{compute_file_digest, Blake2b256, HashingWriter};
+use previous::PreviousRepoData;
+use rattler_digest::{compute_file_digest, digest::Digest, Blake2b256, HashingWriter, Sha256};
+use rattler_error::ErrorCode;
 use rattler_redaction::Redact;
 use reqwest::{
     header::{HeaderMap, HeaderValue},
@@ -15,17 +16,62 @@ use reqwest::{
 };
 use std::sync::Arc;
 use std::{
-    io::ErrorKind,
+    io::{BufReader, ErrorKind, Read},
+    num::NonZeroU64,
     path::{Path, PathBuf},
-    time::SystemTime,
+    time::{Instant, SystemTime},
 };
 use tempfile::NamedTempFile;
-use tokio_util::io::StreamReader;
-use tracing::instrument;
+use tokio_util::{io::StreamReader, sync::CancellationToken};
+use tracing::{field::Empty, instrument, Span};
 use url::Url;
-
-mod cache;
+use zchunk::ZchunkFetch;
+
+#[cfg(feature = "sparse")]
+mod binary_cache;
+pub(crate) mod cache;
+#[cfg(feature = "sparse")]
+mod channel;
+mod checksum;
+mod conda_token;
+mod content_trust;
+#[cfg(feature = "sparse")]
+mod diff;
 pub mod jlap;
+mod memory_cache;
+mod multipart;
+#[cfg(feature = "sparse")]
+mod patch_instructions;
+mod previous;
+mod redirect;
+mod resume;
+mod retry;
+mod revalidate;
+#[cfg(feature = "sparse")]
+mod run_exports;
+mod throttle;
+mod timeout;
+mod validate;
+mod zchunk;
+
+#[cfg(feature = "sparse")]
+pub use channel::{fetch_channel_repo_data, ChannelRepoData};
+pub use content_trust::{ContentTrust, ContentTrustError};
+#[cfg(feature = "sparse")]
+pub use diff::{ChangedRecord, RepoDataDiff};
+pub use memory_cache::MemoryCache;
+pub use multipart::MultipartDownload;
+#[cfg(feature = "sparse")]
+pub use patch_instructions::fetch_patch_instructions;
+pub use redirect::{CrossOriginCredentials, RedirectError, RedirectPolicy};
+pub use retry::RetryOptions;
+pub use revalidate::{fetch_repo_data_stale_while_revalidate, RefreshHandle, StaleWhileRevalidate};
+#[cfg(feature = "sparse")]
+pub use run_exports::fetch_run_exports;
+
+/// Blocking version of [`fetch_repo_data`] for code that does not use an async runtime.
+#[cfg(feature = "blocking")]
+pub use crate::blocking::fetch_repo_data as fetch_repo_data_blocking;
 
 /// `RepoData` could not be found for given channel and platform
 #[derive(Debug, thiserror::Error)]
@@ -45,6 +91,9 @@ pub enum FetchRepoDataError {
     #[error("failed to acquire a lock on the repodata cache")]
     FailedToAcquireLock(#[source] anyhow::Error),
 
+    #[error("timed out after {0:?} waiting for another process to release the lock on the repodata cache")]
+    CacheLockTimedOut(std::time::Duration),
+
     #[error(transparent)]
     HttpError(reqwest_middleware::Error),
 
@@ -60,6 +109,16 @@ pub enum FetchRepoDataError {
     #[error("failed to create temporary file for repodata.json")]
     FailedToCreateTemporaryFile(#[source] std::io::Error),
 
+    #[error("the downloaded repodata is corrupt")]
+    CorruptRepoData(#[source] std::io::Error),
+
+    #[error("the SHA-256 hash of the downloaded repodata is {actual} but the {header} header of the response is {expected}")]
+    ChecksumMismatch {
+        header: String,
+        expected: String,
+        actual: String,
+    },
+
     #[error("failed to persist temporary repodata.json file")]
     FailedToPersistTemporaryFile(#[from] tempfile::PersistError),
 
@@ -74,16 +133,78 @@ pub enum FetchRepoDataError {
 
     #[error("the operation was cancelled")]
     Cancelled,
+
+    #[error("the operation timed out")]
+    TimedOut,
+
+    #[error("failed to verify the signatures of the repodata")]
+    ContentTrust(#[from] ContentTrustError),
+}
+
+impl ErrorCode for RepoDataNotFoundError {
+    fn code(&self) -> &'static str {
+        match self {
+            RepoDataNotFoundError::HttpError(_) => "rattler::fetch_repodata::not_found::http",
+            RepoDataNotFoundError::FileSystemError(_) => {
+                "rattler::fetch_repodata::not_found::file_system"
+            }
+        }
+    }
+}
+
+impl ErrorCode for FetchRepoDataError {
+    fn code(&self) -> &'static str {
+        match self {
+            FetchRepoDataError::FailedToAcquireLock(_) => {
+                "rattler::fetch_repodata::failed_to_acquire_lock"
+            }
+            FetchRepoDataError::CacheLockTimedOut(_) => {
+                "rattler::fetch_repodata::cache_lock_timed_out"
+            }
+            FetchRepoDataError::HttpError(_) => "rattler::fetch_repodata::http",
+            FetchRepoDataError::IoError(_) => "rattler::fetch_repodata::io",
+            FetchRepoDataError::FailedToDownload(..) => {
+                "rattler::fetch_repodata::failed_to_download"
+            }
+            FetchRepoDataError::NotFound(err) => err.code(),
+            FetchRepoDataError::FailedToCreateTemporaryFile(_) => {
+                "rattler::fetch_repodata::failed_to_create_temporary_file"
+            }
+            FetchRepoDataError::CorruptRepoData(_) => "rattler::fetch_repodata::corrupt_repodata",
+            FetchRepoDataError::ChecksumMismatch { .. } => {
+                "rattler::fetch_repodata::checksum_mismatch"
+            }
+            FetchRepoDataError::FailedToPersistTemporaryFile(_) => {
+                "rattler::fetch_repodata::failed_to_persist_temporary_file"
+            }
+            FetchRepoDataError::FailedToGetMetadata(_) => {
+                "rattler::fetch_repodata::failed_to_get_metadata"
+            }
+            FetchRepoDataError::FailedToWriteCacheState(_) => {
+                "rattler::fetch_repodata::failed_to_write_cache_state"
+            }
+            FetchRepoDataError::NoCacheAvailable => "rattler::fetch_repodata::no_cache_available",
+            FetchRepoDataError::Cancelled => "rattler::fetch_repodata::cancelled",
+            FetchRepoDataError::TimedOut => "rattler::fetch_repodata::timed_out",
+            FetchRepoDataError::ContentTrust(err) => err.code(),
+        }
+    }
 }
 
 impl From<reqwest_middleware::Error> for FetchRepoDataError {
     fn from(err: reqwest_middleware::Error) -> Self {
+        if timeout::is_timeout(&err) {
+            return Self::TimedOut;
+        }
         Self::HttpError(err.redact())
     }
 }
 
 impl From<reqwest::Error> for FetchRepoDataError {
     fn from(err: reqwest::Error) -> Self {
+        if err.is_timeout() {
+            return Self::TimedOut;
+        }
         Self::HttpError(err.redact().into())
     }
 }
@@ -121,6 +242,10 @@ pub enum CacheAction {
 
     /// Do not use the cache even if there is an up to date entry.
     NoCache,
+
+    /// Use the cache if it is younger than the given duration or fetch from the URL otherwise.
+    /// This overrides the `max-age` that is advertised by the server.
+    RefreshIfOlderThan(std::time::Duration),
 }
 
 /// Defines which type of repodata.json file to download. Usually you want to use the
@@ -159,6 +284,28 @@ impl Variant {
     }
 }
 
+/// Defines which compressed variant of the repodata file to download.
+#[derive(Default, Copy, Clone, Debug, PartialEq, Eq)]
+pub enum CompressionVariant {
+    /// Determine which variants are available with `HEAD` requests and download the best one.
+    /// The availability is cached so the requests are not repeated every time.
+    #[default]
+    Auto,
+
+    /// Always download the zstd compressed `.zst` file without checking whether it is available.
+    Zst,
+
+    /// Always download the bzip2 compressed `.bz2` file without checking whether it is available.
+    Bz2,
+
+    /// Always download the uncompressed file.
+    Plain,
+}
+
+/// The default duration for which the availability of the variants of a `repodata.json` is cached.
+pub const DEFAULT_VARIANT_AVAILABILITY_EXPIRATION: std::time::Duration =
+    std::time::Duration::from_secs(14 * 24 * 60 * 60);
+
 /// Additional knobs that allow you to tweak the behavior of [`fetch_repo_data`].
 #[derive(Clone)]
 pub struct FetchRepoDataOptions {
@@ -172,11 +319,136 @@ pub struct FetchRepoDataOptions {
     /// When enabled repodata can be fetched incrementally using JLAP
     pub jlap_enabled: bool,
 
+    /// When enabled repodata can be fetched incrementally from a zchunk (`.zck`) file if the
+    /// server provides one. Only the chunks that changed since the last fetch are downloaded. This
+    /// is disabled by default because checking the availability requires an additional request.
+    /// Only applies to [`CompressionVariant::Auto`].
+    pub zchunk_enabled: bool,
+
     /// When enabled, the zstd variant will be used if available
     pub zstd_enabled: bool,
 
     /// When enabled, the bz2 variant will be used if available
     pub bz2_enabled: bool,
+
+    /// Determines which compressed variant to download. If a specific variant is selected the
+    /// server is not probed for the available variants at all, which also means JLAP is only used
+    /// if its availability is known from a previous fetch. `zstd_enabled` and `bz2_enabled` only
+    /// apply to [`CompressionVariant::Auto`].
+    pub compression: CompressionVariant,
+
+    /// How long the availability of the variants (`.zst`, `.bz2` and JLAP) that was determined
+    /// with `HEAD` requests is cached. Set this to [`std::time::Duration::ZERO`] to check the
+    /// availability again without discarding the rest of the cache.
+    pub variant_availability_expiration: std::time::Duration,
+
+    /// Determines how requests that fail because of transient network errors are retried.
+    pub retry: RetryOptions,
+
+    /// When enabled and the server cannot be reached, an outdated cache is used instead of
+    /// returning an error. The result is marked with [`CacheResult::StaleCacheFallback`].
+    pub stale_if_error: bool,
+
+    /// When the token is cancelled the fetch is aborted with [`FetchRepoDataError::Cancelled`].
+    /// The lock on the cache is released and any partially downloaded file is removed.
+    pub cancellation_token: Option<CancellationToken>,
+
+    /// An in-memory cache that is shared between fetches within this process. If set, cache
+    /// entries that were already validated are not read from disk again as long as they did not
+    /// change.
+    pub memory_cache: Option<MemoryCache>,
+
+    /// Fetch the `current_repodata.json` instead of the `repodata.json` if the channel provides
+    /// it. The `current_repodata.json` only contains the latest versions of the packages which
+    /// makes it a lot smaller. Only applies to [`Variant::AfterPatches`].
+    pub prefer_current_repodata: bool,
+
+    /// Verify the signatures of the packages in the repodata using the trusted root metadata. If
+    /// set, fetching fails if the channel is not signed or a signature is invalid.
+    pub content_trust: Option<ContentTrust>,
+
+    /// Additional headers that are sent with every request to the subdirectory (e.g. an
+    /// `X-Api-Key`). The headers the fetcher adds itself (like the cache headers) take
+    /// precedence.
+    pub headers: HeaderMap,
+
+    /// An anaconda.org token that is added to the urls of the requests as `/t/<token>/`. A token
+    /// that is already part of the subdirectory url is used as well. The token is never stored in
+    /// the cache, the cache key and the cache state are based on the url without the token.
+    pub conda_token: Option<String>,
+
+    /// Limits the rate at which the repodata is downloaded to the given number of bytes per
+    /// second. By default the download is not limited.
+    pub download_rate_limit: Option<NonZeroU64>,
+
+    /// Download large files in several parts in parallel if the server supports range requests,
+    /// which reduces the duration of the download on connections with a high latency. The parts
+    /// are kept in memory until they are written to the cache. Files are never downloaded in parts
+    /// if the [`Self::download_rate_limit`] is set.
+    pub multipart_download: Option<MultipartDownload>,
+
+    /// Limits the number of fetches that access the network at the same time. Share the same
+    /// limiter between all fetches that should be limited together.
+    pub concurrency_limiter: Option<ConcurrencyLimiter>,
+
+    /// The maximum duration of the whole fetch, including waiting for the lock on the cache. If
+    /// the fetch does not complete in time it is aborted with [`FetchRepoDataError::TimedOut`].
+    pub timeout: Option<std::time::Duration>,
+
+    /// The maximum duration to wait for the server to respond to a request, including connecting
+    /// to the server. A request that times out is retried according to the retry options.
+    pub connect_timeout: Option<std::time::Duration>,
+
+    /// The maximum duration to wait for the next bytes while the repodata is downloaded.
+    pub read_timeout: Option<std::time::Duration>,
+
+    /// The maximum duration to wait for another process to release the lock on the cache. If
+    /// the lock is not acquired in time the fetch fails with
+    /// [`FetchRepoDataError::CacheLockTimedOut`]. By default the fetch waits indefinitely.
+    pub cache_lock_timeout: Option<std::time::Duration>,
+
+    /// Whether to check that the downloaded repodata is complete and valid JSON before it is
+    /// stored in the cache. A corrupt download fails with [`FetchRepoDataError::CorruptRepoData`]
+    /// instead of being used until the repodata on the server changes. This requires reading the
+    /// file once more after it has been downloaded.
+    pub validate_repo_data: bool,
+
+    /// Store the downloaded `.zst` variant of the repodata in the cache as-is instead of
+    /// decompressing it, which considerably reduces the disk space used by the cache. The
+    /// repodata is decompressed when it is read, see [`CachedRepoData::repo_data_reader`]. If the
+    /// server does not provide a `.zst` variant the repodata is stored uncompressed. JLAP and
+    /// zchunk are not used to update a compressed cache.
+    ///
+    /// A compressed cache entry is never returned if this is disabled, the repodata is fetched
+    /// again instead.
+    pub keep_compressed: bool,
+
+    /// Follow redirects explicitly according to this policy, which determines whether the
+    /// credentials of a request (the `Authorization` header, cookies and the [`Self::headers`])
+    /// are forwarded when a channel redirects to another host, e.g. a CDN or a signed url. This
+    /// requires a client that does not follow redirects itself, requests that are redirected by
+    /// the client fail with [`RedirectError::ClientFollowsRedirects`]. By default the client
+    /// follows the redirects.
+    pub redirect_policy: Option<RedirectPolicy>,
+
+    /// Keep the previous version of the cached repodata when it changes, next to the current
+    /// version. This allows computing what changed since the repodata was fetched the last time,
+    /// see [`CachedRepoData::diff_previous`]. Only a single previous version is kept and it is
+    /// only replaced when the repodata changes.
+    pub keep_previous: bool,
+
+    /// Separates the cache entries of this consumer from those of other consumers that share the
+    /// same cache directory, e.g. two applications or two identities with different credentials
+    /// for the same channel. The namespace is part of the names of the files in the cache and is
+    /// stored in the cache state, an entry that was written for another namespace is never used.
+    pub cache_namespace: Option<String>,
+
+    /// Verify the downloaded repodata against the SHA-256 hash that some servers (e.g.
+    /// Artifactory) send along with the file in an `X-Checksum-Sha256` header. The `ETag` is
+    /// never interpreted as a checksum. A mismatch fails with
+    /// [`FetchRepoDataError::ChecksumMismatch`] instead of storing the repodata in the cache. The verified hash is recorded in
+    /// [`RepoDataState::verified_sha256`]. Enabled by default.
+    pub verify_checksum_headers: bool,
 }
 
 impl Default for FetchRepoDataOptions {
@@ -185,8 +457,32 @@ impl Default for FetchRepoDataOptions {
             cache_action: CacheAction::default(),
             variant: Variant::default(),
             jlap_enabled: true,
+            zchunk_enabled: false,
             zstd_enabled: true,
             bz2_enabled: true,
+            compression: CompressionVariant::default(),
+            variant_availability_expiration: DEFAULT_VARIANT_AVAILABILITY_EXPIRATION,
+            retry: RetryOptions::default(),
+            stale_if_error: false,
+            cancellation_token: None,
+            memory_cache: None,
+            prefer_current_repodata: false,
+            content_trust: None,
+            headers: HeaderMap::default(),
+            conda_token: None,
+            download_rate_limit: None,
+            multipart_download: None,
+            concurrency_limiter: None,
+            timeout: None,
+            connect_timeout: None,
+            read_timeout: None,
+            cache_lock_timeout: None,
+            validate_repo_data: false,
+            keep_compressed: false,
+            redirect_policy: None,
+            keep_previous: false,
+            cache_namespace: None,
+            verify_checksum_headers: true,
         }
     }
 }
@@ -197,7 +493,10 @@ pub struct CachedRepoData {
     /// A lockfile that guards access to any of the repodata.json file or its cache.
     pub lock_file: LockedFile,
 
-    /// The path to the uncompressed repodata.json file.
+    /// The path to the repodata.json file. This file is compressed with zstd if
+    /// [`RepoDataState::compressed`] is set, which only happens if
+    /// [`FetchRepoDataOptions::keep_compressed`] is enabled. Use
+    /// [`CachedRepoData::repo_data_reader`] to read the file regardless of how it is stored.
     pub repo_data_json_path: PathBuf,
 
     /// The cache data.
@@ -205,6 +504,180 @@ pub struct CachedRepoData {
 
     /// How the cache was used for this request.
     pub cache_result: CacheResult,
+
+    /// Timings and sizes that were recorded while fetching.
+    pub metrics: FetchMetrics,
+}
+
+/// Timings and sizes that were recorded by [`fetch_repo_data`], e.g. to find out why fetching
+/// took long.
+#[derive(Debug, Clone, Default)]
+pub struct FetchMetrics {
+    /// How long it took to acquire the lock on the cache. This is the time spent waiting for other
+    /// threads or processes that were using the same cache entry.
+    pub lock_wait: std::time::Duration,
+
+    /// How long it took to determine which variants of the repodata are available, which requires
+    /// `HEAD` requests if the availability is not known from the cache.
+    pub variant_check: std::time::Duration,
+
+    /// The number of bytes that were received to download the repodata. If a compressed variant
+    /// was downloaded this is the compressed size.
+    pub downloaded_bytes: u64,
+
+    /// How long it took to download and decompress the repodata. The repodata is decompressed
+    /// while it is received so this includes both.
+    pub download: std::time::Duration,
+
+    /// How long it took to write the repodata and its state to the cache.
+    pub cache_write: std::time::Duration,
+}
+
+impl CachedRepoData {
+    /// Opens the cached repodata.json file for reading. If the file is stored compressed it is
+    /// decompressed while it is read.
+    pub fn repo_data_reader(&self) -> std::io::Result<Box<dyn Read + Send>> {
+        open_cached_repo_data(&self.repo_data_json_path, self.cache_state.compressed)
+    }
+}
+
+/// Opens the cached repodata at `path` for reading, decompressing it on the fly if it is stored
+/// `compressed`.
+pub(crate) fn open_cached_repo_data(
+    path: &Path,
+    compressed: bool,
+) -> std::io::Result<Box<dyn Read + Send>> {
+    let file = BufReader::new(std::fs::File::open(path)?);
+    if compressed {
+        Ok(Box::new(zstd::Decoder::with_buffer(file)?))
+    } else {
+        Ok(Box::new(file))
+    }
+}
+
+#[cfg(feature = "sparse")]
+impl CachedRepoData {
+    /// Parses the cached repodata.json file.
+    ///
+    /// The lock on the cache is held while the file is read, so the file cannot be modified by
+    /// another fetch in the meantime. After the first parse a binary representation of the
+    /// repodata is stored next to the repodata.json which is used on subsequent calls as long as
+    /// the repodata.json did not change.
+    pub async fn repo_data(&self) -> Result<rattler_conda_types::RepoData, std::io::Error> {
+        let repo_data_json_path = self.repo_data_json_path.clone();
+        let compressed = self.cache_state.compressed;
+        let hash = self.cache_state.blake2_hash;
+        run_blocking_io_task(move || {
+            binary_cache::read_repo_data(&repo_data_json_path, compressed, hash.as_ref())
+        })
+        .await
+    }
+
+    /// Parses the cached repodata.json file and converts it into records that belong to the given
+    /// channel. See [`CachedRepoData::repo_data`].
+    pub async fn repo_data_records(
+        &self,
+        channel: &rattler_conda_types::Channel,
+    ) -> Result<Vec<rattler_conda_types::RepoDataRecord>, std::io::Error> {
+        Ok(self.repo_data().await?.into_repo_data_records(channel))
+    }
+
+    /// Opens the cached repodata.json file as a [`crate::sparse::SparseRepoData`], from which the
+    /// records of individual packages can be loaded without parsing the whole file. A compressed
+    /// cache entry is decompressed into memory.
+    pub async fn sparse_repo_data(
+        &self,
+        channel: rattler_conda_types::Channel,
+    ) -> Result<crate::sparse::SparseRepoData, std::io::Error> {
+        let repo_data_json_path = self.repo_data_json_path.clone();
+        let compressed = self.cache_state.compressed;
+
+        // The repodata is stored at `<subdir>/repodata.json`.
+        let subdir = self
+            .cache_state
+            .url
+            .path_segments()
+            .and_then(|mut segments| segments.nth_back(1))
+            .unwrap_or_default()
+            .to_owned();
+
+        run_blocking_io_task(move || {
+            if compressed {
+                let mut bytes = Vec::new();
+                open_cached_repo_data(&repo_data_json_path, true)?.read_to_end(&mut bytes)?;
+                Ok(crate::sparse::SparseRepoData::from_bytes(
+                    channel,
+                    subdir,
+                    bytes.into(),
+                    None,
+                )?)
+            } else {
+                crate::sparse::SparseRepoData::new(channel, subdir, &repo_data_json_path, None)
+            }
+        })
+        .await
+    }
+
+    /// Loads the records of the packages with the given names and of all the packages they
+    /// depend on from the cached repodata.json file. The dependencies are discovered iteratively
+    /// from the loaded records. Only the records of these packages are parsed, which is a lot
+    /// faster than parsing the whole file when a small environment is solved.
+    ///
+    /// Use [`crate::sparse::SparseRepoData::load_records_recursive`] with the
+    /// [`CachedRepoData::sparse_repo_data`] of several subdirectories to discover the dependencies
+    /// across subdirectories and channels.
+    pub async fn load_records_recursive(
+        &self,
+        channel: &rattler_conda_types::Channel,
+        package_names: impl IntoIterator<Item = rattler_conda_types::PackageName>,
+    ) -> Result<Vec<rattler_conda_types::RepoDataRecord>, std::io::Error> {
+        let sparse = self.sparse_repo_data(channel.clone()).await?;
+        let package_names = package_names.into_iter().collect::<Vec<_>>();
+        run_blocking_io_task(move || {
+            let mut records = crate::sparse::SparseRepoData::load_records_recursive(
+                [&sparse],
+                package_names,
+                None,
+            )?;
+            Ok(records.pop().unwrap_or_default())
+        })
+        .await
+    }
+
+    /// Compares the cached repodata with the version it replaced, which is only kept if
+    /// [`FetchRepoDataOptions::keep_previous`] was enabled when the repodata was fetched. Returns
+    /// `None` if there is no previous version.
+    pub async fn diff_previous(&self) -> Result<Option<RepoDataDiff>, std::io::Error> {
+        let Some((previous_path, compressed)) =
+            previous::find_previous_repo_data(&self.repo_data_json_path)
+        else {
+            return Ok(None);
+        };
+        let previous = run_blocking_io_task(move || {
+            serde_json::from_reader::<_, rattler_conda_types::RepoData>(open_cached_repo_data(
+                &previous_path,
+                compressed,
+            )?)
+            .map_err(|err| std::io::Error::new(ErrorKind::InvalidData, err))
+        })
+        .await?;
+        let current = self.repo_data().await?;
+        Ok(Some(RepoDataDiff::new(previous, current)))
+    }
+}
+
+/// Runs a blocking task that reads from the cache on a separate thread. A panic in the task is
+/// propagated, if the task is cancelled an error is returned.
+#[cfg(feature = "sparse")]
+async fn run_blocking_io_task<T: Send + 'static>(
+    f: impl FnOnce() -> std::io::Result<T> + Send + 'static,
+) -> std::io::Result<T> {
+    tokio::task::spawn_blocking(f)
+        .await
+        .unwrap_or_else(|err| match err.try_into_panic() {
+            Ok(panic) => std::panic::resume_unwind(panic),
+            Err(err) => Err(std::io::Error::new(ErrorKind::Other, err.to_string())),
+        })
 }
 
 /// Indicates whether or not the repodata.json cache was up-to-date or not.
@@ -221,61 +694,201 @@ pub enum CacheResult {
 
     /// There was no cache available
     CacheNotPresent,
+
+    /// The server could not be reached so the outdated cache was used instead. This only happens
+    /// if [`FetchRepoDataOptions::stale_if_error`] is enabled.
+    StaleCacheFallback,
+}
+
+/// Returns a synthetic `ETag` for a local file, derived from its size and modification time
+/// (like most static file servers do). Changes when the file is modified.
+fn local_file_etag(metadata: &std::fs::Metadata) -> Result<String, FetchRepoDataError> {
+    let modified = metadata
+        .modified()
+        .map_err(FetchRepoDataError::FailedToGetMetadata)?
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default();
+    Ok(format!(
+        "\"{:x}-{:x}\"",
+        metadata.len(),
+        modified.as_nanos()
+    ))
 }
 
-/// handle file:/// urls
+/// Handles `file://` urls.
+///
+/// The repodata is copied to the cache, if only a compressed variant (`.zst` or `.bz2`) is
+/// available it is decompressed in the process. The cache state is written just like for remote
+/// repodata. The copy is reused as long as the source file does not change.
+#[allow(clippy::too_many_arguments)]
 async fn repodata_from_file(
-    subdir_url: Url,
+    subdir_url: &Url,
+    file_name: &str,
+    options: &FetchRepoDataOptions,
+    cache_path: &Path,
     out_path: PathBuf,
     cache_state_path: PathBuf,
     lock_file: LockedFile,
+    reporter: Option<&dyn Reporter>,
 ) -> Result<CachedRepoData, FetchRepoDataError> {
-    // copy file from subdir_url to out_path
-    if let Err(e) = tokio::fs::copy(&subdir_url.to_file_path().unwrap(), &out_path).await {
-        return if e.kind() == ErrorKind::NotFound {
-            Err(FetchRepoDataError::NotFound(
-                RepoDataNotFoundError::FileSystemError(e),
-            ))
-        } else {
-            Err(FetchRepoDataError::IoError(e))
-        };
+    let subdir_path = subdir_url.to_file_path().map_err(|_| {
+        FetchRepoDataError::IoError(std::io::Error::new(
+            ErrorKind::InvalidInput,
+            format!("'{subdir_url}' is not a valid local path"),
+        ))
+    })?;
+
+    // Find the available variant of the repodata. The uncompressed file is preferred because it
+    // doesn't have to be decoded.
+    let auto = options.compression == CompressionVariant::Auto;
+    let candidates = [
+        (
+            file_name.to_string(),
+            Encoding::Passthrough,
+            auto || options.compression == CompressionVariant::Plain,
+        ),
+        (
+            format!("{file_name}.zst"),
+            Encoding::Zst,
+            (auto && options.zstd_enabled) || options.compression == CompressionVariant::Zst,
+        ),
+        (
+            format!("{file_name}.bz2"),
+            Encoding::Bz2,
+            (auto && options.bz2_enabled) || options.compression == CompressionVariant::Bz2,
+        ),
+    ];
+    let mut source = None;
+    for (name, encoding, enabled) in candidates {
+        if !enabled {
+            continue;
+        }
+        let path = subdir_path.join(name);
+        match tokio::fs::metadata(&path).await {
+            Ok(metadata) => {
+                source = Some((path, encoding, metadata));
+                break;
+            }
+            Err(e) if e.kind() == ErrorKind::NotFound => {}
+            Err(e) => return Err(FetchRepoDataError::IoError(e)),
+        }
     }
-
-    // create a dummy cache state
-    let new_cache_state = RepoDataState {
-        url: subdir_url.clone(),
-        cache_size: tokio::fs::metadata(&out_path)
-            .await
-            .map_err(FetchRepoDataError::IoError)?
-            .len(),
-        cache_headers: CacheHeaders {
-            etag: None,
-            last_modified: None,
-            cache_control: None,
-        },
-        cache_last_modified: SystemTime::now(),
-        blake2_hash: None,
-        blake2_hash_nominal: None,
-        has_zst: None,
-        has_bz2: None,
-        has_jlap: None,
-        jlap: None,
+    let Some((source_path, encoding, source_metadata)) = source else {
+        return Err(FetchRepoDataError::NotFound(
+            RepoDataNotFoundError::FileSystemError(std::io::Error::new(
+                ErrorKind::NotFound,
+                format!("no {file_name} found in '{}'", subdir_path.display()),
+            )),
+        ));
+    };
+    let source_url = Url::from_file_path(&source_path).unwrap_or_else(|_| subdir_url.clone());
+    let etag = local_file_etag(&source_metadata)?;
+
+    // Reuse the previous copy if the source did not change since.
+    let previous_state = {
+        let cache_state_path = cache_state_path.clone();
+        let out_path = out_path.clone();
+        tokio::task::spawn_blocking(move || {
+            let state = RepoDataState::from_path(&cache_state_path).ok()?;
+            let cached_size = std::fs::metadata(&out_path).ok()?.len();
+            Some((state, cached_size))
+        })
+        .await?
+    };
+    let cache_result = match previous_state {
+        Some((state, cached_size))
+            if state.url == source_url
+                && state.namespace == options.cache_namespace
+                && state.cache_headers.etag.as_deref() == Some(etag.as_str())
+                && state.cache_size == cached_size =>
+        {
+            return Ok(CachedRepoData {
+                lock_file,
+                repo_data_json_path: out_path,
+                cache_state: state,
+                cache_result: CacheResult::CacheHit,
+                metrics: FetchMetrics::default(),
+            });
+        }
+        Some(_) => CacheResult::CacheOutdated,
+        None => CacheResult::CacheNotPresent,
     };
 
-    // write the cache state
-    let new_cache_state = tokio::task::spawn_blocking(move || {
+    // Decode the source into a temporary file while computing its hash.
+    let mut metrics = FetchMetrics::default();
+    let decode_start = Instant::now();
+    let decode_reporter = reporter.map(|r| (r, r.on_decode_start(&source_url)));
+    let source_file = tokio::fs::File::open(&source_path)
+        .await
+        .map_err(FetchRepoDataError::IoError)?;
+    let mut decoded = tokio::io::BufReader::new(source_file).decode(encoding);
+    let temp_file = NamedTempFile::new_in(cache_path)
+        .map_err(FetchRepoDataError::FailedToCreateTemporaryFile)?;
+    let file = tokio::fs::File::from_std(
+        temp_file
+            .as_file()
+            .try_clone()
+            .map_err(FetchRepoDataError::IoError)?,
+    );
+    let mut hashing_file_writer = HashingWriter::<_, Blake2b256>::new(file);
+    tokio::io::copy(&mut decoded, &mut hashing_file_writer)
+        .await
+        .map_err(FetchRepoDataError::IoError)?;
+    let (_, blake2_hash) = hashing_file_writer.finalize();
+    if let Some((reporter, index)) = decode_reporter {
+        reporter.on_decode_completed(index);
+    }
+    metrics.download = decode_start.elapsed();
+
+    // Persist the file and write the cache state
+    let cache_write_start = Instant::now();
+    let write_reporter = reporter.map(|r| (r, r.on_cache_write_start(&source_url)));
+    let namespace = options.cache_namespace.clone();
+    let (cache_state, repo_data_json_path) = tokio::task::spawn_blocking(move || {
+        let file = temp_file.persist(&out_path)?;
+        let metadata = file
+            .metadata()
+            .map_err(FetchRepoDataError::FailedToGetMetadata)?;
+        let new_cache_state = RepoDataState {
+            url: source_url,
+            cache_size: metadata.len(),
+            cache_headers: CacheHeaders {
+                etag: Some(etag),
+                last_modified: None,
+                cache_control: None,
+            },
+            cache_last_modified: metadata
+                .modified()
+                .map_err(FetchRepoDataError::FailedToGetMetadata)?,
+            blake2_hash: Some(blake2_hash),
+            blake2_hash_nominal: None,
+            has_zst: None,
+            has_bz2: None,
+            has_jlap: None,
+            has_zck: None,
+            compressed: false,
+            namespace,
+            verified_sha256: None,
+            jlap: None,
+            cache_version: CACHE_FORMAT_VERSION,
+        };
         new_cache_state
             .to_path(&cache_state_path)
-            .map(|_| new_cache_state)
-            .map_err(FetchRepoDataError::FailedToWriteCacheState)
+            .map_err(FetchRepoDataError::FailedToWriteCacheState)?;
+        Ok::<_, FetchRepoDataError>((new_cache_state, out_path))
     })
     .await??;
+    if let Some((reporter, index)) = write_reporter {
+        reporter.on_cache_write_completed(index);
+    }
+    metrics.cache_write = cache_write_start.elapsed();
 
     Ok(CachedRepoData {
         lock_file,
-        repo_data_json_path: out_path.clone(),
-        cache_state: new_cache_state,
-        cache_result: CacheResult::CacheHit,
+        repo_data_json_path,
+        cache_state,
+        cache_result,
+        metrics,
     })
 }
 
@@ -298,41 +911,384 @@ async fn repodata_from_file(
 ///
 /// The checks to see if a `.zst` and/or `.bz2` file exist are performed by doing a HEAD request to
 /// the respective URLs. The result of these are cached.
-#[instrument(err, skip_all, fields(subdir_url, cache_path = % cache_path.display()))]
+///
+/// Requests are made with the given `client`. This can be a plain [`reqwest::Client`] or a
+/// [`reqwest_middleware::ClientWithMiddleware`] with custom middleware for authentication, retries
+/// or tracing. Use a client with authentication middleware (e.g.
+/// `rattler_networking::AuthenticatedClient`) to access channels that require credentials.
+///
+/// The operation can be aborted with the [`FetchRepoDataOptions::cancellation_token`].
+///
+/// Concurrent fetches of the same repodata into the same cache directory, from this or another
+/// process, are serialized with a lock on the cache. If another fetch updated the cached repodata
+/// while waiting for the lock, the cached repodata is used as is instead of downloading it again.
+///
+/// If [`FetchRepoDataOptions::prefer_current_repodata`] is set the `current_repodata.json` is
+/// fetched if the channel provides it. The `url` of the returned [`CachedRepoData::cache_state`]
+/// indicates which file was fetched.
+///
+/// Slow servers can be handled with [`FetchRepoDataOptions::timeout`],
+/// [`FetchRepoDataOptions::connect_timeout`] and [`FetchRepoDataOptions::read_timeout`], which
+/// make the fetch fail with [`FetchRepoDataError::TimedOut`].
+///
+/// Code that is not async can use `fetch_repo_data_blocking` instead (requires the `blocking`
+/// feature), which drives the fetch on a runtime that is managed by this crate.
+#[instrument(err, skip_all, fields(url = Empty, path = %cache_path.display()))]
 pub async fn fetch_repo_data(
+    subdir_url: Url,
+    client: impl Into<reqwest_middleware::ClientWithMiddleware>,
+    cache_path: PathBuf,
+    options: FetchRepoDataOptions,
+    reporter: Option<Arc<dyn Reporter>>,
+) -> Result<CachedRepoData, FetchRepoDataError> {
+    let client = redirect::with_redirect_policy(
+        client.into(),
+        options.redirect_policy.as_ref(),
+        &options.headers,
+    );
+    let client = timeout::with_response_timeout(client, options.connect_timeout);
+    timeout::with_timeout(
+        options.timeout,
+        fetch_and_verify_repo_data(subdir_url, client, cache_path, options, reporter),
+    )
+    .await
+}
+
+/// Fetches the repodata and verifies its signatures if content trust is enabled.
+async fn fetch_and_verify_repo_data(
+    subdir_url: Url,
+    client: reqwest_middleware::ClientWithMiddleware,
+    cache_path: PathBuf,
+    options: FetchRepoDataOptions,
+    reporter: Option<Arc<dyn Reporter>>,
+) -> Result<CachedRepoData, FetchRepoDataError> {
+    let content_trust_options = options.clone();
+
+    let current = if options.prefer_current_repodata && options.variant == Variant::AfterPatches {
+        match fetch_file(
+            subdir_url.clone(),
+            client.clone(),
+            cache_path.clone(),
+            Variant::Current.file_name(),
+            options.clone(),
+            reporter.clone(),
+        )
+        .await
+        {
+            Err(FetchRepoDataError::NotFound(_)) => {
+                tracing::debug!(
+                    "current_repodata.json is not available, falling back to repodata.json"
+                );
+                None
+            }
+            result => Some(result?),
+        }
+    } else {
+        None
+    };
+
+    let cached = match current {
+        Some(cached) => cached,
+        None => {
+            let file_name = options.variant.file_name();
+            fetch_file(
+                subdir_url.clone(),
+                client.clone(),
+                cache_path,
+                file_name,
+                options,
+                reporter,
+            )
+            .await?
+        }
+    };
+
+    // Verify the signatures while the lock on the cache is still held.
+    if let Some(content_trust) = content_trust_options.content_trust.clone() {
+        verify_content_trust(
+            &client,
+            &subdir_url,
+            &cached,
+            content_trust,
+            &content_trust_options,
+        )
+        .await?;
+    }
+
+    Ok(cached)
+}
+
+/// Verifies the signatures of the cached repodata with the `key_mgr.json` of the channel.
+async fn verify_content_trust(
+    client: &reqwest_middleware::ClientWithMiddleware,
+    subdir_url: &Url,
+    cached: &CachedRepoData,
+    content_trust: ContentTrust,
+    options: &FetchRepoDataOptions,
+) -> Result<(), FetchRepoDataError> {
+    let (subdir_url, url_token) =
+        conda_token::split_conda_token(normalize_subdir_url(subdir_url.clone()));
+    let key_mgr_url = subdir_url
+        .join("../key_mgr.json")
+        .expect("file name is valid");
+    let key_mgr_url = match options.conda_token.as_deref().or(url_token.as_deref()) {
+        Some(token) => conda_token::with_conda_token(&key_mgr_url, token),
+        None => key_mgr_url,
+    };
+    let key_mgr_json = if key_mgr_url.scheme() == "file" {
+        let key_mgr_path = key_mgr_url.to_file_path().map_err(|_| {
+            FetchRepoDataError::IoError(std::io::Error::new(
+                ErrorKind::InvalidInput,
+                format!("'{key_mgr_url}' is not a valid local path"),
+            ))
+        })?;
+        match tokio::fs::read_to_string(&key_mgr_path).await {
+            Ok(key_mgr_json) => key_mgr_json,
+            Err(e) if e.kind() == ErrorKind::NotFound => {
+                return Err(ContentTrustError::UnsignedChannel.into())
+            }
+            Err(e) => return Err(FetchRepoDataError::IoError(e)),
+        }
+    } else {
+        let response = retry::send_with_retry(&options.retry, &key_mgr_url, None, || {
+            client
+                .get(key_mgr_url.clone())
+                .headers(options.headers.clone())
+        })
+        .await?;
+        if response.status() == StatusCode::NOT_FOUND {
+            return Err(ContentTrustError::UnsignedChannel.into());
+        }
+        response.error_for_status()?.text().await?
+    };
+
+    let repo_data_json_path = cached.repo_data_json_path.clone();
+    let compressed = cached.cache_state.compressed;
+    tokio::task::spawn_blocking(move || {
+        let mut repo_data_json = String::new();
+        open_cached_repo_data(&repo_data_json_path, compressed)?
+            .read_to_string(&mut repo_data_json)?;
+        content_trust.verify_repo_data(&key_mgr_json, &repo_data_json)
+    })
+    .await??;
+    Ok(())
+}
+
+/// Fetches the file with the given name from the subdirectory. This uses the same caching, locking
+/// and compression machinery that is used for the repodata.json.
+async fn fetch_file(
+    subdir_url: Url,
+    client: reqwest_middleware::ClientWithMiddleware,
+    cache_path: PathBuf,
+    file_name: &str,
+    options: FetchRepoDataOptions,
+    reporter: Option<Arc<dyn Reporter>>,
+) -> Result<CachedRepoData, FetchRepoDataError> {
+    let cancellation_token = options.cancellation_token.clone();
+    let memory_cache = options.memory_cache.clone();
+    let fetch =
+        fetch_repo_data_with_fallback(subdir_url, client, cache_path, file_name, options, reporter);
+    let result = match cancellation_token {
+        // Dropping the future releases the lock on the cache and removes any temporary files.
+        Some(token) => tokio::select! {
+            biased;
+            () = token.cancelled() => {
+                tracing::debug!("fetching repodata was cancelled");
+                Err(FetchRepoDataError::Cancelled)
+            }
+            result = fetch => result,
+        },
+        None => fetch.await,
+    };
+
+    // Remember the validated state so subsequent fetches of the same subdir can skip validating
+    // the files on disk.
+    if let (Ok(cached), Some(memory_cache)) = (&result, memory_cache) {
+        memory_cache.insert(&cached.repo_data_json_path, cached.cache_state.clone());
+    }
+
+    result
+}
+
+/// Fetches the JSON file with the given name from the subdirectory and parses it while the lock on
+/// the cache is still held. Returns `None` if the file does not exist.
+#[cfg(feature = "sparse")]
+async fn fetch_json_file<T: serde::de::DeserializeOwned + Send + 'static>(
+    subdir_url: Url,
+    client: reqwest_middleware::ClientWithMiddleware,
+    cache_path: PathBuf,
+    file_name: &str,
+    options: FetchRepoDataOptions,
+    reporter: Option<Arc<dyn Reporter>>,
+) -> Result<Option<T>, FetchRepoDataError> {
+    let options = FetchRepoDataOptions {
+        // JLAP is only available for the repodata.json.
+        jlap_enabled: false,
+        keep_compressed: false,
+        ..options
+    };
+    let client =
+        redirect::with_redirect_policy(client, options.redirect_policy.as_ref(), &options.headers);
+    let client = timeout::with_response_timeout(client, options.connect_timeout);
+    let timeout = options.timeout;
+    let fetch = fetch_file(subdir_url, client, cache_path, file_name, options, reporter);
+    let cached = match timeout::with_timeout(timeout, fetch).await {
+        Ok(cached) => cached,
+        Err(FetchRepoDataError::NotFound(_)) => return Ok(None),
+        Err(err) => return Err(err),
+    };
+
+    let value = tokio::task::spawn_blocking(move || {
+        let contents = std::fs::read_to_string(&cached.repo_data_json_path)?;
+        Ok::<T, std::io::Error>(serde_json::from_str(&contents)?)
+    })
+    .await?
+    .map_err(FetchRepoDataError::IoError)?;
+
+    Ok(Some(value))
+}
+
+/// Fetches the repodata and falls back to an outdated cache if
+/// [`FetchRepoDataOptions::stale_if_error`] is enabled and the server could not be reached.
+async fn fetch_repo_data_with_fallback(
+    subdir_url: Url,
+    client: reqwest_middleware::ClientWithMiddleware,
+    cache_path: PathBuf,
+    file_name: &str,
+    options: FetchRepoDataOptions,
+    reporter: Option<Arc<dyn Reporter>>,
+) -> Result<CachedRepoData, FetchRepoDataError> {
+    if !options.stale_if_error || options.cache_action == CacheAction::NoCache {
+        return fetch_repo_data_uncancellable(
+            subdir_url, client, cache_path, file_name, options, reporter,
+        )
+        .await;
+    }
+
+    let fallback_options = FetchRepoDataOptions {
+        cache_action: CacheAction::ForceCacheOnly,
+        ..options.clone()
+    };
+    match fetch_repo_data_uncancellable(
+        subdir_url.clone(),
+        client.clone(),
+        cache_path.clone(),
+        file_name,
+        options,
+        reporter.clone(),
+    )
+    .await
+    {
+        Err(
+            err @ (FetchRepoDataError::HttpError(_)
+            | FetchRepoDataError::FailedToDownload(..)
+            | FetchRepoDataError::CorruptRepoData(_)
+            | FetchRepoDataError::TimedOut),
+        ) => {
+            tracing::warn!(
+                "failed to fetch repodata: {err}. Falling back to the outdated cache..."
+            );
+            match fetch_repo_data_uncancellable(
+                subdir_url,
+                client,
+                cache_path,
+                file_name,
+                fallback_options,
+                reporter,
+            )
+            .await
+            {
+                Ok(cached) => Ok(CachedRepoData {
+                    cache_result: CacheResult::StaleCacheFallback,
+                    ..cached
+                }),
+                Err(_) => Err(err),
+            }
+        }
+        result => result,
+    }
+}
+
+async fn fetch_repo_data_uncancellable(
     subdir_url: Url,
     client: reqwest_middleware::ClientWithMiddleware,
     cache_path: PathBuf,
+    file_name: &str,
     options: FetchRepoDataOptions,
     reporter: Option<Arc<dyn Reporter>>,
 ) -> Result<CachedRepoData, FetchRepoDataError> {
     let subdir_url = normalize_subdir_url(subdir_url);
+    Span::current().record("url", subdir_url.clone().redact().as_str());
+
+    // The cache is based on the url without the conda token, the token is only added to the
+    // requests.
+    let (subdir_url, url_token) = conda_token::split_conda_token(subdir_url);
+    let request_subdir_url = match options.conda_token.as_deref().or(url_token.as_deref()) {
+        Some(token) => conda_token::with_conda_token(&subdir_url, token),
+        None => subdir_url.clone(),
+    };
 
     // Compute the cache key from the url
-    let cache_key = crate::utils::url_to_cache_filename(
-        &subdir_url
-            .join(options.variant.file_name())
-            .expect("file name is valid"),
+    let cache_key = crate::utils::url_to_namespaced_cache_filename(
+        &subdir_url.join(file_name).expect("file name is valid"),
+        options.cache_namespace.as_deref(),
     );
     let repo_data_json_path = cache_path.join(format!("{cache_key}.json"));
+    let compressed_repo_data_path = cache_path.join(format!("{cache_key}.json.zst"));
     let cache_state_path = cache_path.join(format!("{cache_key}.info.json"));
+    let zck_path = cache_path.join(format!("{cache_key}.zck"));
 
     // Lock all files that have to do with that cache key
     let lock_file_path = cache_path.join(format!("{}.lock", &cache_key));
-    let lock_file =
-        tokio::task::spawn_blocking(move || LockedFile::open_rw(lock_file_path, "repodata cache"))
-            .await?
-            .map_err(FetchRepoDataError::FailedToAcquireLock)?;
+    let lock_start = Instant::now();
+    let lock_wait_start = SystemTime::now();
+    let mut lock_contended = false;
+    let lock_reporter = reporter
+        .as_deref()
+        .map(|r| (r, r.on_cache_lock_start(&subdir_url)));
+    let lock_file = LockedFile::open_rw_async(lock_file_path, options.cache_lock_timeout, || {
+        lock_contended = true;
+        if let Some((reporter, index)) = lock_reporter {
+            reporter.on_cache_lock_contended(index);
+        }
+    })
+    .await
+    .map_err(FetchRepoDataError::FailedToAcquireLock)?
+    .ok_or_else(|| {
+        FetchRepoDataError::CacheLockTimedOut(options.cache_lock_timeout.unwrap_or_default())
+    })?;
+    if let Some((reporter, index)) = lock_reporter {
+        reporter.on_cache_lock_acquired(index);
+    }
+    let mut metrics = FetchMetrics {
+        lock_wait: lock_start.elapsed(),
+        ..FetchMetrics::default()
+    };
+
+    // Record when the cache entry was last used so the least recently used
+    // entries can be evicted from the cache (see [`crate::cache::gc`]).
+    let _ = lock_file.file().set_modified(SystemTime::now());
 
     let cache_action = if subdir_url.scheme() == "file" {
         // If we are dealing with a local file, we can skip the cache entirely.
-        return repodata_from_file(
-            subdir_url.join(options.variant.file_name()).unwrap(),
+        let cached = repodata_from_file(
+            &subdir_url,
+            file_name,
+            &options,
+            &cache_path,
             repo_data_json_path,
             cache_state_path,
             lock_file,
+            reporter.as_deref(),
         )
-        .await;
+        .await?;
+        return Ok(CachedRepoData {
+            metrics: FetchMetrics {
+                lock_wait: metrics.lock_wait,
+                ..cached.metrics
+            },
+            ..cached
+        });
     } else {
         options.cache_action
     };
@@ -344,10 +1300,64 @@ pub async fn fetch_repo_data(
         let owned_subdir_url = subdir_url.clone();
         let owned_cache_path = cache_path.clone();
         let owned_cache_key = cache_key.clone();
+        let max_age = match options.cache_action {
+            CacheAction::RefreshIfOlderThan(max_age) => Some(max_age),
+            _ => None,
+        };
+        let memory_cache = options.memory_cache.clone();
+        let owned_repo_data_json_path = repo_data_json_path.clone();
+        let owned_compressed_repo_data_path = compressed_repo_data_path.clone();
+        let allow_compressed = options.keep_compressed;
+        let namespace = options.cache_namespace.clone();
         let cache_state = tokio::task::spawn_blocking(move || {
-            validate_cached_state(&owned_cache_path, &owned_subdir_url, &owned_cache_key)
+            // If the entry was already validated by this process and did not change since, there
+            // is no need to read and validate the files on disk again. The entry is stored under
+            // the path of the file that holds the repodata, which depends on whether it is stored
+            // compressed, and it must pass the same checks as the state on disk.
+            let memory_cached = memory_cache
+                .and_then(|cache| {
+                    cache
+                        .get(&owned_repo_data_json_path)
+                        .or_else(|| cache.get(&owned_compressed_repo_data_path))
+                })
+                .filter(|(cache_state, _)| {
+                    cache_state_applies(
+                        cache_state,
+                        &owned_subdir_url,
+                        allow_compressed,
+                        namespace.as_deref(),
+                    )
+                });
+            match memory_cached {
+                Some((cache_state, cache_last_modified)) => {
+                    validate_cache_freshness(cache_state, cache_last_modified, max_age)
+                }
+                None => validate_cached_state(
+                    &owned_cache_path,
+                    &owned_subdir_url,
+                    &owned_cache_key,
+                    max_age,
+                    allow_compressed,
+                    namespace.as_deref(),
+                ),
+            }
         })
         .await?;
+
+        // If another process updated the repodata while we were waiting for the lock, there is no
+        // need to download the same file again.
+        let cache_state = match cache_state {
+            ValidatedCacheState::OutOfDate(cache_state)
+                if lock_contended && cache_state.cache_last_modified >= lock_wait_start =>
+            {
+                tracing::debug!(
+                    "repodata was updated by another process while waiting for the lock"
+                );
+                ValidatedCacheState::UpToDate(cache_state)
+            }
+            cache_state => cache_state,
+        };
+
         match (cache_state, options.cache_action) {
             (ValidatedCacheState::UpToDate(cache_state), _)
             | (ValidatedCacheState::OutOfDate(cache_state), CacheAction::ForceCacheOnly) => {
@@ -355,9 +1365,14 @@ pub async fn fetch_repo_data(
                 // so just immediately return what we have.
                 return Ok(CachedRepoData {
                     lock_file,
-                    repo_data_json_path,
+                    repo_data_json_path: if cache_state.compressed {
+                        compressed_repo_data_path
+                    } else {
+                        repo_data_json_path
+                    },
                     cache_state,
                     cache_result: CacheResult::CacheHit,
+                    metrics,
                 });
             }
             (ValidatedCacheState::OutOfDate(_), CacheAction::UseCacheOnly)
@@ -388,30 +1403,104 @@ pub async fn fetch_repo_data(
         }
     };
 
-    // Determine the availability of variants based on the cache or by querying the remote.
-    let variant_availability = check_variant_availability(
-        &client,
-        &subdir_url,
-        cache_state.as_ref(),
-        options.variant.file_name(),
-    )
-    .await;
-
+    // Keep hold of the cached repodata before it is replaced.
+    let previous_repo_data = match &cache_state {
+        Some(cache_state) if options.keep_previous => {
+            let owned_cache_state = cache_state.clone();
+            let owned_cache_path = cache_path.clone();
+            let path = if cache_state.compressed {
+                compressed_repo_data_path.clone()
+            } else {
+                repo_data_json_path.clone()
+            };
+            tokio::task::spawn_blocking(move || {
+                PreviousRepoData::stage(&path, &owned_cache_path, &owned_cache_state)
+            })
+            .await?
+            .map_err(|err| tracing::warn!("failed to keep the previous repodata: {err}"))
+            .ok()
+        }
+        _ => None,
+    };
+
+    // From here on the network is accessed, wait until that is allowed.
+    let _permit = match &options.concurrency_limiter {
+        Some(limiter) => Some(limiter.acquire(&subdir_url).await),
+        None => None,
+    };
+
+    // Determine the availability of variants based on the cache or by querying the remote.
+    let variant_check_start = Instant::now();
+    let variant_availability = if options.compression == CompressionVariant::Auto {
+        let variant_reporter = reporter
+            .as_deref()
+            .map(|r| (r, r.on_variant_check_start(&subdir_url)));
+        let mut variant_availability = check_variant_availability(
+            &client,
+            &request_subdir_url,
+            cache_state.as_ref(),
+            file_name,
+            &options.retry,
+            &options.headers,
+            options.variant_availability_expiration,
+        )
+        .await;
+        if options.zchunk_enabled {
+            variant_availability.has_zck = check_zchunk_availability(
+                &client,
+                &request_subdir_url,
+                cache_state.as_ref(),
+                file_name,
+                &options,
+            )
+            .await;
+        }
+        if let Some((reporter, index)) = variant_reporter {
+            reporter.on_variant_check_completed(index);
+        }
+        metrics.variant_check = variant_check_start.elapsed();
+        variant_availability
+    } else {
+        // The variant has been selected explicitly so there is no need to probe the server. Keep
+        // whatever was known about the availability before.
+        VariantAvailability {
+            has_zst: cache_state.as_ref().and_then(|state| state.has_zst.clone()),
+            has_bz2: cache_state.as_ref().and_then(|state| state.has_bz2.clone()),
+            has_jlap: cache_state
+                .as_ref()
+                .and_then(|state| state.has_jlap.clone()),
+            has_zck: cache_state.as_ref().and_then(|state| state.has_zck.clone()),
+        }
+    };
+
     // Now that the caches have been refreshed determine whether or not we can use one of the
     // variants. We don't check the expiration here since we just refreshed it.
-    let has_zst = options.zstd_enabled && variant_availability.has_zst();
-    let has_bz2 = options.bz2_enabled && variant_availability.has_bz2();
+    let (has_zst, has_bz2) = match options.compression {
+        CompressionVariant::Auto => (
+            options.zstd_enabled && variant_availability.has_zst(),
+            options.bz2_enabled && variant_availability.has_bz2(),
+        ),
+        CompressionVariant::Zst => (true, false),
+        CompressionVariant::Bz2 => (false, true),
+        CompressionVariant::Plain => (false, false),
+    };
     let has_jlap = options.jlap_enabled && variant_availability.has_jlap();
+    let has_zck = options.zchunk_enabled
+        && !options.keep_compressed
+        && options.compression == CompressionVariant::Auto
+        && variant_availability.has_zck();
+    let cache_compressed = cache_state.as_ref().map_or(false, |state| state.compressed);
 
     // We first attempt to make a JLAP request; if it fails for any reason, we continue on with
     // a normal request.
-    let jlap_state = if has_jlap && cache_state.is_some() {
+    let jlap_state = if has_jlap && cache_state.is_some() && !cache_compressed {
         let repo_data_state = cache_state.as_ref().unwrap();
         match jlap::patch_repo_data(
             &client,
-            subdir_url.clone(),
+            request_subdir_url.clone(),
             repo_data_state.clone(),
             &repo_data_json_path,
+            &options.headers,
             reporter.clone(),
         )
         .await
@@ -424,11 +1513,23 @@ pub async fn fetch_repo_data(
                     has_zst: variant_availability.has_zst,
                     has_bz2: variant_availability.has_bz2,
                     has_jlap: variant_availability.has_jlap,
+                    has_zck: variant_availability.has_zck,
                     jlap: Some(state),
+                    // The patched file is not what the server sent.
+                    verified_sha256: None,
                     ..cache_state.expect("we must have had a cache, otherwise we wouldn't know the previous state of the cache")
                 };
 
+                let owned_repo_data_json_path = repo_data_json_path.clone();
                 let cache_state = tokio::task::spawn_blocking(move || {
+                    if let Some(previous) = previous_repo_data {
+                        commit_previous_repo_data(
+                            previous,
+                            &owned_repo_data_json_path,
+                            false,
+                            &disk_hash,
+                        );
+                    }
                     cache_state
                         .to_path(&cache_state_path)
                         .map(|_| cache_state)
@@ -441,6 +1542,7 @@ pub async fn fetch_repo_data(
                     repo_data_json_path,
                     cache_state,
                     cache_result: CacheResult::CacheOutdated,
+                    metrics,
                 });
             }
             Err(error) => {
@@ -452,24 +1554,168 @@ pub async fn fetch_repo_data(
         None
     };
 
+    // If JLAP could not be used, we attempt to only download the chunks of the zchunk file that
+    // changed; if it fails for any reason, we continue on with a normal request.
+    if has_zck && jlap_state.is_none() {
+        let zck_file_name = format!("{file_name}.zck");
+        let zck_url = request_subdir_url.join(&zck_file_name).unwrap();
+        let cache_url = subdir_url.join(&zck_file_name).unwrap();
+
+        // The cache headers can only be used if the cache was created from the zchunk file.
+        let cache_headers = cache_state
+            .as_ref()
+            .filter(|state| state.url == cache_url)
+            .map(|state| &state.cache_headers);
+
+        let download_start = Instant::now();
+        let download_reporter = reporter
+            .as_deref()
+            .map(|r| (r, r.on_download_start(&cache_url)));
+        let result = zchunk::fetch_zchunk(
+            &client,
+            zck_url.clone(),
+            zck_path.clone(),
+            cache_headers,
+            &cache_path,
+            &options,
+        )
+        .await;
+        if let Some((reporter, index)) = download_reporter {
+            reporter.on_download_complete(&zck_url, index);
+        }
+        metrics.download = download_start.elapsed();
+
+        match result {
+            Ok(ZchunkFetch::NotModified) => {
+                tracing::debug!("zchunk file was unmodified");
+                let cache_state = RepoDataState {
+                    has_zst: variant_availability.has_zst,
+                    has_bz2: variant_availability.has_bz2,
+                    has_jlap: variant_availability.has_jlap,
+                    has_zck: variant_availability.has_zck,
+                    ..cache_state.expect("we must have had a cache, otherwise we wouldn't have sent the cache headers")
+                };
+
+                let cache_state = tokio::task::spawn_blocking(move || {
+                    cache_state
+                        .to_path(&cache_state_path)
+                        .map(|_| cache_state)
+                        .map_err(FetchRepoDataError::FailedToWriteCacheState)
+                })
+                .await??;
+
+                return Ok(CachedRepoData {
+                    lock_file,
+                    repo_data_json_path,
+                    cache_state,
+                    cache_result: CacheResult::CacheHitAfterFetch,
+                    metrics,
+                });
+            }
+            Ok(ZchunkFetch::Fetched {
+                zck_file,
+                repo_data_file,
+                blake2_hash,
+                cache_headers,
+                downloaded_bytes,
+            }) => {
+                metrics.downloaded_bytes = downloaded_bytes;
+
+                // Persist the files to their final destination. The zchunk file is kept to
+                // reuse its chunks during the next fetch.
+                let cache_write_start = Instant::now();
+                let write_reporter = reporter
+                    .as_deref()
+                    .map(|r| (r, r.on_cache_write_start(&cache_url)));
+                let repo_data_destination_path = repo_data_json_path.clone();
+                let outdated_repo_data_path = compressed_repo_data_path.clone();
+                let validate_repo_data = options.validate_repo_data;
+                let repo_data_json_metadata = tokio::task::spawn_blocking(move || {
+                    if validate_repo_data {
+                        validate::validate_json_file(repo_data_file.path(), false)
+                            .map_err(FetchRepoDataError::CorruptRepoData)?;
+                    }
+                    zck_file.persist(zck_path)?;
+                    let file = repo_data_file.persist(&repo_data_destination_path)?;
+                    let _ = std::fs::remove_file(outdated_repo_data_path);
+                    if let Some(previous) = previous_repo_data {
+                        commit_previous_repo_data(
+                            previous,
+                            &repo_data_destination_path,
+                            false,
+                            &blake2_hash,
+                        );
+                    }
+                    file.metadata()
+                        .map_err(FetchRepoDataError::FailedToGetMetadata)
+                })
+                .await??;
+
+                let had_cache = cache_state.is_some();
+                let new_cache_state = RepoDataState {
+                    url: cache_url,
+                    cache_headers,
+                    cache_last_modified: repo_data_json_metadata
+                        .modified()
+                        .map_err(FetchRepoDataError::FailedToGetMetadata)?,
+                    cache_size: repo_data_json_metadata.len(),
+                    blake2_hash: Some(blake2_hash),
+                    blake2_hash_nominal: Some(blake2_hash),
+                    has_zst: variant_availability.has_zst,
+                    has_bz2: variant_availability.has_bz2,
+                    has_jlap: variant_availability.has_jlap,
+                    has_zck: variant_availability.has_zck,
+                    compressed: false,
+                    namespace: options.cache_namespace.clone(),
+                    verified_sha256: None,
+                    jlap: None,
+                    cache_version: CACHE_FORMAT_VERSION,
+                };
+
+                let new_cache_state = tokio::task::spawn_blocking(move || {
+                    new_cache_state
+                        .to_path(&cache_state_path)
+                        .map(|_| new_cache_state)
+                        .map_err(FetchRepoDataError::FailedToWriteCacheState)
+                })
+                .await??;
+                if let Some((reporter, index)) = write_reporter {
+                    reporter.on_cache_write_completed(index);
+                }
+                metrics.cache_write = cache_write_start.elapsed();
+
+                return Ok(CachedRepoData {
+                    lock_file,
+                    repo_data_json_path,
+                    cache_state: new_cache_state,
+                    cache_result: if had_cache {
+                        CacheResult::CacheOutdated
+                    } else {
+                        CacheResult::CacheNotPresent
+                    },
+                    metrics,
+                });
+            }
+            Err(error) => {
+                tracing::warn!("Error during zchunk request: {}", error);
+            }
+        }
+    }
+
     // Determine which variant to download
-    let repo_data_url = if has_zst {
-        subdir_url
-            .join(&format!("{}.zst", options.variant.file_name()))
-            .unwrap()
+    let variant_file_name = if has_zst {
+        format!("{file_name}.zst")
     } else if has_bz2 {
-        subdir_url
-            .join(&format!("{}.bz2", options.variant.file_name()))
-            .unwrap()
+        format!("{file_name}.bz2")
     } else {
-        subdir_url.join(options.variant.file_name()).unwrap()
+        file_name.to_owned()
     };
+    let repo_data_url = request_subdir_url.join(&variant_file_name).unwrap();
+    let cache_url = subdir_url.join(&variant_file_name).unwrap();
 
     // Construct the HTTP request
-    tracing::debug!("fetching '{}'", &repo_data_url);
-    let request_builder = client.get(repo_data_url.clone());
-
-    let mut headers = HeaderMap::default();
+    tracing::debug!("fetching '{}'", repo_data_url.clone().redact());
+    let mut headers = options.headers.clone();
 
     // We can handle g-zip encoding which is often used. We could also set this option on the
     // client, but that will disable all download progress messages by `reqwest` because the
@@ -489,10 +1735,16 @@ pub async fn fetch_repo_data(
         cache_headers.add_to_request(&mut headers);
     }
     // Send the request and wait for a reply
+    let download_start = Instant::now();
     let download_reporter = reporter
         .as_deref()
-        .map(|r| (r, r.on_download_start(&repo_data_url)));
-    let response = match request_builder.headers(headers).send().await {
+        .map(|r| (r, r.on_download_start(&cache_url)));
+    let response =
+        retry::send_with_retry(&options.retry, &repo_data_url, reporter.as_deref(), || {
+            client.get(repo_data_url.clone()).headers(headers.clone())
+        })
+        .await;
+    let response = match response {
         Ok(response) if response.status() == StatusCode::NOT_FOUND => {
             return Err(FetchRepoDataError::NotFound(RepoDataNotFoundError::from(
                 response.error_for_status().unwrap_err(),
@@ -510,10 +1762,11 @@ pub async fn fetch_repo_data(
 
         // Update the cache on disk with any new findings.
         let cache_state = RepoDataState {
-            url: repo_data_url,
+            url: cache_url,
             has_zst: variant_availability.has_zst,
             has_bz2: variant_availability.has_bz2,
             has_jlap: variant_availability.has_jlap,
+            has_zck: variant_availability.has_zck,
             jlap: jlap_state,
             ..cache_state.expect("we must have had a cache, otherwise we wouldn't know the previous state of the cache")
         };
@@ -528,21 +1781,31 @@ pub async fn fetch_repo_data(
 
         return Ok(CachedRepoData {
             lock_file,
-            repo_data_json_path,
+            repo_data_json_path: if cache_state.compressed {
+                compressed_repo_data_path
+            } else {
+                repo_data_json_path
+            },
             cache_state,
             cache_result: CacheResult::CacheHitAfterFetch,
+            metrics,
         });
     }
 
     // Get cache headers from the response
     let cache_headers = CacheHeaders::from(&response);
 
-    // Stream the content to a temporary file
+    // Stream the content to a temporary file. If the compressed repodata is kept it is stored
+    // exactly as it was downloaded.
+    let keep_compressed = options.keep_compressed && has_zst;
     let response_url = response.url().clone();
-    let (temp_file, blake2_hash) = stream_and_decode_to_file(
+    let (temp_file, blake2_hash, downloaded_bytes, verified_sha256) = stream_and_decode_to_file(
+        &client,
         repo_data_url.clone(),
         response,
-        if has_zst {
+        if keep_compressed {
+            Encoding::Passthrough
+        } else if has_zst {
             Encoding::Zst
         } else if has_bz2 {
             Encoding::Bz2
@@ -550,6 +1813,7 @@ pub async fn fetch_repo_data(
             Encoding::Passthrough
         },
         &cache_path,
+        &options,
         download_reporter,
     )
     .await?;
@@ -557,14 +1821,43 @@ pub async fn fetch_repo_data(
     if let Some((reporter, index)) = download_reporter {
         reporter.on_download_complete(&response_url, index);
     }
+    metrics.downloaded_bytes = downloaded_bytes;
+    metrics.download = download_start.elapsed();
 
     // Persist the file to its final destination
+    let cache_write_start = Instant::now();
+    let write_reporter = reporter
+        .as_deref()
+        .map(|r| (r, r.on_cache_write_start(&cache_url)));
+    let (repo_data_json_path, outdated_repo_data_path) = if keep_compressed {
+        (compressed_repo_data_path, repo_data_json_path)
+    } else {
+        (repo_data_json_path, compressed_repo_data_path)
+    };
     let repo_data_destination_path = repo_data_json_path.clone();
+    let validate_repo_data = options.validate_repo_data;
     let repo_data_json_metadata = tokio::task::spawn_blocking(move || {
+        // Make sure the download is complete before it replaces the cached repodata.
+        if validate_repo_data {
+            validate::validate_json_file(temp_file.path(), keep_compressed)
+                .map_err(FetchRepoDataError::CorruptRepoData)?;
+        }
         let file = temp_file
-            .persist(repo_data_destination_path)
+            .persist(&repo_data_destination_path)
             .map_err(FetchRepoDataError::FailedToPersistTemporaryFile)?;
 
+        // Remove the repodata that was stored the other way, if any.
+        let _ = std::fs::remove_file(outdated_repo_data_path);
+
+        if let Some(previous) = previous_repo_data {
+            commit_previous_repo_data(
+                previous,
+                &repo_data_destination_path,
+                keep_compressed,
+                &blake2_hash,
+            );
+        }
+
         // Determine the last modified date and size of the repodata.json file. We store these values in
         // the cache to link the cache to the corresponding repodata.json file.
         file.metadata()
@@ -575,18 +1868,24 @@ pub async fn fetch_repo_data(
     // Update the cache on disk.
     let had_cache = cache_state.is_some();
     let new_cache_state = RepoDataState {
-        url: repo_data_url,
+        url: cache_url,
         cache_headers,
         cache_last_modified: repo_data_json_metadata
             .modified()
             .map_err(FetchRepoDataError::FailedToGetMetadata)?,
         cache_size: repo_data_json_metadata.len(),
         blake2_hash: Some(blake2_hash),
-        blake2_hash_nominal: Some(blake2_hash),
+        // The hash of a compressed file says nothing about the repodata.json on the server.
+        blake2_hash_nominal: (!keep_compressed).then_some(blake2_hash),
         has_zst: variant_availability.has_zst,
         has_bz2: variant_availability.has_bz2,
         has_jlap: variant_availability.has_jlap,
+        has_zck: variant_availability.has_zck,
+        compressed: keep_compressed,
+        namespace: options.cache_namespace.clone(),
+        verified_sha256,
         jlap: jlap_state,
+        cache_version: CACHE_FORMAT_VERSION,
     };
 
     let new_cache_state = tokio::task::spawn_blocking(move || {
@@ -596,6 +1895,11 @@ pub async fn fetch_repo_data(
             .map_err(FetchRepoDataError::FailedToWriteCacheState)
     })
     .await??;
+    if let Some((reporter, index)) = write_reporter {
+        reporter.on_cache_write_completed(index);
+    }
+    metrics.cache_write = cache_write_start.elapsed();
+    tracing::debug!("fetched repodata: {metrics:?}");
 
     Ok(CachedRepoData {
         lock_file,
@@ -606,30 +1910,97 @@ pub async fn fetch_repo_data(
         } else {
             CacheResult::CacheNotPresent
         },
+        metrics,
     })
 }
 
+/// Stores the repodata that was replaced next to the new repodata at `repo_data_json_path`, see
+/// [`FetchRepoDataOptions::keep_previous`]. The repodata was already updated, so a failure is
+/// only logged.
+fn commit_previous_repo_data(
+    previous: PreviousRepoData,
+    repo_data_json_path: &Path,
+    compressed: bool,
+    blake2_hash: &rattler_digest::Blake2b256Hash,
+) {
+    if let Err(err) = previous.commit(repo_data_json_path, compressed, blake2_hash) {
+        tracing::warn!("failed to keep the previous repodata: {err}");
+    }
+}
+
 /// Streams and decodes the response to a new temporary file in the given directory. While writing
-/// to disk it also computes the BLAKE2 hash of the file.
-#[instrument(skip_all)]
+/// to disk it also computes the BLAKE2 hash of the file. If the connection is interrupted the
+/// download is resumed from the last received byte. Large files are downloaded in parts if
+/// [`FetchRepoDataOptions::multipart_download`] is set. Returns the file, its hash and the number
+/// of bytes that were received.
+#[instrument(skip_all, fields(url = %url.clone().redact(), bytes = Empty, decoded_bytes = Empty))]
 async fn stream_and_decode_to_file(
+    client: &reqwest_middleware::ClientWithMiddleware,
     url: Url,
     response: Response,
     content_encoding: Encoding,
     temp_dir: &Path,
+    options: &FetchRepoDataOptions,
     reporter: Option<(&dyn Reporter, usize)>,
-) -> Result<(NamedTempFile, blake2::digest::Output<Blake2b256>), FetchRepoDataError> {
+) -> Result<
+    (
+        NamedTempFile,
+        blake2::digest::Output<Blake2b256>,
+        u64,
+        Option<rattler_digest::Sha256Hash>,
+    ),
+    FetchRepoDataError,
+> {
     // Determine the encoding of the response
     let transfer_encoding = Encoding::from(&response);
 
-    // Convert the response into a byte stream
+    // Hash the received bytes if the server told us what to expect.
+    let expected_checksum = options
+        .verify_checksum_headers
+        .then(|| checksum::expected_checksum(&response))
+        .flatten();
+    let mut sha256 = expected_checksum.as_ref().map(|_| Sha256::new());
+
+    // Convert the response into a byte stream. Large files are downloaded in several parts in
+    // parallel if possible, these apply the read timeout to every part themselves.
     let mut total_bytes = 0;
-    let bytes_stream = response
-        .byte_stream_with_progress(reporter)
-        .inspect_ok(|bytes| {
-            total_bytes += bytes.len();
-        })
-        .map_err(|e| std::io::Error::new(ErrorKind::Other, e));
+    let bytes_stream = match multipart::split(&response, options) {
+        Some(split) => {
+            multipart::multipart_byte_stream(client, &url, response, split, options, reporter)
+                .left_stream()
+        }
+        None => {
+            let bytes_stream = resume::resumable_byte_stream(
+                client,
+                url.clone(),
+                response,
+                &options.headers,
+                &options.retry,
+                reporter,
+            );
+
+            // Fail the download if the server stops sending data.
+            match options.read_timeout {
+                Some(read_timeout) => {
+                    timeout::read_timeout(bytes_stream, read_timeout).left_stream()
+                }
+                None => bytes_stream.right_stream(),
+            }
+            .right_stream()
+        }
+    }
+    .inspect_ok(|bytes| {
+        total_bytes += bytes.len();
+        if let Some(sha256) = &mut sha256 {
+            sha256.update(bytes);
+        }
+    });
+
+    // Limit the rate at which the bytes are received if requested.
+    let bytes_stream = match options.download_rate_limit {
+        Some(bytes_per_second) => throttle::throttle(bytes_stream, bytes_per_second).left_stream(),
+        None => bytes_stream.right_stream(),
+    };
 
     // Create a new stream from the byte stream that decodes the bytes using the transfer encoding
     // on the fly.
@@ -656,13 +2027,43 @@ async fn stream_and_decode_to_file(
     let mut hashing_file_writer = HashingWriter::<_, Blake2b256>::new(file);
 
     // Decode, hash and write the data to the file.
+    let decode_reporter = reporter.map(|(r, _)| (r, r.on_decode_start(&url)));
     let bytes = tokio::io::copy(&mut decoded_repo_data_json_bytes, &mut hashing_file_writer)
         .await
-        .map_err(|e| FetchRepoDataError::FailedToDownload(url.redact(), e))?;
+        .map_err(|e| {
+            if timeout::is_timeout(&e) {
+                FetchRepoDataError::TimedOut
+            } else {
+                FetchRepoDataError::FailedToDownload(url.redact(), e)
+            }
+        })?;
+    if let Some((reporter, index)) = decode_reporter {
+        reporter.on_decode_completed(index);
+    }
 
     // Finalize the hash
     let (_, hash) = hashing_file_writer.finalize();
 
+    // Make sure we received the file the server told us about.
+    let verified_sha256 = match (expected_checksum, sha256) {
+        (Some(expected), Some(sha256)) => {
+            let actual = sha256.finalize();
+            if actual != expected.sha256 {
+                return Err(FetchRepoDataError::ChecksumMismatch {
+                    header: expected.header.to_string(),
+                    expected: format!("{:x}", expected.sha256),
+                    actual: format!("{actual:x}"),
+                });
+            }
+            Some(actual)
+        }
+        _ => None,
+    };
+
+    Span::current()
+        .record("bytes", total_bytes)
+        .record("decoded_bytes", bytes);
+
     tracing::debug!(
         "downloaded {}, decoded that into {}, BLAKE2 hash: {:x}",
         SizeFormatter::new(total_bytes, DECIMAL),
@@ -670,7 +2071,7 @@ async fn stream_and_decode_to_file(
         hash
     );
 
-    Ok((temp_file, hash))
+    Ok((temp_file, hash, total_bytes as u64, verified_sha256))
 }
 
 /// Describes the availability of certain `repodata.json`.
@@ -679,6 +2080,7 @@ pub struct VariantAvailability {
     has_zst: Option<Expiring<bool>>,
     has_bz2: Option<Expiring<bool>>,
     has_jlap: Option<Expiring<bool>>,
+    has_zck: Option<Expiring<bool>>,
 }
 
 impl VariantAvailability {
@@ -696,19 +2098,30 @@ impl VariantAvailability {
     pub fn has_jlap(&self) -> bool {
         self.has_jlap.as_ref().map_or(false, |state| state.value)
     }
+
+    /// Returns true if there is a zchunk variant available, regardless of when it was checked
+    pub fn has_zck(&self) -> bool {
+        self.has_zck.as_ref().map_or(false, |state| state.value)
+    }
 }
 
 /// Determine the availability of `repodata.json` variants (like a `.zst` or `.bz2`) by checking
 /// a cache or the internet.
+///
+/// Values in the cache that were checked longer than `expiration` ago are checked again. The
+/// `headers` are sent along with every request.
 pub async fn check_variant_availability(
     client: &reqwest_middleware::ClientWithMiddleware,
     subdir_url: &Url,
     cache_state: Option<&RepoDataState>,
     filename: &str,
+    retry: &RetryOptions,
+    headers: &HeaderMap,
+    expiration: std::time::Duration,
 ) -> VariantAvailability {
-    // Determine from the cache which variant are available. This is currently cached for a maximum
-    // of 14 days.
-    let expiration_duration = chrono::TimeDelta::try_days(14).expect("14 days is a valid duration");
+    // Determine from the cache which variant are available.
+    let expiration_duration =
+        chrono::TimeDelta::from_std(expiration).unwrap_or(chrono::TimeDelta::max_value());
     let has_zst = cache_state
         .and_then(|state| state.has_zst.as_ref())
         .and_then(|value| value.value(expiration_duration))
@@ -734,7 +2147,7 @@ pub async fn check_variant_availability(
         }
         None => async {
             Some(Expiring {
-                value: check_valid_download_target(&zst_repodata_url, client).await,
+                value: check_valid_download_target(&zst_repodata_url, client, retry, headers).await,
                 last_checked: chrono::Utc::now(),
             })
         }
@@ -756,7 +2169,8 @@ pub async fn check_variant_availability(
                     cache_state.and_then(|state| state.has_bz2.clone())
                 }
                 None => Some(Expiring {
-                    value: check_valid_download_target(&bz2_repodata_url, client).await,
+                    value: check_valid_download_target(&bz2_repodata_url, client, retry, headers)
+                        .await,
                     last_checked: chrono::Utc::now(),
                 }),
             }
@@ -771,7 +2185,8 @@ pub async fn check_variant_availability(
         }
         None => async {
             Some(Expiring {
-                value: check_valid_download_target(&jlap_repodata_url, client).await,
+                value: check_valid_download_target(&jlap_repodata_url, client, retry, headers)
+                    .await,
                 last_checked: chrono::Utc::now(),
             })
         }
@@ -786,40 +2201,79 @@ pub async fn check_variant_availability(
         has_zst,
         has_bz2,
         has_jlap,
+        has_zck: cache_state.and_then(|state| state.has_zck.clone()),
+    }
+}
+
+/// Determine the availability of a zchunk (`.zck`) variant of the `repodata.json` by checking the
+/// cache or the internet.
+async fn check_zchunk_availability(
+    client: &reqwest_middleware::ClientWithMiddleware,
+    subdir_url: &Url,
+    cache_state: Option<&RepoDataState>,
+    filename: &str,
+    options: &FetchRepoDataOptions,
+) -> Option<Expiring<bool>> {
+    let expiration_duration = chrono::TimeDelta::from_std(options.variant_availability_expiration)
+        .unwrap_or(chrono::TimeDelta::max_value());
+    let has_zck = cache_state.and_then(|state| state.has_zck.as_ref());
+    if has_zck.map_or(false, |value| value.value(expiration_duration).is_some()) {
+        // The last cached value is valid, so we simply copy that
+        return has_zck.cloned();
     }
+
+    let zck_repodata_url = subdir_url.join(&format!("{filename}.zck")).unwrap();
+    Some(Expiring {
+        value: check_valid_download_target(
+            &zck_repodata_url,
+            client,
+            &options.retry,
+            &options.headers,
+        )
+        .await,
+        last_checked: chrono::Utc::now(),
+    })
 }
 
 /// Performs a HEAD request on the given URL to see if it is available.
 async fn check_valid_download_target(
     url: &Url,
     client: &reqwest_middleware::ClientWithMiddleware,
+    retry: &RetryOptions,
+    headers: &HeaderMap,
 ) -> bool {
-    tracing::debug!("checking availability of '{url}'");
+    let redacted_url = url.clone().redact();
+    tracing::debug!("checking availability of '{redacted_url}'");
 
     if url.scheme() == "file" {
         // If the url is a file url we can simply check if the file exists.
         let path = url.to_file_path().unwrap();
         let exists = tokio::fs::metadata(path).await.is_ok();
         tracing::debug!(
-            "'{url}' seems to be {}",
+            "'{redacted_url}' seems to be {}",
             if exists { "available" } else { "unavailable" }
         );
         exists
     } else {
         // Otherwise, perform a HEAD request to determine whether the url seems valid.
-        match client.head(url.clone()).send().await {
+        match retry::send_with_retry(retry, url, None, || {
+            client.head(url.clone()).headers(headers.clone())
+        })
+        .await
+        {
             Ok(response) => {
                 if response.status().is_success() {
-                    tracing::debug!("'{url}' seems to be available");
+                    tracing::debug!("'{redacted_url}' seems to be available");
                     true
                 } else {
-                    tracing::debug!("'{url}' seems to be unavailable");
+                    tracing::debug!("'{redacted_url}' seems to be unavailable");
                     false
                 }
             }
             Err(e) => {
                 tracing::warn!(
-                    "failed to perform HEAD request on '{url}': {e}. Assuming its unavailable.."
+                    "failed to perform HEAD request on '{redacted_url}': {}. Assuming its unavailable..",
+                    e.redact()
                 );
                 false
             }
@@ -860,27 +2314,19 @@ enum ValidatedCacheState {
 ///
 /// This functions reads multiple files from the `cache_path`, it is left up to the user to ensure
 /// that these files stay synchronized during the execution of this function.
+///
+/// If `max_age` is specified the cache is considered up to date if it is younger than `max_age`,
+/// regardless of the cache headers sent by the server.
 fn validate_cached_state(
     cache_path: &Path,
     subdir_url: &Url,
     cache_key: &str,
+    max_age: Option<std::time::Duration>,
+    allow_compressed: bool,
+    namespace: Option<&str>,
 ) -> ValidatedCacheState {
-    let repo_data_json_path = cache_path.join(format!("{cache_key}.json"));
     let cache_state_path = cache_path.join(format!("{cache_key}.info.json"));
 
-    // Check if we have cached repodata.json file
-    let json_metadata = match std::fs::metadata(&repo_data_json_path) {
-        Err(e) if e.kind() == ErrorKind::NotFound => return ValidatedCacheState::InvalidOrMissing,
-        Err(e) => {
-            tracing::warn!(
-                "failed to get metadata of repodata.json file '{}': {e}. Ignoring cached files...",
-                repo_data_json_path.display()
-            );
-            return ValidatedCacheState::InvalidOrMissing;
-        }
-        Ok(metadata) => metadata,
-    };
-
     // Try to read the repodata state cache
     let cache_state = match RepoDataState::from_path(&cache_state_path) {
         Err(e) if e.kind() == ErrorKind::NotFound => {
@@ -888,6 +2334,11 @@ fn validate_cached_state(
             tracing::debug!("repodata cache state is missing. Ignoring cached files...");
             return ValidatedCacheState::InvalidOrMissing;
         }
+        Err(e) if e.kind() == ErrorKind::Unsupported => {
+            // The entry was written by a newer version, it is replaced by our own.
+            tracing::debug!("{e}. Ignoring cached files...");
+            return ValidatedCacheState::InvalidOrMissing;
+        }
         Err(e) => {
             // An error occurred while reading the cached state.
             tracing::warn!(
@@ -899,23 +2350,28 @@ fn validate_cached_state(
         Ok(state) => state,
     };
 
-    // Do the URLs match?
-    let cached_subdir_url = if cache_state.url.path().ends_with('/') {
-        cache_state.url.clone()
-    } else {
-        let path = cache_state.url.path();
-        let (subdir_path, _) = path.rsplit_once('/').unwrap_or(("", path));
-        let mut url = cache_state.url.clone();
-        url.set_path(&format!("{subdir_path}/"));
-        url
-    };
-    if &cached_subdir_url != subdir_url {
-        tracing::warn!(
-            "cache state refers to a different repodata.json url. Ignoring cached files..."
-        );
+    if !cache_state_applies(&cache_state, subdir_url, allow_compressed, namespace) {
         return ValidatedCacheState::InvalidOrMissing;
     }
 
+    // Check if we have cached repodata.json file
+    let repo_data_json_path = if cache_state.compressed {
+        cache_path.join(format!("{cache_key}.json.zst"))
+    } else {
+        cache_path.join(format!("{cache_key}.json"))
+    };
+    let json_metadata = match std::fs::metadata(&repo_data_json_path) {
+        Err(e) if e.kind() == ErrorKind::NotFound => return ValidatedCacheState::InvalidOrMissing,
+        Err(e) => {
+            tracing::warn!(
+                "failed to get metadata of repodata.json file '{}': {e}. Ignoring cached files...",
+                repo_data_json_path.display()
+            );
+            return ValidatedCacheState::InvalidOrMissing;
+        }
+        Ok(metadata) => metadata,
+    };
+
     // Determine last modified date of the repodata.json file.
     let cache_last_modified = match json_metadata.modified() {
         Err(_) => {
@@ -956,6 +2412,60 @@ fn validate_cached_state(
         }
     }
 
+    validate_cache_freshness(cache_state, cache_last_modified, max_age)
+}
+
+/// Returns true if a cache state, read from disk or from the [`MemoryCache`], can be used by this
+/// fetch. This does not look at the cached files themselves.
+fn cache_state_applies(
+    cache_state: &RepoDataState,
+    subdir_url: &Url,
+    allow_compressed: bool,
+    namespace: Option<&str>,
+) -> bool {
+    // A compressed cache can only be used by callers that know how to read it.
+    if cache_state.compressed && !allow_compressed {
+        tracing::debug!("repodata cache is stored compressed. Ignoring cached files...");
+        return false;
+    }
+
+    // The entry could have been written by a consumer with another namespace whose cache key
+    // collides with ours.
+    if cache_state.namespace.as_deref() != namespace {
+        tracing::warn!(
+            "repodata cache state for '{}' belongs to another cache namespace. Ignoring cached files...",
+            cache_state.url
+        );
+        return false;
+    }
+
+    // Do the URLs match?
+    let cached_subdir_url = if cache_state.url.path().ends_with('/') {
+        cache_state.url.clone()
+    } else {
+        let path = cache_state.url.path();
+        let (subdir_path, _) = path.rsplit_once('/').unwrap_or(("", path));
+        let mut url = cache_state.url.clone();
+        url.set_path(&format!("{subdir_path}/"));
+        url
+    };
+    if &cached_subdir_url != subdir_url {
+        tracing::warn!(
+            "cache state refers to a different repodata.json url. Ignoring cached files..."
+        );
+        return false;
+    }
+
+    true
+}
+
+/// Determines whether the cache state of a repodata.json file that was last modified at
+/// `cache_last_modified` is still up-to-date.
+fn validate_cache_freshness(
+    cache_state: RepoDataState,
+    cache_last_modified: SystemTime,
+    max_age: Option<std::time::Duration>,
+) -> ValidatedCacheState {
     // Determine the age of the cache
     let cache_age = match SystemTime::now().duration_since(cache_last_modified) {
         Ok(duration) => duration,
@@ -965,6 +2475,20 @@ fn validate_cached_state(
         }
     };
 
+    // If the caller specified a maximum age, that takes precedence over the cache headers.
+    if let Some(max_age) = max_age {
+        return if cache_age > max_age {
+            tracing::debug!(
+                "Cache is {} old but can at most be {} old. Assuming out of date...",
+                humantime::format_duration(cache_age),
+                humantime::format_duration(max_age),
+            );
+            ValidatedCacheState::OutOfDate(cache_state)
+        } else {
+            ValidatedCacheState::UpToDate(cache_state)
+        };
+    }
+
     // Parse the cache control header, and determine if the cache is out of date or not.
     if let Some(cache_control) = cache_state.cache_headers.cache_control.as_deref() {
         match CacheControl::from_value(cache_control) {
@@ -1009,21 +2533,33 @@ fn validate_cached_state(
 
 #[cfg(test)]
 mod test {
-    use super::{fetch_repo_data, CacheResult, CachedRepoData, FetchRepoDataOptions};
+    use super::{
+        check_variant_availability, fetch_repo_data, CacheAction, CacheResult, CachedRepoData,
+        CompressionVariant, FetchRepoDataOptions, MemoryCache, RetryOptions,
+        DEFAULT_VARIANT_AVAILABILITY_EXPIRATION,
+    };
     use crate::fetch::{FetchRepoDataError, RepoDataNotFoundError};
     use crate::utils::simple_channel_server::SimpleChannelServer;
     use crate::utils::Encoding;
+    use crate::utils::LockedFile;
     use crate::Reporter;
     use assert_matches::assert_matches;
+    use futures::StreamExt;
     use hex_literal::hex;
+    use rattler_error::ErrorCode;
+    use rattler_networking::retry_policies::DoNotRetryPolicy;
     use rattler_networking::AuthenticationMiddleware;
+    use reqwest::header::{HeaderMap, HeaderValue};
     use reqwest::Client;
     use reqwest_middleware::ClientWithMiddleware;
+    use std::future::IntoFuture;
+    use std::io::Read;
     use std::path::Path;
-    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
     use std::sync::Arc;
     use tempfile::TempDir;
     use tokio::io::AsyncWriteExt;
+    use tokio_util::sync::CancellationToken;
     use url::Url;
 
     async fn write_encoded(
@@ -1125,12 +2661,52 @@ mod test {
             result.cache_state.blake2_hash.unwrap()[..],
             hex!("a1861e448e4a62b88dce47c95351bfbe7fc22451a73f89a09d782492540e0675")[..]
         );
+        assert_eq!(result.metrics.downloaded_bytes, FAKE_REPO_DATA.len() as u64);
         assert_eq!(
             std::fs::read_to_string(result.repo_data_json_path).unwrap(),
             FAKE_REPO_DATA
         );
     }
 
+    #[tracing_test::traced_test]
+    #[tokio::test]
+    pub async fn test_zchunk() {
+        // Create a directory with some repodata and a zchunk file of it.
+        let subdir_path = TempDir::new().unwrap();
+        std::fs::write(subdir_path.path().join("repodata.json"), FAKE_REPO_DATA).unwrap();
+        std::fs::write(
+            subdir_path.path().join("repodata.json.zck"),
+            super::zchunk::test::zchunk_file(&[FAKE_REPO_DATA.as_bytes()]),
+        )
+        .unwrap();
+        let server = SimpleChannelServer::new(subdir_path.path()).await;
+
+        // Download the data from the channel with an empty cache.
+        let cache_dir = TempDir::new().unwrap();
+        let result = fetch_repo_data(
+            server.url(),
+            ClientWithMiddleware::from(Client::new()),
+            cache_dir.path().to_owned(),
+            FetchRepoDataOptions {
+                zchunk_enabled: true,
+                ..FetchRepoDataOptions::default()
+            },
+            None,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(
+            std::fs::read_to_string(&result.repo_data_json_path).unwrap(),
+            FAKE_REPO_DATA
+        );
+        assert!(result.cache_state.url.path().ends_with("repodata.json.zck"));
+        assert!(result.cache_state.has_zck.unwrap().value);
+
+        // The zchunk file is kept to reuse its chunks during the next fetch.
+        assert!(result.repo_data_json_path.with_extension("zck").is_file());
+    }
+
     #[tracing_test::traced_test]
     #[tokio::test]
     pub async fn test_cache_works() {
@@ -1191,26 +2767,709 @@ mod test {
         assert_matches!(cache_result, CacheResult::CacheOutdated);
     }
 
-    #[tracing_test::traced_test]
     #[tokio::test]
-    pub async fn test_zst_works() {
+    pub async fn test_refresh_if_older_than() {
+        // Create a directory with some repodata. The server does not send a `Cache-Control`
+        // header so by default the cache is always considered out of date.
         let subdir_path = TempDir::new().unwrap();
-        write_encoded(
-            FAKE_REPO_DATA.as_bytes(),
-            &subdir_path.path().join("repodata.json.zst"),
-            Encoding::Zst,
-        )
+        std::fs::write(subdir_path.path().join("repodata.json"), FAKE_REPO_DATA).unwrap();
+        let server = SimpleChannelServer::new(subdir_path.path()).await;
+
+        let cache_dir = TempDir::new().unwrap();
+        let fetch = |cache_action| {
+            fetch_repo_data(
+                server.url(),
+                ClientWithMiddleware::from(Client::new()),
+                cache_dir.path().to_owned(),
+                FetchRepoDataOptions {
+                    cache_action,
+                    ..FetchRepoDataOptions::default()
+                },
+                None,
+            )
+        };
+
+        let result = fetch(CacheAction::default()).await.unwrap();
+        assert_matches!(result.cache_result, CacheResult::CacheNotPresent);
+        drop(result);
+
+        let result = fetch(CacheAction::RefreshIfOlderThan(
+            std::time::Duration::from_secs(60 * 60),
+        ))
         .await
         .unwrap();
+        assert_matches!(result.cache_result, CacheResult::CacheHit);
+        drop(result);
+
+        let result = fetch(CacheAction::RefreshIfOlderThan(std::time::Duration::ZERO))
+            .await
+            .unwrap();
+        assert_matches!(
+            result.cache_result,
+            CacheResult::CacheHitAfterFetch | CacheResult::CacheOutdated
+        );
+    }
 
+    #[cfg(feature = "sparse")]
+    #[tokio::test]
+    pub async fn test_repo_data_records() {
+        let subdir_path = TempDir::new().unwrap();
+        std::fs::write(subdir_path.path().join("repodata.json"), FAKE_REPO_DATA).unwrap();
         let server = SimpleChannelServer::new(subdir_path.path()).await;
 
-        // Download the data from the channel with an empty cache.
         let cache_dir = TempDir::new().unwrap();
         let result = fetch_repo_data(
             server.url(),
             ClientWithMiddleware::from(Client::new()),
-            cache_dir.into_path(),
+            cache_dir.path().to_owned(),
+            FetchRepoDataOptions::default(),
+            None,
+        )
+        .await
+        .unwrap();
+
+        let channel = rattler_conda_types::Channel::from_url(server.url());
+        let records = result.repo_data_records(&channel).await.unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].package_record.name.as_normalized(), "asttokens");
+        assert_eq!(records[0].file_name, "asttokens-2.2.1-pyhd8ed1ab_0.conda");
+
+        // The second time the repodata is read from the binary cache.
+        assert!(result
+            .repo_data_json_path
+            .with_extension("msgpack")
+            .is_file());
+        std::fs::write(&result.repo_data_json_path, "invalid").unwrap();
+        let repo_data = result.repo_data().await.unwrap();
+        assert_eq!(repo_data.conda_packages.len(), 1);
+    }
+
+    #[cfg(feature = "sparse")]
+    #[tokio::test]
+    pub async fn test_load_records_recursive() {
+        let repo_data = r#"{
+            "info": {"subdir": "noarch"},
+            "packages": {
+                "foobar-2.0-bla_1.tar.bz2": {"name": "foobar", "version": "2.0", "build": "bla_1", "build_number": 1, "depends": ["bors <2.0"]},
+                "bors-1.2-bla_1.tar.bz2": {"name": "bors", "version": "1.2", "build": "bla_1", "build_number": 1, "depends": []},
+                "baz-1.0-bla_1.tar.bz2": {"name": "baz", "version": "1.0", "build": "bla_1", "build_number": 1, "depends": []}
+            }
+        }"#;
+        let subdir_path = TempDir::new().unwrap();
+        std::fs::write(subdir_path.path().join("repodata.json"), repo_data).unwrap();
+        write_encoded(
+            repo_data.as_bytes(),
+            &subdir_path.path().join("repodata.json.zst"),
+            Encoding::Zst,
+        )
+        .await
+        .unwrap();
+        let server = SimpleChannelServer::new(subdir_path.path()).await;
+        let channel = rattler_conda_types::Channel::from_url(server.url());
+
+        for keep_compressed in [false, true] {
+            let cache_dir = TempDir::new().unwrap();
+            let result = fetch_repo_data(
+                server.url(),
+                ClientWithMiddleware::from(Client::new()),
+                cache_dir.path().to_owned(),
+                FetchRepoDataOptions {
+                    keep_compressed,
+                    ..FetchRepoDataOptions::default()
+                },
+                None,
+            )
+            .await
+            .unwrap();
+            assert_eq!(result.cache_state.compressed, keep_compressed);
+
+            let records = result
+                .load_records_recursive(
+                    &channel,
+                    [rattler_conda_types::PackageName::new_unchecked("foobar")],
+                )
+                .await
+                .unwrap();
+            let mut names = records
+                .iter()
+                .map(|record| record.package_record.name.as_normalized())
+                .collect::<Vec<_>>();
+            names.sort_unstable();
+            assert_eq!(names, ["bors", "foobar"]);
+        }
+    }
+
+    #[cfg(feature = "sparse")]
+    #[tokio::test]
+    pub async fn test_diff_previous() {
+        let subdir_path = TempDir::new().unwrap();
+        let repo_data_path = subdir_path.path().join("repodata.json");
+        std::fs::write(
+            &repo_data_path,
+            r#"{
+                "info": {"subdir": "noarch"},
+                "packages": {
+                    "foobar-1.0-bla_1.tar.bz2": {"name": "foobar", "version": "1.0", "build": "bla_1", "build_number": 1, "depends": []},
+                    "bors-1.2-bla_1.tar.bz2": {"name": "bors", "version": "1.2", "build": "bla_1", "build_number": 1, "depends": []}
+                }
+            }"#,
+        )
+        .unwrap();
+        let server = SimpleChannelServer::new(subdir_path.path()).await;
+        let cache_dir = TempDir::new().unwrap();
+        let fetch = || {
+            fetch_repo_data(
+                server.url(),
+                ClientWithMiddleware::from(Client::new()),
+                cache_dir.path().to_owned(),
+                FetchRepoDataOptions {
+                    cache_action: CacheAction::RefreshIfOlderThan(std::time::Duration::ZERO),
+                    keep_previous: true,
+                    ..FetchRepoDataOptions::default()
+                },
+                None,
+            )
+        };
+
+        // There is nothing to compare with after the first fetch.
+        let result = fetch().await.unwrap();
+        assert_eq!(result.diff_previous().await.unwrap(), None);
+        drop(result);
+
+        // Fetching unchanged repodata does not replace the previous version.
+        let result = fetch().await.unwrap();
+        assert_eq!(result.diff_previous().await.unwrap(), None);
+        drop(result);
+
+        std::fs::write(
+            &repo_data_path,
+            r#"{
+                "info": {"subdir": "noarch"},
+                "packages": {
+                    "foobar-1.0-bla_1.tar.bz2": {"name": "foobar", "version": "1.0", "build": "bla_1", "build_number": 1, "depends": ["bors"]},
+                    "baz-1.0-bla_1.tar.bz2": {"name": "baz", "version": "1.0", "build": "bla_1", "build_number": 1, "depends": []}
+                }
+            }"#,
+        )
+        .unwrap();
+        // The server only sends the modification time with seconds precision.
+        std::fs::File::options()
+            .write(true)
+            .open(&repo_data_path)
+            .unwrap()
+            .set_modified(std::time::SystemTime::now() + std::time::Duration::from_secs(60))
+            .unwrap();
+        let result = fetch().await.unwrap();
+        assert_eq!(result.cache_result, CacheResult::CacheOutdated);
+        let diff = result.diff_previous().await.unwrap().unwrap();
+        assert_eq!(
+            diff.added.keys().collect::<Vec<_>>(),
+            ["baz-1.0-bla_1.tar.bz2"]
+        );
+        assert_eq!(
+            diff.removed.keys().collect::<Vec<_>>(),
+            ["bors-1.2-bla_1.tar.bz2"]
+        );
+        let changed = &diff.changed["foobar-1.0-bla_1.tar.bz2"];
+        assert!(changed.previous.depends.is_empty());
+        assert_eq!(changed.current.depends, ["bors"]);
+    }
+
+    #[cfg(feature = "sparse")]
+    #[tokio::test]
+    pub async fn test_patch_instructions() {
+        let subdir_path = TempDir::new().unwrap();
+        std::fs::write(subdir_path.path().join("repodata.json"), FAKE_REPO_DATA).unwrap();
+        let server = SimpleChannelServer::new(subdir_path.path()).await;
+        let cache_dir = TempDir::new().unwrap();
+        let fetch = || {
+            super::fetch_patch_instructions(
+                server.url(),
+                ClientWithMiddleware::from(Client::new()),
+                cache_dir.path().to_owned(),
+                FetchRepoDataOptions {
+                    cache_action: CacheAction::NoCache,
+                    ..FetchRepoDataOptions::default()
+                },
+                None,
+            )
+        };
+
+        // The channel does not provide any patches.
+        assert_eq!(fetch().await.unwrap(), None);
+
+        std::fs::write(
+            subdir_path.path().join("patch_instructions.json"),
+            r#"{"remove": ["asttokens-2.2.1-pyhd8ed1ab_0.conda"]}"#,
+        )
+        .unwrap();
+        let patch_instructions = fetch().await.unwrap().unwrap();
+
+        let result = fetch_repo_data(
+            server.url(),
+            ClientWithMiddleware::from(Client::new()),
+            cache_dir.path().to_owned(),
+            FetchRepoDataOptions::default(),
+            None,
+        )
+        .await
+        .unwrap();
+        let mut repo_data = result.repo_data().await.unwrap();
+        repo_data.apply_patches(&patch_instructions);
+        assert!(repo_data.conda_packages.is_empty());
+        assert!(repo_data
+            .removed
+            .contains("asttokens-2.2.1-pyhd8ed1ab_0.conda"));
+    }
+
+    #[cfg(feature = "sparse")]
+    #[tokio::test]
+    pub async fn test_run_exports() {
+        let subdir_path = TempDir::new().unwrap();
+        std::fs::write(
+            subdir_path.path().join("run_exports.json"),
+            r#"{
+                "info": { "subdir": "noarch" },
+                "packages.conda": {
+                    "asttokens-2.2.1-pyhd8ed1ab_0.conda": {
+                        "run_exports": { "weak": ["asttokens >=2.2.1"] }
+                    }
+                }
+            }"#,
+        )
+        .unwrap();
+        let server = SimpleChannelServer::new(subdir_path.path()).await;
+        let cache_dir = TempDir::new().unwrap();
+
+        let run_exports = super::fetch_run_exports(
+            server.url(),
+            ClientWithMiddleware::from(Client::new()),
+            cache_dir.path().to_owned(),
+            FetchRepoDataOptions::default(),
+            None,
+        )
+        .await
+        .unwrap()
+        .unwrap();
+        assert_eq!(
+            run_exports
+                .get("asttokens-2.2.1-pyhd8ed1ab_0.conda")
+                .unwrap()
+                .weak,
+            vec!["asttokens >=2.2.1"]
+        );
+
+        // The file is cached like the repodata.
+        let run_exports_path = std::fs::read_dir(cache_dir.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .find(|path| {
+                path.extension().is_some_and(|ext| ext == "json")
+                    && !path.to_string_lossy().ends_with(".info.json")
+            })
+            .unwrap();
+        assert!(std::fs::read_to_string(run_exports_path)
+            .unwrap()
+            .contains("asttokens"));
+    }
+
+    #[tokio::test]
+    pub async fn test_prefer_current_repodata() {
+        let subdir_path = TempDir::new().unwrap();
+        std::fs::write(subdir_path.path().join("repodata.json"), FAKE_REPO_DATA).unwrap();
+        let server = SimpleChannelServer::new(subdir_path.path()).await;
+        let cache_dir = TempDir::new().unwrap();
+        let fetch = || {
+            fetch_repo_data(
+                server.url(),
+                ClientWithMiddleware::from(Client::new()),
+                cache_dir.path().to_owned(),
+                FetchRepoDataOptions {
+                    prefer_current_repodata: true,
+                    ..FetchRepoDataOptions::default()
+                },
+                None,
+            )
+        };
+
+        // Fall back to the full repodata if there is no current_repodata.json.
+        let result = fetch().await.unwrap();
+        assert!(result.cache_state.url.path().ends_with("/repodata.json"));
+        drop(result);
+
+        std::fs::write(
+            subdir_path.path().join("current_repodata.json"),
+            FAKE_REPO_DATA,
+        )
+        .unwrap();
+        let result = fetch().await.unwrap();
+        assert!(result
+            .cache_state
+            .url
+            .path()
+            .ends_with("/current_repodata.json"));
+    }
+
+    #[tokio::test]
+    pub async fn test_memory_cache() {
+        let subdir_path = TempDir::new().unwrap();
+        std::fs::write(subdir_path.path().join("repodata.json"), FAKE_REPO_DATA).unwrap();
+        let server = SimpleChannelServer::new(subdir_path.path()).await;
+
+        let cache_dir = TempDir::new().unwrap();
+        let memory_cache = MemoryCache::new();
+        let fetch = |cache_action| {
+            fetch_repo_data(
+                server.url(),
+                ClientWithMiddleware::from(Client::new()),
+                cache_dir.path().to_owned(),
+                FetchRepoDataOptions {
+                    cache_action,
+                    memory_cache: Some(memory_cache.clone()),
+                    ..FetchRepoDataOptions::default()
+                },
+                None,
+            )
+        };
+
+        let result = fetch(CacheAction::default()).await.unwrap();
+        assert_matches!(result.cache_result, CacheResult::CacheNotPresent);
+        let repo_data_json_path = result.repo_data_json_path.clone();
+        let cache_state_path = repo_data_json_path.with_extension("info.json");
+        drop(result);
+
+        // Without the state on disk the cache can only be used because it is still in memory.
+        std::fs::remove_file(&cache_state_path).unwrap();
+        let result = fetch(CacheAction::ForceCacheOnly).await.unwrap();
+        assert_matches!(result.cache_result, CacheResult::CacheHit);
+        drop(result);
+
+        // Modifying the repodata on disk invalidates the entry in memory.
+        std::fs::write(&repo_data_json_path, FAKE_REPO_DATA).unwrap();
+        assert_matches!(
+            fetch(CacheAction::ForceCacheOnly).await,
+            Err(FetchRepoDataError::NoCacheAvailable)
+        );
+    }
+
+    #[tokio::test]
+    pub async fn test_memory_cache_compressed() {
+        let subdir_path = TempDir::new().unwrap();
+        write_encoded(
+            FAKE_REPO_DATA.as_bytes(),
+            &subdir_path.path().join("repodata.json.zst"),
+            Encoding::Zst,
+        )
+        .await
+        .unwrap();
+        let server = SimpleChannelServer::new(subdir_path.path()).await;
+
+        let cache_dir = TempDir::new().unwrap();
+        let memory_cache = MemoryCache::new();
+        let fetch = |cache_action, keep_compressed| {
+            fetch_repo_data(
+                server.url(),
+                ClientWithMiddleware::from(Client::new()),
+                cache_dir.path().to_owned(),
+                FetchRepoDataOptions {
+                    cache_action,
+                    keep_compressed,
+                    memory_cache: Some(memory_cache.clone()),
+                    ..FetchRepoDataOptions::default()
+                },
+                None,
+            )
+        };
+
+        let result = fetch(CacheAction::default(), true).await.unwrap();
+        assert!(result.cache_state.compressed);
+        let cache_state_path = result
+            .repo_data_json_path
+            .with_extension("")
+            .with_extension("info.json");
+        drop(result);
+
+        // The compressed entry is found in memory.
+        std::fs::remove_file(&cache_state_path).unwrap();
+        let result = fetch(CacheAction::ForceCacheOnly, true).await.unwrap();
+        assert_matches!(result.cache_result, CacheResult::CacheHit);
+        assert!(result.cache_state.compressed);
+        drop(result);
+
+        // But it is not used by callers that cannot read compressed repodata.
+        assert_matches!(
+            fetch(CacheAction::ForceCacheOnly, false).await,
+            Err(FetchRepoDataError::NoCacheAvailable)
+        );
+    }
+
+    #[tokio::test]
+    pub async fn test_stale_if_error() {
+        // Start a server that can be switched to responding with an error.
+        let unavailable = Arc::new(AtomicBool::new(false));
+        let app = axum::Router::new().route(
+            "/repodata.json",
+            axum::routing::get({
+                let unavailable = unavailable.clone();
+                move || async move {
+                    if unavailable.load(Ordering::SeqCst) {
+                        Err(axum::http::StatusCode::SERVICE_UNAVAILABLE)
+                    } else {
+                        Ok(FAKE_REPO_DATA)
+                    }
+                }
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = Url::parse(&format!("http://{}/", listener.local_addr().unwrap())).unwrap();
+        tokio::spawn(axum::serve(listener, app).into_future());
+
+        let cache_dir = TempDir::new().unwrap();
+        let fetch = |stale_if_error| {
+            fetch_repo_data(
+                url.clone(),
+                ClientWithMiddleware::from(Client::new()),
+                cache_dir.path().to_owned(),
+                FetchRepoDataOptions {
+                    stale_if_error,
+                    retry: RetryOptions {
+                        policy: Arc::new(DoNotRetryPolicy),
+                        ..RetryOptions::default()
+                    },
+                    ..FetchRepoDataOptions::default()
+                },
+                None,
+            )
+        };
+
+        let result = fetch(true).await.unwrap();
+        assert_matches!(result.cache_result, CacheResult::CacheNotPresent);
+        drop(result);
+
+        unavailable.store(true, Ordering::SeqCst);
+        assert_matches!(fetch(false).await, Err(FetchRepoDataError::HttpError(_)));
+
+        let result = fetch(true).await.unwrap();
+        assert_matches!(result.cache_result, CacheResult::StaleCacheFallback);
+        assert_eq!(
+            std::fs::read_to_string(result.repo_data_json_path).unwrap(),
+            FAKE_REPO_DATA
+        );
+    }
+
+    #[tokio::test]
+    pub async fn test_custom_headers() {
+        // Start a server that rejects every request without an API key.
+        let missing_key = Arc::new(AtomicBool::new(false));
+        let app = axum::Router::new().fallback({
+            let missing_key = missing_key.clone();
+            move |uri: axum::http::Uri, headers: axum::http::HeaderMap| async move {
+                if headers.get("x-api-key").map(|value| value.as_bytes()) != Some(b"secret") {
+                    missing_key.store(true, Ordering::SeqCst);
+                    Err(axum::http::StatusCode::UNAUTHORIZED)
+                } else if uri.path() == "/repodata.json" {
+                    Ok(FAKE_REPO_DATA)
+                } else {
+                    Err(axum::http::StatusCode::NOT_FOUND)
+                }
+            }
+        });
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = Url::parse(&format!("http://{}/", listener.local_addr().unwrap())).unwrap();
+        tokio::spawn(axum::serve(listener, app).into_future());
+
+        let mut headers = HeaderMap::new();
+        headers.insert("x-api-key", HeaderValue::from_static("secret"));
+
+        let cache_dir = TempDir::new().unwrap();
+        let result = fetch_repo_data(
+            url,
+            ClientWithMiddleware::from(Client::new()),
+            cache_dir.path().to_owned(),
+            FetchRepoDataOptions {
+                headers,
+                ..FetchRepoDataOptions::default()
+            },
+            None,
+        )
+        .await
+        .unwrap();
+
+        assert!(!missing_key.load(Ordering::SeqCst));
+        assert_eq!(
+            std::fs::read_to_string(result.repo_data_json_path).unwrap(),
+            FAKE_REPO_DATA
+        );
+    }
+
+    #[tokio::test]
+    pub async fn test_checksum_header() {
+        // Start a server that sends the checksum of the repodata, or a wrong one.
+        let correct = format!(
+            "{:x}",
+            rattler_digest::compute_bytes_digest::<rattler_digest::Sha256>(FAKE_REPO_DATA)
+        );
+        let checksum = Arc::new(std::sync::Mutex::new(correct.clone()));
+        let app = axum::Router::new().route(
+            "/repodata.json",
+            axum::routing::get({
+                let checksum = checksum.clone();
+                move || async move {
+                    let checksum = checksum.lock().unwrap().clone();
+                    ([("X-Checksum-Sha256", checksum)], FAKE_REPO_DATA)
+                }
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = Url::parse(&format!("http://{}/", listener.local_addr().unwrap())).unwrap();
+        tokio::spawn(axum::serve(listener, app).into_future());
+
+        let cache_dir = TempDir::new().unwrap();
+        let fetch = || {
+            fetch_repo_data(
+                url.clone(),
+                ClientWithMiddleware::from(Client::new()),
+                cache_dir.path().to_owned(),
+                FetchRepoDataOptions {
+                    cache_action: CacheAction::NoCache,
+                    ..FetchRepoDataOptions::default()
+                },
+                None,
+            )
+        };
+
+        let result = fetch().await.unwrap();
+        assert_eq!(
+            format!("{:x}", result.cache_state.verified_sha256.unwrap()),
+            correct
+        );
+        drop(result);
+
+        *checksum.lock().unwrap() = "0".repeat(64);
+        let err = fetch().await.unwrap_err();
+        assert_eq!(err.code(), "rattler::fetch_repodata::checksum_mismatch");
+        assert_matches!(err, FetchRepoDataError::ChecksumMismatch { actual, .. } if actual == correct);
+    }
+
+    #[tokio::test]
+    pub async fn test_timeouts() {
+        // Start a server that takes a long time to respond.
+        let app = axum::Router::new().fallback(|| async {
+            tokio::time::sleep(std::time::Duration::from_secs(10)).await;
+            FAKE_REPO_DATA
+        });
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = Url::parse(&format!("http://{}/", listener.local_addr().unwrap())).unwrap();
+        tokio::spawn(axum::serve(listener, app).into_future());
+
+        let cache_dir = TempDir::new().unwrap();
+        let fetch = |options| {
+            fetch_repo_data(
+                url.clone(),
+                ClientWithMiddleware::from(Client::new()),
+                cache_dir.path().to_owned(),
+                FetchRepoDataOptions {
+                    retry: RetryOptions {
+                        policy: Arc::new(DoNotRetryPolicy),
+                        ..RetryOptions::default()
+                    },
+                    ..options
+                },
+                None,
+            )
+        };
+
+        let result = fetch(FetchRepoDataOptions {
+            timeout: Some(std::time::Duration::from_millis(100)),
+            ..FetchRepoDataOptions::default()
+        })
+        .await;
+        assert_matches!(result, Err(FetchRepoDataError::TimedOut));
+
+        let result = fetch(FetchRepoDataOptions {
+            connect_timeout: Some(std::time::Duration::from_millis(100)),
+            compression: CompressionVariant::Plain,
+            ..FetchRepoDataOptions::default()
+        })
+        .await;
+        assert_matches!(result, Err(FetchRepoDataError::TimedOut));
+    }
+
+    #[tokio::test]
+    pub async fn test_conda_token() {
+        // Start a server that only serves the repodata with a token in the url.
+        let app = axum::Router::new().route(
+            "/t/secret/repodata.json",
+            axum::routing::get(|| async { FAKE_REPO_DATA }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(axum::serve(listener, app).into_future());
+
+        let cache_dir = TempDir::new().unwrap();
+        let result = fetch_repo_data(
+            Url::parse(&format!("http://{addr}/")).unwrap(),
+            ClientWithMiddleware::from(Client::new()),
+            cache_dir.path().to_owned(),
+            FetchRepoDataOptions {
+                conda_token: Some(String::from("secret")),
+                ..FetchRepoDataOptions::default()
+            },
+            None,
+        )
+        .await
+        .unwrap();
+        assert_matches!(result.cache_result, CacheResult::CacheNotPresent);
+        assert!(!result.cache_state.url.as_str().contains("secret"));
+        let repo_data_json_path = result.repo_data_json_path.clone();
+        drop(result);
+
+        // Nothing in the cache should contain the token.
+        for entry in std::fs::read_dir(cache_dir.path()).unwrap() {
+            let path = entry.unwrap().path();
+            assert!(!path.to_string_lossy().contains("secret"));
+            if path.extension().is_some_and(|ext| ext == "json") {
+                assert!(!std::fs::read_to_string(&path).unwrap().contains("secret"));
+            }
+        }
+
+        // A token in the url uses the same cache entry.
+        let result = fetch_repo_data(
+            Url::parse(&format!("http://{addr}/t/secret/")).unwrap(),
+            ClientWithMiddleware::from(Client::new()),
+            cache_dir.path().to_owned(),
+            FetchRepoDataOptions {
+                cache_action: CacheAction::ForceCacheOnly,
+                ..FetchRepoDataOptions::default()
+            },
+            None,
+        )
+        .await
+        .unwrap();
+        assert_eq!(result.repo_data_json_path, repo_data_json_path);
+        assert_matches!(result.cache_result, CacheResult::CacheHit);
+    }
+
+    #[tracing_test::traced_test]
+    #[tokio::test]
+    pub async fn test_zst_works() {
+        let subdir_path = TempDir::new().unwrap();
+        write_encoded(
+            FAKE_REPO_DATA.as_bytes(),
+            &subdir_path.path().join("repodata.json.zst"),
+            Encoding::Zst,
+        )
+        .await
+        .unwrap();
+
+        let server = SimpleChannelServer::new(subdir_path.path()).await;
+
+        // Download the data from the channel with an empty cache.
+        let cache_dir = TempDir::new().unwrap();
+        let result = fetch_repo_data(
+            server.url(),
+            ClientWithMiddleware::from(Client::new()),
+            cache_dir.into_path(),
             FetchRepoDataOptions::default(),
             None,
         )
@@ -1218,19 +3477,140 @@ mod test {
         .unwrap();
 
         assert_eq!(
-            std::fs::read_to_string(result.repo_data_json_path).unwrap(),
-            FAKE_REPO_DATA
-        );
-        assert_matches!(
-            result.cache_state.has_zst, Some(super::Expiring {
-                value, ..
-            }) if value
-        );
-        assert_matches!(
-            result.cache_state.has_bz2, Some(super::Expiring {
-                value, ..
-            }) if !value
+            std::fs::read_to_string(result.repo_data_json_path).unwrap(),
+            FAKE_REPO_DATA
+        );
+        assert_matches!(
+            result.cache_state.has_zst, Some(super::Expiring {
+                value, ..
+            }) if value
+        );
+        assert_matches!(
+            result.cache_state.has_bz2, Some(super::Expiring {
+                value, ..
+            }) if !value
+        );
+    }
+
+    #[tokio::test]
+    pub async fn test_keep_compressed() {
+        let subdir_path = TempDir::new().unwrap();
+        write_encoded(
+            FAKE_REPO_DATA.as_bytes(),
+            &subdir_path.path().join("repodata.json.zst"),
+            Encoding::Zst,
+        )
+        .await
+        .unwrap();
+        let server = SimpleChannelServer::new(subdir_path.path()).await;
+
+        // The downloaded file is stored as-is.
+        let cache_dir = TempDir::new().unwrap();
+        let options = FetchRepoDataOptions {
+            keep_compressed: true,
+            validate_repo_data: true,
+            ..FetchRepoDataOptions::default()
+        };
+        let fetch = |options: FetchRepoDataOptions| {
+            fetch_repo_data(
+                server.url(),
+                ClientWithMiddleware::from(Client::new()),
+                cache_dir.path().to_owned(),
+                options,
+                None,
+            )
+        };
+        let result = fetch(options.clone()).await.unwrap();
+        assert!(result.cache_state.compressed);
+        assert_eq!(
+            result
+                .repo_data_json_path
+                .extension()
+                .and_then(|ext| ext.to_str()),
+            Some("zst")
+        );
+        assert_eq!(
+            std::fs::read(&result.repo_data_json_path).unwrap(),
+            std::fs::read(subdir_path.path().join("repodata.json.zst")).unwrap()
+        );
+        let mut repo_data = String::new();
+        result
+            .repo_data_reader()
+            .unwrap()
+            .read_to_string(&mut repo_data)
+            .unwrap();
+        assert_eq!(repo_data, FAKE_REPO_DATA);
+        drop(result);
+
+        // The compressed cache is used as long as compression is requested.
+        let result = fetch(options).await.unwrap();
+        assert_matches!(
+            result.cache_result,
+            CacheResult::CacheHit | CacheResult::CacheHitAfterFetch
+        );
+        assert!(result.cache_state.compressed);
+        drop(result);
+
+        // Otherwise the repodata is fetched again and stored uncompressed.
+        let result = fetch(FetchRepoDataOptions::default()).await.unwrap();
+        assert_eq!(result.cache_result, CacheResult::CacheNotPresent);
+        assert!(!result.cache_state.compressed);
+        assert_eq!(
+            std::fs::read_to_string(&result.repo_data_json_path).unwrap(),
+            FAKE_REPO_DATA
+        );
+        assert!(!result
+            .repo_data_json_path
+            .with_extension("json.zst")
+            .exists());
+    }
+
+    #[tokio::test]
+    pub async fn test_cache_namespace() {
+        let subdir_path = TempDir::new().unwrap();
+        std::fs::write(subdir_path.path().join("repodata.json"), FAKE_REPO_DATA).unwrap();
+        let server = SimpleChannelServer::new(subdir_path.path()).await;
+        let cache_dir = TempDir::new().unwrap();
+        let fetch = |cache_action: CacheAction, cache_namespace: Option<&str>| {
+            fetch_repo_data(
+                server.url(),
+                ClientWithMiddleware::from(Client::new()),
+                cache_dir.path().to_owned(),
+                FetchRepoDataOptions {
+                    cache_action,
+                    cache_namespace: cache_namespace.map(ToOwned::to_owned),
+                    ..FetchRepoDataOptions::default()
+                },
+                None,
+            )
+        };
+
+        // Every namespace has its own cache entry.
+        let mut paths = Vec::new();
+        for namespace in [None, Some("first"), Some("second")] {
+            let result = fetch(CacheAction::CacheOrFetch, namespace).await.unwrap();
+            assert_eq!(result.cache_result, CacheResult::CacheNotPresent);
+            assert_eq!(result.cache_state.namespace.as_deref(), namespace);
+            paths.push(result.repo_data_json_path.clone());
+        }
+        assert_eq!(
+            paths.iter().collect::<std::collections::HashSet<_>>().len(),
+            3
         );
+
+        // An entry that was written for another namespace is not used.
+        let state_path = paths[1].with_extension("info.json");
+        let mut state = super::cache::RepoDataState::from_path(&state_path).unwrap();
+        state.namespace = Some("second".to_owned());
+        state.to_path(&state_path).unwrap();
+        let err = fetch(CacheAction::ForceCacheOnly, Some("first"))
+            .await
+            .unwrap_err();
+        assert_matches!(err, FetchRepoDataError::NoCacheAvailable);
+        let result = fetch(CacheAction::ForceCacheOnly, Some("second"))
+            .await
+            .unwrap();
+        assert_eq!(result.repo_data_json_path, paths[2]);
     }
 
     #[tracing_test::traced_test]
@@ -1325,6 +3705,116 @@ mod test {
         );
     }
 
+    #[tokio::test]
+    pub async fn test_forced_compression_variant() {
+        let subdir_path = TempDir::new().unwrap();
+        std::fs::write(subdir_path.path().join("repodata.json"), FAKE_REPO_DATA).unwrap();
+        write_encoded(
+            FAKE_REPO_DATA.as_bytes(),
+            &subdir_path.path().join("repodata.json.bz2"),
+            Encoding::Bz2,
+        )
+        .await
+        .unwrap();
+        write_encoded(
+            FAKE_REPO_DATA.as_bytes(),
+            &subdir_path.path().join("repodata.json.zst"),
+            Encoding::Zst,
+        )
+        .await
+        .unwrap();
+
+        let server = SimpleChannelServer::new(subdir_path.path()).await;
+
+        for (compression, file_name) in [
+            (CompressionVariant::Plain, "repodata.json"),
+            (CompressionVariant::Bz2, "repodata.json.bz2"),
+            (CompressionVariant::Zst, "repodata.json.zst"),
+        ] {
+            let cache_dir = TempDir::new().unwrap();
+            let result = fetch_repo_data(
+                server.url(),
+                ClientWithMiddleware::from(Client::new()),
+                cache_dir.into_path(),
+                FetchRepoDataOptions {
+                    compression,
+                    ..FetchRepoDataOptions::default()
+                },
+                None,
+            )
+            .await
+            .unwrap();
+
+            assert_eq!(
+                std::fs::read_to_string(result.repo_data_json_path).unwrap(),
+                FAKE_REPO_DATA
+            );
+            assert!(result.cache_state.url.path().ends_with(file_name));
+
+            // The server has not been probed for the available variants.
+            assert!(result.cache_state.has_zst.is_none());
+            assert!(result.cache_state.has_bz2.is_none());
+            assert!(result.cache_state.has_jlap.is_none());
+        }
+    }
+
+    #[tokio::test]
+    pub async fn test_variant_availability_expiration() {
+        let subdir_path = TempDir::new().unwrap();
+        std::fs::write(subdir_path.path().join("repodata.json"), FAKE_REPO_DATA).unwrap();
+        let server = SimpleChannelServer::new(subdir_path.path()).await;
+
+        let client = ClientWithMiddleware::from(Client::new());
+        let cache_dir = TempDir::new().unwrap();
+        let result = fetch_repo_data(
+            server.url(),
+            client.clone(),
+            cache_dir.into_path(),
+            FetchRepoDataOptions::default(),
+            None,
+        )
+        .await
+        .unwrap();
+        assert_matches!(
+            result.cache_state.has_zst, Some(super::Expiring {
+                value, ..
+            }) if !value
+        );
+
+        // Add a zst variant after the availability has been cached.
+        write_encoded(
+            FAKE_REPO_DATA.as_bytes(),
+            &subdir_path.path().join("repodata.json.zst"),
+            Encoding::Zst,
+        )
+        .await
+        .unwrap();
+
+        let availability = check_variant_availability(
+            &client,
+            &server.url(),
+            Some(&result.cache_state),
+            "repodata.json",
+            &RetryOptions::default(),
+            &HeaderMap::default(),
+            DEFAULT_VARIANT_AVAILABILITY_EXPIRATION,
+        )
+        .await;
+        assert!(!availability.has_zst());
+
+        let availability = check_variant_availability(
+            &client,
+            &server.url(),
+            Some(&result.cache_state),
+            "repodata.json",
+            &RetryOptions::default(),
+            &HeaderMap::default(),
+            std::time::Duration::ZERO,
+        )
+        .await;
+        assert!(availability.has_zst());
+    }
+
     #[tracing_test::traced_test]
     #[tokio::test]
     pub async fn test_gzip_transfer_encoding() {
@@ -1412,6 +3902,153 @@ mod test {
         assert_eq!(reporter.last_download_progress.load(Ordering::SeqCst), 1110);
     }
 
+    #[tokio::test]
+    pub async fn test_progress_phases() {
+        // Create a directory with some repodata.
+        let subdir_path = TempDir::new().unwrap();
+        std::fs::write(subdir_path.path().join("repodata.json"), FAKE_REPO_DATA).unwrap();
+        let server = SimpleChannelServer::new(subdir_path.path()).await;
+
+        #[derive(Default)]
+        struct PhaseReporter {
+            events: std::sync::Mutex<Vec<&'static str>>,
+        }
+
+        impl PhaseReporter {
+            fn push(&self, event: &'static str) -> usize {
+                let mut events = self.events.lock().unwrap();
+                events.push(event);
+                events.len()
+            }
+        }
+
+        impl Reporter for PhaseReporter {
+            fn on_download_start(&self, _url: &Url) -> usize {
+                self.push("download_start")
+            }
+            fn on_download_complete(&self, _url: &Url, _index: usize) {
+                self.push("download_complete");
+            }
+            fn on_cache_lock_start(&self, _url: &Url) -> usize {
+                self.push("cache_lock_start")
+            }
+            fn on_cache_lock_acquired(&self, _index: usize) {
+                self.push("cache_lock_acquired");
+            }
+            fn on_variant_check_start(&self, _url: &Url) -> usize {
+                self.push("variant_check_start")
+            }
+            fn on_variant_check_completed(&self, _index: usize) {
+                self.push("variant_check_completed");
+            }
+            fn on_decode_start(&self, _url: &Url) -> usize {
+                self.push("decode_start")
+            }
+            fn on_decode_completed(&self, _index: usize) {
+                self.push("decode_completed");
+            }
+            fn on_cache_write_start(&self, _url: &Url) -> usize {
+                self.push("cache_write_start")
+            }
+            fn on_cache_write_completed(&self, _index: usize) {
+                self.push("cache_write_completed");
+            }
+        }
+
+        let reporter = Arc::new(PhaseReporter::default());
+        let cache_dir = TempDir::new().unwrap();
+        fetch_repo_data(
+            server.url(),
+            ClientWithMiddleware::from(Client::new()),
+            cache_dir.into_path(),
+            FetchRepoDataOptions::default(),
+            Some(reporter.clone()),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(
+            *reporter.events.lock().unwrap(),
+            [
+                "cache_lock_start",
+                "cache_lock_acquired",
+                "variant_check_start",
+                "variant_check_completed",
+                "download_start",
+                "decode_start",
+                "decode_completed",
+                "download_complete",
+                "cache_write_start",
+                "cache_write_completed",
+            ]
+        );
+    }
+
+    #[tracing_test::traced_test]
+    #[tokio::test]
+    pub async fn test_local_zst_channel() {
+        // Create a local channel that only contains compressed repodata.
+        let subdir_path = TempDir::new().unwrap();
+        write_encoded(
+            FAKE_REPO_DATA.as_bytes(),
+            &subdir_path.path().join("repodata.json.zst"),
+            Encoding::Zst,
+        )
+        .await
+        .unwrap();
+        let subdir_url = Url::from_directory_path(subdir_path.path()).unwrap();
+
+        let cache_dir = TempDir::new().unwrap();
+        let fetch = || {
+            fetch_repo_data(
+                subdir_url.clone(),
+                ClientWithMiddleware::from(Client::new()),
+                cache_dir.path().to_owned(),
+                FetchRepoDataOptions::default(),
+                None,
+            )
+        };
+
+        // The repodata is decompressed into the cache.
+        let result = fetch().await.unwrap();
+        assert_matches!(result.cache_result, CacheResult::CacheNotPresent);
+        assert_eq!(
+            std::fs::read_to_string(&result.repo_data_json_path).unwrap(),
+            FAKE_REPO_DATA
+        );
+        assert_eq!(
+            result.cache_state.blake2_hash.unwrap()[..],
+            hex!("a1861e448e4a62b88dce47c95351bfbe7fc22451a73f89a09d782492540e0675")[..]
+        );
+        drop(result);
+
+        // The cached copy is reused if the source did not change.
+        let result = fetch().await.unwrap();
+        assert_matches!(result.cache_result, CacheResult::CacheHit);
+        drop(result);
+
+        // Modifying the source invalidates the cache. The size of the source changes as well, so
+        // the change is detected even if the modification time does not (e.g. on file systems
+        // with a coarse timestamp resolution).
+        let source_path = subdir_path.path().join("repodata.json.zst");
+        let previous_size = std::fs::metadata(&source_path).unwrap().len();
+        write_encoded(
+            FAKE_REPO_DATA
+                .replace("asttokens", "asttokens-modified")
+                .as_bytes(),
+            &source_path,
+            Encoding::Zst,
+        )
+        .await
+        .unwrap();
+        assert_ne!(
+            std::fs::metadata(&source_path).unwrap().len(),
+            previous_size
+        );
+        let result = fetch().await.unwrap();
+        assert_matches!(result.cache_result, CacheResult::CacheOutdated);
+    }
+
     #[tracing_test::traced_test]
     #[tokio::test]
     pub async fn test_repodata_not_found() {
@@ -1460,5 +4097,303 @@ mod test {
                 RepoDataNotFoundError::HttpError(_)
             ))
         ));
+
+        // The error has the code of the error it wraps, also when it is
+        // returned by the gateway.
+        let err = result.unwrap_err();
+        assert_eq!(err.code(), "rattler::fetch_repodata::not_found::http");
+        #[cfg(feature = "gateway")]
+        assert_eq!(
+            crate::GatewayError::from(err).code(),
+            "rattler::fetch_repodata::not_found::http"
+        );
+    }
+
+    #[tokio::test]
+    pub async fn test_cancellation() {
+        // Start a server that sends the first bytes of the repodata and then stalls.
+        let app = axum::Router::new().route(
+            "/repodata.json",
+            axum::routing::get(|| async {
+                axum::body::Body::from_stream(
+                    futures::stream::once(async {
+                        Ok::<_, std::io::Error>(bytes::Bytes::from_static(b"{"))
+                    })
+                    .chain(futures::stream::pending()),
+                )
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = Url::parse(&format!("http://{}/", listener.local_addr().unwrap())).unwrap();
+        tokio::spawn(axum::serve(listener, app).into_future());
+
+        // Cancel the download as soon as the first bytes have been received.
+        struct CancelOnProgress(CancellationToken);
+        impl Reporter for CancelOnProgress {
+            fn on_download_progress(&self, _: &Url, _: usize, _: usize, _: Option<usize>) {
+                self.0.cancel();
+            }
+        }
+
+        let cancellation_token = CancellationToken::new();
+        let cache_dir = TempDir::new().unwrap();
+        let result = fetch_repo_data(
+            url,
+            ClientWithMiddleware::from(Client::new()),
+            cache_dir.path().to_owned(),
+            FetchRepoDataOptions {
+                cancellation_token: Some(cancellation_token.clone()),
+                ..FetchRepoDataOptions::default()
+            },
+            Some(Arc::new(CancelOnProgress(cancellation_token))),
+        )
+        .await;
+        assert_matches!(result, Err(FetchRepoDataError::Cancelled));
+
+        // The partially downloaded file has been removed and the lock has been released.
+        let mut lock_file_path = None;
+        for entry in std::fs::read_dir(cache_dir.path()).unwrap() {
+            let path = entry.unwrap().path();
+            assert_eq!(path.extension().and_then(|ext| ext.to_str()), Some("lock"));
+            lock_file_path = Some(path);
+        }
+        let lock_file_path = lock_file_path.expect("a lock file should have been created");
+        tokio::time::timeout(
+            std::time::Duration::from_secs(10),
+            tokio::task::spawn_blocking(move || LockedFile::open_rw(lock_file_path, "test")),
+        )
+        .await
+        .expect("the lock should have been released")
+        .unwrap()
+        .unwrap();
+    }
+
+    #[tokio::test]
+    pub async fn test_cache_lock_timeout() {
+        let subdir_path = TempDir::new().unwrap();
+        std::fs::write(subdir_path.path().join("repodata.json"), FAKE_REPO_DATA).unwrap();
+        let server = SimpleChannelServer::new(subdir_path.path()).await;
+
+        struct ContendedReporter(AtomicUsize);
+        impl Reporter for ContendedReporter {
+            fn on_cache_lock_contended(&self, _index: usize) {
+                self.0.fetch_add(1, Ordering::SeqCst);
+            }
+        }
+
+        // Hold the lock on the cache as if another process is fetching the repodata.
+        let cache_dir = TempDir::new().unwrap();
+        let cache_key =
+            crate::utils::url_to_cache_filename(&server.url().join("repodata.json").unwrap());
+        let lock_file_path = cache_dir.path().join(format!("{cache_key}.lock"));
+        let lock = LockedFile::open_rw(&lock_file_path, "test").unwrap();
+
+        let reporter = Arc::new(ContendedReporter(Default::default()));
+        let options = FetchRepoDataOptions {
+            cache_lock_timeout: Some(std::time::Duration::from_millis(100)),
+            ..FetchRepoDataOptions::default()
+        };
+        let result = fetch_repo_data(
+            server.url(),
+            ClientWithMiddleware::from(Client::new()),
+            cache_dir.path().to_owned(),
+            options.clone(),
+            Some(reporter.clone()),
+        )
+        .await;
+        assert_matches!(result, Err(FetchRepoDataError::CacheLockTimedOut(_)));
+        assert_eq!(reporter.0.load(Ordering::SeqCst), 1);
+
+        // Once the lock is released the fetch succeeds.
+        drop(lock);
+        fetch_repo_data(
+            server.url(),
+            ClientWithMiddleware::from(Client::new()),
+            cache_dir.path().to_owned(),
+            options,
+            None,
+        )
+        .await
+        .unwrap();
+    }
+
+    #[tokio::test]
+    pub async fn test_concurrent_fetch_is_reused() {
+        /// Signals when the fetch has to wait for the lock on the cache.
+        struct LockContendedReporter(Arc<tokio::sync::Notify>);
+
+        impl Reporter for LockContendedReporter {
+            fn on_cache_lock_contended(&self, _index: usize) {
+                self.0.notify_one();
+            }
+        }
+
+        // Start a server that only sends the repodata when it is released and counts the
+        // downloads.
+        let downloads = Arc::new(AtomicUsize::new(0));
+        let request_received = Arc::new(tokio::sync::Notify::new());
+        let release = Arc::new(tokio::sync::Notify::new());
+        let app = axum::Router::new().route(
+            "/repodata.json",
+            axum::routing::get({
+                let downloads = downloads.clone();
+                let request_received = request_received.clone();
+                let release = release.clone();
+                move || async move {
+                    downloads.fetch_add(1, Ordering::SeqCst);
+                    request_received.notify_one();
+                    release.notified().await;
+                    FAKE_REPO_DATA
+                }
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = Url::parse(&format!("http://{}/", listener.local_addr().unwrap())).unwrap();
+        tokio::spawn(axum::serve(listener, app).into_future());
+
+        // The repodata is always considered out of date, so without deduplication both fetches
+        // would download it.
+        let cache_dir = TempDir::new().unwrap();
+        let fetch = |reporter: Option<Arc<dyn Reporter>>| {
+            fetch_repo_data(
+                url.clone(),
+                ClientWithMiddleware::from(Client::new()),
+                cache_dir.path().to_owned(),
+                FetchRepoDataOptions {
+                    cache_action: CacheAction::RefreshIfOlderThan(std::time::Duration::ZERO),
+                    compression: CompressionVariant::Plain,
+                    ..FetchRepoDataOptions::default()
+                },
+                reporter,
+            )
+        };
+
+        let lock_contended = Arc::new(tokio::sync::Notify::new());
+        let first = fetch(None);
+        let second = async {
+            // Start fetching while the first fetch holds the lock.
+            request_received.notified().await;
+            let reporter: Arc<dyn Reporter> =
+                Arc::new(LockContendedReporter(lock_contended.clone()));
+            fetch(Some(reporter)).await
+        };
+        let release_first = async {
+            // Only finish the first download once the second fetch waits for the lock.
+            lock_contended.notified().await;
+            release.notify_one();
+        };
+        let (first, second, ()) = tokio::join!(first, second, release_first);
+        assert_eq!(first.unwrap().cache_result, CacheResult::CacheNotPresent);
+        assert_eq!(second.unwrap().cache_result, CacheResult::CacheHit);
+        assert_eq!(downloads.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    pub async fn test_retry_after() {
+        use axum::response::IntoResponse;
+
+        // Start a server that throttles the first request for the repodata.
+        let requests = Arc::new(AtomicUsize::new(0));
+        let requests_clone = requests.clone();
+        let app = axum::Router::new().route(
+            "/repodata.json",
+            axum::routing::get(move || {
+                let requests = requests_clone.clone();
+                async move {
+                    if requests.fetch_add(1, Ordering::SeqCst) == 0 {
+                        (
+                            axum::http::StatusCode::TOO_MANY_REQUESTS,
+                            [(axum::http::header::RETRY_AFTER, "1")],
+                            "slow down",
+                        )
+                            .into_response()
+                    } else {
+                        FAKE_REPO_DATA.into_response()
+                    }
+                }
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = Url::parse(&format!("http://{}/", listener.local_addr().unwrap())).unwrap();
+        tokio::spawn(axum::serve(listener, app).into_future());
+
+        #[derive(Default)]
+        struct ThrottleReporter(std::sync::Mutex<Vec<std::time::Duration>>);
+        impl Reporter for ThrottleReporter {
+            fn on_throttled(&self, _url: &Url, retry_after: std::time::Duration) {
+                self.0.lock().unwrap().push(retry_after);
+            }
+        }
+
+        let reporter = Arc::new(ThrottleReporter::default());
+        let start = std::time::Instant::now();
+        let cache_dir = TempDir::new().unwrap();
+        fetch_repo_data(
+            url.clone(),
+            ClientWithMiddleware::from(Client::new()),
+            cache_dir.path().to_owned(),
+            FetchRepoDataOptions::default(),
+            Some(reporter.clone()),
+        )
+        .await
+        .unwrap();
+        assert!(start.elapsed() >= std::time::Duration::from_secs(1));
+        assert_eq!(requests.load(Ordering::SeqCst), 2);
+        assert_eq!(
+            *reporter.0.lock().unwrap(),
+            [std::time::Duration::from_secs(1)]
+        );
+
+        // The request is not retried if the server asks to wait too long.
+        requests.store(0, Ordering::SeqCst);
+        let cache_dir = TempDir::new().unwrap();
+        let result = fetch_repo_data(
+            url,
+            ClientWithMiddleware::from(Client::new()),
+            cache_dir.path().to_owned(),
+            FetchRepoDataOptions {
+                retry: RetryOptions {
+                    max_retry_after: std::time::Duration::from_millis(100),
+                    ..RetryOptions::default()
+                },
+                ..FetchRepoDataOptions::default()
+            },
+            None,
+        )
+        .await;
+        assert_matches!(result, Err(FetchRepoDataError::HttpError(_)));
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    pub async fn test_validate_repo_data() {
+        // Serve a truncated repodata.json.
+        let subdir_path = TempDir::new().unwrap();
+        std::fs::write(
+            subdir_path.path().join("repodata.json"),
+            &FAKE_REPO_DATA[..FAKE_REPO_DATA.len() / 2],
+        )
+        .unwrap();
+        let server = SimpleChannelServer::new(subdir_path.path()).await;
+
+        let cache_dir = TempDir::new().unwrap();
+        let result = fetch_repo_data(
+            server.url(),
+            ClientWithMiddleware::from(Client::new()),
+            cache_dir.path().to_owned(),
+            FetchRepoDataOptions {
+                validate_repo_data: true,
+                ..FetchRepoDataOptions::default()
+            },
+            None,
+        )
+        .await;
+        assert_matches!(result, Err(FetchRepoDataError::CorruptRepoData(_)));
+
+        // Nothing but the lock file was written to the cache.
+        for entry in std::fs::read_dir(cache_dir.path()).unwrap() {
+            let path = entry.unwrap().path();
+            assert_eq!(path.extension().and_then(|ext| ext.to_str()), Some("lock"));
+        }
     }
 }
@@ -0,0 +1,413 @@
+//! Downloading large files in several parts in parallel with `Range`
+//! requests.
+
+use std::{
+    io,
+    num::NonZeroUsize,
+    ops::Range,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::SystemTime,
+};
+
+use bytes::{Bytes, BytesMut};
+use futures::{Stream, StreamExt, TryStreamExt};
+use rattler_redaction::Redact;
+use reqwest::{
+    header::{self, HeaderValue},
+    Response, StatusCode,
+};
+use reqwest_middleware::ClientWithMiddleware;
+use url::Url;
+
+use super::{resume::is_continuation, timeout, FetchRepoDataOptions};
+use crate::Reporter;
+
+/// Determines when and how a file is downloaded in several parts in parallel.
+#[derive(Debug, Clone)]
+pub struct MultipartDownload {
+    /// The number of parts that are downloaded in parallel.
+    pub parts: NonZeroUsize,
+
+    /// Only files of at least this number of bytes are downloaded in parts.
+    pub min_size: u64,
+}
+
+impl Default for MultipartDownload {
+    fn default() -> Self {
+        Self {
+            parts: NonZeroUsize::new(4).unwrap(),
+            min_size: 8 * 1024 * 1024,
+        }
+    }
+}
+
+/// Describes how a response is downloaded in parts.
+pub(crate) struct Split {
+    /// The total number of bytes of the body.
+    total_size: u64,
+
+    /// The number of bytes of every part except for the last one.
+    part_size: u64,
+
+    /// The `ETag` or `Last-Modified` value of the content.
+    validator: HeaderValue,
+}
+
+/// Determines whether the response can be downloaded in parts as configured by
+/// [`FetchRepoDataOptions::multipart_download`].
+///
+/// This is not the case if the server does not accept range requests, the
+/// size of the body is unknown or below the threshold, the body has a
+/// `Content-Encoding` or the content is not identified by an `ETag` or
+/// `Last-Modified` header, which is sent along as `If-Range` to make sure all
+/// parts belong to the same file. Responses are also not split if the download
+/// rate is limited.
+pub(crate) fn split(response: &Response, options: &FetchRepoDataOptions) -> Option<Split> {
+    let multipart = options.multipart_download.as_ref()?;
+    if options.download_rate_limit.is_some() || multipart.parts.get() < 2 {
+        return None;
+    }
+
+    let headers = response.headers();
+    let accepts_ranges = headers
+        .get(header::ACCEPT_RANGES)
+        .map_or(false, |value| value == "bytes");
+    if response.status() != StatusCode::OK
+        || !accepts_ranges
+        || headers.contains_key(header::CONTENT_ENCODING)
+    {
+        return None;
+    }
+
+    let validator = headers
+        .get(header::ETAG)
+        .or_else(|| headers.get(header::LAST_MODIFIED))?
+        .clone();
+    let total_size = response
+        .content_length()
+        .filter(|&size| size > 0 && size >= multipart.min_size)?;
+    Some(Split {
+        total_size,
+        part_size: total_size.div_ceil(multipart.parts.get() as u64),
+        validator,
+    })
+}
+
+/// Converts a response into a stream of bytes that is downloaded in several
+/// parts in parallel. The first part is read from the response itself, the
+/// other parts are requested with `Range` requests. The parts are kept in
+/// memory until they are yielded in order.
+pub(crate) fn multipart_byte_stream<'a>(
+    client: &'a ClientWithMiddleware,
+    url: &Url,
+    response: Response,
+    split: Split,
+    options: &'a FetchRepoDataOptions,
+    reporter: Option<(&'a dyn Reporter, usize)>,
+) -> impl Stream<Item = io::Result<Bytes>> + 'a {
+    let Split {
+        total_size,
+        part_size,
+        validator,
+    } = split;
+    let parts = total_size.div_ceil(part_size);
+    tracing::debug!(
+        "downloading '{}' in {} parts of {} bytes",
+        url.clone().redact(),
+        parts,
+        part_size
+    );
+
+    let download = Arc::new(MultipartState {
+        client,
+        url: url.clone(),
+        response_url: response.url().clone(),
+        options,
+        reporter,
+        validator,
+        total_size,
+        received: AtomicU64::new(0),
+    });
+    let mut response = Some(response);
+    let downloads = (0..total_size)
+        .step_by(part_size as usize)
+        .map(move |start| {
+            let range = start..(start + part_size).min(total_size);
+            download.clone().download_part(range, response.take())
+        });
+
+    futures::stream::iter(downloads).buffered(parts as usize)
+}
+
+/// The state that is shared between the parts of a download.
+struct MultipartState<'a> {
+    client: &'a ClientWithMiddleware,
+    url: Url,
+    response_url: Url,
+    options: &'a FetchRepoDataOptions,
+    reporter: Option<(&'a dyn Reporter, usize)>,
+
+    /// The `ETag` or `Last-Modified` value of the content.
+    validator: HeaderValue,
+
+    /// The total number of bytes of the body.
+    total_size: u64,
+
+    /// The number of bytes that have been received so far by all parts.
+    received: AtomicU64,
+}
+
+impl MultipartState<'_> {
+    /// Downloads the given range of the file. The bytes are read from
+    /// `response` if given, which must contain the file from the start of the
+    /// range. If the connection is interrupted the remainder of the part is
+    /// requested again as long as the retry options allow another attempt.
+    async fn download_part(
+        self: Arc<Self>,
+        range: Range<u64>,
+        mut response: Option<Response>,
+    ) -> io::Result<Bytes> {
+        let mut part = BytesMut::with_capacity((range.end - range.start) as usize);
+        let request_start = SystemTime::now();
+        let mut past_retries = 0;
+        loop {
+            let position = range.start + part.len() as u64;
+            let response = match response.take() {
+                Some(response) => Ok(response),
+                None => self.request(position..range.end).await,
+            };
+            let result = match response {
+                Ok(response) => self.receive(response, &mut part, &range).await,
+                Err(err) => Err(err),
+            };
+
+            let err = match result {
+                Ok(()) => return Ok(part.freeze()),
+                Err(err) if self.options.retry.is_retryable_error(&err) => err,
+                Err(err) => return Err(err),
+            };
+            let Some(delay) = self.options.retry.next_attempt(request_start, past_retries) else {
+                return Err(err);
+            };
+            past_retries += 1;
+
+            tracing::warn!(
+                "download of bytes {}-{} of '{}' failed: {}. Retry #{}, sleeping {:?} until the next attempt...",
+                position,
+                range.end - 1,
+                self.url.clone().redact(),
+                err,
+                past_retries,
+                delay
+            );
+            tokio::time::sleep(delay).await;
+        }
+    }
+
+    /// Requests the given range of the file.
+    async fn request(&self, range: Range<u64>) -> io::Result<Response> {
+        let response = self
+            .client
+            .get(self.url.clone())
+            .headers(self.options.headers.clone())
+            .header(
+                header::RANGE,
+                format!("bytes={}-{}", range.start, range.end - 1),
+            )
+            .header(header::IF_RANGE, self.validator.clone())
+            .header(header::ACCEPT_ENCODING, "identity")
+            .send()
+            .await
+            .and_then(|response| Ok(response.error_for_status()?))
+            .map_err(|err| io::Error::new(io::ErrorKind::Other, err))?;
+
+        if !is_continuation(&response, range.start) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "the server did not respond with bytes {}-{} of '{}' (status: {})",
+                    range.start,
+                    range.end - 1,
+                    self.url.clone().redact(),
+                    response.status()
+                ),
+            ));
+        }
+        Ok(response)
+    }
+
+    /// Appends the body of the response to `part` until it contains the whole
+    /// `range`.
+    async fn receive(
+        &self,
+        response: Response,
+        part: &mut BytesMut,
+        range: &Range<u64>,
+    ) -> io::Result<()> {
+        let size = (range.end - range.start) as usize;
+        let stream = response.bytes_stream().map_err(|err| {
+            // The connection was closed before the whole body was received.
+            let kind = if err.is_body() {
+                io::ErrorKind::UnexpectedEof
+            } else {
+                io::ErrorKind::Other
+            };
+            io::Error::new(kind, err)
+        });
+        let mut stream = std::pin::pin!(match self.options.read_timeout {
+            Some(read_timeout) => timeout::read_timeout(stream, read_timeout).left_stream(),
+            None => stream.right_stream(),
+        });
+
+        while let Some(bytes) = stream.next().await {
+            let bytes = bytes?;
+            let remaining = size - part.len();
+            let bytes = bytes.slice(..bytes.len().min(remaining));
+            part.extend_from_slice(&bytes);
+
+            let received = self
+                .received
+                .fetch_add(bytes.len() as u64, Ordering::Relaxed)
+                + bytes.len() as u64;
+            if let Some((reporter, index)) = self.reporter {
+                reporter.on_download_progress(
+                    &self.response_url,
+                    index,
+                    received as usize,
+                    Some(self.total_size as usize),
+                );
+            }
+
+            if part.len() == size {
+                return Ok(());
+            }
+        }
+
+        Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            format!(
+                "the connection was closed after {} of {} bytes",
+                part.len(),
+                size
+            ),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        future::IntoFuture,
+        num::NonZeroUsize,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+    };
+
+    use axum::{
+        extract::State,
+        http::{header, HeaderMap, StatusCode},
+        response::IntoResponse,
+        routing::get,
+        Router,
+    };
+    use bytes::Bytes;
+    use futures::TryStreamExt;
+    use reqwest_middleware::ClientWithMiddleware;
+    use url::Url;
+
+    use super::{multipart_byte_stream, split, MultipartDownload};
+    use crate::fetch::FetchRepoDataOptions;
+
+    const FILE_SIZE: usize = 1000;
+
+    /// Serves a file that supports range requests and counts the number of
+    /// range requests.
+    async fn serve_file(
+        State(range_requests): State<Arc<AtomicUsize>>,
+        headers: HeaderMap,
+    ) -> impl IntoResponse {
+        let file = (0..FILE_SIZE).map(|i| i as u8).collect::<Vec<_>>();
+        let Some(range) = headers.get(header::RANGE) else {
+            return (
+                StatusCode::OK,
+                [
+                    (header::ACCEPT_RANGES, "bytes".to_string()),
+                    (header::ETAG, "\"abc\"".to_string()),
+                ],
+                file,
+            );
+        };
+        range_requests.fetch_add(1, Ordering::SeqCst);
+        assert_eq!(headers.get(header::IF_RANGE).unwrap(), "\"abc\"");
+
+        let (start, end) = range
+            .to_str()
+            .unwrap()
+            .strip_prefix("bytes=")
+            .and_then(|range| range.split_once('-'))
+            .unwrap();
+        let (start, end) = (start.parse().unwrap(), end.parse::<usize>().unwrap());
+        (
+            StatusCode::PARTIAL_CONTENT,
+            [
+                (header::ACCEPT_RANGES, "bytes".to_string()),
+                (
+                    header::CONTENT_RANGE,
+                    format!("bytes {start}-{end}/{FILE_SIZE}"),
+                ),
+            ],
+            file[start..=end].to_vec(),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_multipart_download() {
+        let range_requests = Arc::new(AtomicUsize::new(0));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = Url::parse(&format!(
+            "http://{}/repodata.json.zst",
+            listener.local_addr().unwrap()
+        ))
+        .unwrap();
+        let router = Router::new()
+            .route("/repodata.json.zst", get(serve_file))
+            .with_state(range_requests.clone());
+        tokio::spawn(axum::serve(listener, router).into_future());
+
+        let client = ClientWithMiddleware::from(reqwest::Client::new());
+        let options = FetchRepoDataOptions {
+            multipart_download: Some(MultipartDownload {
+                parts: NonZeroUsize::new(3).unwrap(),
+                min_size: 100,
+            }),
+            ..FetchRepoDataOptions::default()
+        };
+
+        let response = client.get(url.clone()).send().await.unwrap();
+        let parts = split(&response, &options).expect("the response should be split");
+        let stream = multipart_byte_stream(&client, &url, response, parts, &options, None);
+        let parts: Vec<Bytes> = stream.try_collect().await.unwrap();
+        assert_eq!(parts.len(), 3);
+        assert_eq!(
+            parts.concat(),
+            (0..FILE_SIZE).map(|i| i as u8).collect::<Vec<_>>()
+        );
+        assert_eq!(range_requests.load(Ordering::SeqCst), 2);
+
+        // Small files are not split.
+        let options = FetchRepoDataOptions {
+            multipart_download: Some(MultipartDownload {
+                min_size: FILE_SIZE as u64 + 1,
+                ..MultipartDownload::default()
+            }),
+            ..FetchRepoDataOptions::default()
+        };
+        let response = client.get(url.clone()).send().await.unwrap();
+        assert!(split(&response, &options).is_none());
+    }
+}
@@ -0,0 +1,41 @@
+//! Fetching of the `patch_instructions.json` of a subdirectory.
+
+use std::{path::PathBuf, sync::Arc};
+
+use rattler_conda_types::PatchInstructions;
+use tracing::{field::Empty, instrument};
+use url::Url;
+
+use super::{fetch_json_file, FetchRepoDataError, FetchRepoDataOptions};
+use crate::Reporter;
+
+/// The name of the file that contains the repodata patches of a subdirectory.
+const PATCH_INSTRUCTIONS_FILE_NAME: &str = "patch_instructions.json";
+
+/// Fetches the `patch_instructions.json` file of the given subdirectory. The
+/// file is cached on disk in the same way as the repodata (see
+/// [`super::fetch_repo_data`]).
+///
+/// Channels that hotfix their repodata publish the patches (removals and
+/// modified dependencies) in this file. The patches can be applied to parsed
+/// repodata with [`rattler_conda_types::RepoData::apply_patches`]. Returns
+/// `None` if the channel does not publish patch instructions for the
+/// subdirectory.
+#[instrument(err, skip_all, fields(url = Empty, path = %cache_path.display()))]
+pub async fn fetch_patch_instructions(
+    subdir_url: Url,
+    client: impl Into<reqwest_middleware::ClientWithMiddleware>,
+    cache_path: PathBuf,
+    options: FetchRepoDataOptions,
+    reporter: Option<Arc<dyn Reporter>>,
+) -> Result<Option<PatchInstructions>, FetchRepoDataError> {
+    fetch_json_file(
+        subdir_url,
+        client.into(),
+        cache_path,
+        PATCH_INSTRUCTIONS_FILE_NAME,
+        options,
+        reporter,
+    )
+    .await
+}
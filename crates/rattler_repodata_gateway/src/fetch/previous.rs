@@ -0,0 +1,126 @@
+//! Keeping the previous version of the cached repodata when it is replaced,
+//! see [`super::FetchRepoDataOptions::keep_previous`].
+
+use std::{
+    io,
+    path::{Path, PathBuf},
+};
+
+use rattler_digest::Blake2b256Hash;
+use tempfile::TempPath;
+
+use super::cache::RepoDataState;
+
+/// Returns the paths at which the previous version of the repodata that is
+/// cached at `repo_data_json_path` is stored uncompressed and compressed.
+pub(crate) fn previous_repo_data_paths(repo_data_json_path: &Path) -> (PathBuf, PathBuf) {
+    let file_name = repo_data_json_path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or_default();
+    let cache_key = file_name
+        .strip_suffix(".json.zst")
+        .or_else(|| file_name.strip_suffix(".json"))
+        .unwrap_or(file_name);
+    (
+        repo_data_json_path.with_file_name(format!("{cache_key}.previous.json")),
+        repo_data_json_path.with_file_name(format!("{cache_key}.previous.json.zst")),
+    )
+}
+
+/// Returns the path of the previous version of the repodata that is cached at
+/// `repo_data_json_path` and whether it is compressed, or `None` if no
+/// previous version was kept.
+#[cfg(feature = "sparse")]
+pub(crate) fn find_previous_repo_data(repo_data_json_path: &Path) -> Option<(PathBuf, bool)> {
+    let (path, compressed_path) = previous_repo_data_paths(repo_data_json_path);
+    if compressed_path.is_file() {
+        Some((compressed_path, true))
+    } else if path.is_file() {
+        Some((path, false))
+    } else {
+        None
+    }
+}
+
+/// A copy of the cached repodata that is taken before the repodata is
+/// replaced. The copy is removed when this is dropped, unless it is committed.
+pub(crate) struct PreviousRepoData {
+    path: TempPath,
+    compressed: bool,
+    blake2_hash: Option<Blake2b256Hash>,
+}
+
+impl PreviousRepoData {
+    /// Hard links (or copies, if that is not possible) the repodata at
+    /// `repo_data_json_path` that is described by `state` to a temporary file
+    /// in `cache_path`. The repodata is always replaced by renaming another
+    /// file over it, so the link keeps the current contents.
+    pub(crate) fn stage(
+        repo_data_json_path: &Path,
+        cache_path: &Path,
+        state: &RepoDataState,
+    ) -> io::Result<Self> {
+        let path = tempfile::Builder::new()
+            .tempfile_in(cache_path)?
+            .into_temp_path();
+        std::fs::remove_file(&path)?;
+        if std::fs::hard_link(repo_data_json_path, &path).is_err() {
+            std::fs::copy(repo_data_json_path, &path)?;
+        }
+        Ok(Self {
+            path,
+            compressed: state.compressed,
+            blake2_hash: state.blake2_hash,
+        })
+    }
+
+    /// Stores the copy next to the repodata at `repo_data_json_path`, replacing
+    /// the previous version that was kept before. Nothing happens if the
+    /// repodata did not change, i.e. if it is still stored the same way and its
+    /// hash is `blake2_hash`.
+    pub(crate) fn commit(
+        self,
+        repo_data_json_path: &Path,
+        compressed: bool,
+        blake2_hash: &Blake2b256Hash,
+    ) -> io::Result<()> {
+        if self.compressed == compressed && self.blake2_hash.as_ref() == Some(blake2_hash) {
+            return Ok(());
+        }
+
+        let (path, compressed_path) = previous_repo_data_paths(repo_data_json_path);
+        let (destination, outdated) = if self.compressed {
+            (compressed_path, path)
+        } else {
+            (path, compressed_path)
+        };
+        self.path.persist(&destination).map_err(|err| err.error)?;
+
+        // Remove the previous version that was stored the other way, if any.
+        match std::fs::remove_file(outdated) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::Path;
+
+    use super::previous_repo_data_paths;
+
+    #[test]
+    fn test_previous_repo_data_paths() {
+        for path in ["/cache/abc.json", "/cache/abc.json.zst"] {
+            assert_eq!(
+                previous_repo_data_paths(Path::new(path)),
+                (
+                    Path::new("/cache/abc.previous.json").to_path_buf(),
+                    Path::new("/cache/abc.previous.json.zst").to_path_buf()
+                )
+            );
+        }
+    }
+}
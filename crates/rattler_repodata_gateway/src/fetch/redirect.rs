@@ -0,0 +1,435 @@
+//! Explicit handling of redirects when fetching repodata.
+//!
+//! A private channel may redirect to a CDN or to a signed url on another host.
+//! The credentials that were sent to the channel must not be forwarded to such
+//! a host unless the [`RedirectPolicy`] allows it.
+
+use async_trait::async_trait;
+use rattler_error::ErrorCode;
+use rattler_redaction::Redact;
+use reqwest::{
+    header::{
+        HeaderMap, HeaderName, AUTHORIZATION, CONTENT_LENGTH, CONTENT_TYPE, COOKIE, LOCATION,
+        PROXY_AUTHORIZATION,
+    },
+    Method, Request, Response, StatusCode,
+};
+use reqwest_middleware::{ClientWithMiddleware, Middleware, Next};
+use url::Url;
+
+/// What happens to the credentials of a request that is redirected to another
+/// origin (a different scheme, host or port).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CrossOriginCredentials {
+    /// Remove the credentials from the request and follow the redirect.
+    #[default]
+    Strip,
+
+    /// Forward the credentials to the new origin.
+    Preserve,
+
+    /// Do not follow a redirect that would forward credentials, the request
+    /// fails with [`RedirectError::CrossOrigin`] instead. Requests without
+    /// credentials are still redirected.
+    Reject,
+}
+
+/// Determines how redirects are followed when fetching repodata.
+///
+/// The client that is used to fetch the repodata must not follow redirects
+/// itself, i.e. it must be built with [`reqwest::redirect::Policy::none`].
+/// Otherwise requests that are redirected fail with
+/// [`RedirectError::ClientFollowsRedirects`].
+#[derive(Debug, Clone)]
+pub struct RedirectPolicy {
+    /// The maximum number of redirects that are followed for a single request.
+    pub max_redirects: usize,
+
+    /// What happens to the credentials when a request is redirected to another
+    /// origin.
+    pub cross_origin: CrossOriginCredentials,
+
+    /// Hosts that credentials are always forwarded to, e.g. a mirror that
+    /// accepts the same credentials. An entry also matches its subdomains.
+    pub trusted_hosts: Vec<String>,
+
+    /// Headers that contain credentials in addition to `Authorization`,
+    /// `Cookie`, `Proxy-Authorization` and the headers in
+    /// [`super::FetchRepoDataOptions::headers`].
+    pub sensitive_headers: Vec<HeaderName>,
+}
+
+impl Default for RedirectPolicy {
+    fn default() -> Self {
+        Self {
+            max_redirects: 10,
+            cross_origin: CrossOriginCredentials::default(),
+            trusted_hosts: Vec::new(),
+            sensitive_headers: Vec::new(),
+        }
+    }
+}
+
+impl RedirectPolicy {
+    /// Returns true if credentials may be forwarded to the given url.
+    fn is_trusted(&self, url: &Url) -> bool {
+        let Some(host) = url.host_str() else {
+            return false;
+        };
+        self.trusted_hosts.iter().any(|trusted| {
+            let trusted = trusted.trim_start_matches('.');
+            host.eq_ignore_ascii_case(trusted)
+                || host
+                    .to_ascii_lowercase()
+                    .ends_with(&format!(".{}", trusted.to_ascii_lowercase()))
+        })
+    }
+}
+
+/// An error that occurred while following a redirect.
+#[derive(Debug, thiserror::Error)]
+pub enum RedirectError {
+    /// The request was redirected more often than allowed.
+    #[error("the request was redirected more than {0} times")]
+    TooManyRedirects(usize),
+
+    /// The request was redirected to another origin and the policy does not
+    /// allow forwarding the credentials.
+    #[error("refusing to forward credentials in a redirect from '{from}' to '{to}'")]
+    CrossOrigin {
+        /// The url of the request that was redirected.
+        from: Url,
+
+        /// The url the request was redirected to.
+        to: Url,
+    },
+
+    /// The client followed a redirect itself, so the policy could not be
+    /// applied.
+    #[error(
+        "the request to '{from}' was redirected to '{to}' by the client, a redirect policy \
+         requires a client that does not follow redirects"
+    )]
+    ClientFollowsRedirects {
+        /// The url of the request.
+        from: Url,
+
+        /// The url of the response.
+        to: Url,
+    },
+}
+
+impl ErrorCode for RedirectError {
+    fn code(&self) -> &'static str {
+        match self {
+            RedirectError::TooManyRedirects(_) => "rattler::redirect::too_many_redirects",
+            RedirectError::CrossOrigin { .. } => "rattler::redirect::cross_origin",
+            RedirectError::ClientFollowsRedirects { .. } => {
+                "rattler::redirect::client_follows_redirects"
+            }
+        }
+    }
+}
+
+/// A middleware that follows redirects according to a [`RedirectPolicy`].
+pub(crate) struct RedirectMiddleware {
+    policy: RedirectPolicy,
+    sensitive_headers: Vec<HeaderName>,
+}
+
+impl RedirectMiddleware {
+    /// Constructs the middleware. The names of the `headers` are treated as
+    /// credentials as well.
+    pub(crate) fn new(policy: RedirectPolicy, headers: &HeaderMap) -> Self {
+        let mut sensitive_headers = vec![AUTHORIZATION, COOKIE, PROXY_AUTHORIZATION];
+        sensitive_headers.extend(policy.sensitive_headers.iter().cloned());
+        sensitive_headers.extend(headers.keys().cloned());
+        Self {
+            policy,
+            sensitive_headers,
+        }
+    }
+
+    /// Turns `req` into the request that follows the redirect to `location`.
+    fn redirect(
+        &self,
+        mut req: Request,
+        status: StatusCode,
+        location: Url,
+    ) -> reqwest_middleware::Result<Request> {
+        if location.origin() != req.url().origin() && !self.policy.is_trusted(&location) {
+            match self.policy.cross_origin {
+                CrossOriginCredentials::Preserve => {}
+                CrossOriginCredentials::Strip => {
+                    for name in &self.sensitive_headers {
+                        req.headers_mut().remove(name);
+                    }
+                }
+                CrossOriginCredentials::Reject => {
+                    if self
+                        .sensitive_headers
+                        .iter()
+                        .any(|name| req.headers().contains_key(name))
+                    {
+                        return Err(reqwest_middleware::Error::middleware(
+                            RedirectError::CrossOrigin {
+                                from: req.url().clone().redact(),
+                                to: location.redact(),
+                            },
+                        ));
+                    }
+                }
+            }
+        }
+
+        // Like browsers, a `303 See Other` and a redirected `POST` are
+        // followed with a `GET` without a body.
+        let change_method = (status == StatusCode::SEE_OTHER && req.method() != Method::HEAD)
+            || (matches!(status, StatusCode::MOVED_PERMANENTLY | StatusCode::FOUND)
+                && req.method() == Method::POST);
+        if change_method {
+            *req.method_mut() = Method::GET;
+            *req.body_mut() = None;
+            req.headers_mut().remove(CONTENT_TYPE);
+            req.headers_mut().remove(CONTENT_LENGTH);
+        }
+
+        *req.url_mut() = location;
+        Ok(req)
+    }
+}
+
+/// Returns the url the response redirects to, if it is a redirect that can be
+/// followed.
+fn redirect_location(response: &Response) -> Option<Url> {
+    if !matches!(
+        response.status(),
+        StatusCode::MOVED_PERMANENTLY
+            | StatusCode::FOUND
+            | StatusCode::SEE_OTHER
+            | StatusCode::TEMPORARY_REDIRECT
+            | StatusCode::PERMANENT_REDIRECT
+    ) {
+        return None;
+    }
+    let location = response.headers().get(LOCATION)?.to_str().ok()?;
+    let location = response.url().join(location).ok()?;
+    matches!(location.scheme(), "http" | "https").then_some(location)
+}
+
+#[async_trait]
+impl Middleware for RedirectMiddleware {
+    async fn handle(
+        &self,
+        mut req: Request,
+        extensions: &mut http::Extensions,
+        next: Next<'_>,
+    ) -> reqwest_middleware::Result<Response> {
+        let mut redirects = 0;
+        loop {
+            // Requests with a streaming body cannot be cloned, the redirect is
+            // returned to the caller instead.
+            let next_req = req.try_clone();
+            let url = req.url().clone();
+            let response = next.clone().run(req, extensions).await?;
+            if response.url() != &url {
+                return Err(reqwest_middleware::Error::middleware(
+                    RedirectError::ClientFollowsRedirects {
+                        from: url.redact(),
+                        to: response.url().clone().redact(),
+                    },
+                ));
+            }
+            let (Some(location), Some(next_req)) = (redirect_location(&response), next_req) else {
+                return Ok(response);
+            };
+
+            if redirects == self.policy.max_redirects {
+                return Err(reqwest_middleware::Error::middleware(
+                    RedirectError::TooManyRedirects(self.policy.max_redirects),
+                ));
+            }
+            redirects += 1;
+
+            tracing::debug!(
+                "following redirect from '{}' to '{}'",
+                next_req.url().clone().redact(),
+                location.clone().redact()
+            );
+            req = self.redirect(next_req, response.status(), location)?;
+        }
+    }
+}
+
+/// Adds the [`RedirectMiddleware`] to the client if a policy is given.
+pub(crate) fn with_redirect_policy(
+    client: ClientWithMiddleware,
+    policy: Option<&RedirectPolicy>,
+    headers: &HeaderMap,
+) -> ClientWithMiddleware {
+    match policy {
+        Some(policy) => reqwest_middleware::ClientBuilder::from_client(client)
+            .with(RedirectMiddleware::new(policy.clone(), headers))
+            .build(),
+        None => client,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{collections::HashMap, future::IntoFuture};
+
+    use assert_matches::assert_matches;
+    use axum::{
+        extract::Query,
+        http::{header::LOCATION, HeaderMap, StatusCode},
+        response::IntoResponse,
+        routing::get,
+        Router,
+    };
+    use reqwest::header::{HeaderValue, AUTHORIZATION};
+    use reqwest_middleware::ClientWithMiddleware;
+
+    use super::{with_redirect_policy, CrossOriginCredentials, RedirectError, RedirectPolicy};
+
+    async fn serve(app: Router) -> String {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(axum::serve(listener, app).into_future());
+        format!("http://{addr}")
+    }
+
+    /// Returns the credentials that were received by the server.
+    async fn echo_credentials(headers: HeaderMap) -> String {
+        ["authorization", "x-api-key"]
+            .iter()
+            .map(|name| {
+                headers
+                    .get(*name)
+                    .and_then(|value| value.to_str().ok())
+                    .unwrap_or("-")
+            })
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    async fn redirect(Query(mut query): Query<HashMap<String, String>>) -> impl IntoResponse {
+        let to = query
+            .remove("to")
+            .unwrap_or_else(|| "/redirect".to_string());
+        (StatusCode::FOUND, [(LOCATION, to)])
+    }
+
+    #[tokio::test]
+    async fn test_cross_origin_redirect() {
+        let channel = serve(
+            Router::new()
+                .route("/redirect", get(redirect))
+                .route("/repodata.json", get(echo_credentials)),
+        )
+        .await;
+        let cdn = serve(Router::new().route("/repodata.json", get(echo_credentials))).await;
+
+        let mut headers = reqwest::header::HeaderMap::new();
+        headers.insert("x-api-key", HeaderValue::from_static("key"));
+        let fetch = |policy: RedirectPolicy, to: String| {
+            let headers = headers.clone();
+            let url = format!("{channel}/redirect");
+            async move {
+                let client = reqwest::Client::builder()
+                    .redirect(reqwest::redirect::Policy::none())
+                    .build()
+                    .unwrap();
+                let client = with_redirect_policy(
+                    ClientWithMiddleware::from(client),
+                    Some(&policy),
+                    &headers,
+                );
+                let response = client
+                    .get(url)
+                    .query(&[("to", to)])
+                    .headers(headers)
+                    .header(AUTHORIZATION, "Bearer secret")
+                    .send()
+                    .await?;
+                Ok::<_, reqwest_middleware::Error>(response.text().await.unwrap())
+            }
+        };
+
+        // Credentials are kept when redirecting to the same origin.
+        let body = fetch(
+            RedirectPolicy::default(),
+            format!("{channel}/repodata.json"),
+        )
+        .await
+        .unwrap();
+        assert_eq!(body, "Bearer secret key");
+
+        // By default they are stripped when redirecting to another origin.
+        let body = fetch(RedirectPolicy::default(), format!("{cdn}/repodata.json"))
+            .await
+            .unwrap();
+        assert_eq!(body, "- -");
+
+        let policy = RedirectPolicy {
+            cross_origin: CrossOriginCredentials::Preserve,
+            ..RedirectPolicy::default()
+        };
+        let body = fetch(policy, format!("{cdn}/repodata.json")).await.unwrap();
+        assert_eq!(body, "Bearer secret key");
+
+        let policy = RedirectPolicy {
+            cross_origin: CrossOriginCredentials::Reject,
+            ..RedirectPolicy::default()
+        };
+        let err = fetch(policy, format!("{cdn}/repodata.json"))
+            .await
+            .unwrap_err();
+        assert_matches!(
+            err,
+            reqwest_middleware::Error::Middleware(err)
+                if matches!(err.downcast_ref(), Some(RedirectError::CrossOrigin { .. }))
+        );
+
+        // Trusted hosts receive the credentials.
+        let policy = RedirectPolicy {
+            cross_origin: CrossOriginCredentials::Reject,
+            trusted_hosts: vec!["127.0.0.1".to_string()],
+            ..RedirectPolicy::default()
+        };
+        let body = fetch(policy, format!("{cdn}/repodata.json")).await.unwrap();
+        assert_eq!(body, "Bearer secret key");
+
+        // A redirect loop is aborted.
+        let policy = RedirectPolicy {
+            max_redirects: 3,
+            ..RedirectPolicy::default()
+        };
+        let err = fetch(policy, format!("{channel}/redirect"))
+            .await
+            .unwrap_err();
+        assert_matches!(
+            err,
+            reqwest_middleware::Error::Middleware(err)
+                if matches!(err.downcast_ref(), Some(RedirectError::TooManyRedirects(3)))
+        );
+
+        // The policy cannot be applied if the client follows redirects itself.
+        let client = with_redirect_policy(
+            ClientWithMiddleware::from(reqwest::Client::new()),
+            Some(&RedirectPolicy::default()),
+            &headers,
+        );
+        let err = client
+            .get(format!("{channel}/redirect"))
+            .query(&[("to", format!("{cdn}/repodata.json"))])
+            .send()
+            .await
+            .unwrap_err();
+        assert_matches!(
+            err,
+            reqwest_middleware::Error::Middleware(err)
+                if matches!(err.downcast_ref(), Some(RedirectError::ClientFollowsRedirects { .. }))
+        );
+    }
+}
@@ -0,0 +1,379 @@
+//! Resuming of interrupted downloads with `Range` requests.
+
+use std::{io, time::SystemTime};
+
+use bytes::Bytes;
+use futures::{stream::BoxStream, Stream, StreamExt};
+use rattler_redaction::Redact;
+use reqwest::{
+    header::{self, HeaderMap, HeaderValue},
+    Response, StatusCode,
+};
+use reqwest_middleware::ClientWithMiddleware;
+use url::Url;
+
+use super::RetryOptions;
+use crate::Reporter;
+
+/// Converts a response into a stream of bytes. If the connection is
+/// interrupted while the body is streamed, the download is resumed from the
+/// last received byte with a `Range` request instead of starting over, as long
+/// as the [`RetryOptions`] allow another attempt.
+///
+/// A download can only be resumed if the server identified the content with
+/// an `ETag` or `Last-Modified` header. The value is sent along as `If-Range`
+/// to make sure the remaining bytes belong to the same file. Responses with a
+/// `Content-Encoding` are never resumed because the encoded bytes are not
+/// guaranteed to be identical between requests.
+///
+/// The `headers` are sent along with every range request.
+pub(crate) fn resumable_byte_stream<'a>(
+    client: &'a ClientWithMiddleware,
+    url: Url,
+    response: Response,
+    headers: &'a HeaderMap,
+    retry: &'a RetryOptions,
+    reporter: Option<(&'a dyn Reporter, usize)>,
+) -> impl Stream<Item = io::Result<Bytes>> + 'a {
+    let validator = if response.headers().contains_key(header::CONTENT_ENCODING) {
+        None
+    } else {
+        response
+            .headers()
+            .get(header::ETAG)
+            .or_else(|| response.headers().get(header::LAST_MODIFIED))
+            .cloned()
+    };
+
+    ResumableDownload {
+        client,
+        url,
+        response_url: response.url().clone(),
+        headers,
+        retry,
+        reporter,
+        validator,
+        total_size: response.content_length(),
+        position: 0,
+        request_start: SystemTime::now(),
+        past_retries: 0,
+        stream: Some(response.bytes_stream().boxed()),
+    }
+    .into_stream()
+}
+
+/// The state of a download that can be resumed.
+struct ResumableDownload<'a> {
+    client: &'a ClientWithMiddleware,
+    url: Url,
+    response_url: Url,
+    headers: &'a HeaderMap,
+    retry: &'a RetryOptions,
+    reporter: Option<(&'a dyn Reporter, usize)>,
+
+    /// The `ETag` or `Last-Modified` value of the content, or `None` if the
+    /// download cannot be resumed.
+    validator: Option<HeaderValue>,
+
+    /// The total number of bytes of the body as reported by the server.
+    total_size: Option<u64>,
+
+    /// The number of bytes that have been received so far.
+    position: u64,
+
+    request_start: SystemTime,
+    past_retries: u32,
+    stream: Option<BoxStream<'static, reqwest::Result<Bytes>>>,
+}
+
+impl<'a> ResumableDownload<'a> {
+    /// Converts the download into a stream of the bytes of the body.
+    ///
+    /// The body is considered incomplete if the stream ends before the number
+    /// of bytes that was advertised with the `Content-Length` header has been
+    /// received, in which case the download is resumed like after an error.
+    /// Receiving more bytes than advertised is an error as well, the cached
+    /// repodata must never be a truncated or corrupt copy.
+    fn into_stream(self) -> impl Stream<Item = io::Result<Bytes>> + 'a {
+        futures::stream::unfold(self, |mut state| async move {
+            let mut stream = state.stream.take()?;
+            loop {
+                match stream.next().await {
+                    None => {
+                        let total_size = state.total_size?;
+                        if state.position > total_size {
+                            let err = io::Error::new(
+                                io::ErrorKind::InvalidData,
+                                format!(
+                                    "received {} bytes but the server advertised only {} bytes",
+                                    state.position, total_size
+                                ),
+                            );
+                            return Some((Err(err), state));
+                        } else if state.position == total_size {
+                            return None;
+                        }
+
+                        let err = io::Error::new(
+                            io::ErrorKind::UnexpectedEof,
+                            format!(
+                                "the body ended after {} of {} bytes",
+                                state.position, total_size
+                            ),
+                        );
+                        match state.resume(&err).await {
+                            Some(remainder) => stream = remainder,
+                            None => return Some((Err(err), state)),
+                        }
+                    }
+                    Some(Ok(bytes)) => {
+                        state.position += bytes.len() as u64;
+                        if let Some((reporter, index)) = state.reporter {
+                            reporter.on_download_progress(
+                                &state.response_url,
+                                index,
+                                state.position as usize,
+                                state.total_size.map(|size| size as usize),
+                            );
+                        }
+                        state.stream = Some(stream);
+                        return Some((Ok(bytes), state));
+                    }
+                    Some(Err(err)) if err.is_body() || state.retry.is_retryable_error(&err) => {
+                        match state.resume(&err).await {
+                            Some(remainder) => stream = remainder,
+                            None => {
+                                return Some((
+                                    Err(io::Error::new(io::ErrorKind::Other, err)),
+                                    state,
+                                ))
+                            }
+                        }
+                    }
+                    Some(Err(err)) => {
+                        return Some((Err(io::Error::new(io::ErrorKind::Other, err)), state))
+                    }
+                }
+            }
+        })
+    }
+
+    /// Tries to resume the download after the body stream was interrupted by
+    /// `err`. Returns a stream of the remaining bytes or `None` if the download
+    /// cannot be resumed.
+    async fn resume(
+        &mut self,
+        err: &dyn std::fmt::Display,
+    ) -> Option<BoxStream<'static, reqwest::Result<Bytes>>> {
+        let validator = self.validator.as_ref()?;
+
+        loop {
+            let delay = self
+                .retry
+                .next_attempt(self.request_start, self.past_retries)?;
+            self.past_retries += 1;
+
+            tracing::warn!(
+                "download of '{}' was interrupted after {} bytes: {}. Resuming in {:?}...",
+                self.url.clone().redact(),
+                self.position,
+                err,
+                delay
+            );
+            tokio::time::sleep(delay).await;
+
+            let response = self
+                .client
+                .get(self.url.clone())
+                .headers(self.headers.clone())
+                .header(header::RANGE, format!("bytes={}-", self.position))
+                .header(header::IF_RANGE, validator.clone())
+                .header(header::ACCEPT_ENCODING, "identity")
+                .send()
+                .await;
+
+            match response {
+                Ok(response) if is_continuation(&response, self.position) => {
+                    return Some(response.bytes_stream().boxed());
+                }
+                Ok(response) if self.retry.is_retryable_status(response.status()) => continue,
+                Ok(response) => {
+                    tracing::debug!(
+                        "cannot resume download of '{}', the server responded with {}",
+                        self.url.clone().redact(),
+                        response.status()
+                    );
+                    return None;
+                }
+                Err(err) if self.retry.is_retryable_error(&err) => continue,
+                Err(_) => return None,
+            }
+        }
+    }
+}
+
+/// Returns true if `response` contains the bytes of the requested file
+/// starting at `position`.
+pub(super) fn is_continuation(response: &Response, position: u64) -> bool {
+    response.status() == StatusCode::PARTIAL_CONTENT
+        && response
+            .headers()
+            .get(header::CONTENT_RANGE)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|range| range.strip_prefix("bytes "))
+            .and_then(|range| range.split_once('-'))
+            .and_then(|(start, _)| start.trim().parse::<u64>().ok())
+            == Some(position)
+}
+
+#[cfg(test)]
+mod test {
+    use std::{sync::Arc, time::Duration};
+
+    use futures::TryStreamExt;
+    use rattler_networking::retry_policies::ExponentialBackoff;
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::{TcpListener, TcpStream},
+    };
+
+    use super::*;
+
+    /// Reads the head of an HTTP request from the socket.
+    async fn read_request(socket: &mut TcpStream) -> String {
+        let mut request = Vec::new();
+        let mut buf = [0; 1024];
+        while !request.ends_with(b"\r\n\r\n") {
+            let n = socket.read(&mut buf).await.unwrap();
+            assert_ne!(n, 0, "connection closed before the request was read");
+            request.extend_from_slice(&buf[..n]);
+        }
+        String::from_utf8(request).unwrap().to_lowercase()
+    }
+
+    #[tokio::test]
+    async fn test_resume_interrupted_download() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = Url::parse(&format!(
+            "http://{}/repodata.json.zst",
+            listener.local_addr().unwrap()
+        ))
+        .unwrap();
+
+        let server = tokio::spawn(async move {
+            // The connection is closed after the first 5 bytes of the body.
+            let (mut socket, _) = listener.accept().await.unwrap();
+            read_request(&mut socket).await;
+            socket
+                .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 11\r\nETag: \"abc\"\r\n\r\nhello")
+                .await
+                .unwrap();
+            drop(socket);
+
+            // The remaining bytes are requested with a range request.
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let request = read_request(&mut socket).await;
+            assert!(request.contains("range: bytes=5-\r\n"));
+            assert!(request.contains("if-range: \"abc\"\r\n"));
+            assert!(request.contains("x-api-key: secret\r\n"));
+            socket
+                .write_all(
+                    b"HTTP/1.1 206 Partial Content\r\nContent-Length: 6\r\nContent-Range: bytes 5-10/11\r\n\r\n world",
+                )
+                .await
+                .unwrap();
+        });
+
+        let client = ClientWithMiddleware::from(reqwest::Client::new());
+        let retry = RetryOptions {
+            policy: Arc::new(
+                ExponentialBackoff::builder()
+                    .retry_bounds(Duration::from_millis(1), Duration::from_millis(1))
+                    .build_with_max_retries(3),
+            ),
+            ..RetryOptions::default()
+        };
+        let mut headers = HeaderMap::new();
+        headers.insert("x-api-key", HeaderValue::from_static("secret"));
+        let response = client
+            .get(url.clone())
+            .headers(headers.clone())
+            .send()
+            .await
+            .unwrap();
+        let body: Vec<Bytes> =
+            resumable_byte_stream(&client, url, response, &headers, &retry, None)
+                .try_collect()
+                .await
+                .unwrap();
+
+        assert_eq!(body.concat(), b"hello world");
+        server.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_resume_truncated_body() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = Url::parse(&format!(
+            "http://{}/repodata.json",
+            listener.local_addr().unwrap()
+        ))
+        .unwrap();
+
+        let server = tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let request = read_request(&mut socket).await;
+            assert!(request.contains("range: bytes=5-\r\n"));
+            socket
+                .write_all(
+                    b"HTTP/1.1 206 Partial Content\r\nContent-Length: 6\r\nContent-Range: bytes 5-10/11\r\n\r\n world",
+                )
+                .await
+                .unwrap();
+        });
+
+        let client = ClientWithMiddleware::from(reqwest::Client::new());
+        let retry = RetryOptions {
+            policy: Arc::new(
+                ExponentialBackoff::builder()
+                    .retry_bounds(Duration::from_millis(1), Duration::from_millis(1))
+                    .build_with_max_retries(3),
+            ),
+            ..RetryOptions::default()
+        };
+        let headers = HeaderMap::new();
+
+        // A body that ends cleanly before the advertised number of bytes.
+        let download = |validator: Option<&'static str>| ResumableDownload {
+            client: &client,
+            url: url.clone(),
+            response_url: url.clone(),
+            headers: &headers,
+            retry: &retry,
+            reporter: None,
+            validator: validator.map(HeaderValue::from_static),
+            total_size: Some(11),
+            position: 0,
+            request_start: SystemTime::now(),
+            past_retries: 0,
+            stream: Some(futures::stream::iter([Ok(Bytes::from_static(b"hello"))]).boxed()),
+        };
+
+        // The remaining bytes are requested if possible.
+        let body: Vec<Bytes> = download(Some("\"abc\""))
+            .into_stream()
+            .try_collect()
+            .await
+            .unwrap();
+        assert_eq!(body.concat(), b"hello world");
+        server.await.unwrap();
+
+        // Otherwise the download fails instead of returning a truncated body.
+        let err = download(None)
+            .into_stream()
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+    }
+}
@@ -0,0 +1,252 @@
+//! Retrying of requests that failed because of transient network errors.
+
+use std::{
+    error::Error,
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+
+use rattler_networking::retry_policies::{default_retry_policy, RetryDecision, RetryPolicy};
+use rattler_redaction::Redact;
+use reqwest::{header::RETRY_AFTER, Response, StatusCode};
+use reqwest_middleware::RequestBuilder;
+use url::Url;
+
+use crate::Reporter;
+
+/// Determines which failed requests are retried and how often.
+///
+/// The retry options are applied to the requests that check which variants of
+/// a `repodata.json` are available and to the request that downloads the
+/// `repodata.json` itself.
+#[derive(Clone)]
+pub struct RetryOptions {
+    /// Determines how often a request is retried and how long to wait between
+    /// the attempts. Use
+    /// [`DoNotRetryPolicy`](rattler_networking::retry_policies::DoNotRetryPolicy)
+    /// to disable retries.
+    pub policy: Arc<dyn RetryPolicy + Send + Sync>,
+
+    /// The HTTP status codes of responses that are considered transient.
+    pub retryable_status_codes: Vec<StatusCode>,
+
+    /// The kinds of IO errors that are considered transient. Timeouts and
+    /// failures to connect to the server are always considered transient.
+    pub retryable_io_errors: Vec<std::io::ErrorKind>,
+
+    /// The longest duration to wait before retrying a request when the server
+    /// responds with `429 Too Many Requests` or `503 Service Unavailable` and
+    /// asks to retry later with a `Retry-After` header. If the server asks to
+    /// wait longer the request is not retried. The number of retries is still
+    /// limited by the [`RetryOptions::policy`].
+    pub max_retry_after: Duration,
+}
+
+impl Default for RetryOptions {
+    fn default() -> Self {
+        Self {
+            policy: Arc::new(default_retry_policy()),
+            retryable_status_codes: vec![
+                StatusCode::REQUEST_TIMEOUT,
+                StatusCode::TOO_MANY_REQUESTS,
+                StatusCode::INTERNAL_SERVER_ERROR,
+                StatusCode::BAD_GATEWAY,
+                StatusCode::SERVICE_UNAVAILABLE,
+                StatusCode::GATEWAY_TIMEOUT,
+            ],
+            retryable_io_errors: vec![
+                std::io::ErrorKind::ConnectionReset,
+                std::io::ErrorKind::ConnectionAborted,
+                std::io::ErrorKind::BrokenPipe,
+                std::io::ErrorKind::TimedOut,
+                std::io::ErrorKind::UnexpectedEof,
+                std::io::ErrorKind::Interrupted,
+            ],
+            max_retry_after: Duration::from_secs(60),
+        }
+    }
+}
+
+impl RetryOptions {
+    /// Returns true if a response with the given status should be retried.
+    pub fn is_retryable_status(&self, status: StatusCode) -> bool {
+        self.retryable_status_codes.contains(&status)
+    }
+
+    /// Returns true if a request that failed with the given error should be
+    /// retried. The error is retryable if it or any of its sources is a
+    /// timeout, a failure to connect, a response with a retryable status or
+    /// an IO error of a retryable kind.
+    pub fn is_retryable_error(&self, error: &(dyn Error + 'static)) -> bool {
+        // These errors forward `source` to the error they wrap, which would skip
+        // the wrapped error itself.
+        if let Some(err) = error.downcast_ref::<reqwest_middleware::Error>() {
+            return match err {
+                reqwest_middleware::Error::Reqwest(err) => self.is_retryable_error(err),
+                reqwest_middleware::Error::Middleware(err) => self.is_retryable_error(err.as_ref()),
+            };
+        }
+
+        let retryable = if let Some(err) = error.downcast_ref::<reqwest::Error>() {
+            err.is_timeout()
+                || err.is_connect()
+                || err
+                    .status()
+                    .map_or(false, |status| self.is_retryable_status(status))
+        } else if let Some(err) = error.downcast_ref::<std::io::Error>() {
+            self.retryable_io_errors.contains(&err.kind())
+                || err
+                    .get_ref()
+                    .map_or(false, |inner| self.is_retryable_error(inner))
+        } else {
+            false
+        };
+
+        retryable
+            || error
+                .source()
+                .map_or(false, |source| self.is_retryable_error(source))
+    }
+
+    /// Returns when the next attempt should be made after `past_retries`
+    /// retries of a request that was first sent at `request_start`, or `None`
+    /// if the request should not be retried anymore.
+    pub(crate) fn next_attempt(
+        &self,
+        request_start: SystemTime,
+        past_retries: u32,
+    ) -> Option<Duration> {
+        match self.policy.should_retry(request_start, past_retries) {
+            RetryDecision::Retry { execute_after } => Some(
+                execute_after
+                    .duration_since(SystemTime::now())
+                    .unwrap_or(Duration::ZERO),
+            ),
+            RetryDecision::DoNotRetry => None,
+        }
+    }
+}
+
+/// Returns how long the server asks us to wait before retrying if the response
+/// is a `429 Too Many Requests` or `503 Service Unavailable` response with a
+/// `Retry-After` header. The header either contains a number of seconds or a
+/// date.
+fn retry_after(response: &Response) -> Option<Duration> {
+    if !matches!(
+        response.status(),
+        StatusCode::TOO_MANY_REQUESTS | StatusCode::SERVICE_UNAVAILABLE
+    ) {
+        return None;
+    }
+    parse_retry_after(response.headers().get(RETRY_AFTER)?.to_str().ok()?)
+}
+
+fn parse_retry_after(value: &str) -> Option<Duration> {
+    let value = value.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let date = chrono::DateTime::parse_from_rfc2822(value).ok()?;
+    Some(
+        (date.with_timezone(&chrono::Utc) - chrono::Utc::now())
+            .to_std()
+            .unwrap_or(Duration::ZERO),
+    )
+}
+
+/// Sends the request constructed by `request` and retries it as long as it
+/// fails with a transient error and the retry policy allows it.
+///
+/// If the server asks to retry later with a `Retry-After` header, the next
+/// attempt is delayed accordingly and the `reporter` is notified.
+pub(crate) async fn send_with_retry(
+    options: &RetryOptions,
+    url: &Url,
+    reporter: Option<&dyn Reporter>,
+    request: impl Fn() -> RequestBuilder,
+) -> reqwest_middleware::Result<Response> {
+    let request_start = SystemTime::now();
+    let mut past_retries = 0;
+    loop {
+        let result = request().send().await;
+        let retryable = match &result {
+            Ok(response) => options.is_retryable_status(response.status()),
+            Err(err) => options.is_retryable_error(err),
+        };
+        if !retryable {
+            return result;
+        }
+
+        let Some(mut duration) = options.next_attempt(request_start, past_retries) else {
+            return result;
+        };
+        if let Some(retry_after) = result.as_ref().ok().and_then(retry_after) {
+            if retry_after > options.max_retry_after {
+                tracing::warn!(
+                    "request to '{}' was throttled and the server asked to retry after {:?}, which is longer than the maximum of {:?}",
+                    url.clone().redact(),
+                    retry_after,
+                    options.max_retry_after
+                );
+                return result;
+            }
+            duration = duration.max(retry_after);
+            if let Some(reporter) = reporter {
+                reporter.on_throttled(url, duration);
+            }
+        }
+        past_retries += 1;
+
+        match &result {
+            Ok(response) => tracing::warn!(
+                "request to '{}' failed with status {}. Retry #{}, sleeping {:?} until the next attempt...",
+                url.clone().redact(),
+                response.status(),
+                past_retries,
+                duration
+            ),
+            Err(err) => tracing::warn!(
+                "request to '{}' failed: {}. Retry #{}, sleeping {:?} until the next attempt...",
+                url.clone().redact(),
+                err,
+                past_retries,
+                duration
+            ),
+        }
+        tokio::time::sleep(duration).await;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_retryable_io_error() {
+        let options = RetryOptions::default();
+        let reset = std::io::Error::new(std::io::ErrorKind::ConnectionReset, "reset");
+        assert!(options.is_retryable_error(&reset));
+
+        let wrapped = std::io::Error::new(std::io::ErrorKind::Other, reset);
+        assert!(options.is_retryable_error(&wrapped));
+
+        let not_found = std::io::Error::new(std::io::ErrorKind::NotFound, "not found");
+        assert!(!options.is_retryable_error(&not_found));
+
+        assert!(options.is_retryable_status(StatusCode::SERVICE_UNAVAILABLE));
+        assert!(!options.is_retryable_status(StatusCode::NOT_FOUND));
+    }
+
+    #[test]
+    fn test_parse_retry_after() {
+        assert_eq!(parse_retry_after("120"), Some(Duration::from_secs(120)));
+        assert_eq!(
+            parse_retry_after("Wed, 21 Oct 2015 07:28:00 GMT"),
+            Some(Duration::ZERO)
+        );
+        let in_a_minute = (chrono::Utc::now() + chrono::TimeDelta::seconds(60)).to_rfc2822();
+        let duration = parse_retry_after(&in_a_minute).unwrap();
+        assert!(duration > Duration::from_secs(50) && duration <= Duration::from_secs(60));
+        assert_eq!(parse_retry_after("soon"), None);
+    }
+}
@@ -0,0 +1,142 @@
+//! Returning cached repodata immediately while refreshing it in the background.
+
+use std::{
+    future::Future,
+    path::PathBuf,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use url::Url;
+
+use super::{
+    fetch_repo_data, CacheAction, CachedRepoData, FetchRepoDataError, FetchRepoDataOptions,
+};
+use crate::Reporter;
+
+/// The result of [`fetch_repo_data_stale_while_revalidate`].
+#[derive(Debug)]
+pub struct StaleWhileRevalidate {
+    /// The repodata that is currently in the cache regardless of whether it is up to date, or
+    /// `None` if there is no usable cache.
+    ///
+    /// The refresh in the background has to wait for the lock that is held by this value, drop it
+    /// as soon as the repodata has been read.
+    pub cached: Option<CachedRepoData>,
+
+    /// The refresh of the repodata that runs in the background.
+    pub refresh: RefreshHandle,
+}
+
+/// A handle to a refresh of the repodata that runs in the background. Awaiting the handle returns
+/// the refreshed repodata.
+///
+/// Dropping the handle does not stop the refresh, use
+/// [`FetchRepoDataOptions::cancellation_token`] to abort it.
+#[derive(Debug)]
+pub struct RefreshHandle(tokio::task::JoinHandle<Result<CachedRepoData, FetchRepoDataError>>);
+
+impl Future for RefreshHandle {
+    type Output = Result<CachedRepoData, FetchRepoDataError>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        Pin::new(&mut self.0)
+            .poll(cx)
+            .map(|result| result.unwrap_or_else(|err| Err(err.into())))
+    }
+}
+
+/// Returns the repodata that is currently cached for the given subdirectory and starts refreshing
+/// it in the background with [`fetch_repo_data`].
+///
+/// This allows interactive tools to start instantly with the repodata they already have while
+/// eventually getting the latest repodata. If there is no usable cache, `cached` is `None` and
+/// the refresh has to be awaited to get any repodata at all.
+///
+/// This function must be called from within a tokio runtime.
+pub async fn fetch_repo_data_stale_while_revalidate(
+    subdir_url: Url,
+    client: impl Into<reqwest_middleware::ClientWithMiddleware>,
+    cache_path: PathBuf,
+    options: FetchRepoDataOptions,
+    reporter: Option<Arc<dyn Reporter>>,
+) -> Result<StaleWhileRevalidate, FetchRepoDataError> {
+    let client = client.into();
+
+    let cached = if options.cache_action == CacheAction::NoCache {
+        None
+    } else {
+        let cached_options = FetchRepoDataOptions {
+            cache_action: CacheAction::ForceCacheOnly,
+            ..options.clone()
+        };
+        match fetch_repo_data(
+            subdir_url.clone(),
+            client.clone(),
+            cache_path.clone(),
+            cached_options,
+            None,
+        )
+        .await
+        {
+            Ok(cached) => Some(cached),
+            Err(FetchRepoDataError::NoCacheAvailable) => None,
+            Err(err) => return Err(err),
+        }
+    };
+
+    let refresh = RefreshHandle(tokio::spawn(fetch_repo_data(
+        subdir_url, client, cache_path, options, reporter,
+    )));
+
+    Ok(StaleWhileRevalidate { cached, refresh })
+}
+
+#[cfg(test)]
+mod test {
+    use assert_matches::assert_matches;
+    use tempfile::TempDir;
+
+    use super::*;
+    use crate::{fetch::CacheResult, utils::simple_channel_server::SimpleChannelServer};
+
+    #[tokio::test]
+    pub async fn test_stale_while_revalidate() {
+        let subdir_path = TempDir::new().unwrap();
+        std::fs::write(
+            subdir_path.path().join("repodata.json"),
+            r#"{"info": {"subdir": "noarch"}, "packages": {}}"#,
+        )
+        .unwrap();
+        let server = SimpleChannelServer::new(subdir_path.path()).await;
+        let cache_dir = TempDir::new().unwrap();
+        let fetch = || {
+            fetch_repo_data_stale_while_revalidate(
+                server.url(),
+                reqwest::Client::new(),
+                cache_dir.path().to_owned(),
+                FetchRepoDataOptions::default(),
+                None,
+            )
+        };
+
+        // Without a cache only the refresh returns repodata.
+        let result = fetch().await.unwrap();
+        assert!(result.cached.is_none());
+        let refreshed = result.refresh.await.unwrap();
+        assert_matches!(refreshed.cache_result, CacheResult::CacheNotPresent);
+        drop(refreshed);
+
+        // With a cache the cached repodata is returned immediately.
+        let result = fetch().await.unwrap();
+        let cached = result.cached.expect("the repodata should have been cached");
+        assert_matches!(cached.cache_result, CacheResult::CacheHit);
+        drop(cached);
+        let refreshed = result.refresh.await.unwrap();
+        assert_matches!(
+            refreshed.cache_result,
+            CacheResult::CacheHit | CacheResult::CacheHitAfterFetch | CacheResult::CacheOutdated
+        );
+    }
+}
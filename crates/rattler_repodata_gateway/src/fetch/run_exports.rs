@@ -0,0 +1,40 @@
+//! Fetching of the `run_exports.json` of a subdirectory.
+
+use std::{path::PathBuf, sync::Arc};
+
+use rattler_conda_types::SubdirRunExportsJson;
+use tracing::{field::Empty, instrument};
+use url::Url;
+
+use super::{fetch_json_file, FetchRepoDataError, FetchRepoDataOptions};
+use crate::Reporter;
+
+/// The name of the file that contains the run exports of a subdirectory.
+const RUN_EXPORTS_FILE_NAME: &str = "run_exports.json";
+
+/// Fetches the `run_exports.json` file of the given subdirectory. The file is
+/// cached on disk, compressed variants are used and access is locked in the
+/// same way as for the repodata (see [`super::fetch_repo_data`]).
+///
+/// The file contains the run exports of all packages in the subdirectory so
+/// build tools can resolve run exports without downloading every package.
+/// Returns `None` if the channel does not publish run exports for the
+/// subdirectory.
+#[instrument(err, skip_all, fields(url = Empty, path = %cache_path.display()))]
+pub async fn fetch_run_exports(
+    subdir_url: Url,
+    client: impl Into<reqwest_middleware::ClientWithMiddleware>,
+    cache_path: PathBuf,
+    options: FetchRepoDataOptions,
+    reporter: Option<Arc<dyn Reporter>>,
+) -> Result<Option<SubdirRunExportsJson>, FetchRepoDataError> {
+    fetch_json_file(
+        subdir_url,
+        client.into(),
+        cache_path,
+        RUN_EXPORTS_FILE_NAME,
+        options,
+        reporter,
+    )
+    .await
+}
@@ -0,0 +1,55 @@
+//! Limiting the rate at which a download is received.
+
+use std::{io, num::NonZeroU64, time::Duration};
+
+use bytes::Bytes;
+use futures::{Stream, StreamExt};
+use tokio::time::Instant;
+
+/// Limits the rate at which bytes are read from the stream to
+/// `bytes_per_second` on average. After every chunk the stream sleeps until
+/// the average rate since the start of the download is back at the limit.
+pub(crate) fn throttle<'a>(
+    stream: impl Stream<Item = io::Result<Bytes>> + 'a,
+    bytes_per_second: NonZeroU64,
+) -> impl Stream<Item = io::Result<Bytes>> + 'a {
+    let start = Instant::now();
+    let mut received = 0;
+    stream.then(move |bytes| {
+        if let Ok(bytes) = &bytes {
+            received += bytes.len() as u64;
+        }
+        let expected = Duration::from_secs_f64(received as f64 / bytes_per_second.get() as f64);
+        async move {
+            tokio::time::sleep_until(start + expected).await;
+            bytes
+        }
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use std::num::NonZeroU64;
+
+    use bytes::Bytes;
+    use futures::TryStreamExt;
+    use tokio::time::{Duration, Instant};
+
+    use super::throttle;
+
+    #[tokio::test]
+    async fn test_throttle() {
+        let chunks = (0..4).map(|_| Ok(Bytes::from(vec![0; 50])));
+        let start = Instant::now();
+        let bytes: Vec<Bytes> = throttle(
+            futures::stream::iter(chunks),
+            NonZeroU64::new(1000).unwrap(),
+        )
+        .try_collect()
+        .await
+        .unwrap();
+
+        assert_eq!(bytes.concat().len(), 200);
+        assert!(start.elapsed() >= Duration::from_millis(200));
+    }
+}
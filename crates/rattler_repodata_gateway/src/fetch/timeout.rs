@@ -0,0 +1,135 @@
+//! Timeouts for fetching repodata.
+
+use std::{error::Error, future::Future, io, time::Duration};
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use futures::{Stream, StreamExt};
+use reqwest::{Request, Response};
+use reqwest_middleware::{ClientWithMiddleware, Middleware, Next};
+
+use super::FetchRepoDataError;
+
+/// A middleware that fails a request if the server does not respond within
+/// the given duration. This includes connecting to the server and receiving
+/// the response headers, but not receiving the body.
+pub(crate) struct ResponseTimeoutMiddleware(pub Duration);
+
+#[async_trait]
+impl Middleware for ResponseTimeoutMiddleware {
+    async fn handle(
+        &self,
+        req: Request,
+        extensions: &mut http::Extensions,
+        next: Next<'_>,
+    ) -> reqwest_middleware::Result<Response> {
+        let url = req.url().clone();
+        match tokio::time::timeout(self.0, next.run(req, extensions)).await {
+            Ok(result) => result,
+            Err(_) => Err(reqwest_middleware::Error::middleware(io::Error::new(
+                io::ErrorKind::TimedOut,
+                format!(
+                    "the server did not respond to the request to '{}' within {:?}",
+                    rattler_redaction::Redact::redact(url),
+                    self.0
+                ),
+            ))),
+        }
+    }
+}
+
+/// Adds the [`ResponseTimeoutMiddleware`] to the client if a timeout is given.
+pub(crate) fn with_response_timeout(
+    client: ClientWithMiddleware,
+    timeout: Option<Duration>,
+) -> ClientWithMiddleware {
+    match timeout {
+        Some(timeout) => reqwest_middleware::ClientBuilder::from_client(client)
+            .with(ResponseTimeoutMiddleware(timeout))
+            .build(),
+        None => client,
+    }
+}
+
+/// Fails the stream with an [`io::ErrorKind::TimedOut`] error if no bytes are
+/// received for the given duration.
+pub(crate) fn read_timeout<'a>(
+    stream: impl Stream<Item = io::Result<Bytes>> + 'a,
+    timeout: Duration,
+) -> impl Stream<Item = io::Result<Bytes>> + 'a {
+    futures::stream::unfold(Some(Box::pin(stream)), move |stream| async move {
+        let mut stream = stream?;
+        match tokio::time::timeout(timeout, stream.next()).await {
+            Ok(Some(bytes)) => Some((bytes, Some(stream))),
+            Ok(None) => None,
+            Err(_) => Some((
+                Err(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    format!("no data was received for {timeout:?}"),
+                )),
+                None,
+            )),
+        }
+    })
+}
+
+/// Aborts the fetch with [`FetchRepoDataError::TimedOut`] if it does not
+/// complete within `timeout`.
+pub(crate) async fn with_timeout<T>(
+    timeout: Option<Duration>,
+    fetch: impl Future<Output = Result<T, FetchRepoDataError>>,
+) -> Result<T, FetchRepoDataError> {
+    match timeout {
+        Some(timeout) => tokio::time::timeout(timeout, fetch)
+            .await
+            .unwrap_or_else(|_| {
+                tracing::debug!("fetching repodata did not complete within {timeout:?}");
+                Err(FetchRepoDataError::TimedOut)
+            }),
+        None => fetch.await,
+    }
+}
+
+/// Returns true if the error or any of its sources is caused by a timeout.
+pub(crate) fn is_timeout(error: &(dyn Error + 'static)) -> bool {
+    // These errors forward `source` to the error they wrap, which would skip
+    // the wrapped error itself.
+    if let Some(err) = error.downcast_ref::<reqwest_middleware::Error>() {
+        return match err {
+            reqwest_middleware::Error::Reqwest(err) => is_timeout(err),
+            reqwest_middleware::Error::Middleware(err) => is_timeout(err.as_ref()),
+        };
+    }
+
+    let timed_out = if let Some(err) = error.downcast_ref::<reqwest::Error>() {
+        err.is_timeout()
+    } else if let Some(err) = error.downcast_ref::<io::Error>() {
+        err.kind() == io::ErrorKind::TimedOut
+            || err.get_ref().map_or(false, |inner| is_timeout(inner))
+    } else {
+        false
+    };
+    timed_out || error.source().map_or(false, is_timeout)
+}
+
+#[cfg(test)]
+mod test {
+    use std::{io, time::Duration};
+
+    use bytes::Bytes;
+    use futures::{StreamExt, TryStreamExt};
+
+    use super::{is_timeout, read_timeout};
+
+    #[tokio::test]
+    async fn test_read_timeout() {
+        let stream = futures::stream::iter([Ok(Bytes::from_static(b"repodata"))])
+            .chain(futures::stream::pending());
+        let err = read_timeout(stream, Duration::from_millis(10))
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+        assert!(is_timeout(&io::Error::new(io::ErrorKind::Other, err)));
+    }
+}
@@ -0,0 +1,103 @@
+//! Validation of downloaded repodata before it is stored in the cache.
+
+use std::{
+    collections::HashMap,
+    io::{self, Read},
+    path::Path,
+};
+
+use serde::de::IgnoredAny;
+
+/// The size of the chunks in which the file is read to validate its encoding.
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Checks that the file at `path` is complete, i.e. that it is valid UTF-8 and
+/// contains a single JSON object. Returns an [`io::ErrorKind::InvalidData`]
+/// error otherwise. If the file is `compressed` with zstd it is decompressed
+/// first.
+///
+/// The file is read in a streaming fashion so the repodata is never completely
+/// loaded into memory.
+pub(crate) fn validate_json_file(path: &Path, compressed: bool) -> io::Result<()> {
+    let open = || super::open_cached_repo_data(path, compressed);
+    validate_utf8(&mut open()?)?;
+    serde_json::from_reader::<_, HashMap<String, IgnoredAny>>(open()?)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    Ok(())
+}
+
+/// Checks that all bytes read from `reader` are valid UTF-8.
+fn validate_utf8(reader: &mut impl Read) -> io::Result<()> {
+    let mut buf = vec![0; CHUNK_SIZE];
+
+    // The number of bytes at the start of `buf` that are the start of a
+    // character that is continued in the next chunk.
+    let mut incomplete = 0;
+    loop {
+        let read = reader.read(&mut buf[incomplete..])?;
+        if read == 0 {
+            return if incomplete == 0 {
+                Ok(())
+            } else {
+                Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "the file ends in the middle of a UTF-8 character",
+                ))
+            };
+        }
+
+        let len = incomplete + read;
+        incomplete = match std::str::from_utf8(&buf[..len]) {
+            Ok(_) => 0,
+            Err(e) if e.error_len().is_none() => {
+                buf.copy_within(e.valid_up_to()..len, 0);
+                len - e.valid_up_to()
+            }
+            Err(e) => return Err(io::Error::new(io::ErrorKind::InvalidData, e)),
+        };
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{validate_json_file, validate_utf8, CHUNK_SIZE};
+
+    #[test]
+    fn test_validate_utf8() {
+        // A multi-byte character that is split across two chunks.
+        let mut valid = vec![b'a'; CHUNK_SIZE - 1];
+        valid.extend_from_slice("é".as_bytes());
+        assert!(validate_utf8(&mut valid.as_slice()).is_ok());
+
+        // A truncated multi-byte character.
+        let truncated = &valid[..valid.len() - 1];
+        assert!(validate_utf8(&mut &truncated[..]).is_err());
+
+        let mut invalid = vec![b'a'; CHUNK_SIZE + 10];
+        invalid[CHUNK_SIZE + 5] = 0xff;
+        assert!(validate_utf8(&mut invalid.as_slice()).is_err());
+    }
+
+    #[test]
+    fn test_validate_json_file() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("repodata.json");
+
+        let repo_data = r#"{"info": {"subdir": "noarch"}, "packages": {}}"#;
+        std::fs::write(&path, repo_data).unwrap();
+        assert!(validate_json_file(&path, false).is_ok());
+
+        std::fs::write(&path, zstd::encode_all(repo_data.as_bytes(), 0).unwrap()).unwrap();
+        assert!(validate_json_file(&path, true).is_ok());
+        assert!(validate_json_file(&path, false).is_err());
+
+        std::fs::write(&path, r#"{"info": {"subdir": "noarch"}, "packages": {"#).unwrap();
+        assert!(validate_json_file(&path, false).is_err());
+
+        std::fs::write(&path, b"{\"info\": \"\xff\"}").unwrap();
+        assert!(validate_json_file(&path, false).is_err());
+
+        std::fs::write(&path, "[]").unwrap();
+        assert!(validate_json_file(&path, false).is_err());
+    }
+}
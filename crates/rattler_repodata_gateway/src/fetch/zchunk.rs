@@ -0,0 +1,745 @@
+//! Incremental downloads of zchunk (`repodata.json.zck`) files.
+//!
+//! A zchunk file splits the `repodata.json` into chunks that are compressed
+//! independently. The header of the file contains the checksum and the size of
+//! every chunk. When the file is fetched again, only the header and the chunks
+//! that are not part of the previously downloaded file are requested with range
+//! requests. All other chunks are copied from the cached file.
+//!
+//! The format is described in
+//! <https://github.com/zchunk/zchunk/blob/main/zchunk_format.txt>.
+
+use std::{
+    collections::HashMap,
+    io::{ErrorKind, Write},
+    ops::Range,
+    path::{Path, PathBuf},
+};
+
+use bytes::Bytes;
+use rattler_digest::{Blake2b256, HashingWriter};
+use rattler_redaction::Redact;
+use reqwest::{header::HeaderValue, StatusCode};
+use reqwest_middleware::ClientWithMiddleware;
+use sha2::Digest;
+use simple_spawn_blocking::{tokio::run_blocking_task, Cancelled};
+use tempfile::NamedTempFile;
+use url::Url;
+
+use super::{cache::CacheHeaders, retry, FetchRepoDataOptions};
+
+/// The magic bytes at the start of every zchunk file.
+const MAGIC: &[u8] = b"\0ZCK1";
+
+/// The flag that indicates that every chunk in the index has a stream id.
+const FLAG_HAS_STREAMS: u64 = 1;
+
+/// The flag that indicates that the preface contains optional elements.
+const FLAG_HAS_OPTIONAL_ELEMENTS: u64 = 2;
+
+/// The number of bytes that is requested to read the header of a file. If the
+/// header is larger, the rest of it is requested separately.
+const INITIAL_HEADER_REQUEST_SIZE: usize = 64 * 1024;
+
+/// The maximum number of range requests that is made to download the missing
+/// chunks. If more ranges are missing they are downloaded with a single request
+/// instead.
+const MAX_RANGE_REQUESTS: usize = 32;
+
+/// Represents the errors that can occur while fetching a zchunk file.
+#[derive(Debug, thiserror::Error)]
+pub(crate) enum ZchunkError {
+    #[error("invalid zchunk header: {0}")]
+    InvalidHeader(&'static str),
+
+    #[error("unsupported zchunk checksum type {0}")]
+    UnsupportedChecksumType(u64),
+
+    #[error("unsupported zchunk compression type {0}")]
+    UnsupportedCompressionType(u64),
+
+    #[error("the checksum of chunk {0} does not match the zchunk header")]
+    ChunkChecksumMismatch(usize),
+
+    #[error("the checksum of the data does not match the zchunk header")]
+    DataChecksumMismatch,
+
+    #[error("the server did not respond with the requested byte range")]
+    RangeNotSatisfied,
+
+    #[error(transparent)]
+    HttpError(reqwest_middleware::Error),
+
+    #[error(transparent)]
+    IoError(#[from] std::io::Error),
+
+    #[error("the operation was cancelled")]
+    Cancelled,
+}
+
+impl From<Cancelled> for ZchunkError {
+    fn from(_: Cancelled) -> Self {
+        ZchunkError::Cancelled
+    }
+}
+
+impl From<reqwest_middleware::Error> for ZchunkError {
+    fn from(value: reqwest_middleware::Error) -> Self {
+        Self::HttpError(value.redact())
+    }
+}
+
+impl From<reqwest::Error> for ZchunkError {
+    fn from(value: reqwest::Error) -> Self {
+        Self::HttpError(value.redact().into())
+    }
+}
+
+/// The checksum algorithms that are used by zchunk files.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum ChecksumType {
+    Sha1,
+    Sha256,
+    Sha512,
+    /// The first 128 bits of the SHA-512 checksum.
+    Sha512_128,
+}
+
+impl ChecksumType {
+    fn from_id(id: u64) -> Result<Self, ZchunkError> {
+        match id {
+            0 => Ok(Self::Sha1),
+            1 => Ok(Self::Sha256),
+            2 => Ok(Self::Sha512),
+            3 => Ok(Self::Sha512_128),
+            _ => Err(ZchunkError::UnsupportedChecksumType(id)),
+        }
+    }
+
+    /// The length of a checksum in bytes.
+    fn len(self) -> usize {
+        match self {
+            Self::Sha1 => 20,
+            Self::Sha256 => 32,
+            Self::Sha512 => 64,
+            Self::Sha512_128 => 16,
+        }
+    }
+
+    fn compute(self, data: &[u8]) -> Vec<u8> {
+        match self {
+            Self::Sha1 => rattler_digest::Sha1::digest(data).to_vec(),
+            Self::Sha256 => sha2::Sha256::digest(data).to_vec(),
+            Self::Sha512 => sha2::Sha512::digest(data).to_vec(),
+            Self::Sha512_128 => sha2::Sha512::digest(data)[..16].to_vec(),
+        }
+    }
+}
+
+/// Reads the fields of a zchunk header.
+struct HeaderReader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> HeaderReader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], ZchunkError> {
+        let bytes = self
+            .position
+            .checked_add(len)
+            .and_then(|end| self.bytes.get(self.position..end))
+            .ok_or(ZchunkError::InvalidHeader("unexpected end of the header"))?;
+        self.position += len;
+        Ok(bytes)
+    }
+
+    /// Reads a compressed integer: an unsigned little endian integer stored in
+    /// groups of 7 bits, the highest bit is set in the last byte.
+    fn compressed_int(&mut self) -> Result<u64, ZchunkError> {
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = self.take(1)?[0];
+            value |= u64::from(byte & 0x7f) << shift;
+            if byte & 0x80 != 0 {
+                return Ok(value);
+            }
+        }
+        Err(ZchunkError::InvalidHeader("integer is too large"))
+    }
+
+    fn size(&mut self) -> Result<usize, ZchunkError> {
+        usize::try_from(self.compressed_int()?)
+            .map_err(|_| ZchunkError::InvalidHeader("size is too large"))
+    }
+}
+
+/// A chunk of a zchunk file.
+#[derive(Debug)]
+struct Chunk {
+    checksum: Vec<u8>,
+
+    /// The location of the compressed chunk in the file.
+    range: Range<usize>,
+
+    uncompressed_len: usize,
+}
+
+/// The parsed lead and header of a zchunk file.
+#[derive(Debug)]
+struct Header {
+    /// The raw bytes of the lead and the header.
+    bytes: Vec<u8>,
+
+    data_checksum_type: ChecksumType,
+    data_checksum: Vec<u8>,
+    compressed: bool,
+    chunk_checksum_type: ChecksumType,
+
+    /// The chunks of the file. The first chunk is the dictionary, which is
+    /// empty if the file does not have one.
+    chunks: Vec<Chunk>,
+}
+
+impl Header {
+    /// Returns the length of the lead and the header from the start of a file.
+    fn len(bytes: &[u8]) -> Result<usize, ZchunkError> {
+        let mut reader = HeaderReader { bytes, position: 0 };
+        if reader.take(MAGIC.len())? != MAGIC {
+            return Err(ZchunkError::InvalidHeader("not a zchunk file"));
+        }
+        let checksum_type = ChecksumType::from_id(reader.compressed_int()?)?;
+        let header_size = reader.size()?;
+        reader.take(checksum_type.len())?;
+        reader
+            .position
+            .checked_add(header_size)
+            .ok_or(ZchunkError::InvalidHeader("size is too large"))
+    }
+
+    /// Parses the header from the start of a file.
+    fn parse(bytes: &[u8]) -> Result<Self, ZchunkError> {
+        let len = Self::len(bytes)?;
+        let bytes = bytes
+            .get(..len)
+            .ok_or(ZchunkError::InvalidHeader("unexpected end of the header"))?;
+        let mut reader = HeaderReader { bytes, position: 0 };
+
+        // Lead
+        reader.take(MAGIC.len())?;
+        let data_checksum_type = ChecksumType::from_id(reader.compressed_int()?)?;
+        reader.size()?;
+        reader.take(data_checksum_type.len())?;
+
+        // Preface
+        let data_checksum = reader.take(data_checksum_type.len())?.to_vec();
+        let flags = reader.compressed_int()?;
+        let compressed = match reader.compressed_int()? {
+            0 => false,
+            2 => true,
+            compression => return Err(ZchunkError::UnsupportedCompressionType(compression)),
+        };
+        if flags & FLAG_HAS_OPTIONAL_ELEMENTS != 0 {
+            for _ in 0..reader.compressed_int()? {
+                reader.compressed_int()?;
+                let size = reader.size()?;
+                reader.take(size)?;
+            }
+        }
+
+        // Index
+        reader.size()?;
+        let chunk_checksum_type = ChecksumType::from_id(reader.compressed_int()?)?;
+        let mut offset = len;
+        let chunks = (0..reader.size()?)
+            .map(|_| {
+                if flags & FLAG_HAS_STREAMS != 0 {
+                    reader.compressed_int()?;
+                }
+                let checksum = reader.take(chunk_checksum_type.len())?.to_vec();
+                let chunk_len = reader.size()?;
+                let uncompressed_len = reader.size()?;
+                let end = offset
+                    .checked_add(chunk_len)
+                    .ok_or(ZchunkError::InvalidHeader("chunk is too large"))?;
+                let range = offset..end;
+                offset = end;
+                Ok(Chunk {
+                    checksum,
+                    range,
+                    uncompressed_len,
+                })
+            })
+            .collect::<Result<Vec<_>, ZchunkError>>()?;
+        if chunks.is_empty() {
+            return Err(ZchunkError::InvalidHeader("the dictionary is missing"));
+        }
+
+        Ok(Self {
+            bytes: bytes.to_vec(),
+            data_checksum_type,
+            data_checksum,
+            compressed,
+            chunk_checksum_type,
+            chunks,
+        })
+    }
+}
+
+/// A previously downloaded zchunk file.
+struct CachedFile {
+    bytes: Vec<u8>,
+
+    /// The location of the chunks of the file by checksum.
+    chunks: HashMap<Vec<u8>, Range<usize>>,
+}
+
+/// The bytes from which the chunks of a file are assembled.
+struct ChunkSources {
+    cached: Option<CachedFile>,
+
+    /// The byte ranges of the new file that have been downloaded.
+    downloaded: Vec<(Range<usize>, Bytes)>,
+}
+
+impl ChunkSources {
+    fn new(cached: Option<Vec<u8>>, header: &Header) -> Self {
+        // Chunks can only be reused if their checksums are computed the same way.
+        let cached = cached.and_then(|bytes| {
+            let cached_header = Header::parse(&bytes).ok()?;
+            if cached_header.chunk_checksum_type != header.chunk_checksum_type {
+                return None;
+            }
+            let chunks = cached_header
+                .chunks
+                .into_iter()
+                .filter(|chunk| chunk.range.end <= bytes.len())
+                .map(|chunk| (chunk.checksum, chunk.range))
+                .collect();
+            Some(CachedFile { bytes, chunks })
+        });
+        Self {
+            cached,
+            downloaded: Vec::new(),
+        }
+    }
+
+    /// Returns the bytes of the chunk if they are available.
+    fn get(&self, chunk: &Chunk) -> Option<&[u8]> {
+        if chunk.range.is_empty() {
+            return Some(&[]);
+        }
+        if let Some((range, bytes)) = self
+            .downloaded
+            .iter()
+            .find(|(range, _)| range.start <= chunk.range.start && chunk.range.end <= range.end)
+        {
+            return Some(&bytes[chunk.range.start - range.start..chunk.range.end - range.start]);
+        }
+        let cached = self.cached.as_ref()?;
+        cached
+            .chunks
+            .get(&chunk.checksum)
+            .map(|range| &cached.bytes[range.clone()])
+    }
+
+    /// Returns the byte ranges that have to be downloaded to get all chunks of
+    /// the file.
+    fn missing_ranges(&self, header: &Header) -> Vec<Range<usize>> {
+        let mut missing: Vec<Range<usize>> = Vec::new();
+        for chunk in header
+            .chunks
+            .iter()
+            .filter(|chunk| self.get(chunk).is_none())
+        {
+            match missing.last_mut() {
+                Some(last) if last.end == chunk.range.start => last.end = chunk.range.end,
+                _ => missing.push(chunk.range.clone()),
+            }
+        }
+        if missing.len() > MAX_RANGE_REQUESTS {
+            let end = missing.last().unwrap().end;
+            missing.truncate(1);
+            missing[0].end = end;
+        }
+        missing
+    }
+}
+
+/// The result of [`fetch_zchunk`].
+pub(crate) enum ZchunkFetch {
+    /// The file did not change since the cache headers were received.
+    NotModified,
+
+    /// The file was fetched.
+    Fetched {
+        /// The new zchunk file, which can be used to fetch the next version.
+        zck_file: NamedTempFile,
+
+        /// The decompressed `repodata.json`.
+        repo_data_file: NamedTempFile,
+
+        /// The blake2 hash of the `repodata.json`.
+        blake2_hash: blake2::digest::Output<Blake2b256>,
+
+        /// The cache headers of the zchunk file.
+        cache_headers: CacheHeaders,
+
+        /// The number of bytes that were downloaded.
+        downloaded_bytes: u64,
+    },
+}
+
+/// Fetches the zchunk file at `url` and decompresses it. Chunks that are part
+/// of the previously downloaded zchunk file at `cached_zck_path` are not
+/// downloaded again. If `cache_headers` are given and the file did not change,
+/// [`ZchunkFetch::NotModified`] is returned.
+pub(crate) async fn fetch_zchunk(
+    client: &ClientWithMiddleware,
+    url: Url,
+    cached_zck_path: PathBuf,
+    cache_headers: Option<&CacheHeaders>,
+    temp_dir: &Path,
+    options: &FetchRepoDataOptions,
+) -> Result<ZchunkFetch, ZchunkError> {
+    // Request the start of the file, which contains the header.
+    let mut headers = options.headers.clone();
+    if let Some(cache_headers) = cache_headers {
+        cache_headers.add_to_request(&mut headers);
+    }
+    let initial_range = 0..INITIAL_HEADER_REQUEST_SIZE;
+    headers.insert(reqwest::header::RANGE, range_header(&initial_range));
+    let response = retry::send_with_retry(&options.retry, &url, None, || {
+        client.get(url.clone()).headers(headers.clone())
+    })
+    .await?
+    .error_for_status()?;
+    if response.status() == StatusCode::NOT_MODIFIED {
+        return Ok(ZchunkFetch::NotModified);
+    }
+    let cache_headers = CacheHeaders::from(&response);
+
+    // If the server does not support range requests the whole file is returned.
+    let partial = response.status() == StatusCode::PARTIAL_CONTENT;
+    let mut start = response.bytes().await?.to_vec();
+    let header_len = Header::len(&start)?;
+    if start.len() < header_len && partial {
+        let rest = get_range(client, &url, start.len()..header_len, options).await?;
+        start.extend_from_slice(&rest);
+    }
+    let header = Header::parse(&start)?;
+
+    // Determine which chunks are not available yet and download them.
+    let cached = match tokio::fs::read(&cached_zck_path).await {
+        Ok(bytes) => Some(bytes),
+        Err(err) if err.kind() == ErrorKind::NotFound => None,
+        Err(err) => return Err(err.into()),
+    };
+    let mut sources = ChunkSources::new(cached, &header);
+    sources
+        .downloaded
+        .push((0..start.len(), Bytes::from(start)));
+    let missing = sources.missing_ranges(&header);
+    let missing_chunks = header
+        .chunks
+        .iter()
+        .filter(|chunk| sources.get(chunk).is_none())
+        .count();
+    tracing::debug!(
+        "fetching {} of {} chunks of '{}' with {} requests",
+        missing_chunks,
+        header.chunks.len(),
+        url.clone().redact(),
+        missing.len()
+    );
+    for range in missing {
+        let bytes = get_range(client, &url, range.clone(), options).await?;
+        sources.downloaded.push((range, bytes));
+    }
+    let downloaded_bytes = sources
+        .downloaded
+        .iter()
+        .map(|(range, _)| range.len() as u64)
+        .sum();
+
+    // Verify and assemble the chunks.
+    let temp_dir = temp_dir.to_owned();
+    run_blocking_task(move || {
+        let mut zck_file = NamedTempFile::new_in(&temp_dir)?;
+        let mut repo_data_file = NamedTempFile::new_in(&temp_dir)?;
+        let blake2_hash = assemble(&header, &sources, &mut zck_file, &mut repo_data_file)?;
+        Ok(ZchunkFetch::Fetched {
+            zck_file,
+            repo_data_file,
+            blake2_hash,
+            cache_headers,
+            downloaded_bytes,
+        })
+    })
+    .await
+}
+
+fn range_header(range: &Range<usize>) -> HeaderValue {
+    HeaderValue::from_str(&format!("bytes={}-{}", range.start, range.end - 1))
+        .expect("a range is a valid header value")
+}
+
+/// Downloads a byte range of the file.
+async fn get_range(
+    client: &ClientWithMiddleware,
+    url: &Url,
+    range: Range<usize>,
+    options: &FetchRepoDataOptions,
+) -> Result<Bytes, ZchunkError> {
+    let mut headers = options.headers.clone();
+    headers.insert(reqwest::header::RANGE, range_header(&range));
+    let response = retry::send_with_retry(&options.retry, url, None, || {
+        client.get(url.clone()).headers(headers.clone())
+    })
+    .await?
+    .error_for_status()?;
+    if response.status() != StatusCode::PARTIAL_CONTENT {
+        return Err(ZchunkError::RangeNotSatisfied);
+    }
+    let bytes = response.bytes().await?;
+    if bytes.len() != range.len() {
+        return Err(ZchunkError::RangeNotSatisfied);
+    }
+    Ok(bytes)
+}
+
+/// Verifies the chunks of the file, writes the zchunk file to `zck_file` and
+/// the decompressed data to `repo_data_file`. Returns the blake2 hash of the
+/// decompressed data.
+fn assemble(
+    header: &Header,
+    sources: &ChunkSources,
+    zck_file: &mut impl Write,
+    repo_data_file: &mut impl Write,
+) -> Result<blake2::digest::Output<Blake2b256>, ZchunkError> {
+    let mut data = Vec::new();
+    for (index, chunk) in header.chunks.iter().enumerate() {
+        let bytes = sources.get(chunk).ok_or(ZchunkError::RangeNotSatisfied)?;
+        if !bytes.is_empty() && header.chunk_checksum_type.compute(bytes) != chunk.checksum {
+            return Err(ZchunkError::ChunkChecksumMismatch(index));
+        }
+        data.extend_from_slice(bytes);
+    }
+    if header.data_checksum_type.compute(&data) != header.data_checksum {
+        return Err(ZchunkError::DataChecksumMismatch);
+    }
+    zck_file.write_all(&header.bytes)?;
+    zck_file.write_all(&data)?;
+
+    let data_start = header.bytes.len();
+    let chunk_data =
+        |chunk: &Chunk| &data[chunk.range.start - data_start..chunk.range.end - data_start];
+    let mut writer = HashingWriter::<_, Blake2b256>::new(repo_data_file);
+    let (dictionary, chunks) = header
+        .chunks
+        .split_first()
+        .expect("the header contains the dictionary");
+    if header.compressed {
+        let mut decompressor = if dictionary.range.is_empty() {
+            zstd::bulk::Decompressor::new()?
+        } else {
+            let dictionary =
+                zstd::bulk::decompress(chunk_data(dictionary), dictionary.uncompressed_len)?;
+            zstd::bulk::Decompressor::with_dictionary(&dictionary)?
+        };
+        for chunk in chunks {
+            writer
+                .write_all(&decompressor.decompress(chunk_data(chunk), chunk.uncompressed_len)?)?;
+        }
+    } else {
+        for chunk in chunks {
+            writer.write_all(chunk_data(chunk))?;
+        }
+    }
+    let (_, hash) = writer.finalize();
+    Ok(hash)
+}
+
+#[cfg(test)]
+pub(super) mod test {
+    use std::{
+        future::IntoFuture,
+        sync::{Arc, Mutex},
+    };
+
+    use axum::{extract::Request, middleware::Next};
+    use sha2::Digest;
+    use tempfile::TempDir;
+    use tower_http::services::ServeDir;
+    use url::Url;
+
+    use super::{fetch_zchunk, Header, ZchunkFetch, MAGIC};
+    use crate::fetch::FetchRepoDataOptions;
+
+    fn push_compressed_int(mut value: usize, out: &mut Vec<u8>) {
+        loop {
+            let byte = (value % 128) as u8;
+            value /= 128;
+            if value == 0 {
+                out.push(byte | 0x80);
+                return;
+            }
+            out.push(byte);
+        }
+    }
+
+    /// Creates a zchunk file without a dictionary that contains every part as
+    /// a separate zstd compressed chunk.
+    pub(in crate::fetch) fn zchunk_file(parts: &[&[u8]]) -> Vec<u8> {
+        let compressed: Vec<Vec<u8>> = parts
+            .iter()
+            .map(|part| zstd::bulk::compress(part, 0).unwrap())
+            .collect();
+        let data = compressed.concat();
+
+        // SHA-512/128 chunk checksums, the empty dictionary comes first.
+        let mut chunk_index = Vec::new();
+        push_compressed_int(3, &mut chunk_index);
+        push_compressed_int(compressed.len() + 1, &mut chunk_index);
+        chunk_index.extend_from_slice(&[0; 16]);
+        push_compressed_int(0, &mut chunk_index);
+        push_compressed_int(0, &mut chunk_index);
+        for (chunk, part) in compressed.iter().zip(parts) {
+            chunk_index.extend_from_slice(&sha2::Sha512::digest(chunk)[..16]);
+            push_compressed_int(chunk.len(), &mut chunk_index);
+            push_compressed_int(part.len(), &mut chunk_index);
+        }
+
+        // SHA-256 data checksum, no flags and zstd compression.
+        let mut header = sha2::Sha256::digest(&data).to_vec();
+        push_compressed_int(0, &mut header);
+        push_compressed_int(2, &mut header);
+        push_compressed_int(chunk_index.len(), &mut header);
+        header.extend_from_slice(&chunk_index);
+        push_compressed_int(0, &mut header);
+
+        let mut lead = MAGIC.to_vec();
+        push_compressed_int(1, &mut lead);
+        push_compressed_int(header.len(), &mut lead);
+        let header_checksum = sha2::Sha256::digest([lead.as_slice(), &header].concat());
+
+        [lead, header_checksum.to_vec(), header, data].concat()
+    }
+
+    /// Returns pseudo random bytes that do not compress well.
+    fn noise(seed: u64, len: usize) -> Vec<u8> {
+        let mut state = seed;
+        (0..len)
+            .map(|_| {
+                state = state
+                    .wrapping_mul(6_364_136_223_846_793_005)
+                    .wrapping_add(1_442_695_040_888_963_407);
+                (state >> 56) as u8
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_parse_header() {
+        let file = zchunk_file(&[b"hello ", b"world"]);
+        let header = Header::parse(&file).unwrap();
+        assert_eq!(header.chunks.len(), 3);
+        assert!(header.compressed);
+        assert!(header.chunks[0].range.is_empty());
+        assert_eq!(header.chunks[0].range.start, header.bytes.len());
+        assert_eq!(header.chunks[2].range.end, file.len());
+        assert_eq!(header.chunks[2].uncompressed_len, 5);
+
+        assert!(Header::parse(&file[..header.bytes.len() - 1]).is_err());
+        assert!(Header::parse(b"{\"packages\": {}}").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_fetch_zchunk() {
+        let parts = [noise(0, 50_000), noise(1, 50_000), noise(2, 50_000)];
+        let server_dir = TempDir::new().unwrap();
+        let zck_path = server_dir.path().join("repodata.json.zck");
+        let parts_ref: Vec<&[u8]> = parts.iter().map(Vec::as_slice).collect();
+        std::fs::write(&zck_path, zchunk_file(&parts_ref)).unwrap();
+
+        // Serve the directory and record the requested ranges.
+        let ranges = Arc::new(Mutex::new(Vec::new()));
+        let recorded_ranges = ranges.clone();
+        let app = axum::Router::new()
+            .fallback_service(ServeDir::new(server_dir.path()))
+            .layer(axum::middleware::from_fn(
+                move |request: Request, next: Next| {
+                    let ranges = recorded_ranges.clone();
+                    async move {
+                        if let Some(range) = request.headers().get(reqwest::header::RANGE) {
+                            ranges
+                                .lock()
+                                .unwrap()
+                                .push(range.to_str().unwrap().to_owned());
+                        }
+                        next.run(request).await
+                    }
+                },
+            ));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = Url::parse(&format!(
+            "http://{}/repodata.json.zck",
+            listener.local_addr().unwrap()
+        ))
+        .unwrap();
+        tokio::spawn(axum::serve(listener, app).into_future());
+
+        let client = reqwest_middleware::ClientWithMiddleware::from(reqwest::Client::new());
+        let cache_dir = TempDir::new().unwrap();
+        let cached_zck_path = cache_dir.path().join("cached.zck");
+        let options = FetchRepoDataOptions::default();
+        let fetch = || {
+            fetch_zchunk(
+                &client,
+                url.clone(),
+                cached_zck_path.clone(),
+                None,
+                cache_dir.path(),
+                &options,
+            )
+        };
+
+        // Without a cached file all chunks are downloaded.
+        let ZchunkFetch::Fetched {
+            zck_file,
+            repo_data_file,
+            ..
+        } = fetch().await.unwrap()
+        else {
+            panic!("expected the file to be fetched");
+        };
+        assert_eq!(
+            std::fs::read(repo_data_file.path()).unwrap(),
+            parts.concat()
+        );
+        zck_file.persist(&cached_zck_path).unwrap();
+        assert_eq!(ranges.lock().unwrap().len(), 2);
+
+        // Only the header and the chunk that changed are downloaded.
+        let parts = [noise(0, 50_000), noise(3, 50_000), noise(2, 50_000)];
+        let parts_ref: Vec<&[u8]> = parts.iter().map(Vec::as_slice).collect();
+        let file = zchunk_file(&parts_ref);
+        std::fs::write(&zck_path, &file).unwrap();
+        ranges.lock().unwrap().clear();
+
+        let ZchunkFetch::Fetched { repo_data_file, .. } = fetch().await.unwrap() else {
+            panic!("expected the file to be fetched");
+        };
+        assert_eq!(
+            std::fs::read(repo_data_file.path()).unwrap(),
+            parts.concat()
+        );
+        let changed = &Header::parse(&file).unwrap().chunks[2].range;
+        assert_eq!(
+            *ranges.lock().unwrap(),
+            vec![
+                "bytes=0-65535".to_owned(),
+                format!("bytes={}-{}", changed.start, changed.end - 1)
+            ]
+        );
+    }
+}
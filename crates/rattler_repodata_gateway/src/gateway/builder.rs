@@ -1,9 +1,12 @@
 use crate::gateway::GatewayInner;
-use crate::{ChannelConfig, Gateway};
+use crate::{ChannelConfig, ConcurrencyLimiter, Gateway};
 use dashmap::DashMap;
+#[cfg(not(target_arch = "wasm32"))]
 use rattler_cache::package_cache::PackageCache;
+use rattler_config::RattlerConfig;
+use rattler_networking::OciMiddleware;
 use reqwest::Client;
-use reqwest_middleware::ClientWithMiddleware;
+use reqwest_middleware::{ClientBuilder, ClientWithMiddleware};
 use std::path::PathBuf;
 use std::sync::Arc;
 
@@ -13,8 +16,10 @@ pub struct GatewayBuilder {
     channel_config: ChannelConfig,
     client: Option<ClientWithMiddleware>,
     cache: Option<PathBuf>,
+    #[cfg(not(target_arch = "wasm32"))]
     package_cache: Option<PackageCache>,
     max_concurrent_requests: Option<usize>,
+    max_concurrent_requests_per_host: Option<usize>,
 }
 
 impl GatewayBuilder {
@@ -24,15 +29,20 @@ impl GatewayBuilder {
     }
 
     /// Set the client to use for fetching repodata.
+    ///
+    /// This can be a plain [`reqwest::Client`] or a [`ClientWithMiddleware`]
+    /// with custom middleware (e.g. for authentication, retries or tracing).
+    /// If no client is set a default client is used that is able to fetch
+    /// repodata from `oci://` channels.
     #[must_use]
-    pub fn with_client(mut self, client: ClientWithMiddleware) -> Self {
+    pub fn with_client(mut self, client: impl Into<ClientWithMiddleware>) -> Self {
         self.set_client(client);
         self
     }
 
     /// Set the client to use for fetching repodata.
-    pub fn set_client(&mut self, client: ClientWithMiddleware) -> &mut Self {
-        self.client = Some(client);
+    pub fn set_client(&mut self, client: impl Into<ClientWithMiddleware>) -> &mut Self {
+        self.client = Some(client.into());
         self
     }
 
@@ -50,6 +60,11 @@ impl GatewayBuilder {
     }
 
     /// Set the directory to use for caching repodata.
+    ///
+    /// Defaults to [`rattler_config::default_repodata_cache_dir`], the
+    /// `repodata` directory in the rattler cache. Older versions cached the
+    /// repodata directly in the root of the rattler cache, those entries are
+    /// not used anymore and can be removed.
     #[must_use]
     pub fn with_cache_dir(mut self, cache: impl Into<PathBuf>) -> Self {
         self.set_cache_dir(cache);
@@ -57,6 +72,7 @@ impl GatewayBuilder {
     }
 
     /// Add package cache to the builder to store packages.
+    #[cfg(not(target_arch = "wasm32"))]
     pub fn with_package_cache(mut self, package_cache: PackageCache) -> Self {
         self.set_package_cache(package_cache);
         self
@@ -69,6 +85,7 @@ impl GatewayBuilder {
     }
 
     /// Set the directory to use for caching packages.
+    #[cfg(not(target_arch = "wasm32"))]
     pub fn set_package_cache(&mut self, package_cache: PackageCache) -> &mut Self {
         self.package_cache = Some(package_cache);
         self
@@ -87,33 +104,118 @@ impl GatewayBuilder {
         self
     }
 
+    /// Sets the maximum number of `repodata.json` files that are downloaded
+    /// from the same host at the same time.
+    #[must_use]
+    pub fn with_max_concurrent_requests_per_host(
+        mut self,
+        max_concurrent_requests_per_host: usize,
+    ) -> Self {
+        self.set_max_concurrent_requests_per_host(max_concurrent_requests_per_host);
+        self
+    }
+
+    /// Sets the maximum number of `repodata.json` files that are downloaded
+    /// from the same host at the same time.
+    pub fn set_max_concurrent_requests_per_host(
+        &mut self,
+        max_concurrent_requests_per_host: usize,
+    ) -> &mut Self {
+        self.max_concurrent_requests_per_host = Some(max_concurrent_requests_per_host);
+        self
+    }
+
+    /// Applies the settings from a [`RattlerConfig`]: the cache directories,
+    /// the maximum number of concurrent requests and, if the configuration is
+    /// offline, only reading repodata from the cache.
+    ///
+    /// If no client was set yet, a client with the authentication, SSL and
+    /// proxy settings of the configuration is used (see
+    /// [`RattlerConfig::client_builder`]). A client that is set afterwards
+    /// with [`Self::with_client`] replaces it.
+    #[must_use]
+    pub fn with_config(mut self, config: &RattlerConfig) -> Self {
+        self.set_config(config);
+        self
+    }
+
+    /// Applies the settings from a [`RattlerConfig`]: the cache directories,
+    /// the maximum number of concurrent requests and, if the configuration is
+    /// offline, only reading repodata from the cache.
+    pub fn set_config(&mut self, config: &RattlerConfig) -> &mut Self {
+        // Nothing is cached on wasm.
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            match (config.repodata_cache_dir(), config.package_cache_dir()) {
+                (Ok(repodata_cache_dir), Ok(package_cache_dir)) => {
+                    self.cache = Some(repodata_cache_dir);
+                    self.package_cache = Some(PackageCache::new(package_cache_dir));
+                }
+                (Err(e), _) | (_, Err(e)) => {
+                    tracing::warn!("{e}, using the default cache directory");
+                }
+            }
+            if config.offline {
+                self.channel_config.default.cache_action =
+                    crate::fetch::CacheAction::ForceCacheOnly;
+            }
+            if self.client.is_none() {
+                match config.client_builder() {
+                    Ok(builder) => {
+                        self.client =
+                            Some(builder.with(OciMiddleware::default()).build().into_inner());
+                    }
+                    Err(e) => tracing::warn!("{e}, using the default client"),
+                }
+            }
+        }
+        if let Some(max_concurrent_requests) = config.concurrency.downloads {
+            self.max_concurrent_requests = Some(max_concurrent_requests);
+        }
+        self
+    }
+
     /// Finish the construction of the gateway returning a constructed gateway.
     pub fn finish(self) -> Gateway {
-        let client = self
-            .client
-            .unwrap_or_else(|| ClientWithMiddleware::from(Client::new()));
+        let client = self.client.unwrap_or_else(|| {
+            ClientBuilder::new(Client::new())
+                .with(OciMiddleware::default())
+                .build()
+        });
 
         let cache = self.cache.unwrap_or_else(|| {
-            dirs::cache_dir()
-                .unwrap_or_else(|| PathBuf::from("."))
-                .join("rattler/cache")
+            rattler_config::default_repodata_cache_dir()
+                .unwrap_or_else(|_| PathBuf::from(rattler_config::REPODATA_CACHE_DIR))
         });
 
-        let package_cache = self.package_cache.unwrap_or(PackageCache::new(
-            cache.join(rattler_cache::PACKAGE_CACHE_DIR),
-        ));
+        #[cfg(not(target_arch = "wasm32"))]
+        let package_cache = self.package_cache.unwrap_or_else(|| {
+            PackageCache::new(
+                rattler_config::default_package_cache_dir()
+                    .unwrap_or_else(|_| PathBuf::from(rattler_config::PACKAGE_CACHE_DIR)),
+            )
+        });
 
         let max_concurrent_requests = self.max_concurrent_requests.unwrap_or(100);
+        let concurrent_requests_semaphore =
+            Arc::new(tokio::sync::Semaphore::new(max_concurrent_requests));
+        let mut concurrency_limiter =
+            ConcurrencyLimiter::new().with_semaphore(concurrent_requests_semaphore.clone());
+        if let Some(max_concurrent_requests_per_host) = self.max_concurrent_requests_per_host {
+            concurrency_limiter = concurrency_limiter
+                .with_max_concurrent_requests_per_host(max_concurrent_requests_per_host);
+        }
+
         Gateway {
             inner: Arc::new(GatewayInner {
                 subdirs: DashMap::default(),
                 client,
                 channel_config: self.channel_config,
                 cache,
+                #[cfg(not(target_arch = "wasm32"))]
                 package_cache,
-                concurrent_requests_semaphore: Arc::new(tokio::sync::Semaphore::new(
-                    max_concurrent_requests,
-                )),
+                concurrent_requests_semaphore,
+                concurrency_limiter,
             }),
         }
     }
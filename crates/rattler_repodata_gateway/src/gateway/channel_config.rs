@@ -1,3 +1,4 @@
+#[cfg(not(target_arch = "wasm32"))]
 use crate::fetch::CacheAction;
 use rattler_conda_types::Channel;
 use std::collections::HashMap;
@@ -15,8 +16,20 @@ pub struct SourceConfig {
     /// When enabled, the bz2 variant will be used if available (defaults to true)
     pub bz2_enabled: bool,
 
+    /// When enabled, the sharded repodata index (CEP-16) is used if the channel provides one.
+    /// Only the shards of the requested packages are downloaded instead of the entire
+    /// `repodata.json`. If the channel does not provide a sharded index the regular repodata is
+    /// used (defaults to false, channels hosted on prefix.dev always use sharded repodata)
+    pub sharded_enabled: bool,
+
+    /// When enabled, the `repodata.json` of a local (`file://`) channel is read again if it was
+    /// modified since it was last read. Without this the repodata of a local channel is only read
+    /// once for the lifetime of the gateway (defaults to false)
+    pub watch_local_files: bool,
+
     /// Describes fetching repodata from a channel should interact with any
-    /// caches.
+    /// caches. Not available on wasm where nothing is cached.
+    #[cfg(not(target_arch = "wasm32"))]
     pub cache_action: CacheAction,
 }
 
@@ -26,6 +39,9 @@ impl Default for SourceConfig {
             jlap_enabled: true,
             zstd_enabled: true,
             bz2_enabled: true,
+            sharded_enabled: false,
+            watch_local_files: false,
+            #[cfg(not(target_arch = "wasm32"))]
             cache_action: CacheAction::default(),
         }
     }
@@ -1,7 +1,9 @@
-use crate::fetch;
-use crate::fetch::{FetchRepoDataError, RepoDataNotFoundError};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::fetch::{self, FetchRepoDataError, RepoDataNotFoundError};
+#[cfg(not(target_arch = "wasm32"))]
 use crate::gateway::direct_url_query::DirectUrlQueryError;
 use rattler_conda_types::{Channel, InvalidPackageNameError, MatchSpec};
+use rattler_error::ErrorCode;
 use rattler_redaction::Redact;
 use reqwest_middleware::Error;
 use simple_spawn_blocking::Cancelled;
@@ -21,6 +23,7 @@ pub enum GatewayError {
     #[error(transparent)]
     ReqwestMiddlewareError(anyhow::Error),
 
+    #[cfg(not(target_arch = "wasm32"))]
     #[error(transparent)]
     FetchRepoDataError(#[from] FetchRepoDataError),
 
@@ -36,6 +39,7 @@ pub enum GatewayError {
     #[error("the operation was cancelled")]
     Cancelled,
 
+    #[cfg(not(target_arch = "wasm32"))]
     #[error("the direct url query failed for {0}")]
     DirectUrlQueryError(String, #[source] DirectUrlQueryError),
 
@@ -49,6 +53,28 @@ pub enum GatewayError {
     InvalidPackageName(#[from] InvalidPackageNameError),
 }
 
+impl ErrorCode for GatewayError {
+    fn code(&self) -> &'static str {
+        match self {
+            GatewayError::IoError(..) => "rattler::gateway::io",
+            GatewayError::ReqwestError(_) | GatewayError::ReqwestMiddlewareError(_) => {
+                "rattler::gateway::http"
+            }
+            #[cfg(not(target_arch = "wasm32"))]
+            GatewayError::FetchRepoDataError(err) => err.code(),
+            GatewayError::UnsupportedUrl(_) => "rattler::gateway::unsupported_url",
+            GatewayError::Generic(_) => "rattler::gateway::generic",
+            GatewayError::SubdirNotFoundError(err) => err.code(),
+            GatewayError::Cancelled => "rattler::gateway::cancelled",
+            #[cfg(not(target_arch = "wasm32"))]
+            GatewayError::DirectUrlQueryError(..) => "rattler::gateway::direct_url_query",
+            GatewayError::MatchSpecWithoutName(_) => "rattler::gateway::match_spec_without_name",
+            GatewayError::UrlRecordNameMismatch(..) => "rattler::gateway::url_record_name_mismatch",
+            GatewayError::InvalidPackageName(err) => err.code(),
+        }
+    }
+}
+
 impl From<Cancelled> for GatewayError {
     fn from(_: Cancelled) -> Self {
         GatewayError::Cancelled
@@ -79,6 +105,7 @@ pub enum HttpOrFilesystemError {
     Filesystem(#[from] io::Error),
 }
 
+#[cfg(not(target_arch = "wasm32"))]
 impl From<fetch::RepoDataNotFoundError> for HttpOrFilesystemError {
     fn from(value: RepoDataNotFoundError) -> Self {
         match value {
@@ -102,6 +129,12 @@ pub struct SubdirNotFoundError {
     pub source: HttpOrFilesystemError,
 }
 
+impl ErrorCode for SubdirNotFoundError {
+    fn code(&self) -> &'static str {
+        "rattler::gateway::subdir_not_found"
+    }
+}
+
 impl Display for SubdirNotFoundError {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         write!(
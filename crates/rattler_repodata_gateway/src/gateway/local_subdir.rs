@@ -2,17 +2,38 @@ use crate::gateway::error::SubdirNotFoundError;
 use crate::gateway::subdir::SubdirClient;
 use crate::gateway::GatewayError;
 use crate::sparse::SparseRepoData;
+use crate::utils::run_blocking_task;
 use crate::Reporter;
 use rattler_conda_types::{Channel, PackageName, RepoDataRecord};
-use simple_spawn_blocking::tokio::run_blocking_task;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::time::SystemTime;
 
 /// A client that can be used to fetch repodata for a specific subdirectory from a local directory.
 ///
 /// Use the [`LocalSubdirClient::from_directory`] function to create a new instance of this client.
 pub struct LocalSubdirClient {
     sparse: Arc<SparseRepoData>,
+
+    /// The path and the version of the `repodata.json` file that was read, if known.
+    source: Option<(PathBuf, SourceVersion)>,
+}
+
+/// Identifies the contents of a file by its size and modification time.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct SourceVersion {
+    len: u64,
+    modified: Option<SystemTime>,
+}
+
+impl SourceVersion {
+    fn from_path(path: &Path) -> Option<Self> {
+        let metadata = std::fs::metadata(path).ok()?;
+        Some(Self {
+            len: metadata.len(),
+            modified: metadata.modified().ok(),
+        })
+    }
 }
 
 impl LocalSubdirClient {
@@ -23,9 +44,12 @@ impl LocalSubdirClient {
     ) -> Result<Self, GatewayError> {
         let repodata_path = repodata_path.to_path_buf();
         let subdir = subdir.to_string();
-        let sparse = run_blocking_task(move || {
-            SparseRepoData::new(channel.clone(), subdir.clone(), &repodata_path, None).map_err(
-                |err| {
+        let (sparse, source) = run_blocking_task(move || {
+            // Determine the version before reading the file so a concurrent modification is
+            // detected later.
+            let version = SourceVersion::from_path(&repodata_path);
+            let sparse = SparseRepoData::new(channel.clone(), subdir.clone(), &repodata_path, None)
+                .map_err(|err| {
                     if err.kind() == std::io::ErrorKind::NotFound {
                         GatewayError::SubdirNotFoundError(SubdirNotFoundError {
                             channel: channel.clone(),
@@ -35,18 +59,29 @@ impl LocalSubdirClient {
                     } else {
                         GatewayError::IoError("failed to parse repodata.json".to_string(), err)
                     }
-                },
-            )
+                })?;
+            Ok::<_, GatewayError>((sparse, version.map(|version| (repodata_path, version))))
         })
         .await?;
 
         Ok(Self {
             sparse: Arc::new(sparse),
+            source,
         })
     }
+
+    /// Constructs a client from repodata that has already been loaded.
+    #[cfg_attr(not(target_arch = "wasm32"), allow(dead_code))]
+    pub fn from_sparse(sparse: SparseRepoData) -> Self {
+        Self {
+            sparse: Arc::new(sparse),
+            source: None,
+        }
+    }
 }
 
-#[async_trait::async_trait]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait::async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait::async_trait(?Send))]
 impl SubdirClient for LocalSubdirClient {
     async fn fetch_package_records(
         &self,
@@ -72,4 +107,10 @@ impl SubdirClient for LocalSubdirClient {
             .map(std::convert::Into::into)
             .collect()
     }
+
+    fn is_stale(&self) -> bool {
+        self.source
+            .as_ref()
+            .is_some_and(|(path, version)| SourceVersion::from_path(path).as_ref() != Some(version))
+    }
 }
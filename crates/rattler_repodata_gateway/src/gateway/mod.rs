@@ -1,6 +1,7 @@
 mod barrier_cell;
 mod builder;
 mod channel_config;
+#[cfg(not(target_arch = "wasm32"))]
 mod direct_url_query;
 mod error;
 mod local_subdir;
@@ -24,8 +25,9 @@ pub use error::GatewayError;
 use file_url::url_to_path;
 use local_subdir::LocalSubdirClient;
 pub use query::{NamesQuery, RepoDataQuery};
+#[cfg(not(target_arch = "wasm32"))]
 use rattler_cache::package_cache::PackageCache;
-use rattler_conda_types::{Channel, MatchSpec, Platform};
+use rattler_conda_types::{intern, Channel, MatchSpec, Platform};
 pub use repo_data::RepoData;
 use reqwest_middleware::ClientWithMiddleware;
 use subdir::{Subdir, SubdirData};
@@ -33,7 +35,9 @@ use tokio::sync::broadcast;
 use tracing::instrument;
 use url::Url;
 
-use crate::{fetch::FetchRepoDataError, gateway::error::SubdirNotFoundError, Reporter};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::{fetch::FetchRepoDataError, gateway::error::SubdirNotFoundError};
+use crate::{ConcurrencyLimiter, Reporter};
 
 /// Central access point for high level queries about
 /// [`rattler_conda_types::RepoDataRecord`]s from different channels.
@@ -136,15 +140,47 @@ impl Gateway {
         )
     }
 
+    /// Fetches all records from the given channels and platforms.
+    ///
+    /// All subdirectories are fetched concurrently, the result contains a
+    /// [`RepoData`] for every combination of channel and platform in the same
+    /// order as [`Self::query`]. Prefer [`Self::query`] if only the records
+    /// of specific packages are required, this avoids downloading records of
+    /// packages that are never used.
+    pub async fn fetch_all<AsChannel, ChannelIter, PlatformIter>(
+        &self,
+        channels: ChannelIter,
+        platforms: PlatformIter,
+    ) -> Result<Vec<RepoData>, GatewayError>
+    where
+        AsChannel: Into<Channel>,
+        ChannelIter: IntoIterator<Item = AsChannel>,
+        PlatformIter: IntoIterator<Item = Platform>,
+        <PlatformIter as IntoIterator>::IntoIter: Clone,
+    {
+        let channels = channels.into_iter().map(Into::into).collect::<Vec<_>>();
+        let platforms = platforms.into_iter().collect::<Vec<_>>();
+        let names = self
+            .names(channels.clone(), platforms.clone())
+            .execute()
+            .await?;
+        self.query(channels, platforms, names.into_iter().map(MatchSpec::from))
+            .execute()
+            .await
+    }
+
     /// Clears any in-memory cache for the given channel.
     ///
     /// Any subsequent query will re-fetch any required data from the source.
     ///
-    /// This method does not clear any on-disk cache.
+    /// This method does not clear any on-disk cache. Package names that are
+    /// no longer referenced afterwards are released from the global
+    /// [`rattler_conda_types::intern`] pool.
     pub fn clear_repodata_cache(&self, channel: &Channel, subdirs: SubdirSelection) {
         self.inner.subdirs.retain(|key, _| {
             key.0.base_url() != channel.base_url() || !subdirs.contains(key.1.as_str())
         });
+        intern::global().collect_garbage();
     }
 }
 
@@ -162,10 +198,24 @@ struct GatewayInner {
     cache: PathBuf,
 
     /// The package cache, stored to reuse memory cache
+    #[cfg(not(target_arch = "wasm32"))]
     package_cache: PackageCache,
 
     /// A semaphore to limit the number of concurrent requests.
     concurrent_requests_semaphore: Arc<tokio::sync::Semaphore>,
+
+    /// Limits the number of concurrent repodata fetches, shares the limit of
+    /// `concurrent_requests_semaphore`.
+    concurrency_limiter: ConcurrencyLimiter,
+}
+
+impl Drop for GatewayInner {
+    fn drop(&mut self) {
+        // Drop the cached repodata first so the package names that were only
+        // used by it can be released from the global pool.
+        self.subdirs.clear();
+        intern::global().collect_garbage();
+    }
 }
 
 impl GatewayInner {
@@ -177,7 +227,11 @@ impl GatewayInner {
     /// coalesced, and they will all receive the same subdir. If an error
     /// occurs while creating the subdir all waiting tasks will also return an
     /// error.
-    #[instrument(skip(self, reporter), err)]
+    #[instrument(
+        skip_all,
+        fields(channel = %channel.canonical_name(), platform = %platform),
+        err
+    )]
     async fn get_or_create_subdir(
         &self,
         channel: &Channel,
@@ -238,7 +292,25 @@ impl GatewayInner {
                             sender
                         }
                     }
-                    PendingOrFetched::Fetched(records) => return Ok(records.clone()),
+                    PendingOrFetched::Fetched(records) => {
+                        if !(self.channel_config.get(channel).watch_local_files
+                            && records.is_stale())
+                        {
+                            return Ok(records.clone());
+                        }
+
+                        // The local repodata was modified since it was read, read it again.
+                        tracing::debug!(
+                            "repodata of {}/{} changed, reading it again",
+                            channel.canonical_name(),
+                            platform
+                        );
+                        let (sender, _) = broadcast::channel(1);
+                        let sender = Arc::new(sender);
+                        entry.insert(PendingOrFetched::Pending(Arc::downgrade(&sender)));
+
+                        sender
+                    }
                 }
             }
         };
@@ -272,6 +344,15 @@ impl GatewayInner {
         reporter: Option<Arc<dyn Reporter>>,
     ) -> Result<Subdir, GatewayError> {
         let url = channel.platform_url(platform);
+
+        // There is no filesystem to read local channels from in the browser.
+        #[cfg(target_arch = "wasm32")]
+        if url.scheme() == "file" {
+            return Err(GatewayError::UnsupportedUrl(
+                "file based channels are not supported on wasm".to_string(),
+            ));
+        }
+
         let subdir_data = if url.scheme() == "file" {
             if let Some(path) = url_to_path(&url) {
                 LocalSubdirClient::from_channel_subdir(
@@ -300,18 +381,47 @@ impl GatewayInner {
         } else if url.scheme() == "http"
             || url.scheme() == "https"
             || url.scheme() == "gcs"
+            || url.scheme() == "s3"
+            || url.scheme() == "az"
             || url.scheme() == "oci"
         {
-            remote_subdir::RemoteSubdirClient::new(
-                channel.clone(),
-                platform,
-                self.client.clone(),
-                self.cache.clone(),
-                self.channel_config.get(channel).clone(),
-                reporter,
-            )
-            .await
-            .map(SubdirData::from_client)
+            let source_config = self.channel_config.get(channel);
+            let sharded = if source_config.sharded_enabled
+                && (url.scheme() == "http" || url.scheme() == "https")
+            {
+                match sharded_subdir::ShardedSubdir::new(
+                    channel.clone(),
+                    platform.to_string(),
+                    self.client.clone(),
+                    self.cache.clone(),
+                    self.concurrent_requests_semaphore.clone(),
+                    reporter.as_deref(),
+                )
+                .await
+                {
+                    // The channel does not provide sharded repodata, fall back to the regular
+                    // repodata.
+                    Err(GatewayError::SubdirNotFoundError(_)) => None,
+                    result => Some(result.map(SubdirData::from_client)),
+                }
+            } else {
+                None
+            };
+
+            match sharded {
+                Some(result) => result,
+                None => remote_subdir::RemoteSubdirClient::new(
+                    channel.clone(),
+                    platform,
+                    self.client.clone(),
+                    self.cache.clone(),
+                    source_config.clone(),
+                    self.concurrency_limiter.clone(),
+                    reporter,
+                )
+                .await
+                .map(SubdirData::from_client),
+            }
         } else {
             return Err(GatewayError::UnsupportedUrl(format!(
                 "'{}' is not a supported scheme",
@@ -331,6 +441,7 @@ impl GatewayInner {
                 );
                 Ok(Subdir::NotFound)
             }
+            #[cfg(not(target_arch = "wasm32"))]
             Err(GatewayError::FetchRepoDataError(FetchRepoDataError::NotFound(err))) => {
                 Err(SubdirNotFoundError {
                     subdir: platform.to_string(),
@@ -344,6 +455,16 @@ impl GatewayInner {
     }
 }
 
+/// A boxed future. On wasm requests are made through the single threaded
+/// browser fetch API, so futures are not `Send` there.
+#[cfg(not(target_arch = "wasm32"))]
+pub type BoxFuture<'a, T> = futures::future::BoxFuture<'a, T>;
+
+/// A boxed future. On wasm requests are made through the single threaded
+/// browser fetch API, so futures are not `Send` there.
+#[cfg(target_arch = "wasm32")]
+pub type BoxFuture<'a, T> = futures::future::LocalBoxFuture<'a, T>;
+
 /// A record that is either pending or has been fetched.
 #[derive(Clone)]
 enum PendingOrFetched<T> {
@@ -369,10 +490,11 @@ mod test {
     use dashmap::DashSet;
     use rattler_cache::{default_cache_dir, package_cache::PackageCache};
     use rattler_conda_types::{
-        Channel, ChannelConfig, MatchSpec, PackageName,
+        Channel, ChannelConfig, MatchSpec, PackageName, PackageRecord,
         ParseStrictness::{Lenient, Strict},
-        Platform, RepoDataRecord,
+        Platform, RepoDataRecord, Shard, ShardedRepodata, ShardedSubdirInfo,
     };
+    use rattler_digest::{Sha256, Sha256Hash};
     use rstest::rstest;
     use url::Url;
 
@@ -416,6 +538,22 @@ mod test {
         assert_eq!(total_records, 45060);
     }
 
+    #[tokio::test]
+    async fn test_fetch_all() {
+        let gateway = Gateway::new();
+
+        let channel = Channel::from_directory(
+            &Path::new(env!("CARGO_MANIFEST_DIR")).join("../../test-data/channels/dummy"),
+        );
+        let records = gateway
+            .fetch_all(vec![channel], vec![Platform::Linux64])
+            .await
+            .unwrap();
+
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].len(), 16);
+    }
+
     #[tokio::test]
     async fn test_remote_gateway() {
         let gateway = Gateway::new();
@@ -436,6 +574,27 @@ mod test {
         assert_eq!(total_records, 45060);
     }
 
+    #[tokio::test]
+    async fn test_plain_reqwest_client() {
+        let gateway = Gateway::builder()
+            .with_client(reqwest::Client::new())
+            .finish();
+
+        let index = remote_conda_forge().await;
+
+        let records = gateway
+            .query(
+                vec![index.channel()],
+                vec![Platform::Linux64, Platform::NoArch],
+                vec![PackageName::from_str("rubin-env").unwrap()].into_iter(),
+            )
+            .await
+            .unwrap();
+
+        let total_records: usize = records.iter().map(RepoData::len).sum();
+        assert!(total_records > 0);
+    }
+
     #[tokio::test]
     async fn test_direct_url_spec_from_gateway() {
         let gateway = Gateway::builder()
@@ -717,6 +876,187 @@ mod test {
         assert_eq!(total_records, 84242);
     }
 
+    /// Writes a channel with a `noarch` subdirectory that contains `foo 1` in the regular
+    /// `repodata.json` and `foo 2` in the sharded repodata. The index refers to the shard of `foo`
+    /// by `shard_hash`, or by the actual hash of the shard if it is `None`.
+    fn write_sharded_channel(dir: &Path, shard_hash: Option<Sha256Hash>) {
+        let subdir = dir.join("noarch");
+        std::fs::create_dir_all(subdir.join("shards")).unwrap();
+        std::fs::write(
+            subdir.join("repodata.json"),
+            r#"{"info": {"subdir": "noarch"}, "packages": {"foo-1-0.tar.bz2": {"name": "foo", "version": "1", "build": "0", "build_number": 0, "subdir": "noarch", "depends": []}}}"#,
+        )
+        .unwrap();
+        std::fs::write(subdir.join("token"), "{}").unwrap();
+
+        let record: PackageRecord = serde_json::from_str(
+            r#"{"name": "foo", "version": "2", "build": "0", "build_number": 0, "subdir": "noarch", "depends": []}"#,
+        )
+        .unwrap();
+        let shard = Shard {
+            packages: [("foo-2-0.tar.bz2".to_string(), record)]
+                .into_iter()
+                .collect(),
+            conda_packages: Default::default(),
+            removed: Default::default(),
+        };
+        let shard_bytes =
+            zstd::encode_all(rmp_serde::to_vec_named(&shard).unwrap().as_slice(), 0).unwrap();
+        let shard_hash = shard_hash
+            .unwrap_or_else(|| rattler_digest::compute_bytes_digest::<Sha256>(&shard_bytes));
+        std::fs::write(
+            subdir.join(format!("shards/{shard_hash:x}.msgpack.zst")),
+            shard_bytes,
+        )
+        .unwrap();
+
+        let index = ShardedRepodata {
+            info: ShardedSubdirInfo {
+                subdir: "noarch".to_string(),
+                base_url: "./".to_string(),
+                shards_base_url: "./shards/".to_string(),
+            },
+            shards: [("foo".to_string(), shard_hash)].into_iter().collect(),
+        };
+        std::fs::write(
+            subdir.join("repodata_shards.msgpack.zst"),
+            zstd::encode_all(rmp_serde::to_vec_named(&index).unwrap().as_slice(), 0).unwrap(),
+        )
+        .unwrap();
+    }
+
+    fn sharded_gateway(cache_dir: &Path, sharded_enabled: bool) -> Gateway {
+        Gateway::builder()
+            .with_cache_dir(cache_dir)
+            .with_channel_config(super::ChannelConfig {
+                default: SourceConfig {
+                    sharded_enabled,
+                    ..Default::default()
+                },
+                ..Default::default()
+            })
+            .finish()
+    }
+
+    async fn query_foo_versions(
+        gateway: &Gateway,
+        channel: Channel,
+    ) -> Result<Vec<String>, GatewayError> {
+        let records = gateway
+            .query(
+                vec![channel],
+                vec![Platform::NoArch],
+                vec![PackageName::from_str("foo").unwrap()].into_iter(),
+            )
+            .await?;
+        Ok(records[0]
+            .iter()
+            .map(|record| record.package_record.version.to_string())
+            .collect())
+    }
+
+    #[tokio::test]
+    async fn test_sharded_opt_in() {
+        let channel_dir = tempfile::TempDir::new().unwrap();
+        write_sharded_channel(channel_dir.path(), None);
+        let server = SimpleChannelServer::new(channel_dir.path()).await;
+
+        // Without opting in the regular repodata is used.
+        let cache_dir = tempfile::TempDir::new().unwrap();
+        let gateway = sharded_gateway(cache_dir.path(), false);
+        let versions = query_foo_versions(&gateway, server.channel())
+            .await
+            .unwrap();
+        assert_eq!(versions, ["1"]);
+
+        let cache_dir = tempfile::TempDir::new().unwrap();
+        let gateway = sharded_gateway(cache_dir.path(), true);
+        let versions = query_foo_versions(&gateway, server.channel())
+            .await
+            .unwrap();
+        assert_eq!(versions, ["2"]);
+    }
+
+    #[tokio::test]
+    async fn test_sharded_forbidden_falls_back() {
+        let channel_dir = tempfile::TempDir::new().unwrap();
+        write_sharded_channel(channel_dir.path(), None);
+        let server = SimpleChannelServer::with_forbidden_paths(
+            channel_dir.path(),
+            &["/noarch/token", "/noarch/repodata_shards.msgpack.zst"],
+        )
+        .await;
+
+        let cache_dir = tempfile::TempDir::new().unwrap();
+        let gateway = sharded_gateway(cache_dir.path(), true);
+        let versions = query_foo_versions(&gateway, server.channel())
+            .await
+            .unwrap();
+        assert_eq!(versions, ["1"]);
+    }
+
+    #[tokio::test]
+    async fn test_sharded_hash_mismatch() {
+        let channel_dir = tempfile::TempDir::new().unwrap();
+        write_sharded_channel(channel_dir.path(), Some(Sha256Hash::default()));
+        let server = SimpleChannelServer::new(channel_dir.path()).await;
+
+        let cache_dir = tempfile::TempDir::new().unwrap();
+        let gateway = sharded_gateway(cache_dir.path(), true);
+        let err = query_foo_versions(&gateway, server.channel())
+            .await
+            .unwrap_err();
+        assert_matches!(err, GatewayError::Generic(msg) if msg.contains("does not match the index"));
+    }
+
+    #[tokio::test]
+    async fn test_watch_local_files() {
+        fn write_repodata(dir: &Path, versions: &[&str]) {
+            let packages = versions
+                .iter()
+                .map(|version| {
+                    format!(
+                        r#""foo-{version}-0.tar.bz2": {{"name": "foo", "version": "{version}", "build": "0", "build_number": 0, "subdir": "noarch", "depends": []}}"#
+                    )
+                })
+                .collect::<Vec<_>>()
+                .join(",");
+            std::fs::write(
+                dir.join("noarch/repodata.json"),
+                format!(r#"{{"info": {{"subdir": "noarch"}}, "packages": {{{packages}}}}}"#),
+            )
+            .unwrap();
+        }
+
+        let channel_dir = tempfile::TempDir::new().unwrap();
+        std::fs::create_dir(channel_dir.path().join("noarch")).unwrap();
+        write_repodata(channel_dir.path(), &["1"]);
+        let channel = Channel::from_directory(channel_dir.path());
+
+        let gateway = Gateway::builder()
+            .with_channel_config(super::ChannelConfig {
+                default: SourceConfig {
+                    watch_local_files: true,
+                    ..Default::default()
+                },
+                ..Default::default()
+            })
+            .finish();
+        let query = gateway.query(
+            vec![channel],
+            vec![Platform::NoArch],
+            vec![PackageName::from_str("foo").unwrap()].into_iter(),
+        );
+
+        let records = query.clone().execute().await.unwrap();
+        assert_eq!(records[0].len(), 1);
+
+        // Modifying the repodata should invalidate the subdirectory.
+        write_repodata(channel_dir.path(), &["1", "2"]);
+        let records = query.clone().execute().await.unwrap();
+        assert_eq!(records[0].len(), 2);
+    }
+
     #[tokio::test]
     async fn test_clear_cache() {
         #[derive(Default)]
@@ -4,12 +4,19 @@ use std::{
     sync::Arc,
 };
 
-use futures::{select_biased, stream::FuturesUnordered, FutureExt, StreamExt};
+use futures::{select_biased, stream::FuturesUnordered, StreamExt};
 use itertools::Itertools;
-use rattler_conda_types::{Channel, MatchSpec, Matches, PackageName, Platform};
+use rattler_conda_types::{Channel, MatchSpec, Matches, PackageName, Platform, RepoDataRecord};
+use tracing::{field::Empty, instrument, Span};
 
-use super::{subdir::Subdir, BarrierCell, GatewayError, GatewayInner, RepoData};
-use crate::{gateway::direct_url_query::DirectUrlQuery, Reporter};
+use super::{subdir::Subdir, BarrierCell, BoxFuture, GatewayError, GatewayInner, RepoData};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::gateway::direct_url_query::DirectUrlQuery;
+use crate::Reporter;
+
+/// The records fetched for a set of specs and the index of the result they
+/// belong to.
+type PendingRecordsResult = Result<(usize, Vec<MatchSpec>, Arc<[RepoDataRecord]>), GatewayError>;
 
 /// Represents a query to execute with a [`Gateway`].
 ///
@@ -86,6 +93,17 @@ impl RepoDataQuery {
     }
 
     /// Execute the query and return the resulting repodata records.
+    #[instrument(
+        name = "query_repodata",
+        skip_all,
+        fields(
+            channels = self.channels.len(),
+            platforms = self.platforms.len(),
+            specs = self.specs.len(),
+            records = Empty,
+        ),
+        err
+    )]
     pub async fn execute(self) -> Result<Vec<RepoData>, GatewayError> {
         // Collect all the channels and platforms together
         let channels_and_platforms = self
@@ -100,6 +118,11 @@ impl RepoDataQuery {
         let mut direct_url_specs = vec![];
         for spec in self.specs {
             if let Some(url) = spec.url.clone() {
+                if cfg!(target_arch = "wasm32") {
+                    return Err(GatewayError::UnsupportedUrl(format!(
+                        "direct url queries are not supported on wasm: {url}"
+                    )));
+                }
                 let name = spec
                     .name
                     .clone()
@@ -146,40 +169,39 @@ impl RepoDataQuery {
 
         // A list of futures to fetch the records for the pending package names.
         // The main task awaits these futures.
-        let mut pending_records = FuturesUnordered::new();
+        let mut pending_records: FuturesUnordered<BoxFuture<'_, PendingRecordsResult>> =
+            FuturesUnordered::new();
 
         // Push the direct url queries to the pending_records.
+        #[cfg(not(target_arch = "wasm32"))]
         for (spec, url, name) in direct_url_specs {
             let gateway = self.gateway.clone();
-            pending_records.push(
-                async move {
-                    let query = DirectUrlQuery::new(
-                        url.clone(),
-                        gateway.package_cache.clone(),
-                        gateway.client.clone(),
-                        spec.sha256,
-                    );
-
-                    let record = query
-                        .execute()
-                        .await
-                        .map_err(|e| GatewayError::DirectUrlQueryError(url.to_string(), e))?;
-
-                    // Check if record actually has the same name
-                    if let Some(record) = record.first() {
-                        if record.package_record.name != name {
-                            // Using as_source to get the closest to the retrieved input.
-                            return Err(GatewayError::UrlRecordNameMismatch(
-                                record.package_record.name.as_source().to_string(),
-                                name.as_source().to_string(),
-                            ));
-                        }
+            pending_records.push(Box::pin(async move {
+                let query = DirectUrlQuery::new(
+                    url.clone(),
+                    gateway.package_cache.clone(),
+                    gateway.client.clone(),
+                    spec.sha256,
+                );
+
+                let record = query
+                    .execute()
+                    .await
+                    .map_err(|e| GatewayError::DirectUrlQueryError(url.to_string(), e))?;
+
+                // Check if record actually has the same name
+                if let Some(record) = record.first() {
+                    if record.package_record.name != name {
+                        // Using as_source to get the closest to the retrieved input.
+                        return Err(GatewayError::UrlRecordNameMismatch(
+                            record.package_record.name.as_source().to_string(),
+                            name.as_source().to_string(),
+                        ));
                     }
-                    // Push the direct url in the first subdir result for channel priority logic.
-                    Ok((0, vec![spec], record))
                 }
-                .boxed(),
-            );
+                // Push the direct url in the first subdir result for channel priority logic.
+                Ok((0, vec![spec], record))
+            }));
         }
 
         let len = subdirs.len() + direct_url_offset;
@@ -194,22 +216,19 @@ impl RepoDataQuery {
                     let specs = specs.clone();
                     let package_name = package_name.clone();
                     let reporter = self.reporter.clone();
-                    pending_records.push(
-                        async move {
-                            let barrier_cell = subdir.clone();
-                            let subdir = barrier_cell.wait().await;
-                            match subdir.as_ref() {
-                                Subdir::Found(subdir) => subdir
-                                    .get_or_fetch_package_records(&package_name, reporter)
-                                    .await
-                                    .map(|records| (subdir_idx, specs, records)),
-                                Subdir::NotFound => {
-                                    Ok((subdir_idx + direct_url_offset, specs, Arc::from(vec![])))
-                                }
+                    pending_records.push(Box::pin(async move {
+                        let barrier_cell = subdir.clone();
+                        let subdir = barrier_cell.wait().await;
+                        match subdir.as_ref() {
+                            Subdir::Found(subdir) => subdir
+                                .get_or_fetch_package_records(&package_name, reporter)
+                                .await
+                                .map(|records| (subdir_idx, specs, records)),
+                            Subdir::NotFound => {
+                                Ok((subdir_idx + direct_url_offset, specs, Arc::from(vec![])))
                             }
                         }
-                        .boxed(),
-                    );
+                    }));
                 }
             }
 
@@ -267,16 +286,17 @@ impl RepoDataQuery {
             }
         }
 
+        Span::current().record("records", result.iter().map(RepoData::len).sum::<usize>());
         Ok(result)
     }
 }
 
 impl IntoFuture for RepoDataQuery {
     type Output = Result<Vec<RepoData>, GatewayError>;
-    type IntoFuture = futures::future::BoxFuture<'static, Self::Output>;
+    type IntoFuture = BoxFuture<'static, Self::Output>;
 
     fn into_future(self) -> Self::IntoFuture {
-        self.execute().boxed()
+        Box::pin(self.execute())
     }
 }
 
@@ -371,9 +391,9 @@ impl NamesQuery {
 
 impl IntoFuture for NamesQuery {
     type Output = Result<Vec<PackageName>, GatewayError>;
-    type IntoFuture = futures::future::BoxFuture<'static, Self::Output>;
+    type IntoFuture = BoxFuture<'static, Self::Output>;
 
     fn into_future(self) -> Self::IntoFuture {
-        self.execute().boxed()
+        Box::pin(self.execute())
     }
 }
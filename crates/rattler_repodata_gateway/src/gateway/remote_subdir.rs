@@ -1,8 +1,9 @@
 use super::{local_subdir::LocalSubdirClient, GatewayError, SourceConfig};
+#[cfg(not(target_arch = "wasm32"))]
 use crate::fetch::{fetch_repo_data, FetchRepoDataError, FetchRepoDataOptions, Variant};
 use crate::gateway::error::SubdirNotFoundError;
 use crate::gateway::subdir::SubdirClient;
-use crate::Reporter;
+use crate::{ConcurrencyLimiter, Reporter};
 use rattler_conda_types::{Channel, PackageName, Platform, RepoDataRecord};
 use reqwest_middleware::ClientWithMiddleware;
 use std::{path::PathBuf, sync::Arc};
@@ -11,6 +12,7 @@ pub struct RemoteSubdirClient {
     sparse: LocalSubdirClient,
 }
 
+#[cfg(not(target_arch = "wasm32"))]
 impl RemoteSubdirClient {
     pub async fn new(
         channel: Channel,
@@ -18,6 +20,7 @@ impl RemoteSubdirClient {
         client: ClientWithMiddleware,
         cache_dir: PathBuf,
         source_config: SourceConfig,
+        concurrency_limiter: ConcurrencyLimiter,
         reporter: Option<Arc<dyn Reporter>>,
     ) -> Result<Self, GatewayError> {
         let subdir_url = channel.platform_url(platform);
@@ -33,6 +36,8 @@ impl RemoteSubdirClient {
                 jlap_enabled: source_config.jlap_enabled,
                 zstd_enabled: source_config.zstd_enabled,
                 bz2_enabled: source_config.bz2_enabled,
+                concurrency_limiter: Some(concurrency_limiter),
+                ..FetchRepoDataOptions::default()
             },
             reporter,
         )
@@ -60,7 +65,64 @@ impl RemoteSubdirClient {
     }
 }
 
-#[async_trait::async_trait]
+#[cfg(target_arch = "wasm32")]
+impl RemoteSubdirClient {
+    /// Downloads the `repodata.json` into memory. There is no disk cache on
+    /// wasm, the browser's HTTP cache is used instead.
+    pub async fn new(
+        channel: Channel,
+        platform: Platform,
+        client: ClientWithMiddleware,
+        _cache_dir: PathBuf,
+        _source_config: SourceConfig,
+        concurrency_limiter: ConcurrencyLimiter,
+        reporter: Option<Arc<dyn Reporter>>,
+    ) -> Result<Self, GatewayError> {
+        use crate::sparse::SparseRepoData;
+        use crate::{gateway::error::HttpOrFilesystemError, reporter::ResponseReporterExt};
+
+        let repodata_url = channel
+            .platform_url(platform)
+            .join("repodata.json")
+            .expect("invalid repodata url");
+
+        let _permit = concurrency_limiter.acquire(&repodata_url).await;
+        let response = client.get(repodata_url.clone()).send().await?;
+        if response.status() == http::StatusCode::NOT_FOUND {
+            let err = response
+                .error_for_status()
+                .expect_err("404 is an error status");
+            return Err(GatewayError::SubdirNotFoundError(SubdirNotFoundError {
+                channel,
+                subdir: platform.to_string(),
+                source: HttpOrFilesystemError::Http(err),
+            }));
+        }
+
+        let reporter = reporter
+            .as_deref()
+            .map(|r| (r, r.on_download_start(&repodata_url)));
+        let bytes = response
+            .error_for_status()?
+            .bytes_with_progress(reporter)
+            .await?;
+        if let Some((reporter, index)) = reporter {
+            reporter.on_download_complete(&repodata_url, index);
+        }
+
+        let sparse = SparseRepoData::from_bytes(channel, platform.as_str(), bytes.into(), None)
+            .map_err(|err| {
+                GatewayError::IoError("failed to parse repodata.json".to_string(), err.into())
+            })?;
+
+        Ok(Self {
+            sparse: LocalSubdirClient::from_sparse(sparse),
+        })
+    }
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), async_trait::async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait::async_trait(?Send))]
 impl SubdirClient for RemoteSubdirClient {
     async fn fetch_package_records(
         &self,
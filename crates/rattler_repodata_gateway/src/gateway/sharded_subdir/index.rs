@@ -1,5 +1,6 @@
 use std::{path::Path, str::FromStr, sync::Arc, time::SystemTime};
 
+use crate::utils::run_blocking_task;
 use async_fd_lock::{LockWrite, RwLockWriteGuard};
 use bytes::Bytes;
 use futures::TryFutureExt;
@@ -8,7 +9,6 @@ use http_cache_semantics::{AfterResponse, BeforeRequest, CachePolicy, RequestLik
 use reqwest::Response;
 use reqwest_middleware::ClientWithMiddleware;
 use serde::{Deserialize, Serialize};
-use simple_spawn_blocking::tokio::run_blocking_task;
 use tokio::{
     fs::File,
     io::{AsyncRead, AsyncReadExt, AsyncSeekExt, AsyncWriteExt, BufReader, BufWriter},
@@ -1,21 +1,26 @@
-use std::{borrow::Cow, io::Write, path::PathBuf, sync::Arc};
+use std::{borrow::Cow, path::PathBuf, sync::Arc};
 
+use crate::utils::run_blocking_task;
 use http::{header::CACHE_CONTROL, HeaderValue, StatusCode};
 use rattler_conda_types::{Channel, PackageName, RepoDataRecord, Shard, ShardedRepodata};
+use rattler_digest::Sha256;
 use reqwest_middleware::ClientWithMiddleware;
-use simple_spawn_blocking::tokio::run_blocking_task;
 use token::TokenClient;
 use url::Url;
 
 use crate::{
-    fetch::FetchRepoDataError,
     gateway::{error::SubdirNotFoundError, subdir::SubdirClient},
     reporter::ResponseReporterExt,
     GatewayError, Reporter,
 };
 
+#[cfg(not(target_arch = "wasm32"))]
 mod index;
 mod token;
+#[cfg(target_arch = "wasm32")]
+mod wasm_index;
+#[cfg(target_arch = "wasm32")]
+use wasm_index as index;
 
 pub struct ShardedSubdir {
     channel: Channel,
@@ -24,6 +29,7 @@ pub struct ShardedSubdir {
     package_base_url: Url,
     token_client: TokenClient,
     sharded_repodata: ShardedRepodata,
+    #[cfg_attr(target_arch = "wasm32", allow(dead_code))]
     cache_dir: PathBuf,
     concurrent_requests_semaphore: Arc<tokio::sync::Semaphore>,
 }
@@ -60,7 +66,14 @@ impl ShardedSubdir {
         )
         .await
         .map_err(|e| match e {
-            GatewayError::ReqwestError(e) if e.status() == Some(StatusCode::NOT_FOUND) => {
+            // Hosts that do not allow listing their contents (like S3 buckets) respond with a
+            // 403 instead of a 404 for files that do not exist.
+            GatewayError::ReqwestError(e)
+                if matches!(
+                    e.status(),
+                    Some(StatusCode::NOT_FOUND | StatusCode::FORBIDDEN)
+                ) =>
+            {
                 GatewayError::SubdirNotFoundError(SubdirNotFoundError {
                     channel: channel.clone(),
                     subdir,
@@ -92,9 +105,10 @@ impl ShardedSubdir {
 
         // Determine the cache directory and make sure it exists.
         let cache_dir = cache_dir.join("shards-v1");
-        tokio::fs::create_dir_all(&cache_dir)
-            .await
-            .map_err(FetchRepoDataError::IoError)?;
+        #[cfg(not(target_arch = "wasm32"))]
+        tokio::fs::create_dir_all(&cache_dir).await.map_err(|err| {
+            GatewayError::IoError(format!("failed to create '{}'", cache_dir.display()), err)
+        })?;
 
         Ok(Self {
             channel,
@@ -109,7 +123,8 @@ impl ShardedSubdir {
     }
 }
 
-#[async_trait::async_trait]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait::async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait::async_trait(?Send))]
 impl SubdirClient for ShardedSubdir {
     async fn fetch_package_records(
         &self,
@@ -122,9 +137,11 @@ impl SubdirClient for ShardedSubdir {
         };
 
         // Check if we already have the shard in the cache.
+        #[cfg(not(target_arch = "wasm32"))]
         let shard_cache_path = self.cache_dir.join(format!("{shard:x}.msgpack"));
 
         // Read the cached shard
+        #[cfg(not(target_arch = "wasm32"))]
         match tokio::fs::read(&shard_cache_path).await {
             Ok(cached_bytes) => {
                 // Decode the cached shard
@@ -139,7 +156,12 @@ impl SubdirClient for ShardedSubdir {
             Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
                 // The file is missing from the cache, we need to download it.
             }
-            Err(err) => return Err(FetchRepoDataError::IoError(err).into()),
+            Err(err) => {
+                return Err(GatewayError::IoError(
+                    format!("failed to read cached shard {}", shard_cache_path.display()),
+                    err,
+                ))
+            }
         }
 
         // Get the token
@@ -166,13 +188,9 @@ impl SubdirClient for ShardedSubdir {
                 .client
                 .execute(shard_request)
                 .await
-                .and_then(|r| r.error_for_status().map_err(Into::into))
-                .map_err(FetchRepoDataError::from)?;
+                .and_then(|r| r.error_for_status().map_err(Into::into))?;
 
-            let bytes = shard_response
-                .bytes_with_progress(reporter)
-                .await
-                .map_err(FetchRepoDataError::from)?;
+            let bytes = shard_response.bytes_with_progress(reporter).await?;
 
             if let Some((reporter, index)) = reporter {
                 reporter.on_download_complete(&shard_url, index);
@@ -181,12 +199,32 @@ impl SubdirClient for ShardedSubdir {
             bytes
         };
 
+        // Shards are content addressed, make sure we received the shard that the index refers
+        // to before it is stored in the cache.
+        let shard_hash = rattler_digest::compute_bytes_digest::<Sha256>(&shard_bytes);
+        if &shard_hash != shard {
+            return Err(GatewayError::Generic(format!(
+                "the hash of shard {shard_url} does not match the index, expected {shard:x} but got {shard_hash:x}"
+            )));
+        }
+
         let shard_bytes = decode_zst_bytes_async(shard_bytes).await?;
 
+        // There is no disk cache on wasm, simply parse the records.
+        #[cfg(target_arch = "wasm32")]
+        let records = parse_records(
+            shard_bytes,
+            self.channel.canonical_name(),
+            self.package_base_url.clone(),
+        )
+        .await?;
+
         // Create a future to write the cached bytes to disk
+        #[cfg(not(target_arch = "wasm32"))]
         let write_to_cache_fut = write_shard_to_cache(shard_cache_path, shard_bytes.clone());
 
         // Create a future to parse the records from the shard
+        #[cfg(not(target_arch = "wasm32"))]
         let parse_records_fut = parse_records(
             shard_bytes,
             self.channel.canonical_name(),
@@ -194,6 +232,7 @@ impl SubdirClient for ShardedSubdir {
         );
 
         // Await both futures concurrently.
+        #[cfg(not(target_arch = "wasm32"))]
         let (_, records) = tokio::try_join!(write_to_cache_fut, parse_records_fut)?;
 
         Ok(records.into())
@@ -205,10 +244,13 @@ impl SubdirClient for ShardedSubdir {
 }
 
 /// Atomically writes the shard bytes to the cache.
+#[cfg(not(target_arch = "wasm32"))]
 async fn write_shard_to_cache(
     shard_cache_path: PathBuf,
     shard_bytes: Vec<u8>,
 ) -> Result<(), GatewayError> {
+    use std::io::Write;
+
     run_blocking_task(move || {
         let shard_cache_parent_path = shard_cache_path
             .parent()
@@ -279,7 +321,7 @@ async fn parse_records<R: AsRef<[u8]> + Send + 'static>(
         // map_err(std::io::Error::from)?;
         let shard = rmp_serde::from_slice::<Shard>(bytes.as_ref())
             .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e.to_string()))
-            .map_err(FetchRepoDataError::IoError)?;
+            .map_err(|e| GatewayError::IoError("failed to parse shard".to_string(), e))?;
         let packages =
             itertools::chain(shard.packages.into_iter(), shard.conda_packages.into_iter())
                 .filter(|(name, _record)| !shard.removed.contains(name));
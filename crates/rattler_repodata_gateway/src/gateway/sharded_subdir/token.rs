@@ -1,6 +1,6 @@
 use crate::reporter::ResponseReporterExt;
 use crate::Reporter;
-use crate::{fetch::FetchRepoDataError, gateway::PendingOrFetched, GatewayError};
+use crate::{gateway::PendingOrFetched, GatewayError};
 use chrono::{DateTime, TimeDelta, Utc};
 use http::header::CACHE_CONTROL;
 use http::HeaderValue;
@@ -105,7 +105,6 @@ impl TokenClient {
             let bytes = response
                 .bytes_with_progress(reporter)
                 .await
-                .map_err(FetchRepoDataError::from)
                 .map_err(GatewayError::from)?;
 
             if let Some((reporter, index)) = reporter {
@@ -0,0 +1,63 @@
+//! Fetches the shard index on wasm. There is no filesystem to cache the index
+//! on, the browser's HTTP cache is used instead.
+
+use std::{path::Path, sync::Arc};
+
+use reqwest_middleware::ClientWithMiddleware;
+use url::Url;
+
+use super::{token::TokenClient, ShardedRepodata};
+use crate::{reporter::ResponseReporterExt, GatewayError, Reporter};
+
+const REPODATA_SHARDS_FILENAME: &str = "repodata_shards.msgpack.zst";
+
+// Fetches the shard index from the url.
+pub async fn fetch_index(
+    client: ClientWithMiddleware,
+    channel_base_url: &Url,
+    token_client: &TokenClient,
+    _cache_dir: &Path,
+    concurrent_requests_semaphore: Arc<tokio::sync::Semaphore>,
+    reporter: Option<&dyn Reporter>,
+) -> Result<ShardedRepodata, GatewayError> {
+    // Get the token from the token client
+    let token = token_client.get_token(reporter).await?;
+
+    // Determine the actual URL to use for the request
+    let shards_url = token
+        .shard_base_url
+        .as_ref()
+        .unwrap_or(channel_base_url)
+        .join(REPODATA_SHARDS_FILENAME)
+        .expect("invalid shard base url");
+
+    // Construct the actual request that we will send
+    let mut request = client
+        .get(shards_url.clone())
+        .build()
+        .expect("failed to build request for shard index");
+    token.add_to_headers(request.headers_mut());
+
+    // Acquire a permit to do a request
+    let _request_permit = concurrent_requests_semaphore.acquire().await;
+
+    let reporter = reporter.map(|r| (r, r.on_download_start(&shards_url)));
+    let bytes = client
+        .execute(request)
+        .await?
+        .error_for_status()?
+        .bytes_with_progress(reporter)
+        .await?;
+
+    if let Some((reporter, index)) = reporter {
+        reporter.on_download_complete(&shards_url, index);
+    }
+
+    // Decompress and parse the bytes
+    let decoded_bytes = super::decode_zst_bytes_async(bytes).await?;
+    rmp_serde::from_slice(&decoded_bytes)
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e.to_string()))
+        .map_err(|e| {
+            GatewayError::IoError(format!("failed to parse shard index from {shards_url}"), e)
+        })
+}
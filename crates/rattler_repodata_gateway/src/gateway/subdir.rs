@@ -5,7 +5,9 @@ use dashmap::mapref::entry::Entry;
 use dashmap::DashMap;
 use rattler_conda_types::{PackageName, RepoDataRecord};
 use std::sync::Arc;
-use tokio::{sync::broadcast, task::JoinError};
+use tokio::sync::broadcast;
+#[cfg(not(target_arch = "wasm32"))]
+use tokio::task::JoinError;
 
 pub enum Subdir {
     /// The subdirectory is missing from the channel, it is considered empty.
@@ -23,6 +25,14 @@ impl Subdir {
             Subdir::NotFound => None,
         }
     }
+
+    /// Returns true if the source of the subdirectory changed since it was read.
+    pub fn is_stale(&self) -> bool {
+        match self {
+            Subdir::Found(subdir) => subdir.client.is_stale(),
+            Subdir::NotFound => false,
+        }
+    }
 }
 
 /// Fetches and caches repodata records by package name for a specific subdirectory of a channel.
@@ -112,6 +122,7 @@ impl SubdirData {
         //
         // Let's start by fetching the records. If an error occurs we immediately return the error.
         // This will drop the sender and all other waiting tasks will receive an error.
+        #[cfg(not(target_arch = "wasm32"))]
         let records = match tokio::spawn({
             let client = self.client.clone();
             let name = name.clone();
@@ -135,6 +146,13 @@ impl SubdirData {
             }
         };
 
+        // On wasm there is no multithreaded runtime to spawn the task on.
+        #[cfg(target_arch = "wasm32")]
+        let records = self
+            .client
+            .fetch_package_records(name, reporter.as_deref())
+            .await?;
+
         // Store the fetched files in the entry.
         self.records
             .insert(name.clone(), PendingOrFetched::Fetched(records.clone()));
@@ -152,7 +170,8 @@ impl SubdirData {
 }
 
 /// A client that can be used to fetch repodata for a specific subdirectory.
-#[async_trait::async_trait]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait::async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait::async_trait(?Send))]
 pub trait SubdirClient: Send + Sync {
     /// Fetches all repodata records for the package with the given name in a channel subdirectory.
     async fn fetch_package_records(
@@ -163,4 +182,10 @@ pub trait SubdirClient: Send + Sync {
 
     /// Returns the names of all packages in the subdirectory.
     fn package_names(&self) -> Vec<String>;
+
+    /// Returns true if the source of the repodata changed since the client was created, in which
+    /// case a new client should be created to observe the changes.
+    fn is_stale(&self) -> bool {
+        false
+    }
 }
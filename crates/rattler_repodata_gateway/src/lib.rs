@@ -7,6 +7,15 @@
 //! In the future this crate will also provide more high-level functionality to query information
 //! about specific packages from different sources.
 //!
+//! # WebAssembly
+//! The crate can be compiled for `wasm32-unknown-unknown`. On that target requests are made
+//! through the browser's fetch API and nothing is cached on disk. The [`fetch`] module, local
+//! `file://` channels and direct URL queries are not available.
+//!
+//! # Blocking API
+//! Code that is not async can use the [`blocking`] module (requires the `blocking` feature). It
+//! mirrors the async API but manages its own runtime.
+//!
 //! # Install
 //! Add the following to your *Cargo.toml*:
 //!
@@ -60,11 +69,18 @@
 //! }
 //! ```
 
+#[cfg(all(feature = "blocking", not(target_arch = "wasm32")))]
+pub mod blocking;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod cache;
+mod concurrency;
+#[cfg(not(target_arch = "wasm32"))]
 pub mod fetch;
 mod reporter;
 #[cfg(feature = "sparse")]
 pub mod sparse;
 mod utils;
+pub use concurrency::ConcurrencyLimiter;
 pub use reporter::Reporter;
 
 #[cfg(feature = "gateway")]
@@ -32,6 +32,67 @@ pub trait Reporter: Send + Sync {
     /// The `index` parameter is the index returned by `on_download_start`.
     fn on_download_complete(&self, _url: &Url, _index: usize) {}
 
+    /// Called when waiting for the lock on the repodata cache of `url` started.
+    ///
+    /// Returns an index that can be used to identify the lock in subsequent calls.
+    fn on_cache_lock_start(&self, _url: &Url) -> usize {
+        0
+    }
+
+    /// Called when the lock on the repodata cache is held by another process and we have to wait
+    /// for it to be released. This is called at most once per lock.
+    ///
+    /// The `index` parameter is the index returned by `on_cache_lock_start`.
+    fn on_cache_lock_contended(&self, _index: usize) {}
+
+    /// Called when the lock on the repodata cache has been acquired.
+    ///
+    /// The `index` parameter is the index returned by `on_cache_lock_start`.
+    fn on_cache_lock_acquired(&self, _index: usize) {}
+
+    /// Called when the server at `url` throttles our requests, i.e. it responds with
+    /// `429 Too Many Requests` or `503 Service Unavailable` and asks us to retry later. The request
+    /// is retried after `retry_after`.
+    fn on_throttled(&self, _url: &Url, _retry_after: std::time::Duration) {}
+
+    /// Called when checking which variants (e.g. `.zst` or `.bz2`) of the repodata in the
+    /// subdirectory at `url` are available started. This may involve `HEAD` requests.
+    ///
+    /// Returns an index that can be used to identify the check in subsequent calls.
+    fn on_variant_check_start(&self, _url: &Url) -> usize {
+        0
+    }
+
+    /// Called when the availability of the variants has been determined.
+    ///
+    /// The `index` parameter is the index returned by `on_variant_check_start`.
+    fn on_variant_check_completed(&self, _index: usize) {}
+
+    /// Called when decoding (and decompressing) the repodata from `url` started. For remote
+    /// repodata the decoding happens while the file is downloaded.
+    ///
+    /// Returns an index that can be used to identify the decoding in subsequent calls.
+    fn on_decode_start(&self, _url: &Url) -> usize {
+        0
+    }
+
+    /// Called when decoding the repodata completed.
+    ///
+    /// The `index` parameter is the index returned by `on_decode_start`.
+    fn on_decode_completed(&self, _index: usize) {}
+
+    /// Called when writing the repodata from `url` and its cache state to the cache started.
+    ///
+    /// Returns an index that can be used to identify the write in subsequent calls.
+    fn on_cache_write_start(&self, _url: &Url) -> usize {
+        0
+    }
+
+    /// Called when the repodata and its cache state have been written to the cache.
+    ///
+    /// The `index` parameter is the index returned by `on_cache_write_start`.
+    fn on_cache_write_completed(&self, _index: usize) {}
+
     /// Called when starting to apply JLAP to existing repodata.
     ///
     /// This function should return a unique index that can be used to
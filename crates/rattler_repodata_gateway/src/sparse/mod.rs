@@ -14,8 +14,10 @@ use bytes::Bytes;
 use futures::{stream, StreamExt, TryFutureExt, TryStreamExt};
 use itertools::Itertools;
 use rattler_conda_types::{
-    compute_package_url, Channel, ChannelInfo, PackageName, PackageRecord, RepoDataRecord,
+    compute_package_url, Channel, ChannelInfo, LoadRecords, PackageName, PackageRecord,
+    RepoDataRecord,
 };
+use rattler_error::ErrorCode;
 use serde::{
     de::{Error, MapAccess, Visitor},
     Deserialize, Deserializer,
@@ -258,6 +260,21 @@ impl SparseRepoData {
     }
 }
 
+impl LoadRecords for SparseRepoData {
+    type Error = io::Error;
+
+    fn load_records_for_names<'a>(
+        &self,
+        package_names: impl IntoIterator<Item = &'a PackageName>,
+    ) -> io::Result<Vec<RepoDataRecord>> {
+        let mut records = Vec::new();
+        for package_name in package_names.into_iter().unique() {
+            records.append(&mut self.load_records(package_name)?);
+        }
+        Ok(records)
+    }
+}
+
 /// A serde compatible struct that only sparsely parses a repodata.json file.
 #[derive(Deserialize)]
 struct LazyRepoData<'i> {
@@ -456,6 +473,14 @@ pub enum PackageFilenameError {
     NotEnoughDashes(String),
 }
 
+impl ErrorCode for PackageFilenameError {
+    fn code(&self) -> &'static str {
+        match self {
+            PackageFilenameError::NotEnoughDashes(_) => "rattler::sparse::not_enough_dashes",
+        }
+    }
+}
+
 impl<'de> TryFrom<&'de str> for PackageFilename<'de> {
     type Error = PackageFilenameError;
 
@@ -477,7 +502,9 @@ mod test {
 
     use bytes::Bytes;
     use itertools::Itertools;
-    use rattler_conda_types::{Channel, ChannelConfig, PackageName, RepoData, RepoDataRecord};
+    use rattler_conda_types::{
+        Channel, ChannelConfig, LoadRecords, PackageName, RepoData, RepoDataRecord,
+    };
     use rstest::rstest;
 
     use super::{load_repo_data_recursively, PackageFilename, SparseRepoData};
@@ -552,6 +579,39 @@ mod test {
         .unwrap()
     }
 
+    #[test]
+    fn test_load_records_by_names() {
+        let channel_config = ChannelConfig::default_with_root_dir(std::env::current_dir().unwrap());
+        let sparse = SparseRepoData::new(
+            Channel::from_str("dummy", &channel_config).unwrap(),
+            "linux-64",
+            test_dir().join("channels/dummy/linux-64/repodata.json"),
+            None,
+        )
+        .unwrap();
+
+        let names = ["foo", "bors", "foo", "does-not-exist"]
+            .map(|name| PackageName::try_from(name).unwrap());
+        let records = sparse.load_records_for_names(&names).unwrap();
+        let file_names = records
+            .iter()
+            .map(|record| record.file_name.as_str())
+            .sorted()
+            .collect_vec();
+        assert_eq!(
+            file_names,
+            [
+                "bors-1.0-bla_1.tar.bz2",
+                "bors-1.1-bla_1.tar.bz2",
+                "bors-1.2.1-bla_1.tar.bz2",
+                "bors-2.0-bla_1.tar.bz2",
+                "bors-2.1-bla_1.tar.bz2",
+                "foo-3.0.2-py36h1af98f8_1.tar.bz2",
+                "foo-4.0.2-py36h1af98f8_2.tar.bz2",
+            ]
+        );
+    }
+
     #[tokio::test]
     async fn test_empty_sparse_load() {
         let sparse_empty_data = load_sparse(Vec::<String>::new()).await;
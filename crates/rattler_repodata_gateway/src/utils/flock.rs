@@ -30,6 +30,7 @@ use std::fs::{File, OpenOptions};
 use std::io;
 use std::io::{Read, Seek, SeekFrom, Write};
 use std::path::{Path, PathBuf};
+use std::time::Duration;
 
 use anyhow::Context as _;
 use sys::{
@@ -94,6 +95,86 @@ impl LockedFile {
         )
     }
 
+    /// Like [`LockedFile::open_rw`] but returns `None` instead of blocking if
+    /// the lock is currently held by someone else.
+    pub fn try_open_rw<P>(path: P) -> anyhow::Result<Option<LockedFile>>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        let f = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)
+            .with_context(|| format!("failed to open: {}", path.display()))?;
+        if !try_acquire(path, &|| try_lock_exclusive(&f))? {
+            return Ok(None);
+        }
+        Ok(Some(LockedFile {
+            f: Some(f),
+            path: path.to_owned(),
+            state: State::Exclusive,
+        }))
+    }
+
+    /// Asynchronously opens exclusive access to a file, like
+    /// [`LockedFile::open_rw`].
+    ///
+    /// Instead of blocking a thread until the lock is released, the lock is
+    /// polled with an increasing interval. If the lock is held by someone else
+    /// `on_wait` is called once, which can be used to tell the user that we
+    /// are waiting for another process. Returns `None` if the lock could not be
+    /// acquired within `timeout`.
+    ///
+    /// Dropping the returned future stops waiting for the lock.
+    pub async fn open_rw_async<P>(
+        path: P,
+        timeout: Option<Duration>,
+        on_wait: impl FnOnce(),
+    ) -> anyhow::Result<Option<LockedFile>>
+    where
+        P: Into<PathBuf>,
+    {
+        const MIN_POLL_INTERVAL: Duration = Duration::from_millis(10);
+        const MAX_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+        let path = path.into();
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent)
+                .await
+                .with_context(|| format!("failed to create directory: {}", parent.display()))?;
+        }
+
+        let deadline = timeout.map(|timeout| tokio::time::Instant::now() + timeout);
+        let mut on_wait = Some(on_wait);
+        let mut interval = MIN_POLL_INTERVAL;
+        loop {
+            let lock_path = path.clone();
+            if let Some(lock) =
+                tokio::task::spawn_blocking(move || Self::try_open_rw(lock_path)).await??
+            {
+                return Ok(Some(lock));
+            }
+
+            if let Some(on_wait) = on_wait.take() {
+                tracing::info!("waiting for file lock on {}", path.display());
+                on_wait();
+            }
+
+            let mut wake_up = tokio::time::Instant::now() + interval;
+            if let Some(deadline) = deadline {
+                if tokio::time::Instant::now() >= deadline {
+                    return Ok(None);
+                }
+                wake_up = wake_up.min(deadline);
+            }
+            tokio::time::sleep_until(wake_up).await;
+            interval = (interval * 2).min(MAX_POLL_INTERVAL);
+        }
+    }
+
     /// Opens shared access to a file, returning the locked version of a file.
     ///
     /// This function will fail if `path` doesn't already exist, but if it does
@@ -207,6 +288,20 @@ fn acquire(
     lock_try: &dyn Fn() -> io::Result<()>,
     lock_block: &dyn Fn() -> io::Result<()>,
 ) -> anyhow::Result<()> {
+    if try_acquire(path, lock_try)? {
+        return Ok(());
+    }
+
+    tracing::info!("waiting for file lock on {}", msg);
+
+    lock_block().with_context(|| format!("failed to lock file: {}", path.display()))
+}
+
+/// Attempts to acquire the lock on a `path` with `lock_try` without blocking.
+///
+/// Returns `false` if the lock is held by someone else and an error if any
+/// other error happens.
+fn try_acquire(path: &Path, lock_try: &dyn Fn() -> io::Result<()>) -> anyhow::Result<bool> {
     #[cfg(all(target_os = "linux", not(target_env = "musl")))]
     fn is_on_nfs_mount(path: &Path) -> bool {
         use std::ffi::CString;
@@ -242,29 +337,25 @@ fn acquire(
     //
     // [1]: https://github.com/rust-lang/cargo/issues/2615
     if is_on_nfs_mount(path) {
-        return Ok(());
+        return Ok(true);
     }
 
     match lock_try() {
-        Ok(()) => return Ok(()),
+        Ok(()) => Ok(true),
 
         // In addition to ignoring NFS which is commonly not working we also
         // just ignore locking on filesystems that look like they don't
         // implement file locking.
-        Err(e) if error_unsupported(&e) => return Ok(()),
+        Err(e) if error_unsupported(&e) => Ok(true),
+
+        Err(e) if error_contended(&e) => Ok(false),
 
         Err(e) => {
-            if !error_contended(&e) {
-                let e = anyhow::Error::from(e);
-                let cx = format!("failed to lock file: {}", path.display());
-                return Err(e.context(cx));
-            }
+            let e = anyhow::Error::from(e);
+            let cx = format!("failed to lock file: {}", path.display());
+            Err(e.context(cx))
         }
     }
-
-    tracing::info!("waiting for file lock on {}", msg);
-
-    lock_block().with_context(|| format!("failed to lock file: {}", path.display()))
 }
 
 #[cfg(unix)]
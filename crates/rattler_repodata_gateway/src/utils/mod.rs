@@ -2,18 +2,40 @@ use std::fmt::Write;
 
 use ::url::Url;
 pub use body::BodyStreamExt;
+#[cfg(not(target_arch = "wasm32"))]
 pub use encoding::{AsyncEncoding, Encoding};
+#[cfg(not(target_arch = "wasm32"))]
 pub use flock::LockedFile;
 
+#[cfg(not(target_arch = "wasm32"))]
 mod encoding;
 
 #[cfg(test)]
 pub(crate) mod simple_channel_server;
 
 mod body;
+#[cfg(not(target_arch = "wasm32"))]
 mod flock;
 
+/// Runs a blocking task on a separate thread. On wasm there are no threads to
+/// offload work to, so the task is executed directly.
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) use simple_spawn_blocking::tokio::run_blocking_task;
+
+/// Runs a blocking task on a separate thread. On wasm there are no threads to
+/// offload work to, so the task is executed directly.
+#[cfg(target_arch = "wasm32")]
+pub(crate) async fn run_blocking_task<T, E, F>(f: F) -> Result<T, E>
+where
+    F: FnOnce() -> Result<T, E> + Send + 'static,
+    T: Send + 'static,
+    E: From<simple_spawn_blocking::Cancelled> + Send + 'static,
+{
+    f()
+}
+
 /// Convert a URL to a cache filename
+#[cfg(not(target_arch = "wasm32"))]
 pub(crate) fn url_to_cache_filename(url: &Url) -> String {
     // Start Rant:
     // This function mimics behavior from Mamba which itself mimics this behavior
@@ -42,6 +64,24 @@ pub(crate) fn url_to_cache_filename(url: &Url) -> String {
     result
 }
 
+/// Convert a URL to a cache filename that is unique to the given cache
+/// namespace. Without a namespace this is the same as
+/// [`url_to_cache_filename`].
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) fn url_to_namespaced_cache_filename(url: &Url, namespace: Option<&str>) -> String {
+    let mut result = url_to_cache_filename(url);
+    if let Some(namespace) = namespace {
+        // The namespace is hashed because it may contain characters that are
+        // not allowed in filenames.
+        let hash = rattler_digest::compute_bytes_digest::<rattler_digest::Md5>(namespace);
+        result.push('-');
+        for x in &hash[0..4] {
+            write!(result, "{x:02x}").unwrap();
+        }
+    }
+    result
+}
+
 #[cfg(test)]
 pub(crate) mod test {
     use std::path::{Path, PathBuf};
@@ -49,7 +89,7 @@ pub(crate) mod test {
     use tempfile::NamedTempFile;
     use url::Url;
 
-    use super::url_to_cache_filename;
+    use super::{url_to_cache_filename, url_to_namespaced_cache_filename};
 
     #[test]
     fn test_url_to_cache_filename() {
@@ -59,6 +99,18 @@ pub(crate) mod test {
         );
     }
 
+    #[test]
+    fn test_url_to_namespaced_cache_filename() {
+        let url = Url::parse("http://test.com/1234/").unwrap();
+        assert_eq!(url_to_namespaced_cache_filename(&url, None), "302f0a61");
+
+        let first = url_to_namespaced_cache_filename(&url, Some("first"));
+        let second = url_to_namespaced_cache_filename(&url, Some("second"));
+        assert!(first.starts_with("302f0a61-"));
+        assert_ne!(first, second);
+        assert_eq!(first, url_to_namespaced_cache_filename(&url, Some("first")));
+    }
+
     pub(crate) fn test_dir() -> PathBuf {
         Path::new(env!("CARGO_MANIFEST_DIR")).join("../../test-data")
     }
@@ -1,4 +1,7 @@
-use axum::routing::get_service;
+use axum::{
+    http::StatusCode,
+    routing::{get, get_service},
+};
 use rattler_conda_types::Channel;
 use std::{future::IntoFuture, net::SocketAddr, path::Path};
 use tokio::sync::oneshot;
@@ -33,6 +36,25 @@ impl SimpleChannelServer {
         // Create a router that will serve the static files from the channel.
         let app = axum::Router::new().fallback_service(service);
 
+        Self::serve(app).await
+    }
+
+    /// Same as [`Self::new`] but responds with `403 Forbidden` for the given paths, like S3
+    /// buckets do for files that do not exist.
+    #[allow(dead_code)]
+    pub async fn with_forbidden_paths(path: impl AsRef<Path>, forbidden: &[&str]) -> Self {
+        let service = get_service(ServeDir::new(path).precompressed_gzip());
+        let app = forbidden
+            .iter()
+            .fold(axum::Router::new(), |app, path| {
+                app.route(path, get(|| async { StatusCode::FORBIDDEN }))
+            })
+            .fallback_service(service);
+
+        Self::serve(app).await
+    }
+
+    async fn serve(app: axum::Router) -> Self {
         // Construct the server that will listen on localhost but with a *random port*. The random
         // port is very important because it enables creating multiple instances at the same time.
         // We need this to be able to run tests in parallel.
@@ -4,7 +4,7 @@
 //! environments.
 
 use std::{
-    collections::HashMap,
+    collections::{BTreeSet, HashMap},
     ffi::OsStr,
     fs,
     path::{Path, PathBuf},
@@ -13,6 +13,7 @@ use std::{
 
 use indexmap::IndexMap;
 use rattler_conda_types::Platform;
+use rattler_error::ErrorCode;
 
 use crate::shell::{Shell, ShellScript};
 
@@ -74,10 +75,13 @@ pub struct Activator<T: Shell + 'static> {
     /// Paths that need to be added to the PATH environment variable
     pub paths: Vec<PathBuf>,
 
-    /// A list of scripts to run when activating the environment
+    /// A list of scripts to run when activating the environment, sorted by
+    /// their filename.
     pub activation_scripts: Vec<PathBuf>,
 
-    /// A list of scripts to run when deactivating the environment
+    /// A list of scripts to run when deactivating the environment, sorted by
+    /// their filename in reverse order so that the effects of the activation
+    /// scripts are undone in the opposite order in which they were applied.
     pub deactivation_scripts: Vec<PathBuf>,
 
     /// A list of environment variables to set when activating the environment
@@ -85,6 +89,11 @@ pub struct Activator<T: Shell + 'static> {
 
     /// The platform for which to generate the Activator
     pub platform: Platform,
+
+    /// The modifier (e.g. `(env-name) `) that is prepended to the prompt of
+    /// the shell when activating the environment. If this is `None` the
+    /// prompt is not modified, similar to setting `changeps1: false` in conda.
+    pub prompt_modifier: Option<String>,
 }
 
 /// Collect all script files that match a certain shell type from a given path.
@@ -171,6 +180,29 @@ pub enum ActivationError {
     },
 }
 
+impl ErrorCode for ActivationError {
+    fn code(&self) -> &'static str {
+        match self {
+            ActivationError::IoError(_) => "rattler::activation::io",
+            ActivationError::InvalidEnvVarFileJson(..) => {
+                "rattler::activation::invalid_env_var_file_json"
+            }
+            ActivationError::InvalidEnvVarFileJsonNoObject { .. } => {
+                "rattler::activation::invalid_env_var_file_json_no_object"
+            }
+            ActivationError::InvalidEnvVarFileStateFile { .. } => {
+                "rattler::activation::invalid_env_var_state_file"
+            }
+            ActivationError::FailedToWriteActivationScript(_) => {
+                "rattler::activation::failed_to_write_activation_script"
+            }
+            ActivationError::FailedToRunActivationScript { .. } => {
+                "rattler::activation::failed_to_run_activation_script"
+            }
+        }
+    }
+}
+
 /// Collect all environment variables that are set in a conda environment.
 /// The environment variables are collected from the `state` file and the
 /// `env_vars.d` directory in the given prefix and are returned as a ordered
@@ -302,6 +334,84 @@ pub struct ActivationResult<T: Shell + 'static> {
     pub path: Vec<PathBuf>,
 }
 
+/// The changes to the environment variables that are the result of running an
+/// activation script. See [`Activator::run_activation_diff`].
+///
+/// This can be used to apply the activation to the environment of the current
+/// process (or a process that is about to be spawned) without having to run
+/// the activation script for every command.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct EnvironmentDiff {
+    /// Environment variables that were not present before activation, with
+    /// their new value.
+    pub set: HashMap<String, String>,
+
+    /// Environment variables whose value was changed by the activation, with
+    /// their new value.
+    pub modified: HashMap<String, String>,
+
+    /// Environment variables that were removed by the activation.
+    pub unset: BTreeSet<String>,
+}
+
+impl EnvironmentDiff {
+    /// Computes the difference between the environment before and after
+    /// activation.
+    pub fn between<'a>(
+        before: &HashMap<&'a str, &'a str>,
+        after: &HashMap<&'a str, &'a str>,
+    ) -> Self {
+        let mut diff = Self::default();
+        for (&key, &value) in after {
+            // this happens on Windows for some reason
+            // @SET "=C:=C:\Users\robostack\Programs\pixi"
+            // @SET "=ExitCode=00000000"
+            if key.is_empty() {
+                continue;
+            }
+            match before.get(key) {
+                None => {
+                    diff.set.insert(key.to_owned(), value.to_owned());
+                }
+                Some(&previous) if previous != value => {
+                    diff.modified.insert(key.to_owned(), value.to_owned());
+                }
+                Some(_) => {}
+            }
+        }
+        diff.unset = before
+            .keys()
+            .filter(|key| !key.is_empty() && !after.contains_key(*key))
+            .map(|&key| key.to_owned())
+            .collect();
+        diff
+    }
+
+    /// Returns true if the activation did not change any environment variable.
+    pub fn is_empty(&self) -> bool {
+        self.set.is_empty() && self.modified.is_empty() && self.unset.is_empty()
+    }
+
+    /// Returns all environment variables that were either set or modified by
+    /// the activation with their new value.
+    pub fn changed(&self) -> impl Iterator<Item = (&str, &str)> + '_ {
+        self.set
+            .iter()
+            .chain(self.modified.iter())
+            .map(|(key, value)| (key.as_str(), value.as_str()))
+    }
+
+    /// Applies the changes to the given set of environment variables.
+    pub fn apply_to(&self, env: &mut HashMap<String, String>) {
+        for key in &self.unset {
+            env.remove(key);
+        }
+        for (key, value) in self.changed() {
+            env.insert(key.to_owned(), value.to_owned());
+        }
+    }
+}
+
 impl<T: Shell + Clone> Activator<T> {
     /// Create a new activator for the given conda environment.
     ///
@@ -334,8 +444,9 @@ impl<T: Shell + Clone> Activator<T> {
     ) -> Result<Activator<T>, ActivationError> {
         let activation_scripts = collect_scripts(&path.join("etc/conda/activate.d"), &shell_type)?;
 
-        let deactivation_scripts =
+        let mut deactivation_scripts =
             collect_scripts(&path.join("etc/conda/deactivate.d"), &shell_type)?;
+        deactivation_scripts.reverse();
 
         let env_vars = collect_env_vars(path)?;
 
@@ -349,9 +460,23 @@ impl<T: Shell + Clone> Activator<T> {
             deactivation_scripts,
             env_vars,
             platform,
+            prompt_modifier: None,
         })
     }
 
+    /// Enables prepending `(<env_name>) ` to the prompt of the shell when
+    /// activating the environment.
+    ///
+    /// The prompt is modified in the shells that support it (bash, zsh, fish,
+    /// `PowerShell` and cmd.exe) and the modifier is exported as
+    /// `CONDA_PROMPT_MODIFIER`. [`Self::deactivation`] restores the previous
+    /// prompt and unsets the variable.
+    #[must_use]
+    pub fn with_prompt_modifier(mut self, env_name: &str) -> Self {
+        self.prompt_modifier = Some(format!("({env_name}) "));
+        self
+    }
+
     /// Create an activation script for a given shell and platform. This
     /// returns a tuple of the newly computed PATH variable and the activation
     /// script.
@@ -368,16 +493,14 @@ impl<T: Shell + Clone> Activator<T> {
                 self.shell_type.clone(),
                 self.platform,
             )?;
+            deactivate.write_deactivation(&mut script, &mut path)?;
 
-            for (key, _) in &deactivate.env_vars {
-                script.unset_env_var(key)?;
-            }
-
-            for deactivation_script in &deactivate.deactivation_scripts {
-                script.run_script(deactivation_script)?;
+            // The prompt modifier of the previous environment is replaced below
+            // if this environment modifies the prompt.
+            if self.prompt_modifier.is_none() {
+                script.reset_prompt_modifier()?;
+                script.unset_env_var("CONDA_PROMPT_MODIFIER")?;
             }
-
-            path.retain(|x| !deactivate.paths.contains(x));
         }
 
         // prepend new paths
@@ -389,6 +512,13 @@ impl<T: Shell + Clone> Activator<T> {
         // this point
         script.set_env_var("CONDA_PREFIX", &self.target_prefix.to_string_lossy())?;
 
+        if let Some(prompt_modifier) = &self.prompt_modifier {
+            // The prompt modification may refer to the previous modifier, so it
+            // must be emitted before the variable is updated.
+            script.set_prompt_modifier(prompt_modifier)?;
+            script.set_env_var("CONDA_PROMPT_MODIFIER", prompt_modifier)?;
+        }
+
         for (key, value) in &self.env_vars {
             script.set_env_var(key, value)?;
         }
@@ -400,6 +530,51 @@ impl<T: Shell + Clone> Activator<T> {
         Ok(ActivationResult { script, path })
     }
 
+    /// Create a deactivation script for a given shell and platform. The
+    /// script runs the deactivation scripts of the environment, unsets its
+    /// environment variables and `CONDA_PREFIX`, removes its paths from the
+    /// PATH variable (if `variables.path` is set) and restores the prompt
+    /// from before the activation.
+    pub fn deactivation(
+        &self,
+        variables: ActivationVariables,
+    ) -> Result<ActivationResult<T>, ActivationError> {
+        let mut script = ShellScript::new(self.shell_type.clone(), self.platform);
+
+        let mut path = variables.path.clone().unwrap_or_default();
+        self.write_deactivation(&mut script, &mut path)?;
+        if variables.path.is_some() {
+            script.set_path(path.as_slice(), PathModificationBehavior::Replace)?;
+        }
+
+        script.unset_env_var("CONDA_PREFIX")?;
+        script.reset_prompt_modifier()?;
+        script.unset_env_var("CONDA_PROMPT_MODIFIER")?;
+
+        Ok(ActivationResult { script, path })
+    }
+
+    /// Writes the commands that undo the activation of this environment,
+    /// except for the prompt, and removes its paths from `path`.
+    fn write_deactivation(
+        &self,
+        script: &mut ShellScript<T>,
+        path: &mut Vec<PathBuf>,
+    ) -> Result<(), ActivationError> {
+        // Run the deactivation scripts first, they might still depend on the
+        // environment variables of the environment.
+        for deactivation_script in &self.deactivation_scripts {
+            script.run_script(deactivation_script)?;
+        }
+
+        for (key, _) in &self.env_vars {
+            script.unset_env_var(key)?;
+        }
+
+        path.retain(|x| !self.paths.contains(x));
+        Ok(())
+    }
+
     /// Runs the activation script and returns the environment variables changed
     /// in the environment after running the script.
     ///
@@ -410,6 +585,24 @@ impl<T: Shell + Clone> Activator<T> {
         variables: ActivationVariables,
         environment: Option<HashMap<&OsStr, &OsStr>>,
     ) -> Result<HashMap<String, String>, ActivationError> {
+        let diff = self.run_activation_diff(variables, environment)?;
+        Ok(diff
+            .changed()
+            .map(|(key, value)| (key.to_owned(), value.to_owned()))
+            .collect())
+    }
+
+    /// Runs the activation script in a subshell and returns a structured diff
+    /// of the environment variables that were set, modified or unset by the
+    /// activation.
+    ///
+    /// If the `environment` parameter is not `None`, then it will overwrite the
+    /// parent environment variables when running the activation script.
+    pub fn run_activation_diff(
+        &self,
+        variables: ActivationVariables,
+        environment: Option<HashMap<&OsStr, &OsStr>>,
+    ) -> Result<EnvironmentDiff, ActivationError> {
         let activation_script = self.activation(variables)?.script;
 
         // Create a script that starts by emitting all environment variables, then runs
@@ -469,15 +662,7 @@ impl<T: Shell + Clone> Activator<T> {
         let after_env = self.shell_type.parse_env(after_env);
 
         // Find and return the differences
-        Ok(after_env
-            .into_iter()
-            .filter(|(key, value)| before_env.get(key) != Some(value))
-            // this happens on Windows for some reason
-            // @SET "=C:=C:\Users\robostack\Programs\pixi"
-            // @SET "=ExitCode=00000000"
-            .filter(|(key, _)| !key.is_empty())
-            .map(|(key, value)| (key.to_owned(), value.to_owned()))
-            .collect())
+        Ok(EnvironmentDiff::between(&before_env, &after_env))
     }
 }
 
@@ -522,6 +707,159 @@ mod tests {
         assert_eq!(activator.activation_scripts[2], script3);
     }
 
+    #[test]
+    fn test_collect_scripts_per_shell() {
+        let tdir = TempDir::new("test").unwrap();
+
+        let activate_d = tdir.path().join("etc/conda/activate.d");
+        let deactivate_d = tdir.path().join("etc/conda/deactivate.d");
+        fs::create_dir_all(&activate_d).unwrap();
+        fs::create_dir_all(&deactivate_d).unwrap();
+
+        for name in ["10-b.sh", "00-a.sh", "10-b.bat", "00-a.ps1", "20-c.fish"] {
+            fs::write(activate_d.join(name), "").unwrap();
+            fs::write(deactivate_d.join(name), "").unwrap();
+        }
+
+        let activator = Activator::from_path(tdir.path(), shell::Bash, Platform::Osx64).unwrap();
+        assert_eq!(
+            activator.activation_scripts,
+            vec![activate_d.join("00-a.sh"), activate_d.join("10-b.sh")]
+        );
+        assert_eq!(
+            activator.deactivation_scripts,
+            vec![deactivate_d.join("10-b.sh"), deactivate_d.join("00-a.sh")]
+        );
+
+        let activator = Activator::from_path(tdir.path(), shell::CmdExe, Platform::Win64).unwrap();
+        assert_eq!(
+            activator.activation_scripts,
+            vec![activate_d.join("10-b.bat")]
+        );
+
+        let activator =
+            Activator::from_path(tdir.path(), shell::PowerShell::default(), Platform::Win64)
+                .unwrap();
+        assert_eq!(
+            activator.activation_scripts,
+            vec![activate_d.join("00-a.ps1")]
+        );
+    }
+
+    #[test]
+    fn test_deactivation_scripts_run_before_unset() {
+        let old_env = TempDir::new("old").unwrap();
+        let new_env = TempDir::new("new").unwrap();
+
+        let state_path = old_env.path().join("conda-meta/state");
+        fs::create_dir_all(state_path.parent().unwrap()).unwrap();
+        fs::write(&state_path, r#"{"env_vars": {"OLD_VAR": "1"}}"#).unwrap();
+
+        let deactivate_d = old_env.path().join("etc/conda/deactivate.d");
+        fs::create_dir_all(&deactivate_d).unwrap();
+        fs::write(deactivate_d.join("pkg.sh"), "").unwrap();
+
+        let activator = Activator::from_path(new_env.path(), shell::Bash, Platform::Osx64).unwrap();
+        let script = activator
+            .activation(ActivationVariables {
+                conda_prefix: Some(old_env.path().to_path_buf()),
+                path: None,
+                path_modification_behavior: PathModificationBehavior::Prepend,
+            })
+            .unwrap()
+            .script
+            .contents()
+            .unwrap();
+
+        let run_deactivate = script.find("pkg.sh").unwrap();
+        let unset = script.find("unset OLD_VAR").unwrap();
+        assert!(run_deactivate < unset);
+    }
+
+    #[test]
+    fn test_prompt_modifier() {
+        let tdir = TempDir::new("test").unwrap();
+
+        let activator = Activator::from_path(tdir.path(), shell::Bash, Platform::Linux64).unwrap();
+        let script = activator
+            .activation(ActivationVariables::default())
+            .unwrap()
+            .script
+            .contents()
+            .unwrap();
+        assert!(!script.contains("PS1"));
+        assert!(!script.contains("CONDA_PROMPT_MODIFIER"));
+
+        let activator = activator.with_prompt_modifier("my-env");
+        let script = activator
+            .activation(ActivationVariables::default())
+            .unwrap()
+            .script
+            .contents()
+            .unwrap();
+        assert!(script.contains("PS1=\"(my-env) ${PS1#\"${CONDA_PROMPT_MODIFIER:-}\"}\"\n"));
+        assert!(script.contains("export CONDA_PROMPT_MODIFIER=\"(my-env) \"\n"));
+
+        let activator = Activator::from_path(tdir.path(), shell::CmdExe, Platform::Win64)
+            .unwrap()
+            .with_prompt_modifier("my$env");
+        let script = activator
+            .activation(ActivationVariables::default())
+            .unwrap()
+            .script
+            .contents()
+            .unwrap();
+        assert!(script.contains("@SET \"PROMPT=(my$$env) %_RATTLER_OLD_PROMPT%\""));
+    }
+
+    #[test]
+    fn test_deactivation_resets_prompt() {
+        let tdir = TempDir::new("test").unwrap();
+
+        let activator = Activator::from_path(tdir.path(), shell::Bash, Platform::Linux64)
+            .unwrap()
+            .with_prompt_modifier("my-env");
+        let script = activator
+            .deactivation(ActivationVariables::default())
+            .unwrap()
+            .script
+            .contents()
+            .unwrap();
+        let reset = script
+            .find("PS1=\"${PS1#\"${CONDA_PROMPT_MODIFIER:-}\"}\"\n")
+            .unwrap();
+        let unset = script.find("unset CONDA_PROMPT_MODIFIER\n").unwrap();
+        assert!(reset < unset);
+        assert!(script.contains("unset CONDA_PREFIX\n"));
+
+        let activator = Activator::from_path(tdir.path(), shell::CmdExe, Platform::Win64).unwrap();
+        let script = activator
+            .deactivation(ActivationVariables::default())
+            .unwrap()
+            .script
+            .contents()
+            .unwrap();
+        assert!(script
+            .contains("@IF DEFINED _RATTLER_OLD_PROMPT @SET \"PROMPT=%_RATTLER_OLD_PROMPT%\""));
+        assert!(script.contains("@SET CONDA_PROMPT_MODIFIER="));
+
+        // Switching to an environment without a prompt modifier removes the
+        // modifier of the previous environment.
+        let old_env = TempDir::new("old").unwrap();
+        let activator = Activator::from_path(tdir.path(), shell::Fish, Platform::Linux64).unwrap();
+        let script = activator
+            .activation(ActivationVariables {
+                conda_prefix: Some(old_env.path().to_path_buf()),
+                ..ActivationVariables::default()
+            })
+            .unwrap()
+            .script
+            .contents()
+            .unwrap();
+        assert!(script.contains("functions -c __rattler_original_fish_prompt fish_prompt\n"));
+        assert!(script.contains("set -e CONDA_PROMPT_MODIFIER\n"));
+    }
+
     #[test]
     fn test_collect_env_vars() {
         let tdir = TempDir::new("test").unwrap();
@@ -582,6 +920,39 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_environment_diff() {
+        let before = HashMap::from([("KEEP", "1"), ("CHANGE", "old"), ("REMOVE", "x")]);
+        let after = HashMap::from([("KEEP", "1"), ("CHANGE", "new"), ("NEW", "y"), ("", "z")]);
+
+        let diff = EnvironmentDiff::between(&before, &after);
+        assert_eq!(
+            diff.set,
+            HashMap::from([(String::from("NEW"), String::from("y"))])
+        );
+        assert_eq!(
+            diff.modified,
+            HashMap::from([(String::from("CHANGE"), String::from("new"))])
+        );
+        assert_eq!(diff.unset, BTreeSet::from([String::from("REMOVE")]));
+
+        let mut env = before
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect::<HashMap<_, _>>();
+        diff.apply_to(&mut env);
+        assert_eq!(
+            env,
+            HashMap::from([
+                (String::from("KEEP"), String::from("1")),
+                (String::from("CHANGE"), String::from("new")),
+                (String::from("NEW"), String::from("y")),
+            ])
+        );
+
+        assert!(EnvironmentDiff::between(&before, &before).is_empty());
+    }
+
     #[test]
     fn test_add_to_path() {
         let prefix = PathBuf::from_str("/opt/conda").unwrap();
@@ -1,6 +1,7 @@
 //! Helpers to run commands in an activated environment.
 
 use rattler_conda_types::Platform;
+use rattler_error::ErrorCode;
 use std::process::{Command, Output};
 use std::{collections::HashMap, path::Path};
 
@@ -24,6 +25,16 @@ pub enum RunError {
     IoError(#[from] std::io::Error),
 }
 
+impl ErrorCode for RunError {
+    fn code(&self) -> &'static str {
+        match self {
+            RunError::ActivationError(err) => err.code(),
+            RunError::WriteError(_) => "rattler::run::write",
+            RunError::IoError(_) => "rattler::run::io",
+        }
+    }
+}
+
 /// Execute a script in an activated environment.
 pub fn run_in_environment(
     prefix: &Path,
@@ -14,6 +14,7 @@ use std::{
 use enum_dispatch::enum_dispatch;
 use itertools::Itertools;
 use rattler_conda_types::Platform;
+use rattler_error::ErrorCode;
 use thiserror::Error;
 
 use crate::activation::PathModificationBehavior;
@@ -162,6 +163,128 @@ pub trait Shell {
     fn line_ending(&self) -> &str {
         "\n"
     }
+
+    /// Writes commands that prepend the given modifier (e.g. `(env) `) to the
+    /// prompt of the shell. A modifier that was previously applied through
+    /// the `CONDA_PROMPT_MODIFIER` environment variable is replaced instead of
+    /// stacked.
+    ///
+    /// Shells that do not support modifying the prompt leave the prompt
+    /// untouched.
+    fn set_prompt_modifier(&self, _f: &mut impl Write, _modifier: &str) -> std::fmt::Result {
+        Ok(())
+    }
+
+    /// Writes commands that restore the prompt of the shell from before
+    /// [`Self::set_prompt_modifier`] was applied. The commands may read the
+    /// `CONDA_PROMPT_MODIFIER` environment variable, so they must be emitted
+    /// before it is unset.
+    ///
+    /// Shells that do not support modifying the prompt leave the prompt
+    /// untouched.
+    fn reset_prompt_modifier(&self, _f: &mut impl Write) -> std::fmt::Result {
+        Ok(())
+    }
+
+    /// Writes the "shell hook" that users can add to the initialization file
+    /// of their shell (e.g. `~/.bashrc`).
+    ///
+    /// The hook defines a function with the name of the given `executable`
+    /// that wraps the executable found on the `PATH`. When the function is
+    /// called as `<executable> activate [args]` or
+    /// `<executable> deactivate [args]` it evaluates the output of
+    /// `<executable> shell <activate|deactivate> --shell <shell> [args]` in the
+    /// current shell. All other invocations are forwarded to the executable
+    /// as-is.
+    fn shell_hook(&self, _f: &mut impl Write, _executable: &str) -> Result<(), ShellHookError> {
+        Err(ShellHookError::Unsupported(self.executable().to_string()))
+    }
+}
+
+/// An error that can occur when writing a shell hook with
+/// [`Shell::shell_hook`].
+#[derive(Debug, Error)]
+pub enum ShellHookError {
+    /// The shell does not support shell hooks.
+    #[error("shell hooks are not supported for {0}")]
+    Unsupported(String),
+
+    /// The name of the executable cannot be used as a function name.
+    #[error("'{0}' is not a valid executable name for a shell hook")]
+    InvalidExecutableName(String),
+
+    /// Writing the hook failed.
+    #[error(transparent)]
+    FormatError(#[from] std::fmt::Error),
+}
+
+impl ErrorCode for ShellHookError {
+    fn code(&self) -> &'static str {
+        match self {
+            ShellHookError::Unsupported(_) => "rattler::shell::hook::unsupported",
+            ShellHookError::InvalidExecutableName(_) => {
+                "rattler::shell::hook::invalid_executable_name"
+            }
+            ShellHookError::FormatError(_) => "rattler::shell::hook::format",
+        }
+    }
+}
+
+/// Verifies that the executable name can be used verbatim as a function name
+/// in all supported shells.
+fn validate_hook_executable(executable: &str) -> Result<(), ShellHookError> {
+    let mut chars = executable.chars();
+    let valid = chars
+        .next()
+        .map_or(false, |c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+    if valid {
+        Ok(())
+    } else {
+        Err(ShellHookError::InvalidExecutableName(
+            executable.to_string(),
+        ))
+    }
+}
+
+/// Writes the prompt modification for POSIX compatible shells (bash and zsh).
+/// The `modifier` must already be escaped for use in the prompt of the shell.
+fn posix_set_prompt_modifier(f: &mut impl Write, modifier: &str) -> std::fmt::Result {
+    writeln!(
+        f,
+        "PS1=\"{}${{PS1#\"${{CONDA_PROMPT_MODIFIER:-}}\"}}\"",
+        escape_posix_double_quoted(modifier).replace('$', "\\$")
+    )
+}
+
+/// Removes the prompt modification of [`posix_set_prompt_modifier`].
+fn posix_reset_prompt_modifier(f: &mut impl Write) -> std::fmt::Result {
+    writeln!(f, "PS1=\"${{PS1#\"${{CONDA_PROMPT_MODIFIER:-}}\"}}\"")
+}
+
+/// Writes a shell hook for POSIX compatible shells (bash and zsh).
+fn posix_shell_hook(
+    f: &mut impl Write,
+    executable: &str,
+    shell: &str,
+) -> Result<(), ShellHookError> {
+    validate_hook_executable(executable)?;
+    writeln!(f, "{executable}() {{")?;
+    writeln!(f, "    case \"$1\" in")?;
+    writeln!(f, "        activate|deactivate)")?;
+    writeln!(f, "            local __rattler_cmd=\"$1\"")?;
+    writeln!(f, "            shift")?;
+    writeln!(
+        f,
+        "            eval \"$(command {executable} shell \"$__rattler_cmd\" --shell {shell} \"$@\")\" || return $?"
+    )?;
+    writeln!(f, "            ;;")?;
+    writeln!(f, "        *)")?;
+    writeln!(f, "            command {executable} \"$@\"")?;
+    writeln!(f, "            ;;")?;
+    writeln!(f, "    esac")?;
+    writeln!(f, "}}")?;
+    Ok(())
 }
 
 /// Convert a native PATH on Windows to a Unix style path using cygpath.
@@ -195,13 +318,103 @@ fn native_path_to_unix(path: &str) -> Result<String, std::io::Error> {
     }
 }
 
+/// Escapes a value so it can be placed between double quotes in a POSIX
+/// shell script. Variable references (e.g. `${PATH}`) are still expanded.
+fn escape_posix_double_quoted(value: &str) -> Cow<'_, str> {
+    if value.contains(['\\', '"', '`']) {
+        Cow::Owned(
+            value
+                .replace('\\', "\\\\")
+                .replace('"', "\\\"")
+                .replace('`', "\\`"),
+        )
+    } else {
+        Cow::Borrowed(value)
+    }
+}
+
+/// Escapes a value so it can be placed between double quotes in a fish
+/// script. Variable references (e.g. `$PATH`) are still expanded.
+fn escape_fish_double_quoted(value: &str) -> Cow<'_, str> {
+    if value.contains(['\\', '"']) {
+        Cow::Owned(value.replace('\\', "\\\\").replace('"', "\\\""))
+    } else {
+        Cow::Borrowed(value)
+    }
+}
+
+/// Escapes a value so it can be placed between double quotes in a
+/// `PowerShell` script. Variable references (e.g. `$Env:Path`) are still
+/// expanded.
+fn escape_powershell_double_quoted(value: &str) -> Cow<'_, str> {
+    if value.contains(['`', '"']) {
+        Cow::Owned(value.replace('`', "``").replace('"', "`\""))
+    } else {
+        Cow::Borrowed(value)
+    }
+}
+
+/// Escapes a value so it can be placed between the double quotes of a
+/// `SET "NAME=value"` command in a cmd.exe script. Variable references (e.g.
+/// `%PATH%`) are still expanded, like in the other shells. Percent signs that
+/// are not part of a reference are doubled so they are not removed. Double
+/// quotes in the value end the quoted region, special characters that are not
+/// quoted are escaped with a caret.
+fn escape_cmd_exe_double_quoted(value: &str) -> Cow<'_, str> {
+    if !value.contains(['%', '"']) {
+        return Cow::Borrowed(value);
+    }
+
+    let mut escaped = String::with_capacity(value.len());
+    let mut quoted = true;
+    let mut chars = value.char_indices();
+    while let Some((index, c)) = chars.next() {
+        match c {
+            '%' => match cmd_exe_variable_reference(&value[index..]) {
+                Some(reference) => {
+                    escaped.push_str(reference);
+                    // Skip the remainder of the reference.
+                    chars.nth(reference.len() - 2);
+                }
+                None => escaped.push_str("%%"),
+            },
+            '"' => {
+                quoted = !quoted;
+                escaped.push(c);
+            }
+            '^' | '&' | '|' | '<' | '>' if !quoted => {
+                escaped.push('^');
+                escaped.push(c);
+            }
+            _ => escaped.push(c),
+        }
+    }
+    Cow::Owned(escaped)
+}
+
+/// Returns the variable reference (e.g. `%PATH%`) at the start of `value`, if
+/// any.
+fn cmd_exe_variable_reference(value: &str) -> Option<&str> {
+    let end = value[1..].find('%')? + 1;
+    let name = &value[1..end];
+    let is_name = !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.' | '(' | ')'));
+    is_name.then(|| &value[..=end])
+}
+
 /// A [`Shell`] implementation for the Bash shell.
 #[derive(Debug, Clone, Copy, Default)]
 pub struct Bash;
 
 impl Shell for Bash {
     fn set_env_var(&self, f: &mut impl Write, env_var: &str, value: &str) -> std::fmt::Result {
-        writeln!(f, "export {env_var}=\"{value}\"")
+        writeln!(
+            f,
+            "export {env_var}=\"{}\"",
+            escape_posix_double_quoted(value)
+        )
     }
 
     fn unset_env_var(&self, f: &mut impl Write, env_var: &str) -> std::fmt::Result {
@@ -275,6 +488,19 @@ impl Shell for Bash {
 
         cmd
     }
+
+    fn set_prompt_modifier(&self, f: &mut impl Write, modifier: &str) -> std::fmt::Result {
+        // Backslashes start escape sequences in the bash prompt.
+        posix_set_prompt_modifier(f, &modifier.replace('\\', "\\\\"))
+    }
+
+    fn reset_prompt_modifier(&self, f: &mut impl Write) -> std::fmt::Result {
+        posix_reset_prompt_modifier(f)
+    }
+
+    fn shell_hook(&self, f: &mut impl Write, executable: &str) -> Result<(), ShellHookError> {
+        posix_shell_hook(f, executable, "bash")
+    }
 }
 
 /// A [`Shell`] implementation for the Zsh shell.
@@ -283,7 +509,11 @@ pub struct Zsh;
 
 impl Shell for Zsh {
     fn set_env_var(&self, f: &mut impl Write, env_var: &str, value: &str) -> std::fmt::Result {
-        writeln!(f, "export {env_var}=\"{value}\"")
+        writeln!(
+            f,
+            "export {env_var}=\"{}\"",
+            escape_posix_double_quoted(value)
+        )
     }
 
     fn unset_env_var(&self, f: &mut impl Write, env_var: &str) -> std::fmt::Result {
@@ -307,6 +537,19 @@ impl Shell for Zsh {
         cmd.arg(path);
         cmd
     }
+
+    fn set_prompt_modifier(&self, f: &mut impl Write, modifier: &str) -> std::fmt::Result {
+        // Percent signs start escape sequences in the zsh prompt.
+        posix_set_prompt_modifier(f, &modifier.replace('%', "%%"))
+    }
+
+    fn reset_prompt_modifier(&self, f: &mut impl Write) -> std::fmt::Result {
+        posix_reset_prompt_modifier(f)
+    }
+
+    fn shell_hook(&self, f: &mut impl Write, executable: &str) -> Result<(), ShellHookError> {
+        posix_shell_hook(f, executable, "zsh")
+    }
 }
 
 /// A [`Shell`] implementation for the Xonsh shell.
@@ -364,7 +607,11 @@ impl Shell for CmdExe {
     }
 
     fn set_env_var(&self, f: &mut impl Write, env_var: &str, value: &str) -> std::fmt::Result {
-        writeln!(f, "@SET \"{env_var}={value}\"")
+        writeln!(
+            f,
+            "@SET \"{env_var}={}\"",
+            escape_cmd_exe_double_quoted(value)
+        )
     }
 
     fn unset_env_var(&self, f: &mut impl Write, env_var: &str) -> std::fmt::Result {
@@ -401,6 +648,30 @@ impl Shell for CmdExe {
         format!("%{var_name}%")
     }
 
+    fn set_path(
+        &self,
+        f: &mut impl Write,
+        paths: &[PathBuf],
+        modification_behavior: PathModificationBehavior,
+        platform: &Platform,
+    ) -> std::fmt::Result {
+        // Only the paths are escaped, the reference to the current value of the path variable
+        // must still be expanded.
+        let mut paths_vec = paths
+            .iter()
+            .map(|path| escape_cmd_exe_double_quoted(&path.to_string_lossy()).into_owned())
+            .collect_vec();
+        let path_var = self.path_var(platform);
+        match modification_behavior {
+            PathModificationBehavior::Replace => (),
+            PathModificationBehavior::Append => paths_vec.insert(0, self.format_env_var(path_var)),
+            PathModificationBehavior::Prepend => paths_vec.push(self.format_env_var(path_var)),
+        }
+        let paths_string = paths_vec.join(self.path_separator(platform));
+
+        writeln!(f, "@SET \"{path_var}={paths_string}\"")
+    }
+
     fn echo(&self, f: &mut impl Write, text: &str) -> std::fmt::Result {
         write!(f, "@ECHO ",)?;
 
@@ -425,6 +696,28 @@ impl Shell for CmdExe {
     fn line_ending(&self) -> &str {
         "\r\n"
     }
+
+    fn set_prompt_modifier(&self, f: &mut impl Write, modifier: &str) -> std::fmt::Result {
+        // Remember the original prompt so that repeated activations don't stack
+        // modifiers. `$` starts escape sequences in the prompt.
+        writeln!(
+            f,
+            "@IF NOT DEFINED _RATTLER_OLD_PROMPT @SET \"_RATTLER_OLD_PROMPT=%PROMPT%\""
+        )?;
+        writeln!(
+            f,
+            "@SET \"PROMPT={}%_RATTLER_OLD_PROMPT%\"",
+            modifier.replace('$', "$$")
+        )
+    }
+
+    fn reset_prompt_modifier(&self, f: &mut impl Write) -> std::fmt::Result {
+        writeln!(
+            f,
+            "@IF DEFINED _RATTLER_OLD_PROMPT @SET \"PROMPT=%_RATTLER_OLD_PROMPT%\""
+        )?;
+        writeln!(f, "@SET _RATTLER_OLD_PROMPT=")
+    }
 }
 
 /// A [`Shell`] implementation for `PowerShell`.
@@ -457,7 +750,11 @@ impl Shell for PowerShell {
     }
 
     fn set_env_var(&self, f: &mut impl Write, env_var: &str, value: &str) -> std::fmt::Result {
-        writeln!(f, "${{Env:{env_var}}} = \"{value}\"")
+        writeln!(
+            f,
+            "${{Env:{env_var}}} = \"{}\"",
+            escape_powershell_double_quoted(value)
+        )
     }
 
     fn unset_env_var(&self, f: &mut impl Write, env_var: &str) -> std::fmt::Result {
@@ -465,7 +762,11 @@ impl Shell for PowerShell {
     }
 
     fn run_script(&self, f: &mut impl Write, path: &Path) -> std::fmt::Result {
-        writeln!(f, ". \"{}\"", path.to_string_lossy())
+        writeln!(
+            f,
+            ". \"{}\"",
+            escape_powershell_double_quoted(&path.to_string_lossy())
+        )
     }
 
     fn extension(&self) -> &str {
@@ -490,6 +791,64 @@ impl Shell for PowerShell {
     fn print_env(&self, f: &mut impl Write) -> std::fmt::Result {
         writeln!(f, r##"dir env: | %{{"{{0}}={{1}}" -f $_.Name,$_.Value}}"##)
     }
+
+    fn set_prompt_modifier(&self, f: &mut impl Write, _modifier: &str) -> std::fmt::Result {
+        // The prompt function reads the modifier from `CONDA_PROMPT_MODIFIER`
+        // every time it is invoked so it only has to be wrapped once.
+        writeln!(
+            f,
+            "if (-not (Test-Path function:global:__rattler_original_prompt)) {{"
+        )?;
+        writeln!(
+            f,
+            "    $function:global:__rattler_original_prompt = $function:prompt"
+        )?;
+        writeln!(
+            f,
+            "    function global:prompt {{ \"$Env:CONDA_PROMPT_MODIFIER\" + (__rattler_original_prompt) }}"
+        )?;
+        writeln!(f, "}}")
+    }
+
+    fn reset_prompt_modifier(&self, f: &mut impl Write) -> std::fmt::Result {
+        writeln!(
+            f,
+            "if (Test-Path function:global:__rattler_original_prompt) {{"
+        )?;
+        writeln!(
+            f,
+            "    $function:global:prompt = $function:global:__rattler_original_prompt"
+        )?;
+        writeln!(
+            f,
+            "    Remove-Item function:global:__rattler_original_prompt"
+        )?;
+        writeln!(f, "}}")
+    }
+
+    fn shell_hook(&self, f: &mut impl Write, executable: &str) -> Result<(), ShellHookError> {
+        validate_hook_executable(executable)?;
+        writeln!(f, "function {executable} {{")?;
+        writeln!(
+            f,
+            "    $__rattler_exe = (Get-Command {executable} -CommandType Application | Select-Object -First 1).Source"
+        )?;
+        writeln!(
+            f,
+            "    if ($args.Count -gt 0 -and ($args[0] -eq \"activate\" -or $args[0] -eq \"deactivate\")) {{"
+        )?;
+        writeln!(f, "        $__rattler_cmd, $__rattler_args = $args")?;
+        writeln!(
+            f,
+            "        $__rattler_script = & $__rattler_exe shell $__rattler_cmd --shell powershell @__rattler_args | Out-String"
+        )?;
+        writeln!(f, "        Invoke-Expression $__rattler_script")?;
+        writeln!(f, "    }} else {{")?;
+        writeln!(f, "        & $__rattler_exe @args")?;
+        writeln!(f, "    }}")?;
+        writeln!(f, "}}")?;
+        Ok(())
+    }
 }
 
 /// A [`Shell`] implementation for the Fish shell.
@@ -498,7 +857,11 @@ pub struct Fish;
 
 impl Shell for Fish {
     fn set_env_var(&self, f: &mut impl Write, env_var: &str, value: &str) -> std::fmt::Result {
-        writeln!(f, "set -gx {env_var} \"{value}\"")
+        writeln!(
+            f,
+            "set -gx {env_var} \"{}\"",
+            escape_fish_double_quoted(value)
+        )
     }
 
     fn format_env_var(&self, var_name: &str) -> String {
@@ -527,6 +890,48 @@ impl Shell for Fish {
         cmd.arg(path);
         cmd
     }
+
+    fn set_prompt_modifier(&self, f: &mut impl Write, _modifier: &str) -> std::fmt::Result {
+        // The prompt function reads the modifier from `CONDA_PROMPT_MODIFIER`
+        // every time it is invoked so it only has to be wrapped once.
+        writeln!(f, "if not functions -q __rattler_original_fish_prompt")?;
+        writeln!(
+            f,
+            "    functions -c fish_prompt __rattler_original_fish_prompt"
+        )?;
+        writeln!(f, "    function fish_prompt")?;
+        writeln!(f, "        printf '%s' \"$CONDA_PROMPT_MODIFIER\"")?;
+        writeln!(f, "        __rattler_original_fish_prompt")?;
+        writeln!(f, "    end")?;
+        writeln!(f, "end")
+    }
+
+    fn reset_prompt_modifier(&self, f: &mut impl Write) -> std::fmt::Result {
+        writeln!(f, "if functions -q __rattler_original_fish_prompt")?;
+        writeln!(f, "    functions -e fish_prompt")?;
+        writeln!(
+            f,
+            "    functions -c __rattler_original_fish_prompt fish_prompt"
+        )?;
+        writeln!(f, "    functions -e __rattler_original_fish_prompt")?;
+        writeln!(f, "end")
+    }
+
+    fn shell_hook(&self, f: &mut impl Write, executable: &str) -> Result<(), ShellHookError> {
+        validate_hook_executable(executable)?;
+        writeln!(f, "function {executable}")?;
+        writeln!(f, "    switch \"$argv[1]\"")?;
+        writeln!(f, "        case activate deactivate")?;
+        writeln!(
+            f,
+            "            command {executable} shell $argv[1] --shell fish $argv[2..-1] | source"
+        )?;
+        writeln!(f, "        case '*'")?;
+        writeln!(f, "            command {executable} $argv")?;
+        writeln!(f, "    end")?;
+        writeln!(f, "end")?;
+        Ok(())
+    }
 }
 
 fn escape_backslashes(s: &str) -> String {
@@ -727,6 +1132,12 @@ impl ShellEnum {
 #[error("{0}")]
 pub struct ParseShellEnumError(String);
 
+impl ErrorCode for ParseShellEnumError {
+    fn code(&self) -> &'static str {
+        "rattler::shell::unknown_shell"
+    }
+}
+
 impl FromStr for ShellEnum {
     type Err = ParseShellEnumError;
 
@@ -803,6 +1214,21 @@ impl<T: Shell + 'static> ShellScript<T> {
         Ok(self)
     }
 
+    /// Prepend the given modifier to the prompt of the shell. See
+    /// [`Shell::set_prompt_modifier`].
+    pub fn set_prompt_modifier(&mut self, modifier: &str) -> Result<&mut Self, std::fmt::Error> {
+        self.shell
+            .set_prompt_modifier(&mut self.contents, modifier)?;
+        Ok(self)
+    }
+
+    /// Restore the prompt of the shell from before the modifier was
+    /// prepended. See [`Shell::reset_prompt_modifier`].
+    pub fn reset_prompt_modifier(&mut self) -> Result<&mut Self, std::fmt::Error> {
+        self.shell.reset_prompt_modifier(&mut self.contents)?;
+        Ok(self)
+    }
+
     /// Run a script in the generated shell script.
     pub fn run_script(&mut self, path: &Path) -> Result<&mut Self, std::fmt::Error> {
         self.shell.run_script(&mut self.contents, path)?;
@@ -917,6 +1343,110 @@ mod tests {
         println!("Detected shell: {shell:?}");
     }
 
+    #[test]
+    fn test_posix_quoting() {
+        let mut script = ShellScript::new(Bash, Platform::Linux64);
+        script
+            .set_env_var("FOO", r#"a "quoted" `value` with C:\path and ${BAR}"#)
+            .unwrap();
+        assert_eq!(
+            script.contents,
+            "export FOO=\"a \\\"quoted\\\" \\`value\\` with C:\\\\path and ${BAR}\"\n"
+        );
+
+        let mut script = ShellScript::new(Fish, Platform::Linux64);
+        script
+            .set_env_var("FOO", r#"a "quoted" value with C:\path and $BAR"#)
+            .unwrap();
+        assert_eq!(
+            script.contents,
+            "set -gx FOO \"a \\\"quoted\\\" value with C:\\\\path and $BAR\"\n"
+        );
+    }
+
+    #[test]
+    fn test_powershell_quoting() {
+        let mut script = ShellScript::new(PowerShell::default(), Platform::Win64);
+        script
+            .set_env_var("FOO", r#"a "quoted" `value` with C:\path and $Env:BAR"#)
+            .unwrap()
+            .run_script(Path::new(r"C:\my `env`\activate.ps1"))
+            .unwrap();
+        assert_eq!(
+            script.contents,
+            "${Env:FOO} = \"a `\"quoted`\" ```value``` with C:\\path and $Env:BAR\"\n\
+             . \"C:\\my ``env``\\activate.ps1\"\n"
+        );
+    }
+
+    #[test]
+    fn test_cmd_exe_quoting() {
+        let mut script = ShellScript::new(CmdExe, Platform::Win64);
+        script
+            .set_env_var("FOO", r#"say "hi & bye" ^ 100% of %USERNAME%"#)
+            .unwrap()
+            .set_path(
+                &[PathBuf::from(r"C:\100% & more")],
+                PathModificationBehavior::Prepend,
+            )
+            .unwrap();
+        assert_eq!(
+            script.contents,
+            "@SET \"FOO=say \"hi ^& bye\" ^ 100%% of %USERNAME%\"\n\
+             @SET \"Path=C:\\100%% & more;%Path%\"\n"
+        );
+    }
+
+    #[test]
+    fn test_windows_path_layout() {
+        let prefix = Path::new(r"C:\env");
+        let paths = crate::activation::prefix_path_entries(prefix, &Platform::Win64);
+        assert!(paths.contains(&prefix.join("Scripts")));
+        assert!(paths.contains(&prefix.join("Library/bin")));
+        let joined = paths.iter().map(|path| path.to_string_lossy()).join(";");
+
+        let mut script = ShellScript::new(CmdExe, Platform::Win64);
+        script
+            .set_path(&paths, PathModificationBehavior::Prepend)
+            .unwrap();
+        assert_eq!(script.contents, format!("@SET \"Path={joined};%Path%\"\n"));
+
+        let mut script = ShellScript::new(PowerShell::default(), Platform::Win64);
+        script
+            .set_path(&paths, PathModificationBehavior::Append)
+            .unwrap();
+        assert_eq!(
+            script.contents,
+            format!("${{Env:Path}} = \"$Env:Path;{joined}\"\n")
+        );
+    }
+
+    #[test]
+    fn test_shell_hook() {
+        let mut hook = String::new();
+        Bash.shell_hook(&mut hook, "mytool").unwrap();
+        insta::assert_snapshot!("shell_hook_bash", hook);
+
+        let mut hook = String::new();
+        Fish.shell_hook(&mut hook, "mytool").unwrap();
+        insta::assert_snapshot!("shell_hook_fish", hook);
+
+        let mut hook = String::new();
+        PowerShell::default()
+            .shell_hook(&mut hook, "mytool")
+            .unwrap();
+        insta::assert_snapshot!("shell_hook_powershell", hook);
+
+        assert!(matches!(
+            Bash.shell_hook(&mut String::new(), "my tool"),
+            Err(ShellHookError::InvalidExecutableName(_))
+        ));
+        assert!(matches!(
+            CmdExe.shell_hook(&mut String::new(), "mytool"),
+            Err(ShellHookError::Unsupported(_))
+        ));
+    }
+
     #[test]
     fn test_path_separator() {
         let mut script = ShellScript::new(Bash, Platform::Linux64);
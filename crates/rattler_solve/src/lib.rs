@@ -13,6 +13,7 @@ use std::fmt;
 
 use chrono::{DateTime, Utc};
 use rattler_conda_types::{GenericVirtualPackage, MatchSpec, RepoDataRecord};
+use rattler_error::ErrorCode;
 
 /// Represents a solver implementation, capable of solving [`SolverTask`]s
 pub trait SolverImpl {
@@ -79,6 +80,18 @@ impl fmt::Display for SolveError {
     }
 }
 
+impl ErrorCode for SolveError {
+    fn code(&self) -> &'static str {
+        match self {
+            SolveError::Unsolvable(_) => "rattler::solve::unsolvable",
+            SolveError::UnsupportedOperations(_) => "rattler::solve::unsupported_operations",
+            SolveError::ParseMatchSpecError(err) => err.code(),
+            SolveError::DuplicateRecords(_) => "rattler::solve::duplicate_records",
+            SolveError::Cancelled => "rattler::solve::cancelled",
+        }
+    }
+}
+
 /// Represents the channel priority option to use during solves.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
 #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
@@ -11,6 +11,7 @@ use input::{add_repodata_records, add_solv_file, add_virtual_packages};
 pub use libc_byte_slice::LibcByteSlice;
 use output::get_required_packages;
 use rattler_conda_types::{MatchSpec, NamelessMatchSpec, RepoDataRecord};
+use tracing::{field::Empty, instrument, Span};
 use wrapper::{
     flags::SolverFlag,
     pool::{Pool, Verbosity},
@@ -87,6 +88,11 @@ pub struct Solver;
 impl super::SolverImpl for Solver {
     type RepoData<'a> = RepoData<'a>;
 
+    #[instrument(
+        name = "solve",
+        skip_all,
+        fields(solver = "libsolv_c", specs = task.specs.len(), records = Empty)
+    )]
     fn solve<
         'a,
         R: IntoRepoData<'a, Self::RepoData<'a>>,
@@ -276,6 +282,7 @@ impl super::SolverImpl for Solver {
             )
         })?;
 
+        Span::current().record("records", required_records.len());
         Ok(required_records)
     }
 }
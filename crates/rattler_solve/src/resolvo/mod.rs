@@ -21,6 +21,7 @@ use resolvo::{
     Requirement, SolvableId, Solver as LibSolvRsSolver, SolverCache, StringId,
     UnsolvableOrCancelled, VersionSetId, VersionSetUnionId,
 };
+use tracing::{field::Empty, instrument, Span};
 
 use crate::{
     resolvo::conda_util::CompareStrategy, ChannelPriority, IntoRepoData, SolveError, SolveStrategy,
@@ -216,7 +217,12 @@ impl<'a> CondaDependencyProvider<'a> {
             .collect::<Vec<_>>();
 
         // Hashmap that maps the package name to the channel it was first found in.
-        let mut package_name_found_in_channel = HashMap::<String, &String>::new();
+        let mut package_name_found_in_channel = HashMap::<PackageName, &String>::new();
+
+        // The reason for excluding a record due to strict channel priority only
+        // depends on the channel, so it is interned once per channel instead of
+        // once per excluded record.
+        let mut channel_priority_reasons = HashMap::<&String, StringId>::new();
 
         // Add additional records
         for repo_data in repodata {
@@ -353,20 +359,20 @@ impl<'a> CondaDependencyProvider<'a> {
                             &record.package_record.name.as_normalized(),
                             &record.channel
                         );
-                        candidates.excluded.push((
-                            solvable_id,
-                            pool.intern_string(format!(
-                                "due to strict channel priority not using this option from: '{}'",
-                                &record.channel
-                            )),
-                        ));
+                        let reason = *channel_priority_reasons
+                            .entry(&record.channel)
+                            .or_insert_with(|| {
+                                pool.intern_string(format!(
+                                    "due to strict channel priority not using this option from: '{}'",
+                                    &record.channel
+                                ))
+                            });
+                        candidates.excluded.push((solvable_id, reason));
                         continue;
                     }
                 } else {
-                    package_name_found_in_channel.insert(
-                        record.package_record.name.as_normalized().to_string(),
-                        &record.channel,
-                    );
+                    package_name_found_in_channel
+                        .insert(record.package_record.name.clone(), &record.channel);
                 }
 
                 candidates.hint_dependencies_available.push(solvable_id);
@@ -590,6 +596,11 @@ impl super::SolverImpl for Solver {
     type RepoData<'a> = RepoData<'a>;
 
     #[allow(clippy::redundant_closure_for_method_calls)]
+    #[instrument(
+        name = "solve",
+        skip_all,
+        fields(solver = "resolvo", specs = task.specs.len(), records = Empty)
+    )]
     fn solve<
         'a,
         R: IntoRepoData<'a, Self::RepoData<'a>>,
@@ -674,8 +685,9 @@ impl super::SolverImpl for Solver {
                     SolverPackageRecord::VirtualPackage(_) => None,
                 },
             )
-            .collect();
+            .collect::<Vec<_>>();
 
+        Span::current().record("records", required_records.len());
         Ok(required_records)
     }
 }
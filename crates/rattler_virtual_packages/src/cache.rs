@@ -0,0 +1,59 @@
+//! A small thread-safe memoization primitive used to cache the results of
+//! (expensive) system probing.
+
+use std::sync::{Mutex, PoisonError};
+
+/// Stores the result of a detection function. Unlike a `OnceCell` the stored
+/// value can be cleared again which allows redoing the detection.
+///
+/// The lock is held while the value is being initialized, so concurrent
+/// callers will wait for a single detection instead of all probing the system
+/// at the same time.
+pub(crate) struct DetectionCache<T> {
+    value: Mutex<Option<T>>,
+}
+
+impl<T: Clone> DetectionCache<T> {
+    /// Constructs a new empty cache.
+    pub const fn new() -> Self {
+        Self {
+            value: Mutex::new(None),
+        }
+    }
+
+    /// Returns the cached value or initializes it with the result of `f`. If
+    /// `f` returns an error nothing is cached.
+    pub fn get_or_try_init<E>(&self, f: impl FnOnce() -> Result<T, E>) -> Result<T, E> {
+        let mut value = self.value.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some(value) = value.as_ref() {
+            return Ok(value.clone());
+        }
+        let result = f()?;
+        *value = Some(result.clone());
+        Ok(result)
+    }
+
+    /// Returns the cached value or initializes it with the result of `f`.
+    pub fn get_or_init(&self, f: impl FnOnce() -> T) -> T {
+        match self.get_or_try_init(|| Ok::<_, std::convert::Infallible>(f())) {
+            Ok(value) => value,
+            Err(never) => match never {},
+        }
+    }
+
+    /// Replaces the cached value with the result of `f`. If `f` returns an
+    /// error the cache is left empty.
+    pub fn try_refresh<E>(&self, f: impl FnOnce() -> Result<T, E>) -> Result<T, E> {
+        let mut value = self.value.lock().unwrap_or_else(PoisonError::into_inner);
+        *value = None;
+        let result = f()?;
+        *value = Some(result.clone());
+        Ok(result)
+    }
+
+    /// Clears the cached value so that the next call to
+    /// [`DetectionCache::get_or_try_init`] redoes the detection.
+    pub fn clear(&self) {
+        *self.value.lock().unwrap_or_else(PoisonError::into_inner) = None;
+    }
+}
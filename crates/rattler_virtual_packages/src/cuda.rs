@@ -8,8 +8,8 @@
 //! Both will detect the current supported CUDA version but the first method has less edge cases.
 //! See the function documentation for more information.
 
+use crate::cache::DetectionCache;
 use libloading::Symbol;
-use once_cell::sync::OnceCell;
 use rattler_conda_types::Version;
 use std::process::Command;
 use std::{
@@ -20,10 +20,14 @@ use std::{
 
 /// Returns the maximum Cuda version available on the current platform.
 pub fn cuda_version() -> Option<Version> {
-    static DETECTED_CUDA_VERSION: OnceCell<Option<Version>> = OnceCell::new();
-    DETECTED_CUDA_VERSION
-        .get_or_init(detect_cuda_version)
-        .clone()
+    DETECTED_CUDA_VERSION.get_or_init(detect_cuda_version)
+}
+
+static DETECTED_CUDA_VERSION: DetectionCache<Option<Version>> = DetectionCache::new();
+
+/// Clears the memoized result of [`cuda_version`].
+pub(crate) fn clear_cache() {
+    DETECTED_CUDA_VERSION.clear();
 }
 
 /// Attempts to detect the version of CUDA present in the current operating system by employing the
@@ -12,8 +12,11 @@
 //! virtual packages for the host system.
 //!
 //! To detect all virtual packages for the host system use the
-//! [`VirtualPackage::detect`] method which will return a memoized slice of all
-//! detected virtual packages. The `VirtualPackage` enum represents all
+//! [`VirtualPackage::detect`] method which will return all detected virtual
+//! packages. Since detection involves spawning processes and probing system
+//! libraries, [`VirtualPackages::current`] provides a memoized version of the
+//! same information which can be explicitly redone with
+//! [`VirtualPackages::refresh`]. The `VirtualPackage` enum represents all
 //! available virtual package types. Using it provides some flexibility to the
 //! user to not care about which exact virtual packages exist but still allows
 //! users to override specific virtual package behavior. Say for instance you
@@ -32,6 +35,7 @@
 //! virtual packages. See [`cuda::detect_cuda_version_via_libcuda`] as an
 //! example.
 
+mod cache;
 pub mod cuda;
 pub mod libc;
 pub mod linux;
@@ -40,6 +44,7 @@ pub mod osx;
 use std::{
     env,
     hash::{Hash, Hasher},
+    ops::Deref,
     str::FromStr,
     sync::Arc,
 };
@@ -52,7 +57,7 @@ use rattler_conda_types::{
 };
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
-use crate::osx::ParseOsxVersionError;
+use crate::{cache::DetectionCache, osx::ParseOsxVersionError};
 
 /// Configure the overrides used in in this crate.
 #[derive(Clone, Debug, PartialEq, Default)]
@@ -139,7 +144,11 @@ pub trait EnvOverride: Sized {
 }
 
 /// An enum that represents all virtual package types provided by this library.
+///
+/// New kinds of virtual packages may be added in the future, so this enum is
+/// marked `#[non_exhaustive]`.
 #[derive(Clone, Eq, PartialEq, Hash, Debug)]
+#[non_exhaustive]
 pub enum VirtualPackage {
     /// Available on windows
     Win,
@@ -161,6 +170,10 @@ pub enum VirtualPackage {
 
     /// The CPU architecture
     Archspec(Archspec),
+
+    /// A user supplied virtual package. See
+    /// [`VirtualPackageOverrides::with_custom`].
+    Custom(GenericVirtualPackage),
 }
 
 impl From<VirtualPackage> for GenericVirtualPackage {
@@ -181,6 +194,7 @@ impl From<VirtualPackage> for GenericVirtualPackage {
             VirtualPackage::LibC(libc) => libc.into(),
             VirtualPackage::Cuda(cuda) => cuda.into(),
             VirtualPackage::Archspec(spec) => spec.into(),
+            VirtualPackage::Custom(package) => package,
         }
     }
 }
@@ -205,6 +219,81 @@ impl VirtualPackage {
     }
 }
 
+/// The virtual packages detected on the host system.
+///
+/// Detecting virtual packages requires spawning processes and probing system
+/// libraries. [`VirtualPackages::current`] memoizes the detection for the
+/// lifetime of the process so that repeated solves don't redo this work. Use
+/// [`VirtualPackages::refresh`] to explicitly detect the virtual packages
+/// again, for instance after a new driver has been installed.
+///
+/// The detection does not take any overrides into account. Use
+/// [`VirtualPackage::detect`] to detect virtual packages with overrides.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct VirtualPackages {
+    packages: Arc<[VirtualPackage]>,
+}
+
+static DETECTED_VIRTUAL_PACKAGES: DetectionCache<VirtualPackages> = DetectionCache::new();
+
+impl VirtualPackages {
+    /// Returns the virtual packages of the current system. The first call
+    /// performs the detection, subsequent calls return the memoized result.
+    ///
+    /// This function is thread-safe. If multiple threads call this function
+    /// concurrently only a single detection is performed.
+    pub fn current() -> Result<Self, DetectVirtualPackageError> {
+        DETECTED_VIRTUAL_PACKAGES.get_or_try_init(Self::detect_from_host)
+    }
+
+    /// Discards any memoized information and detects the virtual packages of
+    /// the current system again. Subsequent calls to
+    /// [`VirtualPackages::current`] will return the refreshed result.
+    pub fn refresh() -> Result<Self, DetectVirtualPackageError> {
+        DETECTED_VIRTUAL_PACKAGES.try_refresh(|| {
+            linux::clear_cache();
+            osx::clear_cache();
+            libc::clear_cache();
+            cuda::clear_cache();
+            Self::detect_from_host()
+        })
+    }
+
+    /// Returns the detected virtual packages as a slice.
+    pub fn as_slice(&self) -> &[VirtualPackage] {
+        &self.packages
+    }
+
+    fn detect_from_host() -> Result<Self, DetectVirtualPackageError> {
+        VirtualPackage::detect(&VirtualPackageOverrides::default()).map(Self::from)
+    }
+}
+
+impl From<Vec<VirtualPackage>> for VirtualPackages {
+    fn from(packages: Vec<VirtualPackage>) -> Self {
+        Self {
+            packages: packages.into(),
+        }
+    }
+}
+
+impl Deref for VirtualPackages {
+    type Target = [VirtualPackage];
+
+    fn deref(&self) -> &Self::Target {
+        &self.packages
+    }
+}
+
+impl<'a> IntoIterator for &'a VirtualPackages {
+    type Item = &'a VirtualPackage;
+    type IntoIter = std::slice::Iter<'a, VirtualPackage>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.packages.iter()
+    }
+}
+
 /// An error that might be returned by [`VirtualPackage::current`].
 #[derive(Debug, thiserror::Error)]
 #[allow(missing_docs)]
@@ -231,7 +320,15 @@ pub enum DetectVirtualPackageError {
 ///
 /// Use `VirtualPackageOverrides::from_env()` to create an instance of this
 /// struct with all overrides set to the default environment variables.
+///
+/// Additional virtual packages that cannot be detected from the system (e.g.
+/// `__internal_site=2`) can be added with
+/// [`VirtualPackageOverrides::with_custom`].
+///
+/// This struct is marked `#[non_exhaustive]`, construct it with
+/// `VirtualPackageOverrides::default()` and the `with_*` methods.
 #[derive(Default, Clone, Debug)]
+#[non_exhaustive]
 pub struct VirtualPackageOverrides {
     /// The override for the osx virtual package
     pub osx: Option<Override>,
@@ -239,6 +336,10 @@ pub struct VirtualPackageOverrides {
     pub libc: Option<Override>,
     /// The override for the cuda virtual package
     pub cuda: Option<Override>,
+    /// Custom virtual packages that are added to the detected virtual
+    /// packages. If a detected virtual package has the same name as a custom
+    /// virtual package the custom one takes precedence.
+    pub custom: Vec<GenericVirtualPackage>,
 }
 
 impl VirtualPackageOverrides {
@@ -249,6 +350,7 @@ impl VirtualPackageOverrides {
             osx: Some(ov.clone()),
             libc: Some(ov.clone()),
             cuda: Some(ov),
+            custom: Vec::new(),
         }
     }
 
@@ -257,6 +359,32 @@ impl VirtualPackageOverrides {
     pub fn from_env() -> Self {
         Self::all(Override::DefaultEnvVar)
     }
+
+    /// Sets the override for the osx virtual package.
+    pub fn with_osx(mut self, ov: Option<Override>) -> Self {
+        self.osx = ov;
+        self
+    }
+
+    /// Sets the override for the libc virtual package.
+    pub fn with_libc(mut self, ov: Option<Override>) -> Self {
+        self.libc = ov;
+        self
+    }
+
+    /// Sets the override for the cuda virtual package.
+    pub fn with_cuda(mut self, ov: Option<Override>) -> Self {
+        self.cuda = ov;
+        self
+    }
+
+    /// Adds a custom virtual package. If a virtual package with the same name
+    /// is detected on the system it is replaced by the custom one.
+    pub fn with_custom(mut self, package: GenericVirtualPackage) -> Self {
+        self.custom.retain(|existing| existing.name != package.name);
+        self.custom.push(package);
+        self
+    }
 }
 
 // Detect the available virtual packages on the system
@@ -297,6 +425,11 @@ fn try_detect_virtual_packages_with_overrides(
         result.push(archspec.into());
     }
 
+    for custom in &overrides.custom {
+        result.retain(|package| GenericVirtualPackage::from(package.clone()).name != custom.name);
+        result.push(VirtualPackage::Custom(custom.clone()));
+    }
+
     Ok(result)
 }
 
@@ -343,7 +476,7 @@ impl From<Version> for Linux {
 /// `LibC` virtual package description
 #[derive(Clone, Eq, PartialEq, Hash, Debug, Deserialize)]
 pub struct LibC {
-    /// The family of `LibC`. This could be glibc for instance.
+    /// The family of `LibC`. This could be glibc or musl for instance.
     pub family: String,
 
     /// The version of the libc distribution.
@@ -607,15 +740,57 @@ impl EnvOverride for Osx {
 mod test {
     use std::{env, str::FromStr};
 
-    use rattler_conda_types::Version;
+    use rattler_conda_types::{GenericVirtualPackage, PackageName, Version};
 
-    use crate::{Cuda, EnvOverride, LibC, Osx, Override, VirtualPackage};
+    use crate::{
+        Cuda, EnvOverride, LibC, Osx, Override, VirtualPackage, VirtualPackageOverrides,
+        VirtualPackages,
+    };
 
     #[test]
     fn doesnt_crash() {
         let virtual_packages = VirtualPackage::detect(&Default::default()).unwrap();
         println!("{virtual_packages:?}");
     }
+
+    #[test]
+    fn custom_virtual_packages() {
+        let site = GenericVirtualPackage {
+            name: PackageName::new_unchecked("__internal_site"),
+            version: Version::from_str("2").unwrap(),
+            build_string: "0".into(),
+        };
+        let unix = GenericVirtualPackage {
+            name: PackageName::new_unchecked("__unix"),
+            version: Version::from_str("1").unwrap(),
+            build_string: "custom".into(),
+        };
+        let overrides = VirtualPackageOverrides::default()
+            .with_custom(site.clone())
+            .with_custom(unix.clone());
+        let virtual_packages = VirtualPackage::detect(&overrides)
+            .unwrap()
+            .into_iter()
+            .map(GenericVirtualPackage::from)
+            .collect::<Vec<_>>();
+
+        assert!(virtual_packages.contains(&site));
+        assert_eq!(
+            virtual_packages
+                .iter()
+                .filter(|package| package.name.as_normalized() == "__unix")
+                .collect::<Vec<_>>(),
+            vec![&unix]
+        );
+    }
+
+    #[test]
+    fn current_is_memoized() {
+        let current = VirtualPackages::current().unwrap();
+        assert_eq!(VirtualPackages::current().unwrap(), current);
+        assert_eq!(VirtualPackages::refresh().unwrap(), current);
+        assert_eq!(VirtualPackages::current().unwrap(), current);
+    }
     #[test]
     fn parse_libc() {
         let v = "1.23";
@@ -1,7 +1,7 @@
 //! Low-level functions to detect the `LibC` family and version. See
 //! [`libc_family_and_version`].
 
-use once_cell::sync::OnceCell;
+use crate::cache::DetectionCache;
 use rattler_conda_types::{ParseVersionError, Version};
 
 /// Returns the `LibC` version and family of the current platform.
@@ -10,10 +10,14 @@ use rattler_conda_types::{ParseVersionError, Version};
 /// error. Returns `None` if the current platform does not provide a version of
 /// `LibC`.
 pub fn libc_family_and_version() -> Result<Option<(String, Version)>, DetectLibCError> {
-    static DETECTED_LIBC_VERSION: OnceCell<Option<(String, Version)>> = OnceCell::new();
-    DETECTED_LIBC_VERSION
-        .get_or_try_init(try_detect_libc_version)
-        .cloned()
+    DETECTED_LIBC_VERSION.get_or_try_init(try_detect_libc_version)
+}
+
+static DETECTED_LIBC_VERSION: DetectionCache<Option<(String, Version)>> = DetectionCache::new();
+
+/// Clears the memoized result of [`libc_family_and_version`].
+pub(crate) fn clear_cache() {
+    DETECTED_LIBC_VERSION.clear();
 }
 
 /// An error that could occur when trying to detect to libc version
@@ -32,8 +36,9 @@ pub enum DetectLibCError {
 /// binary can still run on a glibc based system. For environments we are
 /// interested in the libc family that is available on the *system*.
 ///
-/// Currently this code is only able to detect glibc properly. We can add more
-/// detection methods in the future.
+/// Both glibc and musl based systems are detected. On musl based systems (like
+/// Alpine) `ldd --version` prints its version information to stderr and exits
+/// with a non-zero exit code, so both streams are inspected.
 #[cfg(unix)]
 fn try_detect_libc_version() -> Result<Option<(String, Version)>, DetectLibCError> {
     // Run `ldd --version` to detect the libc version and family on the system.
@@ -49,10 +54,15 @@ fn try_detect_libc_version() -> Result<Option<(String, Version)>, DetectLibCErro
         Ok(output) => output,
     };
 
-    Ok(
-        parse_glibc_ldd_version(&String::from_utf8_lossy(&output.stdout))?
-            .map(|version| (String::from("glibc"), version)),
-    )
+    if let Some(version) = parse_glibc_ldd_version(&String::from_utf8_lossy(&output.stdout))? {
+        return Ok(Some((String::from("glibc"), version)));
+    }
+
+    if let Some(version) = parse_musl_ldd_version(&String::from_utf8_lossy(&output.stderr))? {
+        return Ok(Some((String::from("musl"), version)));
+    }
+
+    Ok(None)
 }
 
 #[cfg(any(test, unix))]
@@ -73,6 +83,24 @@ fn parse_glibc_ldd_version(input: &str) -> Result<Option<Version>, DetectLibCErr
     Ok(None)
 }
 
+#[cfg(any(test, unix))]
+fn parse_musl_ldd_version(input: &str) -> Result<Option<Version>, DetectLibCError> {
+    static MUSL_LIBC_RE: once_cell::sync::Lazy<regex::Regex> = once_cell::sync::Lazy::new(|| {
+        regex::Regex::new(r"(?mi)^musl libc[\s\S]*?^Version ([0-9]+(?:\.[0-9]+)*)").unwrap()
+    });
+
+    if let Some(version_match) = MUSL_LIBC_RE
+        .captures(input)
+        .and_then(|captures| captures.get(1))
+        .map(|version_match| version_match.as_str())
+    {
+        let version = std::str::FromStr::from_str(version_match)?;
+        return Ok(Some(version));
+    }
+
+    Ok(None)
+}
+
 #[cfg(not(unix))]
 const fn try_detect_libc_version() -> Result<Option<(String, Version)>, DetectLibCError> {
     Ok(None)
@@ -110,4 +138,20 @@ mod test {
             Some(Version::from_str("2.39").unwrap())
         );
     }
+
+    #[test]
+    pub fn test_parse_musl_ldd_version() {
+        assert_eq!(
+            parse_musl_ldd_version(
+                "musl libc (x86_64)\nVersion 1.2.4\nDynamic Program Loader\nUsage: ldd [options] [--] pathname\n"
+            )
+            .unwrap(),
+            Some(Version::from_str("1.2.4").unwrap())
+        );
+        assert_eq!(parse_musl_ldd_version("ldd (GNU libc) 2.31").unwrap(), None);
+        assert_eq!(
+            parse_glibc_ldd_version("musl libc (aarch64)\nVersion 1.2.3\n").unwrap(),
+            None
+        );
+    }
 }
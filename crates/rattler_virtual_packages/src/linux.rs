@@ -1,6 +1,6 @@
 //! Low-level functions to dect the linux version on the system. See [`linux_version`].
 
-use once_cell::sync::OnceCell;
+use crate::cache::DetectionCache;
 use rattler_conda_types::{ParseVersionError, Version};
 use std::str::FromStr;
 
@@ -9,10 +9,14 @@ use std::str::FromStr;
 /// Returns an error if determining the Linux version resulted in an error. Returns `None` if
 /// the current platform is not a Linux platform.
 pub fn linux_version() -> Result<Option<Version>, ParseLinuxVersionError> {
-    static DETECTED_LINUX_VERSION: OnceCell<Option<Version>> = OnceCell::new();
-    DETECTED_LINUX_VERSION
-        .get_or_try_init(try_detect_linux_version)
-        .cloned()
+    DETECTED_LINUX_VERSION.get_or_try_init(try_detect_linux_version)
+}
+
+static DETECTED_LINUX_VERSION: DetectionCache<Option<Version>> = DetectionCache::new();
+
+/// Clears the memoized result of [`linux_version`].
+pub(crate) fn clear_cache() {
+    DETECTED_LINUX_VERSION.clear();
 }
 
 /// Detects the current linux version.
@@ -1,6 +1,6 @@
 //! Low-level functions to detect the OSX version of the system. See [`osx_version`].
 
-use once_cell::sync::OnceCell;
+use crate::cache::DetectionCache;
 use rattler_conda_types::{ParseVersionError, Version};
 
 /// Returns the OSX version of the current platform.
@@ -8,10 +8,14 @@ use rattler_conda_types::{ParseVersionError, Version};
 /// Returns an error if determining the version resulted in an error. Returns `None` if
 /// the current platform is not a OSX platform.
 pub fn osx_version() -> Result<Option<Version>, ParseOsxVersionError> {
-    static DETECTED_OSX_VERSION: OnceCell<Option<Version>> = OnceCell::new();
-    DETECTED_OSX_VERSION
-        .get_or_try_init(try_detect_osx_version)
-        .cloned()
+    DETECTED_OSX_VERSION.get_or_try_init(try_detect_osx_version)
+}
+
+static DETECTED_OSX_VERSION: DetectionCache<Option<Version>> = DetectionCache::new();
+
+/// Clears the memoized result of [`osx_version`].
+pub(crate) fn clear_cache() {
+    DETECTED_OSX_VERSION.clear();
 }
 
 /// Detects the current linux version.
@@ -46,7 +46,7 @@ pub fn py_fetch_repo_data<'a>(
             Ok((
                 fetch_repo_data(
                     subdir,
-                    client.into(),
+                    client,
                     cache_path,
                     FetchRepoDataOptions::default(),
                     callback,
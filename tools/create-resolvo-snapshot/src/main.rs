@@ -46,7 +46,7 @@ async fn main() {
         eprintln!("fetching repodata for {subdir:?}..");
         let repodata = rattler_repodata_gateway::fetch::fetch_repo_data(
             channel.platform_url(subdir),
-            client.clone().into(),
+            client.clone(),
             rattler_cache::default_cache_dir()
                 .unwrap()
                 .join(rattler_cache::REPODATA_CACHE_DIR),